@@ -1,7 +1,7 @@
 use clap::{Parser, Subcommand};
 use std::path::Path;
 
-use ebook_converter_core::config::{config_path, load_config, read_options_from_config, write_options_from_config, AppConfig};
+use ebook_converter_core::config::{config_path, load_config, read_options_for_path, read_options_from_config, write_options_from_config, AppConfig};
 use ebook_converter_core::convert::{convert_path, parse_format, read_document, write_document};
 use ebook_converter_core::cover::extract_cover;
 use ebook_converter_core::dedup::{find_duplicates, DuplicateStrategy};
@@ -13,7 +13,7 @@ use ebook_converter_core::rename::format_title;
 use ebook_converter_core::repair;
 use ebook_converter_core::rename;
 use ebook_converter_core::split::{split, SplitStrategy};
-use ebook_converter_core::validate::{validate, ValidateOptions, WcagLevel};
+use ebook_converter_core::validate::{validate, validate_archive, ValidateOptions, WcagLevel};
 use ebook_converter_core::writers::WriteOptions;
 use ebook_converter_core::lookup::openlibrary::OpenLibraryProvider;
 use ebook_converter_core::lookup::{MetadataProvider, MetadataQuery};
@@ -45,13 +45,21 @@ enum Commands {
         #[arg(short, long)]
         output: Option<String>,
 
-        /// Output format (epub, txt, html, md, pdf, ssml)
+        /// Output format (epub, txt, html, md, pdf, ssml, fb2, cbz)
         #[arg(short, long)]
         format: Option<String>,
 
         /// Rename output using format string
         #[arg(long)]
         rename: Option<String>,
+
+        /// Write HTML or SSML as a directory with one file per chapter
+        #[arg(long)]
+        split_chapters: bool,
+
+        /// Write EPUB as fixed layout, one page per chapter (comics, picture books)
+        #[arg(long)]
+        fixed_layout: bool,
     },
 
     /// Validate ebook structure
@@ -104,6 +112,10 @@ enum Commands {
         /// Image quality (1-100)
         #[arg(long, default_value = "80")]
         quality: u8,
+
+        /// Downscale images larger than this many pixels on either side
+        #[arg(long)]
+        max_dimension: Option<u32>,
     },
 
     /// Rename ebook files using template
@@ -243,11 +255,11 @@ fn main() {
         .init();
 
     let result = match &cli.command {
-        Commands::Convert { input, output, format, rename } => run_convert(input, output.as_deref(), format.as_deref(), rename.as_deref(), cli.json),
+        Commands::Convert { input, output, format, rename, split_chapters, fixed_layout } => run_convert(input, output.as_deref(), format.as_deref(), rename.as_deref(), *split_chapters, *fixed_layout, cli.json),
         Commands::Validate { input, strict, accessibility, wcag_level } => run_validate(input, *strict, *accessibility, wcag_level, cli.json),
         Commands::Info { input } => run_info(input, cli.json),
         Commands::Repair { input, output } => run_repair(input, output.as_deref(), cli.json),
-        Commands::Optimize { input, output, quality } => run_optimize(input, output.as_deref(), *quality, cli.json),
+        Commands::Optimize { input, output, quality, max_dimension } => run_optimize(input, output.as_deref(), *quality, *max_dimension, cli.json),
         Commands::Rename { input, template, dry_run, outdir } => run_rename(input, template, *dry_run, outdir.as_deref(), cli.json),
         Commands::Meta { input, get, set, strip } => run_meta(input, get.as_deref(), set.as_deref(), *strip, cli.json),
         Commands::Cover { input, output } => run_cover(input, output.as_deref(), cli.json),
@@ -269,15 +281,23 @@ fn run_convert(
     output: Option<&str>,
     format_str: Option<&str>,
     rename_template: Option<&str>,
+    split_chapters: bool,
+    fixed_layout: bool,
     json: bool,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let cfg = load_config();
     let read_opts = read_options_from_config(&cfg);
-    let write_opts = write_options_from_config(&cfg);
+    let mut write_opts = write_options_from_config(&cfg);
+    write_opts.html.split_chapters = split_chapters;
+    write_opts.ssml.split_chapters = split_chapters;
+    write_opts.epub.fixed_layout = fixed_layout;
 
     let output_format = format_str
         .and_then(parse_format)
         .unwrap_or(ebook_converter_core::detect::Format::Epub);
+    // Only split HTML/SSML is written as a directory; other formats ignore the flag.
+    let writes_dir = split_chapters
+        && matches!(output_format, ebook_converter_core::detect::Format::Html | ebook_converter_core::detect::Format::Ssml);
 
     for input_path in inputs {
         let input_path = Path::new(input_path);
@@ -308,17 +328,26 @@ fn run_convert(
         } else {
             let stem = input_path.file_stem().and_then(|s| s.to_str()).unwrap_or("output");
             let ext = output_format.extension();
-            input_path.parent().unwrap_or(Path::new(".")).join(format!("{}.{}", stem, ext))
+            let name = if writes_dir { stem.to_string() } else { format!("{}.{}", stem, ext) };
+            input_path.parent().unwrap_or(Path::new(".")).join(name)
         };
 
-        let out_path = if out_path.is_dir() {
+        // With --split-chapters the output path is the site directory itself.
+        let out_path = if out_path.is_dir() && !writes_dir {
             let stem = input_path.file_stem().and_then(|s| s.to_str()).unwrap_or("output");
             out_path.join(format!("{}.{}", stem, output_format.extension()))
         } else {
             out_path
         };
 
-        convert_path(input_path, &out_path, output_format, &read_opts, &write_opts)?;
+        convert_path(
+            input_path,
+            &out_path,
+            output_format,
+            &read_opts,
+            &write_opts,
+            None,
+        )?;
 
         if !json {
             println!("Converted: {} -> {}", input_path.display(), out_path.display());
@@ -344,15 +373,28 @@ fn run_validate(
     reader.seek(SeekFrom::Start(0))?;
     let filename = path.file_name().and_then(|p| p.to_str());
     let detected = ebook_converter_core::detect::detect(&header, filename)?;
-    let read_opts = read_options_from_config(&load_config());
-    let doc = read_document(detected.format, reader, &read_opts, None)?;
+    let read_opts = read_options_for_path(&load_config(), path);
+    let mut issues = Vec::new();
+    if header.starts_with(b"PK\x03\x04") {
+        issues.extend(validate_archive(&std::fs::read(path)?));
+    }
+    let doc = match read_document(detected.format, reader, &read_opts, None) {
+        Ok(doc) => doc,
+        // A damaged container often keeps the book from being read at all.
+        Err(e) => {
+            for issue in &issues {
+                eprintln!("[{:?}] {}: {}", issue.severity, issue.code, issue.message);
+            }
+            return Err(e.into());
+        }
+    };
 
     let opts = ValidateOptions {
         strict,
         accessibility,
         wcag_level: WcagLevel::from_str(wcag_level),
     };
-    let issues = validate(&doc, &opts);
+    issues.extend(validate(&doc, &opts));
 
     if json {
         println!("{}", serde_json::to_string_pretty(&issues)?);
@@ -379,9 +421,20 @@ fn run_repair(
     json: bool,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let path = Path::new(input);
-    let doc = read_doc_from_path(path)?;
-    let mut doc = doc;
-    let report = repair::repair(&mut doc, &repair::RepairOptions::default());
+    let opts = repair::RepairOptions::default();
+    let read_opts = read_options_for_path(&load_config(), path);
+    // ZIP-level damage has to be fixed before the book can be read.
+    let mut data = std::fs::read(path)?;
+    let mut report = if data.starts_with(b"PK\x03\x04") {
+        repair::repair_archive(&mut data, &opts, &read_opts.security)
+    } else {
+        repair::RepairReport::default()
+    };
+    let filename = path.file_name().and_then(|p| p.to_str());
+    let header = &data[..data.len().min(4096)];
+    let detected = ebook_converter_core::detect::detect(header, filename)?;
+    let mut doc = read_document(detected.format, std::io::Cursor::new(&data), &read_opts, None)?;
+    report.extend(repair::repair(&mut doc, &opts));
     if json {
         println!("{}", serde_json::to_string_pretty(&report)?);
     } else {
@@ -389,7 +442,7 @@ fn run_repair(
             println!("Fixed: {} - {}", a.code, a.description);
         }
         for (a, msg) in &report.fixes_failed {
-            eprintln!("Failed: {} - {}", a.code, msg);
+            eprintln!("Failed: {} - {}: {}", a.code, a.description, msg);
         }
     }
     if let Some(out) = output {
@@ -408,11 +461,12 @@ fn run_optimize(
     input: &str,
     output: Option<&str>,
     quality: u8,
+    max_dimension: Option<u32>,
     json: bool,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let path = Path::new(input);
     let mut doc = read_doc_from_path(path)?;
-    let opts = optimize::OptimizeOptions { image_quality: quality, ..optimize::OptimizeOptions::default() };
+    let opts = optimize::OptimizeOptions { image_quality: quality, max_image_dimension: max_dimension, ..optimize::OptimizeOptions::default() };
     let report = optimize::optimize(&mut doc, &opts);
     if json {
         println!("{}", serde_json::to_string_pretty(&report)?);
@@ -653,7 +707,7 @@ fn read_doc_from_path(path: &Path) -> Result<ebook_converter_core::document::Doc
     reader.seek(SeekFrom::Start(0))?;
     let filename = path.file_name().and_then(|p| p.to_str());
     let detected = ebook_converter_core::detect::detect(&header, filename)?;
-    let opts = read_options_from_config(&load_config());
+    let opts = read_options_for_path(&load_config(), path);
     read_document(detected.format, reader, &opts, None).map_err(|e| e.into())
 }
 
@@ -670,7 +724,7 @@ fn run_info(input: &str, json: bool) -> Result<(), Box<dyn std::error::Error + S
     let filename = path.file_name().and_then(|p| p.to_str());
     let detected = ebook_converter_core::detect::detect(&header, filename)?;
 
-    let read_opts = read_options_from_config(&load_config());
+    let read_opts = read_options_for_path(&load_config(), path);
     let doc = read_document(
         detected.format,
         reader,
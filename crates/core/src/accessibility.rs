@@ -73,11 +73,16 @@ fn check_content_accessibility(
                 }
             }
         }
-        ContentNode::BlockQuote { children } => {
+        ContentNode::BlockQuote { children }
+        | ContentNode::Note { children, .. }
+        | ContentNode::Container { children, .. } => {
             for n in children {
                 check_content_accessibility(n, issues, heading_levels, chapter_idx);
             }
         }
+        ContentNode::Styled { node, .. } => {
+            check_content_accessibility(node, issues, heading_levels, chapter_idx);
+        }
         ContentNode::Table { headers, rows } => {
             for cell in headers {
                 for n in cell {
@@ -3,6 +3,8 @@
 //! Use `read_options_from_config` and `write_options_from_config` to build
 //! read/write options from the loaded config so security and encoding settings apply.
 
+use std::path::Path;
+
 use serde::{Deserialize, Serialize};
 
 use crate::encoding::{EncodingOptions, UnicodeForm};
@@ -162,6 +164,17 @@ pub fn read_options_from_config(cfg: &AppConfig) -> ReadOptions {
         extract_cover: true,
         parse_toc: true,
         encoding: encoding_options_from_config(&cfg.encoding),
+        base_dir: None,
+    }
+}
+
+/// Read options for a specific input file: like `read_options_from_config`, with
+/// `base_dir` set to the file's directory so relative references (local images in
+/// HTML/Markdown) resolve.
+pub fn read_options_for_path(cfg: &AppConfig, input: &Path) -> ReadOptions {
+    ReadOptions {
+        base_dir: input.parent().map(|p| p.to_path_buf()),
+        ..read_options_from_config(cfg)
     }
 }
 
@@ -214,4 +227,11 @@ mod tests {
         assert!(opts.extract_cover);
         assert!(opts.parse_toc);
     }
+
+    #[test]
+    fn read_options_for_path_sets_base_dir() {
+        let cfg = AppConfig::default();
+        let opts = super::read_options_for_path(&cfg, Path::new("books/md/book.md"));
+        assert_eq!(opts.base_dir.as_deref(), Some(Path::new("books/md")));
+    }
 }
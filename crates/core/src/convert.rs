@@ -4,11 +4,18 @@
 //!
 //! | Input  | Output |
 //! |--------|--------|
-//! | EPUB   | EPUB, TXT |
-//! | TXT    | EPUB, TXT |
+//! | CBZ, CBR (ZIP) | CBZ, EPUB, FB2, HTML, MD, PDF, SSML, TXT |
+//! | DOCX   | CBZ, EPUB, FB2, HTML, MD, PDF, SSML, TXT |
+//! | EPUB   | CBZ, EPUB, FB2, HTML, MD, PDF, SSML, TXT |
+//! | FB2    | CBZ, EPUB, FB2, HTML, MD, PDF, SSML, TXT |
+//! | HTML   | CBZ, EPUB, FB2, HTML, MD, PDF, SSML, TXT |
+//! | MD     | CBZ, EPUB, FB2, HTML, MD, PDF, SSML, TXT |
+//! | MOBI/AZW3 | CBZ, EPUB, FB2, HTML, MD, PDF, SSML, TXT |
+//! | PDF    | CBZ, EPUB, FB2, HTML, MD, PDF, SSML, TXT |
+//! | TXT    | CBZ, EPUB, FB2, HTML, MD, PDF, SSML, TXT |
 //!
-//! Other formats (HTML, MD, PDF, SSML, etc.) are detected but readers/writers
-//! may not be implemented yet; see PROJECT-TODO-AND-IMPROVEMENTS.md.
+//! Other detected formats may not have readers/writers
+//! implemented yet; see PROJECT-TODO-AND-IMPROVEMENTS.md.
 
 use std::fs::File;
 use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom};
@@ -17,20 +24,36 @@ use std::path::Path;
 use crate::detect::{detect, Format};
 use crate::document::Document;
 use crate::error::{EbookError, ReadError};
+use crate::progress::ProgressHandler;
+use crate::readers::cbz::CbzReader;
+use crate::readers::docx::DocxReader;
 use crate::readers::epub::EpubReader;
+use crate::readers::fb2::Fb2Reader;
+use crate::readers::html::HtmlReader;
+use crate::readers::markdown::MarkdownReader;
+use crate::readers::mobi::MobiReader;
+use crate::readers::pdf::PdfReader;
 use crate::readers::txt::TxtReader;
 use crate::readers::{FormatReader, ReadOptions};
+use crate::writers::cbz::CbzWriter;
 use crate::writers::epub::EpubWriter;
+use crate::writers::fb2::Fb2Writer;
+use crate::writers::html::HtmlWriter;
+use crate::writers::markdown::MarkdownWriter;
+use crate::writers::pdf::PdfWriter;
+use crate::writers::ssml::SsmlWriter;
 use crate::writers::txt::TxtWriter;
 use crate::writers::{FormatWriter, WriteOptions};
 
-/// Convert an input file to the given output path and format.
+/// Convert an input file to the given output path and format. `progress`
+/// receives the reader's and then the writer's events.
 pub fn convert_path(
     input_path: &Path,
     output_path: &Path,
     output_format: Format,
     read_opts: &ReadOptions,
     write_opts: &WriteOptions,
+    progress: Option<&dyn ProgressHandler>,
 ) -> Result<(), EbookError> {
     let mut input_file = File::open(input_path)?;
     let mut header = vec![0u8; 4096];
@@ -43,7 +66,11 @@ pub fn convert_path(
     let input_format = detected.format;
 
     let input = BufReader::new(input_file);
-    let doc = read_document(input_format, input, read_opts, None)?;
+    let mut read_opts = read_opts.clone();
+    if read_opts.base_dir.is_none() {
+        read_opts.base_dir = input_path.parent().map(|p| p.to_path_buf());
+    }
+    let doc = read_document(input_format, input, &read_opts, progress)?;
 
     // Apply transforms
     let mut doc = doc;
@@ -51,10 +78,30 @@ pub fn convert_path(
         transform.apply(&mut doc)?;
     }
 
+    // Split HTML/SSML is a directory of files rather than a single byte stream.
+    if output_format == Format::Html && write_opts.html.split_chapters {
+        HtmlWriter::write_dir(&doc, output_path, write_opts, progress)?;
+        return Ok(());
+    }
+    if output_format == Format::Ssml && write_opts.ssml.split_chapters {
+        SsmlWriter::write_dir(&doc, output_path, write_opts, progress)?;
+        return Ok(());
+    }
+    // Markdown images are written as files next to the .md.
+    if output_format == Format::Markdown {
+        MarkdownWriter::write_path(&doc, output_path, write_opts, progress)?;
+        return Ok(());
+    }
+
     let output_file = File::create(output_path)?;
     let output = BufWriter::new(output_file);
 
-    write_document(output_format, &doc, output, write_opts, None)?;
+    // EPUB archives are streamed into the file rather than spooled.
+    if output_format == Format::Epub {
+        EpubWriter::write_seekable(&doc, output, write_opts, progress)?;
+        return Ok(());
+    }
+    write_document(output_format, &doc, output, write_opts, progress)?;
 
     Ok(())
 }
@@ -67,10 +114,17 @@ pub fn read_document<R: std::io::Read + std::io::Seek>(
     progress: Option<&dyn crate::progress::ProgressHandler>,
 ) -> Result<Document, ReadError> {
     match format {
+        Format::Cbz | Format::Cbr => CbzReader::read(input, opts, progress),
+        Format::Docx => DocxReader::read(input, opts, progress),
         Format::Epub => EpubReader::read(input, opts, progress),
+        Format::Fb2 => Fb2Reader::read(input, opts, progress),
+        Format::Html => HtmlReader::read(input, opts, progress),
+        Format::Markdown => MarkdownReader::read(input, opts, progress),
+        Format::Mobi | Format::Azw3 => MobiReader::read(input, opts, progress),
+        Format::Pdf => PdfReader::read(input, opts, progress),
         Format::PlainText => TxtReader::read(input, opts, progress),
         _ => Err(ReadError::UnsupportedFormat(format!(
-            "reading {} is not yet supported; supported input formats: epub, txt",
+            "reading {} is not yet supported; supported input formats: azw3, cbr (ZIP), cbz, docx, epub, fb2, html, md, mobi, pdf, txt",
             format
         ))),
     }
@@ -85,7 +139,13 @@ pub fn write_document<W: std::io::Write>(
     progress: Option<&dyn crate::progress::ProgressHandler>,
 ) -> Result<(), crate::error::WriteError> {
     match format {
+        Format::Cbz => CbzWriter::write(doc, output, opts, progress),
         Format::Epub => EpubWriter::write(doc, output, opts, progress),
+        Format::Fb2 => Fb2Writer::write(doc, output, opts, progress),
+        Format::Html => HtmlWriter::write(doc, output, opts, progress),
+        Format::Markdown => MarkdownWriter::write(doc, output, opts, progress),
+        Format::Pdf => PdfWriter::write(doc, output, opts, progress),
+        Format::Ssml => SsmlWriter::write(doc, output, opts, progress),
         Format::PlainText => TxtWriter::write(doc, output, opts, progress),
         _ => Err(crate::error::WriteError::WriteFailed {
             format: format!("{:?}", format),
@@ -100,11 +160,13 @@ pub fn write_document<W: std::io::Write>(
 pub fn parse_format(s: &str) -> Option<Format> {
     match s.to_lowercase().as_str() {
         "epub" => Some(Format::Epub),
+        "cbz" => Some(Format::Cbz),
         "txt" | "text" => Some(Format::PlainText),
         "html" => Some(Format::Html),
         "md" | "markdown" => Some(Format::Markdown),
         "ssml" => Some(Format::Ssml),
         "pdf" => Some(Format::Pdf),
+        "fb2" => Some(Format::Fb2),
         _ => None,
     }
 }
@@ -113,6 +175,36 @@ pub fn parse_format(s: &str) -> Option<Format> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn convert_path_reports_read_and_write_progress() {
+        struct Operations(std::sync::Mutex<Vec<String>>);
+        impl ProgressHandler for Operations {
+            fn on_progress(&self, e: crate::progress::ProgressEvent) {
+                self.0.lock().unwrap().push(e.operation);
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("book.md");
+        let output = dir.path().join("book.epub");
+        std::fs::write(&input, "# One\n\nText.\n").unwrap();
+        let operations = Operations(Default::default());
+        convert_path(
+            &input,
+            &output,
+            Format::Epub,
+            &ReadOptions::default(),
+            &WriteOptions::default(),
+            Some(&operations),
+        )
+        .unwrap();
+
+        let mut operations = operations.0.into_inner().unwrap();
+        operations.dedup();
+        assert_eq!(operations, ["Reading Markdown", "Writing EPUB"]);
+        assert!(output.exists());
+    }
+
     #[test]
     fn parse_format_epub_txt() {
         assert_eq!(parse_format("epub"), Some(Format::Epub));
@@ -122,12 +214,14 @@ mod tests {
     }
 
     #[test]
-    fn parse_format_html_md_ssml_pdf() {
+    fn parse_format_html_md_ssml_pdf_fb2_cbz() {
         assert_eq!(parse_format("html"), Some(Format::Html));
         assert_eq!(parse_format("md"), Some(Format::Markdown));
         assert_eq!(parse_format("markdown"), Some(Format::Markdown));
         assert_eq!(parse_format("ssml"), Some(Format::Ssml));
         assert_eq!(parse_format("pdf"), Some(Format::Pdf));
+        assert_eq!(parse_format("fb2"), Some(Format::Fb2));
+        assert_eq!(parse_format("cbz"), Some(Format::Cbz));
     }
 
     #[test]
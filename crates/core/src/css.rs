@@ -0,0 +1,588 @@
+//! Stylesheets: a small, forgiving rule splitter shared by font subsetting
+//! and optimization, stripping of rules the content cannot match, and
+//! minification.
+//!
+//! Stripping only trusts class and id selectors: a rule goes when every one
+//! of its selectors names a class or id that appears nowhere in the book.
+//! Element, attribute and pseudo-class selectors are assumed to match.
+
+use std::collections::HashSet;
+
+use crate::document::*;
+
+/// Vendor prefixes whose declarations are dropped when the same block has
+/// the standard form. `-epub-` is left alone: EPUB defines it for reading
+/// systems that predate the standard properties.
+const VENDOR_PREFIXES: &[&str] = &["-webkit-", "-moz-", "-ms-", "-o-", "-khtml-"];
+
+/// At-rules whose blocks hold further rules.
+const GROUPING_RULES: &[&str] = &["media", "supports", "layer", "document", "container"];
+
+pub(crate) fn strip_comments(css: &str) -> String {
+    let mut out = String::with_capacity(css.len());
+    let mut rest = css;
+    while let Some(start) = rest.find("/*") {
+        out.push_str(&rest[..start]);
+        rest = match rest[start + 2..].find("*/") {
+            Some(end) => &rest[start + 2 + end + 2..],
+            None => "",
+        };
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Split off the next rule: its prelude, and its block when it has one.
+/// Returns `None` at the end of the input.
+pub(crate) fn next_rule(css: &str) -> Option<(&str, Option<&str>, &str)> {
+    let css = css.trim_start();
+    if css.is_empty() {
+        return None;
+    }
+    let mut quote = None;
+    for (i, c) in css.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, ';') => return Some((&css[..i], None, &css[i + 1..])),
+            (None, '{') => {
+                let end = block_end(&css[i + 1..]);
+                let rest = css[i + 1 + end..].strip_prefix('}').unwrap_or("");
+                return Some((&css[..i], Some(&css[i + 1..i + 1 + end]), rest));
+            }
+            _ => {}
+        }
+    }
+    Some((css, None, ""))
+}
+
+/// Length of a block's contents, up to its closing brace.
+fn block_end(css: &str) -> usize {
+    let (mut depth, mut quote) = (0, None);
+    for (i, c) in css.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '{') => depth += 1,
+            (None, '}') if depth == 0 => return i,
+            (None, '}') => depth -= 1,
+            _ => {}
+        }
+    }
+    css.len()
+}
+
+/// The lower-cased name of an at-rule prelude (`media` for `@media print`).
+pub(crate) fn at_rule_name(prelude: &str) -> Option<String> {
+    let rest = prelude.trim_start().strip_prefix('@')?;
+    let name = rest
+        .split(|c: char| c.is_whitespace() || c == '(' || c == '"' || c == '\'')
+        .next()
+        .unwrap_or(rest);
+    Some(name.to_ascii_lowercase())
+}
+
+/// `name: value` pairs of a declaration block. Names are lower-cased except
+/// custom properties; values are trimmed.
+pub(crate) fn declarations(block: &str) -> Vec<(String, String)> {
+    let mut out = Vec::new();
+    let mut push = |decl: &str| {
+        if let Some((name, value)) = decl.split_once(':') {
+            let name = name.trim();
+            let name = match name.starts_with("--") {
+                true => name.to_string(),
+                false => name.to_ascii_lowercase(),
+            };
+            out.push((name, value.trim().to_string()));
+        }
+    };
+    let mut start = 0;
+    for (i, end) in split_top_level(block, ';') {
+        push(&block[start..i]);
+        start = end;
+    }
+    push(&block[start..]);
+    out
+}
+
+/// Positions of `sep` outside strings, parentheses and brackets, with the
+/// offset just past each.
+fn split_top_level(s: &str, sep: char) -> Vec<(usize, usize)> {
+    let mut out = Vec::new();
+    let (mut quote, mut depth) = (None, 0i32);
+    for (i, c) in s.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '(' | '[') => depth += 1,
+            (None, ')' | ']') => depth -= 1,
+            (None, c) if c == sep && depth == 0 => out.push((i, i + c.len_utf8())),
+            _ => {}
+        }
+    }
+    out
+}
+
+/// The targets of `url(...)` references in a value.
+pub(crate) fn urls(value: &str) -> Vec<String> {
+    url_spans(value)
+        .into_iter()
+        .map(|(start, end)| unquote_url(&value[start..end]).to_string())
+        .collect()
+}
+
+/// Byte ranges of the arguments of each `url(...)`, quotes included.
+fn url_spans(css: &str) -> Vec<(usize, usize)> {
+    let mut out = Vec::new();
+    let mut from = 0;
+    while let Some(at) = css[from..].find("url(") {
+        let start = from + at + 4;
+        let end = css[start..].find(')').map_or(css.len(), |e| start + e);
+        out.push((start, end));
+        from = end;
+    }
+    out
+}
+
+fn unquote_url(arg: &str) -> &str {
+    arg.trim().trim_matches(|c| c == '"' || c == '\'')
+}
+
+/// Replace the `url(...)` targets `f` maps to something new. Returns the
+/// stylesheet and the number of references changed.
+pub(crate) fn rewrite_urls(
+    css: &str,
+    mut f: impl FnMut(&str) -> Option<String>,
+) -> (String, usize) {
+    let mut out = String::with_capacity(css.len());
+    let (mut last, mut changed) = (0, 0);
+    for (start, end) in url_spans(css) {
+        if let Some(new) = f(unquote_url(&css[start..end])) {
+            out.push_str(&css[last..start]);
+            out.push('"');
+            out.push_str(&new.replace('"', "%22"));
+            out.push('"');
+            last = end;
+            changed += 1;
+        }
+    }
+    out.push_str(&css[last..]);
+    (out, changed)
+}
+
+/// Classes and ids used anywhere in a document's content.
+#[derive(Debug, Default)]
+pub struct ContentSelectors {
+    classes: HashSet<String>,
+    ids: HashSet<String>,
+}
+
+impl ContentSelectors {
+    pub fn new(doc: &Document) -> Self {
+        let mut selectors = Self::default();
+        for chapter in &doc.content {
+            selectors.ids.insert(chapter.id.clone());
+            selectors.blocks(&chapter.content);
+        }
+        selectors
+    }
+
+    fn attrs(&mut self, attrs: &Attributes) {
+        self.ids.extend(attrs.id.clone());
+        for class in attrs.class.iter().flat_map(|c| c.split_whitespace()) {
+            self.classes.insert(class.to_string());
+        }
+    }
+
+    fn blocks(&mut self, nodes: &[ContentNode]) {
+        for node in nodes {
+            match node {
+                ContentNode::Paragraph { children } | ContentNode::Heading { children, .. } => {
+                    self.inlines(children)
+                }
+                ContentNode::List { items, .. } => {
+                    for item in items {
+                        self.blocks(item);
+                    }
+                }
+                ContentNode::Table { headers, rows } => {
+                    for cell in headers.iter().chain(rows.iter().flatten()) {
+                        self.inlines(cell);
+                    }
+                }
+                ContentNode::BlockQuote { children } => self.blocks(children),
+                ContentNode::RawHtml(html) => self.raw_html(html),
+                ContentNode::Note { id, children, .. } => {
+                    self.ids.insert(id.clone());
+                    self.blocks(children);
+                }
+                ContentNode::Styled { attrs, node } => {
+                    self.attrs(attrs);
+                    self.blocks(std::slice::from_ref(node.as_ref()));
+                }
+                ContentNode::Container { attrs, children } => {
+                    self.attrs(attrs);
+                    self.blocks(children);
+                }
+                ContentNode::CodeBlock { .. }
+                | ContentNode::Image { .. }
+                | ContentNode::HorizontalRule => {}
+            }
+        }
+    }
+
+    fn inlines(&mut self, nodes: &[InlineNode]) {
+        for node in nodes {
+            match node {
+                InlineNode::Span { attrs, children } => {
+                    self.attrs(attrs);
+                    self.inlines(children);
+                }
+                InlineNode::Emphasis(c)
+                | InlineNode::Strong(c)
+                | InlineNode::Superscript(c)
+                | InlineNode::Subscript(c)
+                | InlineNode::Link { children: c, .. } => self.inlines(c),
+                _ => {}
+            }
+        }
+    }
+
+    /// `class` and `id` attributes of markup kept as raw HTML.
+    fn raw_html(&mut self, html: &str) {
+        for (name, set) in [("class", &mut self.classes), ("id", &mut self.ids)] {
+            let pattern = format!(" {}=", name);
+            let mut rest = html;
+            while let Some(at) = rest.find(&pattern) {
+                rest = &rest[at + pattern.len()..];
+                let Some(quote) = rest.chars().next().filter(|c| matches!(c, '"' | '\'')) else {
+                    continue;
+                };
+                let value = rest[1..].split(quote).next().unwrap_or("");
+                set.extend(value.split_whitespace().map(str::to_string));
+            }
+        }
+    }
+
+    /// Whether `selector` could match an element of the content: false only
+    /// when it requires a class or id the content never uses.
+    fn can_match(&self, selector: &str) -> bool {
+        let mut chars = selector.chars().peekable();
+        let mut depth = 0;
+        while let Some(c) = chars.next() {
+            match c {
+                // Arguments of `:not()` and friends, and attribute values.
+                '(' | '[' => depth += 1,
+                ')' | ']' => depth -= 1,
+                '.' | '#' if depth == 0 => {
+                    let mut name = String::new();
+                    while let Some(&n) = chars.peek() {
+                        if n == '\\' {
+                            chars.next();
+                            match chars.next() {
+                                // Hex escapes are rare enough not to decode.
+                                Some(e) if e.is_ascii_hexdigit() => return true,
+                                Some(e) => name.push(e),
+                                None => break,
+                            }
+                        } else if n.is_alphanumeric() || matches!(n, '-' | '_') || !n.is_ascii() {
+                            name.push(n);
+                            chars.next();
+                        } else {
+                            break;
+                        }
+                    }
+                    let known = match c {
+                        '.' => &self.classes,
+                        _ => &self.ids,
+                    };
+                    if !name.is_empty() && !known.contains(&name) {
+                        return false;
+                    }
+                }
+                _ => {}
+            }
+        }
+        true
+    }
+}
+
+/// What `strip_stylesheet` removed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CssStats {
+    /// Rules none of whose selectors can match the content.
+    pub unused_rules: usize,
+    /// Vendor-prefixed declarations and at-rules with a standard equivalent
+    /// alongside.
+    pub prefixed: usize,
+}
+
+/// Remove the rules of `css` that cannot match `content` and the
+/// vendor-prefixed duplicates, and minify what is left.
+pub fn strip_stylesheet(css: &str, content: &ContentSelectors) -> (String, CssStats) {
+    let mut stats = CssStats::default();
+    let out = rules(&strip_comments(css), Some(content), &mut stats);
+    (out, stats)
+}
+
+/// Minify a stylesheet without removing any rules.
+pub fn minify(css: &str) -> String {
+    rules(&strip_comments(css), None, &mut CssStats::default())
+}
+
+/// Minified rules of a stylesheet or grouping block; rules are only
+/// matched against the content when it is given.
+fn rules(css: &str, content: Option<&ContentSelectors>, stats: &mut CssStats) -> String {
+    // Names of standard `@keyframes`, which make prefixed copies redundant.
+    let mut keyframes = HashSet::new();
+    let mut rest = css;
+    while let Some((prelude, _, next)) = next_rule(rest) {
+        rest = next;
+        if at_rule_name(prelude).as_deref() == Some("keyframes") {
+            keyframes.insert(collapse_whitespace(prelude.trim()).replacen("@keyframes ", "", 1));
+        }
+    }
+
+    let mut out = String::new();
+    let mut rest = css;
+    while let Some((prelude, block, next)) = next_rule(rest) {
+        rest = next;
+        let prelude = collapse_whitespace(prelude.trim());
+        let Some(block) = block else {
+            // `@import`, `@charset` and `@namespace` statements.
+            if prelude.starts_with('@') {
+                out.push_str(&prelude);
+                out.push(';');
+            }
+            continue;
+        };
+        match at_rule_name(&prelude) {
+            Some(name) if GROUPING_RULES.contains(&name.as_str()) => {
+                let inner = rules(block, content, stats);
+                if !inner.is_empty() {
+                    out.push_str(&format!("{}{{{}}}", prelude, inner));
+                }
+            }
+            Some(name) if name.ends_with("keyframes") => {
+                let ident = prelude.split_once(' ').map_or("", |(_, i)| i);
+                if name != "keyframes" && is_vendor_prefixed(&name) && keyframes.contains(ident) {
+                    stats.prefixed += 1;
+                    continue;
+                }
+                let inner = rules(block, None, stats);
+                out.push_str(&format!("{}{{{}}}", prelude, inner));
+            }
+            // Blocks mixing declarations and nested rules (`@page` margin
+            // boxes) are kept as they are.
+            Some(_) if block.contains('{') => {
+                out.push_str(&format!(
+                    "{}{{{}}}",
+                    prelude,
+                    collapse_whitespace(block.trim())
+                ));
+            }
+            Some(_) => {
+                out.push_str(&format!(
+                    "{}{{{}}}",
+                    prelude,
+                    declaration_block(block, stats)
+                ));
+            }
+            None => {
+                let selectors: Vec<&str> = split_selectors(&prelude)
+                    .into_iter()
+                    .filter(|s| content.map_or(true, |c| c.can_match(s)))
+                    .collect();
+                if selectors.is_empty() {
+                    stats.unused_rules += 1;
+                    continue;
+                }
+                let decls = match block.contains('{') {
+                    // Nested rules are kept as they are.
+                    true => collapse_whitespace(block.trim()),
+                    false => declaration_block(block, stats),
+                };
+                if !decls.is_empty() {
+                    out.push_str(&format!("{}{{{}}}", selectors.join(","), decls));
+                }
+            }
+        }
+    }
+    out
+}
+
+fn split_selectors(prelude: &str) -> Vec<&str> {
+    let mut out = Vec::new();
+    let mut start = 0;
+    for (i, end) in split_top_level(prelude, ',') {
+        out.push(prelude[start..i].trim());
+        start = end;
+    }
+    out.push(prelude[start..].trim());
+    out.retain(|s| !s.is_empty());
+    out
+}
+
+fn is_vendor_prefixed(s: &str) -> bool {
+    let s = s.trim_start_matches('@');
+    VENDOR_PREFIXES.iter().any(|p| s.starts_with(p))
+}
+
+/// Minified declarations, without prefixed ones that have a standard form
+/// in the same block: `-webkit-hyphens` next to `hyphens`, or
+/// `display: -webkit-box` next to `display: flex`.
+fn declaration_block(block: &str, stats: &mut CssStats) -> String {
+    let decls = declarations(block);
+    let standard: HashSet<&str> = decls
+        .iter()
+        .filter(|(name, _)| !is_vendor_prefixed(name))
+        .map(|(name, _)| name.as_str())
+        .collect();
+    let standard_value: HashSet<&str> = decls
+        .iter()
+        .filter(|(_, value)| !is_vendor_prefixed(value))
+        .map(|(name, _)| name.as_str())
+        .collect();
+    let mut out = Vec::with_capacity(decls.len());
+    for (name, value) in &decls {
+        let unprefixed = VENDOR_PREFIXES
+            .iter()
+            .find_map(|p| name.strip_prefix(p))
+            .unwrap_or(name);
+        if (unprefixed != name && standard.contains(unprefixed))
+            || (is_vendor_prefixed(value) && standard_value.contains(name.as_str()))
+        {
+            stats.prefixed += 1;
+            continue;
+        }
+        out.push(format!("{}:{}", name, minify_value(value)));
+    }
+    out.join(";")
+}
+
+/// A value with whitespace collapsed, and none after commas or before
+/// `!important`.
+fn minify_value(value: &str) -> String {
+    collapse(value, true)
+}
+
+/// Runs of whitespace outside strings become one space.
+fn collapse_whitespace(s: &str) -> String {
+    collapse(s, false)
+}
+
+fn collapse(s: &str, tight: bool) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut quote = None;
+    let mut space = false;
+    for c in s.chars() {
+        match quote {
+            Some(q) => {
+                out.push(c);
+                if c == q {
+                    quote = None;
+                }
+            }
+            None if c.is_whitespace() => space = true,
+            None => {
+                let needless = tight && (out.ends_with(',') || c == ',' || c == '!');
+                if space && !out.is_empty() && !needless {
+                    out.push(' ');
+                }
+                space = false;
+                if matches!(c, '"' | '\'') {
+                    quote = Some(c);
+                }
+                out.push(c);
+            }
+        }
+    }
+    out
+}
+
+/// Path from the directory of the file `from` to the file `to`, both
+/// relative to the same root.
+pub(crate) fn relative_path(from: &str, to: &str) -> String {
+    let from_dirs: Vec<&str> = match from.rsplit_once('/') {
+        Some((dir, _)) => dir.split('/').collect(),
+        None => Vec::new(),
+    };
+    let to_parts: Vec<&str> = to.split('/').collect();
+    let common = from_dirs
+        .iter()
+        .zip(&to_parts[..to_parts.len() - 1])
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut parts = vec![".."; from_dirs.len() - common];
+    parts.extend(&to_parts[common..]);
+    parts.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_stylesheet() {
+        let doc = Document {
+            content: vec![Chapter {
+                id: "ch1".into(),
+                title: None,
+                content: vec![
+                    ContentNode::Styled {
+                        attrs: Attributes {
+                            class: Some("epigraph".into()),
+                            ..Default::default()
+                        },
+                        node: Box::new(ContentNode::HorizontalRule),
+                    },
+                    ContentNode::RawHtml("<div class='sidebar wide' id=\"box\"></div>".into()),
+                ],
+                text_direction: None,
+            }],
+            ..Default::default()
+        };
+        let css = r#"
+            @charset "utf-8";
+            /* framework */
+            .btn, .btn-primary { color: blue }
+            p.epigraph,  .card > .title { font-style : italic ; }
+            div:not(.missing) > #box { margin: 0 auto !important }
+            @media print { .navbar { display: none } }
+            @media screen { .wide { display: -webkit-box; display: flex } }
+            .sidebar::after { content: "a,  b"; -moz-hyphens: auto; -epub-hyphens: auto; hyphens: auto }
+            @-webkit-keyframes spin { from { opacity: 0 } }
+            @keyframes spin { from { opacity: 0 } to { opacity: 1 } }
+        "#;
+
+        let (out, stats) = strip_stylesheet(css, &ContentSelectors::new(&doc));
+        assert_eq!(
+            out,
+            concat!(
+                r#"@charset "utf-8";"#,
+                "p.epigraph{font-style:italic}",
+                "div:not(.missing) > #box{margin:0 auto!important}",
+                "@media screen{.wide{display:flex}}",
+                r#".sidebar::after{content:"a,  b";-epub-hyphens:auto;hyphens:auto}"#,
+                "@keyframes spin{from{opacity:0}to{opacity:1}}",
+            )
+        );
+        assert_eq!(
+            stats,
+            CssStats {
+                unused_rules: 2,
+                prefixed: 3
+            }
+        );
+
+        assert_eq!(
+            relative_path("styles/book.css", "images/a.png"),
+            "../images/a.png"
+        );
+        assert_eq!(relative_path("book.css", "fonts/f.ttf"), "fonts/f.ttf");
+        assert_eq!(relative_path("a/b/c.css", "a/d.png"), "../d.png");
+    }
+}
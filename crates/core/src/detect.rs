@@ -84,7 +84,14 @@ pub fn detect(header: &[u8], filename: Option<&str>) -> Result<DetectResult, Det
         return Ok(result);
     }
 
-    // 2. Try file extension
+    // 2. Try file extension (`book.fb2.zip` is zipped FB2, not a generic ZIP)
+    if filename.is_some_and(|f| f.to_lowercase().ends_with(".fb2.zip")) {
+        return Ok(DetectResult {
+            format: Format::Fb2,
+            confidence: 0.8,
+            mime_type: Format::Fb2.mime_type(),
+        });
+    }
     if let Some(ext) = filename.and_then(|f| Path::new(f).extension()).and_then(|e| e.to_str()) {
         if let Some(result) = detect_by_extension(ext) {
             return Ok(result);
@@ -215,20 +222,34 @@ fn detect_zip_subformat(header: &[u8]) -> Option<DetectResult> {
             }
         }
 
-        // CBZ: ZIP with only image files
         let file_names: Vec<String> = (0..archive.len())
             .filter_map(|i| archive.by_index(i).ok().map(|f| f.name().to_string()))
             .collect();
-        let all_images = !file_names.is_empty()
-            && file_names.iter().all(|name| {
-                let lower = name.to_lowercase();
+
+        // FB2.ZIP: a single zipped FictionBook document
+        if file_names.iter().any(|name| name.to_lowercase().ends_with(".fb2")) {
+            return Some(DetectResult {
+                format: Format::Fb2,
+                confidence: 0.9,
+                mime_type: Format::Fb2.mime_type(),
+            });
+        }
+
+        // CBZ: ZIP with only image files, besides directory entries and the
+        // optional ComicInfo.xml metadata
+        let pages: Vec<String> = file_names
+            .iter()
+            .map(|name| name.to_lowercase())
+            .filter(|name| !name.ends_with('/') && name.rsplit('/').next() != Some("comicinfo.xml"))
+            .collect();
+        let all_images = !pages.is_empty()
+            && pages.iter().all(|lower| {
                 lower.ends_with(".jpg")
                     || lower.ends_with(".jpeg")
                     || lower.ends_with(".png")
                     || lower.ends_with(".gif")
                     || lower.ends_with(".webp")
                     || lower.ends_with(".bmp")
-                    || lower.ends_with('/')  // directory entries
             });
         if all_images {
             return Some(DetectResult {
@@ -429,6 +450,12 @@ mod tests {
         assert_eq!(result.format, Format::Fb2);
     }
 
+    #[test]
+    fn test_detect_fb2_zip_by_extension() {
+        let result = detect(b"PK\x03\x04", Some("Book.FB2.zip")).unwrap();
+        assert_eq!(result.format, Format::Fb2);
+    }
+
     #[test]
     fn test_detect_by_extension_txt() {
         let result = detect(b"", Some("notes.txt")).unwrap();
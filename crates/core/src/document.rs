@@ -11,6 +11,20 @@ pub struct Document {
     pub resources: ResourceMap,
     pub text_direction: TextDirection,
     pub epub_version: Option<EpubVersion>,
+    /// Problems readers worked around, such as content documents that were
+    /// not well-formed and had to be recovered. Validation reports them.
+    #[serde(default)]
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// A problem a reader worked around while building the document.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    /// Machine-readable code, shared with the `ValidationIssue` it becomes.
+    pub code: String,
+    pub message: String,
+    /// Where in the document, e.g. `chapter[6]`.
+    pub location: Option<String>,
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -29,9 +43,35 @@ pub struct Metadata {
     pub cover_image_id: Option<String>,
     pub page_count: Option<u32>,
     pub rights: Option<String>,
+    /// Everyone credited, with roles and sort names. `authors` stays the list
+    /// of author display names; entries here add detail to it.
+    #[serde(default)]
+    pub contributors: Vec<Contributor>,
+    /// Every identifier of the book with its scheme, the unique one first.
+    #[serde(default)]
+    pub identifiers: Vec<Identifier>,
+    /// Last modification time (`dcterms:modified`), ISO 8601.
+    #[serde(default)]
+    pub modified: Option<String>,
     pub custom: HashMap<String, String>,
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Contributor {
+    pub name: String,
+    /// Sort form of the name, e.g. "Austen, Jane".
+    pub file_as: Option<String>,
+    /// MARC relator code: `aut`, `edt`, `trl`, `ill`, …
+    pub role: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Identifier {
+    pub value: String,
+    /// Upper-cased scheme: `ISBN`, `UUID`, `DOI`, `CALIBRE`, …
+    pub scheme: Option<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SeriesInfo {
     pub name: String,
@@ -85,6 +125,85 @@ pub enum ContentNode {
     },
     HorizontalRule,
     RawHtml(String),
+    /// Body of a footnote or endnote, referenced by `InlineNode::NoteRef`
+    /// with the same `id`. Readers leave notes where the source had them;
+    /// writers decide whether they appear in place, as popups or at the end.
+    Note {
+        id: String,
+        kind: NoteKind,
+        children: Vec<ContentNode>,
+    },
+    /// A block with the class, role and language it had in the source.
+    /// Writers that keep styling put `attrs` on the block's own element.
+    Styled {
+        attrs: Attributes,
+        node: Box<ContentNode>,
+    },
+    /// A `<div>`/`<section>` that only groups blocks for styling or semantics.
+    Container {
+        attrs: Attributes,
+        children: Vec<ContentNode>,
+    },
+}
+
+impl ContentNode {
+    /// The block inside a `Styled` wrapper, or the node itself. Lets
+    /// structural checks ("is this a heading?") ignore source styling.
+    pub fn unstyled(&self) -> &ContentNode {
+        match self {
+            ContentNode::Styled { node, .. } => node.unstyled(),
+            node => node,
+        }
+    }
+}
+
+/// Calls `f` with the resource reference of every image in `nodes`, in
+/// document order.
+pub(crate) fn visit_image_refs(nodes: &[ContentNode], f: &mut impl FnMut(&str)) {
+    for node in nodes {
+        match node {
+            ContentNode::Image { resource_id, .. } => f(resource_id),
+            ContentNode::BlockQuote { children }
+            | ContentNode::Note { children, .. }
+            | ContentNode::Container { children, .. } => visit_image_refs(children, f),
+            ContentNode::Styled { node, .. } => {
+                visit_image_refs(std::slice::from_ref(node.as_ref()), f)
+            }
+            ContentNode::List { items, .. } => {
+                for item in items {
+                    visit_image_refs(item, f);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Like `visit_image_refs`, letting `f` rewrite each reference.
+pub(crate) fn visit_image_refs_mut(nodes: &mut [ContentNode], f: &mut impl FnMut(&mut String)) {
+    for node in nodes {
+        match node {
+            ContentNode::Image { resource_id, .. } => f(resource_id),
+            ContentNode::BlockQuote { children }
+            | ContentNode::Note { children, .. }
+            | ContentNode::Container { children, .. } => visit_image_refs_mut(children, f),
+            ContentNode::Styled { node, .. } => {
+                visit_image_refs_mut(std::slice::from_mut(node.as_mut()), f)
+            }
+            ContentNode::List { items, .. } => {
+                for item in items {
+                    visit_image_refs_mut(item, f);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum NoteKind {
+    Footnote,
+    Endnote,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -104,6 +223,34 @@ pub enum InlineNode {
         annotation: String,
     },
     LineBreak,
+    /// Reference to the `ContentNode::Note` with this `id`; `label` is the
+    /// marker shown in the text ("1", "*").
+    NoteRef {
+        id: String,
+        label: String,
+    },
+    /// Inline run with the class, role and language it had in the source.
+    Span {
+        attrs: Attributes,
+        children: Vec<InlineNode>,
+    },
+}
+
+/// Attributes kept from the source markup so stylesheets and links into
+/// the element still apply after conversion.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attributes {
+    /// Anchor id, kept so links into the element still resolve.
+    pub id: Option<String>,
+    pub class: Option<String>,
+    pub role: Option<String>,
+    pub lang: Option<String>,
+}
+
+impl Attributes {
+    pub fn is_empty(&self) -> bool {
+        self.id.is_none() && self.class.is_none() && self.role.is_none() && self.lang.is_none()
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -166,6 +313,62 @@ pub struct Resource {
     pub media_type: String,
     pub data: Vec<u8>,
     pub filename: Option<String>,
+    /// Set for fonts the source stored obfuscated, as listed in an EPUB's
+    /// `encryption.xml`. `data` always holds the plain font; EPUB output
+    /// obfuscates it again.
+    #[serde(default)]
+    pub obfuscation: Option<FontObfuscation>,
+}
+
+/// Font obfuscation (not DRM): the start of the font file is XORed with a
+/// key derived from the book's identifier.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FontObfuscation {
+    pub algorithm: ObfuscationAlgorithm,
+    pub key: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ObfuscationAlgorithm {
+    /// `http://www.idpf.org/2008/embedding`: the first 1040 bytes, keyed by
+    /// the SHA-1 of the unique identifier.
+    Idpf,
+    /// `http://ns.adobe.com/pdf/enc#RC`: the first 1024 bytes, keyed by the
+    /// book's UUID.
+    Adobe,
+}
+
+impl ObfuscationAlgorithm {
+    /// The `EncryptionMethod` algorithm URI in `encryption.xml`.
+    pub fn uri(self) -> &'static str {
+        match self {
+            ObfuscationAlgorithm::Idpf => "http://www.idpf.org/2008/embedding",
+            ObfuscationAlgorithm::Adobe => "http://ns.adobe.com/pdf/enc#RC",
+        }
+    }
+
+    pub fn from_uri(uri: &str) -> Option<Self> {
+        [ObfuscationAlgorithm::Idpf, ObfuscationAlgorithm::Adobe]
+            .into_iter()
+            .find(|a| a.uri() == uri)
+    }
+}
+
+impl FontObfuscation {
+    /// Obfuscate or de-obfuscate `data` in place; applying it twice restores
+    /// the original.
+    pub fn apply(&self, data: &mut [u8]) {
+        let len = match self.algorithm {
+            ObfuscationAlgorithm::Idpf => 1040,
+            ObfuscationAlgorithm::Adobe => 1024,
+        };
+        if self.key.is_empty() {
+            return;
+        }
+        for (i, byte) in data.iter_mut().take(len).enumerate() {
+            *byte ^= self.key[i % self.key.len()];
+        }
+    }
 }
 
 impl Default for Document {
@@ -177,6 +380,7 @@ impl Default for Document {
             resources: ResourceMap::default(),
             text_direction: TextDirection::default(),
             epub_version: None,
+            diagnostics: Vec::new(),
         }
     }
 }
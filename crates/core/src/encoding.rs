@@ -103,11 +103,14 @@ fn normalize_content_node(node: &mut crate::document::ContentNode) {
                 }
             }
         }
-        crate::document::ContentNode::BlockQuote { children } => {
+        crate::document::ContentNode::BlockQuote { children }
+        | crate::document::ContentNode::Note { children, .. }
+        | crate::document::ContentNode::Container { children, .. } => {
             for n in children {
                 normalize_content_node(n);
             }
         }
+        crate::document::ContentNode::Styled { node, .. } => normalize_content_node(node),
         crate::document::ContentNode::CodeBlock { code, .. } => {
             *code = code.nfc().collect();
         }
@@ -132,7 +135,8 @@ fn normalize_inline(node: &mut crate::document::InlineNode) {
         crate::document::InlineNode::Strong(children) |
         crate::document::InlineNode::Link { children, .. } |
         crate::document::InlineNode::Superscript(children) |
-        crate::document::InlineNode::Subscript(children) => {
+        crate::document::InlineNode::Subscript(children) |
+        crate::document::InlineNode::Span { children, .. } => {
             for n in children {
                 normalize_inline(n);
             }
@@ -142,6 +146,7 @@ fn normalize_inline(node: &mut crate::document::InlineNode) {
             *base = base.nfc().collect();
             *annotation = annotation.nfc().collect();
         }
+        crate::document::InlineNode::NoteRef { label, .. } => *label = label.nfc().collect(),
         crate::document::InlineNode::LineBreak => {}
     }
 }
@@ -0,0 +1,405 @@
+//! Subsetting of OpenType `CFF ` tables: the charstrings of unused glyphs
+//! become an empty `endchar`, and the structures after the CharStrings INDEX
+//! are laid out again with updated offsets.
+
+use std::collections::BTreeSet;
+
+use super::sfnt::read_u16;
+
+/// A Type 2 charstring drawing nothing.
+const EMPTY_CHARSTRING: &[u8] = &[14];
+
+// DICT operators whose operands are offsets into the table.
+const CHARSET: u16 = 15;
+const ENCODING: u16 = 16;
+const CHAR_STRINGS: u16 = 17;
+const PRIVATE: u16 = 18;
+const SUBRS: u16 = 19;
+const FD_ARRAY: u16 = 0x0c24;
+const FD_SELECT: u16 = 0x0c25;
+
+struct Index<'a> {
+    items: Vec<&'a [u8]>,
+    /// Offset just past the INDEX.
+    end: usize,
+}
+
+fn read_index(data: &[u8], at: usize) -> Option<Index<'_>> {
+    let count = read_u16(data, at)? as usize;
+    if count == 0 {
+        return Some(Index {
+            items: Vec::new(),
+            end: at + 2,
+        });
+    }
+    let off_size = *data.get(at + 2)? as usize;
+    if !(1..=4).contains(&off_size) {
+        return None;
+    }
+    let offsets_at = at + 3;
+    let offset = |i: usize| {
+        let start = offsets_at + i * off_size;
+        let bytes = data.get(start..start + off_size)?;
+        Some(bytes.iter().fold(0usize, |v, &b| v << 8 | b as usize))
+    };
+    // Offsets count from the byte before the data.
+    let base = offsets_at + (count + 1) * off_size - 1;
+    let mut items = Vec::with_capacity(count);
+    let mut start = offset(0)?;
+    for i in 1..=count {
+        let end = offset(i)?;
+        items.push(data.get(base + start..base + end)?);
+        start = end;
+    }
+    Some(Index {
+        items,
+        end: base + start,
+    })
+}
+
+fn write_index(items: &[&[u8]], out: &mut Vec<u8>) {
+    out.extend((items.len() as u16).to_be_bytes());
+    if items.is_empty() {
+        return;
+    }
+    let last = 1 + items.iter().map(|i| i.len()).sum::<usize>();
+    let off_size = match last {
+        0..=0xff => 1,
+        0x100..=0xffff => 2,
+        0x1_0000..=0xff_ffff => 3,
+        _ => 4,
+    };
+    out.push(off_size as u8);
+    let mut offset = 1usize;
+    out.extend(&offset.to_be_bytes()[8 - off_size..]);
+    for item in items {
+        offset += item.len();
+        out.extend(&offset.to_be_bytes()[8 - off_size..]);
+    }
+    for item in items {
+        out.extend(*item);
+    }
+}
+
+/// A DICT entry: its operator and the encoded operands before it.
+struct Entry<'a> {
+    op: u16,
+    operands: &'a [u8],
+}
+
+fn parse_dict(data: &[u8]) -> Option<Vec<Entry<'_>>> {
+    let mut entries = Vec::new();
+    let (mut start, mut i) = (0, 0);
+    while i < data.len() {
+        match data[i] {
+            12 => {
+                let op = 0x0c00 | *data.get(i + 1)? as u16;
+                entries.push(Entry {
+                    op,
+                    operands: &data[start..i],
+                });
+                i += 2;
+                start = i;
+            }
+            b @ 0..=21 => {
+                entries.push(Entry {
+                    op: b as u16,
+                    operands: &data[start..i],
+                });
+                i += 1;
+                start = i;
+            }
+            28 => i += 3,
+            29 => i += 5,
+            30 => {
+                i += 1;
+                while i < data.len() {
+                    let nibbles = data[i];
+                    i += 1;
+                    if nibbles & 0x0f == 0x0f || nibbles >> 4 == 0x0f {
+                        break;
+                    }
+                }
+            }
+            32..=246 => i += 1,
+            247..=254 => i += 2,
+            _ => return None,
+        }
+    }
+    Some(entries)
+}
+
+/// Integer operands of a DICT entry; real numbers read as zero.
+fn integers(operands: &[u8]) -> Option<Vec<i32>> {
+    let mut values = Vec::new();
+    let mut i = 0;
+    while i < operands.len() {
+        let b = operands[i] as i32;
+        let next = |n: usize| operands.get(i + n).map(|&b| b as i32);
+        match b {
+            28 => {
+                values.push((next(1)? << 8 | next(2)?) as i16 as i32);
+                i += 3;
+            }
+            29 => {
+                values.push(next(1)? << 24 | next(2)? << 16 | next(3)? << 8 | next(4)?);
+                i += 5;
+            }
+            30 => {
+                i += 1;
+                while i < operands.len() {
+                    let nibbles = operands[i];
+                    i += 1;
+                    if nibbles & 0x0f == 0x0f || nibbles >> 4 == 0x0f {
+                        break;
+                    }
+                }
+                values.push(0);
+            }
+            32..=246 => {
+                values.push(b - 139);
+                i += 1;
+            }
+            247..=250 => {
+                values.push((b - 247) * 256 + next(1)? + 108);
+                i += 2;
+            }
+            251..=254 => {
+                values.push(-(b - 251) * 256 - next(1)? - 108);
+                i += 2;
+            }
+            _ => return None,
+        }
+    }
+    Some(values)
+}
+
+/// Write a DICT, giving the operators in `replace` new integer operands.
+/// Integers are always written in their five-byte form, so the size of the
+/// DICT does not depend on their values.
+fn write_dict(entries: &[Entry], replace: &[(u16, Vec<i32>)]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for entry in entries {
+        match replace.iter().find(|(op, _)| *op == entry.op) {
+            Some((_, values)) => {
+                for value in values {
+                    out.push(29);
+                    out.extend(value.to_be_bytes());
+                }
+            }
+            None => out.extend(entry.operands),
+        }
+        if entry.op > 0xff {
+            out.push(12);
+        }
+        out.push(entry.op as u8);
+    }
+    out
+}
+
+fn operands_of(entries: &[Entry], op: u16) -> Option<Vec<i32>> {
+    integers(entries.iter().find(|e| e.op == op)?.operands)
+}
+
+fn offset_of(entries: &[Entry], op: u16) -> Option<usize> {
+    usize::try_from(*operands_of(entries, op)?.last()?).ok()
+}
+
+/// A Private DICT with its local subroutines, copied as one block so the
+/// relative Subrs offset stays valid.
+struct Private<'a> {
+    size: i32,
+    block: &'a [u8],
+}
+
+fn read_private<'a>(cff: &'a [u8], dict: &[Entry]) -> Option<Option<Private<'a>>> {
+    let Some(values) = operands_of(dict, PRIVATE) else {
+        return Some(None);
+    };
+    let [size, offset] = values[..] else {
+        return None;
+    };
+    let (start, size_bytes) = (usize::try_from(offset).ok()?, usize::try_from(size).ok()?);
+    let private = cff.get(start..start.checked_add(size_bytes)?)?;
+    let mut end = start + size_bytes;
+    if let Some(subrs) = offset_of(&parse_dict(private)?, SUBRS) {
+        if subrs < size_bytes {
+            return None;
+        }
+        end = end.max(read_index(cff, start + subrs)?.end);
+    }
+    Some(Some(Private {
+        size,
+        block: cff.get(start..end)?,
+    }))
+}
+
+fn charset_len(cff: &[u8], at: usize, num_glyphs: usize) -> Option<usize> {
+    let format = *cff.get(at)?;
+    if format == 0 {
+        return Some(1 + 2 * num_glyphs.saturating_sub(1));
+    }
+    let range_len = match format {
+        1 => 3,
+        2 => 4,
+        _ => return None,
+    };
+    let (mut covered, mut pos) = (0, at + 1);
+    while covered < num_glyphs.saturating_sub(1) {
+        let left = match format {
+            1 => *cff.get(pos + 2)? as usize,
+            _ => read_u16(cff, pos + 2)? as usize,
+        };
+        covered += left + 1;
+        pos += range_len;
+    }
+    Some(pos - at)
+}
+
+fn encoding_len(cff: &[u8], at: usize) -> Option<usize> {
+    let format = *cff.get(at)?;
+    let count = *cff.get(at + 1)? as usize;
+    let mut len = match format & 0x7f {
+        0 => 2 + count,
+        1 => 2 + 2 * count,
+        _ => return None,
+    };
+    // Supplements follow when the high bit is set.
+    if format & 0x80 != 0 {
+        len += 1 + 3 * *cff.get(at + len)? as usize;
+    }
+    Some(len)
+}
+
+fn fd_select_len(cff: &[u8], at: usize, num_glyphs: usize) -> Option<usize> {
+    match *cff.get(at)? {
+        0 => Some(1 + num_glyphs),
+        3 => Some(5 + 3 * read_u16(cff, at + 1)? as usize),
+        _ => None,
+    }
+}
+
+/// Rebuild a `CFF ` table keeping only the outlines of `glyphs`.
+pub(super) fn subset(cff: &[u8], glyphs: &BTreeSet<u16>) -> Option<Vec<u8>> {
+    let header_len = *cff.get(2)? as usize;
+    let names = read_index(cff, header_len)?;
+    let top_dicts = read_index(cff, names.end)?;
+    let strings = read_index(cff, top_dicts.end)?;
+    let global_subrs = read_index(cff, strings.end)?;
+    let [top_dict] = top_dicts.items[..] else {
+        return None;
+    };
+    let top = parse_dict(top_dict)?;
+
+    let char_strings = read_index(cff, offset_of(&top, CHAR_STRINGS)?)?;
+    let num_glyphs = char_strings.items.len();
+    let charset = match offset_of(&top, CHARSET) {
+        Some(at) if at > 2 => Some(cff.get(at..at + charset_len(cff, at, num_glyphs)?)?),
+        _ => None,
+    };
+    let encoding = match offset_of(&top, ENCODING) {
+        Some(at) if at > 1 => Some(cff.get(at..at + encoding_len(cff, at)?)?),
+        _ => None,
+    };
+    let fd_select = match offset_of(&top, FD_SELECT) {
+        Some(at) => Some(cff.get(at..at + fd_select_len(cff, at, num_glyphs)?)?),
+        None => None,
+    };
+    let private = read_private(cff, &top)?;
+    let font_dicts = match offset_of(&top, FD_ARRAY) {
+        Some(at) => Some(
+            read_index(cff, at)?
+                .items
+                .into_iter()
+                .map(|dict| {
+                    let entries = parse_dict(dict)?;
+                    let private = read_private(cff, &entries)?;
+                    Some((entries, private))
+                })
+                .collect::<Option<Vec<_>>>()?,
+        ),
+        None => None,
+    };
+
+    let charstrings: Vec<&[u8]> = char_strings
+        .items
+        .iter()
+        .enumerate()
+        .map(|(g, cs)| match glyphs.contains(&(g as u16)) {
+            true => *cs,
+            false => EMPTY_CHARSTRING,
+        })
+        .collect();
+
+    // Everything up to the Global Subrs INDEX keeps its place; the Top DICT
+    // keeps its size because offsets are written as five-byte integers.
+    let mut top_replace: Vec<(u16, Vec<i32>)> = Vec::new();
+    for (op, present) in [
+        (CHARSET, charset.is_some()),
+        (ENCODING, encoding.is_some()),
+        (CHAR_STRINGS, true),
+        (FD_SELECT, fd_select.is_some()),
+        (FD_ARRAY, font_dicts.is_some()),
+    ] {
+        if present {
+            top_replace.push((op, vec![0]));
+        }
+    }
+    if let Some(ref p) = private {
+        top_replace.push((PRIVATE, vec![p.size, 0]));
+    }
+    let mut top_index = Vec::new();
+    write_index(&[&write_dict(&top, &top_replace)], &mut top_index);
+    let start = header_len
+        + (names.end - header_len)
+        + top_index.len()
+        + (global_subrs.end - top_dicts.end);
+
+    let mut body = Vec::new();
+    let place = |op: u16, data: &[u8], body: &mut Vec<u8>, replace: &mut Vec<(u16, Vec<i32>)>| {
+        let offset = (start + body.len()) as i32;
+        if let Some((_, values)) = replace.iter_mut().find(|(o, _)| *o == op) {
+            *values.last_mut().unwrap() = offset;
+        }
+        body.extend(data);
+    };
+    if let Some(data) = charset {
+        place(CHARSET, data, &mut body, &mut top_replace);
+    }
+    if let Some(data) = encoding {
+        place(ENCODING, data, &mut body, &mut top_replace);
+    }
+    if let Some(data) = fd_select {
+        place(FD_SELECT, data, &mut body, &mut top_replace);
+    }
+    let mut index = Vec::new();
+    write_index(&charstrings, &mut index);
+    place(CHAR_STRINGS, &index, &mut body, &mut top_replace);
+    if let Some(ref p) = private {
+        place(PRIVATE, p.block, &mut body, &mut top_replace);
+    }
+    if let Some(font_dicts) = font_dicts {
+        let mut dicts = Vec::new();
+        for (entries, private) in &font_dicts {
+            let mut replace = Vec::new();
+            if let Some(p) = private {
+                replace.push((PRIVATE, vec![p.size, 0]));
+                place(PRIVATE, p.block, &mut body, &mut replace);
+            }
+            dicts.push(write_dict(entries, &replace));
+        }
+        let dicts: Vec<&[u8]> = dicts.iter().map(Vec::as_slice).collect();
+        let mut index = Vec::new();
+        write_index(&dicts, &mut index);
+        place(FD_ARRAY, &index, &mut body, &mut top_replace);
+    }
+
+    let mut out = Vec::with_capacity(start + body.len());
+    out.extend(&cff[..names.end]);
+    let len_before = out.len();
+    write_index(&[&write_dict(&top, &top_replace)], &mut out);
+    debug_assert_eq!(out.len() - len_before, top_index.len());
+    out.extend(&cff[top_dicts.end..global_subrs.end]);
+    out.extend(body);
+    Some(out)
+}
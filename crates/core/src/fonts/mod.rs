@@ -0,0 +1,312 @@
+//! Embedded fonts: subsetting TrueType/OpenType/WOFF resources to the
+//! characters a book uses, and EPUB font obfuscation keys.
+//!
+//! Subsetting keeps glyph ids, so `cmap`, `hmtx`, `kern` and the layout
+//! tables stay valid; the outlines of unused glyphs are dropped from `glyf`
+//! or `CFF `, which is where nearly all of a font's size is.
+
+mod cff;
+mod sfnt;
+mod usage;
+
+use std::collections::BTreeSet;
+
+use sha1::{Digest, Sha1};
+use ttf_parser::gsub::{SingleSubstitution, SubstitutionSubtable};
+use ttf_parser::GlyphId;
+
+use crate::document::{FontObfuscation, ObfuscationAlgorithm, Resource};
+
+pub use usage::characters_by_font;
+
+/// Characters kept in every subset: spaces, hyphenation and the digits a
+/// reading system may render in generated text such as list markers.
+const ALWAYS_KEPT: &str = " \u{a0}-\u{ad}\u{2010}\u{2011}\u{2022}.0123456789";
+
+/// Whether a resource is a font `subset_font` may be able to handle.
+pub fn is_font(res: &Resource) -> bool {
+    let media_type = res.media_type.to_ascii_lowercase();
+    let extension = res
+        .filename
+        .as_deref()
+        .and_then(|f| f.rsplit_once('.'))
+        .map(|(_, ext)| ext.to_ascii_lowercase());
+    media_type.starts_with("font/")
+        || media_type.contains("font-")
+        || media_type.contains("opentype")
+        || media_type.contains("truetype")
+        || matches!(extension.as_deref(), Some("ttf" | "otf" | "woff"))
+}
+
+/// Subset a TrueType, OpenType (CFF) or WOFF 1.0 font to the glyphs needed
+/// for `chars`. Returns `None` for fonts that cannot be subset (collections,
+/// variable fonts, WOFF2) or when the result would not be smaller.
+pub fn subset_font(data: &[u8], chars: &BTreeSet<char>) -> Option<Vec<u8>> {
+    let subset = match data.get(..4)? {
+        b"wOFF" => {
+            let sfnt = sfnt::woff_to_sfnt(data)?;
+            sfnt::sfnt_to_woff(&subset_sfnt(&sfnt, chars)?, data)?
+        }
+        [0, 1, 0, 0] | b"OTTO" | b"true" => subset_sfnt(data, chars)?,
+        _ => return None,
+    };
+    (subset.len() < data.len()).then_some(subset)
+}
+
+fn subset_sfnt(data: &[u8], chars: &BTreeSet<char>) -> Option<Vec<u8>> {
+    let (version, mut tables) = sfnt::read_tables(data)?;
+    // Variation deltas are stored per glyph and would need rebuilding too.
+    if tables.contains_key(b"gvar") || tables.contains_key(b"CFF2") {
+        return None;
+    }
+    let face = ttf_parser::Face::parse(data, 0).ok()?;
+    let mut glyphs = BTreeSet::from([0u16]);
+    for c in chars.iter().copied().chain(ALWAYS_KEPT.chars()) {
+        if let Some(glyph) = face.glyph_index(c) {
+            glyphs.insert(glyph.0);
+        }
+    }
+    substitution_closure(&face, &mut glyphs);
+
+    if tables.contains_key(b"glyf") {
+        sfnt::subset_glyf(&mut tables, &mut glyphs)?;
+    } else {
+        let cff = cff::subset(tables.get(b"CFF ")?, &glyphs)?;
+        tables.insert(*b"CFF ", cff);
+    }
+    // A signature no longer matches the changed font.
+    tables.remove(b"DSIG");
+    let subset = sfnt::write_sfnt(version, &tables);
+
+    // Only trust the result if every kept glyph still draws the same.
+    let check = ttf_parser::Face::parse(&subset, 0).ok()?;
+    glyphs
+        .iter()
+        .all(|&g| check.glyph_bounding_box(GlyphId(g)) == face.glyph_bounding_box(GlyphId(g)))
+        .then_some(subset)
+}
+
+/// Add the glyphs GSUB can substitute for kept ones (ligatures, small caps,
+/// alternates), until nothing more is added.
+fn substitution_closure(face: &ttf_parser::Face, glyphs: &mut BTreeSet<u16>) {
+    let Some(gsub) = face.tables().gsub else {
+        return;
+    };
+    loop {
+        let mut added = Vec::new();
+        for lookup in gsub.lookups {
+            for subtable in lookup.subtables.into_iter::<SubstitutionSubtable>() {
+                let coverage = subtable.coverage();
+                for (glyph, index) in glyphs
+                    .iter()
+                    .filter_map(|&g| Some((g, coverage.get(GlyphId(g))?)))
+                {
+                    match subtable {
+                        SubstitutionSubtable::Single(SingleSubstitution::Format1 {
+                            delta, ..
+                        }) => added.push(glyph.wrapping_add(delta as u16)),
+                        SubstitutionSubtable::Single(SingleSubstitution::Format2 {
+                            substitutes,
+                            ..
+                        }) => added.extend(substitutes.get(index).map(|g| g.0)),
+                        SubstitutionSubtable::Multiple(m) => {
+                            if let Some(sequence) = m.sequences.get(index) {
+                                added.extend(sequence.substitutes.into_iter().map(|g| g.0));
+                            }
+                        }
+                        SubstitutionSubtable::Alternate(a) => {
+                            if let Some(set) = a.alternate_sets.get(index) {
+                                added.extend(set.alternates.into_iter().map(|g| g.0));
+                            }
+                        }
+                        SubstitutionSubtable::Ligature(l) => {
+                            for ligature in l.ligature_sets.get(index).into_iter().flatten() {
+                                if ligature
+                                    .components
+                                    .into_iter()
+                                    .all(|c| glyphs.contains(&c.0))
+                                {
+                                    added.push(ligature.glyph.0);
+                                }
+                            }
+                        }
+                        SubstitutionSubtable::ReverseChainSingle(r) => {
+                            added.extend(r.substitutes.get(index).map(|g| g.0))
+                        }
+                        SubstitutionSubtable::Context(_)
+                        | SubstitutionSubtable::ChainContext(_) => {}
+                    }
+                }
+            }
+        }
+        let before = glyphs.len();
+        glyphs.extend(added);
+        if glyphs.len() == before {
+            break;
+        }
+    }
+}
+
+/// IDPF obfuscation keyed by a book's unique identifier.
+pub fn idpf_obfuscation(unique_identifier: &str) -> FontObfuscation {
+    let uid: String = unique_identifier
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '\t' | '\r' | '\n'))
+        .collect();
+    FontObfuscation {
+        algorithm: ObfuscationAlgorithm::Idpf,
+        key: Sha1::digest(uid.as_bytes()).to_vec(),
+    }
+}
+
+/// Adobe obfuscation keyed by a `urn:uuid:` identifier.
+pub fn adobe_obfuscation(identifier: &str) -> Option<FontObfuscation> {
+    let hex: String = identifier
+        .trim()
+        .strip_prefix("urn:uuid:")?
+        .chars()
+        .filter(|&c| c != '-')
+        .collect();
+    if hex.len() != 32 {
+        return None;
+    }
+    let key = (0..16)
+        .map(|i| u8::from_str_radix(hex.get(2 * i..2 * i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    Some(FontObfuscation {
+        algorithm: ObfuscationAlgorithm::Adobe,
+        key,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::*;
+
+    /// A TrueType font mapping `A`, `B` and `C` to glyphs 1–3, where `C` is
+    /// a composite of `B`.
+    fn tiny_font() -> Vec<u8> {
+        let be16 = |v: u16| v.to_be_bytes();
+        let mut simple = Vec::new();
+        for v in [1u16, 0, 0, 100, 100, 2, 0] {
+            simple.extend(be16(v));
+        }
+        simple.extend([1, 1, 1]);
+        for v in [0u16, 100, 0, 0, 0, 100] {
+            simple.extend(be16(v));
+        }
+        let mut composite = Vec::new();
+        for v in [0xffffu16, 0, 0, 100, 100, 0x0003, 2, 10, 10] {
+            composite.extend(be16(v));
+        }
+
+        let mut glyf = Vec::new();
+        let mut loca = be16(0).to_vec();
+        for glyph in [&[][..], &simple, &simple, &composite] {
+            glyf.extend(glyph);
+            glyf.resize((glyf.len() + 3) & !3, 0);
+            loca.extend(be16((glyf.len() / 2) as u16));
+        }
+
+        let mut head = vec![0u8; 54];
+        head[..4].copy_from_slice(&0x0001_0000u32.to_be_bytes());
+        head[12..16].copy_from_slice(&0x5F0F_3CF5u32.to_be_bytes());
+        head[18..20].copy_from_slice(&be16(1000));
+        let mut hhea = vec![0u8; 36];
+        hhea[..4].copy_from_slice(&0x0001_0000u32.to_be_bytes());
+        hhea[34..36].copy_from_slice(&be16(4));
+        let mut maxp = 0x0000_5000u32.to_be_bytes().to_vec();
+        maxp.extend(be16(4));
+        let mut cmap = Vec::new();
+        for v in [0u16, 1, 3, 1, 0, 12] {
+            cmap.extend(be16(v));
+        }
+        for v in [4u16, 32, 0, 4, 4, 1, 0, 0x43, 0xffff, 0, 0x41, 0xffff] {
+            cmap.extend(be16(v));
+        }
+        for v in [1u16.wrapping_sub(0x41), 1, 0, 0] {
+            cmap.extend(be16(v));
+        }
+
+        let tables = sfnt::Tables::from([
+            (*b"cmap", cmap),
+            (*b"glyf", glyf),
+            (*b"head", head),
+            (*b"hhea", hhea),
+            (*b"hmtx", vec![0; 16]),
+            (*b"loca", loca),
+            (*b"maxp", maxp),
+        ]);
+        sfnt::write_sfnt(0x0001_0000, &tables)
+    }
+
+    #[test]
+    fn test_subset_keeps_used_glyphs_and_components() {
+        let font = tiny_font();
+        let face = ttf_parser::Face::parse(&font, 0).unwrap();
+        assert!(face.glyph_bounding_box(GlyphId(1)).is_some());
+
+        let subset = subset_font(&font, &BTreeSet::from(['C'])).unwrap();
+        assert!(subset.len() < font.len());
+        let face = ttf_parser::Face::parse(&subset, 0).unwrap();
+        assert_eq!(face.number_of_glyphs(), 4);
+        assert_eq!(face.glyph_index('C'), Some(GlyphId(3)));
+        assert!(face.glyph_bounding_box(GlyphId(1)).is_none());
+        assert!(face.glyph_bounding_box(GlyphId(2)).is_some());
+        assert!(face.glyph_bounding_box(GlyphId(3)).is_some());
+
+        // Nothing left to drop.
+        assert!(subset_font(&subset, &BTreeSet::from(['C'])).is_none());
+    }
+
+    #[test]
+    fn test_characters_by_font_follow_css_rules() {
+        let resource = |id: &str, media_type: &str, data: &[u8], filename: &str| Resource {
+            id: id.into(),
+            media_type: media_type.into(),
+            data: data.to_vec(),
+            filename: Some(filename.into()),
+            obfuscation: None,
+        };
+        let css = br#"/* headings */
+            @font-face { font-family: "Display"; src: url(../fonts/display.ttf) format("truetype"); }
+            @font-face { font-family: Body; src: url('../fonts/body.woff'); }
+            @media screen { h1, .title { font: bold 2em/1.2 Display, serif; } }
+            p { font-family: Body, serif; }
+            h1::before { content: "\2014 "; }"#;
+        let mut doc = Document::default();
+        doc.resources.insert(
+            "css".into(),
+            resource("css", "text/css", css, "css/book.css"),
+        );
+        for (id, file) in [
+            ("display", "fonts/display.ttf"),
+            ("body", "fonts/body.woff"),
+            ("spare", "fonts/spare.otf"),
+        ] {
+            doc.resources
+                .insert(id.into(), resource(id, "font/ttf", &[], file));
+        }
+        doc.content.push(Chapter {
+            id: "ch1".into(),
+            title: None,
+            content: vec![
+                ContentNode::Heading {
+                    level: 1,
+                    children: vec![InlineNode::Text("Hi".into())],
+                },
+                ContentNode::Paragraph {
+                    children: vec![InlineNode::Text("yo".into())],
+                },
+            ],
+            text_direction: None,
+        });
+
+        let chars = characters_by_font(&doc);
+        let set = |s: &str| s.chars().collect::<BTreeSet<char>>();
+        assert_eq!(chars["display"], set("HhIi\u{2014}"));
+        assert_eq!(chars["body"], set("YyOo\u{2014}"));
+        assert_eq!(chars["spare"], set("HhIiYyOo\u{2014}"));
+    }
+}
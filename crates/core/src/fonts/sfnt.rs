@@ -0,0 +1,283 @@
+//! The sfnt container (TrueType/OpenType table directory), WOFF 1.0
+//! packing, and `glyf`/`loca` subsetting.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{Read, Write};
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+/// Tables by tag, in the order the table directory lists them.
+pub(super) type Tables = BTreeMap<[u8; 4], Vec<u8>>;
+
+pub(super) fn read_u16(data: &[u8], at: usize) -> Option<u16> {
+    let b = data.get(at..at.checked_add(2)?)?;
+    Some(u16::from_be_bytes([b[0], b[1]]))
+}
+
+pub(super) fn read_u32(data: &[u8], at: usize) -> Option<u32> {
+    let b = data.get(at..at.checked_add(4)?)?;
+    Some(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// The sfnt version and tables of a single-font file.
+pub(super) fn read_tables(data: &[u8]) -> Option<(u32, Tables)> {
+    let version = read_u32(data, 0)?;
+    let count = read_u16(data, 4)? as usize;
+    let mut tables = Tables::new();
+    for i in 0..count {
+        let record = 12 + 16 * i;
+        let tag: [u8; 4] = data.get(record..record + 4)?.try_into().ok()?;
+        let offset = read_u32(data, record + 8)? as usize;
+        let length = read_u32(data, record + 12)? as usize;
+        let table = data.get(offset..offset.checked_add(length)?)?;
+        tables.insert(tag, table.to_vec());
+    }
+    Some((version, tables))
+}
+
+fn checksum(data: &[u8]) -> u32 {
+    data.chunks(4).fold(0u32, |sum, chunk| {
+        let mut word = [0u8; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        sum.wrapping_add(u32::from_be_bytes(word))
+    })
+}
+
+/// Checksum of a table as recorded in the table directory; `head` is summed
+/// with its `checkSumAdjustment` zeroed.
+fn table_checksum(tag: &[u8; 4], data: &[u8]) -> u32 {
+    if tag == b"head" && data.len() >= 12 {
+        let mut head = data.to_vec();
+        head[8..12].fill(0);
+        checksum(&head)
+    } else {
+        checksum(data)
+    }
+}
+
+/// Assemble an sfnt file, filling in the table checksums and
+/// `head.checkSumAdjustment`.
+pub(super) fn write_sfnt(version: u32, tables: &Tables) -> Vec<u8> {
+    let count = tables.len() as u16;
+    let entry_selector = 15u16.saturating_sub(count.leading_zeros() as u16);
+    let search_range = 16u16 << entry_selector;
+    let mut out = Vec::new();
+    out.extend(version.to_be_bytes());
+    out.extend(count.to_be_bytes());
+    out.extend(search_range.to_be_bytes());
+    out.extend(entry_selector.to_be_bytes());
+    out.extend((count * 16).saturating_sub(search_range).to_be_bytes());
+
+    let mut offset = 12 + 16 * tables.len();
+    let mut head_at = None;
+    for (tag, data) in tables {
+        if tag == b"head" {
+            head_at = Some(offset);
+        }
+        out.extend(tag);
+        out.extend(table_checksum(tag, data).to_be_bytes());
+        out.extend((offset as u32).to_be_bytes());
+        out.extend((data.len() as u32).to_be_bytes());
+        offset += (data.len() + 3) & !3;
+    }
+    for data in tables.values() {
+        out.extend(data);
+        out.resize((out.len() + 3) & !3, 0);
+    }
+
+    if let Some(at) = head_at.filter(|at| at + 12 <= out.len()) {
+        out[at + 8..at + 12].fill(0);
+        let adjustment = 0xB1B0_AFBAu32.wrapping_sub(checksum(&out));
+        out[at + 8..at + 12].copy_from_slice(&adjustment.to_be_bytes());
+    }
+    out
+}
+
+/// Unpack a WOFF 1.0 file into a plain sfnt.
+pub(super) fn woff_to_sfnt(data: &[u8]) -> Option<Vec<u8>> {
+    let flavor = read_u32(data, 4)?;
+    let count = read_u16(data, 12)? as usize;
+    let mut tables = Tables::new();
+    for i in 0..count {
+        let record = 44 + 20 * i;
+        let tag: [u8; 4] = data.get(record..record + 4)?.try_into().ok()?;
+        let offset = read_u32(data, record + 4)? as usize;
+        let stored_len = read_u32(data, record + 8)? as usize;
+        let orig_len = read_u32(data, record + 12)? as usize;
+        let stored = data.get(offset..offset.checked_add(stored_len)?)?;
+        let table = if stored_len < orig_len {
+            let mut table = Vec::new();
+            ZlibDecoder::new(stored)
+                .take(orig_len as u64 + 1)
+                .read_to_end(&mut table)
+                .ok()?;
+            table
+        } else {
+            stored.to_vec()
+        };
+        if table.len() != orig_len {
+            return None;
+        }
+        tables.insert(tag, table);
+    }
+    Some(write_sfnt(flavor, &tables))
+}
+
+/// Pack an sfnt as WOFF 1.0, keeping the version, metadata and private data
+/// blocks of the `original` WOFF file.
+pub(super) fn sfnt_to_woff(sfnt: &[u8], original: &[u8]) -> Option<Vec<u8>> {
+    let (flavor, tables) = read_tables(sfnt)?;
+    let dir_len = 44 + 20 * tables.len();
+    let mut dir = Vec::new();
+    let mut body = Vec::new();
+    for (tag, table) in &tables {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(table).ok()?;
+        let compressed = encoder.finish().ok()?;
+        let stored = if compressed.len() < table.len() {
+            &compressed
+        } else {
+            table
+        };
+        dir.extend(tag);
+        dir.extend(((dir_len + body.len()) as u32).to_be_bytes());
+        dir.extend((stored.len() as u32).to_be_bytes());
+        dir.extend((table.len() as u32).to_be_bytes());
+        dir.extend(table_checksum(tag, table).to_be_bytes());
+        body.extend(stored);
+        body.resize((body.len() + 3) & !3, 0);
+    }
+
+    let block = |offset_at: usize, len_at: usize| -> Option<&[u8]> {
+        let offset = read_u32(original, offset_at)? as usize;
+        let len = read_u32(original, len_at)? as usize;
+        original.get(offset..offset.checked_add(len)?)
+    };
+    let meta = block(24, 28)?;
+    let meta_orig_len = read_u32(original, 32)?;
+    let private = block(36, 40)?;
+    let meta_offset = if meta.is_empty() {
+        0
+    } else {
+        dir_len + body.len()
+    };
+    body.extend(meta);
+    if !private.is_empty() {
+        body.resize((body.len() + 3) & !3, 0);
+    }
+    let private_offset = if private.is_empty() {
+        0
+    } else {
+        dir_len + body.len()
+    };
+    body.extend(private);
+
+    let mut out = Vec::with_capacity(dir_len + body.len());
+    out.extend(b"wOFF");
+    out.extend(flavor.to_be_bytes());
+    out.extend(((dir_len + body.len()) as u32).to_be_bytes());
+    out.extend((tables.len() as u16).to_be_bytes());
+    out.extend(0u16.to_be_bytes());
+    out.extend((sfnt.len() as u32).to_be_bytes());
+    out.extend(original.get(20..24)?);
+    out.extend((meta_offset as u32).to_be_bytes());
+    out.extend((meta.len() as u32).to_be_bytes());
+    out.extend(meta_orig_len.to_be_bytes());
+    out.extend((private_offset as u32).to_be_bytes());
+    out.extend((private.len() as u32).to_be_bytes());
+    out.extend(dir);
+    out.extend(body);
+    Some(out)
+}
+
+/// Empty the outlines of glyphs outside `glyphs`, after adding the
+/// components of kept composite glyphs, and rebuild `loca` to match.
+pub(super) fn subset_glyf(tables: &mut Tables, glyphs: &mut BTreeSet<u16>) -> Option<()> {
+    let long = read_u16(tables.get(b"head")?, 50)? == 1;
+    let num_glyphs = read_u16(tables.get(b"maxp")?, 4)? as usize;
+    let loca = tables.get(b"loca")?;
+    let glyf = tables.get(b"glyf")?;
+    let offsets: Vec<usize> = (0..=num_glyphs)
+        .map(|i| match long {
+            true => read_u32(loca, 4 * i).map(|o| o as usize),
+            false => read_u16(loca, 2 * i).map(|o| o as usize * 2),
+        })
+        .collect::<Option<_>>()?;
+    let glyph = |g: usize| {
+        let (start, end) = (offsets[g], offsets[g + 1]);
+        glyf.get(start..end.max(start))
+    };
+
+    let mut pending: Vec<u16> = glyphs.iter().copied().collect();
+    while let Some(g) = pending.pop() {
+        if g as usize >= num_glyphs {
+            continue;
+        }
+        for component in components(glyph(g as usize)?) {
+            if glyphs.insert(component) {
+                pending.push(component);
+            }
+        }
+    }
+
+    let mut new_glyf = Vec::new();
+    let mut new_offsets = vec![0];
+    for g in 0..num_glyphs {
+        if glyphs.contains(&(g as u16)) {
+            new_glyf.extend(glyph(g)?);
+            new_glyf.resize((new_glyf.len() + 3) & !3, 0);
+        }
+        new_offsets.push(new_glyf.len());
+    }
+    // Short offsets store half the byte offset in 16 bits.
+    let long = new_glyf.len() > 0x1FFFE;
+    let mut new_loca = Vec::new();
+    for offset in new_offsets {
+        match long {
+            true => new_loca.extend((offset as u32).to_be_bytes()),
+            false => new_loca.extend(((offset / 2) as u16).to_be_bytes()),
+        }
+    }
+
+    let head = tables.get_mut(b"head")?;
+    head.get_mut(50..52)?
+        .copy_from_slice(&(long as u16).to_be_bytes());
+    tables.insert(*b"glyf", new_glyf);
+    tables.insert(*b"loca", new_loca);
+    Some(())
+}
+
+/// Glyph ids used by a composite glyph.
+fn components(data: &[u8]) -> Vec<u16> {
+    const ARGS_ARE_WORDS: u16 = 0x0001;
+    const HAVE_SCALE: u16 = 0x0008;
+    const MORE_COMPONENTS: u16 = 0x0020;
+    const HAVE_XY_SCALE: u16 = 0x0040;
+    const HAVE_2X2: u16 = 0x0080;
+
+    let mut out = Vec::new();
+    // Composite glyphs have a negative contour count.
+    if !read_u16(data, 0).is_some_and(|n| (n as i16) < 0) {
+        return out;
+    }
+    let mut at = 10;
+    while let (Some(flags), Some(glyph)) = (read_u16(data, at), read_u16(data, at + 2)) {
+        out.push(glyph);
+        at += 4 + if flags & ARGS_ARE_WORDS != 0 { 4 } else { 2 };
+        at += if flags & HAVE_SCALE != 0 {
+            2
+        } else if flags & HAVE_XY_SCALE != 0 {
+            4
+        } else if flags & HAVE_2X2 != 0 {
+            8
+        } else {
+            0
+        };
+        if flags & MORE_COMPONENTS == 0 {
+            break;
+        }
+    }
+    out
+}
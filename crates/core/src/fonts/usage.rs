@@ -0,0 +1,437 @@
+//! Which characters each embedded font has to render, from the stylesheets'
+//! `@font-face` and `font-family` rules and the text of the IR.
+//!
+//! The answer errs on the side of keeping characters: a rule applies to all
+//! text under any element it could match, selectors are judged by their
+//! last compound only, and both cases of every letter are kept so
+//! `text-transform` still has glyphs to use.
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::css::{at_rule_name, declarations, next_rule, strip_comments, urls};
+use crate::document::*;
+use crate::writers::find_resource;
+
+/// What the last compound of a selector matches.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Scope {
+    /// Root-level selectors (`body`, `*`, `:root`, ids) and anything not
+    /// understood: the rule may apply anywhere.
+    All,
+    Element(String),
+    Class(String),
+}
+
+#[derive(Default)]
+struct Stylesheets {
+    /// Resource ids of the faces declared for each lower-cased family.
+    faces: HashMap<String, Vec<String>>,
+    /// Families named by `font-family`/`font` declarations, by scope.
+    rules: HashMap<Scope, Vec<String>>,
+    /// Text from `content:` declarations, which may use any font.
+    generated: String,
+}
+
+/// The characters each font resource needs, by resource id. Fonts that no
+/// rule is known to use get every character of the book.
+pub fn characters_by_font(doc: &Document) -> HashMap<String, BTreeSet<char>> {
+    let mut css = Stylesheets::default();
+    for (_, res) in doc.resources.iter() {
+        if res.media_type == "text/css" {
+            parse_stylesheet(
+                doc,
+                &strip_comments(&String::from_utf8_lossy(&res.data)),
+                &mut css,
+            );
+        }
+    }
+
+    let mut by_family: HashMap<&str, BTreeSet<char>> = HashMap::new();
+    let mut all = BTreeSet::new();
+    {
+        let mut walker = Walker {
+            css: &css,
+            scopes: Vec::new(),
+            by_family: &mut by_family,
+            all: &mut all,
+        };
+        for chapter in &doc.content {
+            if let Some(title) = &chapter.title {
+                walker.text_everywhere(title);
+            }
+            walker.blocks(&chapter.content);
+        }
+        let mut toc = doc.toc.iter().collect::<Vec<_>>();
+        while let Some(entry) = toc.pop() {
+            walker.text_everywhere(&entry.title);
+            toc.extend(&entry.children);
+        }
+        walker.text_everywhere(&css.generated);
+        if let Some(title) = &doc.metadata.title {
+            walker.text_everywhere(title);
+        }
+    }
+
+    let mut out: HashMap<String, BTreeSet<char>> = HashMap::new();
+    for (family, ids) in &css.faces {
+        let used = css.rules.values().flatten().any(|f| f == family);
+        let chars = match by_family.get(family.as_str()) {
+            Some(chars) if used => chars,
+            _ if used => continue,
+            _ => &all,
+        };
+        for id in ids {
+            out.entry(id.clone()).or_default().extend(chars);
+        }
+    }
+    for (id, res) in doc.resources.iter() {
+        if super::is_font(res) && !out.contains_key(id) {
+            out.insert(id.clone(), all.clone());
+        }
+    }
+    out
+}
+
+struct Walker<'c, 'm> {
+    css: &'c Stylesheets,
+    /// Element names and classes of the enclosing nodes.
+    scopes: Vec<Scope>,
+    by_family: &'m mut HashMap<&'c str, BTreeSet<char>>,
+    all: &'m mut BTreeSet<char>,
+}
+
+impl Walker<'_, '_> {
+    fn text(&mut self, text: &str) {
+        add_text(self.all, text);
+        let css = self.css;
+        let families = std::iter::once(&Scope::All)
+            .chain(&self.scopes)
+            .filter_map(|scope| css.rules.get(scope))
+            .flatten();
+        for family in families {
+            add_text(self.by_family.entry(family.as_str()).or_default(), text);
+        }
+    }
+
+    /// Text outside any styled element we can see, such as titles in
+    /// generated navigation: every font may render it.
+    fn text_everywhere(&mut self, text: &str) {
+        add_text(self.all, text);
+        for family in self.css.rules.values().flatten() {
+            add_text(self.by_family.entry(family.as_str()).or_default(), text);
+        }
+    }
+
+    fn within(&mut self, scopes: &[Scope], f: impl FnOnce(&mut Self)) {
+        let depth = self.scopes.len();
+        self.scopes.extend_from_slice(scopes);
+        f(self);
+        self.scopes.truncate(depth);
+    }
+
+    fn attrs(attrs: &Attributes, element: &str) -> Vec<Scope> {
+        let mut scopes = vec![Scope::Element(element.to_string())];
+        for class in attrs.class.iter().flat_map(|c| c.split_whitespace()) {
+            scopes.push(Scope::Class(class.to_string()));
+        }
+        scopes
+    }
+
+    fn blocks(&mut self, nodes: &[ContentNode]) {
+        for node in nodes {
+            self.block(node, &[]);
+        }
+    }
+
+    /// Walk a block; `extra` holds the scopes of a `Styled` wrapper, which
+    /// apply to the block's own element.
+    fn block(&mut self, node: &ContentNode, extra: &[Scope]) {
+        let element = |name: &str| {
+            let mut scopes = vec![Scope::Element(name.to_string())];
+            scopes.extend_from_slice(extra);
+            scopes
+        };
+        match node {
+            ContentNode::Paragraph { children } => {
+                self.within(&element("p"), |w| w.inlines(children))
+            }
+            ContentNode::Heading { level, children } => {
+                self.within(&element(&format!("h{}", level)), |w| w.inlines(children))
+            }
+            ContentNode::List { ordered, items } => {
+                let list = element(if *ordered { "ol" } else { "ul" });
+                self.within(&list, |w| {
+                    for item in items {
+                        w.within(&[Scope::Element("li".into())], |w| w.blocks(item));
+                    }
+                })
+            }
+            ContentNode::Table { headers, rows } => self.within(&element("table"), |w| {
+                for cell in headers {
+                    w.within(&[Scope::Element("th".into())], |w| w.inlines(cell));
+                }
+                for cell in rows.iter().flatten() {
+                    w.within(&[Scope::Element("td".into())], |w| w.inlines(cell));
+                }
+            }),
+            ContentNode::BlockQuote { children } => {
+                self.within(&element("blockquote"), |w| w.blocks(children))
+            }
+            ContentNode::CodeBlock { code, .. } => {
+                let mut scopes = element("pre");
+                scopes.push(Scope::Element("code".into()));
+                self.within(&scopes, |w| w.text(code))
+            }
+            ContentNode::Image {
+                alt_text, caption, ..
+            } => self.within(&element("figure"), |w| {
+                if let Some(caption) = caption {
+                    w.within(&[Scope::Element("figcaption".into())], |w| w.text(caption));
+                }
+                // Shown when the image cannot be.
+                if let Some(alt) = alt_text {
+                    w.text(alt);
+                }
+            }),
+            ContentNode::HorizontalRule => {}
+            ContentNode::RawHtml(html) => self.text_everywhere(&strip_tags(html)),
+            ContentNode::Note { children, .. } => {
+                self.within(&element("aside"), |w| w.blocks(children))
+            }
+            ContentNode::Styled { attrs, node } => {
+                let mut scopes = Self::attrs(attrs, "div");
+                // The wrapper's class lands on the node's own element.
+                scopes.remove(0);
+                scopes.extend_from_slice(extra);
+                self.block(node, &scopes)
+            }
+            ContentNode::Container { attrs, children } => {
+                let mut scopes = Self::attrs(attrs, "div");
+                scopes.push(Scope::Element("section".into()));
+                scopes.extend_from_slice(extra);
+                self.within(&scopes, |w| w.blocks(children))
+            }
+        }
+    }
+
+    fn inlines(&mut self, nodes: &[InlineNode]) {
+        for node in nodes {
+            match node {
+                InlineNode::Text(text) => self.text(text),
+                InlineNode::Emphasis(c) => self.within(
+                    &[Scope::Element("em".into()), Scope::Element("i".into())],
+                    |w| w.inlines(c),
+                ),
+                InlineNode::Strong(c) => self.within(
+                    &[Scope::Element("strong".into()), Scope::Element("b".into())],
+                    |w| w.inlines(c),
+                ),
+                InlineNode::Code(code) => {
+                    self.within(&[Scope::Element("code".into())], |w| w.text(code))
+                }
+                InlineNode::Link { children, .. } => {
+                    self.within(&[Scope::Element("a".into())], |w| w.inlines(children))
+                }
+                InlineNode::Superscript(c) => {
+                    self.within(&[Scope::Element("sup".into())], |w| w.inlines(c))
+                }
+                InlineNode::Subscript(c) => {
+                    self.within(&[Scope::Element("sub".into())], |w| w.inlines(c))
+                }
+                InlineNode::Ruby { base, annotation } => {
+                    self.within(&[Scope::Element("ruby".into())], |w| {
+                        w.text(base);
+                        w.within(&[Scope::Element("rt".into())], |w| w.text(annotation));
+                    })
+                }
+                InlineNode::LineBreak => {}
+                InlineNode::NoteRef { label, .. } => self.within(
+                    &[Scope::Element("a".into()), Scope::Element("sup".into())],
+                    |w| w.text(label),
+                ),
+                InlineNode::Span { attrs, children } => {
+                    self.within(&Self::attrs(attrs, "span"), |w| w.inlines(children))
+                }
+            }
+        }
+    }
+}
+
+/// Add the characters of `text`, with both cases of each letter.
+fn add_text(chars: &mut BTreeSet<char>, text: &str) {
+    for c in text.chars() {
+        chars.insert(c);
+        if c.is_alphabetic() {
+            chars.extend(c.to_uppercase());
+            chars.extend(c.to_lowercase());
+        }
+    }
+}
+
+fn parse_stylesheet(doc: &Document, css: &str, out: &mut Stylesheets) {
+    let mut rest = css;
+    while let Some((prelude, block, next)) = next_rule(rest) {
+        rest = next;
+        let Some(block) = block else {
+            continue;
+        };
+        let prelude = prelude.trim();
+        match at_rule_name(prelude) {
+            Some(r) if r == "font-face" => {
+                let decls = declarations(block);
+                let family = decls.iter().find(|(n, _)| n == "font-family");
+                let Some(family) = family.and_then(|(_, v)| font_families(v).into_iter().next())
+                else {
+                    continue;
+                };
+                let ids = out.faces.entry(family).or_default();
+                for (_, src) in decls.iter().filter(|(n, _)| n == "src") {
+                    for url in urls(src) {
+                        if let Some(res) = find_resource(doc, &url) {
+                            if !ids.contains(&res.id) {
+                                ids.push(res.id.clone());
+                            }
+                        }
+                    }
+                }
+            }
+            Some(r) if matches!(r.as_str(), "media" | "supports" | "layer" | "document") => {
+                parse_stylesheet(doc, block, out)
+            }
+            Some(_) => {}
+            None => {
+                for (name, value) in declarations(block) {
+                    let families = match name.as_str() {
+                        "font-family" => font_families(&value),
+                        "font" => font_shorthand_families(&value),
+                        "content" => {
+                            out.generated.push_str(&quoted_strings(&value));
+                            continue;
+                        }
+                        _ => continue,
+                    };
+                    if families.is_empty() {
+                        continue;
+                    }
+                    for selector in prelude.split(',') {
+                        let rule = out.rules.entry(scope(selector)).or_default();
+                        for family in &families {
+                            if !rule.contains(family) {
+                                rule.push(family.clone());
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Lower-cased family names of a `font-family` value, without quotes.
+fn font_families(value: &str) -> Vec<String> {
+    value
+        .trim()
+        .trim_end_matches("!important")
+        .split(',')
+        .map(|f| {
+            f.trim()
+                .trim_matches(|c| c == '"' || c == '\'')
+                .trim()
+                .to_lowercase()
+        })
+        .filter(|f| !f.is_empty())
+        .collect()
+}
+
+/// Families of a `font` shorthand: everything after the size.
+fn font_shorthand_families(value: &str) -> Vec<String> {
+    const SIZES: &[&str] = &[
+        "xx-small", "x-small", "small", "medium", "large", "x-large", "xx-large", "smaller",
+        "larger",
+    ];
+    let mut rest = value.trim();
+    while let Some((word, tail)) = rest.split_once(char::is_whitespace) {
+        let size = word.split('/').next().unwrap_or(word).to_ascii_lowercase();
+        rest = tail.trim_start();
+        if size.starts_with(|c: char| c.is_ascii_digit() || c == '.')
+            || SIZES.contains(&size.as_str())
+        {
+            // A line height may follow as a separate `/ 1.2`.
+            if let Some(tail) = rest.strip_prefix('/') {
+                rest = tail
+                    .trim_start()
+                    .split_once(char::is_whitespace)
+                    .map_or("", |(_, t)| t);
+            }
+            return font_families(rest);
+        }
+    }
+    Vec::new()
+}
+
+/// The contents of the quoted strings in a value, with CSS escapes resolved.
+fn quoted_strings(value: &str) -> String {
+    let mut out = String::new();
+    let mut chars = value.chars().peekable();
+    let mut quote = None;
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (None, _) => {}
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), '\\') => {
+                let mut hex = String::new();
+                while hex.len() < 6 && chars.peek().is_some_and(|c| c.is_ascii_hexdigit()) {
+                    hex.extend(chars.next());
+                }
+                if hex.is_empty() {
+                    out.extend(chars.next());
+                    continue;
+                }
+                if chars.peek() == Some(&' ') {
+                    chars.next();
+                }
+                out.extend(u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32));
+            }
+            (Some(_), c) => out.push(c),
+        }
+    }
+    out
+}
+
+/// What the last compound selector of `selector` matches.
+fn scope(selector: &str) -> Scope {
+    let last = selector
+        .rsplit(|c: char| c.is_whitespace() || matches!(c, '>' | '+' | '~'))
+        .find(|s| !s.is_empty())
+        .unwrap_or("");
+    // Pseudo-classes and attribute selectors only narrow the match.
+    let compound = last.split([':', '[']).next().unwrap_or("");
+    if let Some((_, class)) = compound.split_once('.') {
+        let class = class.split(['.', '#']).next().unwrap_or(class);
+        return Scope::Class(class.to_string());
+    }
+    let element = compound
+        .split('#')
+        .next()
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    match element.as_str() {
+        "" | "*" | "html" | "body" => Scope::All,
+        _ => Scope::Element(element),
+    }
+}
+
+fn strip_tags(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            c if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    out
+}
@@ -2,12 +2,15 @@ pub mod accessibility;
 pub mod config;
 pub mod convert;
 pub mod cover;
+pub mod css;
 pub mod dedup;
 pub mod detect;
 pub mod document;
 pub mod encoding;
 pub mod error;
+pub mod fonts;
 pub mod library;
+pub mod links;
 pub mod lookup;
 pub mod merge;
 pub mod meta;
@@ -19,11 +22,14 @@ pub mod repair;
 pub mod security;
 pub mod split;
 pub mod stats;
+pub mod toc;
 pub mod transform;
 pub mod validate;
 pub mod watch;
 pub mod writers;
 
+pub(crate) mod xml;
+
 pub mod prelude {
     pub use crate::document::*;
     pub use crate::error::*;
@@ -0,0 +1,383 @@
+//! Internal links: resolving `chapter-id#anchor` hrefs against the document,
+//! reporting the ones that lead nowhere and repairing those that can be.
+
+use std::collections::HashSet;
+
+use crate::document::*;
+use crate::validate::{Severity, ValidationIssue};
+use crate::writers::chapter_index;
+
+/// Where an href leads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkTarget<'a> {
+    /// A URL with a scheme (`https:`, `mailto:`), left alone.
+    External,
+    /// A chapter, and an anchor in it when the href has a fragment.
+    Internal {
+        chapter: usize,
+        anchor: Option<&'a str>,
+    },
+    /// The chapter exists but has no element with the fragment's id.
+    MissingAnchor { chapter: usize, anchor: &'a str },
+    /// No chapter matches.
+    Dangling,
+}
+
+/// Anchor ids of every chapter, for resolving links.
+pub struct LinkIndex<'d> {
+    doc: &'d Document,
+    anchors: Vec<HashSet<&'d str>>,
+}
+
+impl<'d> LinkIndex<'d> {
+    pub fn new(doc: &'d Document) -> Self {
+        let anchors = doc
+            .content
+            .iter()
+            .map(|c| {
+                let mut ids = HashSet::new();
+                block_anchors(&c.content, &mut ids);
+                ids
+            })
+            .collect();
+        Self { doc, anchors }
+    }
+
+    /// Resolve an IR href. Chapters match on id, file name or stem; a bare
+    /// `#anchor` matches the first chapter holding the anchor.
+    pub fn resolve<'h>(&self, href: &'h str) -> LinkTarget<'h> {
+        if is_external(href) {
+            return LinkTarget::External;
+        }
+        let (path, anchor) = match href.split_once('#') {
+            Some((path, anchor)) => (path, Some(anchor).filter(|a| !a.is_empty())),
+            None => (href, None),
+        };
+        if path.is_empty() {
+            return match anchor.and_then(|a| self.anchors.iter().position(|ids| ids.contains(a))) {
+                Some(chapter) => LinkTarget::Internal { chapter, anchor },
+                None => LinkTarget::Dangling,
+            };
+        }
+        let Some(chapter) = chapter_index(self.doc, path) else {
+            return LinkTarget::Dangling;
+        };
+        match anchor {
+            Some(a) if !self.anchors[chapter].contains(a) => {
+                LinkTarget::MissingAnchor { chapter, anchor: a }
+            }
+            _ => LinkTarget::Internal { chapter, anchor },
+        }
+    }
+}
+
+/// Whether an href has a URL scheme (`https:`, `mailto:`, `data:`).
+pub fn is_external(href: &str) -> bool {
+    let Some((scheme, _)) = href.split_once(':') else {
+        return false;
+    };
+    scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+}
+
+/// Internal links and TOC entries that lead nowhere, as `dangling-link`
+/// issues. Links whose chapter exists but whose anchor does not can be
+/// fixed by `fix_links`.
+pub fn check_links(doc: &Document) -> Vec<ValidationIssue> {
+    let index = LinkIndex::new(doc);
+    let mut issues = Vec::new();
+    let mut check = |href: &str, location: String| {
+        let auto_fixable = match index.resolve(href) {
+            LinkTarget::External | LinkTarget::Internal { .. } => return,
+            LinkTarget::MissingAnchor { .. } => true,
+            LinkTarget::Dangling => false,
+        };
+        issues.push(ValidationIssue {
+            severity: Severity::Warning,
+            code: "dangling-link".to_string(),
+            message: format!("Link target '{}' does not exist", href),
+            location: Some(location),
+            auto_fixable,
+        });
+    };
+
+    for (i, chapter) in doc.content.iter().enumerate() {
+        visit_hrefs(&chapter.content, &mut |href| {
+            check(href, format!("chapter[{}]", i))
+        });
+    }
+    fn toc_hrefs<'a>(entries: &'a [TocEntry], out: &mut Vec<&'a str>) {
+        for entry in entries {
+            // Grouping entries may have no target of their own.
+            if !entry.href.is_empty() || entry.children.is_empty() {
+                out.push(&entry.href);
+            }
+            toc_hrefs(&entry.children, out);
+        }
+    }
+    let mut hrefs = Vec::new();
+    toc_hrefs(&doc.toc, &mut hrefs);
+    for href in hrefs {
+        check(href, "toc".to_string());
+    }
+    issues
+}
+
+/// Rewrite internal links to the canonical `chapter-id#anchor` form, pointing
+/// links with a missing anchor at the top of their chapter. Dangling links are
+/// left for `check_links` to report. Returns the number of links changed.
+pub fn fix_links(doc: &mut Document) -> usize {
+    let index = LinkIndex::new(doc);
+    let canonical = |href: &str| -> Option<String> {
+        let fixed = match index.resolve(href) {
+            LinkTarget::Internal {
+                chapter,
+                anchor: Some(a),
+            } => format!("{}#{}", doc.content[chapter].id, a),
+            LinkTarget::Internal { chapter, .. } | LinkTarget::MissingAnchor { chapter, .. } => {
+                doc.content[chapter].id.clone()
+            }
+            LinkTarget::External | LinkTarget::Dangling => return None,
+        };
+        (fixed != href).then_some(fixed)
+    };
+
+    // Work out the changes first; the index borrows the document.
+    let changes: Vec<Vec<Option<String>>> = doc
+        .content
+        .iter()
+        .map(|c| {
+            let mut out = Vec::new();
+            visit_hrefs(&c.content, &mut |href| out.push(canonical(href)));
+            out
+        })
+        .collect();
+    let mut fixed = 0;
+    for (chapter, changes) in doc.content.iter_mut().zip(changes) {
+        let mut changes = changes.into_iter();
+        visit_hrefs_mut(&mut chapter.content, &mut |href| {
+            if let Some(new) = changes.next().flatten() {
+                *href = new;
+                fixed += 1;
+            }
+        });
+    }
+    fixed
+}
+
+fn block_anchors<'a>(nodes: &'a [ContentNode], ids: &mut HashSet<&'a str>) {
+    for node in nodes {
+        match node {
+            ContentNode::Paragraph { children } | ContentNode::Heading { children, .. } => {
+                inline_anchors(children, ids)
+            }
+            ContentNode::List { items, .. } => {
+                for item in items {
+                    block_anchors(item, ids);
+                }
+            }
+            ContentNode::Table { headers, rows } => {
+                for cell in headers.iter().chain(rows.iter().flatten()) {
+                    inline_anchors(cell, ids);
+                }
+            }
+            ContentNode::BlockQuote { children } => block_anchors(children, ids),
+            ContentNode::Note { id, children, .. } => {
+                ids.insert(id);
+                block_anchors(children, ids);
+            }
+            ContentNode::Container { attrs, children } => {
+                ids.extend(attrs.id.as_deref());
+                block_anchors(children, ids);
+            }
+            ContentNode::Styled { attrs, node } => {
+                ids.extend(attrs.id.as_deref());
+                block_anchors(std::slice::from_ref(node.as_ref()), ids);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn inline_anchors<'a>(nodes: &'a [InlineNode], ids: &mut HashSet<&'a str>) {
+    for node in nodes {
+        match node {
+            InlineNode::Span { attrs, children } => {
+                ids.extend(attrs.id.as_deref());
+                inline_anchors(children, ids);
+            }
+            InlineNode::Emphasis(c)
+            | InlineNode::Strong(c)
+            | InlineNode::Superscript(c)
+            | InlineNode::Subscript(c)
+            | InlineNode::Link { children: c, .. } => inline_anchors(c, ids),
+            _ => {}
+        }
+    }
+}
+
+/// Calls `f` with the href of every link in `nodes`, in document order.
+pub(crate) fn visit_hrefs(nodes: &[ContentNode], f: &mut impl FnMut(&str)) {
+    fn inlines(nodes: &[InlineNode], f: &mut impl FnMut(&str)) {
+        for node in nodes {
+            match node {
+                InlineNode::Link { href, children } => {
+                    f(href);
+                    inlines(children, f);
+                }
+                InlineNode::Emphasis(c)
+                | InlineNode::Strong(c)
+                | InlineNode::Superscript(c)
+                | InlineNode::Subscript(c)
+                | InlineNode::Span { children: c, .. } => inlines(c, f),
+                _ => {}
+            }
+        }
+    }
+    for node in nodes {
+        match node {
+            ContentNode::Paragraph { children } | ContentNode::Heading { children, .. } => {
+                inlines(children, f)
+            }
+            ContentNode::List { items, .. } => {
+                for item in items {
+                    visit_hrefs(item, f);
+                }
+            }
+            ContentNode::Table { headers, rows } => {
+                for cell in headers.iter().chain(rows.iter().flatten()) {
+                    inlines(cell, f);
+                }
+            }
+            ContentNode::BlockQuote { children }
+            | ContentNode::Note { children, .. }
+            | ContentNode::Container { children, .. } => visit_hrefs(children, f),
+            ContentNode::Styled { node, .. } => visit_hrefs(std::slice::from_ref(node.as_ref()), f),
+            _ => {}
+        }
+    }
+}
+
+/// Calls `f` with the href of every link in `nodes`, in the same order as
+/// `visit_hrefs`.
+pub(crate) fn visit_hrefs_mut(nodes: &mut [ContentNode], f: &mut impl FnMut(&mut String)) {
+    fn inlines(nodes: &mut [InlineNode], f: &mut impl FnMut(&mut String)) {
+        for node in nodes {
+            match node {
+                InlineNode::Link { href, children } => {
+                    f(href);
+                    inlines(children, f);
+                }
+                InlineNode::Emphasis(c)
+                | InlineNode::Strong(c)
+                | InlineNode::Superscript(c)
+                | InlineNode::Subscript(c)
+                | InlineNode::Span { children: c, .. } => inlines(c, f),
+                _ => {}
+            }
+        }
+    }
+    for node in nodes {
+        match node {
+            ContentNode::Paragraph { children } | ContentNode::Heading { children, .. } => {
+                inlines(children, f)
+            }
+            ContentNode::List { items, .. } => {
+                for item in items {
+                    visit_hrefs_mut(item, f);
+                }
+            }
+            ContentNode::Table { headers, rows } => {
+                for cell in headers.iter_mut().chain(rows.iter_mut().flatten()) {
+                    inlines(cell, f);
+                }
+            }
+            ContentNode::BlockQuote { children }
+            | ContentNode::Note { children, .. }
+            | ContentNode::Container { children, .. } => visit_hrefs_mut(children, f),
+            ContentNode::Styled { node, .. } => {
+                visit_hrefs_mut(std::slice::from_mut(node.as_mut()), f)
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(href: &str) -> ContentNode {
+        ContentNode::Paragraph {
+            children: vec![InlineNode::Link {
+                href: href.into(),
+                children: vec![InlineNode::Text("see".into())],
+            }],
+        }
+    }
+
+    #[test]
+    fn test_check_and_fix_links() {
+        let mut doc = Document {
+            content: vec![
+                Chapter {
+                    id: "ch1".into(),
+                    title: None,
+                    content: vec![
+                        link("ch2.xhtml#fig-3"),
+                        link("ch2#gone"),
+                        link("missing.xhtml"),
+                        link("#fig-3"),
+                        link("https://example.com/#x"),
+                    ],
+                    text_direction: None,
+                },
+                Chapter {
+                    id: "ch2".into(),
+                    title: None,
+                    content: vec![ContentNode::Styled {
+                        attrs: Attributes {
+                            id: Some("fig-3".into()),
+                            ..Default::default()
+                        },
+                        node: Box::new(ContentNode::HorizontalRule),
+                    }],
+                    text_direction: None,
+                },
+            ],
+            ..Default::default()
+        };
+
+        let index = LinkIndex::new(&doc);
+        assert_eq!(
+            index.resolve("#fig-3"),
+            LinkTarget::Internal {
+                chapter: 1,
+                anchor: Some("fig-3")
+            }
+        );
+        assert_eq!(index.resolve("mailto:a@b.c"), LinkTarget::External);
+
+        let issues = check_links(&doc);
+        assert_eq!(issues.len(), 2);
+        assert!(issues[0].message.contains("ch2#gone") && issues[0].auto_fixable);
+        assert!(issues[1].message.contains("missing.xhtml") && !issues[1].auto_fixable);
+
+        assert_eq!(fix_links(&mut doc), 3);
+        let mut hrefs = Vec::new();
+        visit_hrefs(&doc.content[0].content, &mut |h| hrefs.push(h.to_string()));
+        assert_eq!(
+            hrefs,
+            [
+                "ch2#fig-3",
+                "ch2",
+                "missing.xhtml",
+                "ch2#fig-3",
+                "https://example.com/#x"
+            ]
+        );
+        assert_eq!(check_links(&doc).len(), 1);
+    }
+}
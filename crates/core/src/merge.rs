@@ -58,5 +58,6 @@ pub fn merge(docs: &[Document], opts: &MergeOptions) -> Result<Document, MergeEr
         resources,
         text_direction: docs[0].text_direction,
         epub_version: docs[0].epub_version,
+        diagnostics: Vec::new(),
     })
 }
@@ -1,6 +1,6 @@
 //! Standalone metadata editing: get, set, strip, copy.
 
-use crate::document::{Document, Metadata};
+use crate::document::{Contributor, Document, Metadata};
 use crate::error::MetaError;
 
 pub fn meta_get(doc: &Document, field: &str) -> Option<String> {
@@ -9,6 +9,7 @@ pub fn meta_get(doc: &Document, field: &str) -> Option<String> {
         "title" => m.title.clone(),
         "subtitle" => m.subtitle.clone(),
         "author" | "authors" => if m.authors.is_empty() { None } else { Some(m.authors.join(", ")) },
+        "author_sort" => if m.authors.is_empty() { None } else { Some(author_sort(m).join(" & ")) },
         "language" => m.language.clone(),
         "publisher" => m.publisher.clone(),
         "publish_date" => m.publish_date.clone(),
@@ -26,6 +27,7 @@ pub fn meta_set(doc: &mut Document, field: &str, value: &str) -> Result<(), Meta
         "title" => { m.title = Some(value.to_string()); }
         "subtitle" => { m.subtitle = Some(value.to_string()); }
         "author" | "authors" => { m.authors = value.split(',').map(|s| s.trim().to_string()).collect(); }
+        "author_sort" => { set_author_sort(m, value); }
         "language" => { m.language = Some(value.to_string()); }
         "publisher" => { m.publisher = Some(value.to_string()); }
         "publish_date" => { m.publish_date = Some(value.to_string()); }
@@ -46,7 +48,8 @@ pub fn meta_strip(doc: &mut Document, fields: Option<&[&str]>) {
         match f.to_lowercase().as_str() {
             "title" => m.title = None,
             "subtitle" => m.subtitle = None,
-            "authors" | "author" => m.authors.clear(),
+            "authors" | "author" => { m.authors.clear(); m.contributors.retain(|c| c.role.as_deref() != Some("aut")); }
+            "author_sort" => { for c in &mut m.contributors { c.file_as = None; } }
             "language" => m.language = None,
             "publisher" => m.publisher = None,
             "publish_date" => m.publish_date = None,
@@ -66,7 +69,7 @@ pub fn meta_copy(source: &Metadata, target: &mut Metadata, fields: Option<&[&str
         match f.to_lowercase().as_str() {
             "title" => target.title = source.title.clone(),
             "subtitle" => target.subtitle = source.subtitle.clone(),
-            "authors" | "author" => target.authors = source.authors.clone(),
+            "authors" | "author" => { target.authors = source.authors.clone(); target.contributors = source.contributors.clone(); }
             "language" => target.language = source.language.clone(),
             "publisher" => target.publisher = source.publisher.clone(),
             "publish_date" => target.publish_date = source.publish_date.clone(),
@@ -78,3 +81,25 @@ pub fn meta_copy(source: &Metadata, target: &mut Metadata, fields: Option<&[&str
         }
     }
 }
+
+/// Sort name of each author: its contributor's `file_as`, or the name itself.
+fn author_sort(m: &Metadata) -> Vec<String> {
+    m.authors.iter().map(|name| {
+        m.contributors.iter()
+            .find(|c| c.name == *name && matches!(c.role.as_deref(), None | Some("aut")))
+            .and_then(|c| c.file_as.clone())
+            .unwrap_or_else(|| name.clone())
+    }).collect()
+}
+
+/// Set the authors' sort names from `"Austen, Jane & Brontë, Charlotte"`, in author order.
+fn set_author_sort(m: &mut Metadata, value: &str) {
+    for (name, sort) in m.authors.iter().zip(value.split('&').map(str::trim)) {
+        let existing = m.contributors.iter_mut()
+            .find(|c| c.name == *name && matches!(c.role.as_deref(), None | Some("aut")));
+        match existing {
+            Some(c) => c.file_as = Some(sort.to_string()),
+            None => m.contributors.push(Contributor { name: name.clone(), file_as: Some(sort.to_string()), role: Some("aut".to_string()) }),
+        }
+    }
+}
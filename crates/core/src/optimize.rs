@@ -1,15 +1,36 @@
 //! Size optimization: image recompression, font subsetting, CSS/HTML minification.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::{CompressionType, FilterType as PngFilter, PngEncoder};
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat};
 use sha2::{Sha256, Digest};
 
-use crate::document::{Document, ResourceMap};
+use crate::css;
+use crate::document::{visit_image_refs_mut, Document, Resource};
+use crate::fonts;
+use crate::writers::find_resource;
+
+/// PNGs with at least this many distinct colors (in a sample) are treated as
+/// photographs; line art and screenshots stay PNG.
+const PHOTO_MIN_COLORS: usize = 1024;
+/// Pixels sampled when counting colors.
+const PHOTO_SAMPLE_PIXELS: usize = 65_536;
 
 #[derive(Debug, Clone)]
 pub struct OptimizeOptions {
+    /// JPEG quality (1–100) for re-encoded images.
     pub image_quality: u8,
+    /// Downscale images whose width or height exceeds this many pixels.
+    pub max_image_dimension: Option<u32>,
+    /// Re-encode opaque photographic PNGs as JPEG.
+    pub png_to_jpeg: bool,
+    /// Subset embedded fonts to the characters each font-family renders.
     pub subset_fonts: bool,
+    /// Drop stylesheet rules the content cannot match and vendor-prefixed
+    /// duplicates, and minify stylesheets.
     pub strip_css: bool,
     pub minify_html: bool,
     pub dedup_resources: bool,
@@ -20,6 +41,8 @@ impl Default for OptimizeOptions {
     fn default() -> Self {
         Self {
             image_quality: 80,
+            max_image_dimension: None,
+            png_to_jpeg: true,
             subset_fonts: true,
             strip_css: true,
             minify_html: false,
@@ -34,35 +57,547 @@ pub struct OptimizeReport {
     pub original_size_bytes: u64,
     pub optimized_size_bytes: u64,
     pub actions: Vec<String>,
+    /// Every resource of the original document, sorted by id.
+    pub resources: Vec<ResourceReport>,
+}
+
+/// Size of one resource before and after optimization.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ResourceReport {
+    pub id: String,
+    pub media_type: String,
+    pub original_size_bytes: u64,
+    /// Zero when the resource was merged into an identical one.
+    pub optimized_size_bytes: u64,
+    /// The identical resource that replaced this one.
+    pub merged_into: Option<String>,
 }
 
 pub fn optimize(doc: &mut Document, opts: &OptimizeOptions) -> OptimizeReport {
     let original_size: u64 = doc.resources.iter().map(|(_, r)| r.data.len() as u64).sum();
     let mut actions = Vec::new();
 
+    let mut resources: Vec<ResourceReport> = doc
+        .resources
+        .iter()
+        .map(|(id, r)| ResourceReport {
+            id: id.clone(),
+            media_type: r.media_type.clone(),
+            original_size_bytes: r.data.len() as u64,
+            optimized_size_bytes: r.data.len() as u64,
+            merged_into: None,
+        })
+        .collect();
+    resources.sort_by(|a, b| a.id.cmp(&b.id));
+    // Where references to a resource should point afterwards: the duplicate
+    // it was merged into, or its own id once its file name changed.
+    let mut retarget: HashMap<String, String> = HashMap::new();
+
     if opts.dedup_resources {
         let mut seen: HashMap<[u8; 32], String> = HashMap::new();
-        let mut new_resources = ResourceMap::new();
-        for (id, res) in doc.resources.iter() {
+        for report in &mut resources {
+            let Some(res) = doc.resources.get(&report.id) else {
+                continue;
+            };
             let mut hasher = Sha256::new();
             hasher.update(&res.data);
             let key: [u8; 32] = hasher.finalize().into();
             if let Some(first_id) = seen.get(&key) {
-                actions.push(format!("Dedup resource {} -> {}", id, first_id));
+                actions.push(format!("Dedup resource {} -> {}", report.id, first_id));
+                retarget.insert(report.id.clone(), first_id.clone());
+                report.merged_into = Some(first_id.clone());
+                report.optimized_size_bytes = 0;
                 continue;
             }
-            seen.insert(key, id.clone());
-            new_resources.insert(id.clone(), res.clone());
+            seen.insert(key, report.id.clone());
         }
-        if new_resources.len() != doc.resources.len() {
-            doc.resources = new_resources;
+    }
+
+    // Characters are counted before anything is renamed, so stylesheet
+    // references to the fonts still resolve.
+    let mut font_chars = match opts.subset_fonts {
+        true => fonts::characters_by_font(doc),
+        false => HashMap::new(),
+    };
+    for report in &resources {
+        if let Some(target) = &report.merged_into {
+            let chars = font_chars.remove(&report.id).unwrap_or_default();
+            font_chars.entry(target.clone()).or_default().extend(chars);
         }
     }
 
+    // New versions of resources, swapped in once references are updated.
+    let mut replaced: Vec<(String, Resource)> = Vec::new();
+    for report in resources.iter_mut().filter(|r| r.merged_into.is_none()) {
+        let Some(res) = doc.resources.get(&report.id) else {
+            continue;
+        };
+        let jpeg_name = res.filename.as_deref().map(|f| with_extension(f, "jpg"));
+        let to_jpeg = opts.png_to_jpeg
+            && res.media_type == "image/png"
+            && jpeg_name
+                .as_deref()
+                .map_or(true, |f| can_rename(doc, res, f));
+        let Some(image) = reencode(res, opts, to_jpeg) else {
+            continue;
+        };
+
+        let mut optimized = Resource {
+            id: res.id.clone(),
+            media_type: res.media_type.clone(),
+            data: image.data,
+            filename: res.filename.clone(),
+            obfuscation: res.obfuscation.clone(),
+        };
+        let format = if image.format == ImageFormat::Jpeg {
+            "JPEG"
+        } else {
+            "PNG"
+        };
+        if image.format == ImageFormat::Jpeg && res.media_type == "image/png" {
+            optimized.media_type = "image/jpeg".into();
+            optimized.filename = jpeg_name;
+            retarget.insert(report.id.clone(), report.id.clone());
+        }
+        let mut action = format!("Re-encoded {} as {}", report.id, format);
+        if let Some((w, h)) = image.resized {
+            action.push_str(&format!(" at {}x{}", w, h));
+        }
+        action.push_str(&format!(
+            ": {} -> {} bytes",
+            report.original_size_bytes,
+            optimized.data.len()
+        ));
+        actions.push(action);
+        report.optimized_size_bytes = optimized.data.len() as u64;
+        report.media_type = optimized.media_type.clone();
+        replaced.push((report.id.clone(), optimized));
+    }
+
+    for report in resources.iter_mut().filter(|r| r.merged_into.is_none()) {
+        let (Some(res), Some(chars)) = (doc.resources.get(&report.id), font_chars.get(&report.id))
+        else {
+            continue;
+        };
+        if !fonts::is_font(res) {
+            continue;
+        }
+        let Some(data) = fonts::subset_font(&res.data, chars) else {
+            continue;
+        };
+        actions.push(format!(
+            "Subset font {} to {} characters: {} -> {} bytes",
+            report.id,
+            chars.len(),
+            report.original_size_bytes,
+            data.len()
+        ));
+        report.optimized_size_bytes = data.len() as u64;
+        replaced.push((
+            report.id.clone(),
+            Resource {
+                data,
+                ..res.clone()
+            },
+        ));
+    }
+
+    // Stylesheets: point `url()`s at the resources duplicates were merged
+    // into, then drop unused rules and minify.
+    let selectors = opts.strip_css.then(|| css::ContentSelectors::new(doc));
+    let mut stylesheets = Vec::new();
+    for report in resources.iter_mut().filter(|r| r.merged_into.is_none()) {
+        let Some(res) = doc.resources.get(&report.id) else {
+            continue;
+        };
+        if res.media_type != "text/css" {
+            continue;
+        }
+        let source = String::from_utf8_lossy(&res.data);
+        let from = res.filename.as_deref().unwrap_or("");
+        let (mut text, repointed) = css::rewrite_urls(&source, |url| {
+            let id = &find_resource(doc, url)?.id;
+            let target = retarget.get(id).filter(|t| *t != id)?;
+            let target = replaced
+                .iter()
+                .find(|(id, _)| id == target)
+                .map(|(_, r)| r)
+                .or_else(|| doc.resources.get(target))?;
+            Some(css::relative_path(from, target.filename.as_deref()?))
+        });
+        if repointed > 0 {
+            actions.push(format!(
+                "Pointed {} url() references in {} at merged resources",
+                repointed, report.id
+            ));
+        }
+        let stripped = selectors
+            .as_ref()
+            .map(|s| css::strip_stylesheet(&text, s))
+            .filter(|(stripped, _)| stripped.len() < text.len());
+        if let Some((stripped, stats)) = stripped {
+            actions.push(format!(
+                "Minified CSS {}, removing {} unused rules and {} vendor-prefixed duplicates: {} -> {} bytes",
+                report.id,
+                stats.unused_rules,
+                stats.prefixed,
+                report.original_size_bytes,
+                stripped.len()
+            ));
+            text = stripped;
+        } else if repointed == 0 {
+            continue;
+        }
+        report.optimized_size_bytes = text.len() as u64;
+        stylesheets.push((
+            report.id.clone(),
+            Resource {
+                data: text.into_bytes(),
+                ..res.clone()
+            },
+        ));
+    }
+    replaced.extend(stylesheets);
+
+    // Point image references at the surviving resources while the old ids and
+    // file names can still be resolved.
+    if !retarget.is_empty() {
+        let target = |doc: &Document, reference: &str| {
+            find_resource(doc, reference)
+                .and_then(|r| retarget.get(&r.id))
+                .cloned()
+        };
+        let mut content = std::mem::take(&mut doc.content);
+        for chapter in &mut content {
+            visit_image_refs_mut(&mut chapter.content, &mut |reference| {
+                if let Some(id) = target(doc, reference) {
+                    *reference = id;
+                }
+            });
+        }
+        doc.content = content;
+        let cover = doc
+            .metadata
+            .cover_image_id
+            .as_deref()
+            .and_then(|c| target(doc, c));
+        if cover.is_some() {
+            doc.metadata.cover_image_id = cover;
+        }
+    }
+
+    for report in &resources {
+        if report.merged_into.is_some() {
+            doc.resources.remove(&report.id);
+        }
+    }
+    for (id, res) in replaced {
+        doc.resources.insert(id, res);
+    }
+
     let optimized_size: u64 = doc.resources.iter().map(|(_, r)| r.data.len() as u64).sum();
     OptimizeReport {
         original_size_bytes: original_size,
         optimized_size_bytes: optimized_size,
         actions,
+        resources,
+    }
+}
+
+struct Reencoded {
+    data: Vec<u8>,
+    format: ImageFormat,
+    /// New pixel size when the image was downscaled.
+    resized: Option<(u32, u32)>,
+}
+
+/// Downscale and re-encode a JPEG or PNG, turning photographic PNGs into
+/// JPEG when `to_jpeg` is set. Returns `None` when the image cannot be
+/// decoded or the result would not be smaller.
+fn reencode(res: &Resource, opts: &OptimizeOptions, to_jpeg: bool) -> Option<Reencoded> {
+    let format = match res.media_type.as_str() {
+        "image/jpeg" => ImageFormat::Jpeg,
+        "image/png" => ImageFormat::Png,
+        _ => return None,
+    };
+    let mut img = image::load_from_memory_with_format(&res.data, format).ok()?;
+    let resized = match opts.max_image_dimension {
+        Some(max) if img.width() > max || img.height() > max => {
+            img = img.resize(max, max, FilterType::Lanczos3);
+            Some((img.width(), img.height()))
+        }
+        _ => None,
+    };
+    let format = if format == ImageFormat::Png && to_jpeg && is_opaque_photo(&img) {
+        ImageFormat::Jpeg
+    } else {
+        format
+    };
+
+    let mut data = Vec::new();
+    if format == ImageFormat::Jpeg {
+        let encoder = JpegEncoder::new_with_quality(&mut data, opts.image_quality.clamp(1, 100));
+        img.to_rgb8().write_with_encoder(encoder).ok()?;
+    } else {
+        let encoder =
+            PngEncoder::new_with_quality(&mut data, CompressionType::Best, PngFilter::Adaptive);
+        img.write_with_encoder(encoder).ok()?;
+    }
+    (data.len() < res.data.len()).then_some(Reencoded {
+        data,
+        format,
+        resized,
+    })
+}
+
+/// Whether an image has no transparency and enough distinct colors to be a
+/// photograph, which JPEG stores far smaller than PNG.
+fn is_opaque_photo(img: &DynamicImage) -> bool {
+    if img.color().has_alpha() && img.to_rgba8().pixels().any(|p| p[3] < u8::MAX) {
+        return false;
+    }
+    let rgb = img.to_rgb8();
+    let pixels = rgb.width() as usize * rgb.height() as usize;
+    let step = (pixels / PHOTO_SAMPLE_PIXELS).max(1);
+    let mut colors = HashSet::new();
+    for p in rgb.pixels().step_by(step) {
+        colors.insert(p.0);
+        if colors.len() >= PHOTO_MIN_COLORS {
+            return true;
+        }
+    }
+    false
+}
+
+/// Whether a resource can take a new file name: no other resource has it, and
+/// no stylesheet refers to the old one.
+fn can_rename(doc: &Document, res: &Resource, new_name: &str) -> bool {
+    let old_name = res.filename.as_deref().unwrap_or_default();
+    let old_base = old_name.rsplit('/').next().unwrap_or(old_name);
+    doc.resources.iter().all(|(_, other)| {
+        other.filename.as_deref() != Some(new_name)
+            && (other.media_type != "text/css"
+                || !String::from_utf8_lossy(&other.data).contains(old_base))
+    })
+}
+
+fn with_extension(filename: &str, ext: &str) -> String {
+    let base_start = filename.rfind('/').map_or(0, |i| i + 1);
+    match filename[base_start..].rfind('.') {
+        Some(dot) => format!("{}.{}", &filename[..base_start + dot], ext),
+        None => format!("{}.{}", filename, ext),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::{Chapter, ContentNode};
+
+    fn noise_png(width: u32, height: u32) -> Vec<u8> {
+        let mut seed = 1u32;
+        let img = image::RgbImage::from_fn(width, height, |_, _| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            let [r, g, b, _] = seed.to_be_bytes();
+            image::Rgb([r, g, b])
+        });
+        let mut data = Vec::new();
+        img.write_to(&mut std::io::Cursor::new(&mut data), ImageFormat::Png)
+            .unwrap();
+        data
+    }
+
+    fn image(reference: &str) -> ContentNode {
+        ContentNode::Image {
+            resource_id: reference.into(),
+            alt_text: None,
+            caption: None,
+        }
+    }
+
+    #[test]
+    fn test_reencodes_images_and_rewrites_references() {
+        let mut doc = Document::default();
+        let photo = noise_png(200, 100);
+        for (id, filename) in [("a", "images/a.png"), ("b", "images/b.png")] {
+            doc.resources.insert(
+                id.into(),
+                Resource {
+                    id: id.into(),
+                    media_type: "image/png".into(),
+                    data: photo.clone(),
+                    filename: Some(filename.into()),
+                    obfuscation: None,
+                },
+            );
+        }
+        doc.content.push(Chapter {
+            id: "c1".into(),
+            title: None,
+            content: vec![
+                image("../images/a.png"),
+                ContentNode::BlockQuote {
+                    children: vec![image("../images/b.png")],
+                },
+            ],
+            text_direction: None,
+        });
+        doc.metadata.cover_image_id = Some("b".into());
+
+        let opts = OptimizeOptions {
+            max_image_dimension: Some(100),
+            ..Default::default()
+        };
+        let report = optimize(&mut doc, &opts);
+
+        assert_eq!(doc.resources.len(), 1);
+        let a = doc.resources.get("a").unwrap();
+        assert_eq!(a.media_type, "image/jpeg");
+        assert_eq!(a.filename.as_deref(), Some("images/a.jpg"));
+        let img = image::load_from_memory(&a.data).unwrap();
+        assert_eq!((img.width(), img.height()), (100, 50));
+
+        let mut refs = Vec::new();
+        visit_image_refs_mut(&mut doc.content[0].content, &mut |r| refs.push(r.clone()));
+        assert_eq!(refs, ["a", "a"]);
+        assert_eq!(doc.metadata.cover_image_id.as_deref(), Some("a"));
+
+        assert_eq!(report.resources.len(), 2);
+        assert_eq!(report.resources[0].original_size_bytes, photo.len() as u64);
+        assert_eq!(
+            report.resources[0].optimized_size_bytes,
+            a.data.len() as u64
+        );
+        assert_eq!(report.resources[1].merged_into.as_deref(), Some("a"));
+        assert_eq!(report.optimized_size_bytes, a.data.len() as u64);
+        assert!(report.actions[1].starts_with("Re-encoded a as JPEG at 100x50"));
+    }
+
+    #[test]
+    fn test_optimized_images_resolve_in_epub() {
+        use crate::writers::epub::EpubWriter;
+        use crate::writers::{FormatWriter, WriteOptions};
+        use std::io::Read;
+
+        let mut doc = Document::default();
+        let photo = noise_png(200, 100);
+        for (id, media_type, data, filename) in [
+            ("a", "image/png", photo.clone(), "Images/a.png"),
+            ("b", "image/png", photo.clone(), "Images/b.png"),
+            ("c", "image/gif", b"GIF89a".to_vec(), "Images/c.gif"),
+        ] {
+            doc.resources.insert(
+                id.into(),
+                Resource {
+                    id: id.into(),
+                    media_type: media_type.into(),
+                    data,
+                    filename: Some(filename.into()),
+                    obfuscation: None,
+                },
+            );
+        }
+        doc.content.push(Chapter {
+            id: "c1".into(),
+            title: None,
+            content: vec![
+                image("../Images/a.png"),
+                image("../Images/b.png"),
+                image("../Images/c.gif"),
+            ],
+            text_direction: None,
+        });
+        optimize(&mut doc, &OptimizeOptions::default());
+
+        let mut epub = Vec::new();
+        EpubWriter::write(&doc, &mut epub, &WriteOptions::default(), None).unwrap();
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(epub)).unwrap();
+        let mut read = |name: &str| {
+            let mut s = String::new();
+            archive
+                .by_name(name)
+                .unwrap()
+                .read_to_string(&mut s)
+                .unwrap();
+            s
+        };
+        let opf = read("OEBPS/content.opf");
+        let page = read("OEBPS/chapter1.xhtml");
+
+        let srcs: Vec<&str> = page
+            .split("<img src=\"")
+            .skip(1)
+            .filter_map(|rest| rest.split('"').next())
+            .collect();
+        assert_eq!(
+            srcs,
+            [
+                "resources/Images/a.jpg",
+                "resources/Images/a.jpg",
+                "resources/Images/c.gif",
+            ]
+        );
+        for src in srcs {
+            assert!(
+                opf.contains(&format!("href=\"{}\"", src)),
+                "{} not in manifest",
+                src
+            );
+            assert!(archive.by_name(&format!("OEBPS/{}", src)).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_strips_stylesheets_and_repoints_urls() {
+        let mut doc = Document::default();
+        let resource = |id: &str, media_type: &str, data: &[u8], filename: &str| Resource {
+            id: id.into(),
+            media_type: media_type.into(),
+            data: data.to_vec(),
+            filename: Some(filename.into()),
+            obfuscation: None,
+        };
+        let css = b".used { background: url('../images/b.gif') }
+            .unused { color: red }
+            p { -webkit-hyphens: auto; hyphens: auto }";
+        for res in [
+            resource("a", "image/gif", b"GIF89a", "images/a.gif"),
+            resource("b", "image/gif", b"GIF89a", "images/b.gif"),
+            resource("css", "text/css", css, "styles/book.css"),
+        ] {
+            doc.resources.insert(res.id.clone(), res);
+        }
+        doc.content.push(Chapter {
+            id: "c1".into(),
+            title: None,
+            content: vec![ContentNode::Paragraph {
+                children: vec![crate::document::InlineNode::Span {
+                    attrs: crate::document::Attributes {
+                        class: Some("used".into()),
+                        ..Default::default()
+                    },
+                    children: Vec::new(),
+                }],
+            }],
+            text_direction: None,
+        });
+
+        let report = optimize(&mut doc, &OptimizeOptions::default());
+        let css = doc.resources.get("css").unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&css.data),
+            ".used{background:url(\"../images/a.gif\")}p{hyphens:auto}"
+        );
+        assert_eq!(
+            report.actions[1],
+            "Pointed 1 url() references in css at merged resources"
+        );
+        assert!(report.actions[2].starts_with(
+            "Minified CSS css, removing 1 unused rules and 1 vendor-prefixed duplicates"
+        ));
+        assert_eq!(
+            report.resources[2].optimized_size_bytes,
+            css.data.len() as u64
+        );
     }
 }
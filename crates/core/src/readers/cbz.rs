@@ -0,0 +1,477 @@
+//! Comic archive reader: CBZ (and ZIP-packed CBR) → IR.
+//!
+//! Page images are ordered naturally by path ("page2" before "page10") and
+//! each becomes an image-only chapter. `ComicInfo.xml` fills `Metadata`; a
+//! manga reading direction sets `TextDirection::Rtl`. The document is marked
+//! `rendition:layout: pre-paginated` so the EPUB writer produces a
+//! fixed-layout book. RAR-compressed CBR files are not supported.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::io::{Read, Seek};
+
+use quick_xml::events::Event;
+use quick_xml::Reader as XmlReader;
+
+use crate::detect::{DetectResult, Format};
+use crate::document::*;
+use crate::error::ReadError;
+use crate::progress::{emit_progress, ProgressHandler};
+use crate::readers::{guess_media_type, FormatReader, ReadOptions};
+use crate::security;
+
+pub struct CbzReader;
+
+impl FormatReader for CbzReader {
+    fn detect(header: &[u8]) -> DetectResult {
+        let (format, confidence) = if header.starts_with(b"PK\x03\x04") {
+            (Format::Cbz, 0.5)
+        } else if header.starts_with(b"Rar!\x1a\x07") {
+            (Format::Cbr, 0.7)
+        } else {
+            (Format::Cbz, 0.0)
+        };
+        DetectResult {
+            format,
+            confidence,
+            mime_type: format.mime_type(),
+        }
+    }
+
+    fn read<R: Read + Seek>(
+        mut input: R,
+        opts: &ReadOptions,
+        progress: Option<&dyn ProgressHandler>,
+    ) -> Result<Document, ReadError> {
+        let mut magic = [0u8; 7];
+        let n = input.read(&mut magic)?;
+        input.seek(std::io::SeekFrom::Start(0))?;
+        if magic[..n].starts_with(b"Rar!\x1a\x07") {
+            return Err(ReadError::UnsupportedFormat(
+                "RAR-compressed CBR archives are not supported; repack the pages as CBZ".into(),
+            ));
+        }
+
+        let mut archive = zip::ZipArchive::new(input).map_err(|e| ReadError::MalformedFile {
+            format: "CBZ".into(),
+            detail: format!("Invalid ZIP archive: {e}"),
+        })?;
+        security::check_file_count(archive.len() as u64, &opts.security)?;
+
+        let names: Vec<String> = archive.file_names().map(str::to_string).collect();
+        let mut pages: Vec<&String> = names.iter().filter(|n| is_page_image(n)).collect();
+        pages.sort_by(|a, b| natural_cmp(&a.to_lowercase(), &b.to_lowercase()));
+        if pages.is_empty() {
+            return Err(ReadError::MissingContent(
+                "No page images in comic archive".into(),
+            ));
+        }
+
+        let info = names
+            .iter()
+            .find(|n| {
+                n.rsplit('/')
+                    .next()
+                    .is_some_and(|f| f.eq_ignore_ascii_case("ComicInfo.xml"))
+            })
+            .and_then(|name| {
+                let file = archive.by_name(name).ok()?;
+                if let Err(e) = security::check_resource_size(name, file.size(), &opts.security) {
+                    tracing::warn!("Skipping '{}': {}", name, e);
+                    return None;
+                }
+                // The declared size can lie; never read past the limit.
+                let mut xml = String::new();
+                file.take(opts.security.max_resource_size_bytes)
+                    .read_to_string(&mut xml)
+                    .ok()?;
+                Some(parse_comic_info(&xml))
+            })
+            .unwrap_or_default();
+
+        let total = pages.len() as u64;
+        let mut resources = ResourceMap::new();
+        let mut content = Vec::with_capacity(pages.len());
+        let mut total_size = 0u64;
+        for (i, name) in pages.iter().enumerate() {
+            security::check_path_traversal(name)?;
+            let mut file = archive
+                .by_name(name)
+                .map_err(|e| ReadError::MalformedFile {
+                    format: "CBZ".into(),
+                    detail: format!("Failed to open {}: {}", name, e),
+                })?;
+            security::check_resource_size(name, file.size(), &opts.security)?;
+            total_size += file.size();
+            security::check_total_size(total_size, &opts.security)?;
+            let mut data = Vec::with_capacity(file.size() as usize);
+            file.read_to_end(&mut data)?;
+
+            let ext = name
+                .rsplit('.')
+                .next()
+                .unwrap_or("jpg")
+                .to_ascii_lowercase();
+            let id = format!("page{:04}.{}", i + 1, ext);
+            resources.insert(
+                id.clone(),
+                Resource {
+                    id: id.clone(),
+                    media_type: guess_media_type(name).to_string(),
+                    data,
+                    filename: Some(id.clone()),
+                    obfuscation: None,
+                },
+            );
+            content.push(Chapter {
+                id: format!("page-{}", i + 1),
+                title: None,
+                content: vec![ContentNode::Image {
+                    resource_id: id,
+                    alt_text: None,
+                    caption: None,
+                }],
+                text_direction: None,
+            });
+            emit_progress(
+                progress,
+                "Reading comic",
+                i as u64 + 1,
+                Some(total),
+                Some(&format!("Page {}/{}", i + 1, total)),
+            );
+        }
+
+        let mut metadata = info.metadata;
+        if metadata.title.is_none() {
+            metadata.title = metadata.series.as_ref().map(|s| match s.position {
+                Some(n) => format!("{} #{}", s.name, n),
+                None => s.name.clone(),
+            });
+        }
+        metadata.page_count = Some(content.len() as u32);
+        if opts.extract_cover {
+            let cover = info.cover_page.filter(|&p| p < content.len()).unwrap_or(0);
+            metadata.cover_image_id = content[cover].content.iter().find_map(|n| match n {
+                ContentNode::Image { resource_id, .. } => Some(resource_id.clone()),
+                _ => None,
+            });
+        }
+        // Same key the EPUB reader uses for `<meta property="rendition:layout">`;
+        // the EPUB writer switches to fixed layout on it.
+        metadata
+            .custom
+            .insert("rendition:layout".into(), "pre-paginated".into());
+
+        // Bookmarked pages form the TOC; without any, the book starts at page one.
+        let mut toc: Vec<TocEntry> = info
+            .bookmarks
+            .into_iter()
+            .filter(|(page, _)| *page < content.len())
+            .map(|(page, title)| TocEntry {
+                title,
+                href: content[page].id.clone(),
+                children: Vec::new(),
+            })
+            .collect();
+        if toc.is_empty() {
+            toc.push(TocEntry {
+                title: metadata.title.clone().unwrap_or_else(|| "Start".into()),
+                href: content[0].id.clone(),
+                children: Vec::new(),
+            });
+        }
+
+        Ok(Document {
+            metadata,
+            toc: if opts.parse_toc { toc } else { Vec::new() },
+            content,
+            resources,
+            text_direction: if info.right_to_left {
+                TextDirection::Rtl
+            } else {
+                TextDirection::Ltr
+            },
+            epub_version: None,
+            diagnostics: Vec::new(),
+        })
+    }
+}
+
+fn is_page_image(name: &str) -> bool {
+    let lower = name.to_ascii_lowercase();
+    let file = lower.rsplit('/').next().unwrap_or(&lower);
+    // macOS resource forks and hidden files are not pages.
+    !lower.starts_with("__macosx/")
+        && !file.starts_with('.')
+        && matches!(
+            file.rsplit('.').next(),
+            Some("jpg" | "jpeg" | "png" | "gif" | "webp" | "bmp")
+        )
+}
+
+/// Compare strings treating runs of digits as numbers.
+pub(crate) fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let mut na = String::new();
+                while let Some(c) = a.next_if(|c| c.is_ascii_digit()) {
+                    na.push(c);
+                }
+                let mut nb = String::new();
+                while let Some(c) = b.next_if(|c| c.is_ascii_digit()) {
+                    nb.push(c);
+                }
+                // Compare by value without overflow: strip zeros, then length, then digits.
+                let (ta, tb) = (na.trim_start_matches('0'), nb.trim_start_matches('0'));
+                let ord = ta
+                    .len()
+                    .cmp(&tb.len())
+                    .then_with(|| ta.cmp(tb))
+                    .then_with(|| na.len().cmp(&nb.len()));
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(&y);
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+// --- ComicInfo.xml ---
+
+#[derive(Default)]
+struct ComicInfo {
+    metadata: Metadata,
+    right_to_left: bool,
+    /// Index of the page marked `FrontCover`.
+    cover_page: Option<usize>,
+    /// `(page index, title)` for pages with a `Bookmark`.
+    bookmarks: Vec<(usize, String)>,
+}
+
+/// Read the Anansi ComicInfo schema (v2.0/2.1) into `Metadata`.
+fn parse_comic_info(xml: &str) -> ComicInfo {
+    let mut fields: HashMap<String, String> = HashMap::new();
+    let mut info = ComicInfo::default();
+    let mut reader = XmlReader::from_str(xml);
+    reader.config_mut().trim_text(true);
+    let mut current: Option<String> = None;
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(ref e)) => {
+                current = Some(String::from_utf8_lossy(e.local_name().as_ref()).to_string());
+            }
+            Ok(Event::Empty(ref e)) if e.local_name().as_ref() == b"Page" => {
+                let mut image = None;
+                let mut kind = None;
+                let mut bookmark = None;
+                for attr in e.attributes().flatten() {
+                    let value = attr
+                        .unescape_value()
+                        .map(|v| v.to_string())
+                        .unwrap_or_default();
+                    match attr.key.local_name().as_ref() {
+                        b"Image" => image = value.trim().parse::<usize>().ok(),
+                        b"Type" => kind = Some(value),
+                        b"Bookmark" => bookmark = Some(value).filter(|b| !b.trim().is_empty()),
+                        _ => {}
+                    }
+                }
+                if let Some(image) = image {
+                    if kind.as_deref() == Some("FrontCover") && info.cover_page.is_none() {
+                        info.cover_page = Some(image);
+                    }
+                    if let Some(title) = bookmark {
+                        info.bookmarks.push((image, title.trim().to_string()));
+                    }
+                }
+            }
+            Ok(Event::Text(ref e)) => {
+                if let Some(name) = current.take() {
+                    let text = e.unescape().map(|t| t.to_string()).unwrap_or_default();
+                    if !text.trim().is_empty() {
+                        fields.insert(name, text.trim().to_string());
+                    }
+                }
+            }
+            Ok(Event::End(_)) => current = None,
+            Ok(Event::Eof) => break,
+            Err(e) => {
+                tracing::warn!("Ignoring malformed ComicInfo.xml: {}", e);
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    let m = &mut info.metadata;
+    let list = |s: &String| -> Vec<String> {
+        s.split([',', ';'])
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+            .map(str::to_string)
+            .collect()
+    };
+    m.title = fields.get("Title").cloned();
+    m.authors = fields.get("Writer").map(list).unwrap_or_default();
+    m.description = fields.get("Summary").cloned();
+    m.publisher = fields.get("Publisher").cloned();
+    m.language = fields.get("LanguageISO").cloned();
+    m.series = fields.get("Series").map(|name| SeriesInfo {
+        name: name.clone(),
+        position: fields.get("Number").and_then(|n| n.parse().ok()),
+    });
+    if let Some(year) = fields.get("Year").filter(|y| y.parse::<u32>().is_ok()) {
+        let month = fields.get("Month").and_then(|m| m.parse::<u32>().ok());
+        let day = fields.get("Day").and_then(|d| d.parse::<u32>().ok());
+        m.publish_date = Some(match (month, day) {
+            (Some(mo), Some(d)) => format!("{}-{:02}-{:02}", year, mo, d),
+            (Some(mo), None) => format!("{}-{:02}", year, mo),
+            _ => year.clone(),
+        });
+    }
+    for key in ["Genre", "Tags"] {
+        for subject in fields.get(key).map(list).unwrap_or_default() {
+            if !m.subjects.contains(&subject) {
+                m.subjects.push(subject);
+            }
+        }
+    }
+    if let Some(rating) = fields.get("AgeRating").filter(|r| *r != "Unknown") {
+        m.custom.insert("age_rating".into(), rating.clone());
+    }
+    for (field, key) in [
+        ("Number", "issue"),
+        ("Volume", "volume"),
+        ("Penciller", "penciller"),
+        ("Inker", "inker"),
+        ("Colorist", "colorist"),
+        ("Letterer", "letterer"),
+        ("CoverArtist", "cover_artist"),
+        ("Editor", "editor"),
+        ("Imprint", "imprint"),
+        ("Web", "web"),
+    ] {
+        if let Some(value) = fields.get(field) {
+            m.custom.insert(key.into(), value.clone());
+        }
+    }
+    info.right_to_left = fields
+        .get("Manga")
+        .is_some_and(|v| v == "YesAndRightToLeft");
+    info
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+
+    const COMIC_INFO: &str = r#"<?xml version="1.0"?>
+<ComicInfo xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">
+  <Series>Night Watch</Series>
+  <Number>12</Number>
+  <Writer>Ann Writer, Bob Scribe</Writer>
+  <Penciller>Cy Artist</Penciller>
+  <Year>2021</Year><Month>7</Month>
+  <Manga>YesAndRightToLeft</Manga>
+  <Pages>
+    <Page Image="1" Type="FrontCover"/>
+    <Page Image="2" Bookmark="Part Two"/>
+  </Pages>
+</ComicInfo>"#;
+
+    fn comic(names: &[&str]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        {
+            let mut zip = zip::ZipWriter::new(Cursor::new(&mut bytes));
+            let options: zip::write::FileOptions<'_, ()> = zip::write::FileOptions::default();
+            for name in names {
+                zip.start_file(*name, options).unwrap();
+                if *name == "ComicInfo.xml" {
+                    zip.write_all(COMIC_INFO.as_bytes()).unwrap();
+                } else {
+                    zip.write_all(name.as_bytes()).unwrap();
+                }
+            }
+            zip.finish().unwrap();
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_natural_order() {
+        let mut names = vec!["p10.jpg", "p2.jpg", "p1.jpg", "p02b.jpg"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(names, ["p1.jpg", "p2.jpg", "p02b.jpg", "p10.jpg"]);
+    }
+
+    #[test]
+    fn test_pages_and_comic_info() {
+        let bytes = comic(&[
+            "ComicInfo.xml",
+            "pages/page10.jpg",
+            "pages/page2.jpg",
+            "pages/page1.jpg",
+            "__MACOSX/pages/._page1.jpg",
+        ]);
+        let doc = CbzReader::read(Cursor::new(bytes), &ReadOptions::default(), None).unwrap();
+        assert_eq!(doc.content.len(), 3);
+        let page_data: Vec<&[u8]> = doc
+            .content
+            .iter()
+            .map(|c| match &c.content[0] {
+                ContentNode::Image { resource_id, .. } => {
+                    doc.resources.get(resource_id).unwrap().data.as_slice()
+                }
+                other => panic!("expected image, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            page_data,
+            [
+                b"pages/page1.jpg".as_slice(),
+                b"pages/page2.jpg",
+                b"pages/page10.jpg"
+            ]
+        );
+
+        let m = &doc.metadata;
+        assert_eq!(m.title.as_deref(), Some("Night Watch #12"));
+        assert_eq!(m.authors, ["Ann Writer", "Bob Scribe"]);
+        assert_eq!(m.series.as_ref().unwrap().position, Some(12.0));
+        assert_eq!(m.publish_date.as_deref(), Some("2021-07"));
+        assert_eq!(m.cover_image_id.as_deref(), Some("page0002.jpg"));
+        assert_eq!(
+            m.custom.get("rendition:layout").map(String::as_str),
+            Some("pre-paginated")
+        );
+        assert_eq!(doc.text_direction, TextDirection::Rtl);
+        assert_eq!(doc.toc[0].title, "Part Two");
+        assert_eq!(doc.toc[0].href, "page-3");
+    }
+
+    #[test]
+    fn test_rar_cbr_is_rejected() {
+        let err = CbzReader::read(
+            Cursor::new(b"Rar!\x1a\x07\x00rest".to_vec()),
+            &ReadOptions::default(),
+            None,
+        )
+        .unwrap_err();
+        assert!(matches!(err, ReadError::UnsupportedFormat(_)));
+    }
+}
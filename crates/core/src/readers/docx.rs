@@ -0,0 +1,1540 @@
+//! DOCX reader: WordprocessingML (`word/document.xml`) → IR.
+//!
+//! Paragraph styles carry the structure: `Heading1`–`Heading6` (or any style
+//! with an outline level) become headings, quote styles block quotes and
+//! numbered paragraphs lists, ordered or not per `numbering.xml`. Each level-1
+//! heading starts a chapter. Run formatting becomes inline nodes, footnotes
+//! and endnotes become note references with their notes at the end, images from
+//! `word/media` become resources, and `docProps/core.xml` fills `Metadata`.
+
+use std::collections::HashMap;
+use std::io::{Read, Seek};
+
+use zip::ZipArchive;
+
+use crate::detect::{DetectResult, Format};
+use crate::document::*;
+use crate::error::ReadError;
+use crate::progress::{emit_progress, ProgressHandler};
+use crate::readers::xml_tree::{parse_tree, Element, Xml};
+use crate::readers::{
+    guess_media_type, inline_text, split_into_chapters, toc_from_chapters, trim_inlines,
+    FormatReader, ReadOptions,
+};
+use crate::security::{self, SecurityLimits};
+
+pub struct DocxReader;
+
+impl FormatReader for DocxReader {
+    fn detect(header: &[u8]) -> DetectResult {
+        let is_docx = header.starts_with(b"PK\x03\x04") && header.windows(5).any(|w| w == b"word/");
+        DetectResult {
+            format: Format::Docx,
+            confidence: if is_docx { 0.8 } else { 0.0 },
+            mime_type: Format::Docx.mime_type(),
+        }
+    }
+
+    fn read<R: Read + Seek>(
+        input: R,
+        opts: &ReadOptions,
+        progress: Option<&dyn ProgressHandler>,
+    ) -> Result<Document, ReadError> {
+        let mut archive =
+            ZipArchive::new(input).map_err(|e| malformed(format!("Invalid ZIP archive: {e}")))?;
+        security::check_file_count(archive.len() as u64, &opts.security)?;
+        read_docx_impl(&mut archive, opts, progress)
+    }
+}
+
+fn malformed(detail: impl Into<String>) -> ReadError {
+    ReadError::MalformedFile {
+        format: "DOCX".into(),
+        detail: detail.into(),
+    }
+}
+
+/// Read a package part, or `None` when the package does not contain it.
+fn read_part<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    path: &str,
+    limits: &SecurityLimits,
+) -> Result<Option<Vec<u8>>, ReadError> {
+    let mut file = match archive.by_name(path) {
+        Ok(file) => file,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(malformed(format!("Failed to open {}: {}", path, e))),
+    };
+    security::check_path_traversal(path)?;
+    security::check_resource_size(path, file.size(), limits)?;
+    let mut buf = Vec::with_capacity(file.size() as usize);
+    file.read_to_end(&mut buf)
+        .map_err(|e| malformed(format!("Failed to read {}: {}", path, e)))?;
+    Ok(Some(buf))
+}
+
+fn read_xml_part<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    path: &str,
+    limits: &SecurityLimits,
+) -> Result<Option<Element>, ReadError> {
+    match read_part(archive, path, limits)? {
+        Some(bytes) => parse_tree(&String::from_utf8_lossy(&bytes), limits, |detail| {
+            malformed(format!("{}: {}", path, detail))
+        })
+        .map(Some),
+        None => Ok(None),
+    }
+}
+
+fn read_docx_impl<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    opts: &ReadOptions,
+    progress: Option<&dyn ProgressHandler>,
+) -> Result<Document, ReadError> {
+    let limits = &opts.security;
+    emit_progress(
+        progress,
+        "Reading DOCX",
+        0,
+        Some(4),
+        Some("Reading package"),
+    );
+
+    let main_path = read_xml_part(archive, "_rels/.rels", limits)?
+        .and_then(|rels| {
+            parse_rels(&rels, "")
+                .into_values()
+                .find(|r| r.kind == "officeDocument")
+                .map(|r| r.target)
+        })
+        .unwrap_or_else(|| "word/document.xml".to_string());
+    let document = read_xml_part(archive, &main_path, limits)?
+        .ok_or_else(|| ReadError::MissingContent(format!("Missing main document: {main_path}")))?;
+    let body = document
+        .child("body")
+        .ok_or_else(|| malformed("document has no <w:body>"))?;
+
+    let (part_dir, part_name) = main_path.rsplit_once('/').unwrap_or(("", &main_path));
+    let rels_path = if part_dir.is_empty() {
+        format!("_rels/{}.rels", part_name)
+    } else {
+        format!("{}/_rels/{}.rels", part_dir, part_name)
+    };
+    let rels = read_xml_part(archive, &rels_path, limits)?
+        .map(|r| parse_rels(&r, part_dir))
+        .unwrap_or_default();
+    // Styles, numbering and notes live at the targets the document's rels give,
+    // conventionally next to it.
+    let related = |kind: &str, default: &str| {
+        rels.values()
+            .find(|r| r.kind == kind && !r.external)
+            .map(|r| r.target.clone())
+            .unwrap_or_else(|| join_path(part_dir, default))
+    };
+    let styles_path = related("styles", "styles.xml");
+    let numbering_path = related("numbering", "numbering.xml");
+    let footnotes_path = related("footnotes", "footnotes.xml");
+    let endnotes_path = related("endnotes", "endnotes.xml");
+
+    emit_progress(progress, "Reading DOCX", 1, Some(4), Some("Reading styles"));
+    let styles = read_xml_part(archive, &styles_path, limits)?
+        .map(|s| Styles::parse(&s))
+        .unwrap_or_default();
+    let numbering = read_xml_part(archive, &numbering_path, limits)?
+        .map(|n| Numbering::parse(&n))
+        .unwrap_or_default();
+    let mut notes = HashMap::new();
+    for (kind, path) in [
+        (NoteKind::Footnote, &footnotes_path),
+        (NoteKind::Endnote, &endnotes_path),
+    ] {
+        if let Some(root) = read_xml_part(archive, path, limits)? {
+            for note in root.elements() {
+                // Separator "notes" hold the rule drawn above the notes, not content.
+                if note.attr("type").is_some_and(|t| t != "normal") {
+                    continue;
+                }
+                if let Some(id) = note.attr("id") {
+                    notes.insert((kind, id.to_string()), note.clone());
+                }
+            }
+        }
+    }
+
+    emit_progress(
+        progress,
+        "Reading DOCX",
+        2,
+        Some(4),
+        Some("Reading metadata"),
+    );
+    let mut metadata = read_xml_part(archive, "docProps/core.xml", limits)?
+        .map(|core| parse_core_properties(&core))
+        .unwrap_or_default();
+    if metadata.language.is_none() {
+        metadata.language = styles.default_language.clone();
+    }
+
+    emit_progress(
+        progress,
+        "Reading DOCX",
+        3,
+        Some(4),
+        Some("Converting body"),
+    );
+    let mut builder = DocxBuilder {
+        archive,
+        limits,
+        rels,
+        styles,
+        numbering,
+        notes,
+        note_numbers: HashMap::new(),
+        note_order: Vec::new(),
+        resources: ResourceMap::new(),
+        image_ids: HashMap::new(),
+        title: None,
+        subtitle: None,
+    };
+    let mut flow = Vec::new();
+    builder.blocks(body, &mut flow);
+    builder.append_notes(&mut flow);
+
+    if metadata.title.is_none() {
+        metadata.title = builder.title.take();
+    }
+    if metadata.subtitle.is_none() {
+        metadata.subtitle = builder.subtitle.take();
+    }
+    let chapters = split_into_chapters(flow);
+    if metadata.title.is_none() {
+        metadata.title = chapters.iter().find_map(|c| c.title.clone());
+    }
+    let toc = if opts.parse_toc {
+        toc_from_chapters(&chapters)
+    } else {
+        Vec::new()
+    };
+
+    emit_progress(progress, "Reading DOCX", 4, Some(4), Some("Done"));
+    Ok(Document {
+        metadata,
+        toc,
+        content: chapters,
+        resources: builder.resources,
+        text_direction: TextDirection::Ltr,
+        epub_version: None,
+        diagnostics: Vec::new(),
+    })
+}
+
+// --- XML tree ---
+
+/// WordprocessingML accessors on top of the shared tree.
+impl Element {
+    /// `w:val` of a child property element such as `<w:pStyle w:val="…"/>`.
+    fn val(&self, child: &str) -> Option<&str> {
+        self.child(child).and_then(|c| c.attr("val"))
+    }
+
+    /// Text of a `<w:t>`, whose spaces are significant.
+    fn text_preserved(&self) -> String {
+        self.children
+            .iter()
+            .filter_map(|c| match c {
+                Xml::Text(t) => Some(t.as_str()),
+                Xml::Element(_) => None,
+            })
+            .collect()
+    }
+}
+
+// --- Relationships ---
+
+struct Relationship {
+    /// Package path for internal targets, the URL for external ones.
+    target: String,
+    external: bool,
+    /// Last segment of the relationship type URI (`image`, `hyperlink`, …).
+    kind: String,
+}
+
+fn parse_rels(rels: &Element, base_dir: &str) -> HashMap<String, Relationship> {
+    rels.children_named("Relationship")
+        .filter_map(|r| {
+            let id = r.attr("Id")?;
+            let target = r.attr("Target")?;
+            let external = r.attr("TargetMode") == Some("External");
+            let kind = r
+                .attr("Type")
+                .unwrap_or("")
+                .rsplit('/')
+                .next()
+                .unwrap_or("");
+            let target = if external {
+                target.to_string()
+            } else if let Some(absolute) = target.strip_prefix('/') {
+                absolute.to_string()
+            } else {
+                join_path(base_dir, target)
+            };
+            Some((
+                id.to_string(),
+                Relationship {
+                    target,
+                    external,
+                    kind: kind.to_string(),
+                },
+            ))
+        })
+        .collect()
+}
+
+/// Resolve `target` relative to `dir`, folding `.` and `..` segments.
+fn join_path(dir: &str, target: &str) -> String {
+    let mut parts: Vec<&str> = dir.split('/').filter(|p| !p.is_empty()).collect();
+    for segment in target.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            s => parts.push(s),
+        }
+    }
+    parts.join("/")
+}
+
+// --- Styles and numbering ---
+
+/// What a paragraph style means structurally.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Role {
+    Heading(u8),
+    Title,
+    Subtitle,
+    Quote,
+    Code,
+    Caption,
+    ListBullet,
+    ListNumber,
+    Body,
+}
+
+#[derive(Debug, Default)]
+struct Style {
+    name: String,
+    based_on: Option<String>,
+    outline_level: Option<u8>,
+    /// Numbering inherited by paragraphs using the style (`List Bullet` etc.).
+    num: Option<(String, u8)>,
+    bold: Option<bool>,
+    italic: Option<bool>,
+}
+
+#[derive(Debug, Default)]
+struct Styles {
+    styles: HashMap<String, Style>,
+    default_language: Option<String>,
+}
+
+impl Styles {
+    fn parse(root: &Element) -> Self {
+        let mut styles = HashMap::new();
+        for style in root.children_named("style") {
+            let Some(id) = style.attr("styleId") else {
+                continue;
+            };
+            let ppr = style.child("pPr");
+            let rpr = style.child("rPr");
+            styles.insert(
+                id.to_string(),
+                Style {
+                    name: style.val("name").unwrap_or(id).to_ascii_lowercase(),
+                    based_on: style.val("basedOn").map(str::to_string),
+                    outline_level: ppr
+                        .and_then(|p| p.val("outlineLvl"))
+                        .and_then(|l| l.parse().ok()),
+                    num: ppr.and_then(|p| p.child("numPr")).and_then(num_pr),
+                    bold: rpr.and_then(|r| toggle(r, "b")),
+                    italic: rpr.and_then(|r| toggle(r, "i")),
+                },
+            );
+        }
+        let default_language = root
+            .child("docDefaults")
+            .and_then(|d| d.find("lang"))
+            .and_then(|l| l.attr("val"))
+            .map(str::to_string);
+        Self {
+            styles,
+            default_language,
+        }
+    }
+
+    /// Walk a style and the styles it is based on, nearest first.
+    fn chain<'a>(&'a self, id: &str) -> impl Iterator<Item = &'a Style> + 'a {
+        let mut next = self.styles.get(id);
+        let mut steps = 0;
+        std::iter::from_fn(move || {
+            // Guard against `basedOn` cycles.
+            steps += 1;
+            let style = next.filter(|_| steps <= 16)?;
+            next = style.based_on.as_deref().and_then(|b| self.styles.get(b));
+            Some(style)
+        })
+    }
+
+    fn paragraph_role(&self, id: &str) -> Role {
+        for style in self.chain(id) {
+            if let Some(role) = role_from_name(&style.name) {
+                return role;
+            }
+            if let Some(level) = style.outline_level.filter(|l| *l < 9) {
+                return Role::Heading((level + 1).min(6));
+            }
+        }
+        // Documents without styles.xml still name built-in styles by id.
+        role_from_name(&id.to_ascii_lowercase()).unwrap_or(Role::Body)
+    }
+
+    fn numbering(&self, id: &str) -> Option<(String, u8)> {
+        self.chain(id).find_map(|s| s.num.clone())
+    }
+
+    /// Bold/italic from a character style (`Strong`, `Emphasis`, custom styles).
+    fn run_flags(&self, id: &str) -> (bool, bool, bool) {
+        let mut bold = None;
+        let mut italic = None;
+        let mut code = false;
+        for style in self.chain(id) {
+            match style.name.as_str() {
+                "strong" | "book title" => bold = bold.or(Some(true)),
+                "emphasis" | "intense emphasis" | "subtle emphasis" => {
+                    italic = italic.or(Some(true))
+                }
+                n if n.contains("code") || n.contains("verbatim") || n.contains("typewriter") => {
+                    code = true
+                }
+                _ => {}
+            }
+            bold = bold.or(style.bold);
+            italic = italic.or(style.italic);
+        }
+        (bold.unwrap_or(false), italic.unwrap_or(false), code)
+    }
+}
+
+fn role_from_name(name: &str) -> Option<Role> {
+    let compact: String = name.chars().filter(|c| !c.is_whitespace()).collect();
+    if let Some(n) = compact
+        .strip_prefix("heading")
+        .and_then(|n| n.parse::<u8>().ok())
+    {
+        return Some(Role::Heading(n.clamp(1, 6)));
+    }
+    Some(match compact.as_str() {
+        "title" => Role::Title,
+        "subtitle" => Role::Subtitle,
+        "quote" | "intensequote" | "blocktext" | "blockquote" => Role::Quote,
+        "caption" => Role::Caption,
+        "listbullet" | "listbullet2" | "listbullet3" => Role::ListBullet,
+        "listnumber" | "listnumber2" | "listnumber3" => Role::ListNumber,
+        n if n.contains("code") || n.contains("preformatted") || n == "plaintext" => Role::Code,
+        _ => return None,
+    })
+}
+
+/// `(numId, ilvl)` from a `<w:numPr>`; `numId` 0 switches numbering off.
+fn num_pr(num_pr: &Element) -> Option<(String, u8)> {
+    let id = num_pr.val("numId")?;
+    let level = num_pr.val("ilvl").and_then(|l| l.parse().ok()).unwrap_or(0);
+    Some((id.to_string(), level))
+}
+
+/// A toggle property such as `<w:b/>` or `<w:i w:val="0"/>`.
+fn toggle(rpr: &Element, name: &str) -> Option<bool> {
+    rpr.child(name)
+        .map(|e| !matches!(e.attr("val"), Some("0" | "false" | "off" | "none")))
+}
+
+#[derive(Debug, Default)]
+struct Numbering {
+    /// `numId` → `abstractNumId`.
+    nums: HashMap<String, String>,
+    /// `(abstractNumId, ilvl)` → number format (`decimal`, `bullet`, …).
+    formats: HashMap<(String, u8), String>,
+}
+
+impl Numbering {
+    fn parse(root: &Element) -> Self {
+        let mut numbering = Self::default();
+        for abs in root.children_named("abstractNum") {
+            let Some(id) = abs.attr("abstractNumId") else {
+                continue;
+            };
+            for lvl in abs.children_named("lvl") {
+                let level = lvl.attr("ilvl").and_then(|l| l.parse().ok()).unwrap_or(0);
+                if let Some(fmt) = lvl.val("numFmt") {
+                    numbering
+                        .formats
+                        .insert((id.to_string(), level), fmt.to_string());
+                }
+            }
+        }
+        for num in root.children_named("num") {
+            if let (Some(id), Some(abs)) = (num.attr("numId"), num.val("abstractNumId")) {
+                numbering.nums.insert(id.to_string(), abs.to_string());
+            }
+        }
+        numbering
+    }
+
+    fn is_ordered(&self, num_id: &str, level: u8) -> bool {
+        self.nums
+            .get(num_id)
+            .and_then(|abs| self.formats.get(&(abs.clone(), level)))
+            .is_some_and(|fmt| fmt != "bullet" && fmt != "none")
+    }
+}
+
+// --- Core properties ---
+
+fn parse_core_properties(core: &Element) -> Metadata {
+    let mut metadata = Metadata::default();
+    let text = |name: &str| core.child(name).map(|e| e.text()).filter(|t| !t.is_empty());
+    metadata.title = text("title");
+    metadata.description = text("description");
+    metadata.language = text("language");
+    metadata.authors = text("creator")
+        .map(|c| {
+            c.split(';')
+                .map(str::trim)
+                .filter(|a| !a.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default();
+    metadata.publish_date = text("created").map(|d| d.split('T').next().unwrap_or(&d).to_string());
+    for subject in [text("subject"), text("category")].into_iter().flatten() {
+        metadata.subjects.push(subject);
+    }
+    if let Some(keywords) = text("keywords") {
+        for k in keywords.split([',', ';']).map(str::trim) {
+            if !k.is_empty() && !metadata.subjects.iter().any(|s| s == k) {
+                metadata.subjects.push(k.to_string());
+            }
+        }
+    }
+    metadata
+}
+
+// --- Body ---
+
+/// One open list level while grouping numbered paragraphs.
+struct ListLevel {
+    ordered: bool,
+    items: Vec<Vec<ContentNode>>,
+}
+
+/// Run formatting that maps to inline wrappers.
+#[derive(Default)]
+struct RunFormat {
+    bold: bool,
+    italic: bool,
+    superscript: bool,
+    subscript: bool,
+    code: bool,
+}
+
+struct DocxBuilder<'a, R> {
+    archive: &'a mut ZipArchive<R>,
+    limits: &'a SecurityLimits,
+    rels: HashMap<String, Relationship>,
+    styles: Styles,
+    numbering: Numbering,
+    notes: HashMap<(NoteKind, String), Element>,
+    /// Note → number, in order of first reference.
+    note_numbers: HashMap<(NoteKind, String), usize>,
+    note_order: Vec<(NoteKind, String)>,
+    resources: ResourceMap,
+    /// Package path → resource id, so repeated images share one resource.
+    image_ids: HashMap<String, String>,
+    /// Text of the first `Title`/`Subtitle` paragraphs, for metadata fallback.
+    title: Option<String>,
+    subtitle: Option<String>,
+}
+
+impl<R: Read + Seek> DocxBuilder<'_, R> {
+    /// Convert block-level content (`w:body`, table cells, notes) into `out`.
+    fn blocks(&mut self, parent: &Element, out: &mut Vec<ContentNode>) {
+        let mut list: Vec<ListLevel> = Vec::new();
+        // Consecutive quote paragraphs form one block quote.
+        let mut in_quote = false;
+        for el in parent.elements() {
+            match el.name.as_str() {
+                "p" => self.paragraph(el, out, &mut list, &mut in_quote),
+                "tbl" => {
+                    flush_list(&mut list, out);
+                    in_quote = false;
+                    self.table(el, out);
+                }
+                "sdt" | "customXml" => {
+                    flush_list(&mut list, out);
+                    in_quote = false;
+                    if let Some(content) = el.child("sdtContent") {
+                        self.blocks(content, out);
+                    } else {
+                        self.blocks(el, out);
+                    }
+                }
+                _ => {}
+            }
+        }
+        flush_list(&mut list, out);
+    }
+
+    fn paragraph(
+        &mut self,
+        p: &Element,
+        out: &mut Vec<ContentNode>,
+        list: &mut Vec<ListLevel>,
+        in_quote: &mut bool,
+    ) {
+        let ppr = p.child("pPr");
+        let style_id = ppr.and_then(|p| p.val("pStyle")).unwrap_or("");
+        let mut role = self.styles.paragraph_role(style_id);
+        if let Some(level) = ppr
+            .and_then(|p| p.val("outlineLvl"))
+            .and_then(|l| l.parse::<u8>().ok())
+            .filter(|l| *l < 9)
+        {
+            role = Role::Heading((level + 1).min(6));
+        }
+        let num = ppr
+            .and_then(|p| p.child("numPr"))
+            .and_then(num_pr)
+            .or_else(|| self.styles.numbering(style_id))
+            .filter(|(id, _)| id != "0");
+
+        let mut inlines = Vec::new();
+        let mut images = Vec::new();
+        self.inline_children(p, &RunFormat::default(), &mut inlines, &mut images);
+        trim_inlines(&mut inlines);
+        let text = inline_text(&inlines);
+
+        // List items: numbered paragraphs, or list styles without numbering.
+        let list_item = match (&num, role) {
+            (Some((id, level)), Role::Body | Role::ListBullet | Role::ListNumber) => {
+                Some((*level, self.numbering.is_ordered(id, *level)))
+            }
+            (None, Role::ListBullet) => Some((0, false)),
+            (None, Role::ListNumber) => Some((0, true)),
+            _ => None,
+        };
+        if let Some((level, ordered)) = list_item {
+            *in_quote = false;
+            let mut item = Vec::new();
+            if !inlines.is_empty() {
+                item.push(ContentNode::Paragraph { children: inlines });
+            }
+            item.extend(images);
+            if !item.is_empty() {
+                push_list_item(list, level as usize, ordered, item);
+            }
+            return;
+        }
+        flush_list(list, out);
+
+        let was_quote = std::mem::replace(in_quote, role == Role::Quote);
+        match role {
+            Role::Heading(level) if !text.is_empty() => out.push(ContentNode::Heading {
+                level,
+                children: inlines,
+            }),
+            Role::Title if !text.is_empty() => {
+                self.title.get_or_insert(text);
+                out.push(ContentNode::Heading {
+                    level: 1,
+                    children: inlines,
+                });
+            }
+            Role::Subtitle if !text.is_empty() => {
+                self.subtitle.get_or_insert(text);
+                out.push(ContentNode::Paragraph {
+                    children: vec![InlineNode::Emphasis(inlines)],
+                });
+            }
+            Role::Quote if !inlines.is_empty() => {
+                let para = ContentNode::Paragraph { children: inlines };
+                match out.last_mut() {
+                    Some(ContentNode::BlockQuote { children }) if was_quote => children.push(para),
+                    _ => out.push(ContentNode::BlockQuote {
+                        children: vec![para],
+                    }),
+                }
+            }
+            Role::Code => {
+                // Code keeps its spacing; read the raw run text.
+                let line = plain_runs(p);
+                match out.last_mut() {
+                    Some(ContentNode::CodeBlock { code, .. }) => {
+                        code.push('\n');
+                        code.push_str(&line);
+                    }
+                    _ if line.trim().is_empty() => {}
+                    _ => out.push(ContentNode::CodeBlock {
+                        language: None,
+                        code: line,
+                    }),
+                }
+            }
+            Role::Caption if !text.is_empty() => match out.last_mut() {
+                Some(ContentNode::Image { caption, .. })
+                    if caption.is_none() && images.is_empty() =>
+                {
+                    *caption = Some(text)
+                }
+                _ => out.push(ContentNode::Paragraph {
+                    children: vec![InlineNode::Emphasis(inlines)],
+                }),
+            },
+            _ if text.is_empty() && images.is_empty() && has_bottom_border(ppr) => {
+                out.push(ContentNode::HorizontalRule)
+            }
+            _ if is_scene_break(&text) => out.push(ContentNode::HorizontalRule),
+            _ if !text.is_empty() => out.push(ContentNode::Paragraph { children: inlines }),
+            _ => {}
+        }
+        out.extend(images);
+    }
+
+    fn table(&mut self, tbl: &Element, out: &mut Vec<ContentNode>) {
+        let mut headers = Vec::new();
+        let mut rows = Vec::new();
+        let mut images = Vec::new();
+        for tr in tbl.children_named("tr") {
+            let is_header = tr
+                .child("trPr")
+                .and_then(|p| p.child("tblHeader"))
+                .is_some_and(|h| !matches!(h.attr("val"), Some("0" | "false")));
+            let mut row = Vec::new();
+            for tc in tr.children_named("tc") {
+                let tcpr = tc.child("tcPr");
+                let mut cell = Vec::new();
+                // The continuation of a vertical merge repeats nothing.
+                let continues_merge = tcpr
+                    .and_then(|p| p.child("vMerge"))
+                    .is_some_and(|m| m.attr("val") != Some("restart"));
+                if !continues_merge {
+                    for p in tc.children_named("p") {
+                        let mut inlines = Vec::new();
+                        self.inline_children(p, &RunFormat::default(), &mut inlines, &mut images);
+                        trim_inlines(&mut inlines);
+                        if inlines.is_empty() {
+                            continue;
+                        }
+                        if !cell.is_empty() {
+                            cell.push(InlineNode::LineBreak);
+                        }
+                        cell.extend(inlines);
+                    }
+                }
+                row.push(cell);
+                // Keep columns aligned across horizontally merged cells.
+                let span = tcpr
+                    .and_then(|p| p.val("gridSpan"))
+                    .and_then(|s| s.parse::<usize>().ok())
+                    .unwrap_or(1);
+                row.extend((1..span.min(64)).map(|_| Vec::new()));
+            }
+            if is_header && rows.is_empty() && headers.is_empty() {
+                headers = row;
+            } else {
+                rows.push(row);
+            }
+        }
+        if !headers.is_empty() || !rows.is_empty() {
+            out.push(ContentNode::Table { headers, rows });
+        }
+        out.extend(images);
+    }
+
+    /// Inline content of a paragraph or inline container. Drawings are block
+    /// images in the IR, so they are collected into `images`.
+    fn inline_children(
+        &mut self,
+        el: &Element,
+        format: &RunFormat,
+        out: &mut Vec<InlineNode>,
+        images: &mut Vec<ContentNode>,
+    ) {
+        for child in el.elements() {
+            match child.name.as_str() {
+                "r" => self.run(child, format, out, images),
+                "hyperlink" => {
+                    let href = match (child.attr("id"), child.attr("anchor")) {
+                        (Some(id), _) => self.rels.get(id).map(|r| {
+                            if r.external {
+                                r.target.clone()
+                            } else {
+                                format!("#{}", r.target)
+                            }
+                        }),
+                        (None, Some(anchor)) => Some(format!("#{}", anchor)),
+                        (None, None) => None,
+                    };
+                    let mut children = Vec::new();
+                    self.inline_children(child, format, &mut children, images);
+                    match href {
+                        Some(href) if !children.is_empty() => {
+                            out.push(InlineNode::Link { href, children })
+                        }
+                        _ => children.into_iter().for_each(|c| push_inline(out, c)),
+                    }
+                }
+                "sdt" => {
+                    if let Some(content) = child.child("sdtContent") {
+                        self.inline_children(content, format, out, images);
+                    }
+                }
+                // Tracked insertions are part of the text; deletions are not.
+                "ins" | "moveTo" | "smartTag" | "customXml" | "fldSimple" | "dir" | "bdo" => {
+                    self.inline_children(child, format, out, images)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn run(
+        &mut self,
+        r: &Element,
+        outer: &RunFormat,
+        out: &mut Vec<InlineNode>,
+        images: &mut Vec<ContentNode>,
+    ) {
+        let rpr = r.child("rPr");
+        let (style_bold, style_italic, style_code) = rpr
+            .and_then(|p| p.val("rStyle"))
+            .map(|s| self.styles.run_flags(s))
+            .unwrap_or_default();
+        let vert = rpr.and_then(|p| p.val("vertAlign"));
+        let format = RunFormat {
+            bold: outer.bold || rpr.and_then(|p| toggle(p, "b")).unwrap_or(style_bold),
+            italic: outer.italic || rpr.and_then(|p| toggle(p, "i")).unwrap_or(style_italic),
+            superscript: outer.superscript || vert == Some("superscript"),
+            subscript: outer.subscript || vert == Some("subscript"),
+            code: outer.code || style_code,
+        };
+
+        let mut content = Vec::new();
+        for child in r.elements() {
+            match child.name.as_str() {
+                "t" => {
+                    let text = child.text_preserved();
+                    if format.code {
+                        content.push(InlineNode::Code(text));
+                    } else {
+                        push_inline(&mut content, InlineNode::Text(text));
+                    }
+                }
+                "tab" | "ptab" => push_inline(&mut content, InlineNode::Text(" ".into())),
+                "noBreakHyphen" => push_inline(&mut content, InlineNode::Text("\u{2011}".into())),
+                "softHyphen" => push_inline(&mut content, InlineNode::Text("\u{AD}".into())),
+                "sym" => {
+                    if let Some(c) = child
+                        .attr("char")
+                        .and_then(|c| u32::from_str_radix(c, 16).ok())
+                        .and_then(char::from_u32)
+                    {
+                        push_inline(&mut content, InlineNode::Text(c.to_string()));
+                    }
+                }
+                // Page and column breaks are layout, not content.
+                "br" if child.attr("type").map_or(true, |t| t == "textWrapping") => {
+                    content.push(InlineNode::LineBreak)
+                }
+                "cr" => content.push(InlineNode::LineBreak),
+                "footnoteReference" | "endnoteReference" => {
+                    let kind = if child.name == "footnoteReference" {
+                        NoteKind::Footnote
+                    } else {
+                        NoteKind::Endnote
+                    };
+                    if let Some(id) = child.attr("id") {
+                        // Flush so the marker is not wrapped in the run's superscript.
+                        wrap_into(out, std::mem::take(&mut content), &format);
+                        out.push(self.note_reference(kind, id));
+                    }
+                }
+                "drawing" => {
+                    if let Some(image) = self.drawing(child) {
+                        images.push(image);
+                    }
+                }
+                "pict" | "object" => {
+                    if let Some(id) = child.find("imagedata").and_then(|d| d.attr("id")) {
+                        if let Some(resource_id) = self.image(id) {
+                            images.push(ContentNode::Image {
+                                resource_id,
+                                alt_text: None,
+                                caption: None,
+                            });
+                        }
+                    }
+                }
+                "ruby" => {
+                    let base = child.child("rubyBase").map(plain_runs).unwrap_or_default();
+                    let annotation = child.child("rt").map(plain_runs).unwrap_or_default();
+                    if !base.is_empty() {
+                        content.push(InlineNode::Ruby { base, annotation });
+                    }
+                }
+                _ => {}
+            }
+        }
+        wrap_into(out, content, &format);
+    }
+
+    fn note_reference(&mut self, kind: NoteKind, id: &str) -> InlineNode {
+        let key = (kind, id.to_string());
+        let next = self.note_numbers.len() + 1;
+        let number = *self.note_numbers.entry(key.clone()).or_insert_with(|| {
+            self.note_order.push(key);
+            next
+        });
+        InlineNode::NoteRef {
+            id: note_id(kind, number),
+            label: number.to_string(),
+        }
+    }
+
+    /// Append referenced footnotes and endnotes, in order of first reference.
+    fn append_notes(&mut self, flow: &mut Vec<ContentNode>) {
+        // Notes may reference further notes, which extends `note_order`.
+        let mut i = 0;
+        while i < self.note_order.len() {
+            let key = self.note_order[i].clone();
+            i += 1;
+            let mut children = Vec::new();
+            if let Some(note) = self.notes.get(&key).cloned() {
+                self.blocks(&note, &mut children);
+            }
+            flow.push(ContentNode::Note {
+                id: note_id(key.0, i),
+                kind: key.0,
+                children,
+            });
+        }
+    }
+
+    /// An inline or floating DrawingML picture.
+    fn drawing(&mut self, drawing: &Element) -> Option<ContentNode> {
+        let embed = drawing.find("blip")?;
+        let rel_id = embed.attr("embed").or_else(|| embed.attr("link"))?;
+        let resource_id = self.image(rel_id)?;
+        let doc_pr = drawing.find("docPr");
+        let alt_text = doc_pr
+            .and_then(|d| d.attr("descr").or_else(|| d.attr("title")))
+            .map(str::trim)
+            .filter(|a| !a.is_empty())
+            .map(str::to_string);
+        Some(ContentNode::Image {
+            resource_id,
+            alt_text,
+            caption: None,
+        })
+    }
+
+    /// Load the image a relationship points at; returns its resource id.
+    fn image(&mut self, rel_id: &str) -> Option<String> {
+        let rel = self.rels.get(rel_id)?;
+        if rel.external {
+            tracing::warn!("Skipping linked image '{}'", rel.target);
+            return None;
+        }
+        let path = rel.target.clone();
+        if let Some(id) = self.image_ids.get(&path) {
+            return Some(id.clone());
+        }
+        let media_type = guess_media_type(&path);
+        if !media_type.starts_with("image/") {
+            // EMF/WMF and other formats no writer can display.
+            tracing::warn!("Skipping unsupported image '{}'", path);
+            return None;
+        }
+        let data = match read_part(self.archive, &path, self.limits) {
+            Ok(Some(data)) => data,
+            Ok(None) => {
+                tracing::warn!("Skipping missing image '{}'", path);
+                return None;
+            }
+            Err(e) => {
+                tracing::warn!("Skipping image '{}': {}", path, e);
+                return None;
+            }
+        };
+        let name = path.rsplit('/').next().unwrap_or(&path);
+        let mut id = name.to_string();
+        let mut n = 2;
+        while self.resources.get(&id).is_some() {
+            id = format!("{}-{}", n, name);
+            n += 1;
+        }
+        self.resources.insert(
+            id.clone(),
+            Resource {
+                id: id.clone(),
+                media_type: media_type.to_string(),
+                data,
+                filename: Some(id.clone()),
+                obfuscation: None,
+            },
+        );
+        self.image_ids.insert(path, id.clone());
+        Some(id)
+    }
+}
+
+/// Raw text of all runs below `el`, tabs as spaces.
+fn plain_runs(el: &Element) -> String {
+    fn collect(el: &Element, out: &mut String) {
+        for child in el.elements() {
+            match child.name.as_str() {
+                "t" => out.push_str(&child.text_preserved()),
+                "tab" => out.push_str("    "),
+                "br" | "cr" => out.push('\n'),
+                "del" | "moveFrom" | "instrText" | "delText" => {}
+                _ => collect(child, out),
+            }
+        }
+    }
+    let mut s = String::new();
+    collect(el, &mut s);
+    s
+}
+
+fn has_bottom_border(ppr: Option<&Element>) -> bool {
+    ppr.and_then(|p| p.child("pBdr"))
+        .and_then(|b| b.child("bottom"))
+        .is_some_and(|b| b.attr("val").is_some_and(|v| v != "none" && v != "nil"))
+}
+
+fn is_scene_break(text: &str) -> bool {
+    !text.is_empty()
+        && text
+            .chars()
+            .all(|c| matches!(c, '*' | '#' | '~' | '—' | '•' | '⁂') || c.is_whitespace())
+}
+
+/// Anchor id of a note; footnotes and endnotes share one numbering.
+fn note_id(kind: NoteKind, number: usize) -> String {
+    match kind {
+        NoteKind::Footnote => format!("fn-{}", number),
+        NoteKind::Endnote => format!("en-{}", number),
+    }
+}
+
+/// Wrap a run's content in its formatting and append it, merging with the
+/// previous run when the formatting matches (Word splits runs freely).
+fn wrap_into(out: &mut Vec<InlineNode>, content: Vec<InlineNode>, format: &RunFormat) {
+    if content.is_empty() {
+        return;
+    }
+    let mut nodes = content;
+    if format.superscript {
+        nodes = vec![InlineNode::Superscript(nodes)];
+    } else if format.subscript {
+        nodes = vec![InlineNode::Subscript(nodes)];
+    }
+    if format.italic {
+        nodes = vec![InlineNode::Emphasis(nodes)];
+    }
+    if format.bold {
+        nodes = vec![InlineNode::Strong(nodes)];
+    }
+    for node in nodes {
+        push_inline(out, node);
+    }
+}
+
+fn push_inline(out: &mut Vec<InlineNode>, node: InlineNode) {
+    match (out.last_mut(), node) {
+        (Some(InlineNode::Text(prev)), InlineNode::Text(t)) => prev.push_str(&t),
+        (Some(InlineNode::Code(prev)), InlineNode::Code(t)) => prev.push_str(&t),
+        (Some(InlineNode::Strong(prev)), InlineNode::Strong(c))
+        | (Some(InlineNode::Emphasis(prev)), InlineNode::Emphasis(c))
+        | (Some(InlineNode::Superscript(prev)), InlineNode::Superscript(c))
+        | (Some(InlineNode::Subscript(prev)), InlineNode::Subscript(c)) => {
+            c.into_iter().for_each(|n| push_inline(prev, n))
+        }
+        (_, InlineNode::Text(t)) if t.is_empty() => {}
+        (_, node) => out.push(node),
+    }
+}
+
+/// Add an item at `level`, opening or closing nested lists as needed.
+fn push_list_item(list: &mut Vec<ListLevel>, level: usize, ordered: bool, item: Vec<ContentNode>) {
+    let level = level.min(8);
+    while list.len() > level + 1 {
+        close_level(list);
+    }
+    while list.len() < level + 1 {
+        list.push(ListLevel {
+            ordered,
+            items: Vec::new(),
+        });
+    }
+    if let Some(top) = list.last_mut() {
+        top.items.push(item);
+    }
+}
+
+/// Close the innermost list level into the last item of its parent.
+fn close_level(list: &mut Vec<ListLevel>) {
+    let Some(done) = list.pop() else {
+        return;
+    };
+    let node = ContentNode::List {
+        ordered: done.ordered,
+        items: done.items,
+    };
+    if let Some(parent) = list.last_mut() {
+        match parent.items.last_mut() {
+            Some(item) => item.push(node),
+            None => parent.items.push(vec![node]),
+        }
+    }
+}
+
+fn flush_list(list: &mut Vec<ListLevel>, out: &mut Vec<ContentNode>) {
+    while list.len() > 1 {
+        close_level(list);
+    }
+    if let Some(root) = list.pop() {
+        out.push(ContentNode::List {
+            ordered: root.ordered,
+            items: root.items,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+
+    const DOCUMENT: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<w:document xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships" xmlns:a="http://schemas.openxmlformats.org/drawingml/2006/main" xmlns:wp="http://schemas.openxmlformats.org/drawingml/2006/wordprocessingDrawing">
+<w:body>
+<w:p><w:pPr><w:pStyle w:val="Heading1"/></w:pPr><w:r><w:t>Chapter One</w:t></w:r></w:p>
+<w:p><w:r><w:t xml:space="preserve">Plain </w:t></w:r><w:r><w:rPr><w:b/></w:rPr><w:t>bold</w:t></w:r><w:r><w:rPr><w:b/></w:rPr><w:t xml:space="preserve"> still</w:t></w:r><w:r><w:t xml:space="preserve"> and </w:t></w:r><w:r><w:rPr><w:i/></w:rPr><w:t>italic</w:t></w:r><w:r><w:t>.</w:t></w:r><w:r><w:rPr><w:rStyle w:val="FootnoteReference"/></w:rPr><w:footnoteReference w:id="2"/></w:r></w:p>
+<w:p><w:pPr><w:pStyle w:val="MyQuote"/></w:pPr><w:r><w:t>Quoted.</w:t></w:r></w:p>
+<w:p><w:pPr><w:pStyle w:val="ListParagraph"/><w:numPr><w:ilvl w:val="0"/><w:numId w:val="1"/></w:numPr></w:pPr><w:r><w:t>First</w:t></w:r></w:p>
+<w:p><w:pPr><w:pStyle w:val="ListParagraph"/><w:numPr><w:ilvl w:val="1"/><w:numId w:val="1"/></w:numPr></w:pPr><w:r><w:t>Nested</w:t></w:r></w:p>
+<w:p><w:pPr><w:pStyle w:val="ListParagraph"/><w:numPr><w:ilvl w:val="0"/><w:numId w:val="1"/></w:numPr></w:pPr><w:r><w:t>Second</w:t></w:r></w:p>
+<w:p><w:r><w:drawing><wp:inline><wp:docPr id="1" name="Picture 1" descr="A cat"/><a:graphic><a:graphicData><a:blip r:embed="rId5"/></a:graphicData></a:graphic></wp:inline></w:drawing></w:r></w:p>
+<w:p><w:pPr><w:pStyle w:val="Heading1"/></w:pPr><w:r><w:t>Chapter Two</w:t></w:r></w:p>
+<w:tbl><w:tr><w:trPr><w:tblHeader/></w:trPr><w:tc><w:p><w:r><w:t>A</w:t></w:r></w:p></w:tc><w:tc><w:p><w:r><w:t>B</w:t></w:r></w:p></w:tc></w:tr>
+<w:tr><w:tc><w:p><w:r><w:t>1</w:t></w:r></w:p></w:tc><w:tc><w:p><w:r><w:t>2</w:t></w:r><w:r><w:rPr><w:vertAlign w:val="superscript"/></w:rPr><w:t>x</w:t></w:r></w:p></w:tc></w:tr></w:tbl>
+<w:p><w:r><w:t>See </w:t></w:r><w:hyperlink r:id="rId9"><w:r><w:t>site</w:t></w:r></w:hyperlink></w:p>
+<w:sectPr/>
+</w:body>
+</w:document>"#;
+
+    const STYLES: &str = r#"<w:styles xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">
+<w:docDefaults><w:rPrDefault><w:rPr><w:lang w:val="en-GB"/></w:rPr></w:rPrDefault></w:docDefaults>
+<w:style w:type="paragraph" w:styleId="Heading1"><w:name w:val="heading 1"/><w:pPr><w:outlineLvl w:val="0"/></w:pPr></w:style>
+<w:style w:type="paragraph" w:styleId="Quote"><w:name w:val="Quote"/></w:style>
+<w:style w:type="paragraph" w:styleId="MyQuote"><w:name w:val="Letter"/><w:basedOn w:val="Quote"/></w:style>
+<w:style w:type="paragraph" w:styleId="ListParagraph"><w:name w:val="List Paragraph"/></w:style>
+</w:styles>"#;
+
+    const NUMBERING: &str = r#"<w:numbering xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">
+<w:abstractNum w:abstractNumId="0"><w:lvl w:ilvl="0"><w:numFmt w:val="decimal"/></w:lvl><w:lvl w:ilvl="1"><w:numFmt w:val="bullet"/></w:lvl></w:abstractNum>
+<w:num w:numId="1"><w:abstractNumId w:val="0"/></w:num>
+</w:numbering>"#;
+
+    const FOOTNOTES: &str = r#"<w:footnotes xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">
+<w:footnote w:type="separator" w:id="-1"><w:p><w:r><w:separator/></w:r></w:p></w:footnote>
+<w:footnote w:id="2"><w:p><w:r><w:footnoteRef/></w:r><w:r><w:t xml:space="preserve"> A footnote.</w:t></w:r></w:p></w:footnote>
+</w:footnotes>"#;
+
+    const RELS: &str = r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+<Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles" Target="styles.xml"/>
+<Relationship Id="rId5" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/image" Target="media/image1.png"/>
+<Relationship Id="rId9" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/hyperlink" Target="https://example.com" TargetMode="External"/>
+</Relationships>"#;
+
+    const CORE: &str = r#"<cp:coreProperties xmlns:cp="http://schemas.openxmlformats.org/package/2006/metadata/core-properties" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:dcterms="http://purl.org/dc/terms/">
+<dc:title>Manuscript</dc:title><dc:creator>Ann Author; Bob Writer</dc:creator><cp:keywords>fiction, draft</cp:keywords><dcterms:created>2024-03-01T10:00:00Z</dcterms:created>
+</cp:coreProperties>"#;
+
+    /// A package with the root relationships plus `parts`.
+    fn package(parts: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        {
+            let mut zip = zip::ZipWriter::new(Cursor::new(&mut bytes));
+            let options: zip::write::FileOptions<'_, ()> = zip::write::FileOptions::default();
+            let root: [(&str, &[u8]); 2] = [
+                ("[Content_Types].xml", b"<Types/>"),
+                ("_rels/.rels", br#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="word/document.xml"/></Relationships>"#),
+            ];
+            for (name, data) in root.iter().chain(parts) {
+                zip.start_file(*name, options).unwrap();
+                zip.write_all(data).unwrap();
+            }
+            zip.finish().unwrap();
+        }
+        bytes
+    }
+
+    fn docx_bytes() -> Vec<u8> {
+        package(&[
+            ("word/document.xml", DOCUMENT.as_bytes()),
+            ("word/_rels/document.xml.rels", RELS.as_bytes()),
+            ("word/styles.xml", STYLES.as_bytes()),
+            ("word/numbering.xml", NUMBERING.as_bytes()),
+            ("word/footnotes.xml", FOOTNOTES.as_bytes()),
+            ("word/media/image1.png", b"\x89PNG\r\n\x1a\n"),
+            ("docProps/core.xml", CORE.as_bytes()),
+        ])
+    }
+
+    fn read() -> Document {
+        read_bytes(docx_bytes())
+    }
+
+    fn read_bytes(bytes: Vec<u8>) -> Document {
+        DocxReader::read(Cursor::new(bytes), &ReadOptions::default(), None).unwrap()
+    }
+
+    /// A `word/document.xml` whose body is `body`.
+    fn document(body: &str) -> String {
+        format!(
+            r#"<w:document xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships" xmlns:a="http://schemas.openxmlformats.org/drawingml/2006/main" xmlns:wp="http://schemas.openxmlformats.org/drawingml/2006/wordprocessingDrawing"><w:body>{}</w:body></w:document>"#,
+            body
+        )
+    }
+
+    fn para(style: &str, text: &str) -> String {
+        format!(
+            r#"<w:p><w:pPr><w:pStyle w:val="{}"/></w:pPr><w:r><w:t>{}</w:t></w:r></w:p>"#,
+            style, text
+        )
+    }
+
+    fn numbered(num_id: &str, level: u8, text: &str) -> String {
+        format!(
+            r#"<w:p><w:pPr><w:numPr><w:ilvl w:val="{}"/><w:numId w:val="{}"/></w:numPr></w:pPr><w:r><w:t>{}</w:t></w:r></w:p>"#,
+            level, num_id, text
+        )
+    }
+
+    #[test]
+    fn test_metadata_from_core_properties() {
+        let doc = read();
+        let m = &doc.metadata;
+        assert_eq!(m.title.as_deref(), Some("Manuscript"));
+        assert_eq!(m.authors, ["Ann Author", "Bob Writer"]);
+        assert_eq!(m.subjects, ["fiction", "draft"]);
+        assert_eq!(m.publish_date.as_deref(), Some("2024-03-01"));
+        assert_eq!(m.language.as_deref(), Some("en-GB"));
+    }
+
+    #[test]
+    fn test_styles_runs_lists_and_notes() {
+        let doc = read();
+        assert_eq!(doc.content.len(), 2);
+        assert_eq!(doc.toc[1].title, "Chapter Two");
+
+        let first = &doc.content[0].content;
+        assert!(matches!(first[0], ContentNode::Heading { level: 1, .. }));
+        let ContentNode::Paragraph { children } = &first[1] else {
+            panic!("expected paragraph, got {:?}", first[1]);
+        };
+        assert!(matches!(&children[0], InlineNode::Text(t) if t == "Plain "));
+        assert!(matches!(
+            &children[1],
+            InlineNode::Strong(c) if matches!(&c[..], [InlineNode::Text(t)] if t == "bold still")
+        ));
+        assert!(matches!(&children[3], InlineNode::Emphasis(_)));
+        assert!(matches!(
+            children.last(),
+            Some(InlineNode::NoteRef { id, label }) if id == "fn-1" && label == "1"
+        ));
+        assert!(matches!(first[2], ContentNode::BlockQuote { .. }));
+        let ContentNode::List {
+            ordered: true,
+            items,
+        } = &first[3]
+        else {
+            panic!("expected ordered list, got {:?}", first[3]);
+        };
+        assert_eq!(items.len(), 2);
+        assert!(matches!(
+            items[0][1],
+            ContentNode::List { ordered: false, .. }
+        ));
+        assert!(matches!(
+            &first[4],
+            ContentNode::Image { resource_id, alt_text: Some(alt), .. }
+                if resource_id == "image1.png" && alt == "A cat"
+        ));
+        assert_eq!(
+            doc.resources.get("image1.png").unwrap().media_type,
+            "image/png"
+        );
+
+        let second = &doc.content[1].content;
+        let ContentNode::Table { headers, rows } = &second[1] else {
+            panic!("expected table, got {:?}", second[1]);
+        };
+        assert_eq!(headers.len(), 2);
+        assert!(matches!(rows[0][1][1], InlineNode::Superscript(_)));
+        let ContentNode::Paragraph { children } = &second[2] else {
+            panic!("expected paragraph");
+        };
+        assert!(
+            matches!(&children[1], InlineNode::Link { href, .. } if href == "https://example.com")
+        );
+        let ContentNode::Note {
+            id,
+            kind: NoteKind::Footnote,
+            children,
+        } = second.last().unwrap()
+        else {
+            panic!("expected footnote, got {:?}", second.last());
+        };
+        assert_eq!(id, "fn-1");
+        assert!(matches!(
+            &children[0],
+            ContentNode::Paragraph { children } if matches!(&children[0], InlineNode::Text(t) if t == "A footnote.")
+        ));
+    }
+
+    #[test]
+    fn test_heading_levels_from_styles() {
+        let styles = r#"<w:styles xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">
+<w:style w:type="paragraph" w:styleId="PartBase"><w:name w:val="Part Base"/><w:pPr><w:outlineLvl w:val="0"/></w:pPr></w:style>
+<w:style w:type="paragraph" w:styleId="Part"><w:name w:val="Part Title"/><w:basedOn w:val="PartBase"/></w:style>
+<w:style w:type="paragraph" w:styleId="berschrift2"><w:name w:val="heading 2"/></w:style>
+<w:style w:type="paragraph" w:styleId="SideHead"><w:name w:val="Side Head"/><w:basedOn w:val="berschrift2"/></w:style>
+</w:styles>"#;
+        let body = [
+            para("Part", "Part One"),
+            para("berschrift2", "Localized"),
+            para("SideHead", "Derived"),
+            r#"<w:p><w:pPr><w:outlineLvl w:val="2"/></w:pPr><w:r><w:t>Direct</w:t></w:r></w:p>"#
+                .to_string(),
+            para("Heading4", "By id"),
+            para("Part", "Part Two"),
+        ]
+        .concat();
+        let doc = read_bytes(package(&[
+            ("word/document.xml", document(&body).as_bytes()),
+            ("word/styles.xml", styles.as_bytes()),
+        ]));
+
+        // The custom style inherits outline level 0, so each one opens a chapter.
+        assert_eq!(doc.content.len(), 2);
+        assert_eq!(doc.content[1].title.as_deref(), Some("Part Two"));
+        let levels: Vec<(u8, String)> = doc.content[0]
+            .content
+            .iter()
+            .map(|node| match node {
+                ContentNode::Heading {
+                    level, children, ..
+                } => (*level, inline_text(children)),
+                other => panic!("expected heading, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            levels,
+            [
+                (1, "Part One".to_string()),
+                (2, "Localized".to_string()),
+                (2, "Derived".to_string()),
+                (3, "Direct".to_string()),
+                (4, "By id".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_numbered_and_bulleted_lists() {
+        let numbering = r#"<w:numbering xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">
+<w:abstractNum w:abstractNumId="0"><w:lvl w:ilvl="0"><w:numFmt w:val="bullet"/></w:lvl></w:abstractNum>
+<w:abstractNum w:abstractNumId="1"><w:lvl w:ilvl="0"><w:numFmt w:val="lowerRoman"/></w:lvl><w:lvl w:ilvl="1"><w:numFmt w:val="decimal"/></w:lvl></w:abstractNum>
+<w:num w:numId="2"><w:abstractNumId w:val="0"/></w:num>
+<w:num w:numId="3"><w:abstractNumId w:val="1"/></w:num>
+</w:numbering>"#;
+        let body = [
+            numbered("2", 0, "Apples"),
+            numbered("2", 0, "Pears"),
+            para("Normal", "Between"),
+            numbered("3", 0, "One"),
+            numbered("3", 1, "One point one"),
+            numbered("3", 0, "Two"),
+            para("Normal", "Between"),
+            para("ListBullet", "Styled bullet"),
+            numbered("0", 0, "Numbering off"),
+        ]
+        .concat();
+        let doc = read_bytes(package(&[
+            ("word/document.xml", document(&body).as_bytes()),
+            ("word/numbering.xml", numbering.as_bytes()),
+        ]));
+        let nodes = &doc.content[0].content;
+
+        let ContentNode::List {
+            ordered: false,
+            items,
+        } = &nodes[0]
+        else {
+            panic!("expected bulleted list, got {:?}", nodes[0]);
+        };
+        assert_eq!(items.len(), 2);
+
+        let ContentNode::List {
+            ordered: true,
+            items,
+        } = &nodes[2]
+        else {
+            panic!("expected numbered list, got {:?}", nodes[2]);
+        };
+        assert_eq!(items.len(), 2);
+        assert!(matches!(
+            &items[0][1],
+            ContentNode::List { ordered: true, items } if items.len() == 1
+        ));
+
+        assert!(matches!(
+            &nodes[4],
+            ContentNode::List { ordered: false, items } if items.len() == 1
+        ));
+        assert!(matches!(
+            &nodes[5],
+            ContentNode::Paragraph { children } if inline_text(children) == "Numbering off"
+        ));
+    }
+
+    #[test]
+    fn test_footnotes_and_endnotes_become_notes() {
+        let body = concat!(
+            r#"<w:p><w:r><w:t>Claim.</w:t></w:r><w:r><w:footnoteReference w:id="1"/></w:r>"#,
+            r#"<w:r><w:t xml:space="preserve"> Aside.</w:t></w:r><w:r><w:endnoteReference w:id="1"/></w:r></w:p>"#,
+            r#"<w:p><w:r><w:t>Again.</w:t></w:r><w:r><w:footnoteReference w:id="1"/></w:r></w:p>"#,
+        );
+        let footnotes = r#"<w:footnotes xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">
+<w:footnote w:type="separator" w:id="-1"><w:p><w:r><w:separator/></w:r></w:p></w:footnote>
+<w:footnote w:id="1"><w:p><w:r><w:footnoteRef/></w:r><w:r><w:t xml:space="preserve"> Source.</w:t></w:r></w:p></w:footnote>
+</w:footnotes>"#;
+        let endnotes = r#"<w:endnotes xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">
+<w:endnote w:id="1"><w:p><w:r><w:endnoteRef/></w:r><w:r><w:t xml:space="preserve"> Later.</w:t></w:r></w:p></w:endnote>
+</w:endnotes>"#;
+        let doc = read_bytes(package(&[
+            ("word/document.xml", document(body).as_bytes()),
+            ("word/footnotes.xml", footnotes.as_bytes()),
+            ("word/endnotes.xml", endnotes.as_bytes()),
+        ]));
+        let nodes = &doc.content[0].content;
+
+        let refs: Vec<(String, String)> = nodes
+            .iter()
+            .filter_map(|node| match node {
+                ContentNode::Paragraph { children } => Some(children),
+                _ => None,
+            })
+            .flatten()
+            .filter_map(|inline| match inline {
+                InlineNode::NoteRef { id, label } => Some((id.clone(), label.clone())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            refs,
+            [
+                ("fn-1".to_string(), "1".to_string()),
+                ("en-2".to_string(), "2".to_string()),
+                ("fn-1".to_string(), "1".to_string()),
+            ]
+        );
+
+        // One note per distinct reference, and every reference resolves.
+        let notes: Vec<(&str, NoteKind, String)> = nodes
+            .iter()
+            .filter_map(|node| match node {
+                ContentNode::Note { id, kind, children } => Some((
+                    id.as_str(),
+                    *kind,
+                    children
+                        .iter()
+                        .map(|c| match c {
+                            ContentNode::Paragraph { children } => inline_text(children),
+                            _ => String::new(),
+                        })
+                        .collect(),
+                )),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            notes,
+            [
+                ("fn-1", NoteKind::Footnote, "Source.".to_string()),
+                ("en-2", NoteKind::Endnote, "Later.".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_images_become_shared_resources() {
+        let drawing = |kind: &str, doc_pr: &str| {
+            format!(
+                r#"<w:p><w:r><w:drawing><wp:{kind}><wp:docPr id="1" name="Picture" {doc_pr}/><a:graphic><a:graphicData><a:blip r:embed="rId7"/></a:graphicData></a:graphic></wp:{kind}></w:drawing></w:r></w:p>"#
+            )
+        };
+        let body = [
+            drawing("inline", r#"descr="Harbour at dawn""#),
+            drawing("anchor", r#"title="Harbour again""#),
+            drawing("inline", r#"descr="  ""#),
+        ]
+        .concat();
+        let rels = r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+<Relationship Id="rId7" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/image" Target="media/photo.jpeg"/>
+</Relationships>"#;
+        let doc = read_bytes(package(&[
+            ("word/document.xml", document(&body).as_bytes()),
+            ("word/_rels/document.xml.rels", rels.as_bytes()),
+            ("word/media/photo.jpeg", b"\xff\xd8\xff\xe0"),
+        ]));
+
+        let images: Vec<(&str, Option<&str>)> = doc.content[0]
+            .content
+            .iter()
+            .map(|node| match node {
+                ContentNode::Image {
+                    resource_id,
+                    alt_text,
+                    ..
+                } => (resource_id.as_str(), alt_text.as_deref()),
+                other => panic!("expected image, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            images,
+            [
+                ("photo.jpeg", Some("Harbour at dawn")),
+                ("photo.jpeg", Some("Harbour again")),
+                ("photo.jpeg", None),
+            ]
+        );
+        assert_eq!(doc.resources.len(), 1);
+        let photo = doc.resources.get("photo.jpeg").unwrap();
+        assert_eq!(photo.media_type, "image/jpeg");
+        assert_eq!(photo.data, b"\xff\xd8\xff\xe0");
+    }
+}
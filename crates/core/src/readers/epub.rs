@@ -1,7 +1,7 @@
 //! EPUB reader: parse ZIP → OPF → content documents → IR.
 //! Supports both EPUB2 (NCX navigation) and EPUB3 (NAV document).
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io::{Read, Seek};
 
 use quick_xml::events::Event;
@@ -10,8 +10,10 @@ use quick_xml::Reader as XmlReader;
 use crate::detect::{DetectResult, Format};
 use crate::document::*;
 use crate::error::ReadError;
+use crate::fonts;
 use crate::progress::{emit_progress, ProgressHandler};
-use crate::readers::{FormatReader, ReadOptions};
+use crate::readers::resources::percent_decode;
+use crate::readers::{html_to_xhtml, FormatReader, ReadOptions, XhtmlElement};
 use crate::security;
 
 pub struct EpubReader;
@@ -41,11 +43,13 @@ fn read_epub_impl<R: Read + Seek>(
         // Security checks
         security::check_file_count(archive.len() as u64, &opts.security)?;
 
-        // Check for DRM
+        // Check for DRM; font obfuscation is allowed and undone below
+        let mut obfuscated = HashMap::new();
         if let Ok(mut enc_file) = archive.by_name("META-INF/encryption.xml") {
             let mut enc_xml = String::new();
             enc_file.read_to_string(&mut enc_xml).ok();
             security::check_epub_drm(&enc_xml)?;
+            obfuscated = parse_encryption(&enc_xml);
         }
 
         emit_progress(progress, "Reading EPUB", 0, Some(5), Some("Parsing container"));
@@ -69,18 +73,26 @@ fn read_epub_impl<R: Read + Seek>(
 
         // 3. Read content documents (spine items) → chapters
         let mut chapters = Vec::new();
+        let mut diagnostics = Vec::new();
+        // Manifest href of each chapter, for resolving its links.
+        let mut chapter_hrefs = Vec::new();
         let total_spine = opf.spine_items.len();
         for (i, spine_item) in opf.spine_items.iter().enumerate() {
             if let Some(manifest_item) = opf.manifest.get(spine_item) {
                 let full_path = format!("{}{}", opf_dir, manifest_item.href);
                 match read_archive_entry(&mut archive, &full_path, &opts.security) {
                     Ok(content) => {
-                        let chapter = parse_xhtml_to_chapter(
+                        let (chapter, error) = parse_xhtml_to_chapter(
                             &content,
                             spine_item,
                             &opts.security,
                         );
+                        if let Some(error) = error {
+                            let diagnostic = malformed_chapter(chapters.len(), &full_path, &error);
+                            diagnostics.push(diagnostic);
+                        }
                         chapters.push(chapter);
+                        chapter_hrefs.push(manifest_item.href.as_str());
                     }
                     Err(e) => {
                         tracing::warn!("Skipping spine item '{}': {}", spine_item, e);
@@ -96,6 +108,9 @@ fn read_epub_impl<R: Read + Seek>(
             }
         }
 
+        resolve_links(&mut chapters, &chapter_hrefs, &opf);
+        link_note_refs(&mut chapters);
+
         emit_progress(progress, "Reading EPUB", 3, Some(5), Some("Loading resources"));
 
         // 4. Load resources (images, fonts, stylesheets)
@@ -103,13 +118,21 @@ fn read_epub_impl<R: Read + Seek>(
         for (id, item) in &opf.manifest {
             if is_resource_media_type(&item.media_type) {
                 let full_path = format!("{}{}", opf_dir, item.href);
-                if let Ok(data) = read_archive_entry_bytes(&mut archive, &full_path, &opts.security)
+                if let Ok(mut data) =
+                    read_archive_entry_bytes(&mut archive, &full_path, &opts.security)
                 {
+                    let obfuscation = obfuscated
+                        .get(&percent_decode(&full_path))
+                        .and_then(|&algorithm| opf.obfuscation(algorithm));
+                    if let Some(obfuscation) = &obfuscation {
+                        obfuscation.apply(&mut data);
+                    }
                     let resource = Resource {
                         id: id.clone(),
                         media_type: item.media_type.clone(),
                         data,
                         filename: Some(item.href.clone()),
+                        obfuscation,
                     };
                     resources.insert(id.clone(), resource);
                 }
@@ -134,6 +157,7 @@ fn read_epub_impl<R: Read + Seek>(
             resources,
             text_direction: opf.text_direction,
             epub_version: opf.epub_version,
+            diagnostics,
         })
 }
 
@@ -174,6 +198,54 @@ struct OpfData {
     text_direction: TextDirection,
     toc_id: Option<String>, // NCX id for EPUB2
     nav_href: Option<String>, // NAV doc href for EPUB3
+    /// Text of the package's unique identifier, which keys font obfuscation.
+    unique_identifier: Option<String>,
+}
+
+impl OpfData {
+    /// The key a font obfuscated with `algorithm` was obfuscated with.
+    fn obfuscation(&self, algorithm: ObfuscationAlgorithm) -> Option<FontObfuscation> {
+        match algorithm {
+            ObfuscationAlgorithm::Idpf => {
+                Some(fonts::idpf_obfuscation(self.unique_identifier.as_deref()?))
+            }
+            // Adobe keys on the book's UUID, whichever identifier holds it.
+            ObfuscationAlgorithm::Adobe => self
+                .unique_identifier
+                .iter()
+                .chain(self.metadata.identifiers.iter().map(|i| &i.value))
+                .find_map(|id| fonts::adobe_obfuscation(id)),
+        }
+    }
+}
+
+/// A `dc:*` element of the OPF metadata.
+struct DcEntry {
+    /// Local name: `title`, `creator`, `identifier`, …
+    name: String,
+    id: Option<String>,
+    text: String,
+    /// EPUB2 `opf:role`, `opf:file-as` and `opf:scheme`, by local name.
+    opf: HashMap<String, String>,
+}
+
+/// An OPF `<meta>`: an EPUB3 `property` (refining another element when
+/// `refines` is set) or an EPUB2 `name`/`content` pair.
+#[derive(Default)]
+struct OpfMeta {
+    property: Option<String>,
+    refines: Option<String>,
+    id: Option<String>,
+    name: Option<String>,
+    content: Option<String>,
+    text: String,
+}
+
+/// Which metadata element is collecting text.
+#[derive(Clone, Copy)]
+enum OpfText {
+    Dc(usize),
+    Meta(usize),
 }
 
 #[derive(Debug, Clone)]
@@ -222,7 +294,6 @@ fn parse_opf(content: &str, _opf_dir: &str) -> Result<OpfData, ReadError> {
     let mut reader = XmlReader::from_str(content);
     let mut buf = Vec::new();
 
-    let mut metadata = Metadata::default();
     let mut manifest = HashMap::new();
     let mut spine_items = Vec::new();
     let mut epub_version = None;
@@ -232,8 +303,11 @@ fn parse_opf(content: &str, _opf_dir: &str) -> Result<OpfData, ReadError> {
 
     // Track parsing state
     let mut in_metadata = false;
-    let mut current_element: Option<String> = None;
-    let mut current_text = String::new();
+    let mut unique_id = None;
+    let mut dc_entries: Vec<DcEntry> = Vec::new();
+    let mut metas: Vec<OpfMeta> = Vec::new();
+    // The metadata element whose text is being read.
+    let mut current: Option<OpfText> = None;
 
     // Parse package attributes for version and direction
     loop {
@@ -263,6 +337,7 @@ fn parse_opf(content: &str, _opf_dir: &str) -> Result<OpfData, ReadError> {
                                         _ => TextDirection::Auto,
                                     };
                                 }
+                                "unique-identifier" => unique_id = Some(val),
                                 _ => {}
                             }
                         }
@@ -270,42 +345,49 @@ fn parse_opf(content: &str, _opf_dir: &str) -> Result<OpfData, ReadError> {
                     "metadata" => {
                         in_metadata = true;
                     }
-                    "title" | "creator" | "language" | "publisher" | "date" | "description"
-                    | "subject" | "identifier" | "rights"
+                    "title" | "creator" | "contributor" | "language" | "publisher" | "date"
+                    | "description" | "subject" | "identifier" | "rights"
                         if in_metadata =>
                     {
-                        current_element = Some(name.clone());
-                        current_text.clear();
-                    }
-                    "meta" if in_metadata => {
-                        // EPUB3 meta elements with property attribute
-                        let mut property = None;
-                        let mut meta_content = None;
+                        let mut entry = DcEntry {
+                            name: name.clone(),
+                            id: None,
+                            text: String::new(),
+                            opf: HashMap::new(),
+                        };
                         for attr in e.attributes().flatten() {
                             let key = String::from_utf8_lossy(attr.key.local_name().as_ref())
                                 .to_string();
                             let val = String::from_utf8_lossy(&attr.value).to_string();
                             match key.as_str() {
-                                "property" => property = Some(val),
-                                "content" => meta_content = Some(val),
-                                "name" if val == "cover" => {
-                                    // EPUB2 cover meta
-                                    property = Some("cover".to_string());
+                                "id" => entry.id = Some(val),
+                                // EPUB2 `opf:role`, `opf:file-as`, `opf:scheme`
+                                "role" | "file-as" | "scheme" => {
+                                    entry.opf.insert(key, val);
                                 }
                                 _ => {}
                             }
                         }
-                        // EPUB2 style: <meta name="cover" content="cover-image-id"/>
-                        if property.as_deref() == Some("cover") {
-                            if let Some(cover_id) = meta_content {
-                                metadata.cover_image_id = Some(cover_id);
+                        dc_entries.push(entry);
+                        current = Some(OpfText::Dc(dc_entries.len() - 1));
+                    }
+                    "meta" if in_metadata => {
+                        let mut meta = OpfMeta::default();
+                        for attr in e.attributes().flatten() {
+                            let key = String::from_utf8_lossy(attr.key.local_name().as_ref())
+                                .to_string();
+                            let val = String::from_utf8_lossy(&attr.value).to_string();
+                            match key.as_str() {
+                                "property" => meta.property = Some(val),
+                                "refines" => meta.refines = Some(val),
+                                "id" => meta.id = Some(val),
+                                "name" => meta.name = Some(val),
+                                "content" => meta.content = Some(val),
+                                _ => {}
                             }
                         }
-                        if matches!(e, quick_xml::events::BytesStart { .. }) {
-                            // Element has content between tags
-                            current_element = property.map(|p| format!("meta:{p}"));
-                            current_text.clear();
-                        }
+                        metas.push(meta);
+                        current = Some(OpfText::Meta(metas.len() - 1));
                     }
                     "item" => {
                         let mut id = String::new();
@@ -364,9 +446,11 @@ fn parse_opf(content: &str, _opf_dir: &str) -> Result<OpfData, ReadError> {
                 }
             }
             Ok(Event::Text(ref e)) => {
-                if current_element.is_some() {
-                    current_text
-                        .push_str(&e.unescape().unwrap_or_default());
+                let text = e.unescape().unwrap_or_default();
+                match current {
+                    Some(OpfText::Dc(i)) => dc_entries[i].text.push_str(&text),
+                    Some(OpfText::Meta(i)) => metas[i].text.push_str(&text),
+                    None => {}
                 }
             }
             Ok(Event::End(ref e)) => {
@@ -375,62 +459,7 @@ fn parse_opf(content: &str, _opf_dir: &str) -> Result<OpfData, ReadError> {
                 if name == "metadata" {
                     in_metadata = false;
                 }
-
-                if let Some(ref elem) = current_element {
-                    let text = current_text.trim().to_string();
-                    if !text.is_empty() {
-                        match elem.as_str() {
-                            "title" => metadata.title = Some(text),
-                            "creator" => metadata.authors.push(text),
-                            "language" => metadata.language = Some(text),
-                            "publisher" => metadata.publisher = Some(text),
-                            "date" => metadata.publish_date = Some(text),
-                            "description" => metadata.description = Some(text),
-                            "subject" => metadata.subjects.push(text),
-                            "rights" => metadata.rights = Some(text),
-                            "identifier" => {
-                                // Try to detect ISBN
-                                let cleaned: String = text.chars()
-                                    .filter(|c| c.is_ascii_digit() || *c == '-' || *c == 'X' || *c == 'x')
-                                    .collect();
-                                let digits: String = cleaned.chars()
-                                    .filter(|c| c.is_ascii_digit() || *c == 'X' || *c == 'x')
-                                    .collect();
-                                if digits.len() == 13 {
-                                    metadata.isbn_13 = Some(digits);
-                                } else if digits.len() == 10 {
-                                    metadata.isbn_10 = Some(digits);
-                                }
-                            }
-                            s if s.starts_with("meta:") => {
-                                let prop = &s[5..];
-                                match prop {
-                                    "belongs-to-collection" => {
-                                        if metadata.series.is_none() {
-                                            metadata.series = Some(SeriesInfo {
-                                                name: text,
-                                                position: None,
-                                            });
-                                        }
-                                    }
-                                    "group-position" => {
-                                        if let Ok(pos) = text.parse::<f32>() {
-                                            if let Some(ref mut series) = metadata.series {
-                                                series.position = Some(pos);
-                                            }
-                                        }
-                                    }
-                                    _ => {
-                                        metadata.custom.insert(prop.to_string(), text);
-                                    }
-                                }
-                            }
-                            _ => {}
-                        }
-                    }
-                    current_element = None;
-                    current_text.clear();
-                }
+                current = None;
             }
             Ok(Event::Eof) => break,
             Err(e) => {
@@ -444,6 +473,13 @@ fn parse_opf(content: &str, _opf_dir: &str) -> Result<OpfData, ReadError> {
         buf.clear();
     }
 
+    let metadata = build_metadata(&dc_entries, &metas, unique_id.as_deref());
+    let unique_identifier = dc_entries
+        .iter()
+        .filter(|e| e.name == "identifier")
+        .find(|e| e.id.is_some() && e.id == unique_id)
+        .map(|e| e.text.trim().to_string());
+
     Ok(OpfData {
         metadata,
         manifest,
@@ -452,16 +488,283 @@ fn parse_opf(content: &str, _opf_dir: &str) -> Result<OpfData, ReadError> {
         text_direction,
         toc_id,
         nav_href,
+        unique_identifier,
     })
 }
 
+/// Archive paths of the obfuscated fonts listed in `encryption.xml`, with
+/// their algorithm. Other encryption has already been refused as DRM.
+fn parse_encryption(xml: &str) -> HashMap<String, ObfuscationAlgorithm> {
+    let mut reader = XmlReader::from_str(xml);
+    let mut buf = Vec::new();
+    let mut fonts = HashMap::new();
+    let mut algorithm = None;
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(ref e)) | Ok(Event::Empty(ref e)) => {
+                let attr = |name: &[u8]| {
+                    e.attributes()
+                        .flatten()
+                        .find(|a| a.key.local_name().as_ref() == name)
+                        .map(|a| String::from_utf8_lossy(&a.value).to_string())
+                };
+                match e.local_name().as_ref() {
+                    b"EncryptedData" => algorithm = None,
+                    b"EncryptionMethod" => {
+                        algorithm = attr(b"Algorithm")
+                            .and_then(|uri| ObfuscationAlgorithm::from_uri(&uri));
+                    }
+                    b"CipherReference" => {
+                        if let (Some(algorithm), Some(uri)) = (algorithm, attr(b"URI")) {
+                            fonts.insert(percent_decode(&uri), algorithm);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Ok(Event::Eof) | Err(_) => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    fonts
+}
+
+/// Assemble `Metadata` from the OPF's `dc:*` elements and metas, applying
+/// EPUB3 refinements (roles, file-as, identifier types, collections) and
+/// Calibre's `calibre:series` metas.
+fn build_metadata(dc: &[DcEntry], metas: &[OpfMeta], unique_id: Option<&str>) -> Metadata {
+    let mut refinements: HashMap<&str, Vec<(&str, &str)>> = HashMap::new();
+    for meta in metas {
+        if let (Some(refines), Some(property)) = (&meta.refines, &meta.property) {
+            refinements
+                .entry(refines.trim_start_matches('#'))
+                .or_default()
+                .push((property.as_str(), meta.text.trim()));
+        }
+    }
+    let refined = |id: Option<&str>, property: &str| -> Option<String> {
+        refinements
+            .get(id?)?
+            .iter()
+            .find(|(p, v)| *p == property && !v.is_empty())
+            .map(|(_, v)| v.to_string())
+    };
+
+    let mut m = Metadata::default();
+    for entry in dc {
+        let text = entry.text.trim().to_string();
+        if text.is_empty() {
+            continue;
+        }
+        let id = entry.id.as_deref();
+        match entry.name.as_str() {
+            "title" => match refined(id, "title-type").as_deref() {
+                Some("subtitle") => {
+                    m.subtitle.get_or_insert(text);
+                }
+                _ => {
+                    m.title.get_or_insert(text);
+                }
+            },
+            "creator" | "contributor" => {
+                let role = entry
+                    .opf
+                    .get("role")
+                    .cloned()
+                    .or_else(|| refined(id, "role"))
+                    // A creator without a role is an author.
+                    .or_else(|| (entry.name == "creator").then(|| "aut".to_string()));
+                let file_as = entry
+                    .opf
+                    .get("file-as")
+                    .cloned()
+                    .or_else(|| refined(id, "file-as"));
+                if role.as_deref() == Some("aut") {
+                    m.authors.push(text.clone());
+                }
+                m.contributors.push(Contributor {
+                    name: text,
+                    file_as,
+                    role,
+                });
+            }
+            "identifier" => {
+                let scheme = entry.opf.get("scheme").cloned().or_else(|| {
+                    refined(id, "identifier-type").map(|t| match t.as_str() {
+                        // ONIX code list 5
+                        "02" | "15" => "ISBN".to_string(),
+                        "06" => "DOI".to_string(),
+                        _ => t,
+                    })
+                });
+                let identifier = parse_identifier(&text, scheme);
+                if matches!(identifier.scheme.as_deref(), None | Some("ISBN")) {
+                    let digits: String = identifier
+                        .value
+                        .chars()
+                        .filter(|c| c.is_ascii_digit() || *c == 'X' || *c == 'x')
+                        .collect();
+                    if digits.len() == 13 {
+                        m.isbn_13.get_or_insert(digits);
+                    } else if digits.len() == 10 {
+                        m.isbn_10.get_or_insert(digits);
+                    }
+                }
+                if id.is_some() && id == unique_id {
+                    m.identifiers.insert(0, identifier);
+                } else {
+                    m.identifiers.push(identifier);
+                }
+            }
+            "language" => {
+                m.language.get_or_insert(text);
+            }
+            "publisher" => {
+                m.publisher.get_or_insert(text);
+            }
+            "date" => {
+                m.publish_date.get_or_insert(text);
+            }
+            "description" => {
+                m.description.get_or_insert(text);
+            }
+            "rights" => {
+                m.rights.get_or_insert(text);
+            }
+            "subject" => m.subjects.push(text),
+            _ => {}
+        }
+    }
+
+    let mut calibre_series = None;
+    let mut calibre_index = None;
+    for meta in metas.iter().filter(|meta| meta.refines.is_none()) {
+        let text = meta.text.trim();
+        match (meta.property.as_deref(), meta.name.as_deref()) {
+            (Some("dcterms:modified"), _) if !text.is_empty() => {
+                m.modified.get_or_insert(text.to_string());
+            }
+            (Some("belongs-to-collection"), _) if !text.is_empty() => {
+                let id = meta.id.as_deref();
+                // "set" collections group books without ordering them.
+                if m.series.is_none() && refined(id, "collection-type").as_deref() != Some("set") {
+                    m.series = Some(SeriesInfo {
+                        name: text.to_string(),
+                        position: refined(id, "group-position").and_then(|p| p.parse().ok()),
+                    });
+                }
+            }
+            (Some(property), _) if !text.is_empty() => {
+                m.custom.insert(property.to_string(), text.to_string());
+            }
+            (None, Some("cover")) => m.cover_image_id = meta.content.clone(),
+            (None, Some("calibre:series")) => calibre_series = meta.content.clone(),
+            (None, Some("calibre:series_index")) => {
+                calibre_index = meta.content.as_deref().and_then(|c| c.parse().ok())
+            }
+            _ => {}
+        }
+    }
+    if m.series.is_none() {
+        m.series = calibre_series
+            .filter(|name| !name.trim().is_empty())
+            .map(|name| SeriesInfo {
+                name,
+                position: calibre_index,
+            });
+    }
+    m
+}
+
+/// Split `urn:isbn:978…` and Calibre-style `isbn:978…` identifiers into
+/// scheme and value. Schemes are upper-cased.
+fn parse_identifier(text: &str, scheme: Option<String>) -> Identifier {
+    let is_scheme = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric());
+    let rest = text
+        .get(..4)
+        .filter(|p| p.eq_ignore_ascii_case("urn:"))
+        .map(|_| &text[4..]);
+    let split = match rest {
+        Some(rest) => rest.split_once(':'),
+        // `http://…` is a URL, not a scheme.
+        None if scheme.is_none() => text.split_once(':').filter(|(_, v)| !v.starts_with("//")),
+        None => None,
+    };
+    match split.filter(|(s, v)| is_scheme(s) && !v.is_empty()) {
+        Some((s, value)) => Identifier {
+            value: value.to_string(),
+            scheme: Some(scheme.unwrap_or_else(|| s.to_string()).to_uppercase()),
+        },
+        None => Identifier {
+            value: text.to_string(),
+            scheme: scheme.map(|s| s.to_uppercase()),
+        },
+    }
+}
+
 // --- XHTML Content Parsing ---
 
-fn parse_xhtml_to_chapter(
+/// Parse one XHTML content document into a chapter. Also used by the MOBI
+/// reader for KF8 parts, which are XHTML once reassembled.
+///
+/// Documents quick-xml rejects (unclosed or mismatched tags, HTML entities
+/// such as `&nbsp;`) are parsed again with html5ever's tolerant tree builder;
+/// the XML error is returned with the chapter so callers can record the
+/// recovery.
+pub(crate) fn parse_xhtml_to_chapter(
     content: &str,
     id: &str,
     limits: &crate::security::SecurityLimits,
-) -> Chapter {
+) -> (Chapter, Option<String>) {
+    match parse_xhtml_events(content, id, limits) {
+        (chapter, None) => (chapter, None),
+        (_, Some(error)) => {
+            tracing::warn!(
+                "Content document '{}' is malformed ({}), recovering",
+                id,
+                error
+            );
+            let (chapter, _) = parse_xhtml_events(&recover_xhtml(content, limits), id, limits);
+            (chapter, Some(error))
+        }
+    }
+}
+
+/// Rewrite malformed XHTML as the tolerant HTML parser understands it,
+/// keeping every element and attribute that can be written as XML.
+fn recover_xhtml(content: &str, limits: &crate::security::SecurityLimits) -> String {
+    html_to_xhtml(
+        content,
+        limits.max_nesting_depth,
+        |_| XhtmlElement::Keep,
+        |_, attr, value| Some((attr.to_string(), value.to_string())),
+    )
+}
+
+/// The diagnostic recorded when chapter `index`, read from `source`, was
+/// not well-formed and had to be recovered.
+pub(crate) fn malformed_chapter(index: usize, source: &str, error: &str) -> Diagnostic {
+    Diagnostic {
+        code: "malformed-xhtml".to_string(),
+        message: format!(
+            "Chapter {} ({}) is not well-formed XHTML and was recovered: {}",
+            index + 1,
+            source,
+            error
+        ),
+        location: Some(format!("chapter[{}]", index)),
+    }
+}
+
+/// The strict event loop behind `parse_xhtml_to_chapter`. On malformed
+/// input, returns what was read up to the error along with the error.
+fn parse_xhtml_events(
+    content: &str,
+    id: &str,
+    limits: &crate::security::SecurityLimits,
+) -> (Chapter, Option<String>) {
+    let mut error = None;
     let mut nodes = Vec::new();
     let mut reader = XmlReader::from_str(content);
     reader.config_mut().trim_text(true);
@@ -472,7 +775,12 @@ fn parse_xhtml_to_chapter(
     let mut inline_stack: Vec<Vec<InlineNode>> = Vec::new();
     let mut chapter_title: Option<String> = None;
     let mut current_heading_level: Option<u8> = None;
-    let mut link_href_stack: Vec<String> = Vec::new();
+    let mut link_href_stack: Vec<(String, LinkRole)> = Vec::new();
+    let mut frames: Vec<BlockFrame> = Vec::new();
+    // `(depth, kind)` of enclosing `<section epub:type="endnotes">`-style lists.
+    let mut note_containers: Vec<(u32, NoteKind)> = Vec::new();
+    // `(depth, attributes)` of open elements whose id/class/role/lang is kept.
+    let mut styles: Vec<(u32, Attributes)> = Vec::new();
 
     // Simple state machine for parsing XHTML into content nodes
     loop {
@@ -490,23 +798,63 @@ fn parse_xhtml_to_chapter(
 
                 let name = String::from_utf8_lossy(e.local_name().as_ref()).to_string();
 
+                let attrs = if in_body {
+                    source_attributes(e)
+                } else {
+                    Attributes::default()
+                };
+                let mut opened_note = false;
+                if in_body && name != "a" {
+                    let in_container = note_containers.last().map(|(_, kind)| *kind);
+                    let note = match note_marks(e) {
+                        (Some(NoteMark::Container(kind)), _) => {
+                            note_containers.push((depth, kind));
+                            None
+                        }
+                        (Some(NoteMark::Note(kind)), Some(id)) => Some((id, kind)),
+                        // Items of a notes list are notes even without their own markup.
+                        (None, Some(id)) if name == "li" || name == "aside" => {
+                            in_container.map(|kind| (id, kind))
+                        }
+                        _ => None,
+                    };
+                    if let Some((id, kind)) = note {
+                        let kind = FrameKind::Note { id, kind };
+                        frames.push(BlockFrame::open(kind, depth, &mut nodes, &mut inline_stack));
+                        opened_note = true;
+                    }
+                }
+                // Note bodies are marked up by their kind, not their classes.
+                let keep_style = in_body && !opened_note && !attrs.is_empty();
+
                 match name.as_str() {
                     "body" => {
                         in_body = true;
                     }
                     "p" if in_body => {
                         inline_stack.push(Vec::new());
+                        if keep_style {
+                            styles.push((depth, attrs));
+                        }
                     }
                     "h1" | "h2" | "h3" | "h4" | "h5" | "h6" if in_body => {
                         let level = name.as_bytes()[1] - b'0';
                         current_heading_level = Some(level);
                         inline_stack.push(Vec::new());
+                        if keep_style {
+                            styles.push((depth, attrs));
+                        }
                     }
-                    "em" | "i" if in_body && !inline_stack.is_empty() => {
-                        inline_stack.push(Vec::new());
-                    }
-                    "strong" | "b" if in_body && !inline_stack.is_empty() => {
-                        inline_stack.push(Vec::new());
+                    "em" | "i" | "strong" | "b" | "code" | "span"
+                        if in_body && !inline_stack.is_empty() =>
+                    {
+                        // Unstyled spans add nothing; their text joins the parent.
+                        if name != "span" || keep_style {
+                            inline_stack.push(Vec::new());
+                        }
+                        if keep_style {
+                            styles.push((depth, attrs));
+                        }
                     }
                     "a" if in_body && !inline_stack.is_empty() => {
                         let mut href = String::new();
@@ -518,20 +866,31 @@ fn parse_xhtml_to_chapter(
                                 break;
                             }
                         }
-                        link_href_stack.push(href);
-                        inline_stack.push(Vec::new());
-                    }
-                    "code" if in_body && !inline_stack.is_empty() => {
+                        let role = link_role(e);
+                        if keep_style && role == LinkRole::Link {
+                            styles.push((depth, attrs));
+                        }
+                        link_href_stack.push((href, role));
                         inline_stack.push(Vec::new());
                     }
                     "blockquote" if in_body => {
-                        // We'll collect block-level content inside
+                        let kind = FrameKind::BlockQuote(attrs);
+                        frames.push(BlockFrame::open(kind, depth, &mut nodes, &mut inline_stack));
+                    }
+                    "div" | "section" | "article" | "aside" | "figure" | "header" | "footer"
+                        if keep_style =>
+                    {
+                        let kind = FrameKind::Container(attrs);
+                        frames.push(BlockFrame::open(kind, depth, &mut nodes, &mut inline_stack));
                     }
                     "ul" | "ol" if in_body => {
                         // List handling
                     }
                     "pre" if in_body => {
                         inline_stack.push(Vec::new());
+                        if keep_style {
+                            styles.push((depth, attrs));
+                        }
                     }
                     "img" if in_body => {
                         let mut src = String::new();
@@ -547,11 +906,12 @@ fn parse_xhtml_to_chapter(
                             }
                         }
                         if !src.is_empty() {
-                            nodes.push(ContentNode::Image {
+                            let image = ContentNode::Image {
                                 resource_id: src,
                                 alt_text: alt,
                                 caption: None,
-                            });
+                            };
+                            nodes.push(styled(image, attrs));
                         }
                     }
                     _ => {}
@@ -563,8 +923,17 @@ fn parse_xhtml_to_chapter(
                     if let Some(inlines) = inline_stack.last_mut() {
                         inlines.push(InlineNode::LineBreak);
                     }
+                } else if (name == "a" || name == "span") && in_body {
+                    // Empty anchors (`<a id="page12"/>`) are link targets.
+                    let attrs = source_attributes(e);
+                    if let (Some(inlines), Some(_)) = (inline_stack.last_mut(), &attrs.id) {
+                        inlines.push(InlineNode::Span {
+                            attrs,
+                            children: Vec::new(),
+                        });
+                    }
                 } else if name == "hr" && in_body {
-                    nodes.push(ContentNode::HorizontalRule);
+                    nodes.push(styled(ContentNode::HorizontalRule, source_attributes(e)));
                 } else if name == "img" && in_body {
                     let mut src = String::new();
                     let mut alt = None;
@@ -579,17 +948,24 @@ fn parse_xhtml_to_chapter(
                         }
                     }
                     if !src.is_empty() {
-                        nodes.push(ContentNode::Image {
+                        let image = ContentNode::Image {
                             resource_id: src,
                             alt_text: alt,
                             caption: None,
-                        });
+                        };
+                        nodes.push(styled(image, source_attributes(e)));
                     }
                 }
             }
             Ok(Event::Text(ref e)) => {
                 if in_body {
-                    let text = e.unescape().unwrap_or_default().to_string();
+                    let text = match e.unescape() {
+                        Ok(text) => text.to_string(),
+                        Err(e) => {
+                            error = Some(e.to_string());
+                            break;
+                        }
+                    };
                     if !text.is_empty() {
                         if let Some(inlines) = inline_stack.last_mut() {
                             inlines.push(InlineNode::Text(text));
@@ -602,6 +978,11 @@ fn parse_xhtml_to_chapter(
                     depth -= 1;
                 }
                 let name = String::from_utf8_lossy(e.local_name().as_ref()).to_string();
+                let attrs = match styles.last() {
+                    Some((d, _)) if *d > depth => styles.pop().map(|(_, a)| a),
+                    _ => None,
+                }
+                .unwrap_or_default();
 
                 match name.as_str() {
                     "body" => {
@@ -610,7 +991,7 @@ fn parse_xhtml_to_chapter(
                     "p" if in_body => {
                         if let Some(children) = inline_stack.pop() {
                             if !children.is_empty() {
-                                nodes.push(ContentNode::Paragraph { children });
+                                nodes.push(styled(ContentNode::Paragraph { children }, attrs));
                             }
                         }
                     }
@@ -621,32 +1002,49 @@ fn parse_xhtml_to_chapter(
                             if chapter_title.is_none() {
                                 chapter_title = extract_text_from_inlines(&children);
                             }
-                            nodes.push(ContentNode::Heading { level, children });
+                            nodes.push(styled(ContentNode::Heading { level, children }, attrs));
                             current_heading_level = None;
                         }
                     }
                     "em" | "i" if in_body => {
                         if let Some(children) = inline_stack.pop() {
                             if let Some(parent) = inline_stack.last_mut() {
-                                parent.push(InlineNode::Emphasis(children));
+                                parent.push(span(InlineNode::Emphasis(children), attrs));
                             }
                         }
                     }
                     "strong" | "b" if in_body => {
                         if let Some(children) = inline_stack.pop() {
                             if let Some(parent) = inline_stack.last_mut() {
-                                parent.push(InlineNode::Strong(children));
+                                parent.push(span(InlineNode::Strong(children), attrs));
                             }
                         }
                     }
                     "a" if in_body => {
                         if let Some(children) = inline_stack.pop() {
                             if let Some(parent) = inline_stack.last_mut() {
-                                let href = link_href_stack.pop().unwrap_or_default();
-                                parent.push(InlineNode::Link {
-                                    href,
-                                    children,
-                                });
+                                let (href, role) = link_href_stack.pop().unwrap_or_default();
+                                match role {
+                                    // `<a id="p12">` anchors without a target.
+                                    LinkRole::Link if href.is_empty() => {
+                                        if attrs.is_empty() {
+                                            parent.extend(children);
+                                        } else {
+                                            parent.push(InlineNode::Span { attrs, children });
+                                        }
+                                    }
+                                    LinkRole::Link => parent
+                                        .push(span(InlineNode::Link { href, children }, attrs)),
+                                    LinkRole::NoteRef => parent.push(InlineNode::NoteRef {
+                                        id: fragment(&href).to_string(),
+                                        label: extract_text_from_inlines(&children)
+                                            .unwrap_or_default()
+                                            .trim()
+                                            .to_string(),
+                                    }),
+                                    // "Back to text" arrows inside notes.
+                                    LinkRole::BackLink => {}
+                                }
                             }
                         }
                     }
@@ -655,7 +1053,14 @@ fn parse_xhtml_to_chapter(
                             if let Some(parent) = inline_stack.last_mut() {
                                 let text = extract_text_from_inlines(&children)
                                     .unwrap_or_default();
-                                parent.push(InlineNode::Code(text));
+                                parent.push(span(InlineNode::Code(text), attrs));
+                            }
+                        }
+                    }
+                    "span" if in_body && !attrs.is_empty() => {
+                        if let Some(children) = inline_stack.pop() {
+                            if let Some(parent) = inline_stack.last_mut() {
+                                parent.push(InlineNode::Span { attrs, children });
                             }
                         }
                     }
@@ -663,27 +1068,324 @@ fn parse_xhtml_to_chapter(
                         if let Some(children) = inline_stack.pop() {
                             let code = extract_text_from_inlines(&children)
                                 .unwrap_or_default();
-                            nodes.push(ContentNode::CodeBlock {
+                            let block = ContentNode::CodeBlock {
                                 language: None,
                                 code,
-                            });
+                            };
+                            nodes.push(styled(block, attrs));
                         }
                     }
                     _ => {}
                 }
+
+                note_containers.retain(|(d, _)| *d <= depth);
+                if frames.last().is_some_and(|f| f.depth > depth) {
+                    if let Some(frame) = frames.pop() {
+                        nodes = frame.close(nodes, &mut inline_stack);
+                    }
+                }
+            }
+            Ok(Event::Eof) => {
+                // Content of elements left open never reaches `nodes`.
+                if depth > 0 {
+                    error = Some(format!("{} elements are not closed", depth));
+                }
+                break;
+            }
+            Err(e) => {
+                error = Some(format!("{} at byte {}", e, reader.error_position()));
+                break;
             }
-            Ok(Event::Eof) => break,
-            Err(_) => break,
             _ => {}
         }
         buf.clear();
     }
 
-    Chapter {
+    let chapter = Chapter {
         id: id.to_string(),
         title: chapter_title,
         content: nodes,
         text_direction: None,
+    };
+    (chapter, error)
+}
+
+// --- Notes ---
+
+/// How an `<a>` element is used.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum LinkRole {
+    #[default]
+    Link,
+    NoteRef,
+    BackLink,
+}
+
+enum NoteMark {
+    /// The element is a note body.
+    Note(NoteKind),
+    /// The element holds a list of notes (`epub:type="endnotes"`).
+    Container(NoteKind),
+}
+
+/// Lower-cased tokens of `epub:type`, `role` and `class`, plus the `id`.
+fn semantic_tokens(e: &quick_xml::events::BytesStart) -> (Vec<String>, Option<String>) {
+    let mut tokens = Vec::new();
+    let mut id = None;
+    for attr in e.attributes().flatten() {
+        let value = String::from_utf8_lossy(&attr.value).to_string();
+        match attr.key.as_ref() {
+            b"epub:type" | b"role" | b"class" => {
+                tokens.extend(value.split_whitespace().map(|t| t.to_ascii_lowercase()))
+            }
+            b"id" => id = Some(value),
+            _ => {}
+        }
+    }
+    (tokens, id)
+}
+
+/// Recognise note bodies and note lists from EPUB3 `epub:type`, DPUB-ARIA
+/// roles and the class names common in converted books.
+fn note_marks(e: &quick_xml::events::BytesStart) -> (Option<NoteMark>, Option<String>) {
+    let (tokens, id) = semantic_tokens(e);
+    let mark = tokens.iter().find_map(|t| match t.as_str() {
+        "footnote" | "note" | "doc-footnote" => Some(NoteMark::Note(NoteKind::Footnote)),
+        "endnote" | "rearnote" | "doc-endnote" => Some(NoteMark::Note(NoteKind::Endnote)),
+        "footnotes" => Some(NoteMark::Container(NoteKind::Footnote)),
+        "endnotes" | "rearnotes" | "doc-endnotes" => Some(NoteMark::Container(NoteKind::Endnote)),
+        _ => None,
+    });
+    (mark, id)
+}
+
+fn link_role(e: &quick_xml::events::BytesStart) -> LinkRole {
+    let (tokens, _) = semantic_tokens(e);
+    let has = |names: &[&str]| tokens.iter().any(|t| names.contains(&t.as_str()));
+    const NOTEREF: &[&str] = &[
+        "noteref",
+        "doc-noteref",
+        "footnote-ref",
+        "footnote-reference",
+    ];
+    const BACKLINK: &[&str] = &[
+        "backlink",
+        "doc-backlink",
+        "footnote-back",
+        "footnote-backref",
+    ];
+    if has(NOTEREF) {
+        LinkRole::NoteRef
+    } else if has(BACKLINK) {
+        LinkRole::BackLink
+    } else {
+        LinkRole::Link
+    }
+}
+
+fn fragment(href: &str) -> &str {
+    href.rsplit_once('#').map_or(href, |(_, f)| f)
+}
+
+/// What a `BlockFrame` becomes when its element closes.
+enum FrameKind {
+    Note { id: String, kind: NoteKind },
+    BlockQuote(Attributes),
+    Container(Attributes),
+}
+
+/// A block element being parsed: its blocks collect in the parser's `nodes`
+/// while the surrounding blocks wait in `outer`.
+struct BlockFrame {
+    kind: FrameKind,
+    depth: u32,
+    outer: Vec<ContentNode>,
+    /// Length of the inline stack before the frame's own text buffer.
+    inline_base: usize,
+}
+
+impl BlockFrame {
+    fn open(
+        kind: FrameKind,
+        depth: u32,
+        nodes: &mut Vec<ContentNode>,
+        inline_stack: &mut Vec<Vec<InlineNode>>,
+    ) -> Self {
+        let frame = Self {
+            kind,
+            depth,
+            outer: std::mem::take(nodes),
+            inline_base: inline_stack.len(),
+        };
+        // Catches text that is not wrapped in a `<p>`.
+        inline_stack.push(Vec::new());
+        frame
+    }
+
+    /// Wrap the blocks parsed since `open`; returns the outer blocks.
+    fn close(
+        self,
+        mut children: Vec<ContentNode>,
+        inline_stack: &mut Vec<Vec<InlineNode>>,
+    ) -> Vec<ContentNode> {
+        inline_stack.truncate(self.inline_base + 1);
+        if let Some(loose) = inline_stack.pop().filter(|i| !i.is_empty()) {
+            children.insert(0, ContentNode::Paragraph { children: loose });
+        }
+        let mut outer = self.outer;
+        match self.kind {
+            FrameKind::Note { id, kind } => outer.push(ContentNode::Note { id, kind, children }),
+            FrameKind::BlockQuote(attrs) => {
+                outer.push(styled(ContentNode::BlockQuote { children }, attrs))
+            }
+            // Chapter structure wins over styling: a container holding
+            // headings (`<section class="chapter">`) is dropped so writers
+            // still see the headings. Its id moves to the first block.
+            FrameKind::Container(attrs)
+                if children
+                    .iter()
+                    .any(|n| matches!(n.unstyled(), ContentNode::Heading { .. })) =>
+            {
+                if let (Some(id), Some(first)) = (attrs.id, children.first_mut()) {
+                    let node = std::mem::replace(first, ContentNode::HorizontalRule);
+                    *first = match node {
+                        ContentNode::Styled { mut attrs, node } if attrs.id.is_none() => {
+                            attrs.id = Some(id);
+                            ContentNode::Styled { attrs, node }
+                        }
+                        node => styled(
+                            node,
+                            Attributes {
+                                id: Some(id),
+                                ..Default::default()
+                            },
+                        ),
+                    };
+                }
+                outer.extend(children)
+            }
+            FrameKind::Container(attrs) => {
+                if !children.is_empty() {
+                    outer.push(ContentNode::Container { attrs, children })
+                }
+            }
+        }
+        outer
+    }
+}
+
+// --- Styling ---
+
+/// `id`, `class`, `role` and `lang`/`xml:lang` of an element.
+fn source_attributes(e: &quick_xml::events::BytesStart) -> Attributes {
+    let mut attrs = Attributes::default();
+    for attr in e.attributes().flatten() {
+        let value = String::from_utf8_lossy(&attr.value).trim().to_string();
+        if value.is_empty() {
+            continue;
+        }
+        match attr.key.as_ref() {
+            b"id" => attrs.id = Some(value),
+            b"class" => attrs.class = Some(value),
+            b"role" => attrs.role = Some(value),
+            b"lang" | b"xml:lang" => attrs.lang = Some(value),
+            _ => {}
+        }
+    }
+    attrs
+}
+
+fn styled(node: ContentNode, attrs: Attributes) -> ContentNode {
+    if attrs.is_empty() {
+        node
+    } else {
+        ContentNode::Styled {
+            attrs,
+            node: Box::new(node),
+        }
+    }
+}
+
+fn span(node: InlineNode, attrs: Attributes) -> InlineNode {
+    if attrs.is_empty() {
+        node
+    } else {
+        InlineNode::Span {
+            attrs,
+            children: vec![node],
+        }
+    }
+}
+
+/// Turn plain links to note bodies (`<sup><a href="#fn3">3</a></sup>`) into
+/// note references, once every chapter's note ids are known.
+pub(crate) fn link_note_refs(chapters: &mut [Chapter]) {
+    fn note_ids(nodes: &[ContentNode], ids: &mut HashSet<String>) {
+        for node in nodes {
+            match node {
+                ContentNode::Note { id, children, .. } => {
+                    ids.insert(id.clone());
+                    note_ids(children, ids);
+                }
+                ContentNode::BlockQuote { children } | ContentNode::Container { children, .. } => {
+                    note_ids(children, ids)
+                }
+                ContentNode::Styled { node, .. } => {
+                    note_ids(std::slice::from_ref(node.as_ref()), ids)
+                }
+                _ => {}
+            }
+        }
+    }
+    fn blocks(nodes: &mut [ContentNode], ids: &HashSet<String>) {
+        for node in nodes {
+            match node {
+                ContentNode::Paragraph { children } | ContentNode::Heading { children, .. } => {
+                    inlines(children, ids)
+                }
+                ContentNode::BlockQuote { children }
+                | ContentNode::Note { children, .. }
+                | ContentNode::Container { children, .. } => blocks(children, ids),
+                ContentNode::Styled { node, .. } => {
+                    blocks(std::slice::from_mut(node.as_mut()), ids)
+                }
+                _ => {}
+            }
+        }
+    }
+    fn inlines(nodes: &mut [InlineNode], ids: &HashSet<String>) {
+        for node in nodes {
+            match node {
+                InlineNode::Link { href, children }
+                    if href.contains('#') && ids.contains(fragment(href)) =>
+                {
+                    *node = InlineNode::NoteRef {
+                        id: fragment(href).to_string(),
+                        label: extract_text_from_inlines(children)
+                            .unwrap_or_default()
+                            .trim()
+                            .to_string(),
+                    };
+                }
+                InlineNode::Emphasis(c)
+                | InlineNode::Strong(c)
+                | InlineNode::Superscript(c)
+                | InlineNode::Subscript(c)
+                | InlineNode::Span { children: c, .. } => inlines(c, ids),
+                _ => {}
+            }
+        }
+    }
+
+    let mut ids = HashSet::new();
+    for chapter in chapters.iter() {
+        note_ids(&chapter.content, &mut ids);
+    }
+    if ids.is_empty() {
+        return;
+    }
+    for chapter in chapters.iter_mut() {
+        blocks(&mut chapter.content, &ids);
     }
 }
 
@@ -698,7 +1400,7 @@ fn extract_text_from_inlines(inlines: &[InlineNode]) -> Option<String> {
                 }
             }
             InlineNode::Code(c) => text.push_str(c),
-            InlineNode::Link { children, .. } => {
+            InlineNode::Link { children, .. } | InlineNode::Span { children, .. } => {
                 if let Some(t) = extract_text_from_inlines(children) {
                     text.push_str(&t);
                 }
@@ -726,7 +1428,8 @@ fn parse_toc<R: Read + Seek>(
     if let Some(ref nav_href) = opf.nav_href {
         let full_path = format!("{}{}", opf_dir, nav_href);
         if let Ok(content) = read_archive_entry(archive, &full_path, limits) {
-            if let Some(entries) = parse_nav_document(&content) {
+            if let Some(mut entries) = parse_nav_document(&content) {
+                toc_hrefs_to_chapters(&mut entries, nav_href, opf);
                 return entries;
             }
         }
@@ -737,7 +1440,9 @@ fn parse_toc<R: Read + Seek>(
         if let Some(item) = opf.manifest.get(toc_id) {
             let full_path = format!("{}{}", opf_dir, item.href);
             if let Ok(content) = read_archive_entry(archive, &full_path, limits) {
-                return parse_ncx(&content);
+                let mut entries = parse_ncx(&content);
+                toc_hrefs_to_chapters(&mut entries, &item.href, opf);
+                return entries;
             }
         }
     }
@@ -745,6 +1450,86 @@ fn parse_toc<R: Read + Seek>(
     Vec::new()
 }
 
+/// Spine item ids by the normalized manifest href of their document.
+fn spine_ids_by_href(opf: &OpfData) -> HashMap<String, &str> {
+    opf.spine_items
+        .iter()
+        .filter_map(|id| {
+            let item = opf.manifest.get(id)?;
+            Some((normalize_path(&item.href), id.as_str()))
+        })
+        .collect()
+}
+
+/// `href` in the IR's `chapter-id#fragment` form (chapter ids are spine item
+/// ids), given the directory of the document it appears in. `None` when it
+/// does not point at a spine document.
+fn chapter_href(href: &str, base_dir: &str, by_href: &HashMap<String, &str>) -> Option<String> {
+    let (path, fragment) = match href.split_once('#') {
+        Some((path, fragment)) => (path, Some(fragment)),
+        None => (href, None),
+    };
+    let id = by_href.get(&normalize_path(&format!("{}{}", base_dir, path)))?;
+    Some(match fragment {
+        Some(f) if !f.is_empty() => format!("{}#{}", id, f),
+        _ => id.to_string(),
+    })
+}
+
+fn dir_of(href: &str) -> &str {
+    href.rfind('/').map_or("", |i| &href[..i + 1])
+}
+
+/// Rewrite TOC hrefs, which are relative to the navigation document, to
+/// chapter ids.
+fn toc_hrefs_to_chapters(entries: &mut [TocEntry], toc_href: &str, opf: &OpfData) {
+    fn rewrite(entries: &mut [TocEntry], toc_dir: &str, by_href: &HashMap<String, &str>) {
+        for entry in entries {
+            if let Some(href) = chapter_href(&entry.href, toc_dir, by_href) {
+                entry.href = href;
+            }
+            rewrite(&mut entry.children, toc_dir, by_href);
+        }
+    }
+    rewrite(entries, dir_of(toc_href), &spine_ids_by_href(opf));
+}
+
+/// Rewrite links between content documents (`../Text/ch03.xhtml#note12`,
+/// `#sec2`) to chapter ids, so they survive the writer renaming files.
+/// Links to anything but a spine document are left as they are.
+fn resolve_links(chapters: &mut [Chapter], hrefs: &[&str], opf: &OpfData) {
+    let by_href = spine_ids_by_href(opf);
+    for (chapter, href) in chapters.iter_mut().zip(hrefs) {
+        let base_dir = dir_of(href);
+        let id = chapter.id.clone();
+        crate::links::visit_hrefs_mut(&mut chapter.content, &mut |link| {
+            if crate::links::is_external(link) {
+                return;
+            }
+            if let Some(fragment) = link.strip_prefix('#') {
+                *link = format!("{}#{}", id, fragment);
+            } else if let Some(resolved) = chapter_href(link, base_dir, &by_href) {
+                *link = resolved;
+            }
+        });
+    }
+}
+
+/// Resolve `.` and `..` segments of an archive path.
+fn normalize_path(path: &str) -> String {
+    let mut parts: Vec<&str> = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            p => parts.push(p),
+        }
+    }
+    parts.join("/")
+}
+
 /// Parse EPUB3 NAV document (HTML with <nav epub:type="toc">).
 fn parse_nav_document(content: &str) -> Option<Vec<TocEntry>> {
     // Simple scraper-based approach for HTML NAV
@@ -794,10 +1579,13 @@ fn parse_nav_ol(ol: &scraper::ElementRef) -> Vec<TocEntry> {
 fn parse_ncx(content: &str) -> Vec<TocEntry> {
     let mut reader = XmlReader::from_str(content);
     let mut buf = Vec::new();
-    let mut entries = Vec::new();
-    let mut stack: Vec<Vec<TocEntry>> = vec![Vec::new()];
-    let mut current_title = String::new();
-    let mut current_href = String::new();
+    // One entry per open navPoint; the first collects the top-level entries.
+    // Nested navPoints follow their parent's label, so each keeps its own.
+    let mut stack: Vec<TocEntry> = vec![TocEntry {
+        title: String::new(),
+        href: String::new(),
+        children: Vec::new(),
+    }];
     let mut in_text = false;
 
     loop {
@@ -806,18 +1594,21 @@ fn parse_ncx(content: &str) -> Vec<TocEntry> {
                 let name = String::from_utf8_lossy(e.local_name().as_ref()).to_string();
                 match name.as_str() {
                     "navPoint" => {
-                        stack.push(Vec::new());
-                        current_title.clear();
-                        current_href.clear();
+                        stack.push(TocEntry {
+                            title: String::new(),
+                            href: String::new(),
+                            children: Vec::new(),
+                        });
                     }
                     "text" => {
-                        in_text = true;
+                        in_text = stack.len() > 1;
                     }
                     "content" => {
                         for attr in e.attributes().flatten() {
                             if attr.key.local_name().as_ref() == b"src" {
-                                current_href =
-                                    String::from_utf8_lossy(&attr.value).to_string();
+                                if let Some(point) = stack.last_mut() {
+                                    point.href = String::from_utf8_lossy(&attr.value).to_string();
+                                }
                             }
                         }
                     }
@@ -829,15 +1620,16 @@ fn parse_ncx(content: &str) -> Vec<TocEntry> {
                 if name == "content" {
                     for attr in e.attributes().flatten() {
                         if attr.key.local_name().as_ref() == b"src" {
-                            current_href = String::from_utf8_lossy(&attr.value).to_string();
+                            if let Some(point) = stack.last_mut() {
+                                point.href = String::from_utf8_lossy(&attr.value).to_string();
+                            }
                         }
                     }
                 }
             }
-            Ok(Event::Text(ref e)) => {
-                if in_text {
-                    current_title
-                        .push_str(&e.unescape().unwrap_or_default());
+            Ok(Event::Text(ref e)) if in_text => {
+                if let Some(point) = stack.last_mut() {
+                    point.title.push_str(&e.unescape().unwrap_or_default());
                 }
             }
             Ok(Event::End(ref e)) => {
@@ -846,18 +1638,13 @@ fn parse_ncx(content: &str) -> Vec<TocEntry> {
                     "text" => {
                         in_text = false;
                     }
-                    "navPoint" => {
-                        let children = stack.pop().unwrap_or_default();
-                        let entry = TocEntry {
-                            title: current_title.trim().to_string(),
-                            href: current_href.clone(),
-                            children,
-                        };
-                        if let Some(parent) = stack.last_mut() {
-                            parent.push(entry);
+                    "navPoint" if stack.len() > 1 => {
+                        if let Some(mut point) = stack.pop() {
+                            point.title = point.title.trim().to_string();
+                            if let Some(parent) = stack.last_mut() {
+                                parent.children.push(point);
+                            }
                         }
-                        current_title.clear();
-                        current_href.clear();
                     }
                     _ => {}
                 }
@@ -869,11 +1656,8 @@ fn parse_ncx(content: &str) -> Vec<TocEntry> {
         buf.clear();
     }
 
-    if let Some(top) = stack.pop() {
-        entries = top;
-    }
-
-    entries
+    stack.truncate(1);
+    stack.pop().map(|root| root.children).unwrap_or_default()
 }
 
 // --- Archive Helpers ---
@@ -944,6 +1728,321 @@ fn is_resource_media_type(media_type: &str) -> bool {
             | "application/font-sfnt"
             | "application/x-font-ttf"
             | "application/x-font-opentype"
+            | "application/vnd.ms-opentype"
+            | "application/font-woff"
             | "text/css"
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_footnotes_and_endnotes() {
+        let xhtml = r##"<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops"><body>
+            <p>Text<a epub:type="noteref" href="#fn1">1</a> and more<sup><a href="notes.xhtml#en2">2</a></sup>.</p>
+            <aside epub:type="footnote" id="fn1"><p>A <em>footnote</em>.</p></aside>
+            <section epub:type="endnotes"><ol>
+                <li id="en2"><p>An endnote. <a epub:type="backlink" href="#r2">&#8617;</a></p></li>
+            </ol></section>
+            <p>After.</p>
+        </body></html>"##;
+        let mut chapters = vec![
+            parse_xhtml_to_chapter(xhtml, "ch1", &crate::security::SecurityLimits::default()).0,
+        ];
+        link_note_refs(&mut chapters);
+        let content = &chapters[0].content;
+        assert_eq!(content.len(), 4);
+
+        let ContentNode::Paragraph { children } = &content[0] else {
+            panic!("expected paragraph, got {:?}", content[0]);
+        };
+        assert!(
+            matches!(&children[1], InlineNode::NoteRef { id, label } if id == "fn1" && label == "1")
+        );
+        assert!(children
+            .iter()
+            .any(|i| matches!(i, InlineNode::NoteRef { id, .. } if id == "en2")));
+
+        let ContentNode::Note { id, kind, children } = &content[1] else {
+            panic!("expected note, got {:?}", content[1]);
+        };
+        assert_eq!((id.as_str(), *kind), ("fn1", NoteKind::Footnote));
+        assert!(matches!(&children[0], ContentNode::Paragraph { .. }));
+
+        let ContentNode::Note { id, kind, children } = &content[2] else {
+            panic!("expected note, got {:?}", content[2]);
+        };
+        assert_eq!((id.as_str(), *kind), ("en2", NoteKind::Endnote));
+        let ContentNode::Paragraph { children } = &children[0] else {
+            panic!("expected paragraph");
+        };
+        assert_eq!(
+            extract_text_from_inlines(children).unwrap().trim(),
+            "An endnote."
+        );
+        assert!(matches!(&content[3], ContentNode::Paragraph { .. }));
+    }
+
+    #[test]
+    fn test_classes_and_languages_kept() {
+        let xhtml = r#"<html xmlns="http://www.w3.org/1999/xhtml"><body class="calibre">
+            <section class="chapter"><h1 class="title">One</h1>
+            <div class="epigraph" lang="fr"><p class="noindent">Je <span class="sc">pense</span>.</p></div>
+            <blockquote><p>Quoted <em class="name">Ada</em></p></blockquote>
+            <p>Plain</p></section>
+        </body></html>"#;
+        let (chapter, _) =
+            parse_xhtml_to_chapter(xhtml, "ch1", &crate::security::SecurityLimits::default());
+        let content = &chapter.content;
+        // The section holds a heading, so it is flattened rather than kept.
+        assert_eq!(content.len(), 4);
+        assert_eq!(chapter.title.as_deref(), Some("One"));
+        assert!(matches!(
+            &content[0],
+            ContentNode::Styled { attrs, node }
+                if attrs.class.as_deref() == Some("title")
+                    && matches!(node.as_ref(), ContentNode::Heading { level: 1, .. })
+        ));
+
+        let ContentNode::Container { attrs, children } = &content[1] else {
+            panic!("expected container, got {:?}", content[1]);
+        };
+        assert_eq!(attrs.class.as_deref(), Some("epigraph"));
+        assert_eq!(attrs.lang.as_deref(), Some("fr"));
+        let ContentNode::Styled { attrs, node } = &children[0] else {
+            panic!("expected styled paragraph");
+        };
+        assert_eq!(attrs.class.as_deref(), Some("noindent"));
+        let ContentNode::Paragraph { children } = node.as_ref() else {
+            panic!("expected paragraph");
+        };
+        assert!(matches!(
+            &children[1],
+            InlineNode::Span { attrs, .. } if attrs.class.as_deref() == Some("sc")
+        ));
+
+        let ContentNode::BlockQuote { children } = &content[2] else {
+            panic!("expected block quote, got {:?}", content[2]);
+        };
+        let ContentNode::Paragraph { children } = &children[0] else {
+            panic!("expected paragraph");
+        };
+        assert!(matches!(
+            &children[1],
+            InlineNode::Span { children, .. } if matches!(children[0], InlineNode::Emphasis(_))
+        ));
+        assert!(matches!(&content[3], ContentNode::Paragraph { .. }));
+    }
+
+    #[test]
+    fn test_malformed_xhtml_recovered() {
+        let limits = crate::security::SecurityLimits::default();
+        let xhtml = r#"<html xmlns="http://www.w3.org/1999/xhtml"><body>
+            <h1 class="title">Title</h1>
+            <p>One&nbsp;two<br>three</p>
+            <p>Unclosed paragraph
+            <p>Last &amp; <a href="ch2.xhtml">link</a></p>
+        </body></html>"#;
+        let (strict, error) = parse_xhtml_events(xhtml, "ch1", &limits);
+        assert!(error.is_some());
+        assert_eq!(strict.content.len(), 1);
+
+        let (chapter, error) = parse_xhtml_to_chapter(xhtml, "ch1", &limits);
+        assert!(error.unwrap().contains("nbsp"));
+        assert_eq!(chapter.title.as_deref(), Some("Title"));
+        let content = &chapter.content;
+        assert_eq!(content.len(), 4);
+        assert!(matches!(&content[0], ContentNode::Styled { .. }));
+        let ContentNode::Paragraph { children } = &content[1] else {
+            panic!("expected paragraph, got {:?}", content[1]);
+        };
+        assert!(matches!(
+            &children[..],
+            [InlineNode::Text(a), InlineNode::LineBreak, InlineNode::Text(b)]
+                if a == "One\u{a0}two" && b == "three"
+        ));
+        assert!(matches!(
+            &content[2],
+            ContentNode::Paragraph { children }
+                if matches!(&children[..], [InlineNode::Text(t)] if t == "Unclosed paragraph")
+        ));
+        let ContentNode::Paragraph { children } = &content[3] else {
+            panic!("expected paragraph, got {:?}", content[3]);
+        };
+        assert!(matches!(&children[0], InlineNode::Text(t) if t == "Last &"));
+        assert!(matches!(&children[1], InlineNode::Link { href, .. } if href == "ch2.xhtml"));
+
+        // The re-serialised tree is well-formed.
+        let (_, error) = parse_xhtml_to_chapter(&recover_xhtml(xhtml, &limits), "ch1", &limits);
+        assert!(error.is_none());
+    }
+
+    #[test]
+    fn test_opf_refines_identifiers_and_series() {
+        let opf = r##"<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="bookid">
+          <metadata xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:opf="http://www.idpf.org/2007/opf">
+            <dc:title id="t1">Persuasion</dc:title>
+            <meta refines="#t1" property="title-type">main</meta>
+            <dc:title id="t2">A Novel</dc:title>
+            <meta refines="#t2" property="title-type">subtitle</meta>
+            <dc:creator id="c1">Jane Austen</dc:creator>
+            <meta refines="#c1" property="role" scheme="marc:relators">aut</meta>
+            <meta refines="#c1" property="file-as">Austen, Jane</meta>
+            <dc:creator id="c2">Hugh Thomson</dc:creator>
+            <meta refines="#c2" property="role" scheme="marc:relators">ill</meta>
+            <dc:contributor opf:role="edt" opf:file-as="Doe, John">John Doe</dc:contributor>
+            <dc:identifier id="isbn">urn:isbn:978-0-14-143968-8</dc:identifier>
+            <dc:identifier id="bookid">urn:uuid:1b2c3d4e-0000-4000-8000-123456789abc</dc:identifier>
+            <dc:identifier id="amzn">B008TVLRHQ</dc:identifier>
+            <meta refines="#amzn" property="identifier-type">AMAZON</meta>
+            <meta property="belongs-to-collection" id="col">Austen Novels</meta>
+            <meta refines="#col" property="collection-type">series</meta>
+            <meta refines="#col" property="group-position">6</meta>
+            <meta property="dcterms:modified">2020-01-02T03:04:05Z</meta>
+            <meta name="calibre:series" content="Ignored"/>
+          </metadata>
+          <manifest/><spine/>
+        </package>"##;
+        let m = parse_opf(opf, "").unwrap().metadata;
+        assert_eq!(m.title.as_deref(), Some("Persuasion"));
+        assert_eq!(m.subtitle.as_deref(), Some("A Novel"));
+        assert_eq!(m.authors, ["Jane Austen"]);
+        assert_eq!(
+            m.contributors,
+            [
+                Contributor {
+                    name: "Jane Austen".into(),
+                    file_as: Some("Austen, Jane".into()),
+                    role: Some("aut".into()),
+                },
+                Contributor {
+                    name: "Hugh Thomson".into(),
+                    file_as: None,
+                    role: Some("ill".into()),
+                },
+                Contributor {
+                    name: "John Doe".into(),
+                    file_as: Some("Doe, John".into()),
+                    role: Some("edt".into()),
+                },
+            ]
+        );
+        let ids: Vec<(&str, Option<&str>)> = m
+            .identifiers
+            .iter()
+            .map(|i| (i.value.as_str(), i.scheme.as_deref()))
+            .collect();
+        assert_eq!(
+            ids,
+            [
+                ("1b2c3d4e-0000-4000-8000-123456789abc", Some("UUID")),
+                ("978-0-14-143968-8", Some("ISBN")),
+                ("B008TVLRHQ", Some("AMAZON")),
+            ]
+        );
+        assert_eq!(m.isbn_13.as_deref(), Some("9780141439688"));
+        let series = m.series.unwrap();
+        assert_eq!(
+            (series.name.as_str(), series.position),
+            ("Austen Novels", Some(6.0))
+        );
+        assert_eq!(m.modified.as_deref(), Some("2020-01-02T03:04:05Z"));
+        assert!(m.custom.is_empty());
+
+        // EPUB2 books written by Calibre carry the series in name/content metas.
+        let opf = r##"<package xmlns="http://www.idpf.org/2007/opf" version="2.0">
+          <metadata xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:opf="http://www.idpf.org/2007/opf">
+            <dc:creator opf:file-as="Tolkien, J. R. R.">J. R. R. Tolkien</dc:creator>
+            <dc:identifier opf:scheme="calibre">42</dc:identifier>
+            <meta name="calibre:series" content="The Lord of the Rings"/>
+            <meta name="calibre:series_index" content="2.0"/>
+          </metadata>
+          <manifest/><spine/>
+        </package>"##;
+        let m = parse_opf(opf, "").unwrap().metadata;
+        assert_eq!(m.authors, ["J. R. R. Tolkien"]);
+        assert_eq!(
+            m.contributors[0].file_as.as_deref(),
+            Some("Tolkien, J. R. R.")
+        );
+        assert_eq!(m.identifiers[0].scheme.as_deref(), Some("CALIBRE"));
+        assert_eq!(m.isbn_10, None);
+        let series = m.series.unwrap();
+        assert_eq!(
+            (series.name.as_str(), series.position),
+            ("The Lord of the Rings", Some(2.0))
+        );
+    }
+
+    #[test]
+    fn test_links_resolved_to_chapters() {
+        use crate::readers::ReadOptions;
+        use crate::writers::{epub::EpubWriter, FormatWriter, WriteOptions};
+        use std::io::{Cursor, Write};
+
+        let mut epub = Vec::new();
+        {
+            let mut zip = zip::ZipWriter::new(Cursor::new(&mut epub));
+            let options: zip::write::FileOptions<'_, ()> = zip::write::FileOptions::default();
+            let files = [
+                ("mimetype", "application/epub+zip"),
+                (
+                    "META-INF/container.xml",
+                    r#"<container xmlns="urn:oasis:names:tc:opendocument:xmlns:container"><rootfiles><rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/></rootfiles></container>"#,
+                ),
+                (
+                    "OEBPS/content.opf",
+                    r#"<package xmlns="http://www.idpf.org/2007/opf" version="3.0"><metadata/><manifest>
+                        <item id="c1" href="Text/ch01.xhtml" media-type="application/xhtml+xml"/>
+                        <item id="c2" href="Text/ch02.xhtml" media-type="application/xhtml+xml"/>
+                    </manifest><spine><itemref idref="c1"/><itemref idref="c2"/></spine></package>"#,
+                ),
+                (
+                    "OEBPS/Text/ch01.xhtml",
+                    r##"<html xmlns="http://www.w3.org/1999/xhtml"><body>
+                        <p id="top">See <a href="../Text/ch02.xhtml#sec2">part two</a> or <a href="#top">the top</a>.</p>
+                    </body></html>"##,
+                ),
+                (
+                    "OEBPS/Text/ch02.xhtml",
+                    r#"<html xmlns="http://www.w3.org/1999/xhtml"><body>
+                        <h2 id="sec2">Two</h2><p>Text<a id="p12"/>.</p>
+                    </body></html>"#,
+                ),
+            ];
+            for (name, data) in files {
+                zip.start_file(name, options).unwrap();
+                zip.write_all(data.as_bytes()).unwrap();
+            }
+            zip.finish().unwrap();
+        }
+
+        let doc = EpubReader::read(Cursor::new(epub), &ReadOptions::default(), None).unwrap();
+        let mut hrefs = Vec::new();
+        crate::links::visit_hrefs(&doc.content[0].content, &mut |h| hrefs.push(h.to_string()));
+        assert_eq!(hrefs, ["c2#sec2", "c1#top"]);
+        assert!(crate::links::check_links(&doc).is_empty());
+
+        let mut out = Vec::new();
+        EpubWriter::write(&doc, &mut out, &WriteOptions::default(), None).unwrap();
+        let mut archive = zip::ZipArchive::new(Cursor::new(out)).unwrap();
+        let mut read = |name: &str| {
+            let mut s = String::new();
+            archive
+                .by_name(name)
+                .unwrap()
+                .read_to_string(&mut s)
+                .unwrap();
+            s
+        };
+        let first = read("OEBPS/chapter1.xhtml");
+        assert!(first.contains("<p id=\"top\">"));
+        assert!(first.contains("<a href=\"chapter2.xhtml#sec2\">part two</a>"));
+        assert!(first.contains("<a href=\"#top\">the top</a>"));
+        let second = read("OEBPS/chapter2.xhtml");
+        assert!(second.contains("<h2 id=\"sec2\">Two</h2>"));
+        assert!(second.contains("<span id=\"p12\"></span>"));
+    }
+}
@@ -0,0 +1,928 @@
+//! FictionBook 2 reader: FB2 XML (or a `.fb2.zip` holding one) → IR.
+//!
+//! `<title-info>` and `<publish-info>` become `Metadata`; each top-level
+//! `<section>` of the main body becomes a chapter, with nested sections as
+//! lower-level headings and TOC children. Poems, epigraphs and citations are
+//! block quotes. Sections of a `notes` (or `comments`) body become footnotes
+//! (endnotes) at the end of the last chapter, referenced from
+//! `<a type="note">` links, and base64 `<binary>` elements become resources.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Seek};
+
+use base64::Engine;
+
+use crate::detect::{DetectResult, Format};
+use crate::document::*;
+use crate::error::ReadError;
+use crate::progress::{emit_progress, ProgressHandler};
+use crate::readers::xml_tree::{parse_tree, Element, Xml};
+use crate::readers::{collapse_whitespace, inline_text, trim_inlines, FormatReader, ReadOptions};
+use crate::security::{self, SecurityLimits};
+
+pub struct Fb2Reader;
+
+impl FormatReader for Fb2Reader {
+    fn detect(header: &[u8]) -> DetectResult {
+        let head = String::from_utf8_lossy(&header[..header.len().min(1024)]);
+        DetectResult {
+            format: Format::Fb2,
+            confidence: if head.contains("<FictionBook") {
+                0.95
+            } else {
+                0.0
+            },
+            mime_type: Format::Fb2.mime_type(),
+        }
+    }
+
+    fn read<R: Read + Seek>(
+        mut input: R,
+        opts: &ReadOptions,
+        progress: Option<&dyn ProgressHandler>,
+    ) -> Result<Document, ReadError> {
+        let mut raw = Vec::new();
+        input.read_to_end(&mut raw)?;
+        if raw.starts_with(b"PK\x03\x04") {
+            raw = unzip_fb2(&raw, &opts.security)?;
+        }
+        read_fb2_impl(&raw, opts, progress)
+    }
+}
+
+fn malformed(detail: impl Into<String>) -> ReadError {
+    ReadError::MalformedFile {
+        format: "FB2".into(),
+        detail: detail.into(),
+    }
+}
+
+/// Extract the `.fb2` document from a `.fb2.zip` archive.
+fn unzip_fb2(raw: &[u8], limits: &SecurityLimits) -> Result<Vec<u8>, ReadError> {
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(raw))
+        .map_err(|e| malformed(format!("Invalid ZIP archive: {e}")))?;
+    security::check_file_count(archive.len() as u64, limits)?;
+    let name = archive
+        .file_names()
+        .filter(|n| n.to_ascii_lowercase().ends_with(".fb2"))
+        .min()
+        .map(str::to_string)
+        .ok_or_else(|| ReadError::MissingContent("No .fb2 file in archive".into()))?;
+    let mut file = archive
+        .by_name(&name)
+        .map_err(|e| malformed(format!("Failed to open {}: {}", name, e)))?;
+    security::check_path_traversal(&name)?;
+    security::check_resource_size(&name, file.size(), limits)?;
+    let mut buf = Vec::with_capacity(file.size() as usize);
+    file.read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+/// Decode the document using the encoding named in the XML declaration.
+/// Russian FB2 files are often `windows-1251`.
+fn decode_xml(raw: &[u8]) -> String {
+    if let Some((encoding, _)) = encoding_rs::Encoding::for_bom(raw) {
+        return encoding.decode_with_bom_removal(raw).0.into_owned();
+    }
+    let prolog = String::from_utf8_lossy(&raw[..raw.len().min(200)]);
+    let label = prolog
+        .strip_prefix("<?xml")
+        .and_then(|p| p.split("?>").next())
+        .and_then(|decl| decl.split("encoding=").nth(1))
+        .and_then(|rest| {
+            let quote = rest.chars().next().filter(|q| matches!(q, '"' | '\''))?;
+            rest[1..].split(quote).next()
+        });
+    let encoding = label
+        .and_then(|l| encoding_rs::Encoding::for_label(l.trim().as_bytes()))
+        .unwrap_or(encoding_rs::UTF_8);
+    encoding.decode_without_bom_handling(raw).0.into_owned()
+}
+
+fn read_fb2_impl(
+    raw: &[u8],
+    opts: &ReadOptions,
+    progress: Option<&dyn ProgressHandler>,
+) -> Result<Document, ReadError> {
+    security::check_total_size(raw.len() as u64, &opts.security)?;
+    emit_progress(progress, "Reading FB2", 0, Some(4), Some("Parsing XML"));
+    let content = decode_xml(raw);
+    let root = parse_tree(&content, &opts.security, malformed)?;
+    if root.name != "FictionBook" {
+        return Err(malformed("missing <FictionBook> root element"));
+    }
+
+    emit_progress(
+        progress,
+        "Reading FB2",
+        1,
+        Some(4),
+        Some("Reading metadata"),
+    );
+    let mut metadata = root
+        .child("description")
+        .map(parse_description)
+        .unwrap_or_default();
+
+    emit_progress(
+        progress,
+        "Reading FB2",
+        2,
+        Some(4),
+        Some("Loading binaries"),
+    );
+    let mut resources = ResourceMap::new();
+    for binary in root.children_named("binary") {
+        let Some(id) = binary.attr("id") else {
+            continue;
+        };
+        let encoded: String = binary
+            .text()
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect();
+        let data = match base64::engine::general_purpose::STANDARD.decode(encoded.as_bytes()) {
+            Ok(data) => data,
+            Err(e) => {
+                tracing::warn!("Skipping binary '{}': {}", id, e);
+                continue;
+            }
+        };
+        if let Err(e) = security::check_resource_size(id, data.len() as u64, &opts.security) {
+            tracing::warn!("Skipping binary '{}': {}", id, e);
+            continue;
+        }
+        let media_type = binary
+            .attr("content-type")
+            .map(str::to_string)
+            .unwrap_or_else(|| crate::readers::guess_media_type(id).to_string());
+        resources.insert(
+            id.to_string(),
+            Resource {
+                id: id.to_string(),
+                media_type,
+                data,
+                filename: Some(id.to_string()),
+                obfuscation: None,
+            },
+        );
+    }
+    if metadata
+        .cover_image_id
+        .as_ref()
+        .is_some_and(|id| resources.get(id).is_none())
+    {
+        metadata.cover_image_id = None;
+    }
+
+    emit_progress(
+        progress,
+        "Reading FB2",
+        3,
+        Some(4),
+        Some("Parsing sections"),
+    );
+    let bodies: Vec<&Element> = root.children_named("body").collect();
+    let note_kind = |b: &Element| match b.attr("name") {
+        Some("notes") => Some(NoteKind::Footnote),
+        Some("comments") => Some(NoteKind::Endnote),
+        _ => None,
+    };
+    let main = bodies.iter().find(|b| note_kind(b).is_none()).copied();
+    let notes: Vec<(&Element, NoteKind)> = bodies
+        .iter()
+        .filter_map(|b| Some((*b, note_kind(b)?)))
+        .collect();
+
+    let mut builder = BodyBuilder::default();
+    if let Some(main) = main {
+        builder.plan_main(main);
+    }
+    for (body, _) in &notes {
+        builder.plan_notes(body);
+    }
+    let mut content = Vec::new();
+    let mut toc = Vec::new();
+    if let Some(main) = main {
+        builder.read_main(main, &mut content, &mut toc);
+    }
+    let mut nodes = Vec::new();
+    for (body, kind) in &notes {
+        for section in body.children_named("section") {
+            builder.note(section, *kind, &mut nodes);
+        }
+    }
+    if !nodes.is_empty() {
+        if content.is_empty() {
+            content.push(Chapter {
+                id: builder.notes_chapter().to_string(),
+                title: None,
+                content: Vec::new(),
+                text_direction: None,
+            });
+        }
+        if let Some(last) = content.last_mut() {
+            last.content.append(&mut nodes);
+        }
+    }
+
+    emit_progress(progress, "Reading FB2", 4, Some(4), Some("Done"));
+    Ok(Document {
+        metadata,
+        toc: if opts.parse_toc { toc } else { Vec::new() },
+        content,
+        resources,
+        text_direction: TextDirection::Ltr,
+        epub_version: None,
+        diagnostics: Vec::new(),
+    })
+}
+
+// --- Description ---
+
+fn parse_description(description: &Element) -> Metadata {
+    let mut metadata = Metadata::default();
+    if let Some(info) = description.child("title-info") {
+        metadata.title = info
+            .child("book-title")
+            .map(|t| t.text())
+            .filter(|t| !t.is_empty());
+        metadata.authors = info
+            .children_named("author")
+            .filter_map(person_name)
+            .collect();
+        metadata.subjects = info
+            .children_named("genre")
+            .map(|g| g.text())
+            .filter(|g| !g.is_empty())
+            .collect();
+        if let Some(keywords) = info.child("keywords") {
+            for k in keywords.text().split(',') {
+                let k = k.trim();
+                if !k.is_empty() && !metadata.subjects.iter().any(|s| s == k) {
+                    metadata.subjects.push(k.to_string());
+                }
+            }
+        }
+        metadata.description = info
+            .child("annotation")
+            .map(block_text)
+            .filter(|d| !d.is_empty());
+        metadata.publish_date = info.child("date").and_then(date_value);
+        metadata.language = info
+            .child("lang")
+            .map(|l| l.text())
+            .filter(|l| !l.is_empty());
+        metadata.cover_image_id = info
+            .child("coverpage")
+            .and_then(|c| c.child("image"))
+            .and_then(|i| i.attr("href"))
+            .map(|h| h.trim_start_matches('#').to_string());
+        metadata.series = info.child("sequence").and_then(|s| {
+            let name = s.attr("name")?.trim();
+            (!name.is_empty()).then(|| SeriesInfo {
+                name: name.to_string(),
+                position: s.attr("number").and_then(|n| n.trim().parse().ok()),
+            })
+        });
+        let translators: Vec<String> = info
+            .children_named("translator")
+            .filter_map(person_name)
+            .collect();
+        if !translators.is_empty() {
+            metadata
+                .custom
+                .insert("translator".into(), translators.join("; "));
+        }
+        if let Some(src) = info
+            .child("src-lang")
+            .map(|l| l.text())
+            .filter(|l| !l.is_empty())
+        {
+            metadata.custom.insert("source_language".into(), src);
+        }
+    }
+    if let Some(publish) = description.child("publish-info") {
+        metadata.publisher = publish
+            .child("publisher")
+            .map(|p| p.text())
+            .filter(|p| !p.is_empty());
+        if let Some(year) = publish
+            .child("year")
+            .map(|y| y.text())
+            .filter(|y| !y.is_empty())
+        {
+            // The print edition's year is more useful than the text's writing date.
+            metadata.publish_date = Some(year);
+        }
+        for isbn in publish.children_named("isbn") {
+            let digits: String = isbn
+                .text()
+                .chars()
+                .filter(|c| c.is_ascii_alphanumeric())
+                .collect();
+            match digits.len() {
+                13 => metadata.isbn_13 = Some(digits),
+                10 => metadata.isbn_10 = Some(digits),
+                _ => {}
+            }
+        }
+    }
+    if let Some(id) = description
+        .child("document-info")
+        .and_then(|d| d.child("id"))
+        .map(|i| i.text())
+        .filter(|i| !i.is_empty())
+    {
+        metadata.custom.insert("document_id".into(), id);
+    }
+    metadata
+}
+
+/// "First Middle Last", or the nickname when no name parts are given.
+fn person_name(author: &Element) -> Option<String> {
+    let parts: Vec<String> = ["first-name", "middle-name", "last-name"]
+        .iter()
+        .filter_map(|n| author.child(n).map(|e| e.text()))
+        .filter(|s| !s.is_empty())
+        .collect();
+    let name = if parts.is_empty() {
+        author.child("nickname").map(|n| n.text())?
+    } else {
+        parts.join(" ")
+    };
+    (!name.is_empty()).then_some(name)
+}
+
+fn date_value(date: &Element) -> Option<String> {
+    date.attr("value")
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .or_else(|| Some(date.text()).filter(|t| !t.is_empty()))
+}
+
+/// Paragraph texts separated by blank lines (for annotations).
+fn block_text(el: &Element) -> String {
+    let paragraphs: Vec<String> = el
+        .elements()
+        .map(|e| e.text())
+        .filter(|t| !t.is_empty())
+        .collect();
+    if paragraphs.is_empty() {
+        el.text()
+    } else {
+        paragraphs.join("\n\n")
+    }
+}
+
+// --- Bodies ---
+
+/// Converts body sections to chapters. A planning pass records which chapter
+/// each element id lands in, so `#id` links can point at the right chapter.
+#[derive(Default)]
+struct BodyBuilder {
+    /// Element id → chapter id.
+    anchors: HashMap<String, String>,
+    /// Chapter id for each top-level section of the main body, in order.
+    chapter_ids: Vec<String>,
+    /// Ids of note sections, whose links become note references.
+    note_ids: HashSet<String>,
+}
+
+impl BodyBuilder {
+    fn plan_main(&mut self, body: &Element) {
+        let mut used = HashSet::new();
+        let has_front = has_front_matter(body);
+        if has_front {
+            used.insert("chapter-1".to_string());
+            self.chapter_ids.push("chapter-1".to_string());
+        }
+        for section in body.children_named("section") {
+            let id = match section
+                .attr("id")
+                .filter(|id| !id.is_empty() && !used.contains(*id))
+            {
+                Some(id) => id.to_string(),
+                None => (self.chapter_ids.len() + 1..)
+                    .map(|n| format!("chapter-{}", n))
+                    .find(|id| !used.contains(id))
+                    .unwrap(),
+            };
+            used.insert(id.clone());
+            self.collect_anchors(section, &id);
+            self.chapter_ids.push(id);
+        }
+        if self.chapter_ids.is_empty() {
+            self.chapter_ids.push("chapter-1".to_string());
+        }
+    }
+
+    fn plan_notes(&mut self, body: &Element) {
+        fn note_ids(section: &Element, ids: &mut HashSet<String>) {
+            if let Some(id) = section.attr("id").filter(|id| !id.is_empty()) {
+                ids.insert(id.to_string());
+            }
+            for child in section.children_named("section") {
+                note_ids(child, ids);
+            }
+        }
+        let chapter = self.notes_chapter().to_string();
+        for section in body.children_named("section") {
+            note_ids(section, &mut self.note_ids);
+            self.collect_anchors(section, &chapter);
+        }
+    }
+
+    /// The chapter notes are appended to: the last one of the main body.
+    fn notes_chapter(&self) -> &str {
+        self.chapter_ids.last().map_or("chapter-1", String::as_str)
+    }
+
+    fn collect_anchors(&mut self, el: &Element, chapter: &str) {
+        if let Some(id) = el.attr("id") {
+            self.anchors
+                .entry(id.to_string())
+                .or_insert_with(|| chapter.to_string());
+        }
+        for child in el.elements() {
+            self.collect_anchors(child, chapter);
+        }
+    }
+
+    /// Map an FB2 link to the IR convention: `chapter-id#fragment` for
+    /// internal links, unchanged for external ones.
+    fn resolve_href(&self, href: &str) -> String {
+        match href.strip_prefix('#') {
+            Some(fragment) => match self.anchors.get(fragment) {
+                Some(chapter) if chapter == fragment => chapter.clone(),
+                Some(chapter) => format!("{}#{}", chapter, fragment),
+                None => href.to_string(),
+            },
+            None => href.to_string(),
+        }
+    }
+
+    fn read_main(&self, body: &Element, chapters: &mut Vec<Chapter>, toc: &mut Vec<TocEntry>) {
+        let mut ids = self.chapter_ids.iter();
+        let sections: Vec<&Element> = body.children_named("section").collect();
+
+        if has_front_matter(body) || sections.is_empty() {
+            let id = ids.next().cloned().unwrap_or_else(|| "chapter-1".into());
+            let mut nodes = Vec::new();
+            for child in body.elements() {
+                match child.name.as_str() {
+                    "title" => self.title(child, 1, &mut nodes),
+                    "section" => {}
+                    _ => self.block(child, &mut nodes),
+                }
+            }
+            let title = body
+                .child("title")
+                .map(|t| t.text())
+                .filter(|t| !t.is_empty());
+            if sections.is_empty() || !nodes.is_empty() {
+                if let Some(title) = &title {
+                    toc.push(TocEntry {
+                        title: title.clone(),
+                        href: id.clone(),
+                        children: Vec::new(),
+                    });
+                }
+                chapters.push(Chapter {
+                    id,
+                    title,
+                    content: nodes,
+                    text_direction: None,
+                });
+            }
+        }
+
+        for section in sections {
+            let Some(id) = ids.next() else {
+                break;
+            };
+            let mut nodes = Vec::new();
+            let mut children = Vec::new();
+            self.section(section, 1, id, &mut nodes, &mut children);
+            let title = section
+                .child("title")
+                .map(|t| t.text())
+                .filter(|t| !t.is_empty());
+            match &title {
+                Some(title) => toc.push(TocEntry {
+                    title: title.clone(),
+                    href: id.clone(),
+                    children,
+                }),
+                // Untitled sections keep their subsections reachable.
+                None => toc.extend(children),
+            }
+            chapters.push(Chapter {
+                id: id.clone(),
+                title,
+                content: nodes,
+                text_direction: None,
+            });
+        }
+    }
+
+    /// Flatten a section into `nodes`, its title becoming a heading at `level`.
+    fn section(
+        &self,
+        section: &Element,
+        level: u8,
+        chapter_id: &str,
+        nodes: &mut Vec<ContentNode>,
+        toc: &mut Vec<TocEntry>,
+    ) {
+        for child in section.elements() {
+            match child.name.as_str() {
+                "title" => self.title(child, level, nodes),
+                "section" => {
+                    let mut children = Vec::new();
+                    self.section(child, (level + 1).min(6), chapter_id, nodes, &mut children);
+                    match child
+                        .child("title")
+                        .map(|t| t.text())
+                        .filter(|t| !t.is_empty())
+                    {
+                        Some(title) => toc.push(TocEntry {
+                            title,
+                            href: match child.attr("id") {
+                                Some(id) => format!("{}#{}", chapter_id, id),
+                                None => chapter_id.to_string(),
+                            },
+                            children,
+                        }),
+                        None => toc.extend(children),
+                    }
+                }
+                _ => self.block(child, nodes),
+            }
+        }
+    }
+
+    fn title(&self, title: &Element, level: u8, nodes: &mut Vec<ContentNode>) {
+        // Title paragraphs are lines of one heading.
+        let mut children = Vec::new();
+        for p in title.children_named("p") {
+            let inlines = self.inlines(p);
+            if inlines.is_empty() {
+                continue;
+            }
+            if !children.is_empty() {
+                children.push(InlineNode::LineBreak);
+            }
+            children.extend(inlines);
+        }
+        if !children.is_empty() {
+            nodes.push(ContentNode::Heading { level, children });
+        }
+    }
+
+    fn block(&self, el: &Element, nodes: &mut Vec<ContentNode>) {
+        match el.name.as_str() {
+            "p" => {
+                let children = self.inlines(el);
+                if !children.is_empty() {
+                    nodes.push(ContentNode::Paragraph { children });
+                }
+            }
+            "subtitle" => {
+                let children = self.inlines(el);
+                let text = inline_text(&children);
+                if is_scene_break(&text) {
+                    nodes.push(ContentNode::HorizontalRule);
+                } else if !children.is_empty() {
+                    nodes.push(ContentNode::Paragraph {
+                        children: vec![InlineNode::Strong(children)],
+                    });
+                }
+            }
+            "image" => {
+                if let Some(href) = el.attr("href") {
+                    nodes.push(ContentNode::Image {
+                        resource_id: href.trim_start_matches('#').to_string(),
+                        alt_text: el.attr("alt").map(str::to_string),
+                        caption: el.attr("title").map(str::to_string),
+                    });
+                }
+            }
+            "poem" | "epigraph" | "cite" | "annotation" => {
+                let mut children = Vec::new();
+                for child in el.elements() {
+                    match child.name.as_str() {
+                        "title" => self.title(child, 4, &mut children),
+                        "stanza" => self.stanza(child, &mut children),
+                        "text-author" => {
+                            let inlines = self.inlines(child);
+                            if !inlines.is_empty() {
+                                children.push(ContentNode::Paragraph {
+                                    children: vec![InlineNode::Emphasis(inlines)],
+                                });
+                            }
+                        }
+                        "date" => {
+                            let text = child.text();
+                            if !text.is_empty() {
+                                children.push(ContentNode::Paragraph {
+                                    children: vec![InlineNode::Emphasis(vec![InlineNode::Text(
+                                        text,
+                                    )])],
+                                });
+                            }
+                        }
+                        _ => self.block(child, &mut children),
+                    }
+                }
+                if !children.is_empty() {
+                    nodes.push(ContentNode::BlockQuote { children });
+                }
+            }
+            "stanza" => self.stanza(el, nodes),
+            "table" => self.table(el, nodes),
+            // `<empty-line/>` is vertical spacing only.
+            _ => {}
+        }
+    }
+
+    /// A stanza is one paragraph, its verses separated by line breaks.
+    fn stanza(&self, stanza: &Element, nodes: &mut Vec<ContentNode>) {
+        let mut children = Vec::new();
+        for child in stanza.elements() {
+            match child.name.as_str() {
+                "title" => self.title(child, 5, nodes),
+                "subtitle" => self.block(child, nodes),
+                "v" => {
+                    let line = self.inlines(child);
+                    if line.is_empty() {
+                        continue;
+                    }
+                    if !children.is_empty() {
+                        children.push(InlineNode::LineBreak);
+                    }
+                    children.extend(line);
+                }
+                _ => {}
+            }
+        }
+        if !children.is_empty() {
+            nodes.push(ContentNode::Paragraph { children });
+        }
+    }
+
+    fn table(&self, table: &Element, nodes: &mut Vec<ContentNode>) {
+        let mut headers = Vec::new();
+        let mut rows = Vec::new();
+        for (i, tr) in table.children_named("tr").enumerate() {
+            let cells: Vec<&Element> = tr
+                .elements()
+                .filter(|c| c.name == "td" || c.name == "th")
+                .collect();
+            let row: Vec<Vec<InlineNode>> = cells.iter().map(|c| self.inlines(c)).collect();
+            if i == 0 && !cells.is_empty() && cells.iter().all(|c| c.name == "th") {
+                headers = row;
+            } else {
+                rows.push(row);
+            }
+        }
+        if !headers.is_empty() || !rows.is_empty() {
+            nodes.push(ContentNode::Table { headers, rows });
+        }
+    }
+
+    /// Inline content of a paragraph-like element, whitespace collapsed and trimmed.
+    fn inlines(&self, el: &Element) -> Vec<InlineNode> {
+        let mut out = self.inline_children(el);
+        trim_inlines(&mut out);
+        out
+    }
+
+    fn inline_children(&self, el: &Element) -> Vec<InlineNode> {
+        let mut out = Vec::new();
+        for child in &el.children {
+            match child {
+                Xml::Text(t) => push_text(&mut out, &collapse_whitespace(t)),
+                Xml::Element(e) => match e.name.as_str() {
+                    "emphasis" => out.push(InlineNode::Emphasis(self.inline_children(e))),
+                    "strong" => out.push(InlineNode::Strong(self.inline_children(e))),
+                    "sup" => out.push(InlineNode::Superscript(self.inline_children(e))),
+                    "sub" => out.push(InlineNode::Subscript(self.inline_children(e))),
+                    "code" => out.push(InlineNode::Code(e.text())),
+                    "a" => {
+                        let raw = e.attr("href").unwrap_or_default();
+                        let children = self.inline_children(e);
+                        match raw
+                            .strip_prefix('#')
+                            .filter(|id| self.note_ids.contains(*id))
+                        {
+                            Some(id) => out.push(InlineNode::NoteRef {
+                                id: id.to_string(),
+                                label: inline_text(&children),
+                            }),
+                            None => out.push(InlineNode::Link {
+                                href: self.resolve_href(raw),
+                                children,
+                            }),
+                        }
+                    }
+                    "image" => {
+                        if let Some(alt) = e.attr("alt").filter(|a| !a.is_empty()) {
+                            push_text(&mut out, alt);
+                        }
+                    }
+                    // `<style>`, `<strikethrough>` and unknown wrappers keep their text.
+                    _ => out.extend(self.inline_children(e)),
+                },
+            }
+        }
+        out
+    }
+
+    /// A note section becomes a `Note`; its title is the label its
+    /// references already carry. Nested sections are notes of their own, and
+    /// the content of sections without an id is kept as plain blocks.
+    fn note(&self, section: &Element, kind: NoteKind, nodes: &mut Vec<ContentNode>) {
+        let mut children = Vec::new();
+        let mut nested = Vec::new();
+        for child in section.elements() {
+            match child.name.as_str() {
+                "title" => {}
+                "section" => self.note(child, kind, &mut nested),
+                _ => self.block(child, &mut children),
+            }
+        }
+        match section.attr("id").filter(|id| !id.is_empty()) {
+            Some(id) => nodes.push(ContentNode::Note {
+                id: id.to_string(),
+                kind,
+                children,
+            }),
+            None => nodes.append(&mut children),
+        }
+        nodes.append(&mut nested);
+    }
+}
+
+/// The main body has content (title, epigraphs, images) before its first section.
+fn has_front_matter(body: &Element) -> bool {
+    body.elements()
+        .take_while(|e| e.name != "section")
+        .any(|e| e.name != "empty-line")
+        && body.child("section").is_some()
+}
+
+fn is_scene_break(text: &str) -> bool {
+    !text.is_empty()
+        && text
+            .chars()
+            .all(|c| matches!(c, '*' | '#' | '~' | '-' | '—' | '•' | '⁂') || c.is_whitespace())
+}
+
+fn push_text(out: &mut Vec<InlineNode>, text: &str) {
+    if text.is_empty() {
+        return;
+    }
+    if let Some(InlineNode::Text(prev)) = out.last_mut() {
+        prev.push_str(text);
+    } else {
+        out.push(InlineNode::Text(text.to_string()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+
+    const SAMPLE: &str = r##"<?xml version="1.0" encoding="windows-1251"?>
+<FictionBook xmlns="http://www.gribuser.ru/xml/fictionbook/2.0" xmlns:l="http://www.w3.org/1999/xlink">
+<description>
+  <title-info>
+    <genre>sf</genre>
+    <author><first-name>Ivan</first-name><last-name>Petrov</last-name></author>
+    <book-title>TITLE</book-title>
+    <annotation><p>First.</p><p>Second.</p></annotation>
+    <lang>ru</lang>
+    <coverpage><image l:href="#cover.png"/></coverpage>
+    <sequence name="Saga" number="3"/>
+  </title-info>
+  <publish-info><publisher>Pub</publisher><year>2001</year><isbn>5-17-000000-1</isbn></publish-info>
+</description>
+<body>
+  <section id="s1">
+    <title><p>Part One</p></title>
+    <epigraph><p>Quote</p><text-author>Someone</text-author></epigraph>
+    <section id="s1a">
+      <title><p>Chapter 1</p></title>
+      <p>Some <emphasis>text</emphasis> here<a l:href="#n1" type="note">1</a>.</p>
+      <subtitle>* * *</subtitle>
+      <poem><stanza><v>Line one</v><v>Line two</v></stanza></poem>
+      <image l:href="#cover.png"/>
+    </section>
+  </section>
+  <section><title><p>Part Two</p></title><p>See <a l:href="#s1a">chapter one</a>.</p></section>
+</body>
+<body name="notes">
+  <section id="n1"><title><p>1</p></title><p>A note.</p></section>
+</body>
+<binary id="cover.png" content-type="image/png">iVBORw0KGgo=</binary>
+</FictionBook>
+"##;
+
+    fn sample_bytes() -> Vec<u8> {
+        let text = SAMPLE.replace("TITLE", "Война и мир");
+        encoding_rs::WINDOWS_1251.encode(&text).0.into_owned()
+    }
+
+    #[test]
+    fn reads_metadata_sections_and_notes() {
+        let doc =
+            Fb2Reader::read(Cursor::new(sample_bytes()), &ReadOptions::default(), None).unwrap();
+        let m = &doc.metadata;
+        assert_eq!(m.title.as_deref(), Some("Война и мир"));
+        assert_eq!(m.authors, ["Ivan Petrov"]);
+        assert_eq!(m.description.as_deref(), Some("First.\n\nSecond."));
+        assert_eq!(m.publish_date.as_deref(), Some("2001"));
+        assert_eq!(m.isbn_10.as_deref(), Some("5170000001"));
+        assert_eq!(m.cover_image_id.as_deref(), Some("cover.png"));
+        let series = m.series.as_ref().unwrap();
+        assert_eq!((series.name.as_str(), series.position), ("Saga", Some(3.0)));
+        assert_eq!(
+            doc.resources.get("cover.png").unwrap().data,
+            b"\x89PNG\r\n\x1a\n"
+        );
+
+        let ids: Vec<&str> = doc.content.iter().map(|c| c.id.as_str()).collect();
+        assert_eq!(ids, ["s1", "chapter-2"]);
+        assert_eq!(doc.toc[0].title, "Part One");
+        assert_eq!(doc.toc[0].children[0].href, "s1#s1a");
+
+        let first = &doc.content[0].content;
+        assert!(matches!(first[0], ContentNode::Heading { level: 1, .. }));
+        assert!(matches!(first[1], ContentNode::BlockQuote { .. }));
+        assert!(matches!(first[2], ContentNode::Heading { level: 2, .. }));
+        let ContentNode::Paragraph { children } = &first[3] else {
+            panic!("expected paragraph, got {:?}", first[3]);
+        };
+        assert!(matches!(&children[0], InlineNode::Text(t) if t == "Some "));
+        assert!(matches!(
+            &children[3],
+            InlineNode::NoteRef { id, label } if id == "n1" && label == "1"
+        ));
+        assert!(matches!(first[4], ContentNode::HorizontalRule));
+        let ContentNode::BlockQuote { children: poem } = &first[5] else {
+            panic!("expected poem");
+        };
+        assert!(
+            matches!(&poem[0], ContentNode::Paragraph { children } if matches!(children[1], InlineNode::LineBreak))
+        );
+
+        let ContentNode::Paragraph { children } = &doc.content[1].content[1] else {
+            panic!("expected paragraph");
+        };
+        assert!(children
+            .iter()
+            .any(|c| matches!(c, InlineNode::Link { href, .. } if href == "s1#s1a")));
+        let ContentNode::Note { id, kind, children } = doc.content[1].content.last().unwrap()
+        else {
+            panic!("expected note at the end of the last chapter");
+        };
+        assert_eq!((id.as_str(), *kind), ("n1", NoteKind::Footnote));
+        assert!(
+            matches!(&children[..], [ContentNode::Paragraph { children }] if inline_text(children) == "A note.")
+        );
+    }
+
+    #[test]
+    fn reads_zipped_fb2() {
+        let mut zip_bytes = Vec::new();
+        {
+            let mut zip = zip::ZipWriter::new(Cursor::new(&mut zip_bytes));
+            let options: zip::write::FileOptions<'_, ()> = zip::write::FileOptions::default();
+            zip.start_file("book.fb2", options).unwrap();
+            zip.write_all(&sample_bytes()).unwrap();
+            zip.finish().unwrap();
+        }
+        let doc = Fb2Reader::read(Cursor::new(zip_bytes), &ReadOptions::default(), None).unwrap();
+        assert_eq!(doc.metadata.language.as_deref(), Some("ru"));
+        assert_eq!(doc.content.len(), 2);
+    }
+
+    #[test]
+    fn ignores_unquoted_encoding_label() {
+        let raw = "<?xml version=\"1.0\" encoding=«utf-8»?><FictionBook/>".as_bytes();
+        assert_eq!(decode_xml(raw), String::from_utf8_lossy(raw));
+    }
+
+    #[test]
+    fn generated_chapter_ids_skip_explicit_ones() {
+        let xml = r#"<FictionBook><body>
+            <section id="chapter-2"><p>One</p></section>
+            <section><p>Two</p></section>
+        </body></FictionBook>"#;
+        let doc = Fb2Reader::read(Cursor::new(xml), &ReadOptions::default(), None).unwrap();
+        let ids: Vec<&str> = doc.content.iter().map(|c| c.id.as_str()).collect();
+        assert_eq!(ids, ["chapter-2", "chapter-3"]);
+    }
+}
@@ -0,0 +1,743 @@
+//! HTML reader: parse XHTML/HTML5 with html5ever (via scraper) → IR.
+//! Each `<h1>` starts a new chapter; local `<img>` files and data URIs become resources.
+
+use std::io::{Read, Seek};
+
+use ego_tree::NodeRef;
+use scraper::{Html, Node};
+
+use crate::detect::{DetectResult, Format};
+use crate::document::*;
+use crate::error::ReadError;
+use crate::progress::{emit_progress, ProgressHandler};
+use crate::readers::resources::ResourceLoader;
+use crate::readers::{
+    collapse_whitespace, split_into_chapters, toc_from_chapters, trim_inlines, FormatReader,
+    ReadOptions,
+};
+
+pub struct HtmlReader;
+
+impl FormatReader for HtmlReader {
+    fn detect(header: &[u8]) -> DetectResult {
+        let head = String::from_utf8_lossy(&header[..header.len().min(1024)]).to_lowercase();
+        let confidence = if head.contains("<!doctype html") || head.contains("<html") {
+            0.9
+        } else if head.contains("<body") || head.contains("<p>") {
+            0.4
+        } else {
+            0.0
+        };
+        DetectResult {
+            format: Format::Html,
+            confidence,
+            mime_type: Format::Html.mime_type(),
+        }
+    }
+
+    fn read<R: Read + Seek>(
+        mut input: R,
+        opts: &ReadOptions,
+        progress: Option<&dyn ProgressHandler>,
+    ) -> Result<Document, ReadError> {
+        let mut raw = Vec::new();
+        input.read_to_end(&mut raw)?;
+        let content = if raw.starts_with(&[0xEF, 0xBB, 0xBF]) {
+            &raw[3..]
+        } else {
+            &raw[..]
+        };
+        let text = String::from_utf8_lossy(content);
+
+        emit_progress(progress, "Reading HTML", 0, Some(3), Some("Parsing markup"));
+        let html = Html::parse_document(&text);
+
+        emit_progress(
+            progress,
+            "Reading HTML",
+            1,
+            Some(3),
+            Some("Reading metadata"),
+        );
+        let root = html.root_element();
+        let mut metadata = parse_head_metadata(&html);
+        let text_direction = match root.value().attr("dir").map(|d| d.to_lowercase()) {
+            Some(d) if d == "rtl" => TextDirection::Rtl,
+            Some(d) if d == "auto" => TextDirection::Auto,
+            _ => TextDirection::Ltr,
+        };
+
+        emit_progress(
+            progress,
+            "Reading HTML",
+            2,
+            Some(3),
+            Some("Parsing content"),
+        );
+        let mut parser = HtmlContentParser::new(opts);
+        let mut flow = Vec::new();
+        if let Some(body) = root.child_elements().find(|e| e.value().name() == "body") {
+            parser.walk_flow(*body, &mut flow, 0);
+        }
+
+        let chapters = split_into_chapters(flow);
+        if metadata.title.is_none() {
+            metadata.title = chapters.iter().find_map(|c| c.title.clone());
+        }
+
+        let toc = if opts.parse_toc {
+            toc_from_chapters(&chapters)
+        } else {
+            Vec::new()
+        };
+
+        emit_progress(progress, "Reading HTML", 3, Some(3), Some("Done"));
+
+        Ok(Document {
+            metadata,
+            toc,
+            content: chapters,
+            resources: parser.resources.into_resources(),
+            text_direction,
+            epub_version: None,
+            diagnostics: Vec::new(),
+        })
+    }
+}
+
+// --- Metadata ---
+
+fn parse_head_metadata(html: &Html) -> Metadata {
+    let mut metadata = Metadata::default();
+    let root = html.root_element();
+
+    if let Some(lang) = root
+        .value()
+        .attr("lang")
+        .or_else(|| root.value().attr("xml:lang"))
+    {
+        if !lang.trim().is_empty() {
+            metadata.language = Some(lang.trim().to_string());
+        }
+    }
+
+    let Some(head) = root.child_elements().find(|e| e.value().name() == "head") else {
+        return metadata;
+    };
+
+    for el in head.descendent_elements() {
+        match el.value().name() {
+            "title" => {
+                let title = collapse_whitespace(&el.text().collect::<String>());
+                let title = title.trim();
+                if !title.is_empty() && metadata.title.is_none() {
+                    metadata.title = Some(title.to_string());
+                }
+            }
+            "meta" => {
+                let name = el
+                    .value()
+                    .attr("name")
+                    .or_else(|| el.value().attr("property"))
+                    .map(|n| n.to_lowercase());
+                let content = el.value().attr("content").map(|c| c.trim().to_string());
+                let (Some(name), Some(content)) = (name, content) else {
+                    continue;
+                };
+                if content.is_empty() {
+                    continue;
+                }
+                match name.as_str() {
+                    "author" | "dc.creator" | "dcterms.creator" => metadata.authors.push(content),
+                    "description" | "dc.description" | "dcterms.description" => {
+                        metadata.description = Some(content)
+                    }
+                    "keywords" => metadata.subjects.extend(
+                        content
+                            .split(',')
+                            .map(|s| s.trim().to_string())
+                            .filter(|s| !s.is_empty()),
+                    ),
+                    "dc.subject" | "dcterms.subject" => metadata.subjects.push(content),
+                    "dc.title" | "dcterms.title" | "og:title" => {
+                        if metadata.title.is_none() {
+                            metadata.title = Some(content);
+                        }
+                    }
+                    "dc.language" | "dcterms.language" | "language" => {
+                        metadata.language = Some(content)
+                    }
+                    "dc.publisher" | "dcterms.publisher" | "publisher" => {
+                        metadata.publisher = Some(content)
+                    }
+                    "dc.date" | "dcterms.date" | "dcterms.issued" | "date" => {
+                        metadata.publish_date = Some(content)
+                    }
+                    "dc.rights" | "dcterms.rights" | "copyright" => metadata.rights = Some(content),
+                    "dc.identifier" | "dcterms.identifier" | "isbn" => {
+                        let digits: String = content
+                            .chars()
+                            .filter(|c| c.is_ascii_digit() || *c == 'X' || *c == 'x')
+                            .collect();
+                        if digits.len() == 13 {
+                            metadata.isbn_13 = Some(digits);
+                        } else if digits.len() == 10 {
+                            metadata.isbn_10 = Some(digits);
+                        }
+                    }
+                    "generator" | "viewport" => {}
+                    other => {
+                        metadata.custom.insert(other.to_string(), content);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    metadata
+}
+
+// --- Content ---
+
+/// Elements whose content is never rendered.
+const SKIPPED_ELEMENTS: &[&str] = &[
+    "head", "script", "style", "noscript", "template", "iframe", "object", "svg", "math",
+];
+
+/// Block-level containers that are flattened into their parent flow.
+const CONTAINER_ELEMENTS: &[&str] = &[
+    "body", "div", "section", "article", "main", "header", "footer", "nav", "aside", "address",
+    "center", "details", "summary", "form", "fieldset",
+];
+
+struct HtmlContentParser<'a> {
+    opts: &'a ReadOptions,
+    resources: ResourceLoader<'a>,
+}
+
+impl<'a> HtmlContentParser<'a> {
+    fn new(opts: &'a ReadOptions) -> Self {
+        Self {
+            opts,
+            resources: ResourceLoader::new(opts),
+        }
+    }
+
+    /// Walk block-level flow content, flattening containers into `out`.
+    /// Loose inline content between blocks is gathered into paragraphs.
+    fn walk_flow(&mut self, node: NodeRef<Node>, out: &mut Vec<ContentNode>, depth: u32) {
+        if depth > self.opts.security.max_nesting_depth {
+            tracing::warn!(
+                "Nesting depth {} exceeds limit {}, truncating",
+                depth,
+                self.opts.security.max_nesting_depth
+            );
+            return;
+        }
+
+        let mut pending: Vec<InlineNode> = Vec::new();
+        for child in node.children() {
+            match child.value() {
+                Node::Text(t) => push_text(&mut pending, &collapse_whitespace(t)),
+                Node::Element(el) => {
+                    let name = el.name();
+                    if SKIPPED_ELEMENTS.contains(&name) {
+                        continue;
+                    }
+                    if is_block_element(name) {
+                        flush_paragraph(&mut pending, out);
+                        self.walk_block(child, name, out, depth + 1);
+                    } else {
+                        let mut hoisted = Vec::new();
+                        self.walk_inline(child, &mut pending, &mut hoisted, depth + 1);
+                        if !hoisted.is_empty() {
+                            flush_paragraph(&mut pending, out);
+                            out.extend(hoisted);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        flush_paragraph(&mut pending, out);
+    }
+
+    fn walk_block(
+        &mut self,
+        node: NodeRef<Node>,
+        name: &str,
+        out: &mut Vec<ContentNode>,
+        depth: u32,
+    ) {
+        match name {
+            "p" => {
+                let mut children = Vec::new();
+                let mut hoisted = Vec::new();
+                self.walk_inline_children(node, &mut children, &mut hoisted, depth);
+                trim_inlines(&mut children);
+                if !children.is_empty() {
+                    out.push(ContentNode::Paragraph { children });
+                }
+                out.extend(hoisted);
+            }
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let level = name.as_bytes()[1] - b'0';
+                let mut children = Vec::new();
+                let mut hoisted = Vec::new();
+                self.walk_inline_children(node, &mut children, &mut hoisted, depth);
+                trim_inlines(&mut children);
+                if !children.is_empty() {
+                    out.push(ContentNode::Heading { level, children });
+                }
+                out.extend(hoisted);
+            }
+            "ul" | "ol" | "menu" => {
+                let mut items = Vec::new();
+                for li in node.children() {
+                    let Some(el) = li.value().as_element() else {
+                        continue;
+                    };
+                    if el.name() != "li" {
+                        continue;
+                    }
+                    let mut item = Vec::new();
+                    self.walk_flow(li, &mut item, depth + 1);
+                    items.push(item);
+                }
+                if !items.is_empty() {
+                    out.push(ContentNode::List {
+                        ordered: name == "ol",
+                        items,
+                    });
+                }
+            }
+            "dl" => {
+                for entry in node.children() {
+                    let Some(el) = entry.value().as_element() else {
+                        continue;
+                    };
+                    let mut children = Vec::new();
+                    let mut hoisted = Vec::new();
+                    self.walk_inline_children(entry, &mut children, &mut hoisted, depth);
+                    trim_inlines(&mut children);
+                    if children.is_empty() {
+                        continue;
+                    }
+                    match el.name() {
+                        "dt" => out.push(ContentNode::Paragraph {
+                            children: vec![InlineNode::Strong(children)],
+                        }),
+                        _ => out.push(ContentNode::Paragraph { children }),
+                    }
+                    out.extend(hoisted);
+                }
+            }
+            "blockquote" => {
+                let mut children = Vec::new();
+                self.walk_flow(node, &mut children, depth);
+                if !children.is_empty() {
+                    out.push(ContentNode::BlockQuote { children });
+                }
+            }
+            "pre" => {
+                let code = node_text(node);
+                let language = node
+                    .children()
+                    .filter_map(|c| c.value().as_element())
+                    .find(|e| e.name() == "code")
+                    .and_then(code_language);
+                let code = code.strip_prefix('\n').unwrap_or(&code).to_string();
+                out.push(ContentNode::CodeBlock { language, code });
+            }
+            "table" => {
+                if let Some(table) = self.parse_table(node, depth) {
+                    out.push(table);
+                }
+            }
+            "hr" => out.push(ContentNode::HorizontalRule),
+            "img" => {
+                if let Some(image) = self.image_node(node, None) {
+                    out.push(image);
+                }
+            }
+            "figure" => self.walk_figure(node, out, depth),
+            _ => self.walk_flow(node, out, depth),
+        }
+    }
+
+    fn walk_figure(&mut self, node: NodeRef<Node>, out: &mut Vec<ContentNode>, depth: u32) {
+        let caption = node
+            .children()
+            .find(|c| {
+                c.value()
+                    .as_element()
+                    .is_some_and(|e| e.name() == "figcaption")
+            })
+            .map(|c| collapse_whitespace(&node_text(c)).trim().to_string())
+            .filter(|c| !c.is_empty());
+
+        let images: Vec<NodeRef<Node>> = node
+            .descendants()
+            .filter(|d| d.value().as_element().is_some_and(|e| e.name() == "img"))
+            .collect();
+        if images.is_empty() {
+            self.walk_flow(node, out, depth);
+            return;
+        }
+        let last = images.len() - 1;
+        for (i, img) in images.into_iter().enumerate() {
+            let caption = if i == last { caption.clone() } else { None };
+            if let Some(image) = self.image_node(img, caption) {
+                out.push(image);
+            }
+        }
+    }
+
+    fn parse_table(&mut self, node: NodeRef<Node>, depth: u32) -> Option<ContentNode> {
+        let mut headers: Vec<Vec<InlineNode>> = Vec::new();
+        let mut rows: Vec<Vec<Vec<InlineNode>>> = Vec::new();
+
+        let mut tr_nodes = Vec::new();
+        for child in node.children() {
+            let Some(el) = child.value().as_element() else {
+                continue;
+            };
+            match el.name() {
+                "tr" => tr_nodes.push((child, false)),
+                "thead" | "tbody" | "tfoot" => {
+                    let in_head = el.name() == "thead";
+                    for tr in child.children() {
+                        if tr.value().as_element().is_some_and(|e| e.name() == "tr") {
+                            tr_nodes.push((tr, in_head));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        for (tr, in_head) in tr_nodes {
+            let mut cells = Vec::new();
+            let mut all_th = true;
+            for cell in tr.children() {
+                let Some(el) = cell.value().as_element() else {
+                    continue;
+                };
+                if el.name() != "td" && el.name() != "th" {
+                    continue;
+                }
+                all_th &= el.name() == "th";
+                let mut inlines = Vec::new();
+                let mut hoisted = Vec::new();
+                self.walk_inline_children(cell, &mut inlines, &mut hoisted, depth);
+                trim_inlines(&mut inlines);
+                cells.push(inlines);
+            }
+            if cells.is_empty() {
+                continue;
+            }
+            if headers.is_empty() && rows.is_empty() && (in_head || all_th) {
+                headers = cells;
+            } else {
+                rows.push(cells);
+            }
+        }
+
+        if headers.is_empty() && rows.is_empty() {
+            None
+        } else {
+            Some(ContentNode::Table { headers, rows })
+        }
+    }
+
+    fn walk_inline_children(
+        &mut self,
+        node: NodeRef<Node>,
+        out: &mut Vec<InlineNode>,
+        hoisted: &mut Vec<ContentNode>,
+        depth: u32,
+    ) {
+        for child in node.children() {
+            self.walk_inline(child, out, hoisted, depth + 1);
+        }
+    }
+
+    /// Walk phrasing content. Images cannot be inline in the IR, so they are
+    /// hoisted into `hoisted` and emitted after the enclosing block.
+    fn walk_inline(
+        &mut self,
+        node: NodeRef<Node>,
+        out: &mut Vec<InlineNode>,
+        hoisted: &mut Vec<ContentNode>,
+        depth: u32,
+    ) {
+        if depth > self.opts.security.max_nesting_depth {
+            return;
+        }
+        let el = match node.value() {
+            Node::Text(t) => {
+                push_text(out, &collapse_whitespace(t));
+                return;
+            }
+            Node::Element(el) => el,
+            _ => return,
+        };
+
+        match el.name() {
+            name if SKIPPED_ELEMENTS.contains(&name) => {}
+            "em" | "i" | "cite" | "dfn" | "var" => {
+                let mut children = Vec::new();
+                self.walk_inline_children(node, &mut children, hoisted, depth);
+                if !children.is_empty() {
+                    out.push(InlineNode::Emphasis(children));
+                }
+            }
+            "strong" | "b" => {
+                let mut children = Vec::new();
+                self.walk_inline_children(node, &mut children, hoisted, depth);
+                if !children.is_empty() {
+                    out.push(InlineNode::Strong(children));
+                }
+            }
+            "code" | "kbd" | "samp" | "tt" => {
+                let text = collapse_whitespace(&node_text(node));
+                if !text.is_empty() {
+                    out.push(InlineNode::Code(text));
+                }
+            }
+            "a" => {
+                let mut children = Vec::new();
+                self.walk_inline_children(node, &mut children, hoisted, depth);
+                match el.attr("href") {
+                    Some(href) if !children.is_empty() => out.push(InlineNode::Link {
+                        href: href.to_string(),
+                        children,
+                    }),
+                    _ => out.extend(children),
+                }
+            }
+            "sup" => {
+                let mut children = Vec::new();
+                self.walk_inline_children(node, &mut children, hoisted, depth);
+                if !children.is_empty() {
+                    out.push(InlineNode::Superscript(children));
+                }
+            }
+            "sub" => {
+                let mut children = Vec::new();
+                self.walk_inline_children(node, &mut children, hoisted, depth);
+                if !children.is_empty() {
+                    out.push(InlineNode::Subscript(children));
+                }
+            }
+            "ruby" => {
+                let mut base = String::new();
+                let mut annotation = String::new();
+                for child in node.children() {
+                    match child.value() {
+                        Node::Text(t) => base.push_str(t),
+                        Node::Element(e) if e.name() == "rt" => {
+                            annotation.push_str(&node_text(child))
+                        }
+                        Node::Element(e) if e.name() == "rp" => {}
+                        Node::Element(_) => base.push_str(&node_text(child)),
+                        _ => {}
+                    }
+                }
+                out.push(InlineNode::Ruby {
+                    base: base.trim().to_string(),
+                    annotation: annotation.trim().to_string(),
+                });
+            }
+            "br" => out.push(InlineNode::LineBreak),
+            "img" => {
+                if let Some(image) = self.image_node(node, None) {
+                    hoisted.push(image);
+                }
+            }
+            _ => self.walk_inline_children(node, out, hoisted, depth),
+        }
+    }
+
+    fn image_node(&mut self, node: NodeRef<Node>, caption: Option<String>) -> Option<ContentNode> {
+        let el = node.value().as_element()?;
+        let src = el.attr("src")?.trim();
+        if src.is_empty() {
+            return None;
+        }
+        let resource_id = self.resources.load(src).unwrap_or_else(|| src.to_string());
+        Some(ContentNode::Image {
+            resource_id,
+            alt_text: el.attr("alt").map(|a| a.to_string()),
+            caption,
+        })
+    }
+}
+
+// --- Helpers ---
+
+fn is_block_element(name: &str) -> bool {
+    CONTAINER_ELEMENTS.contains(&name)
+        || matches!(
+            name,
+            "p" | "h1"
+                | "h2"
+                | "h3"
+                | "h4"
+                | "h5"
+                | "h6"
+                | "ul"
+                | "ol"
+                | "menu"
+                | "dl"
+                | "blockquote"
+                | "pre"
+                | "table"
+                | "hr"
+                | "figure"
+                | "li"
+        )
+}
+
+fn code_language(code: &scraper::node::Element) -> Option<String> {
+    code.classes().find_map(|c| {
+        c.strip_prefix("language-")
+            .or_else(|| c.strip_prefix("lang-"))
+            .map(|l| l.to_string())
+    })
+}
+
+fn node_text(node: NodeRef<Node>) -> String {
+    node.descendants()
+        .filter_map(|d| d.value().as_text().map(|t| t.to_string()))
+        .collect()
+}
+
+/// Append text, merging with a preceding text node and avoiding doubled spaces.
+fn push_text(out: &mut Vec<InlineNode>, text: &str) {
+    if text.is_empty() {
+        return;
+    }
+    if let Some(InlineNode::Text(prev)) = out.last_mut() {
+        if prev.ends_with(' ') && text.starts_with(' ') {
+            prev.push_str(&text[1..]);
+        } else {
+            prev.push_str(text);
+        }
+        return;
+    }
+    if text == " " && out.is_empty() {
+        return;
+    }
+    out.push(InlineNode::Text(text.to_string()));
+}
+
+fn flush_paragraph(pending: &mut Vec<InlineNode>, out: &mut Vec<ContentNode>) {
+    trim_inlines(pending);
+    if !pending.is_empty() {
+        out.push(ContentNode::Paragraph {
+            children: std::mem::take(pending),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn read_html(html: &str) -> Document {
+        HtmlReader::read(Cursor::new(html.as_bytes()), &ReadOptions::default(), None).unwrap()
+    }
+
+    #[test]
+    fn test_metadata_from_head() {
+        let doc = read_html(
+            r#"<!DOCTYPE html><html lang="fr" dir="rtl"><head>
+            <title>My Book</title>
+            <meta name="author" content="Jane Doe">
+            <meta name="keywords" content="fiction, mystery">
+            </head><body><p>Hi</p></body></html>"#,
+        );
+        assert_eq!(doc.metadata.title.as_deref(), Some("My Book"));
+        assert_eq!(doc.metadata.authors, vec!["Jane Doe"]);
+        assert_eq!(doc.metadata.language.as_deref(), Some("fr"));
+        assert_eq!(doc.metadata.subjects, vec!["fiction", "mystery"]);
+        assert_eq!(doc.text_direction, TextDirection::Rtl);
+    }
+
+    #[test]
+    fn test_h1_starts_chapters() {
+        let doc = read_html(
+            "<html><body><p>Preface</p><h1>One</h1><p>a</p>\
+             <section><h1>Two</h1><p>b <em>c</em></p></section></body></html>",
+        );
+        assert_eq!(doc.content.len(), 3);
+        assert_eq!(doc.content[1].title.as_deref(), Some("One"));
+        assert_eq!(doc.content[2].title.as_deref(), Some("Two"));
+        assert_eq!(doc.toc.len(), 2);
+        assert!(matches!(
+            &doc.content[2].content[1],
+            ContentNode::Paragraph { children } if children.len() == 2
+        ));
+    }
+
+    #[test]
+    fn test_lists_tables_and_code() {
+        let doc = read_html(
+            "<body><ul><li>one</li><li>two<ol><li>x</li></ol></li></ul>\
+             <table><tr><th>A</th><th>B</th></tr><tr><td>1</td><td>2</td></tr></table>\
+             <pre><code class=\"language-rust\">fn main() {}</code></pre></body>",
+        );
+        let nodes = &doc.content[0].content;
+        assert!(
+            matches!(&nodes[0], ContentNode::List { ordered: false, items } if items.len() == 2)
+        );
+        assert!(
+            matches!(&nodes[1], ContentNode::Table { headers, rows } if headers.len() == 2 && rows.len() == 1)
+        );
+        assert!(matches!(
+            &nodes[2],
+            ContentNode::CodeBlock { language: Some(l), code } if l == "rust" && code == "fn main() {}"
+        ));
+    }
+
+    #[test]
+    fn test_data_uri_image_becomes_resource() {
+        let doc = read_html(
+            r#"<body><p>See <img src="data:image/png;base64,iVBORw0KGgo=" alt="dot"></p></body>"#,
+        );
+        assert_eq!(doc.resources.len(), 1);
+        let (id, res) = doc.resources.iter().next().unwrap();
+        assert_eq!(res.media_type, "image/png");
+        assert!(matches!(
+            &doc.content[0].content[1],
+            ContentNode::Image { resource_id, .. } if resource_id == id
+        ));
+    }
+
+    #[test]
+    fn test_local_image_resolved_against_base_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("images")).unwrap();
+        std::fs::write(dir.path().join("images/pic one.png"), b"\x89PNG\r\n\x1a\n").unwrap();
+        let path = dir.path().join("book.html");
+        std::fs::write(
+            &path,
+            r#"<body><p><img src="images/pic%20one.png" alt="A picture"></p></body>"#,
+        )
+        .unwrap();
+
+        let opts = crate::config::read_options_for_path(&Default::default(), &path);
+        let file = std::fs::File::open(&path).unwrap();
+        let doc = HtmlReader::read(file, &opts, None).unwrap();
+        let res = doc.resources.get("pic_one.png").unwrap();
+        assert_eq!(res.media_type, "image/png");
+        assert_eq!(res.data, b"\x89PNG\r\n\x1a\n");
+        assert!(matches!(
+            &doc.content[0].content[0],
+            ContentNode::Image { resource_id, .. } if resource_id == "pic_one.png"
+        ));
+    }
+}
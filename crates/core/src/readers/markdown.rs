@@ -0,0 +1,801 @@
+//! Markdown reader: CommonMark + GFM (tables, footnotes, strikethrough, task lists)
+//! via pulldown-cmark → IR. A leading YAML (`---`) or TOML (`+++`) front-matter
+//! block is read into `Metadata`.
+
+use std::collections::HashMap;
+use std::io::{Read, Seek};
+
+use pulldown_cmark::{CodeBlockKind, Event, MetadataBlockKind, Options, Parser, Tag, TagEnd};
+
+use crate::detect::{DetectResult, Format};
+use crate::document::*;
+use crate::error::ReadError;
+use crate::progress::{emit_progress, ProgressHandler};
+use crate::readers::resources::ResourceLoader;
+use crate::readers::{
+    split_into_chapters, toc_from_chapters, trim_inlines, FormatReader, ReadOptions,
+};
+
+pub struct MarkdownReader;
+
+impl FormatReader for MarkdownReader {
+    fn detect(header: &[u8]) -> DetectResult {
+        let text = String::from_utf8_lossy(header);
+        let markers = text
+            .lines()
+            .filter(|l| {
+                let l = l.trim_start();
+                l.starts_with("# ")
+                    || l.starts_with("## ")
+                    || l.starts_with("```")
+                    || l.starts_with("- ")
+                    || l.starts_with("* ")
+                    || l.starts_with("> ")
+                    || l.contains("](")
+            })
+            .count();
+        let confidence = match markers {
+            0 => 0.1,
+            1..=2 => 0.4,
+            _ => 0.7,
+        };
+        DetectResult {
+            format: Format::Markdown,
+            confidence,
+            mime_type: Format::Markdown.mime_type(),
+        }
+    }
+
+    fn read<R: Read + Seek>(
+        mut input: R,
+        opts: &ReadOptions,
+        progress: Option<&dyn ProgressHandler>,
+    ) -> Result<Document, ReadError> {
+        let mut raw = Vec::new();
+        input.read_to_end(&mut raw)?;
+        let content = if raw.starts_with(&[0xEF, 0xBB, 0xBF]) {
+            &raw[3..]
+        } else {
+            &raw[..]
+        };
+        let text = String::from_utf8_lossy(content);
+
+        emit_progress(progress, "Reading Markdown", 0, Some(2), Some("Parsing"));
+
+        let mut builder = MarkdownBuilder::new(opts);
+        for event in Parser::new_ext(&text, markdown_options()) {
+            builder.handle(event);
+        }
+        let (flow, front_matter, mut resources) = builder.finish();
+
+        emit_progress(
+            progress,
+            "Reading Markdown",
+            1,
+            Some(2),
+            Some("Reading front matter"),
+        );
+
+        let mut metadata = Metadata::default();
+        let mut text_direction = TextDirection::Ltr;
+        if let Some((kind, source)) = front_matter {
+            match parse_front_matter(kind, &source) {
+                Ok(value) => {
+                    apply_front_matter(&value, &mut metadata, &mut text_direction, &mut resources)
+                }
+                Err(e) => tracing::warn!("Ignoring malformed front matter: {}", e),
+            }
+        }
+
+        let chapters = split_into_chapters(flow);
+        if metadata.title.is_none() {
+            metadata.title = chapters.iter().find_map(|c| c.title.clone());
+        }
+        let toc = if opts.parse_toc {
+            toc_from_chapters(&chapters)
+        } else {
+            Vec::new()
+        };
+
+        emit_progress(progress, "Reading Markdown", 2, Some(2), Some("Done"));
+
+        Ok(Document {
+            metadata,
+            toc,
+            content: chapters,
+            resources: resources.into_resources(),
+            text_direction,
+            epub_version: None,
+            diagnostics: Vec::new(),
+        })
+    }
+}
+
+fn markdown_options() -> Options {
+    Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_YAML_STYLE_METADATA_BLOCKS
+        | Options::ENABLE_PLUSES_DELIMITED_METADATA_BLOCKS
+}
+
+// --- Event → IR ---
+
+enum BlockKind {
+    Root,
+    BlockQuote,
+    List { ordered: bool },
+    Item,
+    Footnote(String),
+}
+
+struct BlockFrame {
+    kind: BlockKind,
+    children: Vec<ContentNode>,
+    items: Vec<Vec<ContentNode>>,
+}
+
+enum InlineKind {
+    Paragraph { implicit: bool },
+    Heading(u8),
+    Emphasis,
+    Strong,
+    Strikethrough,
+    Link(String),
+    Image { src: String, title: String },
+    TableCell,
+}
+
+struct InlineFrame {
+    kind: InlineKind,
+    children: Vec<InlineNode>,
+}
+
+#[derive(Default)]
+struct TableState {
+    headers: Vec<Vec<InlineNode>>,
+    rows: Vec<Vec<Vec<InlineNode>>>,
+    row: Vec<Vec<InlineNode>>,
+}
+
+struct MarkdownBuilder<'a> {
+    resources: ResourceLoader<'a>,
+    blocks: Vec<BlockFrame>,
+    inlines: Vec<InlineFrame>,
+    /// Images met inside the current inline block, emitted after it.
+    hoisted: Vec<ContentNode>,
+    code: Option<(Option<String>, String)>,
+    raw_html: Option<String>,
+    table: Option<TableState>,
+    front_matter: Option<(MetadataBlockKind, String)>,
+    in_front_matter: bool,
+    footnotes: Vec<(String, Vec<ContentNode>)>,
+    footnote_numbers: HashMap<String, usize>,
+}
+
+impl<'a> MarkdownBuilder<'a> {
+    fn new(opts: &'a ReadOptions) -> Self {
+        Self {
+            resources: ResourceLoader::new(opts),
+            blocks: vec![BlockFrame::new(BlockKind::Root)],
+            inlines: Vec::new(),
+            hoisted: Vec::new(),
+            code: None,
+            raw_html: None,
+            table: None,
+            front_matter: None,
+            in_front_matter: false,
+            footnotes: Vec::new(),
+            footnote_numbers: HashMap::new(),
+        }
+    }
+
+    fn handle(&mut self, event: Event) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) => {
+                if self.in_front_matter {
+                    if let Some((_, source)) = self.front_matter.as_mut() {
+                        source.push_str(&text);
+                    }
+                } else if let Some((_, code)) = self.code.as_mut() {
+                    code.push_str(&text);
+                } else {
+                    self.push_inline(InlineNode::Text(text.into_string()));
+                }
+            }
+            Event::Code(code) => self.push_inline(InlineNode::Code(code.into_string())),
+            Event::InlineMath(math) | Event::DisplayMath(math) => {
+                self.push_inline(InlineNode::Code(math.into_string()))
+            }
+            Event::Html(html) => {
+                if let Some(raw) = self.raw_html.as_mut() {
+                    raw.push_str(&html);
+                } else {
+                    self.close_implicit_paragraph();
+                    self.push_block(ContentNode::RawHtml(html.into_string()));
+                }
+            }
+            Event::InlineHtml(html) => {
+                let tag = html.trim().to_ascii_lowercase();
+                if matches!(tag.as_str(), "<br>" | "<br/>" | "<br />") {
+                    self.push_inline(InlineNode::LineBreak);
+                }
+            }
+            Event::FootnoteReference(label) => {
+                let next = self.footnote_numbers.len() + 1;
+                let number = *self
+                    .footnote_numbers
+                    .entry(label.to_string())
+                    .or_insert(next);
+                self.push_inline(InlineNode::NoteRef {
+                    id: note_id(&label),
+                    label: number.to_string(),
+                });
+            }
+            Event::SoftBreak => self.push_inline(InlineNode::Text(" ".to_string())),
+            Event::HardBreak => self.push_inline(InlineNode::LineBreak),
+            Event::Rule => {
+                self.close_implicit_paragraph();
+                self.push_block(ContentNode::HorizontalRule);
+            }
+            Event::TaskListMarker(checked) => self.push_inline(InlineNode::Text(
+                if checked { "[x] " } else { "[ ] " }.to_string(),
+            )),
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph => {
+                self.close_implicit_paragraph();
+                self.open_inline(InlineKind::Paragraph { implicit: false });
+            }
+            Tag::Heading { level, .. } => {
+                self.close_implicit_paragraph();
+                self.open_inline(InlineKind::Heading(level as u8));
+            }
+            Tag::BlockQuote(_) => {
+                self.close_implicit_paragraph();
+                self.blocks.push(BlockFrame::new(BlockKind::BlockQuote));
+            }
+            Tag::CodeBlock(kind) => {
+                self.close_implicit_paragraph();
+                let language = match kind {
+                    CodeBlockKind::Fenced(info) => info
+                        .split_whitespace()
+                        .next()
+                        .map(|l| l.trim_matches(|c| c == '{' || c == '}' || c == '.'))
+                        .filter(|l| !l.is_empty())
+                        .map(|l| l.to_string()),
+                    CodeBlockKind::Indented => None,
+                };
+                self.code = Some((language, String::new()));
+            }
+            Tag::HtmlBlock => {
+                self.close_implicit_paragraph();
+                self.raw_html = Some(String::new());
+            }
+            Tag::List(start) => {
+                self.close_implicit_paragraph();
+                self.blocks.push(BlockFrame::new(BlockKind::List {
+                    ordered: start.is_some(),
+                }));
+            }
+            Tag::Item => self.blocks.push(BlockFrame::new(BlockKind::Item)),
+            Tag::FootnoteDefinition(label) => {
+                self.close_implicit_paragraph();
+                self.blocks
+                    .push(BlockFrame::new(BlockKind::Footnote(label.to_string())));
+            }
+            Tag::Table(_) => {
+                self.close_implicit_paragraph();
+                self.table = Some(TableState::default());
+            }
+            Tag::TableHead | Tag::TableRow => {
+                if let Some(table) = self.table.as_mut() {
+                    table.row.clear();
+                }
+            }
+            Tag::TableCell => self.open_inline(InlineKind::TableCell),
+            Tag::Emphasis => self.open_inline(InlineKind::Emphasis),
+            Tag::Strong => self.open_inline(InlineKind::Strong),
+            Tag::Strikethrough => self.open_inline(InlineKind::Strikethrough),
+            Tag::Link { dest_url, .. } => {
+                self.open_inline(InlineKind::Link(dest_url.into_string()))
+            }
+            Tag::Image {
+                dest_url, title, ..
+            } => self.open_inline(InlineKind::Image {
+                src: dest_url.into_string(),
+                title: title.into_string(),
+            }),
+            Tag::MetadataBlock(kind) => {
+                self.in_front_matter = true;
+                self.front_matter = Some((kind, String::new()));
+            }
+            Tag::DefinitionList | Tag::DefinitionListTitle | Tag::DefinitionListDefinition => {}
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Paragraph => {
+                if let Some(frame) = self.inlines.pop() {
+                    self.emit_paragraph(frame.children);
+                }
+            }
+            TagEnd::Heading(_) => {
+                if let Some(InlineFrame {
+                    kind: InlineKind::Heading(level),
+                    mut children,
+                }) = self.inlines.pop()
+                {
+                    trim_inlines(&mut children);
+                    if !children.is_empty() {
+                        self.push_block(ContentNode::Heading { level, children });
+                    }
+                    self.flush_hoisted();
+                }
+            }
+            TagEnd::BlockQuote(_) => {
+                self.close_implicit_paragraph();
+                if let Some(frame) = self.blocks.pop() {
+                    self.push_block(ContentNode::BlockQuote {
+                        children: frame.children,
+                    });
+                }
+            }
+            TagEnd::CodeBlock => {
+                if let Some((language, mut code)) = self.code.take() {
+                    if code.ends_with('\n') {
+                        code.pop();
+                    }
+                    self.push_block(ContentNode::CodeBlock { language, code });
+                }
+            }
+            TagEnd::HtmlBlock => {
+                if let Some(raw) = self.raw_html.take() {
+                    self.push_block(ContentNode::RawHtml(raw));
+                }
+            }
+            TagEnd::List(_) => {
+                self.close_implicit_paragraph();
+                if let Some(BlockFrame {
+                    kind: BlockKind::List { ordered },
+                    items,
+                    ..
+                }) = self.blocks.pop()
+                {
+                    self.push_block(ContentNode::List { ordered, items });
+                }
+            }
+            TagEnd::Item => {
+                self.close_implicit_paragraph();
+                if let Some(item) = self.blocks.pop() {
+                    if let Some(list) = self.blocks.last_mut() {
+                        list.items.push(item.children);
+                    }
+                }
+            }
+            TagEnd::FootnoteDefinition => {
+                self.close_implicit_paragraph();
+                if let Some(BlockFrame {
+                    kind: BlockKind::Footnote(label),
+                    children,
+                    ..
+                }) = self.blocks.pop()
+                {
+                    self.footnotes.push((label, children));
+                }
+            }
+            TagEnd::Table => {
+                if let Some(table) = self.table.take() {
+                    self.push_block(ContentNode::Table {
+                        headers: table.headers,
+                        rows: table.rows,
+                    });
+                }
+            }
+            TagEnd::TableHead => {
+                if let Some(table) = self.table.as_mut() {
+                    table.headers = std::mem::take(&mut table.row);
+                }
+            }
+            TagEnd::TableRow => {
+                if let Some(table) = self.table.as_mut() {
+                    let row = std::mem::take(&mut table.row);
+                    table.rows.push(row);
+                }
+            }
+            TagEnd::TableCell => {
+                if let Some(mut frame) = self.inlines.pop() {
+                    trim_inlines(&mut frame.children);
+                    if let Some(table) = self.table.as_mut() {
+                        table.row.push(frame.children);
+                    }
+                }
+            }
+            TagEnd::Emphasis | TagEnd::Strong | TagEnd::Strikethrough | TagEnd::Link => {
+                if let Some(frame) = self.inlines.pop() {
+                    let node = match frame.kind {
+                        InlineKind::Emphasis => Some(InlineNode::Emphasis(frame.children)),
+                        InlineKind::Strong => Some(InlineNode::Strong(frame.children)),
+                        InlineKind::Link(href) => Some(InlineNode::Link {
+                            href,
+                            children: frame.children,
+                        }),
+                        _ => {
+                            for child in frame.children {
+                                self.push_inline(child);
+                            }
+                            None
+                        }
+                    };
+                    if let Some(node) = node {
+                        self.push_inline(node);
+                    }
+                }
+            }
+            TagEnd::Image => {
+                if let Some(InlineFrame {
+                    kind: InlineKind::Image { src, title },
+                    children,
+                }) = self.inlines.pop()
+                {
+                    let alt = crate::readers::inline_text(&children);
+                    let resource_id = self.resources.load(&src).unwrap_or_else(|| src.clone());
+                    self.hoisted.push(ContentNode::Image {
+                        resource_id,
+                        alt_text: Some(alt).filter(|a| !a.is_empty()),
+                        caption: Some(title).filter(|t| !t.is_empty()),
+                    });
+                }
+            }
+            TagEnd::MetadataBlock(_) => self.in_front_matter = false,
+            TagEnd::DefinitionList
+            | TagEnd::DefinitionListTitle
+            | TagEnd::DefinitionListDefinition => {}
+        }
+    }
+
+    fn open_inline(&mut self, kind: InlineKind) {
+        self.inlines.push(InlineFrame {
+            kind,
+            children: Vec::new(),
+        });
+    }
+
+    /// Push inline content, opening an implicit paragraph for tight list items.
+    fn push_inline(&mut self, node: InlineNode) {
+        if self.inlines.is_empty() {
+            self.open_inline(InlineKind::Paragraph { implicit: true });
+        }
+        let Some(frame) = self.inlines.last_mut() else {
+            return;
+        };
+        match (frame.children.last_mut(), node) {
+            (Some(InlineNode::Text(prev)), InlineNode::Text(text)) => prev.push_str(&text),
+            (_, node) => frame.children.push(node),
+        }
+    }
+
+    fn close_implicit_paragraph(&mut self) {
+        if matches!(
+            self.inlines.last(),
+            Some(InlineFrame {
+                kind: InlineKind::Paragraph { implicit: true },
+                ..
+            })
+        ) {
+            if let Some(frame) = self.inlines.pop() {
+                self.emit_paragraph(frame.children);
+            }
+        }
+    }
+
+    fn emit_paragraph(&mut self, mut children: Vec<InlineNode>) {
+        trim_inlines(&mut children);
+        if !children.is_empty() {
+            self.push_block(ContentNode::Paragraph { children });
+        }
+        self.flush_hoisted();
+    }
+
+    fn flush_hoisted(&mut self) {
+        for image in std::mem::take(&mut self.hoisted) {
+            self.push_block(image);
+        }
+    }
+
+    fn push_block(&mut self, node: ContentNode) {
+        if let Some(frame) = self.blocks.last_mut() {
+            frame.children.push(node);
+        }
+    }
+
+    /// Finish parsing: returns the root flow (with footnote bodies appended as
+    /// notes in reference order), the raw front matter, and the loaded resources.
+    fn finish(
+        mut self,
+    ) -> (
+        Vec<ContentNode>,
+        Option<(MetadataBlockKind, String)>,
+        ResourceLoader<'a>,
+    ) {
+        self.close_implicit_paragraph();
+        let mut flow = self
+            .blocks
+            .drain(..)
+            .next()
+            .map(|root| root.children)
+            .unwrap_or_default();
+
+        if !self.footnotes.is_empty() {
+            let numbers = &self.footnote_numbers;
+            self.footnotes
+                .sort_by_key(|(label, _)| numbers.get(label).copied().unwrap_or(usize::MAX));
+            flow.extend(
+                self.footnotes
+                    .into_iter()
+                    .map(|(label, children)| ContentNode::Note {
+                        id: note_id(&label),
+                        kind: NoteKind::Footnote,
+                        children,
+                    }),
+            );
+        }
+
+        (flow, self.front_matter, self.resources)
+    }
+}
+
+impl BlockFrame {
+    fn new(kind: BlockKind) -> Self {
+        Self {
+            kind,
+            children: Vec::new(),
+            items: Vec::new(),
+        }
+    }
+}
+
+/// Note id for a footnote label; the prefix keeps it apart from heading anchors.
+fn note_id(label: &str) -> String {
+    format!("fn-{}", label)
+}
+
+// --- Front matter ---
+
+fn parse_front_matter(kind: MetadataBlockKind, source: &str) -> Result<serde_json::Value, String> {
+    match kind {
+        MetadataBlockKind::YamlStyle => {
+            serde_yaml::from_str::<serde_json::Value>(source).map_err(|e| e.to_string())
+        }
+        MetadataBlockKind::PlusesStyle => toml::from_str::<toml::Table>(source)
+            .map(|t| toml_to_json(toml::Value::Table(t)))
+            .map_err(|e| e.to_string()),
+    }
+}
+
+fn toml_to_json(value: toml::Value) -> serde_json::Value {
+    match value {
+        toml::Value::String(s) => serde_json::Value::String(s),
+        toml::Value::Integer(i) => serde_json::Value::from(i),
+        toml::Value::Float(f) => serde_json::Value::from(f),
+        toml::Value::Boolean(b) => serde_json::Value::Bool(b),
+        toml::Value::Datetime(d) => serde_json::Value::String(d.to_string()),
+        toml::Value::Array(a) => {
+            serde_json::Value::Array(a.into_iter().map(toml_to_json).collect())
+        }
+        toml::Value::Table(t) => {
+            serde_json::Value::Object(t.into_iter().map(|(k, v)| (k, toml_to_json(v))).collect())
+        }
+    }
+}
+
+fn apply_front_matter(
+    value: &serde_json::Value,
+    metadata: &mut Metadata,
+    text_direction: &mut TextDirection,
+    resources: &mut ResourceLoader,
+) {
+    let Some(map) = value.as_object() else {
+        return;
+    };
+    let mut series_position = None;
+
+    for (key, value) in map {
+        let key = key.to_lowercase().replace('-', "_");
+        match key.as_str() {
+            "title" => metadata.title = scalar_string(value),
+            "subtitle" => metadata.subtitle = scalar_string(value),
+            "author" | "authors" | "creator" | "creators" => {
+                metadata.authors.extend(string_list(value, false))
+            }
+            "lang" | "language" => metadata.language = scalar_string(value),
+            "publisher" => metadata.publisher = scalar_string(value),
+            "date" | "publish_date" | "published" => metadata.publish_date = scalar_string(value),
+            "description" | "summary" | "abstract" => metadata.description = scalar_string(value),
+            "subjects" | "subject" | "tags" | "keywords" | "categories" => {
+                metadata.subjects.extend(string_list(value, true))
+            }
+            "series" => {
+                metadata.series = match value {
+                    serde_json::Value::Object(s) => s
+                        .get("name")
+                        .or_else(|| s.get("title"))
+                        .and_then(scalar_string)
+                        .map(|name| SeriesInfo {
+                            name,
+                            position: ["position", "index", "number"]
+                                .iter()
+                                .find_map(|k| s.get(*k).and_then(number_value)),
+                        }),
+                    other => scalar_string(other).map(|name| SeriesInfo {
+                        name,
+                        position: None,
+                    }),
+                };
+            }
+            "series_index" | "series_position" | "series_number" => {
+                series_position = number_value(value)
+            }
+            "isbn" | "isbn_13" | "isbn_10" | "identifier" => {
+                if let Some(raw) = scalar_string(value) {
+                    let digits: String = raw
+                        .chars()
+                        .filter(|c| c.is_ascii_digit() || *c == 'X' || *c == 'x')
+                        .collect();
+                    match digits.len() {
+                        13 => metadata.isbn_13 = Some(digits),
+                        10 => metadata.isbn_10 = Some(digits),
+                        _ => {
+                            metadata.custom.insert(key.clone(), raw);
+                        }
+                    }
+                }
+            }
+            "rights" | "copyright" | "license" => metadata.rights = scalar_string(value),
+            "cover" | "cover_image" => {
+                if let Some(id) = scalar_string(value).and_then(|src| resources.load(&src)) {
+                    metadata.cover_image_id = Some(id);
+                }
+            }
+            "dir" | "direction" => {
+                *text_direction = match scalar_string(value).as_deref() {
+                    Some("rtl") => TextDirection::Rtl,
+                    Some("auto") => TextDirection::Auto,
+                    _ => TextDirection::Ltr,
+                };
+            }
+            _ => {
+                if let Some(s) = scalar_string(value) {
+                    metadata.custom.insert(key, s);
+                }
+            }
+        }
+    }
+
+    if let (Some(series), Some(pos)) = (metadata.series.as_mut(), series_position) {
+        series.position = Some(pos);
+    }
+}
+
+fn scalar_string(value: &serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::String(s) => Some(s.trim().to_string()).filter(|s| !s.is_empty()),
+        serde_json::Value::Number(n) => Some(n.to_string()),
+        serde_json::Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+fn number_value(value: &serde_json::Value) -> Option<f32> {
+    match value {
+        serde_json::Value::Number(n) => n.as_f64().map(|f| f as f32),
+        serde_json::Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+/// A string or list of strings; with `split_commas`, a single string is split on commas.
+fn string_list(value: &serde_json::Value, split_commas: bool) -> Vec<String> {
+    match value {
+        serde_json::Value::Array(items) => items.iter().filter_map(scalar_string).collect(),
+        other => match scalar_string(other) {
+            Some(s) if split_commas => s
+                .split(',')
+                .map(|p| p.trim().to_string())
+                .filter(|p| !p.is_empty())
+                .collect(),
+            Some(s) => vec![s],
+            None => Vec::new(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn read_md(md: &str) -> Document {
+        MarkdownReader::read(Cursor::new(md.as_bytes()), &ReadOptions::default(), None).unwrap()
+    }
+
+    #[test]
+    fn test_yaml_front_matter() {
+        let doc = read_md(
+            "---\ntitle: The Book\nauthors: [Ann, Bob]\nlanguage: en\n\
+             series:\n  name: Saga\n  position: 2\nsubjects: fantasy, adventure\n---\n\n# One\n\nText.\n",
+        );
+        assert_eq!(doc.metadata.title.as_deref(), Some("The Book"));
+        assert_eq!(doc.metadata.authors, vec!["Ann", "Bob"]);
+        assert_eq!(doc.metadata.language.as_deref(), Some("en"));
+        let series = doc.metadata.series.unwrap();
+        assert_eq!(series.name, "Saga");
+        assert_eq!(series.position, Some(2.0));
+        assert_eq!(doc.metadata.subjects, vec!["fantasy", "adventure"]);
+        assert_eq!(doc.content.len(), 1);
+    }
+
+    #[test]
+    fn test_toml_front_matter() {
+        let doc = read_md("+++\ntitle = \"T\"\nseries = \"S\"\nseries_index = 3\n+++\n\nBody\n");
+        assert_eq!(doc.metadata.title.as_deref(), Some("T"));
+        let series = doc.metadata.series.unwrap();
+        assert_eq!(series.name, "S");
+        assert_eq!(series.position, Some(3.0));
+    }
+
+    #[test]
+    fn test_blocks_map_to_ir() {
+        let doc = read_md(
+            "# Title\n\n- a\n- b\n  1. nested\n\n```rust\nfn x() {}\n```\n\n\
+             | A | B |\n|---|---|\n| 1 | 2 |\n\n> quoted\n",
+        );
+        let nodes = &doc.content[0].content;
+        assert!(matches!(&nodes[0], ContentNode::Heading { level: 1, .. }));
+        match &nodes[1] {
+            ContentNode::List {
+                ordered: false,
+                items,
+            } => {
+                assert_eq!(items.len(), 2);
+                assert!(matches!(
+                    items[1][1],
+                    ContentNode::List { ordered: true, .. }
+                ));
+            }
+            other => panic!("expected list, got {other:?}"),
+        }
+        assert!(matches!(
+            &nodes[2],
+            ContentNode::CodeBlock { language: Some(l), code } if l == "rust" && code == "fn x() {}"
+        ));
+        assert!(
+            matches!(&nodes[3], ContentNode::Table { headers, rows } if headers.len() == 2 && rows.len() == 1)
+        );
+        assert!(matches!(&nodes[4], ContentNode::BlockQuote { .. }));
+    }
+
+    #[test]
+    fn test_footnotes_collected_at_end() {
+        let doc = read_md("Text[^n].\n\n[^n]: The note.\n");
+        let nodes = &doc.content[0].content;
+        let ContentNode::Paragraph { children } = &nodes[0] else {
+            panic!("expected paragraph, got {:?}", nodes[0]);
+        };
+        assert!(
+            matches!(&children[1], InlineNode::NoteRef { id, label } if id == "fn-n" && label == "1")
+        );
+        assert!(matches!(
+            nodes.last(),
+            Some(ContentNode::Note { id, kind: NoteKind::Footnote, children })
+                if id == "fn-n" && children.len() == 1
+        ));
+    }
+}
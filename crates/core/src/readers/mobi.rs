@@ -0,0 +1,1570 @@
+//! MOBI / AZW3 reader: Palm database → MOBI/EXTH headers → text records → IR.
+//!
+//! Text records are PalmDOC (LZ77) or HUFF/CDIC compressed. Legacy Mobipocket
+//! markup is split into chapters at `<mbp:pagebreak>`; KF8 books (AZW3 and the
+//! KF8 half of combined files) are rebuilt from the skeleton and fragment
+//! indexes, and each part goes through the EPUB reader's chapter parser.
+//! DRM-protected books are rejected.
+
+use std::collections::HashMap;
+use std::io::{Read, Seek};
+
+use crate::detect::{DetectResult, Format};
+use crate::document::*;
+use crate::error::{ReadError, SecurityError};
+use crate::progress::{emit_progress, ProgressHandler};
+use crate::readers::epub::{link_note_refs, malformed_chapter, parse_xhtml_to_chapter};
+use crate::readers::{html_to_xhtml, toc_from_chapters, FormatReader, ReadOptions, XhtmlElement};
+use crate::security;
+
+pub struct MobiReader;
+
+impl FormatReader for MobiReader {
+    fn detect(header: &[u8]) -> DetectResult {
+        let confidence = if header.len() >= 68 && &header[60..68] == b"BOOKMOBI" {
+            0.95
+        } else {
+            0.0
+        };
+        DetectResult {
+            format: Format::Mobi,
+            confidence,
+            mime_type: Format::Mobi.mime_type(),
+        }
+    }
+
+    fn read<R: Read + Seek>(
+        mut input: R,
+        opts: &ReadOptions,
+        progress: Option<&dyn ProgressHandler>,
+    ) -> Result<Document, ReadError> {
+        let mut raw = Vec::new();
+        input.read_to_end(&mut raw)?;
+        read_mobi_impl(&raw, opts, progress)
+    }
+}
+
+/// Index value meaning "no such record".
+const NULL_INDEX: u32 = 0xFFFF_FFFF;
+
+fn malformed(detail: impl Into<String>) -> ReadError {
+    ReadError::MalformedFile {
+        format: "MOBI".into(),
+        detail: detail.into(),
+    }
+}
+
+fn be16(data: &[u8], off: usize) -> Option<u16> {
+    data.get(off..off + 2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]))
+}
+
+fn be32(data: &[u8], off: usize) -> Option<u32> {
+    data.get(off..off + 4)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn read_mobi_impl(
+    raw: &[u8],
+    opts: &ReadOptions,
+    progress: Option<&dyn ProgressHandler>,
+) -> Result<Document, ReadError> {
+    emit_progress(
+        progress,
+        "Reading MOBI",
+        0,
+        Some(4),
+        Some("Parsing headers"),
+    );
+    let pdb = Pdb::parse(raw)?;
+    security::check_file_count(pdb.len() as u64, &opts.security)?;
+
+    let rec0 = pdb
+        .record(0)
+        .ok_or_else(|| malformed("missing header record"))?;
+    let header = MobiHeader::parse(rec0)?;
+    if header.encryption != 0 {
+        return Err(SecurityError::DrmProtected {
+            format: "MOBI".into(),
+            drm_type: if header.encryption == 1 {
+                "Mobipocket (legacy)".into()
+            } else {
+                "Mobipocket".into()
+            },
+        }
+        .into());
+    }
+
+    // Combined MOBI6 + KF8 files mark where the KF8 half starts with EXTH 121;
+    // record indexes in its header are relative to that record.
+    let boundary = header
+        .exth_u32(121)
+        .filter(|&b| b != NULL_INDEX && (b as usize) < pdb.len() && b > 0);
+    let kf8_header = match boundary {
+        Some(b) => pdb
+            .record(b as usize)
+            .and_then(|r| MobiHeader::parse(r).ok())
+            .map(|h| (b as usize, h)),
+        None if header.version >= 8 => Some((0, header.clone())),
+        None => None,
+    };
+    let resources_end = boundary.map(|b| b as usize - 1).unwrap_or(pdb.len());
+
+    let mut metadata = header.metadata();
+    let text_direction = match header.exth_string(527).as_deref() {
+        Some("rtl") => TextDirection::Rtl,
+        _ => match header.exth_string(525).as_deref() {
+            Some(mode) if mode.ends_with("-rl") => TextDirection::Rtl,
+            _ => TextDirection::Ltr,
+        },
+    };
+
+    emit_progress(
+        progress,
+        "Reading MOBI",
+        1,
+        Some(4),
+        Some("Loading resources"),
+    );
+    let images = load_resources(&pdb, header.first_resource, resources_end, opts)?;
+    let mut resources = ResourceMap::new();
+    let mut resource_ids = HashMap::new();
+    for (number, resource) in images {
+        resource_ids.insert(number, resource.id.clone());
+        resources.insert(resource.id.clone(), resource);
+    }
+    if let Some(offset) = header.exth_u32(201).filter(|&o| o != NULL_INDEX) {
+        metadata.cover_image_id = resource_ids.get(&(offset + 1)).cloned();
+    }
+
+    emit_progress(
+        progress,
+        "Reading MOBI",
+        2,
+        Some(4),
+        Some("Decompressing text"),
+    );
+    let (content, toc, diagnostics) = match kf8_header {
+        Some((base, kf8)) => {
+            let text = read_text(&pdb, base, &kf8, &opts.security)?;
+            read_kf8(&pdb, base, &kf8, &text, &resource_ids, &mut resources, opts)?
+        }
+        None => {
+            let text = read_text(&pdb, 0, &header, &opts.security)?;
+            read_mobi6(&pdb, &header, &text, &resource_ids, opts)?
+        }
+    };
+
+    emit_progress(
+        progress,
+        "Reading MOBI",
+        3,
+        Some(4),
+        Some("Building contents"),
+    );
+    let toc = if !opts.parse_toc {
+        Vec::new()
+    } else if toc.is_empty() {
+        toc_from_chapters(&content)
+    } else {
+        toc
+    };
+    if metadata.title.is_none() {
+        metadata.title = content.iter().find_map(|c| c.title.clone());
+    }
+
+    emit_progress(progress, "Reading MOBI", 4, Some(4), Some("Done"));
+    Ok(Document {
+        metadata,
+        toc,
+        content,
+        resources,
+        text_direction,
+        epub_version: None,
+        diagnostics,
+    })
+}
+
+// --- Palm database ---
+
+struct Pdb<'a> {
+    data: &'a [u8],
+    offsets: Vec<usize>,
+}
+
+impl<'a> Pdb<'a> {
+    fn parse(data: &'a [u8]) -> Result<Self, ReadError> {
+        if data.len() < 78 || &data[60..68] != b"BOOKMOBI" {
+            return Err(malformed("not a Mobipocket database"));
+        }
+        let count = be16(data, 76).unwrap_or(0) as usize;
+        let mut offsets = Vec::with_capacity(count);
+        for i in 0..count {
+            let off = be32(data, 78 + i * 8).ok_or_else(|| malformed("truncated record list"))?;
+            offsets.push(off as usize);
+        }
+        Ok(Self { data, offsets })
+    }
+
+    fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    fn record(&self, i: usize) -> Option<&'a [u8]> {
+        let start = *self.offsets.get(i)?;
+        let end = self
+            .offsets
+            .get(i + 1)
+            .copied()
+            .unwrap_or(self.data.len())
+            .min(self.data.len());
+        self.data.get(start..end.max(start))
+    }
+}
+
+// --- MOBI / EXTH headers ---
+
+#[derive(Debug, Clone)]
+struct MobiHeader {
+    compression: u16,
+    text_length: u32,
+    text_records: u16,
+    encryption: u16,
+    text_encoding: u32,
+    version: u32,
+    full_name: Vec<u8>,
+    first_resource: u32,
+    huff_record: u32,
+    huff_count: u32,
+    extra_flags: u16,
+    fdst: u32,
+    ncx_index: u32,
+    fragment_index: u32,
+    skeleton_index: u32,
+    exth: Vec<(u32, Vec<u8>)>,
+}
+
+impl MobiHeader {
+    fn parse(rec0: &[u8]) -> Result<Self, ReadError> {
+        if rec0.get(16..20) != Some(b"MOBI") {
+            return Err(malformed("missing MOBI header"));
+        }
+        let field = |off: usize| be32(rec0, off).unwrap_or(NULL_INDEX);
+        let header_len = field(0x14) as usize;
+        let name_off = field(0x54) as usize;
+        let name_len = field(0x58) as usize;
+        let full_name = rec0
+            .get(name_off..name_off.saturating_add(name_len))
+            .unwrap_or_default()
+            .to_vec();
+        // Fields past the end of a short header are absent, not garbage.
+        let optional = |off: usize| {
+            if off + 4 <= 16 + header_len {
+                field(off)
+            } else {
+                NULL_INDEX
+            }
+        };
+
+        let mut exth = Vec::new();
+        if field(0x80) & 0x40 != 0 {
+            let start = 16 + header_len;
+            if rec0.get(start..start + 4) == Some(b"EXTH") {
+                let count = be32(rec0, start + 8).unwrap_or(0);
+                let mut pos = start + 12;
+                for _ in 0..count {
+                    let (Some(kind), Some(len)) = (be32(rec0, pos), be32(rec0, pos + 4)) else {
+                        break;
+                    };
+                    let len = len as usize;
+                    let Some(value) = rec0.get(pos + 8..pos + len.max(8)) else {
+                        break;
+                    };
+                    exth.push((kind, value.to_vec()));
+                    pos += len.max(8);
+                }
+            }
+        }
+
+        Ok(Self {
+            compression: be16(rec0, 0).unwrap_or(1),
+            text_length: be32(rec0, 4).unwrap_or(0),
+            text_records: be16(rec0, 8).unwrap_or(0),
+            encryption: be16(rec0, 12).unwrap_or(0),
+            text_encoding: field(0x1C),
+            version: field(0x24),
+            full_name,
+            first_resource: field(0x6C),
+            huff_record: field(0x70),
+            huff_count: field(0x74),
+            extra_flags: if header_len >= 0xE4 {
+                be16(rec0, 0xF2).unwrap_or(0)
+            } else {
+                0
+            },
+            fdst: optional(0xC0),
+            ncx_index: optional(0xF4),
+            fragment_index: optional(0xF8),
+            skeleton_index: optional(0xFC),
+            exth,
+        })
+    }
+
+    fn decode(&self, bytes: &[u8]) -> String {
+        decode_text(bytes, self.text_encoding)
+    }
+
+    fn exth_values(&self, kind: u32) -> impl Iterator<Item = &[u8]> {
+        self.exth
+            .iter()
+            .filter(move |(k, _)| *k == kind)
+            .map(|(_, v)| v.as_slice())
+    }
+
+    fn exth_string(&self, kind: u32) -> Option<String> {
+        self.exth_values(kind)
+            .map(|v| self.decode(v).trim().to_string())
+            .find(|s| !s.is_empty())
+    }
+
+    fn exth_u32(&self, kind: u32) -> Option<u32> {
+        self.exth_values(kind).find_map(|v| be32(v, 0))
+    }
+
+    fn metadata(&self) -> Metadata {
+        let mut metadata = Metadata::default();
+        let strings = |kind: u32| -> Vec<String> {
+            self.exth_values(kind)
+                .map(|v| self.decode(v).trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect()
+        };
+
+        metadata.title = self.exth_string(503).or_else(|| {
+            let name = self.decode(&self.full_name).trim().to_string();
+            (!name.is_empty()).then_some(name)
+        });
+        metadata.authors = strings(100)
+            .iter()
+            .flat_map(|a| a.split(';'))
+            .map(|a| a.trim().to_string())
+            .filter(|a| !a.is_empty())
+            .collect();
+        metadata.publisher = self.exth_string(101);
+        metadata.description = self.exth_string(103);
+        metadata.subjects = strings(105);
+        metadata.publish_date = self.exth_string(106);
+        metadata.rights = self.exth_string(109);
+        metadata.language = self.exth_string(524);
+        for isbn in strings(104) {
+            let digits: String = isbn.chars().filter(|c| c.is_ascii_alphanumeric()).collect();
+            match digits.len() {
+                13 => metadata.isbn_13 = Some(digits),
+                10 => metadata.isbn_10 = Some(digits),
+                _ => {}
+            }
+        }
+        for (kind, key) in [
+            (108, "contributor"),
+            (112, "source"),
+            (113, "asin"),
+            (504, "asin"),
+        ] {
+            if let Some(value) = self.exth_string(kind) {
+                metadata.custom.entry(key.to_string()).or_insert(value);
+            }
+        }
+        metadata
+    }
+}
+
+/// Decode text in the book's declared encoding: 65001 is UTF-8, anything
+/// else is treated as Windows-1252 (the only other value Kindle tools write).
+fn decode_text(bytes: &[u8], encoding: u32) -> String {
+    if encoding == 65001 {
+        String::from_utf8_lossy(bytes).into_owned()
+    } else {
+        encoding_rs::WINDOWS_1252.decode(bytes).0.into_owned()
+    }
+}
+
+// --- Text records ---
+
+fn read_text(
+    pdb: &Pdb,
+    base: usize,
+    header: &MobiHeader,
+    limits: &security::SecurityLimits,
+) -> Result<Vec<u8>, ReadError> {
+    let mut huff = match header.compression {
+        1 | 2 => None,
+        17480 => {
+            let start = base + header.huff_record as usize;
+            let huff = pdb
+                .record(start)
+                .ok_or_else(|| malformed("missing HUFF record"))?;
+            if start + header.huff_count as usize > pdb.len() {
+                return Err(malformed("HUFF/CDIC record count exceeds the record list"));
+            }
+            let cdics: Vec<&[u8]> = (1..header.huff_count as usize)
+                .filter_map(|i| pdb.record(start + i))
+                .collect();
+            Some(HuffCdic::new(huff, &cdics).ok_or_else(|| malformed("invalid HUFF/CDIC tables"))?)
+        }
+        other => return Err(malformed(format!("unknown compression type {}", other))),
+    };
+
+    // `text_length` comes from the file; don't trust it beyond what the
+    // records could plausibly decompress to.
+    let mut text = Vec::with_capacity((header.text_length as usize).min(pdb.data.len() * 8));
+    for i in 1..=header.text_records as usize {
+        let Some(record) = pdb.record(base + i) else {
+            break;
+        };
+        let record = trim_trailing_entries(record, header.extra_flags);
+        match (&mut huff, header.compression) {
+            (Some(h), _) => text.extend(h.unpack(record, 0)),
+            (None, 2) => text.extend(palmdoc_decompress(record)),
+            _ => text.extend_from_slice(record),
+        }
+        security::check_total_size(text.len() as u64, limits)?;
+    }
+    if header.text_length > 0 && text.len() > header.text_length as usize {
+        text.truncate(header.text_length as usize);
+    }
+    Ok(text)
+}
+
+/// Strip the trailing entries the `extra_flags` bits say each text record carries.
+fn trim_trailing_entries(data: &[u8], flags: u16) -> &[u8] {
+    let mut size = data.len();
+    let mut bits = flags >> 1;
+    while bits != 0 {
+        if bits & 1 != 0 {
+            // Entry size is a backwards variable-width integer in the last bytes.
+            let tail = &data[size.saturating_sub(4)..size];
+            let mut n = 0usize;
+            for &b in tail {
+                if b & 0x80 != 0 {
+                    n = 0;
+                }
+                n = (n << 7) | (b & 0x7F) as usize;
+            }
+            size = size.saturating_sub(n);
+        }
+        bits >>= 1;
+    }
+    if flags & 1 != 0 && size > 0 {
+        // Multibyte character overlap: low two bits give the extra byte count.
+        size = size.saturating_sub((data[size - 1] & 0x3) as usize + 1);
+    }
+    &data[..size]
+}
+
+/// PalmDOC LZ77 decompression.
+fn palmdoc_decompress(input: &[u8]) -> Vec<u8> {
+    let mut out: Vec<u8> = Vec::with_capacity(input.len() * 2);
+    let mut i = 0;
+    while i < input.len() {
+        let c = input[i];
+        i += 1;
+        match c {
+            0x01..=0x08 => {
+                let end = (i + c as usize).min(input.len());
+                out.extend_from_slice(&input[i..end]);
+                i = end;
+            }
+            0x00 | 0x09..=0x7F => out.push(c),
+            0x80..=0xBF => {
+                let Some(&next) = input.get(i) else {
+                    break;
+                };
+                i += 1;
+                let pair = ((c as usize) << 8) | next as usize;
+                let distance = (pair >> 3) & 0x7FF;
+                let length = (pair & 0x7) + 3;
+                if distance == 0 || distance > out.len() {
+                    continue;
+                }
+                let start = out.len() - distance;
+                // Byte by byte: the copy may overlap its own output.
+                for k in 0..length {
+                    out.push(out[start + k]);
+                }
+            }
+            0xC0..=0xFF => {
+                out.push(b' ');
+                out.push(c ^ 0x80);
+            }
+        }
+    }
+    out
+}
+
+/// Huffman decoder with CDIC phrase dictionaries (Mobipocket "HUFF/CDIC").
+struct HuffCdic {
+    /// (code length, terminal, max code) for each leading byte.
+    dict1: Vec<(u32, bool, u64)>,
+    mincode: [u64; 33],
+    maxcode: [u64; 33],
+    /// Phrase bytes and whether they are already fully decoded.
+    phrases: Vec<(Vec<u8>, bool)>,
+}
+
+/// Phrases expand recursively; real books nest a handful of levels.
+const MAX_PHRASE_DEPTH: u32 = 32;
+
+impl HuffCdic {
+    fn new(huff: &[u8], cdics: &[&[u8]]) -> Option<Self> {
+        if huff.get(0..8) != Some(b"HUFF\x00\x00\x00\x18") {
+            return None;
+        }
+        let off1 = be32(huff, 8)? as usize;
+        let off2 = be32(huff, 12)? as usize;
+
+        let mut dict1 = Vec::with_capacity(256);
+        for i in 0..256 {
+            let v = be32(huff, off1 + i * 4)?;
+            let codelen = v & 0x1F;
+            let term = v & 0x80 != 0;
+            if codelen == 0 {
+                return None;
+            }
+            let maxcode = (((v >> 8) as u64 + 1) << (32 - codelen)).wrapping_sub(1);
+            dict1.push((codelen, term, maxcode));
+        }
+
+        let mut mincode = [0u64; 33];
+        let mut maxcode = [0u64; 33];
+        maxcode[0] = (1u64 << 32) - 1;
+        for len in 1..=32usize {
+            let lo = be32(huff, off2 + (len - 1) * 8)? as u64;
+            let hi = be32(huff, off2 + (len - 1) * 8 + 4)? as u64;
+            mincode[len] = lo << (32 - len);
+            maxcode[len] = ((hi + 1) << (32 - len)).wrapping_sub(1);
+        }
+
+        let mut phrases = Vec::new();
+        for cdic in cdics {
+            if cdic.get(0..8) != Some(b"CDIC\x00\x00\x00\x10") {
+                return None;
+            }
+            let total = be32(cdic, 8)? as usize;
+            let bits = be32(cdic, 12)?.min(31);
+            let n = (1usize << bits).min(total.saturating_sub(phrases.len()));
+            for i in 0..n {
+                let off = be16(cdic, 16 + i * 2)? as usize;
+                let blen = be16(cdic, 16 + off)? as usize;
+                let start = 18 + off;
+                let bytes = cdic.get(start..start + (blen & 0x7FFF))?.to_vec();
+                phrases.push((bytes, blen & 0x8000 != 0));
+            }
+        }
+
+        Some(Self {
+            dict1,
+            mincode,
+            maxcode,
+            phrases,
+        })
+    }
+
+    fn unpack(&mut self, data: &[u8], depth: u32) -> Vec<u8> {
+        let mut out = Vec::new();
+        if depth > MAX_PHRASE_DEPTH {
+            return out;
+        }
+        let mut buf = data.to_vec();
+        buf.extend_from_slice(&[0; 8]);
+        let word = |pos: usize| -> u64 {
+            let mut b = [0u8; 8];
+            b.copy_from_slice(&buf[pos..pos + 8]);
+            u64::from_be_bytes(b)
+        };
+
+        let mut bits_left = data.len() as i64 * 8;
+        let mut pos = 0usize;
+        let mut x = word(pos);
+        let mut n: i64 = 32;
+        loop {
+            if n <= 0 {
+                pos += 4;
+                if pos + 8 > buf.len() {
+                    break;
+                }
+                x = word(pos);
+                n += 32;
+            }
+            let code = (x >> n) & 0xFFFF_FFFF;
+            let (mut codelen, term, mut maxcode) = self.dict1[(code >> 24) as usize];
+            if !term {
+                while codelen < 32 && code < self.mincode[codelen as usize] {
+                    codelen += 1;
+                }
+                maxcode = self.maxcode[codelen as usize];
+            }
+            n -= codelen as i64;
+            bits_left -= codelen as i64;
+            if bits_left < 0 {
+                break;
+            }
+            let Some(diff) = maxcode.checked_sub(code) else {
+                break;
+            };
+            let r = (diff >> (32 - codelen)) as usize;
+            let Some((phrase, done)) = self.phrases.get(r).cloned() else {
+                break;
+            };
+            if done {
+                out.extend_from_slice(&phrase);
+            } else {
+                // Mark in progress so a self-referencing phrase can't loop forever.
+                self.phrases[r] = (Vec::new(), true);
+                let expanded = self.unpack(&phrase, depth + 1);
+                out.extend_from_slice(&expanded);
+                self.phrases[r] = (expanded, true);
+            }
+        }
+        out
+    }
+}
+
+// --- Resources ---
+
+/// Load image and font records, keyed by their 1-based resource number (the
+/// number `recindex` and `kindle:embed` references use).
+fn load_resources(
+    pdb: &Pdb,
+    first: u32,
+    end: usize,
+    opts: &ReadOptions,
+) -> Result<Vec<(u32, Resource)>, ReadError> {
+    let mut out = Vec::new();
+    if first == NULL_INDEX {
+        return Ok(out);
+    }
+    for index in first as usize..end.min(pdb.len()) {
+        let Some(data) = pdb.record(index) else {
+            break;
+        };
+        let number = (index - first as usize + 1) as u32;
+        let (data, media_type, stem) = if let Some(media_type) = image_media_type(data) {
+            (data.to_vec(), media_type, "image")
+        } else if data.starts_with(b"FONT") {
+            match decode_font_record(data, opts.security.max_resource_size_bytes) {
+                Some(font) => {
+                    let media_type = if font.starts_with(b"OTTO") {
+                        "font/otf"
+                    } else {
+                        "font/ttf"
+                    };
+                    (font, media_type, "font")
+                }
+                None => continue,
+            }
+        } else if data.starts_with(b"BOUNDARY") || data.starts_with(b"\xE9\x8E\r\n") {
+            break;
+        } else {
+            continue;
+        };
+        let ext = media_type
+            .rsplit('/')
+            .next()
+            .unwrap_or("bin")
+            .replace("jpeg", "jpg");
+        let id = format!("{}{:05}.{}", stem, number, ext);
+        if let Err(e) = security::check_resource_size(&id, data.len() as u64, &opts.security) {
+            tracing::warn!("Skipping resource '{}': {}", id, e);
+            continue;
+        }
+        out.push((
+            number,
+            Resource {
+                id: id.clone(),
+                media_type: media_type.to_string(),
+                data,
+                filename: Some(id),
+                obfuscation: None,
+            },
+        ));
+    }
+    Ok(out)
+}
+
+fn image_media_type(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(b"\xFF\xD8\xFF") {
+        Some("image/jpeg")
+    } else if data.starts_with(b"\x89PNG") {
+        Some("image/png")
+    } else if data.starts_with(b"GIF8") {
+        Some("image/gif")
+    } else if data.starts_with(b"BM") && data.len() > 14 {
+        Some("image/bmp")
+    } else {
+        None
+    }
+}
+
+/// KF8 font records: optionally XOR-obfuscated, then optionally zlib-compressed.
+/// Fonts whose declared size exceeds `limit` bytes are rejected before inflating.
+fn decode_font_record(data: &[u8], limit: u64) -> Option<Vec<u8>> {
+    let size = be32(data, 4)? as u64;
+    if size > limit {
+        tracing::warn!(
+            "Skipping font of {} bytes: exceeds resource size limit",
+            size
+        );
+        return None;
+    }
+    let flags = be32(data, 8)?;
+    let start = be32(data, 12)? as usize;
+    let key_len = be32(data, 16)? as usize;
+    let key_start = be32(data, 20)? as usize;
+    let mut font = data.get(start..)?.to_vec();
+    if flags & 0x2 != 0 && key_len > 0 {
+        let key = data.get(key_start..key_start + key_len)?;
+        for (i, b) in font.iter_mut().take(1040).enumerate() {
+            *b ^= key[i % key_len];
+        }
+    }
+    if flags & 0x1 != 0 {
+        let mut inflated = Vec::new();
+        flate2::read::ZlibDecoder::new(font.as_slice())
+            .take(size)
+            .read_to_end(&mut inflated)
+            .ok()?;
+        font = inflated;
+    }
+    Some(font)
+}
+
+// --- Indexes (INDX/TAGX) ---
+
+#[derive(Debug)]
+struct IndexEntry {
+    text: Vec<u8>,
+    tags: HashMap<u8, Vec<u32>>,
+}
+
+impl IndexEntry {
+    fn tag(&self, tag: u8, i: usize) -> Option<u32> {
+        self.tags.get(&tag).and_then(|v| v.get(i)).copied()
+    }
+}
+
+/// Index entries and the CTOC strings they reference, keyed by offset.
+type Index = (Vec<IndexEntry>, HashMap<u32, Vec<u8>>);
+
+/// Read an INDX index: the main record (with its TAGX table), the entry
+/// records that follow it, and the CTOC string records after those.
+fn read_index(pdb: &Pdb, index: usize) -> Result<Index, ReadError> {
+    let mut entries = Vec::new();
+    let mut strings = HashMap::new();
+    let Some(main) = pdb.record(index).filter(|r| r.starts_with(b"INDX")) else {
+        return Ok((entries, strings));
+    };
+    let header_len = be32(main, 4).unwrap_or(0) as usize;
+    let count = be32(main, 24).unwrap_or(0) as usize;
+    let ctoc_count = be32(main, 52).unwrap_or(0) as usize;
+    if index + count + ctoc_count >= pdb.len() {
+        return Err(malformed(format!(
+            "index at record {} spans more records than the file has",
+            index
+        )));
+    }
+
+    for j in 0..ctoc_count {
+        let Some(data) = pdb.record(index + count + 1 + j) else {
+            break;
+        };
+        let mut pos = 0;
+        while pos < data.len() && data[pos] != 0 {
+            let offset = pos;
+            let Some((consumed, len)) = varint(data, pos) else {
+                break;
+            };
+            pos += consumed;
+            let end = (pos + len as usize).min(data.len());
+            strings.insert((j * 0x10000 + offset) as u32, data[pos..end].to_vec());
+            pos = end;
+        }
+    }
+
+    // TAGX: (tag, values per entry, mask, end-of-control-byte flag)
+    let mut control_bytes = 0;
+    let mut tag_table = Vec::new();
+    if main.get(header_len..header_len + 4) == Some(b"TAGX") {
+        let first_entry = be32(main, header_len + 4).unwrap_or(12) as usize;
+        control_bytes = be32(main, header_len + 8).unwrap_or(0) as usize;
+        let mut off = 12;
+        while off + 4 <= first_entry {
+            if let Some(t) = main.get(header_len + off..header_len + off + 4) {
+                tag_table.push((t[0], t[1], t[2], t[3]));
+            }
+            off += 4;
+        }
+    }
+
+    for i in index + 1..=index + count {
+        let Some(data) = pdb.record(i).filter(|r| r.starts_with(b"INDX")) else {
+            continue;
+        };
+        let idxt = be32(data, 20).unwrap_or(0) as usize;
+        let entry_count = be32(data, 24).unwrap_or(0) as usize;
+        // Each entry has a two-byte offset in the IDXT table.
+        if idxt + 4 + 2 * entry_count > data.len() {
+            return Err(malformed(format!(
+                "index record {} lists more entries than it holds",
+                i
+            )));
+        }
+        let mut positions: Vec<usize> = (0..entry_count)
+            .filter_map(|j| be16(data, idxt + 4 + 2 * j).map(|p| p as usize))
+            .collect();
+        positions.push(idxt);
+        for w in positions.windows(2) {
+            let (start, end) = (w[0], w[1].min(data.len()));
+            let Some(&len) = data.get(start) else {
+                continue;
+            };
+            let text_end = start + 1 + len as usize;
+            let Some(text) = data.get(start + 1..text_end) else {
+                continue;
+            };
+            let tags = tag_map(control_bytes, &tag_table, data, text_end, end);
+            entries.push(IndexEntry {
+                text: text.to_vec(),
+                tags,
+            });
+        }
+    }
+    Ok((entries, strings))
+}
+
+/// Forward variable-width integer (high bit marks the last byte).
+fn varint(data: &[u8], pos: usize) -> Option<(usize, u32)> {
+    let mut value: u32 = 0;
+    for (i, &b) in data.get(pos..)?.iter().enumerate().take(5) {
+        value = (value << 7) | (b & 0x7F) as u32;
+        if b & 0x80 != 0 {
+            return Some((i + 1, value));
+        }
+    }
+    None
+}
+
+fn tag_map(
+    control_bytes: usize,
+    table: &[(u8, u8, u8, u8)],
+    data: &[u8],
+    start: usize,
+    end: usize,
+) -> HashMap<u8, Vec<u32>> {
+    let mut map = HashMap::new();
+    let mut control_index = 0;
+    let mut pos = start + control_bytes;
+    // (tag, value count, byte length, values per entry)
+    let mut pending = Vec::new();
+    for &(tag, per_entry, mask, end_flag) in table {
+        if end_flag == 1 {
+            control_index += 1;
+            continue;
+        }
+        let Some(&control) = data.get(start + control_index) else {
+            break;
+        };
+        let value = control & mask;
+        if value == 0 {
+            continue;
+        }
+        if value == mask && mask.count_ones() > 1 {
+            // All mask bits set: a varint gives the byte length of the values.
+            let Some((consumed, len)) = varint(data, pos) else {
+                break;
+            };
+            pos += consumed;
+            pending.push((tag, None, Some(len as usize), per_entry));
+        } else {
+            pending.push((
+                tag,
+                Some((value >> mask.trailing_zeros()) as usize),
+                None,
+                per_entry,
+            ));
+        }
+    }
+    for (tag, count, byte_len, per_entry) in pending {
+        let mut values = Vec::new();
+        if let Some(count) = count {
+            for _ in 0..count * per_entry as usize {
+                let Some((consumed, v)) = varint(data, pos).filter(|_| pos < end) else {
+                    break;
+                };
+                pos += consumed;
+                values.push(v);
+            }
+        } else if let Some(byte_len) = byte_len {
+            let stop = pos + byte_len;
+            while pos < stop.min(end) {
+                let Some((consumed, v)) = varint(data, pos) else {
+                    break;
+                };
+                pos += consumed;
+                values.push(v);
+            }
+        }
+        map.insert(tag, values);
+    }
+    map
+}
+
+/// Build the TOC from an NCX index. `target` maps an entry to a chapter href.
+fn ncx_toc(
+    pdb: &Pdb,
+    index: usize,
+    decode: impl Fn(&[u8]) -> String,
+    target: impl Fn(&IndexEntry) -> Option<String>,
+) -> Result<Vec<TocEntry>, ReadError> {
+    let (entries, strings) = read_index(pdb, index)?;
+    let mut roots: Vec<TocEntry> = Vec::new();
+    // Open ancestors: (depth, path of child indexes from the roots).
+    let mut stack: Vec<(u32, Vec<usize>)> = Vec::new();
+    for entry in &entries {
+        let Some(href) = target(entry) else {
+            continue;
+        };
+        let title = entry
+            .tag(3, 0)
+            .and_then(|off| strings.get(&off))
+            .map(|t| decode(t).trim().to_string())
+            .unwrap_or_default();
+        let depth = entry.tag(4, 0).unwrap_or(0);
+        while stack.last().is_some_and(|(d, _)| *d >= depth) {
+            stack.pop();
+        }
+        let item = TocEntry {
+            title,
+            href,
+            children: Vec::new(),
+        };
+        let path = match stack.last() {
+            Some((_, parent)) => {
+                let mut siblings = &mut roots;
+                for &i in parent {
+                    siblings = &mut siblings[i].children;
+                }
+                siblings.push(item);
+                let mut path = parent.clone();
+                path.push(siblings.len() - 1);
+                path
+            }
+            None => {
+                roots.push(item);
+                vec![roots.len() - 1]
+            }
+        };
+        stack.push((depth, path));
+    }
+    Ok(roots)
+}
+
+fn part_name(n: usize) -> String {
+    format!("part{:04}", n)
+}
+
+// --- Legacy Mobipocket markup ---
+
+fn read_mobi6(
+    pdb: &Pdb,
+    header: &MobiHeader,
+    text: &[u8],
+    resource_ids: &HashMap<u32, String>,
+    opts: &ReadOptions,
+) -> Result<Parts, ReadError> {
+    // Split at page breaks, remembering where each part starts so `filepos`
+    // links and NCX offsets can be mapped to a part.
+    let mut starts = vec![0usize];
+    let lower = text.to_ascii_lowercase();
+    let mut search = 0;
+    while let Some(found) = find(&lower[search..], b"<mbp:pagebreak") {
+        let at = search + found;
+        if at > *starts.last().unwrap_or(&0) {
+            starts.push(at);
+        }
+        search = at + 1;
+    }
+    let part_of = |filepos: usize| starts.partition_point(|&s| s <= filepos).saturating_sub(1);
+
+    let mut chapters = Vec::new();
+    let mut diagnostics = Vec::new();
+    for (i, &start) in starts.iter().enumerate() {
+        let end = starts.get(i + 1).copied().unwrap_or(text.len());
+        let html = header.decode(&text[start..end]);
+        let xhtml = mobi6_to_xhtml(
+            &html,
+            opts.security.max_nesting_depth,
+            |name, attr, value| match (name, attr) {
+                ("img", "recindex") => value
+                    .trim()
+                    .parse::<u32>()
+                    .ok()
+                    .and_then(|n| resource_ids.get(&n))
+                    .map(|id| ("src", id.clone())),
+                ("a", "filepos") => value
+                    .trim()
+                    .parse::<usize>()
+                    .ok()
+                    .map(|p| ("href", format!("{}.xhtml", part_name(part_of(p))))),
+                _ => None,
+            },
+        );
+        let (chapter, error) = parse_xhtml_to_chapter(&xhtml, &part_name(i), &opts.security);
+        if !chapter.content.is_empty() {
+            if let Some(error) = error {
+                let source = format!("{}.xhtml", part_name(i));
+                diagnostics.push(malformed_chapter(chapters.len(), &source, &error));
+            }
+            chapters.push(chapter);
+        }
+    }
+    link_note_refs(&mut chapters);
+
+    let toc = if header.ncx_index != NULL_INDEX && opts.parse_toc {
+        ncx_toc(
+            pdb,
+            header.ncx_index as usize,
+            |b| header.decode(b),
+            |e| {
+                e.tag(1, 0)
+                    .map(|p| format!("{}.xhtml", part_name(part_of(p as usize))))
+            },
+        )?
+    } else {
+        Vec::new()
+    };
+    Ok((chapters, toc, diagnostics))
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Chapters, TOC and recovery diagnostics extracted from a MOBI text stream.
+type Parts = (Vec<Chapter>, Vec<TocEntry>, Vec<Diagnostic>);
+
+/// Re-serialize tag-soup Mobipocket HTML as well-formed XHTML so the EPUB
+/// chapter parser can read it. `rewrite` may replace an attribute (e.g.
+/// `recindex` → `src`); other prefixed attributes are dropped, and vendor
+/// elements like `<mbp:nu>` keep only their content.
+fn mobi6_to_xhtml(
+    html: &str,
+    max_depth: u32,
+    rewrite: impl Fn(&str, &str, &str) -> Option<(&'static str, String)>,
+) -> String {
+    html_to_xhtml(
+        html,
+        max_depth,
+        |name| match name {
+            "head" | "script" | "style" | "guide" => XhtmlElement::Skip,
+            _ if name.contains(':') => XhtmlElement::Unwrap,
+            _ => XhtmlElement::Keep,
+        },
+        |name, attr, value| match rewrite(name, attr, value) {
+            Some((attr, value)) => Some((attr.to_string(), value)),
+            None if attr.contains(':') => None,
+            None => Some((attr.to_string(), value.to_string())),
+        },
+    )
+}
+
+// --- KF8 ---
+
+struct Fragment {
+    insert_pos: usize,
+    file: usize,
+    length: usize,
+}
+
+fn read_kf8(
+    pdb: &Pdb,
+    base: usize,
+    header: &MobiHeader,
+    text: &[u8],
+    resource_ids: &HashMap<u32, String>,
+    resources: &mut ResourceMap,
+    opts: &ReadOptions,
+) -> Result<Parts, ReadError> {
+    let index = |i: u32| (i != NULL_INDEX).then(|| base + i as usize);
+
+    // FDST splits the text into flows: flow 0 is the markup, the rest are
+    // stylesheets and SVG referenced as `kindle:flow:N`.
+    let mut flows: Vec<&[u8]> = Vec::new();
+    if let Some(fdst) = index(header.fdst).and_then(|i| pdb.record(i)) {
+        if fdst.starts_with(b"FDST") {
+            let count = be32(fdst, 8).unwrap_or(0) as usize;
+            for i in 0..count {
+                let (Some(s), Some(e)) = (be32(fdst, 12 + i * 8), be32(fdst, 16 + i * 8)) else {
+                    break;
+                };
+                let (s, e) = (s as usize, (e as usize).min(text.len()));
+                flows.push(text.get(s..e.max(s)).unwrap_or_default());
+            }
+        }
+    }
+    if flows.is_empty() {
+        flows.push(text);
+    }
+
+    let mut flow_ids = HashMap::new();
+    for (n, flow) in flows.iter().enumerate().skip(1) {
+        let head = String::from_utf8_lossy(&flow[..flow.len().min(256)]).to_lowercase();
+        let (media_type, ext) = if head.contains("<svg") {
+            ("image/svg+xml", "svg")
+        } else {
+            ("text/css", "css")
+        };
+        let id = format!("flow{:05}.{}", n, ext);
+        resources.insert(
+            id.clone(),
+            Resource {
+                id: id.clone(),
+                media_type: media_type.to_string(),
+                data: flow.to_vec(),
+                filename: Some(id.clone()),
+                obfuscation: None,
+            },
+        );
+        flow_ids.insert(n as u32, id);
+    }
+
+    let (skeletons, _) = index(header.skeleton_index)
+        .map(|i| read_index(pdb, i))
+        .transpose()?
+        .unwrap_or_default();
+    let fragments: Vec<Fragment> = index(header.fragment_index)
+        .map(|i| read_index(pdb, i))
+        .transpose()?
+        .unwrap_or_default()
+        .0
+        .iter()
+        .map(|e| Fragment {
+            insert_pos: std::str::from_utf8(&e.text)
+                .ok()
+                .and_then(|t| t.trim().parse().ok())
+                .unwrap_or(0),
+            file: e.tag(3, 0).unwrap_or(0) as usize,
+            length: e.tag(6, 1).unwrap_or(0) as usize,
+        })
+        .collect();
+
+    let markup = flows[0];
+    let mut parts: Vec<Vec<u8>> = Vec::new();
+    if skeletons.is_empty() {
+        parts.push(markup.to_vec());
+    }
+    let mut next_fragment = 0;
+    for skeleton in &skeletons {
+        let frag_count = skeleton.tag(1, 0).unwrap_or(0) as usize;
+        let start = (skeleton.tag(6, 0).unwrap_or(0) as usize).min(markup.len());
+        let end = (start + skeleton.tag(6, 1).unwrap_or(0) as usize).min(markup.len());
+        let mut part = markup[start..end].to_vec();
+        let mut pos = end;
+        for fragment in fragments.iter().skip(next_fragment).take(frag_count) {
+            let slice_end = (pos + fragment.length).min(markup.len());
+            let at = fragment.insert_pos.saturating_sub(start).min(part.len());
+            part.splice(at..at, markup[pos..slice_end].iter().copied());
+            pos = slice_end;
+        }
+        next_fragment += frag_count;
+        parts.push(part);
+    }
+
+    // `kindle:pos:fid:N` links name a fragment; its file is the chapter.
+    let fragment_part = |fid: usize| -> Option<String> {
+        fragments
+            .get(fid)
+            .map(|f| format!("{}.xhtml", part_name(f.file)))
+    };
+
+    let mut chapters = Vec::new();
+    let mut diagnostics = Vec::new();
+    for (i, part) in parts.iter().enumerate() {
+        let xhtml = header.decode(part);
+        let xhtml = rewrite_kindle_urls(&xhtml, |url| {
+            let rest = url.strip_prefix("kindle:")?;
+            if let Some(embed) = rest.strip_prefix("embed:") {
+                base32(embed.get(..4)?).and_then(|n| resource_ids.get(&n).cloned())
+            } else if let Some(flow) = rest.strip_prefix("flow:") {
+                base32(flow.get(..4)?).and_then(|n| flow_ids.get(&n).cloned())
+            } else if let Some(pos) = rest.strip_prefix("pos:fid:") {
+                fragment_part(base32(pos.get(..4)?)? as usize)
+            } else {
+                None
+            }
+        });
+        let (chapter, error) = parse_xhtml_to_chapter(&xhtml, &part_name(i), &opts.security);
+        if !chapter.content.is_empty() {
+            if let Some(error) = error {
+                let source = format!("{}.xhtml", part_name(i));
+                diagnostics.push(malformed_chapter(chapters.len(), &source, &error));
+            }
+            chapters.push(chapter);
+        }
+    }
+    link_note_refs(&mut chapters);
+
+    let toc = match index(header.ncx_index) {
+        Some(ncx) if opts.parse_toc => ncx_toc(
+            pdb,
+            ncx,
+            |b| header.decode(b),
+            |e| e.tag(6, 0).and_then(|fid| fragment_part(fid as usize)),
+        )?,
+        _ => Vec::new(),
+    };
+    Ok((chapters, toc, diagnostics))
+}
+
+/// KF8 numbers are base 32 with digits `0-9A-V`.
+fn base32(s: &str) -> Option<u32> {
+    s.chars().try_fold(0u32, |acc, c| {
+        let d = c.to_digit(32)?;
+        acc.checked_mul(32)?.checked_add(d)
+    })
+}
+
+/// Replace every `kindle:` URL (up to the closing quote or parenthesis) that
+/// `resolve` knows with its replacement.
+fn rewrite_kindle_urls(html: &str, resolve: impl Fn(&str) -> Option<String>) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(at) = rest.find("kindle:") {
+        out.push_str(&rest[..at]);
+        let tail = &rest[at..];
+        let end = tail.find(['"', '\'', ')', ' ', '>']).unwrap_or(tail.len());
+        let url = &tail[..end];
+        match resolve(url) {
+            Some(replacement) => out.push_str(&replacement),
+            None => out.push_str(url),
+        }
+        rest = &tail[end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Assemble a Palm database from records.
+    fn pdb(records: &[Vec<u8>]) -> Vec<u8> {
+        let mut out = vec![0u8; 78];
+        out[..9].copy_from_slice(b"Test Book");
+        out[60..68].copy_from_slice(b"BOOKMOBI");
+        out[76..78].copy_from_slice(&(records.len() as u16).to_be_bytes());
+        let mut offset = 78 + records.len() * 8 + 2;
+        for (i, r) in records.iter().enumerate() {
+            out.extend_from_slice(&(offset as u32).to_be_bytes());
+            out.extend_from_slice(&(i as u32 * 2).to_be_bytes());
+            offset += r.len();
+        }
+        out.extend_from_slice(&[0, 0]);
+        for r in records {
+            out.extend_from_slice(r);
+        }
+        out
+    }
+
+    fn put32(buf: &mut [u8], off: usize, v: u32) {
+        buf[off..off + 4].copy_from_slice(&v.to_be_bytes());
+    }
+
+    /// Record 0 with a 0x108-byte MOBI header and the given EXTH records.
+    fn record0(
+        compression: u16,
+        text: &[u8],
+        text_records: u16,
+        first_resource: u32,
+        exth: &[(u32, &[u8])],
+    ) -> Vec<u8> {
+        let mut r = vec![0u8; 16 + 0x108];
+        r[0..2].copy_from_slice(&compression.to_be_bytes());
+        put32(&mut r, 4, text.len() as u32);
+        r[8..10].copy_from_slice(&text_records.to_be_bytes());
+        r[10..12].copy_from_slice(&4096u16.to_be_bytes());
+        r[16..20].copy_from_slice(b"MOBI");
+        put32(&mut r, 0x14, 0x108);
+        put32(&mut r, 0x1C, 65001);
+        put32(&mut r, 0x24, 6);
+        put32(&mut r, 0x6C, first_resource);
+        put32(&mut r, 0x80, 0x40);
+        for off in [0x70, 0xC0, 0xF4, 0xF8, 0xFC] {
+            put32(&mut r, off, NULL_INDEX);
+        }
+        put32(&mut r, 0x74, 0);
+        r.extend_from_slice(b"EXTH");
+        let body: usize = exth.iter().map(|(_, v)| v.len() + 8).sum();
+        r.extend_from_slice(&(body as u32 + 12).to_be_bytes());
+        r.extend_from_slice(&(exth.len() as u32).to_be_bytes());
+        for (kind, value) in exth {
+            r.extend_from_slice(&kind.to_be_bytes());
+            r.extend_from_slice(&(value.len() as u32 + 8).to_be_bytes());
+            r.extend_from_slice(value);
+        }
+        let name_off = r.len() as u32;
+        r.extend_from_slice(b"Header Title");
+        put32(&mut r, 0x54, name_off);
+        put32(&mut r, 0x58, 12);
+        r
+    }
+
+    #[test]
+    fn reads_mobi6_with_exth_cover_and_page_breaks() {
+        let template = "<html><head><guide></guide></head><body>\
+            <h1>One</h1><p>First <b>part</b> <a filepos=FILEPOS___>next</a></p>\
+            <mbp:pagebreak/><h1>Two</h1><p>Second<br>part</p><p><img recindex=\"00001\"></p>\
+            </body></html>";
+        let pagebreak = template.find("<mbp:pagebreak").unwrap();
+        let html = template.replace("FILEPOS___", &format!("{:010}", pagebreak));
+        let html = html.as_str();
+        let image = b"\x89PNG\r\n\x1a\nfake".to_vec();
+        let cover = 0u32.to_be_bytes();
+        let rec0 = record0(
+            1,
+            html.as_bytes(),
+            1,
+            2,
+            &[
+                (100, b"Jane Doe".as_slice()),
+                (101, b"Pub House"),
+                (524, b"en"),
+                (104, b"978-0-00-000000-2"),
+                (201, &cover),
+            ],
+        );
+        let data = pdb(&[rec0, html.as_bytes().to_vec(), image]);
+
+        let doc = MobiReader::read(Cursor::new(data), &ReadOptions::default(), None).unwrap();
+        assert_eq!(doc.metadata.title.as_deref(), Some("Header Title"));
+        assert_eq!(doc.metadata.authors, ["Jane Doe"]);
+        assert_eq!(doc.metadata.publisher.as_deref(), Some("Pub House"));
+        assert_eq!(doc.metadata.language.as_deref(), Some("en"));
+        assert_eq!(doc.metadata.isbn_13.as_deref(), Some("9780000000002"));
+        assert_eq!(
+            doc.metadata.cover_image_id.as_deref(),
+            Some("image00001.png")
+        );
+        assert_eq!(doc.resources.len(), 1);
+
+        assert_eq!(doc.content.len(), 2);
+        assert_eq!(doc.content[0].title.as_deref(), Some("One"));
+        assert_eq!(doc.content[1].id, "part0001");
+        let link = doc.content[0].content.iter().find_map(|n| match n {
+            ContentNode::Paragraph { children } => children.iter().find_map(|c| match c {
+                InlineNode::Link { href, .. } => Some(href.clone()),
+                _ => None,
+            }),
+            _ => None,
+        });
+        assert_eq!(link.as_deref(), Some("part0001.xhtml"));
+        assert!(doc.content[1].content.iter().any(
+            |n| matches!(n, ContentNode::Image { resource_id, .. } if resource_id == "image00001.png")
+        ));
+        assert_eq!(doc.toc.len(), 2);
+    }
+
+    /// INDX main record with a TAGX table, followed by one entry record.
+    fn index(tagx: &[[u8; 4]], entries: &[Vec<u8>]) -> [Vec<u8>; 2] {
+        let mut main = vec![0u8; 56];
+        main[..4].copy_from_slice(b"INDX");
+        put32(&mut main, 4, 56);
+        put32(&mut main, 24, 1);
+        main.extend_from_slice(b"TAGX");
+        main.extend_from_slice(&(12 + 4 * tagx.len() as u32).to_be_bytes());
+        main.extend_from_slice(&1u32.to_be_bytes());
+        for t in tagx {
+            main.extend_from_slice(t);
+        }
+
+        let mut data = vec![0u8; 28];
+        data[..4].copy_from_slice(b"INDX");
+        let mut positions = Vec::new();
+        for e in entries {
+            positions.push(data.len() as u16);
+            data.extend_from_slice(e);
+        }
+        let idxt = data.len() as u32;
+        put32(&mut data, 20, idxt);
+        put32(&mut data, 24, entries.len() as u32);
+        data.extend_from_slice(b"IDXT");
+        for p in positions {
+            data.extend_from_slice(&p.to_be_bytes());
+        }
+        [main, data]
+    }
+
+    /// Index entry: length-prefixed text, one control byte, varint values.
+    fn entry(text: &str, control: u8, values: &[u32]) -> Vec<u8> {
+        let mut e = vec![text.len() as u8];
+        e.extend_from_slice(text.as_bytes());
+        e.push(control);
+        for &v in values {
+            let mut bytes = vec![(v & 0x7F) as u8 | 0x80];
+            let mut rest = v >> 7;
+            while rest > 0 {
+                bytes.insert(0, (rest & 0x7F) as u8);
+                rest >>= 7;
+            }
+            e.extend_from_slice(&bytes);
+        }
+        e
+    }
+
+    #[test]
+    fn reassembles_kf8_skeleton_and_fragments() {
+        let skeleton =
+            "<html xmlns=\"http://www.w3.org/1999/xhtml\"><body><div></div></body></html>";
+        let fragment =
+            "<h1>Kay</h1><p>Eff <a href=\"kindle:pos:fid:0000:off:0000000000\">eight</a></p>";
+        let css = "p { margin: 0 }";
+        let markup = format!("{}{}", skeleton, fragment);
+        let text = format!("{}{}", markup, css);
+        let insert = skeleton.find("</div>").unwrap() as u32;
+
+        let mut fdst = b"FDST".to_vec();
+        fdst.extend_from_slice(&12u32.to_be_bytes());
+        fdst.extend_from_slice(&2u32.to_be_bytes());
+        for v in [0, markup.len(), markup.len(), text.len()] {
+            fdst.extend_from_slice(&(v as u32).to_be_bytes());
+        }
+
+        let mut rec0 = record0(1, text.as_bytes(), 1, NULL_INDEX, &[(100, b"K. Eight")]);
+        put32(&mut rec0, 0x24, 8);
+        put32(&mut rec0, 0xFC, 2);
+        put32(&mut rec0, 0xF8, 4);
+        put32(&mut rec0, 0xC0, 6);
+        let [skel_main, skel_data] = index(
+            &[[1, 1, 0x03, 0], [6, 2, 0x0C, 0], [0, 0, 0, 1]],
+            &[entry(
+                "SKEL0000000000",
+                0x05,
+                &[1, 0, skeleton.len() as u32],
+            )],
+        );
+        let [frag_main, frag_data] = index(
+            &[[3, 1, 0x01, 0], [6, 2, 0x02, 0], [0, 0, 0, 1]],
+            &[entry(
+                &format!("{:010}", insert),
+                0x03,
+                &[0, insert, fragment.len() as u32],
+            )],
+        );
+        let data = pdb(&[
+            rec0,
+            text.as_bytes().to_vec(),
+            skel_main,
+            skel_data,
+            frag_main,
+            frag_data,
+            fdst,
+        ]);
+
+        let doc = MobiReader::read(Cursor::new(data), &ReadOptions::default(), None).unwrap();
+        assert_eq!(doc.metadata.authors, ["K. Eight"]);
+        assert_eq!(doc.content.len(), 1);
+        let chapter = &doc.content[0];
+        assert_eq!(chapter.id, "part0000");
+        assert_eq!(chapter.title.as_deref(), Some("Kay"));
+        let ContentNode::Paragraph { children } = &chapter.content[1] else {
+            panic!("expected paragraph, got {:?}", chapter.content[1]);
+        };
+        assert!(children
+            .iter()
+            .any(|c| matches!(c, InlineNode::Link { href, .. } if href == "part0000.xhtml")));
+        let css_resource = doc.resources.get("flow00001.css").unwrap();
+        assert_eq!(css_resource.data, css.as_bytes());
+    }
+
+    #[test]
+    fn rejects_index_counts_beyond_the_file() {
+        let [main, mut data] = index(&[[1, 1, 1, 0], [0, 0, 0, 1]], &[entry("0", 1, &[0])]);
+        put32(&mut data, 24, u32::MAX);
+        let raw = pdb(&[main.clone(), data]);
+        assert!(read_index(&Pdb::parse(&raw).unwrap(), 0).is_err());
+
+        let mut main = main;
+        put32(&mut main, 24, u32::MAX);
+        let raw = pdb(&[main]);
+        assert!(read_index(&Pdb::parse(&raw).unwrap(), 0).is_err());
+    }
+
+    #[test]
+    fn rejects_fonts_over_the_size_limit() {
+        let mut record = b"FONT".to_vec();
+        for v in [u32::MAX, 1, 24, 0, 0] {
+            record.extend_from_slice(&v.to_be_bytes());
+        }
+        record.extend_from_slice(&[0x78, 0x9C, 0x03, 0x00, 0x00, 0x00, 0x00, 0x01]);
+        assert!(decode_font_record(&record, 1024).is_none());
+        assert!(decode_font_record(&record, u64::MAX).is_some());
+    }
+
+    #[test]
+    fn rejects_drm() {
+        let mut rec0 = record0(1, b"x", 1, NULL_INDEX, &[]);
+        rec0[12..14].copy_from_slice(&2u16.to_be_bytes());
+        let data = pdb(&[rec0, b"x".to_vec()]);
+        let err = MobiReader::read(Cursor::new(data), &ReadOptions::default(), None).unwrap_err();
+        assert!(matches!(
+            err,
+            ReadError::Security(SecurityError::DrmProtected { .. })
+        ));
+    }
+
+    #[test]
+    fn palmdoc_and_trailing_entries() {
+        // "abcabcabc" as literal "abc" + back-reference (distance 3, length 6),
+        // then a space+letter pair and a 2-byte literal run.
+        let pair: u16 = 0x8000 | (3 << 3) | (6 - 3);
+        let mut packed = b"abc".to_vec();
+        packed.extend_from_slice(&pair.to_be_bytes());
+        packed.push(b'x' ^ 0x80);
+        packed.extend_from_slice(&[0x02, 0xE9, 0x21]);
+        assert_eq!(palmdoc_decompress(&packed), b"abcabcabc x\xE9!");
+
+        // One trailing entry of 3 bytes (size byte included), preceded by a
+        // multibyte overlap of 1 + 1 bytes.
+        let record = b"text\x01zz\x83";
+        assert_eq!(trim_trailing_entries(record, 0b11), b"tex");
+    }
+
+    #[test]
+    fn huffcdic_decodes_literal_dictionary() {
+        // Every byte is an 8-bit terminal code; phrase 255 - b is the byte itself.
+        let mut huff = b"HUFF\x00\x00\x00\x18".to_vec();
+        huff.extend_from_slice(&24u32.to_be_bytes());
+        huff.extend_from_slice(&(24u32 + 1024).to_be_bytes());
+        huff.extend_from_slice(&[0u8; 8]);
+        for _ in 0..256 {
+            huff.extend_from_slice(&((255u32 << 8) | 0x80 | 8).to_be_bytes());
+        }
+        huff.extend_from_slice(&[0u8; 256]);
+
+        let mut cdic = b"CDIC\x00\x00\x00\x10".to_vec();
+        cdic.extend_from_slice(&256u32.to_be_bytes());
+        cdic.extend_from_slice(&8u32.to_be_bytes());
+        let table_len = 256 * 2;
+        for r in 0..256u16 {
+            cdic.extend_from_slice(&(table_len as u16 + r * 3).to_be_bytes());
+        }
+        for r in 0..256u16 {
+            cdic.extend_from_slice(&0x8001u16.to_be_bytes());
+            cdic.push(255 - r as u8);
+        }
+
+        let mut decoder = HuffCdic::new(&huff, &[&cdic]).unwrap();
+        assert_eq!(decoder.unpack(b"Kindle", 0), b"Kindle");
+    }
+}
@@ -1,20 +1,26 @@
 //! Format readers — each format implements FormatReader to parse into the Document IR.
 
+pub mod cbz;
+pub mod docx;
 pub mod epub;
+pub mod fb2;
+pub mod html;
+pub mod markdown;
+pub mod mobi;
+pub mod pdf;
 pub mod txt;
 
-// Phase 2
-// pub mod html;
-// pub mod markdown;
+pub(crate) mod resources;
+pub(crate) mod xml_tree;
 
-// Phase 3
-// pub mod pdf;
+use std::path::PathBuf;
 
-use crate::document::Document;
+use crate::document::{Chapter, ContentNode, Document, InlineNode, TocEntry};
 use crate::encoding::EncodingOptions;
 use crate::error::ReadError;
 use crate::progress::ProgressHandler;
 use crate::security::SecurityLimits;
+use crate::xml::escape_xml;
 
 pub trait FormatReader: Send + Sync {
     /// Check if this reader can handle the given input. Called with first 4KB.
@@ -38,6 +44,8 @@ pub struct ReadOptions {
     pub extract_cover: bool,
     pub parse_toc: bool,
     pub encoding: EncodingOptions,
+    /// Directory used to resolve relative references (e.g. local `<img>` files in HTML).
+    pub base_dir: Option<PathBuf>,
 }
 
 impl Default for ReadOptions {
@@ -47,6 +55,239 @@ impl Default for ReadOptions {
             extract_cover: true,
             parse_toc: true,
             encoding: EncodingOptions::default(),
+            base_dir: None,
         }
     }
 }
+
+/// HTML elements that never have content, written as empty XML elements when
+/// HTML is turned into XHTML.
+pub(crate) const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+/// What `html_to_xhtml` does with an element.
+pub(crate) enum XhtmlElement {
+    Keep,
+    /// Leave the element out but keep its content.
+    Unwrap,
+    /// Leave the element and its content out.
+    Skip,
+}
+
+/// Parse markup with html5ever's tolerant tree builder and write the tree
+/// back as well-formed XHTML, for the strict XHTML chapter parser.
+///
+/// `element` decides what happens to each element by name; elements whose
+/// names are not XML names are always unwrapped. `attribute` gets the element
+/// name and an attribute's (prefixed) name and value, and returns the name and
+/// value to write, or `None` to leave the attribute out. Nesting deeper than
+/// `max_depth` is dropped.
+pub(crate) fn html_to_xhtml(
+    html: &str,
+    max_depth: u32,
+    element: impl Fn(&str) -> XhtmlElement,
+    attribute: impl Fn(&str, &str, &str) -> Option<(String, String)>,
+) -> String {
+    use ego_tree::NodeRef;
+    use scraper::Node;
+
+    type AttributeHook<'a> = dyn Fn(&str, &str, &str) -> Option<(String, String)> + 'a;
+
+    fn is_xml_name(name: &str) -> bool {
+        name.starts_with(|c: char| c.is_alphabetic() || c == '_')
+            && name
+                .chars()
+                .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'))
+    }
+    fn walk(
+        node: NodeRef<Node>,
+        out: &mut String,
+        depth: u32,
+        max_depth: u32,
+        element: &dyn Fn(&str) -> XhtmlElement,
+        attribute: &AttributeHook,
+    ) {
+        if depth > max_depth {
+            return;
+        }
+        for child in node.children() {
+            match child.value() {
+                Node::Text(t) => out.push_str(&escape_xml(t)),
+                Node::Element(el) => {
+                    let name = el.name();
+                    let action = if is_xml_name(name) {
+                        element(name)
+                    } else {
+                        XhtmlElement::Unwrap
+                    };
+                    match action {
+                        XhtmlElement::Skip => continue,
+                        XhtmlElement::Unwrap => {
+                            walk(child, out, depth + 1, max_depth, element, attribute);
+                            continue;
+                        }
+                        XhtmlElement::Keep => {}
+                    }
+                    out.push('<');
+                    out.push_str(name);
+                    for (attr, value) in el.attrs.iter() {
+                        let attr = match &attr.prefix {
+                            Some(prefix) => format!("{}:{}", prefix, attr.local),
+                            None => attr.local.to_string(),
+                        };
+                        match attribute(name, &attr, value) {
+                            Some((attr, value)) if is_xml_name(&attr) => {
+                                out.push_str(&format!(" {}=\"{}\"", attr, escape_xml(&value)))
+                            }
+                            _ => {}
+                        }
+                    }
+                    if VOID_ELEMENTS.contains(&name) {
+                        out.push_str("/>");
+                    } else {
+                        out.push('>');
+                        walk(child, out, depth + 1, max_depth, element, attribute);
+                        out.push_str(&format!("</{}>", name));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let doc = scraper::Html::parse_document(html);
+    let mut out = String::with_capacity(html.len() + html.len() / 8);
+    walk(
+        doc.tree.root(),
+        &mut out,
+        0,
+        max_depth,
+        &element,
+        &attribute,
+    );
+    out
+}
+
+/// Guess a resource media type from a file name or path extension.
+pub(crate) fn guess_media_type(path: &str) -> &'static str {
+    let ext = path.rsplit('.').next().unwrap_or("").to_ascii_lowercase();
+    match ext.as_str() {
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        "css" => "text/css",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Split a flat list of blocks into chapters at each top-level level-1 heading.
+pub(crate) fn split_into_chapters(flow: Vec<ContentNode>) -> Vec<Chapter> {
+    let mut chapters: Vec<Chapter> = Vec::new();
+    let mut current = Vec::new();
+    let mut current_title: Option<String> = None;
+
+    for node in flow {
+        if let ContentNode::Heading { level: 1, children } = &node {
+            if !current.is_empty() {
+                chapters.push(Chapter {
+                    id: format!("chapter-{}", chapters.len() + 1),
+                    title: current_title.take(),
+                    content: std::mem::take(&mut current),
+                    text_direction: None,
+                });
+            }
+            current_title = Some(inline_text(children));
+        }
+        current.push(node);
+    }
+
+    if !current.is_empty() || chapters.is_empty() {
+        chapters.push(Chapter {
+            id: format!("chapter-{}", chapters.len() + 1),
+            title: current_title,
+            content: current,
+            text_direction: None,
+        });
+    }
+
+    chapters
+}
+
+/// Flat TOC with one entry per titled chapter, linking to the chapter id.
+pub(crate) fn toc_from_chapters(chapters: &[Chapter]) -> Vec<TocEntry> {
+    chapters
+        .iter()
+        .filter_map(|c| {
+            c.title.as_ref().map(|t| TocEntry {
+                title: t.clone(),
+                href: c.id.clone(),
+                children: Vec::new(),
+            })
+        })
+        .collect()
+}
+
+/// Plain text of a run of inlines, trimmed. Used for chapter and TOC titles.
+pub(crate) fn inline_text(nodes: &[InlineNode]) -> String {
+    fn collect(nodes: &[InlineNode], s: &mut String) {
+        for node in nodes {
+            match node {
+                InlineNode::Text(t) | InlineNode::Code(t) => s.push_str(t),
+                InlineNode::Emphasis(c)
+                | InlineNode::Strong(c)
+                | InlineNode::Superscript(c)
+                | InlineNode::Subscript(c)
+                | InlineNode::Link { children: c, .. }
+                | InlineNode::Span { children: c, .. } => collect(c, s),
+                InlineNode::Ruby { base, .. } => s.push_str(base),
+                InlineNode::LineBreak => s.push(' '),
+                InlineNode::NoteRef { .. } => {}
+            }
+        }
+    }
+    let mut s = String::new();
+    collect(nodes, &mut s);
+    s.trim().to_string()
+}
+
+/// Trim leading/trailing whitespace of a paragraph and drop empty text nodes
+/// and trailing line breaks.
+pub(crate) fn trim_inlines(nodes: &mut Vec<InlineNode>) {
+    while matches!(nodes.last(), Some(InlineNode::LineBreak)) {
+        nodes.pop();
+    }
+    if let Some(InlineNode::Text(t)) = nodes.first_mut() {
+        *t = t.trim_start().to_string();
+    }
+    if let Some(InlineNode::Text(t)) = nodes.last_mut() {
+        *t = t.trim_end().to_string();
+    }
+    nodes.retain(|n| !matches!(n, InlineNode::Text(t) if t.is_empty()));
+}
+
+/// Collapse each run of whitespace to a single space. Non-breaking spaces
+/// are content, not layout, and are kept.
+pub(crate) fn collapse_whitespace(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut last_space = false;
+    for c in s.chars() {
+        if c.is_whitespace() && c != '\u{a0}' {
+            if !last_space {
+                out.push(' ');
+            }
+            last_space = true;
+        } else {
+            out.push(c);
+            last_space = false;
+        }
+    }
+    out
+}
@@ -0,0 +1,1383 @@
+//! PDF reader: text and structure recovery via lopdf.
+//!
+//! Text runs are taken from page content streams, grouped into lines by
+//! baseline and put in reading order (top to bottom, then left to right).
+//! Paragraphs are rebuilt from line spacing and
+//! indentation, lines set noticeably larger than the body text become headings,
+//! and repeated running headers / page numbers are dropped. The outline
+//! (bookmarks) drives chapter splitting and the TOC; the Info dictionary fills
+//! `Metadata`. Embedded images are not extracted.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Seek};
+
+use lopdf::content::Content;
+use lopdf::{Dictionary, Encoding, Object, ObjectId};
+
+use crate::detect::{DetectResult, Format};
+use crate::document::*;
+use crate::error::{ReadError, SecurityError};
+use crate::progress::{emit_progress, ProgressHandler};
+use crate::readers::{
+    collapse_whitespace, split_into_chapters, toc_from_chapters, trim_inlines, FormatReader,
+    ReadOptions,
+};
+use crate::security;
+
+pub struct PdfReader;
+
+impl FormatReader for PdfReader {
+    fn detect(header: &[u8]) -> DetectResult {
+        let confidence = if header.starts_with(b"%PDF-") {
+            1.0
+        } else {
+            0.0
+        };
+        DetectResult {
+            format: Format::Pdf,
+            confidence,
+            mime_type: Format::Pdf.mime_type(),
+        }
+    }
+
+    fn read<R: Read + Seek>(
+        mut input: R,
+        opts: &ReadOptions,
+        progress: Option<&dyn ProgressHandler>,
+    ) -> Result<Document, ReadError> {
+        let mut raw = Vec::new();
+        input.read_to_end(&mut raw)?;
+        security::check_total_size(raw.len() as u64, &opts.security)?;
+
+        let mut pdf = lopdf::Document::load_mem(&raw).map_err(pdf_err)?;
+        if pdf.is_encrypted() && pdf.decrypt("").is_err() {
+            return Err(SecurityError::DrmProtected {
+                format: "PDF".into(),
+                drm_type: "password protection".into(),
+            }
+            .into());
+        }
+
+        let pages = pdf.get_pages();
+        let total = pages.len() as u64 + 1;
+        let page_numbers: HashMap<ObjectId, u32> =
+            pages.iter().map(|(num, id)| (*id, *num)).collect();
+
+        // 1. Text lines per page
+        let mut page_lines = Vec::with_capacity(pages.len());
+        for (i, (num, page_id)) in pages.iter().enumerate() {
+            let lines = match extract_page_lines(&pdf, *page_id, *num) {
+                Ok(lines) => lines,
+                Err(e) => {
+                    tracing::warn!("Skipping text on PDF page {}: {}", num, e);
+                    Vec::new()
+                }
+            };
+            page_lines.push(lines);
+            emit_progress(
+                progress,
+                "Reading PDF",
+                i as u64 + 1,
+                Some(total),
+                Some(&format!("Page {}/{}", i + 1, pages.len())),
+            );
+        }
+        strip_running_lines(&mut page_lines);
+        let lines: Vec<Line> = page_lines.into_iter().flatten().collect();
+
+        // 2. Paragraphs and headings
+        let blocks = build_blocks(&lines);
+
+        // 3. Chapters and TOC, from the outline when there is one
+        let outline = read_outline(&pdf, &page_numbers, &opts.security);
+        let (chapters, toc) = if outline.iter().any(|o| o.page.is_some()) {
+            chapters_from_outline(blocks, &outline)
+        } else {
+            let chapters = split_into_chapters(blocks.into_iter().map(|(_, n)| n).collect());
+            let toc = toc_from_chapters(&chapters);
+            (chapters, toc)
+        };
+        let toc = if opts.parse_toc { toc } else { Vec::new() };
+
+        // 4. Metadata
+        let mut metadata = read_info(&pdf);
+        if metadata.title.is_none() {
+            metadata.title = chapters.iter().find_map(|c| c.title.clone());
+        }
+        metadata.page_count = Some(pages.len() as u32);
+
+        emit_progress(progress, "Reading PDF", total, Some(total), Some("Done"));
+
+        Ok(Document {
+            metadata,
+            toc,
+            content: chapters,
+            resources: ResourceMap::new(),
+            text_direction: TextDirection::Ltr,
+            epub_version: None,
+            diagnostics: Vec::new(),
+        })
+    }
+}
+
+fn pdf_err(e: lopdf::Error) -> ReadError {
+    ReadError::MalformedFile {
+        format: "PDF".into(),
+        detail: e.to_string(),
+    }
+}
+
+// --- Text extraction ---
+
+/// A piece of text drawn in one font at one position.
+#[derive(Debug, Clone)]
+struct Run {
+    text: String,
+    bold: bool,
+    italic: bool,
+}
+
+/// Runs sharing a baseline, in drawing order.
+#[derive(Debug, Clone)]
+struct Line {
+    page: u32,
+    x: f32,
+    y: f32,
+    size: f32,
+    runs: Vec<Run>,
+}
+
+impl Line {
+    fn text(&self) -> String {
+        self.runs.iter().map(|r| r.text.as_str()).collect()
+    }
+}
+
+struct FontInfo<'a> {
+    encoding: Option<Encoding<'a>>,
+    two_byte: bool,
+    /// Glyph widths in 1/1000 text-space units, indexed from `first_char`.
+    widths: Vec<f32>,
+    first_char: u32,
+    default_width: f32,
+    bold: bool,
+    italic: bool,
+}
+
+impl FontInfo<'_> {
+    fn decode(&self, bytes: &[u8]) -> String {
+        self.encoding
+            .as_ref()
+            .and_then(|e| e.bytes_to_string(bytes).ok())
+            .unwrap_or_else(|| bytes.iter().map(|&b| b as char).collect())
+    }
+
+    /// Advance width of `bytes` in text-space units (before font size scaling).
+    fn width(&self, bytes: &[u8]) -> f32 {
+        let code_width = |code: u32| {
+            code.checked_sub(self.first_char)
+                .and_then(|i| self.widths.get(i as usize).copied())
+                .filter(|w| *w > 0.0)
+                .unwrap_or(self.default_width)
+        };
+        let total: f32 = if self.two_byte {
+            bytes
+                .chunks(2)
+                .map(|c| code_width(c.iter().fold(0u32, |acc, b| (acc << 8) | *b as u32)))
+                .sum()
+        } else {
+            bytes.iter().map(|b| code_width(*b as u32)).sum()
+        };
+        total / 1000.0
+    }
+}
+
+fn load_font<'a>(pdf: &'a lopdf::Document, font: &'a Dictionary) -> FontInfo<'a> {
+    let encoding = font.get_font_encoding(pdf).ok();
+    let two_byte = matches!(encoding, Some(Encoding::UnicodeMapEncoding(_)))
+        || font
+            .get(b"Subtype")
+            .and_then(Object::as_name)
+            .is_ok_and(|s| s == b"Type0");
+    let base_font = font
+        .get(b"BaseFont")
+        .and_then(Object::as_name_str)
+        .unwrap_or("")
+        .to_ascii_lowercase();
+
+    let first_char = font
+        .get(b"FirstChar")
+        .and_then(Object::as_i64)
+        .unwrap_or(0)
+        .max(0) as u32;
+    let widths = font
+        .get_deref(b"Widths", pdf)
+        .and_then(Object::as_array)
+        .map(|a| a.iter().map(|w| w.as_float().unwrap_or(0.0)).collect())
+        .unwrap_or_default();
+    // CID fonts: DW on the descendant font, usually a full em.
+    let default_width = if two_byte {
+        font.get_deref(b"DescendantFonts", pdf)
+            .and_then(Object::as_array)
+            .ok()
+            .and_then(|a| a.first())
+            .and_then(|d| pdf.dereference(d).ok())
+            .and_then(|(_, d)| d.as_dict().ok())
+            .and_then(|d| d.get(b"DW").and_then(Object::as_float).ok())
+            .unwrap_or(1000.0)
+    } else {
+        500.0
+    };
+
+    FontInfo {
+        encoding,
+        two_byte,
+        widths,
+        first_char,
+        default_width,
+        bold: base_font.contains("bold")
+            || base_font.contains("black")
+            || base_font.contains("heavy"),
+        italic: base_font.contains("italic") || base_font.contains("oblique"),
+    }
+}
+
+type Matrix = [f32; 6];
+
+const IDENTITY: Matrix = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+fn multiply(m: &Matrix, n: &Matrix) -> Matrix {
+    [
+        m[0] * n[0] + m[1] * n[2],
+        m[0] * n[1] + m[1] * n[3],
+        m[2] * n[0] + m[3] * n[2],
+        m[2] * n[1] + m[3] * n[3],
+        m[4] * n[0] + m[5] * n[2] + n[4],
+        m[4] * n[1] + m[5] * n[3] + n[5],
+    ]
+}
+
+fn number(o: &Object) -> f32 {
+    o.as_float().unwrap_or(0.0)
+}
+
+fn matrix_operands(ops: &[Object]) -> Option<Matrix> {
+    if ops.len() < 6 {
+        return None;
+    }
+    Some([
+        number(&ops[0]),
+        number(&ops[1]),
+        number(&ops[2]),
+        number(&ops[3]),
+        number(&ops[4]),
+        number(&ops[5]),
+    ])
+}
+
+/// Interprets the text operators of one content stream.
+struct TextState<'a> {
+    fonts: &'a HashMap<Vec<u8>, FontInfo<'a>>,
+    font: Option<&'a FontInfo<'a>>,
+    font_size: f32,
+    leading: f32,
+    ctm: Matrix,
+    ctm_stack: Vec<Matrix>,
+    tm: Matrix,
+    tlm: Matrix,
+    page: u32,
+    lines: Vec<Line>,
+    /// Where the last shown string ended (device x), for word-gap detection.
+    last_end: Option<f32>,
+}
+
+impl<'a> TextState<'a> {
+    fn rendering_matrix(&self) -> Matrix {
+        multiply(&self.tm, &self.ctm)
+    }
+
+    fn move_line(&mut self, tx: f32, ty: f32) {
+        self.tlm = multiply(&[1.0, 0.0, 0.0, 1.0, tx, ty], &self.tlm);
+        self.tm = self.tlm;
+    }
+
+    fn show(&mut self, bytes: &[u8]) {
+        let Some(font) = self.font else {
+            return;
+        };
+        let text = font.decode(bytes);
+        let m = self.rendering_matrix();
+        let size = self.font_size * (m[2] * m[2] + m[3] * m[3]).sqrt();
+        let (x, y) = (m[4], m[5]);
+        self.push_text(text, x, y, size, font.bold, font.italic);
+
+        let advance = font.width(bytes) * self.font_size;
+        self.tm = multiply(&[1.0, 0.0, 0.0, 1.0, advance, 0.0], &self.tm);
+    }
+
+    fn push_text(&mut self, text: String, x: f32, y: f32, size: f32, bold: bool, italic: bool) {
+        if text.is_empty() || size <= 0.0 {
+            return;
+        }
+        let tolerance = size.max(1.0) * 0.4;
+        let same_line = self
+            .lines
+            .last()
+            .is_some_and(|l| (l.y - y).abs() <= tolerance && x >= l.x - tolerance);
+        if same_line {
+            let gap = self.gap_before(x, size);
+            let line = self.lines.last_mut().expect("checked above");
+            // A visible gap between runs on one baseline is a word space.
+            let space = gap
+                && line
+                    .runs
+                    .last()
+                    .is_some_and(|r| !r.text.ends_with(' ') && !text.starts_with(' '));
+            line.size = line.size.max(size);
+            match line.runs.last_mut() {
+                Some(r) if r.bold == bold && r.italic == italic => {
+                    if space {
+                        r.text.push(' ');
+                    }
+                    r.text.push_str(&text);
+                }
+                _ => {
+                    let text = if space { format!(" {}", text) } else { text };
+                    line.runs.push(Run { text, bold, italic });
+                }
+            }
+            return;
+        }
+        self.lines.push(Line {
+            page: self.page,
+            x,
+            y,
+            size,
+            runs: vec![Run { text, bold, italic }],
+        });
+    }
+
+    /// Whether `x` lies past the end of the previous run by more than a sliver.
+    fn gap_before(&self, x: f32, size: f32) -> bool {
+        self.last_end.map_or(true, |end| x - end > size * 0.15)
+    }
+}
+
+fn extract_page_lines(
+    pdf: &lopdf::Document,
+    page_id: ObjectId,
+    page_num: u32,
+) -> Result<Vec<Line>, lopdf::Error> {
+    let fonts: HashMap<Vec<u8>, FontInfo> = pdf
+        .get_page_fonts(page_id)?
+        .into_iter()
+        .map(|(name, dict)| (name, load_font(pdf, dict)))
+        .collect();
+    let content = Content::decode(&pdf.get_page_content(page_id)?)?;
+
+    let mut state = TextState {
+        fonts: &fonts,
+        font: None,
+        font_size: 0.0,
+        leading: 0.0,
+        ctm: IDENTITY,
+        ctm_stack: Vec::new(),
+        tm: IDENTITY,
+        tlm: IDENTITY,
+        page: page_num,
+        lines: Vec::new(),
+        last_end: None,
+    };
+
+    for op in &content.operations {
+        let ops = &op.operands;
+        match op.operator.as_str() {
+            "q" => state.ctm_stack.push(state.ctm),
+            "Q" => state.ctm = state.ctm_stack.pop().unwrap_or(IDENTITY),
+            "cm" => {
+                if let Some(m) = matrix_operands(ops) {
+                    state.ctm = multiply(&m, &state.ctm);
+                }
+            }
+            "BT" => {
+                state.tm = IDENTITY;
+                state.tlm = IDENTITY;
+            }
+            "Tf" => {
+                if let [name, size, ..] = ops.as_slice() {
+                    state.font = name.as_name().ok().and_then(|n| state.fonts.get(n));
+                    state.font_size = number(size);
+                }
+            }
+            "TL" => state.leading = ops.first().map(number).unwrap_or(0.0),
+            "Td" => {
+                if let [tx, ty, ..] = ops.as_slice() {
+                    state.move_line(number(tx), number(ty));
+                }
+            }
+            "TD" => {
+                if let [tx, ty, ..] = ops.as_slice() {
+                    state.leading = -number(ty);
+                    state.move_line(number(tx), number(ty));
+                }
+            }
+            "Tm" => {
+                if let Some(m) = matrix_operands(ops) {
+                    state.tlm = m;
+                    state.tm = m;
+                }
+            }
+            "T*" => state.move_line(0.0, -state.leading),
+            "Tj" => {
+                if let Some(Object::String(bytes, _)) = ops.first() {
+                    state.show_tracked(bytes);
+                }
+            }
+            "'" => {
+                state.move_line(0.0, -state.leading);
+                if let Some(Object::String(bytes, _)) = ops.first() {
+                    state.show_tracked(bytes);
+                }
+            }
+            "\"" => {
+                state.move_line(0.0, -state.leading);
+                if let Some(Object::String(bytes, _)) = ops.get(2) {
+                    state.show_tracked(bytes);
+                }
+            }
+            "TJ" => {
+                if let Some(Object::Array(items)) = ops.first() {
+                    for item in items {
+                        match item {
+                            Object::String(bytes, _) => state.show_tracked(bytes),
+                            other => {
+                                // Negative adjustments move right; large ones are word gaps.
+                                let adjust = number(other);
+                                let shift = -adjust / 1000.0 * state.font_size;
+                                state.tm = multiply(&[1.0, 0.0, 0.0, 1.0, shift, 0.0], &state.tm);
+                            }
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut lines: Vec<Line> = state
+        .lines
+        .into_iter()
+        .filter(|l| !l.text().trim().is_empty())
+        .collect();
+    sort_reading_order(&mut lines);
+    Ok(lines)
+}
+
+/// Order a page's lines top to bottom, then left to right. Content streams
+/// are free to draw text in any order.
+fn sort_reading_order(lines: &mut [Line]) {
+    lines.sort_by(|a, b| b.y.total_cmp(&a.y).then(a.x.total_cmp(&b.x)));
+}
+
+impl TextState<'_> {
+    /// Show a string and remember where it ended, for word-gap detection.
+    fn show_tracked(&mut self, bytes: &[u8]) {
+        self.show(bytes);
+        let m = self.rendering_matrix();
+        self.last_end = Some(m[4]);
+    }
+}
+
+/// Drop page numbers and running headers/footers: the top or bottom line of a
+/// page that is only a number, or whose text (digits ignored) repeats at the
+/// same edge on at least three pages. Edges go by position rather than drawing
+/// order, since headers and footers are often drawn before the body.
+fn strip_running_lines(pages: &mut [Vec<Line>]) {
+    let key = |l: &Line| -> String {
+        l.text()
+            .chars()
+            .filter(|c| !c.is_ascii_digit())
+            .collect::<String>()
+            .trim()
+            .to_lowercase()
+    };
+    let mut counts: HashMap<(bool, String), usize> = HashMap::new();
+    for lines in pages.iter() {
+        if let Some(top) = edge_line(lines, true) {
+            *counts.entry((true, key(&lines[top]))).or_default() += 1;
+        }
+        if lines.len() > 1 {
+            if let Some(bottom) = edge_line(lines, false) {
+                *counts.entry((false, key(&lines[bottom]))).or_default() += 1;
+            }
+        }
+    }
+    let running = |top: bool, l: &Line| {
+        let k = key(l);
+        k.is_empty() || counts.get(&(top, k)).copied().unwrap_or(0) >= 3
+    };
+    for lines in pages.iter_mut() {
+        if lines.len() > 1 {
+            if let Some(bottom) = edge_line(lines, false).filter(|&i| running(false, &lines[i])) {
+                lines.remove(bottom);
+            }
+        }
+        if let Some(top) = edge_line(lines, true).filter(|&i| running(true, &lines[i])) {
+            lines.remove(top);
+        }
+    }
+}
+
+/// Index of the highest (`top`) or lowest line on a page.
+fn edge_line(lines: &[Line], top: bool) -> Option<usize> {
+    let by_y = |a: &(usize, &Line), b: &(usize, &Line)| a.1.y.total_cmp(&b.1.y);
+    let indexed = lines.iter().enumerate();
+    let edge = if top {
+        indexed.max_by(by_y)
+    } else {
+        indexed.min_by(by_y)
+    };
+    edge.map(|(i, _)| i)
+}
+
+// --- Layout → IR ---
+
+fn size_key(size: f32) -> i32 {
+    (size * 2.0).round() as i32
+}
+
+/// Rebuild headings and paragraphs, each tagged with its page number.
+fn build_blocks(lines: &[Line]) -> Vec<(u32, ContentNode)> {
+    if lines.is_empty() {
+        return Vec::new();
+    }
+
+    // Body size: the size carrying the most characters.
+    let mut weight: HashMap<i32, usize> = HashMap::new();
+    for l in lines {
+        *weight.entry(size_key(l.size)).or_default() += l.text().chars().count();
+    }
+    let body_key = weight
+        .iter()
+        .max_by_key(|(k, w)| (**w, -**k))
+        .map(|(k, _)| *k)
+        .unwrap_or(0);
+    let body_size = body_key as f32 / 2.0;
+
+    // Heading levels: distinct larger sizes, largest first.
+    let mut heading_keys: Vec<i32> = weight
+        .keys()
+        .copied()
+        .filter(|k| (*k as f32 / 2.0) >= body_size * 1.15)
+        .collect();
+    heading_keys.sort_unstable_by(|a, b| b.cmp(a));
+    let heading_level = |size: f32| -> Option<u8> {
+        heading_keys
+            .iter()
+            .position(|k| *k == size_key(size))
+            .map(|i| (i + 1).min(6) as u8)
+    };
+
+    // Typical gap between consecutive body lines on a page (lower quartile, so
+    // paragraph spacing does not inflate it).
+    let mut gaps: Vec<f32> = lines
+        .windows(2)
+        .filter(|w| {
+            w[0].page == w[1].page
+                && size_key(w[0].size) == body_key
+                && size_key(w[1].size) == body_key
+        })
+        .map(|w| w[0].y - w[1].y)
+        .filter(|g| *g > 0.0)
+        .collect();
+    gaps.sort_by(|a, b| a.total_cmp(b));
+    let line_gap = gaps.get(gaps.len() / 4).copied().unwrap_or(body_size * 1.2);
+
+    let mut blocks: Vec<(u32, ContentNode)> = Vec::new();
+    let mut para: Vec<Run> = Vec::new();
+    let mut para_page = 0;
+    let mut para_x = 0.0;
+    let mut prev: Option<&Line> = None;
+    let mut prev_heading: Option<(u8, usize)> = None;
+
+    for line in lines {
+        let text = line.text();
+        let level = if text.chars().count() <= 200 {
+            heading_level(line.size)
+        } else {
+            None
+        };
+
+        if let Some(level) = level {
+            flush_paragraph(&mut blocks, &mut para, para_page);
+            let continues = prev_heading.is_some_and(|(l, idx)| {
+                l == level
+                    && idx + 1 == blocks.len()
+                    && prev.is_some_and(|p| p.page == line.page && p.y - line.y <= line.size * 1.6)
+            });
+            if continues {
+                if let Some((_, ContentNode::Heading { children, .. })) = blocks.last_mut() {
+                    push_runs(
+                        children,
+                        &[Run {
+                            text: format!(" {}", text.trim()),
+                            bold: false,
+                            italic: false,
+                        }],
+                    );
+                }
+            } else {
+                blocks.push((
+                    line.page,
+                    ContentNode::Heading {
+                        level,
+                        children: vec![InlineNode::Text(collapse_whitespace(&text))],
+                    },
+                ));
+            }
+            prev_heading = Some((level, blocks.len() - 1));
+            prev = Some(line);
+            continue;
+        }
+        prev_heading = None;
+
+        let starts_paragraph = match prev {
+            None => true,
+            Some(_) if para.is_empty() => true,
+            Some(p) if p.page != line.page => {
+                // Carry a paragraph over a page break unless the last line ended a sentence.
+                let last = p.text();
+                let last = last.trim_end();
+                last.ends_with(['.', '!', '?', ':', '"', '\u{201D}'])
+                    || !line.text().trim_start().starts_with(char::is_lowercase)
+            }
+            Some(p) => {
+                let gap = p.y - line.y;
+                gap > line_gap * 1.4
+                    || gap < 0.0
+                    || size_key(p.size) != size_key(line.size)
+                    || line.x > para_x + line.size
+            }
+        };
+        if starts_paragraph {
+            flush_paragraph(&mut blocks, &mut para, para_page);
+            para_page = line.page;
+            para_x = line.x;
+        } else {
+            join_line(&mut para);
+        }
+        para.extend(line.runs.iter().cloned());
+        // Indented first lines shouldn't make the following lines look indented.
+        para_x = para_x.min(line.x);
+        prev = Some(line);
+    }
+    flush_paragraph(&mut blocks, &mut para, para_page);
+    blocks
+}
+
+/// Join the next line onto a paragraph: de-hyphenate or add a space.
+fn join_line(para: &mut [Run]) {
+    let Some(last) = para.last_mut() else {
+        return;
+    };
+    let trimmed_len = last.text.trim_end().len();
+    last.text.truncate(trimmed_len);
+    let prev_char = last.text.chars().rev().nth(1);
+    if last.text.ends_with('-') && prev_char.is_some_and(char::is_alphabetic) {
+        last.text.pop();
+    } else {
+        last.text.push(' ');
+    }
+}
+
+fn flush_paragraph(blocks: &mut Vec<(u32, ContentNode)>, para: &mut Vec<Run>, page: u32) {
+    if para.is_empty() {
+        return;
+    }
+    let mut children = Vec::new();
+    push_runs(&mut children, para);
+    para.clear();
+    trim_inlines(&mut children);
+    if !children.is_empty() {
+        blocks.push((page, ContentNode::Paragraph { children }));
+    }
+}
+
+fn push_runs(children: &mut Vec<InlineNode>, runs: &[Run]) {
+    for run in runs {
+        let text = collapse_whitespace(&run.text);
+        let node = match (run.bold, run.italic) {
+            (false, false) => InlineNode::Text(text),
+            (true, false) => InlineNode::Strong(vec![InlineNode::Text(text)]),
+            (false, true) => InlineNode::Emphasis(vec![InlineNode::Text(text)]),
+            (true, true) => {
+                InlineNode::Strong(vec![InlineNode::Emphasis(vec![InlineNode::Text(text)])])
+            }
+        };
+        // Merge with the previous node when the styling matches.
+        match (children.last_mut(), node) {
+            (Some(InlineNode::Text(prev)), InlineNode::Text(t)) => prev.push_str(&t),
+            (Some(InlineNode::Strong(prev)), InlineNode::Strong(next))
+            | (Some(InlineNode::Emphasis(prev)), InlineNode::Emphasis(next))
+                if matches!(
+                    (prev.as_slice(), next.as_slice()),
+                    ([InlineNode::Text(_)], [InlineNode::Text(_)])
+                ) =>
+            {
+                if let ([InlineNode::Text(a)], [InlineNode::Text(b)]) =
+                    (prev.as_mut_slice(), next.as_slice())
+                {
+                    a.push_str(b);
+                }
+            }
+            (_, node) => children.push(node),
+        }
+    }
+}
+
+// --- Outline ---
+
+struct OutlineItem {
+    title: String,
+    page: Option<u32>,
+    children: Vec<OutlineItem>,
+}
+
+fn read_outline(
+    pdf: &lopdf::Document,
+    page_numbers: &HashMap<ObjectId, u32>,
+    limits: &security::SecurityLimits,
+) -> Vec<OutlineItem> {
+    let Ok(catalog) = pdf.catalog() else {
+        return Vec::new();
+    };
+    let Some(root) = catalog
+        .get(b"Outlines")
+        .ok()
+        .and_then(|o| pdf.dereference(o).ok())
+        .and_then(|(_, o)| o.as_dict().ok())
+    else {
+        return Vec::new();
+    };
+    let mut seen = HashSet::new();
+    walk_outline(
+        pdf,
+        catalog,
+        root.get(b"First").ok(),
+        page_numbers,
+        limits,
+        0,
+        &mut seen,
+    )
+}
+
+fn walk_outline(
+    pdf: &lopdf::Document,
+    catalog: &Dictionary,
+    first: Option<&Object>,
+    page_numbers: &HashMap<ObjectId, u32>,
+    limits: &security::SecurityLimits,
+    depth: u32,
+    seen: &mut HashSet<ObjectId>,
+) -> Vec<OutlineItem> {
+    let mut items = Vec::new();
+    if depth > limits.max_nesting_depth {
+        tracing::warn!("PDF outline nested too deeply; truncating");
+        return items;
+    }
+    let mut next = first.cloned();
+    while let Some(Object::Reference(id)) = next {
+        if !seen.insert(id) {
+            break;
+        }
+        let Ok(node) = pdf.get_dictionary(id) else {
+            break;
+        };
+        let title = node
+            .get(b"Title")
+            .ok()
+            .and_then(|t| pdf.dereference(t).ok())
+            .and_then(|(_, t)| lopdf::decode_text_string(t).ok())
+            .map(|t| collapse_whitespace(t.trim()))
+            .unwrap_or_default();
+        let dest = node.get(b"Dest").ok().or_else(|| {
+            node.get(b"A")
+                .ok()
+                .and_then(|a| pdf.dereference(a).ok())
+                .and_then(|(_, a)| a.as_dict().ok())
+                .and_then(|a| a.get(b"D").ok())
+        });
+        let page = dest.and_then(|d| dest_page(pdf, catalog, d, page_numbers));
+        let children = walk_outline(
+            pdf,
+            catalog,
+            node.get(b"First").ok(),
+            page_numbers,
+            limits,
+            depth + 1,
+            seen,
+        );
+        if !title.is_empty() {
+            items.push(OutlineItem {
+                title,
+                page,
+                children,
+            });
+        }
+        next = node.get(b"Next").ok().cloned();
+    }
+    items
+}
+
+/// Resolve an outline destination (explicit array, `/D` dictionary or named
+/// destination) to a 1-based page number.
+fn dest_page(
+    pdf: &lopdf::Document,
+    catalog: &Dictionary,
+    dest: &Object,
+    page_numbers: &HashMap<ObjectId, u32>,
+) -> Option<u32> {
+    let (_, dest) = pdf.dereference(dest).ok()?;
+    match dest {
+        Object::Array(parts) => match parts.first()? {
+            Object::Reference(page) => page_numbers.get(page).copied(),
+            // Remote-style destinations give a 0-based page index.
+            Object::Integer(i) => u32::try_from(*i).ok().map(|i| i + 1),
+            _ => None,
+        },
+        Object::Dictionary(d) => dest_page(pdf, catalog, d.get(b"D").ok()?, page_numbers),
+        Object::Name(name) | Object::String(name, _) => {
+            let target = named_destination(pdf, catalog, name)?;
+            dest_page(pdf, catalog, &target, page_numbers)
+        }
+        _ => None,
+    }
+}
+
+fn named_destination(pdf: &lopdf::Document, catalog: &Dictionary, name: &[u8]) -> Option<Object> {
+    // PDF 1.1 style: /Dests dictionary in the catalog.
+    if let Some(dests) = catalog
+        .get(b"Dests")
+        .ok()
+        .and_then(|d| pdf.dereference(d).ok())
+        .and_then(|(_, d)| d.as_dict().ok())
+    {
+        if let Ok(d) = dests.get(name) {
+            return Some(d.clone());
+        }
+    }
+    // PDF 1.2+: /Names /Dests name tree.
+    let tree = catalog
+        .get(b"Names")
+        .ok()
+        .and_then(|n| pdf.dereference(n).ok())
+        .and_then(|(_, n)| n.as_dict().ok())?
+        .get(b"Dests")
+        .ok()
+        .and_then(|d| pdf.dereference(d).ok())
+        .and_then(|(_, d)| d.as_dict().ok())?;
+    search_name_tree(pdf, tree, name, 0)
+}
+
+fn search_name_tree(
+    pdf: &lopdf::Document,
+    node: &Dictionary,
+    name: &[u8],
+    depth: u32,
+) -> Option<Object> {
+    if depth > 32 {
+        return None;
+    }
+    if let Ok(names) = node.get(b"Names").and_then(Object::as_array) {
+        for pair in names.chunks(2) {
+            if let [Object::String(key, _), value] = pair {
+                if key == name {
+                    return Some(value.clone());
+                }
+            }
+        }
+    }
+    let kids = node.get(b"Kids").and_then(Object::as_array).ok()?;
+    kids.iter().find_map(|kid| {
+        let (_, kid) = pdf.dereference(kid).ok()?;
+        search_name_tree(pdf, kid.as_dict().ok()?, name, depth + 1)
+    })
+}
+
+/// Split blocks at the pages of top-level outline entries; the TOC mirrors the
+/// outline with each entry pointing at the chapter containing its page.
+fn chapters_from_outline(
+    blocks: Vec<(u32, ContentNode)>,
+    outline: &[OutlineItem],
+) -> (Vec<Chapter>, Vec<TocEntry>) {
+    let mut starts: Vec<(u32, &str)> = outline
+        .iter()
+        .filter_map(|o| o.page.map(|p| (p, o.title.as_str())))
+        .collect();
+    starts.sort_by_key(|(p, _)| *p);
+    starts.dedup_by_key(|(p, _)| *p);
+
+    let mut chapters: Vec<(u32, Chapter)> = Vec::new();
+    let mut next_start = 0;
+    for (page, node) in blocks {
+        let mut new_title = None;
+        while next_start < starts.len() && page >= starts[next_start].0 {
+            new_title = Some(starts[next_start]);
+            next_start += 1;
+        }
+        if new_title.is_some() || chapters.is_empty() {
+            let (start_page, title) =
+                new_title.map_or((page, None), |(p, t)| (p, Some(t.to_string())));
+            chapters.push((
+                start_page,
+                Chapter {
+                    id: format!("chapter-{}", chapters.len() + 1),
+                    title,
+                    content: Vec::new(),
+                    text_direction: None,
+                },
+            ));
+        }
+        if let Some((_, chapter)) = chapters.last_mut() {
+            chapter.content.push(node);
+        }
+    }
+    if chapters.is_empty() {
+        chapters.push((
+            1,
+            Chapter {
+                id: "chapter-1".into(),
+                title: None,
+                content: Vec::new(),
+                text_direction: None,
+            },
+        ));
+    }
+
+    fn to_toc(items: &[OutlineItem], chapters: &[(u32, Chapter)]) -> Vec<TocEntry> {
+        items
+            .iter()
+            .map(|item| {
+                let chapter = item
+                    .page
+                    .and_then(|p| chapters.iter().rev().find(|(start, _)| *start <= p))
+                    .or_else(|| chapters.first())
+                    .map(|(_, c)| c.id.clone())
+                    .unwrap_or_default();
+                TocEntry {
+                    title: item.title.clone(),
+                    href: chapter,
+                    children: to_toc(&item.children, chapters),
+                }
+            })
+            .collect()
+    }
+    let toc = to_toc(outline, &chapters);
+    (chapters.into_iter().map(|(_, c)| c).collect(), toc)
+}
+
+// --- Info dictionary ---
+
+fn read_info(pdf: &lopdf::Document) -> Metadata {
+    let mut metadata = Metadata::default();
+    if let Some(lang) = pdf
+        .catalog()
+        .ok()
+        .and_then(|c| c.get(b"Lang").ok())
+        .and_then(|l| lopdf::decode_text_string(l).ok())
+        .filter(|l| !l.trim().is_empty())
+    {
+        metadata.language = Some(lang.trim().to_string());
+    }
+
+    let Some(info) = pdf
+        .trailer
+        .get(b"Info")
+        .ok()
+        .and_then(|i| pdf.dereference(i).ok())
+        .and_then(|(_, i)| i.as_dict().ok())
+    else {
+        return metadata;
+    };
+
+    for (key, value) in info.iter() {
+        let Some(text) = pdf
+            .dereference(value)
+            .ok()
+            .and_then(|(_, v)| lopdf::decode_text_string(v).ok())
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty())
+        else {
+            continue;
+        };
+        match key.as_slice() {
+            b"Title" => metadata.title = Some(text),
+            b"Author" => {
+                metadata.authors = text
+                    .split([';', '&'])
+                    .map(|a| a.trim().to_string())
+                    .filter(|a| !a.is_empty())
+                    .collect()
+            }
+            b"Subject" => metadata.description = Some(text),
+            b"Keywords" => {
+                metadata.subjects = text
+                    .split([',', ';'])
+                    .map(|s| s.trim().to_string())
+                    .filter(|s| !s.is_empty())
+                    .collect()
+            }
+            b"CreationDate" => metadata.publish_date = Some(pdf_date(&text)),
+            other => {
+                metadata
+                    .custom
+                    .insert(String::from_utf8_lossy(other).to_lowercase(), text);
+            }
+        }
+    }
+    metadata
+}
+
+/// `D:YYYYMMDDHHmmSS…` → `YYYY-MM-DD` (or as much of it as is present).
+fn pdf_date(raw: &str) -> String {
+    let digits: String = raw
+        .trim_start_matches("D:")
+        .chars()
+        .take_while(|c| c.is_ascii_digit())
+        .collect();
+    match digits.len() {
+        n if n >= 8 => format!("{}-{}-{}", &digits[..4], &digits[4..6], &digits[6..8]),
+        6 | 7 => format!("{}-{}", &digits[..4], &digits[4..6]),
+        4 | 5 => digits[..4].to_string(),
+        _ => raw.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::dictionary;
+    use lopdf::Stream;
+    use std::io::Cursor;
+
+    /// Build a two-page PDF with a heading, two paragraphs, a running page number
+    /// and a one-entry outline.
+    fn sample_pdf() -> Vec<u8> {
+        let mut doc = lopdf::Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let font_id = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Helvetica",
+            "Encoding" => "WinAnsiEncoding",
+        });
+        let resources_id = doc.add_object(dictionary! {
+            "Font" => dictionary! { "F1" => font_id },
+        });
+
+        let page = |doc: &mut lopdf::Document, body: &str| {
+            let content_id = doc.add_object(Stream::new(dictionary! {}, body.as_bytes().to_vec()));
+            doc.add_object(dictionary! {
+                "Type" => "Page",
+                "Parent" => pages_id,
+                "Contents" => content_id,
+            })
+        };
+        let p1 = page(
+            &mut doc,
+            "BT /F1 24 Tf 72 720 Td (Chapter One) Tj ET \
+             BT /F1 12 Tf 72 680 Td 14 TL (The first paragraph starts here and) Tj \
+             T* (continues on a second line.) Tj T* T* (A second paragraph.) Tj ET \
+             BT /F1 10 Tf 300 40 Td (1) Tj ET",
+        );
+        let p2 = page(
+            &mut doc,
+            "BT /F1 12 Tf 72 720 Td (Text on the second page.) Tj ET \
+             BT /F1 10 Tf 300 40 Td (2) Tj ET",
+        );
+        doc.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => vec![p1.into(), p2.into()],
+                "Count" => 2,
+                "Resources" => resources_id,
+                "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+            }),
+        );
+
+        let outlines_id = doc.new_object_id();
+        let item_id = doc.add_object(dictionary! {
+            "Title" => Object::string_literal("Chapter One"),
+            "Parent" => outlines_id,
+            "Dest" => vec![p1.into(), "Fit".into()],
+        });
+        doc.objects.insert(
+            outlines_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Outlines",
+                "First" => item_id,
+                "Last" => item_id,
+                "Count" => 1,
+            }),
+        );
+        let catalog_id = doc.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+            "Outlines" => outlines_id,
+        });
+        let info_id = doc.add_object(dictionary! {
+            "Title" => Object::string_literal("Sample Book"),
+            "Author" => Object::string_literal("Ann Author; Bob Writer"),
+            "CreationDate" => Object::string_literal("D:20240131120000Z"),
+        });
+        doc.trailer.set("Root", catalog_id);
+        doc.trailer.set("Info", info_id);
+
+        let mut out = Vec::new();
+        doc.save_to(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_reads_structure_and_metadata() {
+        let doc =
+            PdfReader::read(Cursor::new(sample_pdf()), &ReadOptions::default(), None).unwrap();
+        assert_eq!(doc.metadata.title.as_deref(), Some("Sample Book"));
+        assert_eq!(doc.metadata.authors, vec!["Ann Author", "Bob Writer"]);
+        assert_eq!(doc.metadata.publish_date.as_deref(), Some("2024-01-31"));
+        assert_eq!(doc.metadata.page_count, Some(2));
+
+        assert_eq!(doc.toc.len(), 1);
+        assert_eq!(doc.toc[0].title, "Chapter One");
+        assert_eq!(doc.content.len(), 1);
+
+        let texts: Vec<String> = doc.content[0]
+            .content
+            .iter()
+            .map(|n| match n {
+                ContentNode::Heading { level, children } => {
+                    format!("h{}:{}", level, crate::readers::inline_text(children))
+                }
+                ContentNode::Paragraph { children } => crate::readers::inline_text(children),
+                other => format!("{other:?}"),
+            })
+            .collect();
+        assert_eq!(
+            texts,
+            vec![
+                "h1:Chapter One",
+                "The first paragraph starts here and continues on a second line.",
+                "A second paragraph.",
+                "Text on the second page.",
+            ]
+        );
+    }
+
+    fn line(page: u32, y: f32, size: f32, text: &str) -> Line {
+        Line {
+            page,
+            x: 72.0,
+            y,
+            size,
+            runs: vec![Run {
+                text: text.to_string(),
+                bold: false,
+                italic: false,
+            }],
+        }
+    }
+
+    fn describe(blocks: &[(u32, ContentNode)]) -> Vec<String> {
+        blocks
+            .iter()
+            .map(|(page, n)| match n {
+                ContentNode::Heading { level, children } => {
+                    format!(
+                        "{}:h{}:{}",
+                        page,
+                        level,
+                        crate::readers::inline_text(children)
+                    )
+                }
+                ContentNode::Paragraph { children } => {
+                    format!("{}:p:{}", page, crate::readers::inline_text(children))
+                }
+                other => format!("{other:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_heading_levels_from_font_size() {
+        let lines = vec![
+            line(1, 720.0, 24.0, "Part One"),
+            line(1, 690.0, 18.0, "The Beginning"),
+            line(
+                1,
+                660.0,
+                12.0,
+                "Body text that carries most of the characters on the page.",
+            ),
+            line(
+                1,
+                646.0,
+                12.0,
+                "More body text so twelve points is clearly the body size.",
+            ),
+            line(1, 610.0, 12.5, "Barely larger text stays a paragraph."),
+            line(1, 580.0, 18.0, "A Long Heading That"),
+            line(1, 560.0, 18.0, "Wraps Onto Two Lines"),
+        ];
+        assert_eq!(
+            describe(&build_blocks(&lines)),
+            vec![
+                "1:h1:Part One",
+                "1:h2:The Beginning",
+                "1:p:Body text that carries most of the characters on the page. \
+                 More body text so twelve points is clearly the body size.",
+                "1:p:Barely larger text stays a paragraph.",
+                "1:h2:A Long Heading That Wraps Onto Two Lines",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_paragraphs_rebuilt_across_lines_hyphens_and_pages() {
+        let lines = vec![
+            line(1, 700.0, 12.0, "The experi-"),
+            line(1, 686.0, 12.0, "ment went well, and the"),
+            line(1, 672.0, 12.0, "result held."),
+            line(1, 640.0, 12.0, "After a wider gap a new paragraph"),
+            line(1, 626.0, 12.0, "runs to the foot of the page and"),
+            line(2, 720.0, 12.0, "carries on over the break."),
+            line(2, 706.0, 12.0, "Then a sentence ends the page."),
+            line(3, 720.0, 12.0, "So the next page starts afresh."),
+        ];
+        assert_eq!(
+            describe(&build_blocks(&lines)),
+            vec![
+                "1:p:The experiment went well, and the result held.",
+                "1:p:After a wider gap a new paragraph runs to the foot of the page and \
+                 carries on over the break. Then a sentence ends the page.",
+                "3:p:So the next page starts afresh.",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lines_follow_reading_order() {
+        let mut doc = lopdf::Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let font_id = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Helvetica",
+        });
+        // Bottom paragraph first, then the heading, then the right-hand half
+        // of a line before its left-hand half.
+        let content_id = doc.add_object(Stream::new(
+            dictionary! {},
+            b"BT /F1 12 Tf 72 600 Td (Last paragraph.) Tj ET \
+              BT /F1 24 Tf 72 720 Td (Heading) Tj ET \
+              BT /F1 12 Tf 200 680 Td (second half.) Tj ET \
+              BT /F1 12 Tf 72 680 Td (First half,) Tj ET"
+                .to_vec(),
+        ));
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "Contents" => content_id,
+            "Resources" => dictionary! { "Font" => dictionary! { "F1" => font_id } },
+        });
+        doc.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => vec![page_id.into()],
+                "Count" => 1,
+            }),
+        );
+
+        let lines = extract_page_lines(&doc, page_id, 1).unwrap();
+        let texts: Vec<String> = lines.iter().map(Line::text).collect();
+        assert_eq!(
+            texts,
+            ["Heading", "First half,", "second half.", "Last paragraph."]
+        );
+    }
+
+    #[test]
+    fn test_running_lines_stripped_by_position() {
+        // Footer number and header drawn before the body, as page decorations often are.
+        let mut pages: Vec<Vec<Line>> = (1..=3)
+            .map(|n| {
+                vec![
+                    line(n, 20.0, 9.0, &n.to_string()),
+                    line(n, 780.0, 9.0, "Book Title"),
+                    line(n, 700.0, 12.0, "Body text."),
+                ]
+            })
+            .collect();
+        strip_running_lines(&mut pages);
+        for lines in &pages {
+            let texts: Vec<String> = lines.iter().map(Line::text).collect();
+            assert_eq!(texts, ["Body text."]);
+        }
+    }
+
+    #[test]
+    fn test_outline_maps_to_chapters_and_toc() {
+        let para = |text: &str| ContentNode::Paragraph {
+            children: vec![InlineNode::Text(text.into())],
+        };
+        let blocks = vec![
+            (1, para("Front matter")),
+            (2, para("Start of one")),
+            (3, para("Middle of one")),
+            (5, para("Start of two")),
+        ];
+        let item = |title: &str, page: Option<u32>, children| OutlineItem {
+            title: title.into(),
+            page,
+            children,
+        };
+        let outline = vec![
+            item(
+                "One",
+                Some(2),
+                vec![
+                    item("One, Section", Some(3), vec![]),
+                    item("Unresolved", None, vec![]),
+                ],
+            ),
+            item("Two", Some(5), vec![]),
+        ];
+
+        let (chapters, toc) = chapters_from_outline(blocks, &outline);
+        let shape: Vec<(Option<&str>, usize)> = chapters
+            .iter()
+            .map(|c| (c.title.as_deref(), c.content.len()))
+            .collect();
+        assert_eq!(shape, vec![(None, 1), (Some("One"), 2), (Some("Two"), 1)]);
+
+        assert_eq!(toc.len(), 2);
+        assert_eq!(
+            (toc[0].title.as_str(), toc[0].href.as_str()),
+            ("One", "chapter-2")
+        );
+        assert_eq!(toc[0].children[0].href, "chapter-2");
+        // Entries without a resolvable page point at the first chapter.
+        assert_eq!(toc[0].children[1].href, "chapter-1");
+        assert_eq!(
+            (toc[1].title.as_str(), toc[1].href.as_str()),
+            ("Two", "chapter-3")
+        );
+    }
+
+    #[test]
+    fn test_pdf_date() {
+        assert_eq!(pdf_date("D:20240131120000Z"), "2024-01-31");
+        assert_eq!(pdf_date("D:2024"), "2024");
+    }
+}
@@ -0,0 +1,163 @@
+//! Shared loading of local and inline resources for text-based readers
+//! (HTML, Markdown) that reference images by path or `data:` URI.
+
+use std::collections::HashMap;
+
+use base64::Engine;
+
+use crate::document::{Resource, ResourceMap};
+use crate::readers::{guess_media_type, ReadOptions};
+use crate::security;
+
+pub(crate) struct ResourceLoader<'a> {
+    opts: &'a ReadOptions,
+    resources: ResourceMap,
+    /// Original reference → resource id, so repeated references share one resource.
+    ids: HashMap<String, String>,
+}
+
+impl<'a> ResourceLoader<'a> {
+    pub(crate) fn new(opts: &'a ReadOptions) -> Self {
+        Self {
+            opts,
+            resources: ResourceMap::new(),
+            ids: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn into_resources(self) -> ResourceMap {
+        self.resources
+    }
+
+    /// Load a referenced file into the resource map and return its id. Paths are
+    /// resolved against `ReadOptions::base_dir`; remote URLs are not fetched and
+    /// missing local files are skipped with a warning.
+    pub(crate) fn load(&mut self, src: &str) -> Option<String> {
+        if let Some(id) = self.ids.get(src) {
+            return Some(id.clone());
+        }
+
+        let (data, media_type, name) = if let Some(data_uri) = src.strip_prefix("data:") {
+            let (data, media_type) = decode_data_uri(data_uri)?;
+            let ext = media_type
+                .rsplit('/')
+                .next()
+                .unwrap_or("bin")
+                .replace("svg+xml", "svg");
+            (
+                data,
+                media_type,
+                format!("image{}.{}", self.ids.len() + 1, ext),
+            )
+        } else if src.contains("://") || src.starts_with("//") {
+            tracing::warn!("Skipping remote resource '{}'", src);
+            return None;
+        } else {
+            let path = src.split(['#', '?']).next().unwrap_or(src);
+            let path = percent_decode(path);
+            if let Err(e) = security::check_path_traversal(&path) {
+                tracing::warn!("Skipping resource '{}': {}", src, e);
+                return None;
+            }
+            let base = self.opts.base_dir.as_ref()?;
+            let full_path = base.join(&path);
+            let size = std::fs::metadata(&full_path).ok()?.len();
+            if let Err(e) = security::check_resource_size(&path, size, &self.opts.security) {
+                tracing::warn!("Skipping resource '{}': {}", src, e);
+                return None;
+            }
+            let data = match std::fs::read(&full_path) {
+                Ok(d) => d,
+                Err(e) => {
+                    tracing::warn!("Skipping resource '{}': {}", src, e);
+                    return None;
+                }
+            };
+            let name = path.rsplit('/').next().unwrap_or(&path).to_string();
+            (data, guess_media_type(&path).to_string(), name)
+        };
+
+        let id = self.unique_id(&name);
+        self.resources.insert(
+            id.clone(),
+            Resource {
+                id: id.clone(),
+                media_type,
+                data,
+                filename: Some(id.clone()),
+                obfuscation: None,
+            },
+        );
+        self.ids.insert(src.to_string(), id.clone());
+        Some(id)
+    }
+
+    fn unique_id(&self, name: &str) -> String {
+        let sanitized: String = name
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        if self.resources.get(&sanitized).is_none() {
+            return sanitized;
+        }
+        let (stem, ext) = sanitized
+            .rsplit_once('.')
+            .unwrap_or((sanitized.as_str(), ""));
+        (2..)
+            .map(|n| {
+                if ext.is_empty() {
+                    format!("{stem}-{n}")
+                } else {
+                    format!("{stem}-{n}.{ext}")
+                }
+            })
+            .find(|candidate| self.resources.get(candidate).is_none())
+            .unwrap_or(sanitized)
+    }
+}
+
+/// Decode the part of a `data:` URI after the scheme into bytes and media type.
+pub(crate) fn decode_data_uri(uri: &str) -> Option<(Vec<u8>, String)> {
+    let (header, payload) = uri.split_once(',')?;
+    let is_base64 = header.ends_with(";base64");
+    let media_type = header
+        .split(';')
+        .next()
+        .filter(|m| !m.is_empty())
+        .unwrap_or("application/octet-stream")
+        .to_string();
+    let data = if is_base64 {
+        let cleaned: String = payload.chars().filter(|c| !c.is_whitespace()).collect();
+        base64::engine::general_purpose::STANDARD
+            .decode(cleaned)
+            .ok()?
+    } else {
+        percent_decode(payload).into_bytes()
+    };
+    Some((data, media_type))
+}
+
+pub(crate) fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+            if let Some(b) = hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                out.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
@@ -1,10 +1,17 @@
-//! Plain text reader: UTF-8 text with optional BOM handling.
+//! Plain text reader.
+//!
+//! Decodes UTF-8/UTF-16 (by BOM), Shift-JIS and Windows-1252/Latin-1, strips
+//! Project Gutenberg headers and footers (the licence goes to
+//! `Metadata::rights`), and rebuilds structure from layout: blank lines
+//! separate paragraphs, lines such as "Chapter 12", "CHAPTER XII" or
+//! "Part One" start chapters, and divider lines ("* * *", "#") become scene
+//! breaks.
 
-use crate::document::*;
 use crate::detect::{DetectResult, Format};
+use crate::document::*;
 use crate::error::ReadError;
+use crate::progress::{emit_progress, ProgressHandler};
 use crate::readers::{FormatReader, ReadOptions};
-use crate::progress::ProgressHandler;
 
 pub struct TxtReader;
 
@@ -28,69 +35,539 @@ impl FormatReader for TxtReader {
         opts: &ReadOptions,
         progress: Option<&dyn ProgressHandler>,
     ) -> Result<Document, ReadError> {
-        let _ = opts;
-        let _ = progress;
-
         let mut raw = Vec::new();
         input.read_to_end(&mut raw).map_err(ReadError::from)?;
 
-        // Strip BOM if present
-        let content = if raw.starts_with(&[0xEF, 0xBB, 0xBF]) {
-            &raw[3..]
-        } else {
-            &raw[..]
-        };
+        let (text, encoding) = decode(&raw);
+        tracing::debug!("Reading plain text as {}", encoding);
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
 
-        let text = std::str::from_utf8(content).map_err(|e| ReadError::MalformedFile {
-            format: "TXT".into(),
-            detail: format!("Invalid UTF-8: {e}"),
-        })?;
-
-        // Split into paragraphs: blank lines separate paragraphs; single newlines become spaces within paragraph (or we keep newlines as line breaks)
-        let mut content_nodes = Vec::new();
-        let paragraphs: Vec<&str> = text
-            .split("\n\n")
-            .map(|s| s.trim())
-            .filter(|s| !s.is_empty())
-            .collect();
-
-        for para in paragraphs {
-            let line = para.replace("\n", " ");
-            if line.is_empty() {
-                continue;
-            }
-            content_nodes.push(ContentNode::Paragraph {
-                children: vec![InlineNode::Text(line)],
-            });
+        let gutenberg = strip_gutenberg(&text);
+        let body = gutenberg.as_ref().map_or(text.as_str(), |g| g.body);
+        let blocks = blocks(body);
+        let sections = sections(&blocks);
+
+        let mut metadata = Metadata::default();
+        if let Some(g) = &gutenberg {
+            metadata.title = g.title.clone();
+            metadata.authors = g.author.iter().cloned().collect();
+            metadata.language = g.language.clone();
+            metadata.publish_date = g.release_date.clone();
+            metadata.rights = g.licence.clone();
+        }
+        if metadata.title.is_none() {
+            // Without front matter the first line is usually the title.
+            metadata.title = blocks
+                .first()
+                .filter(|b| b.len() == 1 && b[0].chars().count() <= 100)
+                .map(|b| b[0].to_string());
         }
 
-        // If no paragraphs (e.g. empty file or single line without double newline), treat whole text as one paragraph
-        if content_nodes.is_empty() && !text.trim().is_empty() {
-            let line = text.trim().replace("\n", " ");
-            content_nodes.push(ContentNode::Paragraph {
-                children: vec![InlineNode::Text(line)],
+        let total = sections.len() as u64;
+        let mut content = Vec::with_capacity(sections.len());
+        let mut toc: Vec<TocEntry> = Vec::new();
+        let mut current_part: Option<usize> = None;
+        let has_parts = sections
+            .iter()
+            .any(|s| matches!(s.heading, Some((HeadingKind::Part, _))))
+            && sections
+                .iter()
+                .any(|s| matches!(s.heading, Some((HeadingKind::Chapter, _))));
+        for (i, section) in sections.into_iter().enumerate() {
+            let id = format!("chapter-{}", i + 1);
+            let mut nodes = Vec::new();
+            let mut title = None;
+            if let Some((kind, heading)) = section.heading {
+                let level = if has_parts && kind == HeadingKind::Chapter {
+                    2
+                } else {
+                    1
+                };
+                nodes.push(ContentNode::Heading {
+                    level,
+                    children: vec![InlineNode::Text(heading.clone())],
+                });
+                let entry = TocEntry {
+                    title: heading.clone(),
+                    href: id.clone(),
+                    children: Vec::new(),
+                };
+                match current_part {
+                    Some(part) if level == 2 => toc[part].children.push(entry),
+                    _ => {
+                        if kind == HeadingKind::Part {
+                            current_part = Some(toc.len());
+                        }
+                        toc.push(entry);
+                    }
+                }
+                title = Some(heading);
+            }
+            nodes.extend(section.blocks.iter().map(|b| block_node(b)));
+            content.push(Chapter {
+                id,
+                title,
+                content: nodes,
+                text_direction: None,
             });
+            emit_progress(
+                progress,
+                "Reading TXT",
+                i as u64 + 1,
+                Some(total),
+                Some(&format!("Chapter {}/{}", i + 1, total)),
+            );
         }
-
-        let title = text.lines().next().map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
-
-        let doc = Document {
-            metadata: Metadata {
-                title: title.clone(),
-                ..Default::default()
-            },
-            toc: vec![],
-            content: vec![Chapter {
+        if content.is_empty() {
+            content.push(Chapter {
                 id: "chapter-1".to_string(),
-                title,
-                content: content_nodes,
+                title: None,
+                content: Vec::new(),
                 text_direction: None,
-            }],
+            });
+        }
+
+        Ok(Document {
+            metadata,
+            toc: if opts.parse_toc { toc } else { Vec::new() },
+            content,
             resources: ResourceMap::new(),
             text_direction: TextDirection::default(),
             epub_version: None,
+            diagnostics: Vec::new(),
+        })
+    }
+}
+
+// --- Decoding ---
+
+/// Decode by BOM, then as UTF-8, then Shift-JIS if the bytes are valid
+/// Shift-JIS containing kana, and Windows-1252 otherwise (a superset of
+/// Latin-1 for printable text).
+fn decode(raw: &[u8]) -> (String, &'static str) {
+    if let Some((encoding, bom_len)) = encoding_rs::Encoding::for_bom(raw) {
+        let (text, _) = encoding.decode_without_bom_handling(&raw[bom_len..]);
+        return (text.into_owned(), encoding.name());
+    }
+    if let Ok(text) = std::str::from_utf8(raw) {
+        return (text.to_string(), "UTF-8");
+    }
+    if let Some(text) =
+        encoding_rs::SHIFT_JIS.decode_without_bom_handling_and_without_replacement(raw)
+    {
+        // Legacy single-byte text almost never decodes to full-width kana.
+        if text.chars().any(|c| ('\u{3041}'..='\u{30FF}').contains(&c)) {
+            return (text.into_owned(), "Shift_JIS");
+        }
+    }
+    let (text, _) = encoding_rs::WINDOWS_1252.decode_without_bom_handling(raw);
+    (text.into_owned(), "windows-1252")
+}
+
+// --- Project Gutenberg ---
+
+struct Gutenberg<'a> {
+    body: &'a str,
+    title: Option<String>,
+    author: Option<String>,
+    language: Option<String>,
+    release_date: Option<String>,
+    licence: Option<String>,
+}
+
+/// Split a Project Gutenberg file at its `*** START OF ...` and
+/// `*** END OF ...` markers, reading the header's `Title:`-style fields.
+fn strip_gutenberg(text: &str) -> Option<Gutenberg<'_>> {
+    let start = find_marker(
+        text,
+        &[
+            "*** START OF THE PROJECT GUTENBERG",
+            "*** START OF THIS PROJECT GUTENBERG",
+            "***START OF THE PROJECT GUTENBERG",
+        ],
+    )?;
+    let header = &text[..start];
+    let body_start = text[start..]
+        .find('\n')
+        .map_or(text.len(), |n| start + n + 1);
+    let end = find_marker(
+        &text[body_start..],
+        &[
+            "*** END OF THE PROJECT GUTENBERG",
+            "*** END OF THIS PROJECT GUTENBERG",
+            "***END OF THE PROJECT GUTENBERG",
+            "END OF THE PROJECT GUTENBERG EBOOK",
+            "END OF THIS PROJECT GUTENBERG EBOOK",
+            "END OF PROJECT GUTENBERG'S",
+        ],
+    )
+    .map_or(text.len(), |n| body_start + n);
+    let footer = &text[end..];
+
+    let field = |name: &str| -> Option<String> {
+        let mut lines = header.lines();
+        let first = lines.find_map(|l| l.trim().strip_prefix(name).map(str::trim))?;
+        // Long values wrap onto indented continuation lines.
+        let mut value = first.to_string();
+        for line in lines.take_while(|l| l.starts_with(' ') && !l.trim().is_empty()) {
+            value.push(' ');
+            value.push_str(line.trim());
+        }
+        Some(value).filter(|v| !v.is_empty())
+    };
+
+    // The licence is the footer after the END marker line; older files only
+    // carry the usage notice at the top.
+    let licence = footer
+        .split_once('\n')
+        .map(|(_, rest)| rest.trim())
+        .filter(|rest| !rest.is_empty())
+        .map(str::to_string)
+        .or_else(|| {
+            blocks(header)
+                .into_iter()
+                .find(|b| b[0].starts_with("This eBook is for the use of anyone"))
+                .map(|b| b.join(" "))
+        });
+
+    let mut body = &text[body_start..end];
+    // Drop a leading "Produced by ..." credit.
+    let trimmed = body.trim_start();
+    if trimmed.starts_with("Produced by") || trimmed.starts_with("E-text prepared by") {
+        body = trimmed.find("\n\n").map_or("", |n| &trimmed[n..]);
+    }
+
+    Some(Gutenberg {
+        body,
+        title: field("Title:"),
+        author: field("Author:"),
+        language: field("Language:").map(|l| language_code(&l)),
+        release_date: field("Release Date:")
+            .map(|d| d.split(" [").next().unwrap_or(&d).trim().to_string()),
+        licence,
+    })
+}
+
+fn find_marker(text: &str, markers: &[&str]) -> Option<usize> {
+    let upper = text.to_ascii_uppercase();
+    let pos = markers.iter().filter_map(|m| upper.find(m)).min()?;
+    // Start of the marker's line.
+    Some(text[..pos].rfind('\n').map_or(0, |n| n + 1))
+}
+
+fn language_code(name: &str) -> String {
+    match name.to_ascii_lowercase().as_str() {
+        "english" => "en",
+        "french" => "fr",
+        "german" => "de",
+        "spanish" => "es",
+        "italian" => "it",
+        "portuguese" => "pt",
+        "dutch" => "nl",
+        "finnish" => "fi",
+        "swedish" => "sv",
+        "latin" => "la",
+        "chinese" => "zh",
+        "japanese" => "ja",
+        "russian" => "ru",
+        _ => return name.to_string(),
+    }
+    .to_string()
+}
+
+// --- Structure ---
+
+/// Blank-line separated blocks, each a list of trimmed lines.
+fn blocks(text: &str) -> Vec<Vec<&str>> {
+    let mut blocks = Vec::new();
+    let mut current = Vec::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            if !current.is_empty() {
+                blocks.push(std::mem::take(&mut current));
+            }
+        } else {
+            current.push(line);
+        }
+    }
+    if !current.is_empty() {
+        blocks.push(current);
+    }
+    blocks
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HeadingKind {
+    /// Part, Book or Volume.
+    Part,
+    Chapter,
+}
+
+struct Section<'a, 'b> {
+    heading: Option<(HeadingKind, String)>,
+    blocks: Vec<&'b [&'a str]>,
+}
+
+/// Group blocks into sections, one per detected heading. Text before the
+/// first heading becomes an untitled section.
+fn sections<'a, 'b>(blocks: &'b [Vec<&'a str>]) -> Vec<Section<'a, 'b>> {
+    let mut sections: Vec<Section> = Vec::new();
+    let mut i = 0;
+    while i < blocks.len() {
+        let block = &blocks[i];
+        let heading = if block.len() <= 3 {
+            heading_kind(block[0])
+        } else {
+            None
         };
+        let Some(kind) = heading else {
+            match sections.last_mut() {
+                Some(section) => section.blocks.push(block.as_slice()),
+                None => sections.push(Section {
+                    heading: None,
+                    blocks: vec![block.as_slice()],
+                }),
+            }
+            i += 1;
+            continue;
+        };
+
+        let mut title = decorated_title(block[0])
+            .unwrap_or(block[0])
+            .trim_end_matches(['.', ':'])
+            .to_string();
+        let mut subtitle: Vec<&str> = block[1..].to_vec();
+        // "CHAPTER I." on its own, then the chapter name as the next block.
+        if subtitle.is_empty() {
+            if let Some(next) = blocks.get(i + 1).filter(|b| is_subtitle(b)) {
+                subtitle.push(next[0]);
+                i += 1;
+            }
+        }
+        if !subtitle.is_empty() {
+            title = format!("{}: {}", title, subtitle.join(" ").trim_end_matches('.'));
+        }
+        sections.push(Section {
+            heading: Some((kind, title)),
+            blocks: Vec::new(),
+        });
+        i += 1;
+    }
+    sections
+}
+
+const NUMBER_WORDS: &[&str] = &[
+    "one",
+    "two",
+    "three",
+    "four",
+    "five",
+    "six",
+    "seven",
+    "eight",
+    "nine",
+    "ten",
+    "eleven",
+    "twelve",
+    "thirteen",
+    "fourteen",
+    "fifteen",
+    "sixteen",
+    "seventeen",
+    "eighteen",
+    "nineteen",
+    "twenty",
+    "thirty",
+    "forty",
+    "fifty",
+    "sixty",
+    "seventy",
+    "eighty",
+    "ninety",
+    "hundred",
+    "first",
+    "second",
+    "third",
+    "fourth",
+    "fifth",
+    "sixth",
+    "seventh",
+    "eighth",
+    "ninth",
+    "tenth",
+    "last",
+    "final",
+];
+
+/// Classify a line as a heading: "Chapter 12", "CHAPTER XII. The Return",
+/// "Part One", "Prologue", or a title wrapped in asterisks ("*** Storm ***").
+fn heading_kind(line: &str) -> Option<HeadingKind> {
+    if line.chars().count() > 80 {
+        return None;
+    }
+    if decorated_title(line).is_some() {
+        return Some(HeadingKind::Chapter);
+    }
+    let lower = line.to_lowercase();
+    let bare = lower.trim_end_matches(['.', ':']);
+    if matches!(
+        bare,
+        "prologue" | "epilogue" | "preface" | "foreword" | "introduction" | "afterword"
+    ) {
+        return Some(HeadingKind::Chapter);
+    }
+
+    let (keyword, rest) = lower.split_once(char::is_whitespace)?;
+    let kind = match keyword.trim_end_matches('.') {
+        "chapter" | "chap" => HeadingKind::Chapter,
+        "part" | "book" | "volume" | "vol" => HeadingKind::Part,
+        _ => return None,
+    };
+    let rest = rest.trim_start();
+    let number_len = rest
+        .find(|c: char| !(c.is_alphanumeric() || c == '-'))
+        .unwrap_or(rest.len());
+    let (number, after) = rest.split_at(number_len);
+    let is_number = number.chars().all(|c| c.is_ascii_digit())
+        || number.chars().all(|c| "ivxlcdm".contains(c))
+        || number.split('-').all(|w| NUMBER_WORDS.contains(&w));
+    if number.is_empty() || !is_number {
+        return None;
+    }
+    // Allow "Chapter 3. Title" and "Chapter 3 — Title", but not prose that
+    // merely starts with "Chapter three of ...".
+    let after = after.trim_start();
+    let ok = after.is_empty()
+        || after.starts_with(['.', ':', '-', '—', '–', ')'])
+        || (line.chars().count() <= 60 && line == line.to_uppercase());
+    ok.then_some(kind)
+}
+
+/// The title inside `*** Title ***`, if the line is one.
+fn decorated_title(line: &str) -> Option<&str> {
+    let inner = line.strip_prefix("***")?.strip_suffix("***")?;
+    let inner = inner.trim_matches(|c: char| c == '*' || c.is_whitespace());
+    (!inner.is_empty()).then_some(inner)
+}
+
+/// A short single-line block without closing punctuation: the name of the
+/// chapter whose number heading precedes it.
+fn is_subtitle(block: &[&str]) -> bool {
+    let [line] = block else {
+        return false;
+    };
+    line.chars().count() <= 60
+        && !line.ends_with(['.', '!', '?', ',', ';', ':', '"', '\'', '”', '’'])
+        && heading_kind(line).is_none()
+        && !is_divider(line)
+}
+
+/// A scene break: a short line of only `*`, `#`, `~`, `-`, `=` or `•`.
+fn is_divider(line: &str) -> bool {
+    let marks = line.chars().filter(|c| !c.is_whitespace()).count();
+    line.chars().count() <= 20
+        && line
+            .chars()
+            .all(|c| c.is_whitespace() || "*#~-=•·".contains(c))
+        && (marks >= 3 || line == "#" || line == "*")
+}
+
+fn block_node(block: &[&str]) -> ContentNode {
+    if block.iter().all(|l| is_divider(l)) {
+        return ContentNode::HorizontalRule;
+    }
+    ContentNode::Paragraph {
+        children: vec![InlineNode::Text(block.join(" "))],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn read(bytes: &[u8]) -> Document {
+        TxtReader::read(Cursor::new(bytes.to_vec()), &ReadOptions::default(), None).unwrap()
+    }
+
+    fn heading(chapter: &Chapter) -> (u8, String) {
+        match &chapter.content[0] {
+            ContentNode::Heading { level, children } => {
+                (*level, crate::readers::inline_text(children))
+            }
+            other => panic!("expected heading, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_gutenberg_chapters_and_scene_breaks() {
+        let text = "The Project Gutenberg eBook of Sample\n\n\
+            Title: Sample Tale\n\
+            Author: Ann Writer\n\
+            Release Date: May 1, 2001 [eBook #42]\n\
+            Language: English\n\n\
+            *** START OF THE PROJECT GUTENBERG EBOOK SAMPLE ***\n\n\
+            Produced by Volunteers\n\n\
+            PART ONE\n\n\
+            CHAPTER I.\n\n\
+            The Beginning\n\n\
+            It was dark.\nVery dark.\n\n\
+            * * *\n\n\
+            Morning came.\n\n\
+            Chapter 2. The End\n\n\
+            Fin.\n\n\
+            *** END OF THE PROJECT GUTENBERG EBOOK SAMPLE ***\n\n\
+            START: FULL LICENSE\nTerms of use.\n";
+        let doc = read(text.as_bytes());
+
+        let m = &doc.metadata;
+        assert_eq!(m.title.as_deref(), Some("Sample Tale"));
+        assert_eq!(m.authors, ["Ann Writer"]);
+        assert_eq!(m.language.as_deref(), Some("en"));
+        assert_eq!(m.publish_date.as_deref(), Some("May 1, 2001"));
+        assert_eq!(
+            m.rights.as_deref(),
+            Some("START: FULL LICENSE\nTerms of use.")
+        );
+
+        assert_eq!(doc.content.len(), 3);
+        assert_eq!(heading(&doc.content[0]), (1, "PART ONE".into()));
+        assert_eq!(
+            heading(&doc.content[1]),
+            (2, "CHAPTER I: The Beginning".into())
+        );
+        assert_eq!(heading(&doc.content[2]), (2, "Chapter 2. The End".into()));
+        match &doc.content[1].content[1] {
+            ContentNode::Paragraph { children } => {
+                assert_eq!(
+                    crate::readers::inline_text(children),
+                    "It was dark. Very dark."
+                )
+            }
+            other => panic!("expected paragraph, got {:?}", other),
+        }
+        assert!(matches!(
+            doc.content[1].content[2],
+            ContentNode::HorizontalRule
+        ));
+
+        assert_eq!(doc.toc.len(), 1);
+        assert_eq!(doc.toc[0].children.len(), 2);
+        assert_eq!(doc.toc[0].children[1].href, "chapter-3");
+    }
+
+    #[test]
+    fn test_legacy_encodings() {
+        let (latin, _, _) = encoding_rs::WINDOWS_1252.encode("Café crème\n\nÀ bientôt");
+        let doc = read(&latin);
+        assert_eq!(doc.metadata.title.as_deref(), Some("Café crème"));
+
+        let (sjis, _, _) = encoding_rs::SHIFT_JIS.encode("こんにちは、世界");
+        assert_eq!(decode(&sjis), ("こんにちは、世界".to_string(), "Shift_JIS"));
 
-        Ok(doc)
+        let mut utf16 = vec![0xFF, 0xFE];
+        utf16.extend("Hello".encode_utf16().flat_map(|u| u.to_le_bytes()));
+        assert_eq!(decode(&utf16).0, "Hello");
     }
 }
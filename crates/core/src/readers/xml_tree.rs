@@ -0,0 +1,173 @@
+//! Small owned XML tree for readers of XML formats (FB2, DOCX parts) that
+//! need random access to the document rather than a single event pass.
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader as XmlReader;
+
+use crate::error::ReadError;
+use crate::readers::collapse_whitespace;
+use crate::security::{self, SecurityLimits};
+
+#[derive(Debug, Clone)]
+pub(crate) enum Xml {
+    Element(Element),
+    Text(String),
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Element {
+    /// Local name, without namespace prefix.
+    pub name: String,
+    /// Attributes by local name (`l:href` and `xlink:href` are both `href`,
+    /// `w:val` is `val`).
+    pub attrs: Vec<(String, String)>,
+    pub children: Vec<Xml>,
+}
+
+impl Element {
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|c| match c {
+            Xml::Element(e) => Some(e),
+            Xml::Text(_) => None,
+        })
+    }
+
+    pub fn child(&self, name: &str) -> Option<&Element> {
+        self.elements().find(|e| e.name == name)
+    }
+
+    pub fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.elements().filter(move |e| e.name == name)
+    }
+
+    /// First descendant with the given name, depth first.
+    pub fn find(&self, name: &str) -> Option<&Element> {
+        self.elements().find_map(|e| {
+            if e.name == name {
+                Some(e)
+            } else {
+                e.find(name)
+            }
+        })
+    }
+
+    /// Concatenated text content, whitespace collapsed. Paragraphs (`p`) and
+    /// FB2 verse lines (`v`) are followed by a space so they don't run together.
+    pub fn text(&self) -> String {
+        fn collect(el: &Element, out: &mut String) {
+            for c in &el.children {
+                match c {
+                    Xml::Text(t) => out.push_str(t),
+                    Xml::Element(e) => {
+                        collect(e, out);
+                        if e.name == "p" || e.name == "v" {
+                            out.push(' ');
+                        }
+                    }
+                }
+            }
+        }
+        let mut s = String::new();
+        collect(self, &mut s);
+        collapse_whitespace(&s).trim().to_string()
+    }
+}
+
+fn element_from(e: &BytesStart) -> Element {
+    Element {
+        name: String::from_utf8_lossy(e.local_name().as_ref()).to_string(),
+        attrs: e
+            .attributes()
+            .flatten()
+            .map(|a| {
+                let key = String::from_utf8_lossy(a.key.local_name().as_ref()).to_string();
+                let value = a
+                    .unescape_value()
+                    .map(|v| v.to_string())
+                    .unwrap_or_else(|_| String::from_utf8_lossy(&a.value).to_string());
+                (key, value)
+            })
+            .collect(),
+        children: Vec::new(),
+    }
+}
+
+/// Parse a whole document and return its root element. Elements left open by
+/// a truncated file are closed; `malformed` turns a description of a syntax
+/// error (or a missing root) into the reader's error.
+pub(crate) fn parse_tree(
+    content: &str,
+    limits: &SecurityLimits,
+    malformed: impl Fn(String) -> ReadError,
+) -> Result<Element, ReadError> {
+    let mut reader = XmlReader::from_str(content);
+    let mut stack: Vec<Element> = vec![Element::default()];
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(ref e)) => {
+                security::check_nesting_depth(stack.len() as u32, limits)?;
+                stack.push(element_from(e));
+            }
+            Ok(Event::Empty(ref e)) => {
+                if let Some(parent) = stack.last_mut() {
+                    parent.children.push(Xml::Element(element_from(e)));
+                }
+            }
+            Ok(Event::End(_)) if stack.len() > 1 => {
+                let el = stack.pop().unwrap_or_default();
+                if let Some(parent) = stack.last_mut() {
+                    parent.children.push(Xml::Element(el));
+                }
+            }
+            Ok(Event::Text(ref e)) => {
+                let text = e
+                    .unescape()
+                    .map(|t| t.to_string())
+                    .unwrap_or_else(|_| String::from_utf8_lossy(e).to_string());
+                if let Some(parent) = stack.last_mut() {
+                    parent.children.push(Xml::Text(text));
+                }
+            }
+            Ok(Event::CData(ref e)) => {
+                if let Some(parent) = stack.last_mut() {
+                    parent
+                        .children
+                        .push(Xml::Text(String::from_utf8_lossy(e).to_string()));
+                }
+            }
+            Ok(Event::Eof) => break,
+            Err(e) => {
+                return Err(malformed(format!(
+                    "XML error at position {}: {}",
+                    reader.buffer_position(),
+                    e
+                )))
+            }
+            _ => {}
+        }
+    }
+    // Close anything left open by a truncated file.
+    while stack.len() > 1 {
+        let el = stack.pop().unwrap_or_default();
+        if let Some(parent) = stack.last_mut() {
+            parent.children.push(Xml::Element(el));
+        }
+    }
+    stack
+        .pop()
+        .unwrap_or_default()
+        .children
+        .into_iter()
+        .find_map(|c| match c {
+            Xml::Element(e) => Some(e),
+            Xml::Text(_) => None,
+        })
+        .ok_or_else(|| malformed("no root element".to_string()))
+}
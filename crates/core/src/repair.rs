@@ -1,7 +1,14 @@
 //! Auto-fix common ebook issues. Each repair action maps to a ValidationIssue code.
 
-use crate::document::Document;
-use crate::validate::ValidationIssue;
+use std::collections::HashSet;
+use std::io::{Cursor, Read, Write};
+
+use crate::document::{
+    visit_image_refs, visit_image_refs_mut, Attributes, ContentNode, Document, TocEntry,
+};
+use crate::security::{self, SecurityLimits};
+use crate::toc;
+use crate::validate::{mimetype_problem, validate_archive, ValidationIssue, LOCAL_HEADER};
 
 #[derive(Debug, Clone)]
 pub struct RepairOptions {
@@ -26,13 +33,38 @@ impl Default for RepairOptions {
     }
 }
 
-#[derive(Debug, Clone, serde::Serialize)]
+#[derive(Debug, Clone, Default, serde::Serialize)]
 pub struct RepairReport {
     pub fixes_applied: Vec<RepairAction>,
     pub fixes_failed: Vec<(RepairAction, String)>,
     pub issues_remaining: Vec<ValidationIssue>,
 }
 
+impl RepairReport {
+    /// Append the report of a later repair step, e.g. `repair` after
+    /// `repair_archive`.
+    pub fn extend(&mut self, other: RepairReport) {
+        self.fixes_applied.extend(other.fixes_applied);
+        self.fixes_failed.extend(other.fixes_failed);
+        self.issues_remaining.extend(other.issues_remaining);
+    }
+
+    fn applied(&mut self, code: &str, description: String) {
+        self.fixes_applied.push(RepairAction {
+            code: code.to_string(),
+            description,
+        });
+    }
+
+    fn failed(&mut self, code: &str, description: String, reason: String) {
+        let action = RepairAction {
+            code: code.to_string(),
+            description,
+        };
+        self.fixes_failed.push((action, reason));
+    }
+}
+
 #[derive(Debug, Clone, serde::Serialize)]
 pub struct RepairAction {
     pub code: String,
@@ -40,30 +72,785 @@ pub struct RepairAction {
 }
 
 pub fn repair(doc: &mut Document, opts: &RepairOptions) -> RepairReport {
-    let mut fixes_applied = Vec::new();
-    let fixes_failed = Vec::new();
+    let mut report = RepairReport::default();
 
     if opts.fix_encoding {
         crate::encoding::normalize_encoding(doc, &crate::encoding::EncodingOptions::default());
-        fixes_applied.push(RepairAction {
-            code: "encoding".to_string(),
-            description: "Normalized text encoding".to_string(),
-        });
+        report.applied("encoding", "Normalized text encoding".to_string());
     }
 
     if opts.fix_metadata && doc.metadata.language.as_deref().unwrap_or("").is_empty() {
         doc.metadata.language = Some("en".to_string());
-        fixes_applied.push(RepairAction {
-            code: "metadata-language".to_string(),
-            description: "Set default language".to_string(),
+        report.applied("missing-language", "Set default language".to_string());
+    }
+
+    // Readers have already parsed malformed chapters leniently. A chapter
+    // counts as fixed once its recovered content serialises as well-formed
+    // XHTML; otherwise its diagnostic stays.
+    if opts.fix_xml {
+        let (recovered, rest): (Vec<_>, Vec<_>) = std::mem::take(&mut doc.diagnostics)
+            .into_iter()
+            .partition(|d| d.code == "malformed-xhtml");
+        doc.diagnostics = rest;
+        for diagnostic in recovered {
+            let location = diagnostic
+                .location
+                .clone()
+                .unwrap_or_else(|| "document".to_string());
+            match reserialize_chapter(doc, diagnostic.location.as_deref()) {
+                Ok(()) => report.applied(
+                    "malformed-xhtml",
+                    format!(
+                        "Rewrote the content recovered from malformed XHTML in {} as well-formed XHTML",
+                        location
+                    ),
+                ),
+                Err(reason) => {
+                    report.failed(
+                        "malformed-xhtml",
+                        format!(
+                            "Rewrite the content recovered from malformed XHTML in {}",
+                            location
+                        ),
+                        reason,
+                    );
+                    doc.diagnostics.push(diagnostic);
+                }
+            }
+        }
+    }
+
+    // Chapter ids come first: the TOC and links point at them.
+    let renamed = fix_chapter_ids(doc);
+    if renamed > 0 {
+        report.applied(
+            "empty-chapter-id",
+            format!("Gave {} chapters without an id a generated one", renamed),
+        );
+    }
+
+    if opts.fix_links {
+        let (retargeted, unresolved) = fix_images(doc);
+        if retargeted > 0 {
+            report.applied(
+                "missing-image",
+                format!(
+                    "Pointed {} image references at resources with the same file name",
+                    retargeted
+                ),
+            );
+        }
+        for (i, reference) in unresolved {
+            report.failed(
+                "missing-image",
+                format!("Retarget an image in chapter[{}]", i),
+                format!("No image resource matches '{}'", reference),
+            );
+        }
+
+        let fixed = crate::links::fix_links(doc);
+        if fixed > 0 {
+            report.applied(
+                "dangling-link",
+                format!(
+                    "Rewrote {} internal links to existing chapters and anchors",
+                    fixed
+                ),
+            );
+        }
+        for issue in crate::links::check_links(doc) {
+            let location = issue.location.as_deref().unwrap_or("document");
+            report.failed(
+                &issue.code,
+                format!("Retarget a link in {}", location),
+                issue.message,
+            );
+        }
+    }
+
+    if opts.generate_toc && doc.toc.is_empty() && !doc.content.is_empty() {
+        doc.toc = toc_from_headings(doc);
+        if doc.toc.is_empty() {
+            report.failed(
+                "missing-toc",
+                "Build a table of contents from headings".to_string(),
+                "No chapter has a heading or title to list".to_string(),
+            );
+        } else {
+            report.applied(
+                "missing-toc",
+                format!(
+                    "Built a table of contents with {} top-level entries from headings",
+                    doc.toc.len()
+                ),
+            );
+        }
+    }
+
+    report.issues_remaining =
+        crate::validate::validate(doc, &crate::validate::ValidateOptions::default());
+    report
+}
+
+/// Fix the ZIP container of an EPUB or other ZIP-based ebook before it is
+/// read: rebuild a missing or damaged central directory from the local file
+/// headers (`truncated-zip`) and write the `mimetype` entry first and
+/// uncompressed (`mimetype-entry`). `data` is replaced when anything changed.
+pub fn repair_archive(
+    data: &mut Vec<u8>,
+    opts: &RepairOptions,
+    limits: &SecurityLimits,
+) -> RepairReport {
+    let mut report = RepairReport::default();
+    let issues = validate_archive(data);
+    let has_issue = |code: &str| issues.iter().any(|i| i.code == code);
+    if !opts.fix_zip || issues.is_empty() {
+        report.issues_remaining = issues;
+        return report;
+    }
+
+    let entries = if has_issue("truncated-zip") {
+        let (entries, lost) = scan_local_entries(data, limits);
+        for (name, reason) in lost {
+            report.failed("truncated-zip", format!("Recover entry {}", name), reason);
+        }
+        if entries.is_empty() {
+            report.failed(
+                "truncated-zip",
+                "Rebuild the ZIP central directory".to_string(),
+                "No readable entries were found by scanning local file headers".to_string(),
+            );
+            report.issues_remaining = issues;
+            return report;
+        }
+        report.applied(
+            "truncated-zip",
+            format!(
+                "Rebuilt the ZIP from {} entries found by scanning local file headers",
+                entries.len()
+            ),
+        );
+        entries
+    } else {
+        match read_entries(data, limits) {
+            Ok(entries) => entries,
+            Err(reason) => {
+                report.failed("mimetype-entry", "Rewrite the archive".to_string(), reason);
+                report.issues_remaining = issues;
+                return report;
+            }
+        }
+    };
+
+    let has_entry = |name: &str| entries.iter().any(|(n, _)| n == name);
+    let epub = has_entry("mimetype") || has_entry("META-INF/container.xml");
+    match write_archive(&entries, epub) {
+        Ok(rebuilt) => {
+            if let Some(problem) = mimetype_problem(data, has_entry("mimetype")).filter(|_| epub) {
+                report.applied(
+                    "mimetype-entry",
+                    format!(
+                        "Wrote the mimetype entry first and uncompressed (it {})",
+                        problem
+                    ),
+                );
+            }
+            *data = rebuilt;
+        }
+        Err(e) => {
+            let code = if has_issue("truncated-zip") {
+                "truncated-zip"
+            } else {
+                "mimetype-entry"
+            };
+            report.failed(
+                code,
+                "Write the repaired archive".to_string(),
+                e.to_string(),
+            );
+        }
+    }
+    report.issues_remaining = validate_archive(data);
+    report
+}
+
+/// Serialise the chapter a `malformed-xhtml` diagnostic points at
+/// (`chapter[N]`) as the EPUB writer would, and check the XHTML is well-formed.
+fn reserialize_chapter(doc: &Document, location: Option<&str>) -> Result<(), String> {
+    let index = location
+        .and_then(|l| l.strip_prefix("chapter["))
+        .and_then(|l| l.strip_suffix(']'))
+        .and_then(|n| n.parse::<usize>().ok())
+        .filter(|&i| i < doc.content.len())
+        .ok_or_else(|| "The diagnostic does not name a chapter".to_string())?;
+    let xhtml = crate::writers::epub::chapter_xhtml(doc, index).map_err(|e| e.to_string())?;
+    let xhtml = String::from_utf8(xhtml).map_err(|e| e.to_string())?;
+
+    let mut reader = quick_xml::Reader::from_str(&xhtml);
+    let mut depth = 0usize;
+    loop {
+        match reader.read_event() {
+            Ok(quick_xml::events::Event::Start(_)) => depth += 1,
+            Ok(quick_xml::events::Event::End(_)) => depth = depth.saturating_sub(1),
+            Ok(quick_xml::events::Event::Eof) if depth == 0 => return Ok(()),
+            Ok(quick_xml::events::Event::Eof) => {
+                return Err("The rewritten chapter leaves elements unclosed".to_string())
+            }
+            Ok(_) => {}
+            Err(e) => {
+                return Err(format!(
+                    "The rewritten chapter is not well-formed at position {}: {}",
+                    reader.buffer_position(),
+                    e
+                ))
+            }
+        }
+    }
+}
+
+/// Give chapters with an empty id one of the `chapter-N` ids readers use.
+fn fix_chapter_ids(doc: &mut Document) -> usize {
+    let mut taken: HashSet<String> = doc.content.iter().map(|c| c.id.clone()).collect();
+    let mut renamed = 0;
+    for (i, chapter) in doc.content.iter_mut().enumerate() {
+        if !chapter.id.is_empty() {
+            continue;
+        }
+        let mut id = format!("chapter-{}", i + 1);
+        let mut n = 1;
+        while taken.contains(&id) {
+            n += 1;
+            id = format!("chapter-{}-{}", i + 1, n);
+        }
+        taken.insert(id.clone());
+        chapter.id = id;
+        renamed += 1;
+    }
+    renamed
+}
+
+/// Point images whose resource is missing at the one image resource with the
+/// same file stem (`a.png` after conversion to `a.jpg`). Returns the number
+/// retargeted and, by chapter index, the references left unresolved; those
+/// images are kept, since the resource may only have failed to load.
+fn fix_images(doc: &mut Document) -> (usize, Vec<(usize, String)>) {
+    fn stem(reference: &str) -> String {
+        let file = reference.split(['#', '?']).next().unwrap_or(reference);
+        let file = file.rsplit('/').next().unwrap_or(file);
+        file.rsplit_once('.')
+            .map_or(file, |(s, _)| s)
+            .to_lowercase()
+    }
+
+    // Decide the fixes first; resolving borrows the document.
+    let mut fixes = std::collections::HashMap::new();
+    let mut unresolved = Vec::new();
+    for (i, chapter) in doc.content.iter().enumerate() {
+        visit_image_refs(&chapter.content, &mut |reference| {
+            if crate::links::is_external(reference)
+                || crate::writers::find_resource(doc, reference).is_some()
+            {
+                return;
+            }
+            let key = stem(reference);
+            let mut candidates = doc.resources.iter().filter(|(_, r)| {
+                r.media_type.starts_with("image/")
+                    && r.filename.as_deref().is_some_and(|f| stem(f) == key)
+            });
+            match (candidates.next(), candidates.next()) {
+                (Some((id, _)), None) => {
+                    fixes.insert(reference.to_string(), id.clone());
+                }
+                _ => unresolved.push((i, reference.to_string())),
+            }
+        });
+    }
+
+    let mut retargeted = 0;
+    for chapter in &mut doc.content {
+        visit_image_refs_mut(&mut chapter.content, &mut |reference| {
+            if let Some(id) = fixes.get(reference.as_str()) {
+                *reference = id.clone();
+                retargeted += 1;
+            }
+        });
+    }
+    (retargeted, unresolved)
+}
+
+/// A TOC of each chapter's headings, or titles for chapters without any
+/// (see `toc::toc_from_headings`). Headings without an id first get the
+/// `heading-N` id the EPUB writer would give them, so the entries resolve as
+/// links.
+fn toc_from_headings(doc: &mut Document) -> Vec<TocEntry> {
+    for chapter in &mut doc.content {
+        anchor_headings(&mut chapter.content, &mut 0);
+    }
+    let headings: Vec<_> = doc.content.iter().map(toc::chapter_headings).collect();
+    toc::toc_from_headings(doc, &headings, |i, id| match id {
+        Some(id) => format!("{}#{}", doc.content[i].id, id),
+        None => doc.content[i].id.clone(),
+    })
+}
+
+/// Put an id on the innermost `Styled` wrapper of every heading, adding the
+/// wrapper where there is none. `count` is the number of headings before
+/// `nodes` in the chapter.
+fn anchor_headings(nodes: &mut [ContentNode], count: &mut usize) {
+    for node in nodes {
+        match node {
+            ContentNode::Heading { .. } => {
+                let heading = std::mem::replace(node, ContentNode::HorizontalRule);
+                *node = ContentNode::Styled {
+                    attrs: Attributes {
+                        id: Some(toc::heading_id(*count)),
+                        ..Default::default()
+                    },
+                    node: Box::new(heading),
+                };
+                *count += 1;
+            }
+            ContentNode::Styled { attrs, node }
+                if matches!(**node, ContentNode::Heading { .. }) =>
+            {
+                attrs.id.get_or_insert_with(|| toc::heading_id(*count));
+                *count += 1;
+            }
+            ContentNode::Styled { node, .. } => {
+                anchor_headings(std::slice::from_mut(node.as_mut()), count)
+            }
+            ContentNode::List { items, .. } => {
+                for item in items {
+                    anchor_headings(item, count);
+                }
+            }
+            ContentNode::BlockQuote { children }
+            | ContentNode::Note { children, .. }
+            | ContentNode::Container { children, .. } => anchor_headings(children, count),
+            _ => {}
+        }
+    }
+}
+
+type Entries = Vec<(String, Vec<u8>)>;
+
+/// The files of a readable archive, in central directory order.
+fn read_entries(data: &[u8], limits: &SecurityLimits) -> Result<Entries, String> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(data)).map_err(|e| e.to_string())?;
+    security::check_file_count(archive.len() as u64, limits).map_err(|e| e.to_string())?;
+    let mut entries = Vec::new();
+    let mut total = 0u64;
+    for i in 0..archive.len() {
+        let file = archive.by_index(i).map_err(|e| e.to_string())?;
+        if file.is_dir() {
+            continue;
+        }
+        let name = file.name().to_string();
+        security::check_compression_ratio(file.compressed_size(), file.size(), limits)
+            .and_then(|_| security::check_resource_size(&name, file.size(), limits))
+            .map_err(|e| e.to_string())?;
+        let mut content = Vec::new();
+        file.take(limits.max_resource_size_bytes + 1)
+            .read_to_end(&mut content)
+            .map_err(|e| format!("{}: {}", name, e))?;
+        total += content.len() as u64;
+        security::check_total_size(total, limits).map_err(|e| e.to_string())?;
+        entries.push((name, content));
+    }
+    Ok(entries)
+}
+
+/// Walk the local file headers from the start of the archive, for ZIPs whose
+/// central directory is missing or damaged. Returns the entries recovered and
+/// the ones lost, with why; scanning stops at the first entry whose extent is
+/// unknown or cut off by the end of the data.
+fn scan_local_entries(data: &[u8], limits: &SecurityLimits) -> (Entries, Vec<(String, String)>) {
+    const DATA_DESCRIPTOR: &[u8] = b"PK\x07\x08";
+    let u16_at = |at: usize| {
+        data.get(at..at + 2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]))
+    };
+    let u32_at = |at: usize| {
+        data.get(at..at + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    };
+
+    let mut entries = Vec::new();
+    let mut lost = Vec::new();
+    let mut names = HashSet::new();
+    let mut total = 0u64;
+    let mut at = 0;
+    while data.get(at..at + 4) == Some(LOCAL_HEADER) {
+        let (Some(flags), Some(method), Some(crc), Some(csize), Some(name_len), Some(extra_len)) = (
+            u16_at(at + 6),
+            u16_at(at + 8),
+            u32_at(at + 14),
+            u32_at(at + 18),
+            u16_at(at + 26),
+            u16_at(at + 28),
+        ) else {
+            break;
+        };
+        let name_at = at + 30;
+        let start = name_at + name_len as usize + extra_len as usize;
+        let Some(name) = data.get(name_at..name_at + name_len as usize) else {
+            break;
+        };
+        let name = String::from_utf8_lossy(name).into_owned();
+        let Some(body) = data.get(start..) else {
+            lost.push((name, "The entry is cut off in its header".to_string()));
+            break;
+        };
+
+        // With a data descriptor the sizes follow the data: a deflate
+        // stream marks its own end, and stored data ends at a signed
+        // descriptor recording its length.
+        let descriptor = flags & 0x08 != 0;
+        let stored = if descriptor && method == 8 {
+            body
+        } else if descriptor && method == 0 {
+            let end = body
+                .windows(4)
+                .enumerate()
+                .filter(|(_, w)| *w == DATA_DESCRIPTOR)
+                .map(|(p, _)| p)
+                .find(|&p| u32_at(start + p + 8) == Some(p as u32));
+            match end {
+                Some(end) => &body[..end],
+                None => {
+                    lost.push((name, "The entry is cut off".to_string()));
+                    break;
+                }
+            }
+        } else if descriptor {
+            lost.push((name, "The entry has no recorded size".to_string()));
+            break;
+        } else if csize == u32::MAX {
+            lost.push((name, "The entry uses ZIP64 sizes".to_string()));
+            break;
+        } else {
+            match body.get(..csize as usize) {
+                Some(stored) => stored,
+                None => {
+                    let reason = format!(
+                        "The entry is cut off after {} of {} bytes",
+                        body.len(),
+                        csize
+                    );
+                    lost.push((name, reason));
+                    break;
+                }
+            }
+        };
+
+        let inflated = match method {
+            _ if flags & 0x01 != 0 => Err("The entry is encrypted".to_string()),
+            0 => Ok((stored.to_vec(), stored.len())),
+            8 => inflate(stored, limits.max_resource_size_bytes),
+            m => Err(format!(
+                "The entry uses unsupported compression method {}",
+                m
+            )),
+        };
+        let (content, used, crc) = match inflated {
+            Ok((content, used)) if descriptor => {
+                let record = if body[used..].starts_with(DATA_DESCRIPTOR) {
+                    used + 4
+                } else {
+                    used
+                };
+                match u32_at(start + record) {
+                    Some(crc) if data.len() >= start + record + 12 => {
+                        (Ok(content), record + 12, crc)
+                    }
+                    _ => {
+                        lost.push((
+                            name,
+                            "The entry is cut off in its data descriptor".to_string(),
+                        ));
+                        break;
+                    }
+                }
+            }
+            Ok((content, _)) => (Ok(content), stored.len(), crc),
+            Err(reason) if descriptor => {
+                lost.push((name, reason));
+                break;
+            }
+            Err(reason) => (Err(reason), stored.len(), crc),
+        };
+        at = start + used;
+
+        let checked = content.and_then(|content| {
+            let mut sum = flate2::Crc::new();
+            sum.update(&content);
+            if sum.sum() != crc {
+                return Err("The entry fails its CRC check".to_string());
+            }
+            total += content.len() as u64;
+            security::check_path_traversal(&name)
+                .and_then(|_| {
+                    security::check_compression_ratio(used as u64, content.len() as u64, limits)
+                })
+                .and_then(|_| security::check_total_size(total, limits))
+                .and_then(|_| security::check_file_count(entries.len() as u64 + 1, limits))
+                .map_err(|e| e.to_string())?;
+            Ok(content)
         });
+        match checked {
+            Ok(_) if name.ends_with('/') || !names.insert(name.clone()) => {}
+            Ok(content) => entries.push((name, content)),
+            Err(reason) => lost.push((name, reason)),
+        }
+    }
+    (entries, lost)
+}
+
+/// Inflate a raw deflate stream of at most `limit` bytes, returning the data
+/// and how many input bytes the stream took.
+fn inflate(input: &[u8], limit: u64) -> Result<(Vec<u8>, usize), String> {
+    let mut inflater = flate2::Decompress::new(false);
+    let mut out = Vec::with_capacity(input.len().saturating_mul(2).min(1 << 20));
+    loop {
+        if out.len() == out.capacity() {
+            if out.len() as u64 > limit {
+                return Err("The entry exceeds the resource size limit".to_string());
+            }
+            out.reserve(out.len().max(64 * 1024));
+        }
+        let (before_in, before_out) = (inflater.total_in(), inflater.total_out());
+        let status = inflater
+            .decompress_vec(
+                &input[before_in as usize..],
+                &mut out,
+                flate2::FlushDecompress::None,
+            )
+            .map_err(|e| format!("The entry cannot be inflated: {}", e))?;
+        if status == flate2::Status::StreamEnd {
+            return Ok((out, inflater.total_in() as usize));
+        }
+        if inflater.total_in() == before_in && inflater.total_out() == before_out {
+            return Err("The entry is cut off".to_string());
+        }
     }
+}
+
+/// Write `entries` as a new ZIP; for EPUB the `mimetype` entry goes first,
+/// stored.
+fn write_archive(entries: &Entries, epub: bool) -> zip::result::ZipResult<Vec<u8>> {
+    let store: zip::write::FileOptions<'_, ()> =
+        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    let deflate: zip::write::FileOptions<'_, ()> =
+        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    if epub {
+        zip.start_file("mimetype", store)?;
+        zip.write_all(b"application/epub+zip")?;
+    }
+    for (name, content) in entries {
+        if epub && name == "mimetype" {
+            continue;
+        }
+        zip.start_file(name.as_str(), deflate)?;
+        zip.write_all(content)?;
+    }
+    Ok(zip.finish()?.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::{Chapter, InlineNode, Resource};
+
+    #[test]
+    fn test_repair_fixes_what_validate_reports() {
+        let image = |reference: &str| ContentNode::Image {
+            resource_id: reference.into(),
+            alt_text: None,
+            caption: None,
+        };
+        let mut doc = Document::default();
+        doc.resources.insert(
+            "img".into(),
+            Resource {
+                id: "img".into(),
+                media_type: "image/jpeg".into(),
+                data: vec![0xff, 0xd8],
+                filename: Some("images/photo.jpg".into()),
+                obfuscation: None,
+            },
+        );
+        doc.content.push(Chapter {
+            id: String::new(),
+            title: None,
+            content: vec![
+                ContentNode::Heading {
+                    level: 1,
+                    children: vec![InlineNode::Text("Opening".into())],
+                },
+                image("../images/photo.png"),
+                ContentNode::BlockQuote {
+                    children: vec![image("gone.png")],
+                },
+                ContentNode::Heading {
+                    level: 2,
+                    children: vec![InlineNode::Link {
+                        href: "missing-chapter".into(),
+                        children: vec![InlineNode::Text("Aside".into())],
+                    }],
+                },
+            ],
+            text_direction: None,
+        });
+        let codes =
+            |issues: &[ValidationIssue]| issues.iter().map(|i| i.code.clone()).collect::<Vec<_>>();
+        let before = crate::validate::validate(&doc, &Default::default());
+        assert_eq!(
+            codes(&before),
+            [
+                "empty-chapter-id",
+                "missing-language",
+                "missing-toc",
+                "missing-image",
+                "missing-image",
+                "dangling-link"
+            ]
+        );
+
+        let report = repair(&mut doc, &RepairOptions::default());
+        let chapter = &doc.content[0];
+        assert_eq!(chapter.id, "chapter-1");
+        assert!(
+            matches!(&chapter.content[1], ContentNode::Image { resource_id, .. } if resource_id == "img")
+        );
+        // An image whose resource cannot be found is kept and reported.
+        assert!(matches!(
+            &chapter.content[2],
+            ContentNode::BlockQuote { children }
+                if matches!(&children[..], [ContentNode::Image { resource_id, .. }] if resource_id == "gone.png")
+        ));
+        assert_eq!(doc.toc.len(), 1);
+        assert_eq!(doc.toc[0].href, "chapter-1#heading-1");
+        assert_eq!(doc.toc[0].children[0].title, "Aside");
+        assert_eq!(doc.toc[0].children[0].href, "chapter-1#heading-2");
+
+        let failed: Vec<&str> = report
+            .fixes_failed
+            .iter()
+            .map(|(a, _)| a.code.as_str())
+            .collect();
+        assert_eq!(failed, ["missing-image", "dangling-link"]);
+        assert_eq!(
+            report.fixes_failed[0].1,
+            "No image resource matches 'gone.png'"
+        );
+        assert_eq!(
+            codes(&report.issues_remaining),
+            ["missing-image", "dangling-link"]
+        );
+    }
+
+    #[test]
+    fn test_fix_xml_keeps_diagnostics_it_cannot_confirm() {
+        let chapter = |id: &str, node| Chapter {
+            id: id.into(),
+            title: None,
+            content: vec![node],
+            text_direction: None,
+        };
+        let mut doc = Document::default();
+        doc.content.push(chapter(
+            "c1",
+            ContentNode::Paragraph {
+                children: vec![InlineNode::Text("Recovered <text> & more".into())],
+            },
+        ));
+        // Raw markup is written as is, so it can still be broken.
+        doc.content
+            .push(chapter("c2", ContentNode::RawHtml("<p>Unclosed".into())));
+        for i in 0..2 {
+            doc.diagnostics
+                .push(crate::readers::epub::malformed_chapter(
+                    i,
+                    "source.xhtml",
+                    "unexpected end",
+                ));
+        }
+
+        let report = repair(&mut doc, &RepairOptions::default());
+        let applied: Vec<&RepairAction> = report
+            .fixes_applied
+            .iter()
+            .filter(|a| a.code == "malformed-xhtml")
+            .collect();
+        assert_eq!(applied.len(), 1);
+        assert!(applied[0].description.contains("chapter[0]"));
+        let (action, reason) = report
+            .fixes_failed
+            .iter()
+            .find(|(a, _)| a.code == "malformed-xhtml")
+            .unwrap();
+        assert!(action.description.contains("chapter[1]"));
+        assert!(reason.contains("expected `</p>`"), "{}", reason);
+        assert_eq!(doc.diagnostics.len(), 1);
+        assert_eq!(doc.diagnostics[0].location.as_deref(), Some("chapter[1]"));
+        assert!(report
+            .issues_remaining
+            .iter()
+            .any(|i| i.code == "malformed-xhtml"));
+    }
+
+    #[test]
+    fn test_repair_archive_recovers_truncated_epub() {
+        let deflate: zip::write::FileOptions<'_, ()> =
+            zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        let store = deflate.compression_method(zip::CompressionMethod::Stored);
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let files = [
+            ("META-INF/container.xml", deflate, "<container/>".repeat(20)),
+            ("mimetype", deflate, "application/epub+zip".to_string()),
+            ("OEBPS/a.xhtml", deflate, "<p>a</p>".repeat(100)),
+            ("OEBPS/b.xhtml", store, "<p>b</p>".repeat(100)),
+        ];
+        for (name, options, content) in &files {
+            zip.start_file(*name, *options).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        let mut data = zip.finish().unwrap().into_inner();
+        let cut = data.windows(4).rposition(|w| w == LOCAL_HEADER).unwrap() + 200;
+        data.truncate(cut);
+        let issues = validate_archive(&data);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].code, "truncated-zip");
 
-    let issues_remaining = crate::validate::validate(doc, &crate::validate::ValidateOptions::default());
+        let report = repair_archive(
+            &mut data,
+            &RepairOptions::default(),
+            &SecurityLimits::default(),
+        );
+        let applied: Vec<_> = report
+            .fixes_applied
+            .iter()
+            .map(|a| a.code.as_str())
+            .collect();
+        assert_eq!(applied, ["truncated-zip", "mimetype-entry"]);
+        assert_eq!(report.fixes_failed.len(), 1);
+        assert_eq!(
+            report.fixes_failed[0].0.description,
+            "Recover entry OEBPS/b.xhtml"
+        );
+        assert!(report.issues_remaining.is_empty());
 
-    RepairReport {
-        fixes_applied,
-        fixes_failed,
-        issues_remaining,
+        let mut archive = zip::ZipArchive::new(Cursor::new(&data)).unwrap();
+        let names: Vec<_> = archive.file_names().collect();
+        assert_eq!(names.len(), 3);
+        let mut a = String::new();
+        archive
+            .by_name("OEBPS/a.xhtml")
+            .unwrap()
+            .read_to_string(&mut a)
+            .unwrap();
+        assert_eq!(a, files[2].2);
+        assert_eq!(archive.by_index(0).unwrap().name(), "mimetype");
     }
 }
@@ -22,6 +22,7 @@ pub fn split(doc: &Document, strategy: SplitStrategy) -> Result<Vec<Document>, S
                     resources: doc.resources.clone(),
                     text_direction: doc.text_direction,
                     epub_version: doc.epub_version,
+                    diagnostics: Vec::new(),
                 };
                 out.push(new_doc);
             }
@@ -37,6 +38,7 @@ pub fn split(doc: &Document, strategy: SplitStrategy) -> Result<Vec<Document>, S
                     resources: doc.resources.clone(),
                     text_direction: doc.text_direction,
                     epub_version: doc.epub_version,
+                    diagnostics: Vec::new(),
                 };
                 out.push(new_doc);
             }
@@ -72,6 +74,7 @@ pub fn split(doc: &Document, strategy: SplitStrategy) -> Result<Vec<Document>, S
                     resources: doc.resources.clone(),
                     text_direction: doc.text_direction,
                     epub_version: doc.epub_version,
+                    diagnostics: Vec::new(),
                 });
             }
             Ok(out)
@@ -84,7 +87,10 @@ fn content_node_char_count(n: &crate::document::ContentNode) -> usize {
         crate::document::ContentNode::Paragraph { children } => inline_char_count(children),
         crate::document::ContentNode::Heading { children, .. } => inline_char_count(children),
         crate::document::ContentNode::CodeBlock { code, .. } => code.len(),
-        crate::document::ContentNode::BlockQuote { children } => children.iter().map(content_node_char_count).sum(),
+        crate::document::ContentNode::BlockQuote { children }
+        | crate::document::ContentNode::Note { children, .. }
+        | crate::document::ContentNode::Container { children, .. } => children.iter().map(content_node_char_count).sum(),
+        crate::document::ContentNode::Styled { node, .. } => content_node_char_count(node),
         crate::document::ContentNode::List { items, .. } => items.iter().flat_map(|r| r.iter()).map(content_node_char_count).sum(),
         crate::document::ContentNode::Table { headers, rows } => {
             headers.iter().flat_map(|c| c.iter()).map(|c| inline_char_count(std::slice::from_ref(c))).sum::<usize>()
@@ -97,7 +103,7 @@ fn content_node_char_count(n: &crate::document::ContentNode) -> usize {
 fn inline_char_count(nodes: &[crate::document::InlineNode]) -> usize {
     nodes.iter().map(|n| match n {
         crate::document::InlineNode::Text(s) => s.len(),
-        crate::document::InlineNode::Emphasis(c) | crate::document::InlineNode::Strong(c) | crate::document::InlineNode::Link { children: c, .. } => inline_char_count(c),
+        crate::document::InlineNode::Emphasis(c) | crate::document::InlineNode::Strong(c) | crate::document::InlineNode::Link { children: c, .. } | crate::document::InlineNode::Span { children: c, .. } => inline_char_count(c),
         crate::document::InlineNode::Code(s) => s.len(),
         crate::document::InlineNode::Ruby { base, annotation } => base.len() + annotation.len(),
         _ => 0,
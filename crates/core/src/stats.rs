@@ -104,7 +104,9 @@ fn content_node_stats(node: &ContentNode) -> (u64, u64, u64, u32) {
             }
             (w, ch, sent, 0)
         }
-        ContentNode::BlockQuote { children } => {
+        ContentNode::BlockQuote { children }
+        | ContentNode::Note { children, .. }
+        | ContentNode::Container { children, .. } => {
             let mut w = 0u64;
             let mut c = 0u64;
             let mut s = 0u64;
@@ -122,6 +124,7 @@ fn content_node_stats(node: &ContentNode) -> (u64, u64, u64, u32) {
             let sents = code.matches(|c| c == '.' || c == '!' || c == '?').count() as u64;
             (words, chars, sents, 0)
         }
+        ContentNode::Styled { node, .. } => content_node_stats(node),
         ContentNode::Image { .. } => (0, 0, 0, 1),
         ContentNode::HorizontalRule | ContentNode::RawHtml(_) => (0, 0, 0, 0),
     }
@@ -141,7 +144,7 @@ fn inline_stats(
                 chars += t.chars().count() as u64;
                 sents += t.matches(|c: char| c == '.' || c == '!' || c == '?').count() as u64;
             }
-            InlineNode::Emphasis(children) | InlineNode::Strong(children) | InlineNode::Link { children, .. } | InlineNode::Superscript(children) | InlineNode::Subscript(children) => {
+            InlineNode::Emphasis(children) | InlineNode::Strong(children) | InlineNode::Link { children, .. } | InlineNode::Superscript(children) | InlineNode::Subscript(children) | InlineNode::Span { children, .. } => {
                 let (tw, tc, ts, _) = inline_stats(children, 0, 0, 0);
                 words += tw;
                 chars += tc;
@@ -155,7 +158,7 @@ fn inline_stats(
                 words += base.split_whitespace().count() as u64;
                 chars += base.chars().count() as u64;
             }
-            InlineNode::LineBreak => {}
+            InlineNode::LineBreak | InlineNode::NoteRef { .. } => {}
         }
     }
     (words, chars, sents, 0)
@@ -0,0 +1,115 @@
+//! Tables of contents synthesised from chapter headings, for books whose
+//! source has none (EPUB navigation, `repair`).
+
+use crate::document::*;
+
+/// Heading levels a synthesised TOC lists.
+pub const SYNTHESIZED_TOC_DEPTH: u8 = 3;
+
+/// A heading as writers emit it: its level, text and element id.
+pub struct Heading {
+    pub level: u8,
+    pub text: String,
+    pub id: String,
+}
+
+/// The headings of a chapter in document order. A heading's id is that of
+/// its innermost `Styled` wrapper, or `heading-N` for the N-th heading of
+/// the chapter, which is the id the EPUB writer gives it.
+pub fn chapter_headings(chapter: &Chapter) -> Vec<Heading> {
+    fn collect(node: &ContentNode, own_id: Option<&str>, out: &mut Vec<Heading>) {
+        match node {
+            ContentNode::Heading { level, children } => {
+                let id = match own_id {
+                    Some(id) => id.to_string(),
+                    None => heading_id(out.len()),
+                };
+                out.push(Heading {
+                    level: *level,
+                    text: crate::readers::inline_text(children),
+                    id,
+                });
+            }
+            ContentNode::List { items, .. } => {
+                for c in items.iter().flatten() {
+                    collect(c, None, out);
+                }
+            }
+            ContentNode::BlockQuote { children }
+            | ContentNode::Note { children, .. }
+            | ContentNode::Container { children, .. } => {
+                for c in children {
+                    collect(c, None, out);
+                }
+            }
+            // The innermost wrapper's attributes land on the heading.
+            ContentNode::Styled { attrs, node } => collect(node, attrs.id.as_deref(), out),
+            _ => {}
+        }
+    }
+    let mut out = Vec::new();
+    for node in &chapter.content {
+        collect(node, None, &mut out);
+    }
+    out
+}
+
+/// Id of the heading with the given index among its chapter's headings.
+pub(crate) fn heading_id(index: usize) -> String {
+    format!("heading-{}", index + 1)
+}
+
+/// A TOC listing each chapter's headings (`headings`, from
+/// `chapter_headings`) down to `SYNTHESIZED_TOC_DEPTH`, nested by level, or
+/// the chapter title for chapters without headings. `href` gives the target
+/// of a chapter index and, for headings, the heading's id.
+pub fn toc_from_headings(
+    doc: &Document,
+    headings: &[Vec<Heading>],
+    href: impl Fn(usize, Option<&str>) -> String,
+) -> Vec<TocEntry> {
+    fn close(stack: &mut Vec<(u8, TocEntry)>, level: u8) {
+        while stack.len() > 1 && stack.last().is_some_and(|(l, _)| *l >= level) {
+            let (_, entry) = stack.pop().unwrap();
+            stack.last_mut().unwrap().1.children.push(entry);
+        }
+    }
+
+    let root = TocEntry {
+        title: String::new(),
+        href: String::new(),
+        children: Vec::new(),
+    };
+    let mut stack = vec![(0, root)];
+    for (i, chapter) in doc.content.iter().enumerate() {
+        let mut entries: Vec<(u8, TocEntry)> = headings[i]
+            .iter()
+            .filter(|h| h.level <= SYNTHESIZED_TOC_DEPTH && !h.text.trim().is_empty())
+            .map(|h| {
+                let entry = TocEntry {
+                    title: h.text.trim().to_string(),
+                    href: href(i, Some(&h.id)),
+                    children: Vec::new(),
+                };
+                (h.level, entry)
+            })
+            .collect();
+        if entries.is_empty() {
+            if let Some(title) = chapter.title.as_ref().filter(|t| !t.trim().is_empty()) {
+                let entry = TocEntry {
+                    title: title.trim().to_string(),
+                    href: href(i, None),
+                    children: Vec::new(),
+                };
+                entries.push((1, entry));
+            }
+        }
+        for (level, entry) in entries {
+            close(&mut stack, level);
+            stack.push((level, entry));
+        }
+    }
+    close(&mut stack, 1);
+    let (_, root) = stack.pop().unwrap();
+    root.children
+}
@@ -2,7 +2,7 @@
 
 use serde::{Deserialize, Serialize};
 
-use crate::document::Document;
+use crate::document::{visit_image_refs, ContentNode, Document};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ValidateOptions {
@@ -102,9 +102,134 @@ pub fn validate(doc: &Document, opts: &ValidateOptions) -> Vec<ValidationIssue>
         });
     }
 
+    if doc.toc.is_empty() && !doc.content.is_empty() {
+        let buildable = doc
+            .content
+            .iter()
+            .any(|c| c.title.is_some() || c.content.iter().any(has_heading));
+        issues.push(ValidationIssue {
+            severity: Severity::Warning,
+            code: "missing-toc".to_string(),
+            message: "Document has no table of contents".to_string(),
+            location: Some("toc".to_string()),
+            auto_fixable: buildable,
+        });
+    }
+
+    for (i, chapter) in doc.content.iter().enumerate() {
+        visit_image_refs(&chapter.content, &mut |reference| {
+            if crate::links::is_external(reference)
+                || crate::writers::find_resource(doc, reference).is_some()
+            {
+                return;
+            }
+            issues.push(ValidationIssue {
+                severity: Severity::Warning,
+                code: "missing-image".to_string(),
+                message: format!("Image '{}' refers to a missing resource", reference),
+                location: Some(format!("chapter[{}]", i)),
+                auto_fixable: true,
+            });
+        });
+    }
+
+    for diagnostic in &doc.diagnostics {
+        issues.push(ValidationIssue {
+            severity: Severity::Warning,
+            code: diagnostic.code.clone(),
+            message: diagnostic.message.clone(),
+            location: diagnostic.location.clone(),
+            // Writing the recovered chapter out again makes it well-formed.
+            auto_fixable: diagnostic.code == "malformed-xhtml",
+        });
+    }
+
+    issues.extend(crate::links::check_links(doc));
+
     if opts.accessibility {
         issues.extend(crate::accessibility::check_accessibility(doc, opts.wcag_level));
     }
 
     issues
 }
+
+/// Container problems of a ZIP-based ebook, checked on the file before it is
+/// read: a central directory that cannot be read (`truncated-zip`) and, for
+/// EPUB, a `mimetype` entry that is missing, compressed or not first
+/// (`mimetype-entry`).
+pub fn validate_archive(data: &[u8]) -> Vec<ValidationIssue> {
+    let mut issues = Vec::new();
+    let archive = match zip::ZipArchive::new(std::io::Cursor::new(data)) {
+        Ok(archive) => archive,
+        Err(e) => {
+            issues.push(ValidationIssue {
+                severity: Severity::Error,
+                code: "truncated-zip".to_string(),
+                message: format!("ZIP central directory cannot be read: {}", e),
+                location: None,
+                auto_fixable: data.starts_with(LOCAL_HEADER),
+            });
+            return issues;
+        }
+    };
+    let has_mimetype = archive.index_for_name("mimetype").is_some();
+    let is_epub = has_mimetype || archive.index_for_name("META-INF/container.xml").is_some();
+    if let Some(problem) = mimetype_problem(data, has_mimetype).filter(|_| is_epub) {
+        issues.push(ValidationIssue {
+            severity: Severity::Error,
+            code: "mimetype-entry".to_string(),
+            message: format!("The mimetype entry {}", problem),
+            location: Some("mimetype".to_string()),
+            auto_fixable: true,
+        });
+    }
+    issues
+}
+
+/// Signature of a ZIP local file header.
+pub(crate) const LOCAL_HEADER: &[u8] = b"PK\x03\x04";
+
+/// What is wrong with an EPUB's `mimetype` entry, which must be the first
+/// entry, stored uncompressed and hold exactly `application/epub+zip`.
+pub(crate) fn mimetype_problem(data: &[u8], present: bool) -> Option<&'static str> {
+    let u16_at = |at: usize| {
+        data.get(at..at + 2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]) as usize)
+    };
+    let first_is_mimetype = data.starts_with(LOCAL_HEADER)
+        && u16_at(26) == Some(8)
+        && data.get(30..38) == Some(b"mimetype".as_slice());
+    if !first_is_mimetype {
+        return Some(if present {
+            "is not the first entry in the archive"
+        } else {
+            "is missing"
+        });
+    }
+    if u16_at(8) != Some(0) {
+        return Some("is compressed");
+    }
+    if u16_at(6).map_or(true, |flags| flags & 0x08 != 0) {
+        return Some("records its size after the data");
+    }
+    let start = 38 + u16_at(28)?;
+    let len = data
+        .get(18..22)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)?;
+    if data.get(start..start + len) != Some(b"application/epub+zip".as_slice()) {
+        return Some("does not hold exactly application/epub+zip");
+    }
+    None
+}
+
+fn has_heading(node: &ContentNode) -> bool {
+    match node {
+        ContentNode::Heading { .. } => true,
+        ContentNode::Styled { node, .. } => has_heading(node),
+        ContentNode::BlockQuote { children }
+        | ContentNode::Note { children, .. }
+        | ContentNode::Container { children, .. } => children.iter().any(has_heading),
+        ContentNode::List { items, .. } => items.iter().flatten().any(has_heading),
+        _ => false,
+    }
+}
@@ -0,0 +1,435 @@
+//! Comic archive writer: IR → CBZ.
+//!
+//! Images are packed in reading order (the order chapters reference them,
+//! cover first) as `page0001.jpg`, `page0002.png`, … next to a `ComicInfo.xml`
+//! built from `Metadata`. Text content is not rendered. Pages can optionally be
+//! scaled down to a target width and re-encoded as JPEG.
+
+use std::collections::HashSet;
+use std::io::{Cursor, Write};
+
+use crate::document::*;
+use crate::error::WriteError;
+use crate::progress::{emit_progress, ProgressHandler};
+use crate::readers::cbz::natural_cmp;
+use crate::writers::{find_resource, FormatWriter, WriteOptions};
+use crate::xml::escape_xml;
+
+pub struct CbzWriter;
+
+/// Options for `CbzWriter`, set through `WriteOptions::cbz`.
+#[derive(Debug, Clone, Default)]
+pub struct CbzWriteOptions {
+    /// Scale pages wider than this many pixels down to it and re-encode them
+    /// as JPEG at `WriteOptions::image_quality`. Narrower pages and formats
+    /// that cannot be decoded are stored unchanged.
+    pub page_width: Option<u32>,
+}
+
+fn write_err(detail: impl Into<String>) -> WriteError {
+    WriteError::WriteFailed {
+        format: "CBZ".into(),
+        detail: detail.into(),
+    }
+}
+
+/// A page image ready to be stored.
+struct Page {
+    data: Vec<u8>,
+    ext: String,
+    /// TOC title of the chapter this page opens, if any.
+    bookmark: Option<String>,
+}
+
+impl FormatWriter for CbzWriter {
+    fn write<W: Write>(
+        doc: &Document,
+        output: W,
+        opts: &WriteOptions,
+        progress: Option<&dyn ProgressHandler>,
+    ) -> Result<(), WriteError> {
+        let sources = page_order(doc);
+        if sources.is_empty() {
+            return Err(write_err("document has no images to use as comic pages"));
+        }
+
+        let total = sources.len() as u64;
+        let mut pages = Vec::with_capacity(sources.len());
+        for (i, (res, bookmark)) in sources.into_iter().enumerate() {
+            let (data, ext) = match opts.cbz.page_width {
+                Some(width) => recompress(res, width, opts.image_quality),
+                None => None,
+            }
+            .unwrap_or_else(|| (res.data.clone(), extension(res).to_string()));
+            pages.push(Page {
+                data,
+                ext,
+                bookmark,
+            });
+            emit_progress(
+                progress,
+                "Writing CBZ",
+                i as u64 + 1,
+                Some(total),
+                Some(&format!("Page {}/{}", i + 1, total)),
+            );
+        }
+
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let stored: zip::write::FileOptions<'_, ()> =
+            zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        let deflated: zip::write::FileOptions<'_, ()> =
+            zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        let zip_err = |e: zip::result::ZipError| write_err(e.to_string());
+
+        zip.start_file("ComicInfo.xml", deflated).map_err(zip_err)?;
+        write_comic_info(&mut zip, doc, &pages)?;
+        // Page images are already compressed.
+        for (i, page) in pages.iter().enumerate() {
+            zip.start_file(format!("page{:04}.{}", i + 1, page.ext), stored)
+                .map_err(zip_err)?;
+            zip.write_all(&page.data)?;
+        }
+        let buffer = zip.finish().map_err(zip_err)?.into_inner();
+
+        let mut out = output;
+        out.write_all(&buffer)?;
+        Ok(())
+    }
+}
+
+/// Image resources in reading order, each with the bookmark for the page.
+fn page_order(doc: &Document) -> Vec<(&Resource, Option<String>)> {
+    let mut seen = HashSet::new();
+    let mut pages = Vec::new();
+
+    if let Some(cover) = doc
+        .metadata
+        .cover_image_id
+        .as_deref()
+        .and_then(|id| find_resource(doc, id))
+    {
+        if is_image(cover) {
+            seen.insert(cover.id.as_str());
+            pages.push((cover, None));
+        }
+    }
+
+    for chapter in &doc.content {
+        let mut bookmark = toc_title(&doc.toc, &chapter.id);
+        let mut ids = Vec::new();
+        collect_images(&chapter.content, &mut ids);
+        for id in ids {
+            let Some(res) = find_resource(doc, id) else {
+                continue;
+            };
+            if !is_image(res) {
+                continue;
+            }
+            if seen.insert(res.id.as_str()) {
+                pages.push((res, bookmark.take()));
+            } else if bookmark.is_some() {
+                // The chapter opens on an image already placed (usually the cover).
+                if let Some(page) = pages.iter_mut().find(|(r, _)| r.id == res.id) {
+                    page.1 = page.1.take().or(bookmark.take());
+                }
+            }
+        }
+    }
+
+    // Nothing referenced from the text: fall back to the images by name.
+    if pages.is_empty() {
+        let mut images: Vec<&Resource> = doc
+            .resources
+            .iter()
+            .map(|(_, r)| r)
+            .filter(|r| is_image(r))
+            .collect();
+        images.sort_by(|a, b| {
+            natural_cmp(
+                &a.filename.as_deref().unwrap_or(&a.id).to_lowercase(),
+                &b.filename.as_deref().unwrap_or(&b.id).to_lowercase(),
+            )
+        });
+        pages = images.into_iter().map(|r| (r, None)).collect();
+    }
+    pages
+}
+
+fn collect_images<'a>(nodes: &'a [ContentNode], out: &mut Vec<&'a str>) {
+    for node in nodes {
+        match node {
+            ContentNode::Image { resource_id, .. } => out.push(resource_id),
+            ContentNode::BlockQuote { children } | ContentNode::Container { children, .. } => {
+                collect_images(children, out)
+            }
+            ContentNode::Styled { node, .. } => {
+                collect_images(std::slice::from_ref(node.as_ref()), out)
+            }
+            ContentNode::List { items, .. } => {
+                for item in items {
+                    collect_images(item, out);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Title of the TOC entry pointing at `chapter_id`, searched depth-first.
+fn toc_title(toc: &[TocEntry], chapter_id: &str) -> Option<String> {
+    toc.iter().find_map(|entry| {
+        let target = entry.href.split('#').next().unwrap_or(&entry.href);
+        let target = target.rsplit('/').next().unwrap_or(target);
+        let target = target.strip_suffix(".xhtml").unwrap_or(target);
+        if target == chapter_id {
+            Some(entry.title.clone())
+        } else {
+            toc_title(&entry.children, chapter_id)
+        }
+    })
+}
+
+fn is_image(res: &Resource) -> bool {
+    res.media_type.starts_with("image/") && res.media_type != "image/svg+xml"
+}
+
+fn extension(res: &Resource) -> &'static str {
+    match res.media_type.as_str() {
+        "image/png" => "png",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        "image/bmp" => "bmp",
+        _ => "jpg",
+    }
+}
+
+/// Scale `res` down to `width` pixels and encode it as JPEG.
+fn recompress(res: &Resource, width: u32, quality: u8) -> Option<(Vec<u8>, String)> {
+    let img = image::load_from_memory(&res.data).ok()?;
+    if img.width() <= width {
+        return None;
+    }
+    let img = img.resize(width, u32::MAX, image::imageops::FilterType::Lanczos3);
+    let mut data = Vec::new();
+    let encoder =
+        image::codecs::jpeg::JpegEncoder::new_with_quality(&mut data, quality.clamp(1, 100));
+    img.to_rgb8().write_with_encoder(encoder).ok()?;
+    Some((data, "jpg".into()))
+}
+
+/// Write `ComicInfo.xml` (Anansi schema 2.0) from the document metadata.
+fn write_comic_info<W: Write>(w: &mut W, doc: &Document, pages: &[Page]) -> Result<(), WriteError> {
+    let m = &doc.metadata;
+    let custom = |key: &str| m.custom.get(key).map(String::as_str);
+
+    writeln!(w, r#"<?xml version="1.0" encoding="utf-8"?>"#)?;
+    writeln!(
+        w,
+        r#"<ComicInfo xmlns:xsd="http://www.w3.org/2001/XMLSchema" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">"#
+    )?;
+    let mut field = |name: &str, value: Option<&str>| -> Result<(), WriteError> {
+        if let Some(value) = value.map(str::trim).filter(|v| !v.is_empty()) {
+            writeln!(w, "  <{name}>{}</{name}>", escape_xml(value))?;
+        }
+        Ok(())
+    };
+
+    field("Title", m.title.as_deref())?;
+    if let Some(ref series) = m.series {
+        field("Series", Some(&series.name))?;
+        let number = series.position.map(|n| n.to_string());
+        field("Number", number.as_deref().or(custom("issue")))?;
+    } else {
+        field("Number", custom("issue"))?;
+    }
+    field("Volume", custom("volume"))?;
+    field("Summary", m.description.as_deref())?;
+
+    if let Some(ref date) = m.publish_date {
+        let mut parts = date.split('-');
+        field(
+            "Year",
+            parts
+                .next()
+                .filter(|y| y.len() == 4 && y.parse::<u32>().is_ok()),
+        )?;
+        for name in ["Month", "Day"] {
+            let value = parts
+                .next()
+                .and_then(|p| p.get(..2))
+                .and_then(|p| p.parse::<u32>().ok())
+                .map(|n| n.to_string());
+            field(name, value.as_deref())?;
+        }
+    }
+
+    field("Writer", Some(&m.authors.join(", ")))?;
+    for (key, name) in [
+        ("penciller", "Penciller"),
+        ("inker", "Inker"),
+        ("colorist", "Colorist"),
+        ("letterer", "Letterer"),
+        ("cover_artist", "CoverArtist"),
+        ("editor", "Editor"),
+    ] {
+        field(name, custom(key))?;
+    }
+    field("Publisher", m.publisher.as_deref())?;
+    field("Imprint", custom("imprint"))?;
+    field("Genre", Some(&m.subjects.join(", ")))?;
+    field("Web", custom("web"))?;
+    field("PageCount", Some(&pages.len().to_string()))?;
+    field("LanguageISO", m.language.as_deref())?;
+    if doc.text_direction == TextDirection::Rtl {
+        field("Manga", Some("YesAndRightToLeft"))?;
+    }
+    field("AgeRating", custom("age_rating"))?;
+
+    writeln!(w, "  <Pages>")?;
+    let has_cover = m.cover_image_id.is_some();
+    for (i, page) in pages.iter().enumerate() {
+        write!(
+            w,
+            "    <Page Image=\"{}\" ImageSize=\"{}\"",
+            i,
+            page.data.len()
+        )?;
+        if i == 0 && has_cover {
+            write!(w, " Type=\"FrontCover\"")?;
+        }
+        if let Some(ref bookmark) = page.bookmark {
+            write!(w, " Bookmark=\"{}\"", escape_xml(bookmark))?;
+        }
+        writeln!(w, "/>")?;
+    }
+    writeln!(w, "  </Pages>")?;
+    writeln!(w, "</ComicInfo>")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::readers::cbz::CbzReader;
+    use crate::readers::{FormatReader, ReadOptions};
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut data = Vec::new();
+        image::RgbImage::new(width, height)
+            .write_to(&mut Cursor::new(&mut data), image::ImageFormat::Png)
+            .unwrap();
+        data
+    }
+
+    fn image_chapter(id: &str, resource_id: &str) -> Chapter {
+        Chapter {
+            id: id.into(),
+            title: None,
+            content: vec![ContentNode::Image {
+                resource_id: resource_id.into(),
+                alt_text: None,
+                caption: None,
+            }],
+            text_direction: None,
+        }
+    }
+
+    fn doc() -> Document {
+        let mut resources = ResourceMap::new();
+        for (id, width) in [("cover.png", 80), ("a.png", 40), ("b.png", 40)] {
+            resources.insert(
+                id.into(),
+                Resource {
+                    id: id.into(),
+                    media_type: "image/png".into(),
+                    data: png(width, 60),
+                    filename: Some(format!("images/{}", id)),
+                    obfuscation: None,
+                },
+            );
+        }
+        Document {
+            metadata: Metadata {
+                title: Some("Issue One".into()),
+                authors: vec!["Ann Writer".into(), "Bob Scribe".into()],
+                series: Some(SeriesInfo {
+                    name: "Night & Day".into(),
+                    position: Some(1.0),
+                }),
+                publish_date: Some("2021-07-04".into()),
+                cover_image_id: Some("cover.png".into()),
+                ..Default::default()
+            },
+            toc: vec![TocEntry {
+                title: "Part Two".into(),
+                href: "p2".into(),
+                children: Vec::new(),
+            }],
+            content: vec![
+                image_chapter("p1", "images/b.png"),
+                image_chapter("p2", "a.png"),
+            ],
+            resources,
+            text_direction: TextDirection::Rtl,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_round_trips_through_comic_reader() {
+        let mut out = Vec::new();
+        CbzWriter::write(&doc(), &mut out, &WriteOptions::default(), None).unwrap();
+        let back = CbzReader::read(Cursor::new(out), &ReadOptions::default(), None).unwrap();
+
+        assert_eq!(back.content.len(), 3);
+        let m = &back.metadata;
+        assert_eq!(m.title.as_deref(), Some("Issue One"));
+        assert_eq!(m.authors, ["Ann Writer", "Bob Scribe"]);
+        let series = m.series.as_ref().unwrap();
+        assert_eq!(
+            (series.name.as_str(), series.position),
+            ("Night & Day", Some(1.0))
+        );
+        assert_eq!(m.publish_date.as_deref(), Some("2021-07-04"));
+        assert_eq!(m.cover_image_id.as_deref(), Some("page0001.png"));
+        assert_eq!(back.text_direction, TextDirection::Rtl);
+        assert_eq!(back.toc[0].title, "Part Two");
+        assert_eq!(back.toc[0].href, "page-3");
+        // Cover first, then pages in chapter order.
+        let widths: Vec<u32> = ["page0001.png", "page0002.png"]
+            .iter()
+            .map(|id| {
+                image::load_from_memory(&back.resources.get(id).unwrap().data)
+                    .unwrap()
+                    .width()
+            })
+            .collect();
+        assert_eq!(widths, [80, 40]);
+    }
+
+    #[test]
+    fn test_output_is_detected_as_cbz() {
+        let mut out = Vec::new();
+        CbzWriter::write(&doc(), &mut out, &WriteOptions::default(), None).unwrap();
+        // Content sniffing alone, despite the ComicInfo.xml entry.
+        let result = crate::detect::detect(&out, None).unwrap();
+        assert_eq!(result.format, crate::detect::Format::Cbz);
+    }
+
+    #[test]
+    fn test_recompresses_wide_pages() {
+        let opts = WriteOptions {
+            cbz: CbzWriteOptions {
+                page_width: Some(50),
+            },
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        CbzWriter::write(&doc(), &mut out, &opts, None).unwrap();
+        let archive = zip::ZipArchive::new(Cursor::new(out)).unwrap();
+        let names: Vec<&str> = archive.file_names().collect();
+        assert!(names.contains(&"page0001.jpg"));
+        assert!(names.contains(&"page0002.png"));
+    }
+}
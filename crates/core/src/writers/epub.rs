@@ -1,12 +1,69 @@
 //! EPUB writer: IR → content documents → OPF → ZIP.
 //! Defaults to EPUB3; supports EPUB2 via `--epub-version 2`.
+//! Fixed-layout (pre-paginated) output is used for comics: each chapter
+//! becomes one page sized to its image.
+//! Navigation is written both as an EPUB3 `nav.xhtml` and an EPUB2
+//! `toc.ncx`, from the document's TOC or from its headings.
+//! The archive is streamed to the sink; `FormatWriter::write` spools
+//! through a temporary file since a ZIP needs a seekable output.
+//! Fonts the source obfuscated are obfuscated again with the IDPF algorithm
+//! and listed in `META-INF/encryption.xml`.
 
-use std::io::{Cursor, Write};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::io::{Cursor, Seek, SeekFrom, Write};
 
 use crate::document::*;
 use crate::error::WriteError;
-use crate::writers::{FormatWriter, WriteOptions};
-use crate::progress::ProgressHandler;
+use crate::fonts;
+use crate::links::{LinkIndex, LinkTarget};
+use crate::writers::{collect_notes, find_resource, FormatWriter, WriteOptions};
+use crate::progress::{emit_progress, ProgressHandler};
+use crate::toc::{chapter_headings, toc_from_headings, Heading};
+use crate::xml::escape_xml;
+
+/// Options for `EpubWriter`, set through `WriteOptions::epub`.
+#[derive(Debug, Clone)]
+pub struct EpubWriteOptions {
+    /// Write a fixed-layout book with one page per chapter. Also enabled when
+    /// the document carries `rendition:layout: pre-paginated` metadata
+    /// (e.g. from the comic reader or a fixed-layout EPUB).
+    pub fixed_layout: bool,
+    /// Page size in CSS pixels for pages whose image size cannot be read.
+    pub viewport: (u32, u32),
+    /// When reading systems may show two pages side by side.
+    pub spread: Spread,
+}
+
+impl Default for EpubWriteOptions {
+    fn default() -> Self {
+        Self {
+            fixed_layout: false,
+            viewport: (1200, 1800),
+            spread: Spread::Landscape,
+        }
+    }
+}
+
+/// Values of the EPUB3 `rendition:spread` property.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Spread {
+    None,
+    Landscape,
+    Both,
+    Auto,
+}
+
+impl Spread {
+    fn as_str(self) -> &'static str {
+        match self {
+            Spread::None => "none",
+            Spread::Landscape => "landscape",
+            Spread::Both => "both",
+            Spread::Auto => "auto",
+        }
+    }
+}
 
 fn zip_err(e: zip::result::ZipError) -> WriteError {
     WriteError::WriteFailed {
@@ -19,24 +76,69 @@ pub struct EpubWriter;
 
 const OPF_DIR: &str = "OEBPS/";
 const OPF_PATH: &str = "OEBPS/content.opf";
+const DEFAULT_CSS_HREF: &str = "style.css";
+const NAV_HREF: &str = "nav.xhtml";
+const NCX_HREF: &str = "toc.ncx";
+/// Books up to this size are spooled in memory by `FormatWriter::write`,
+/// larger ones in a temporary file.
+const SPOOL_THRESHOLD: usize = 16 * 1024 * 1024;
+
+/// Stylesheet for books that bring none of their own.
+const DEFAULT_CSS: &str = r#"@namespace epub "http://www.idpf.org/2007/ops";
+body { margin: 0 5%; line-height: 1.4; }
+h1, h2, h3, h4, h5, h6 { text-align: center; page-break-after: avoid; break-after: avoid; }
+p { margin: 0; text-indent: 1.5em; }
+h1 + p, h2 + p, h3 + p, h4 + p, hr + p, blockquote p { text-indent: 0; }
+blockquote { margin: 1em 2em; }
+pre { white-space: pre-wrap; font-size: 0.9em; }
+hr { width: 30%; margin: 1.5em auto; border: 0; border-top: 1px solid; }
+img { max-width: 100%; }
+table { border-collapse: collapse; margin: 1em 0; }
+th, td { border: 1px solid #999; padding: 0.2em 0.5em; }
+aside[epub|type~="footnote"], aside[epub|type~="endnote"] { font-size: 0.9em; }
+"#;
 
 impl FormatWriter for EpubWriter {
     fn write<W: std::io::Write>(
         doc: &Document,
-        output: W,
+        mut output: W,
         opts: &WriteOptions,
         progress: Option<&dyn ProgressHandler>,
     ) -> Result<(), WriteError> {
-        let _ = progress;
+        let mut spool = tempfile::SpooledTempFile::new(SPOOL_THRESHOLD);
+        Self::write_seekable(doc, &mut spool, opts, progress)?;
+        spool.seek(SeekFrom::Start(0))?;
+        std::io::copy(&mut spool, &mut output)?;
+        output.flush()?;
+        Ok(())
+    }
+}
 
+impl EpubWriter {
+    /// Write the EPUB straight into a seekable sink such as a file, without
+    /// holding the archive in memory. Reports progress per chapter and per
+    /// resource.
+    pub fn write_seekable<W: Write + Seek>(
+        doc: &Document,
+        output: W,
+        opts: &WriteOptions,
+        progress: Option<&dyn ProgressHandler>,
+    ) -> Result<(), WriteError> {
         let epub3 = opts
             .epub_version
             .map(|v| v == EpubVersion::V3)
             .unwrap_or(true);
+        let fixed_layout = opts.epub.fixed_layout
+            || doc.metadata.custom.get("rendition:layout").map(String::as_str)
+                == Some("pre-paginated");
+        let viewports: Option<Vec<(u32, u32)>> = fixed_layout.then(|| {
+            doc.content
+                .iter()
+                .map(|c| page_size(doc, c).unwrap_or(opts.epub.viewport))
+                .collect()
+        });
 
-        let buffer = Vec::new();
-        let cursor = Cursor::new(buffer);
-        let mut zip = zip::ZipWriter::new(cursor);
+        let mut zip = zip::ZipWriter::new(output);
 
         let opts_store: zip::write::FileOptions<'_, ()> = zip::write::FileOptions::default()
             .compression_method(zip::CompressionMethod::Stored);
@@ -60,50 +162,177 @@ impl FormatWriter for EpubWriter {
             OPF_PATH
         )?;
 
+        // The book's own stylesheets, or a default one for reflowable books.
+        let mut stylesheets: Vec<String> = doc
+            .resources
+            .iter()
+            .filter(|(_, res)| res.media_type == "text/css")
+            .map(|(id, res)| resource_href(id, res))
+            .collect();
+        stylesheets.sort();
+        let default_css = stylesheets.is_empty() && viewports.is_none();
+        if default_css {
+            stylesheets.push(DEFAULT_CSS_HREF.to_string());
+        }
+
         // 3. OPF
         zip.start_file(OPF_PATH, opts_deflate).map_err(zip_err)?;
-        write_opf(doc, epub3, &mut zip)?;
+        let layout = viewports.as_ref().map(|_| opts.epub.spread);
+        write_opf(doc, epub3, layout, default_css, &mut zip)?;
+
+        // Navigation: nav.xhtml for EPUB3 reading systems, toc.ncx for older ones.
+        let links = LinkIndex::new(doc);
+        let headings: Vec<Vec<Heading>> = doc.content.iter().map(chapter_headings).collect();
+        let nav = if doc.toc.is_empty() {
+            synthesized_nav(doc, &headings)
+        } else {
+            nav_points(&links, &headings, &doc.toc)
+        };
+        let page_count = viewports.as_ref().map_or(0, Vec::len);
+        if epub3 {
+            zip.start_file(format!("{}{}", OPF_DIR, NAV_HREF), opts_deflate)
+                .map_err(zip_err)?;
+            write_nav_xhtml(doc, &nav, page_count, &mut zip)?;
+        }
+        zip.start_file(format!("{}{}", OPF_DIR, NCX_HREF), opts_deflate)
+            .map_err(zip_err)?;
+        write_ncx(doc, &nav, page_count, &mut zip)?;
 
         // 4. Content XHTML files
+        let total = (doc.content.len() + doc.resources.len()) as u64;
+        let note_chapters: HashMap<&str, usize> = collect_notes(doc)
+            .iter()
+            .map(|n| (n.id, n.chapter))
+            .collect();
         for (i, chapter) in doc.content.iter().enumerate() {
             let href = format!("chapter{}.xhtml", i + 1);
             zip.start_file(
                 format!("{}{}", OPF_DIR, href),
                 opts_deflate,
             ).map_err(zip_err)?;
-            write_chapter_xhtml(chapter, &mut zip)?;
+            let viewport = viewports.as_ref().map(|v| v[i]);
+            let ctx = XhtmlContext {
+                doc,
+                note_chapters: &note_chapters,
+                links: &links,
+                stylesheets: &stylesheets,
+                chapter: i,
+                headings: Cell::new(0),
+            };
+            write_chapter_xhtml(chapter, viewport, &ctx, &mut zip)?;
+            emit_progress(
+                progress,
+                "Writing EPUB",
+                i as u64 + 1,
+                Some(total),
+                Some(&format!("Chapter {}/{}", i + 1, doc.content.len())),
+            );
         }
 
-        // 5. Resources (images, etc.)
-        for (id, res) in doc.resources.iter() {
-            let default_name = format!("{}.bin", id);
-            let name = res.filename.as_deref().unwrap_or(&default_name);
-            let path = format!("{}resources/{}", OPF_DIR, name);
-            zip.start_file(path.clone(), opts_deflate).map_err(zip_err)?;
-            zip.write_all(&res.data)?;
+        // 5. Resources (images, stylesheets, fonts). Fonts the source
+        // obfuscated are obfuscated again with this book's identifier.
+        let obfuscation = fonts::idpf_obfuscation(&book_uid(doc));
+        let mut obfuscated = Vec::new();
+        for (i, (id, res)) in doc.resources.iter().enumerate() {
+            let href = resource_href(id, res);
+            zip.start_file(format!("{}{}", OPF_DIR, href), opts_deflate)
+                .map_err(zip_err)?;
+            if res.obfuscation.is_some() {
+                let mut data = res.data.clone();
+                obfuscation.apply(&mut data);
+                zip.write_all(&data)?;
+                obfuscated.push(format!("{}{}", OPF_DIR, href));
+            } else {
+                zip.write_all(&res.data)?;
+            }
+            emit_progress(
+                progress,
+                "Writing EPUB",
+                (doc.content.len() + i) as u64 + 1,
+                Some(total),
+                Some(&href),
+            );
+        }
+        if default_css {
+            zip.start_file(format!("{}{}", OPF_DIR, DEFAULT_CSS_HREF), opts_deflate)
+                .map_err(zip_err)?;
+            zip.write_all(DEFAULT_CSS.as_bytes())?;
+        }
+        if !obfuscated.is_empty() {
+            zip.start_file("META-INF/encryption.xml", opts_deflate)
+                .map_err(zip_err)?;
+            write_encryption_xml(&obfuscated, &mut zip)?;
         }
 
-        let cursor = zip.finish().map_err(|e| WriteError::WriteFailed {
+        let mut out = zip.finish().map_err(|e| WriteError::WriteFailed {
             format: "EPUB".into(),
             detail: format!("Zip finish: {}", e),
         })?;
-        let buffer = cursor.into_inner();
-
-        let mut out = output;
-        out.write_all(&buffer)?;
+        out.flush()?;
 
         Ok(())
     }
 }
 
-fn write_opf<W: Write>(doc: &Document, epub3: bool, w: &mut W) -> Result<(), WriteError> {
+/// `META-INF/encryption.xml` listing the fonts obfuscated with the IDPF
+/// algorithm, by archive path.
+fn write_encryption_xml(paths: &[String], w: &mut impl Write) -> Result<(), WriteError> {
+    writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        w,
+        r#"<encryption xmlns="urn:oasis:names:tc:opendocument:xmlns:container" xmlns:enc="http://www.w3.org/2001/04/xmlenc#">"#
+    )?;
+    for path in paths {
+        writeln!(w, "  <enc:EncryptedData>")?;
+        writeln!(
+            w,
+            r#"    <enc:EncryptionMethod Algorithm="{}"/>"#,
+            ObfuscationAlgorithm::Idpf.uri()
+        )?;
+        writeln!(
+            w,
+            r#"    <enc:CipherData><enc:CipherReference URI="{}"/></enc:CipherData>"#,
+            escape_xml(path)
+        )?;
+        writeln!(w, "  </enc:EncryptedData>")?;
+    }
+    writeln!(w, "</encryption>")?;
+    Ok(())
+}
+
+/// Path of a resource relative to the OPF, keeping the source's layout so
+/// relative `url()`s between stylesheets, fonts and images still resolve.
+fn resource_href(id: &str, res: &Resource) -> String {
+    match res.filename.as_deref() {
+        Some(name) => format!("resources/{}", name),
+        None => format!("resources/{}.bin", id),
+    }
+}
+
+/// Pixel size of the first image in a fixed-layout page.
+fn page_size(doc: &Document, chapter: &Chapter) -> Option<(u32, u32)> {
+    let resource_id = chapter.content.iter().find_map(|n| match n {
+        ContentNode::Image { resource_id, .. } => Some(resource_id),
+        _ => None,
+    })?;
+    let res = find_resource(doc, resource_id)?;
+    image::ImageReader::new(Cursor::new(&res.data))
+        .with_guessed_format()
+        .ok()?
+        .into_dimensions()
+        .ok()
+}
+
+/// `layout` is the spread setting when writing a fixed-layout book;
+/// `default_css` adds the generated stylesheet to the manifest.
+fn write_opf<W: Write>(
+    doc: &Document,
+    epub3: bool,
+    layout: Option<Spread>,
+    default_css: bool,
+    w: &mut W,
+) -> Result<(), WriteError> {
     let version = if epub3 { "3.0" } else { "2.0" };
-    let uid = doc
-        .metadata
-        .isbn_13
-        .as_deref()
-        .or(doc.metadata.isbn_10.as_deref())
-        .unwrap_or("urn:uuid:default");
 
     writeln!(
         w,
@@ -111,27 +340,40 @@ fn write_opf<W: Write>(doc: &Document, epub3: bool, w: &mut W) -> Result<(), Wri
 <package xmlns="http://www.idpf.org/2007/opf" version="{}" unique-identifier="uid">"#,
         version
     )?;
-    writeln!(w, "  <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">")?;
-
-    if let Some(ref t) = doc.metadata.title {
-        writeln!(w, "    <dc:title>{}</dc:title>", escape_xml(t))?;
-    }
-    for a in &doc.metadata.authors {
-        writeln!(w, "    <dc:creator>{}</dc:creator>", escape_xml(a))?;
-    }
-    if let Some(ref lang) = doc.metadata.language {
-        writeln!(w, "    <dc:language>{}</dc:language>", escape_xml(lang))?;
-    }
-    writeln!(w, "    <dc:identifier id=\"uid\">{}</dc:identifier>", escape_xml(uid))?;
-    if let Some(ref p) = doc.metadata.publisher {
-        writeln!(w, "    <dc:publisher>{}</dc:publisher>", escape_xml(p))?;
+    writeln!(
+        w,
+        "  <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\" xmlns:opf=\"http://www.idpf.org/2007/opf\">"
+    )?;
+    write_dc_metadata(&doc.metadata, &book_identifiers(doc), epub3, w)?;
+    if let (Some(spread), true) = (layout, epub3) {
+        writeln!(w, "    <meta property=\"rendition:layout\">pre-paginated</meta>")?;
+        writeln!(w, "    <meta property=\"rendition:orientation\">auto</meta>")?;
+        writeln!(
+            w,
+            "    <meta property=\"rendition:spread\">{}</meta>",
+            spread.as_str()
+        )?;
     }
-    if let Some(ref d) = doc.metadata.description {
-        writeln!(w, "    <dc:description>{}</dc:description>", escape_xml(d))?;
+    if let Some(ref cover) = doc.metadata.cover_image_id {
+        if doc.resources.get(cover).is_some() {
+            writeln!(w, "    <meta name=\"cover\" content=\"{}\"/>", escape_xml(cover))?;
+        }
     }
 
     writeln!(w, "  </metadata>")?;
     writeln!(w, "  <manifest>")?;
+    if epub3 {
+        writeln!(
+            w,
+            "    <item id=\"nav\" href=\"{}\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>",
+            NAV_HREF
+        )?;
+    }
+    writeln!(
+        w,
+        "    <item id=\"ncx\" href=\"{}\" media-type=\"application/x-dtbncx+xml\"/>",
+        NCX_HREF
+    )?;
 
     for (i, _) in doc.content.iter().enumerate() {
         let id = format!("chapter{}", i + 1);
@@ -144,24 +386,48 @@ fn write_opf<W: Write>(doc: &Document, epub3: bool, w: &mut W) -> Result<(), Wri
     }
 
     for (id, res) in doc.resources.iter() {
-        let default_name = format!("{}.bin", id);
-        let name = res.filename.as_deref().unwrap_or(&default_name);
-        let href = format!("resources/{}", name);
         writeln!(
             w,
             "    <item id=\"{}\" href=\"{}\" media-type=\"{}\"/>",
             id,
-            href,
+            escape_xml(&resource_href(id, res)),
             escape_xml(&res.media_type)
         )?;
     }
+    if default_css {
+        writeln!(
+            w,
+            "    <item id=\"default-style\" href=\"{}\" media-type=\"text/css\"/>",
+            DEFAULT_CSS_HREF
+        )?;
+    }
 
     writeln!(w, "  </manifest>")?;
-    writeln!(w, "  <spine>")?;
+    let rtl = doc.text_direction == TextDirection::Rtl;
+    if epub3 && rtl {
+        writeln!(
+            w,
+            "  <spine toc=\"ncx\" page-progression-direction=\"rtl\">"
+        )?;
+    } else {
+        writeln!(w, "  <spine toc=\"ncx\">")?;
+    }
 
+    // Facing pages: the first page sits on the recto (right in left-to-right
+    // books, left in manga) and sides alternate from there.
+    let spreads = epub3 && layout.is_some_and(|s| s != Spread::None);
     for (i, _) in doc.content.iter().enumerate() {
         let id = format!("chapter{}", i + 1);
-        writeln!(w, "    <itemref idref=\"{}\"/>", id)?;
+        if spreads {
+            let side = if (i % 2 == 0) != rtl { "right" } else { "left" };
+            writeln!(
+                w,
+                "    <itemref idref=\"{}\" properties=\"page-spread-{}\"/>",
+                id, side
+            )?;
+        } else {
+            writeln!(w, "    <itemref idref=\"{}\"/>", id)?;
+        }
     }
 
     writeln!(w, "  </spine>")?;
@@ -170,7 +436,374 @@ fn write_opf<W: Write>(doc: &Document, epub3: bool, w: &mut W) -> Result<(), Wri
     Ok(())
 }
 
-fn write_chapter_xhtml<W: Write>(chapter: &Chapter, w: &mut W) -> Result<(), WriteError> {
+/// Schemes written as `urn:<scheme>:<value>` identifiers.
+const URN_SCHEMES: [&str; 3] = ["ISBN", "UUID", "DOI"];
+
+/// Dublin Core elements, contributors, identifiers and series. Contributor
+/// roles, sort names and identifier schemes are `opf:` attributes in EPUB2
+/// and refining metas in EPUB3.
+fn write_dc_metadata<W: Write>(
+    m: &Metadata,
+    identifiers: &[Identifier],
+    epub3: bool,
+    w: &mut W,
+) -> Result<(), WriteError> {
+    if let Some(title) = &m.title {
+        match (&m.subtitle, epub3) {
+            (Some(subtitle), true) => {
+                writeln!(w, "    <dc:title id=\"title-main\">{}</dc:title>", escape_xml(title))?;
+                writeln!(
+                    w,
+                    "    <meta refines=\"#title-main\" property=\"title-type\">main</meta>"
+                )?;
+                writeln!(
+                    w,
+                    "    <dc:title id=\"title-sub\">{}</dc:title>",
+                    escape_xml(subtitle)
+                )?;
+                writeln!(
+                    w,
+                    "    <meta refines=\"#title-sub\" property=\"title-type\">subtitle</meta>"
+                )?;
+            }
+            _ => writeln!(w, "    <dc:title>{}</dc:title>", escape_xml(title))?,
+        }
+    }
+
+    // `authors` decides who the authors are; `contributors` adds their sort
+    // names and lists everyone else credited.
+    let is_author = |c: &Contributor| match c.role.as_deref() {
+        Some("aut") => true,
+        None => m.authors.contains(&c.name),
+        Some(_) => false,
+    };
+    let authors = m.authors.iter().map(|name| {
+        let file_as = m
+            .contributors
+            .iter()
+            .find(|c| c.name == *name && is_author(c))
+            .and_then(|c| c.file_as.as_deref());
+        ("creator", name.as_str(), Some("aut"), file_as)
+    });
+    let others = m.contributors.iter().filter(|c| !is_author(c)).map(|c| {
+        let role = c.role.as_deref();
+        ("contributor", c.name.as_str(), role, c.file_as.as_deref())
+    });
+    for (i, (element, name, role, file_as)) in authors.chain(others).enumerate() {
+        if epub3 {
+            let id = format!("{}{}", element, i + 1);
+            writeln!(
+                w,
+                "    <dc:{} id=\"{}\">{}</dc:{}>",
+                element,
+                id,
+                escape_xml(name),
+                element
+            )?;
+            if let Some(role) = role {
+                writeln!(
+                    w,
+                    "    <meta refines=\"#{}\" property=\"role\" scheme=\"marc:relators\">{}</meta>",
+                    id,
+                    escape_xml(role)
+                )?;
+            }
+            if let Some(file_as) = file_as {
+                writeln!(
+                    w,
+                    "    <meta refines=\"#{}\" property=\"file-as\">{}</meta>",
+                    id,
+                    escape_xml(file_as)
+                )?;
+            }
+        } else {
+            let mut attrs = String::new();
+            if let Some(role) = role {
+                attrs.push_str(&format!(" opf:role=\"{}\"", escape_xml(role)));
+            }
+            if let Some(file_as) = file_as {
+                attrs.push_str(&format!(" opf:file-as=\"{}\"", escape_xml(file_as)));
+            }
+            writeln!(
+                w,
+                "    <dc:{}{}>{}</dc:{}>",
+                element,
+                attrs,
+                escape_xml(name),
+                element
+            )?;
+        }
+    }
+
+    if let Some(lang) = &m.language {
+        writeln!(w, "    <dc:language>{}</dc:language>", escape_xml(lang))?;
+    }
+    for (i, identifier) in identifiers.iter().enumerate() {
+        let id = match i {
+            0 => "uid".to_string(),
+            _ => format!("identifier{}", i + 1),
+        };
+        let text = escape_xml(&identifier_text(identifier));
+        match identifier.scheme.as_deref() {
+            Some(scheme) if !epub3 => writeln!(
+                w,
+                "    <dc:identifier id=\"{}\" opf:scheme=\"{}\">{}</dc:identifier>",
+                id,
+                escape_xml(scheme),
+                text
+            )?,
+            Some(scheme) if !URN_SCHEMES.contains(&scheme) => {
+                writeln!(
+                    w,
+                    "    <dc:identifier id=\"{}\">{}</dc:identifier>",
+                    id, text
+                )?;
+                writeln!(
+                    w,
+                    "    <meta refines=\"#{}\" property=\"identifier-type\">{}</meta>",
+                    id,
+                    escape_xml(scheme)
+                )?;
+            }
+            _ => writeln!(
+                w,
+                "    <dc:identifier id=\"{}\">{}</dc:identifier>",
+                id, text
+            )?,
+        }
+    }
+    if let Some(publisher) = &m.publisher {
+        writeln!(
+            w,
+            "    <dc:publisher>{}</dc:publisher>",
+            escape_xml(publisher)
+        )?;
+    }
+    if let Some(date) = &m.publish_date {
+        writeln!(w, "    <dc:date>{}</dc:date>", escape_xml(date))?;
+    }
+    if let Some(description) = &m.description {
+        writeln!(
+            w,
+            "    <dc:description>{}</dc:description>",
+            escape_xml(description)
+        )?;
+    }
+    for subject in &m.subjects {
+        writeln!(w, "    <dc:subject>{}</dc:subject>", escape_xml(subject))?;
+    }
+    if let Some(rights) = &m.rights {
+        writeln!(w, "    <dc:rights>{}</dc:rights>", escape_xml(rights))?;
+    }
+
+    if let Some(series) = &m.series {
+        let position = series.position.map(|p| p.to_string());
+        if epub3 {
+            writeln!(
+                w,
+                "    <meta property=\"belongs-to-collection\" id=\"series\">{}</meta>",
+                escape_xml(&series.name)
+            )?;
+            writeln!(
+                w,
+                "    <meta refines=\"#series\" property=\"collection-type\">series</meta>"
+            )?;
+            if let Some(position) = &position {
+                writeln!(
+                    w,
+                    "    <meta refines=\"#series\" property=\"group-position\">{}</meta>",
+                    position
+                )?;
+            }
+        }
+        // Calibre and the tools built around it read these in both versions.
+        writeln!(
+            w,
+            "    <meta name=\"calibre:series\" content=\"{}\"/>",
+            escape_xml(&series.name)
+        )?;
+        if let Some(position) = &position {
+            writeln!(
+                w,
+                "    <meta name=\"calibre:series_index\" content=\"{}\"/>",
+                position
+            )?;
+        }
+    }
+    if epub3 {
+        let modified = m.modified.clone().unwrap_or_else(utc_now);
+        writeln!(
+            w,
+            "    <meta property=\"dcterms:modified\">{}</meta>",
+            escape_xml(&modified)
+        )?;
+    }
+    Ok(())
+}
+
+/// Identifiers written to the OPF, the unique one first: the document's own,
+/// then its ISBN fields when they are not among them.
+fn book_identifiers(doc: &Document) -> Vec<Identifier> {
+    let digits = |s: &str| -> String {
+        s.chars()
+            .filter(|c| c.is_ascii_digit() || *c == 'X' || *c == 'x')
+            .collect()
+    };
+    let m = &doc.metadata;
+    let mut identifiers = m.identifiers.clone();
+    for isbn in [&m.isbn_13, &m.isbn_10].into_iter().flatten() {
+        if !identifiers.iter().any(|i| digits(&i.value) == digits(isbn)) {
+            identifiers.push(Identifier {
+                value: isbn.clone(),
+                scheme: Some("ISBN".into()),
+            });
+        }
+    }
+    if identifiers.is_empty() {
+        identifiers.push(Identifier {
+            value: "default".into(),
+            scheme: Some("UUID".into()),
+        });
+    }
+    identifiers
+}
+
+/// Text of a `dc:identifier`: `urn:isbn:…` for ISBNs, UUIDs and DOIs, the
+/// bare value otherwise.
+fn identifier_text(identifier: &Identifier) -> String {
+    match identifier.scheme.as_deref() {
+        Some(scheme) if URN_SCHEMES.contains(&scheme) => {
+            format!("urn:{}:{}", scheme.to_lowercase(), identifier.value)
+        }
+        _ => identifier.value.clone(),
+    }
+}
+
+/// The book's unique identifier, shared by the OPF and the NCX.
+fn book_uid(doc: &Document) -> String {
+    identifier_text(&book_identifiers(doc)[0])
+}
+
+/// Current UTC time as `CCYY-MM-DDThh:mm:ssZ`, the form `dcterms:modified`
+/// requires.
+fn utc_now() -> String {
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let (days, time) = ((secs / 86_400) as i64, secs % 86_400);
+    // Days since 1970-01-01 to a civil date (Howard Hinnant's algorithm).
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+/// Lower-cased words of a title, for matching TOC entries to headings.
+fn title_key(s: &str) -> String {
+    s.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Points the source TOC at the written chapter files. A fragment naming no
+/// element becomes the id of the heading with the entry's title, or is
+/// dropped when none matches; entries that point nowhere take the target of
+/// their first child.
+fn nav_points(links: &LinkIndex, headings: &[Vec<Heading>], entries: &[TocEntry]) -> Vec<TocEntry> {
+    let mut out = Vec::new();
+    for entry in entries {
+        let children = nav_points(links, headings, &entry.children);
+        let href = match links.resolve(&entry.href) {
+            LinkTarget::Internal {
+                chapter,
+                anchor: Some(anchor),
+            } => Some(format!("chapter{}.xhtml#{}", chapter + 1, anchor)),
+            LinkTarget::Internal { chapter, .. } => Some(format!("chapter{}.xhtml", chapter + 1)),
+            LinkTarget::MissingAnchor { chapter, .. } => {
+                let key = title_key(&entry.title);
+                Some(
+                    match headings[chapter].iter().find(|h| title_key(&h.text) == key) {
+                        Some(h) => format!("chapter{}.xhtml#{}", chapter + 1, h.id),
+                        None => format!("chapter{}.xhtml", chapter + 1),
+                    },
+                )
+            }
+            LinkTarget::External | LinkTarget::Dangling => None,
+        }
+        .or_else(|| children.first().map(|c| c.href.clone()));
+        if let Some(href) = href {
+            out.push(TocEntry {
+                title: entry.title.clone(),
+                href,
+                children,
+            });
+        }
+    }
+    out
+}
+
+/// A TOC built from the headings of every chapter, pointing at the written
+/// chapter files; a book with nothing to list gets a single entry.
+fn synthesized_nav(doc: &Document, headings: &[Vec<Heading>]) -> Vec<TocEntry> {
+    let nav = toc_from_headings(doc, headings, |chapter, id| match id {
+        Some(id) => format!("chapter{}.xhtml#{}", chapter + 1, id),
+        None => format!("chapter{}.xhtml", chapter + 1),
+    });
+    if nav.is_empty() && !doc.content.is_empty() {
+        return vec![TocEntry {
+            title: doc.metadata.title.clone().unwrap_or_else(|| "Start".into()),
+            href: "chapter1.xhtml".into(),
+            children: Vec::new(),
+        }];
+    }
+    nav
+}
+
+/// EPUB3 navigation document: the TOC, landmarks, and for fixed-layout books
+/// (`page_count` > 0) a page list.
+fn write_nav_xhtml<W: Write>(
+    doc: &Document,
+    nav: &[TocEntry],
+    page_count: usize,
+    w: &mut W,
+) -> Result<(), WriteError> {
+    fn write_ol<W: Write>(points: &[TocEntry], indent: usize, w: &mut W) -> Result<(), WriteError> {
+        let pad = "  ".repeat(indent);
+        writeln!(w, "{}<ol>", pad)?;
+        for p in points {
+            write!(
+                w,
+                "{}  <li><a href=\"{}\">{}</a>",
+                pad,
+                escape_xml(&p.href),
+                escape_xml(&p.title)
+            )?;
+            if !p.children.is_empty() {
+                writeln!(w)?;
+                write_ol(&p.children, indent + 2, w)?;
+                write!(w, "{}  ", pad)?;
+            }
+            writeln!(w, "</li>")?;
+        }
+        writeln!(w, "{}</ol>", pad)?;
+        Ok(())
+    }
+
     writeln!(
         w,
         r#"<?xml version="1.0" encoding="UTF-8"?>
@@ -180,7 +813,244 @@ fn write_chapter_xhtml<W: Write>(chapter: &Chapter, w: &mut W) -> Result<(), Wri
   <meta charset="UTF-8"/>
   <title>{}</title>
 </head>
-<body>"#,
+<body>
+  <nav epub:type="toc" id="toc">
+    <h1>Contents</h1>"#,
+        escape_xml(doc.metadata.title.as_deref().unwrap_or("Contents"))
+    )?;
+    write_ol(nav, 2, w)?;
+    writeln!(w, "  </nav>")?;
+
+    writeln!(
+        w,
+        "  <nav epub:type=\"landmarks\" id=\"landmarks\" hidden=\"\">"
+    )?;
+    writeln!(w, "    <ol>")?;
+    writeln!(
+        w,
+        "      <li><a epub:type=\"toc\" href=\"#toc\">Contents</a></li>"
+    )?;
+    if !doc.content.is_empty() {
+        writeln!(
+            w,
+            "      <li><a epub:type=\"bodymatter\" href=\"chapter1.xhtml\">Start</a></li>"
+        )?;
+    }
+    writeln!(w, "    </ol>")?;
+    writeln!(w, "  </nav>")?;
+
+    if page_count > 0 {
+        writeln!(
+            w,
+            "  <nav epub:type=\"page-list\" id=\"page-list\" hidden=\"\">"
+        )?;
+        writeln!(w, "    <ol>")?;
+        for i in 1..=page_count {
+            writeln!(w, "      <li><a href=\"chapter{}.xhtml\">{}</a></li>", i, i)?;
+        }
+        writeln!(w, "    </ol>")?;
+        writeln!(w, "  </nav>")?;
+    }
+
+    writeln!(w, "</body>")?;
+    writeln!(w, "</html>")?;
+    Ok(())
+}
+
+/// EPUB2 NCX with the same entries as the nav document. Targets share a
+/// `playOrder` when they point at the same place.
+fn write_ncx<W: Write>(
+    doc: &Document,
+    nav: &[TocEntry],
+    page_count: usize,
+    w: &mut W,
+) -> Result<(), WriteError> {
+    struct Ncx<'a> {
+        play_order: HashMap<&'a str, usize>,
+        next_id: usize,
+    }
+
+    impl<'a> Ncx<'a> {
+        fn play_order(&mut self, src: &'a str) -> usize {
+            let next = self.play_order.len() + 1;
+            *self.play_order.entry(src).or_insert(next)
+        }
+
+        fn write_points<W: Write>(
+            &mut self,
+            points: &'a [TocEntry],
+            indent: usize,
+            w: &mut W,
+        ) -> Result<(), WriteError> {
+            let pad = "  ".repeat(indent);
+            for p in points {
+                self.next_id += 1;
+                let order = self.play_order(&p.href);
+                writeln!(
+                    w,
+                    "{}<navPoint id=\"navpoint-{}\" playOrder=\"{}\">",
+                    pad, self.next_id, order
+                )?;
+                writeln!(
+                    w,
+                    "{}  <navLabel><text>{}</text></navLabel>",
+                    pad,
+                    escape_xml(&p.title)
+                )?;
+                writeln!(w, "{}  <content src=\"{}\"/>", pad, escape_xml(&p.href))?;
+                self.write_points(&p.children, indent + 1, w)?;
+                writeln!(w, "{}</navPoint>", pad)?;
+            }
+            Ok(())
+        }
+    }
+
+    fn depth(points: &[TocEntry]) -> usize {
+        points
+            .iter()
+            .map(|p| 1 + depth(&p.children))
+            .max()
+            .unwrap_or(0)
+    }
+
+    writeln!(
+        w,
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<ncx xmlns="http://www.daisy.org/z3986/2005/ncx/" version="2005-1">
+  <head>
+    <meta name="dtb:uid" content="{}"/>
+    <meta name="dtb:depth" content="{}"/>
+    <meta name="dtb:totalPageCount" content="{}"/>
+    <meta name="dtb:maxPageNumber" content="{}"/>
+  </head>
+  <docTitle><text>{}</text></docTitle>
+  <navMap>"#,
+        escape_xml(&book_uid(doc)),
+        depth(nav).max(1),
+        page_count,
+        page_count,
+        escape_xml(doc.metadata.title.as_deref().unwrap_or("Untitled"))
+    )?;
+    let mut ncx = Ncx {
+        play_order: HashMap::new(),
+        next_id: 0,
+    };
+    ncx.write_points(nav, 2, w)?;
+    writeln!(w, "  </navMap>")?;
+
+    if page_count > 0 {
+        let srcs: Vec<String> = (1..=page_count)
+            .map(|i| format!("chapter{}.xhtml", i))
+            .collect();
+        writeln!(w, "  <pageList>")?;
+        for (i, src) in srcs.iter().enumerate() {
+            writeln!(
+                w,
+                "    <pageTarget id=\"page-{}\" type=\"normal\" value=\"{}\" playOrder=\"{}\">",
+                i + 1,
+                i + 1,
+                ncx.play_order(src)
+            )?;
+            writeln!(w, "      <navLabel><text>{}</text></navLabel>", i + 1)?;
+            writeln!(w, "      <content src=\"{}\"/>", src)?;
+            writeln!(w, "    </pageTarget>")?;
+        }
+        writeln!(w, "  </pageList>")?;
+    }
+
+    writeln!(w, "</ncx>")?;
+    Ok(())
+}
+
+/// Per-chapter state for XHTML output.
+struct XhtmlContext<'a> {
+    doc: &'a Document,
+    /// Index of the chapter holding each note, by note id.
+    note_chapters: &'a HashMap<&'a str, usize>,
+    /// Targets of internal links.
+    links: &'a LinkIndex<'a>,
+    /// Stylesheet hrefs linked from every chapter.
+    stylesheets: &'a [String],
+    /// Index of the chapter being written.
+    chapter: usize,
+    /// Headings written so far, numbering their `heading-N` ids.
+    headings: Cell<usize>,
+}
+
+impl XhtmlContext<'_> {
+    /// `src` of an image: the manifest href of the resource it references,
+    /// or the reference as is when no resource matches (e.g. a remote URL).
+    fn image_src(&self, reference: &str) -> String {
+        match find_resource(self.doc, reference) {
+            Some(res) => resource_href(&res.id, res),
+            None => reference.to_string(),
+        }
+    }
+
+    /// `href` of a note: a fragment in this file, or `chapterN.xhtml#id`.
+    fn note_href(&self, id: &str) -> String {
+        match self.note_chapters.get(id) {
+            Some(&i) if i != self.chapter => format!("chapter{}.xhtml#{}", i + 1, id),
+            _ => format!("#{}", id),
+        }
+    }
+
+    /// `href` of a link: internal links point at the written chapter files,
+    /// dropping anchors that no longer exist; anything else is kept as is.
+    fn link_href(&self, href: &str) -> String {
+        match self.links.resolve(href) {
+            LinkTarget::Internal {
+                chapter,
+                anchor: Some(anchor),
+            } if chapter == self.chapter => format!("#{}", anchor),
+            LinkTarget::Internal {
+                chapter,
+                anchor: Some(anchor),
+            } => format!("chapter{}.xhtml#{}", chapter + 1, anchor),
+            LinkTarget::Internal { chapter, .. } | LinkTarget::MissingAnchor { chapter, .. } => {
+                format!("chapter{}.xhtml", chapter + 1)
+            }
+            LinkTarget::External | LinkTarget::Dangling => href.to_string(),
+        }
+    }
+}
+
+/// Chapter `index` as the writer serialises it, for checking the XHTML a
+/// chapter produces without writing a whole book.
+pub(crate) fn chapter_xhtml(doc: &Document, index: usize) -> Result<Vec<u8>, WriteError> {
+    let note_chapters: HashMap<&str, usize> = collect_notes(doc)
+        .iter()
+        .map(|n| (n.id, n.chapter))
+        .collect();
+    let links = LinkIndex::new(doc);
+    let ctx = XhtmlContext {
+        doc,
+        note_chapters: &note_chapters,
+        links: &links,
+        stylesheets: &[],
+        chapter: index,
+        headings: Cell::new(0),
+    };
+    let mut out = Vec::new();
+    write_chapter_xhtml(&doc.content[index], None, &ctx, &mut out)?;
+    Ok(out)
+}
+
+/// `viewport` is the page size in pixels for fixed-layout pages.
+fn write_chapter_xhtml<W: Write>(
+    chapter: &Chapter,
+    viewport: Option<(u32, u32)>,
+    ctx: &XhtmlContext,
+    w: &mut W,
+) -> Result<(), WriteError> {
+    writeln!(
+        w,
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head>
+  <meta charset="UTF-8"/>
+  <title>{}</title>"#,
         chapter
             .title
             .as_deref()
@@ -189,9 +1059,24 @@ fn write_chapter_xhtml<W: Write>(chapter: &Chapter, w: &mut W) -> Result<(), Wri
             .replace('>', "&gt;")
             .replace('&', "&amp;")
     )?;
+    for href in ctx.stylesheets {
+        writeln!(
+            w,
+            "  <link rel=\"stylesheet\" type=\"text/css\" href=\"{}\"/>",
+            escape_xml(href)
+        )?;
+    }
+    if let Some((width, height)) = viewport {
+        writeln!(
+            w,
+            r#"  <meta name="viewport" content="width={width}, height={height}"/>
+  <style>html, body {{ margin: 0; padding: 0; width: {width}px; height: {height}px; }} img {{ display: block; width: {width}px; height: {height}px; object-fit: contain; }}</style>"#
+        )?;
+    }
+    writeln!(w, "</head>\n<body>")?;
 
     for node in &chapter.content {
-        write_content_node_xhtml(node, w)?;
+        write_content_node_xhtml(node, ctx, w)?;
     }
 
     writeln!(w, "</body>")?;
@@ -200,41 +1085,64 @@ fn write_chapter_xhtml<W: Write>(chapter: &Chapter, w: &mut W) -> Result<(), Wri
     Ok(())
 }
 
-fn write_content_node_xhtml<W: Write>(node: &ContentNode, w: &mut W) -> Result<(), WriteError> {
+fn write_content_node_xhtml<W: Write>(
+    node: &ContentNode,
+    ctx: &XhtmlContext,
+    w: &mut W,
+) -> Result<(), WriteError> {
+    write_block_xhtml(node, &Attributes::default(), ctx, w)
+}
+
+/// `attrs` come from an enclosing `Styled` node and are written on the
+/// block's own element.
+fn write_block_xhtml<W: Write>(
+    node: &ContentNode,
+    attrs: &Attributes,
+    ctx: &XhtmlContext,
+    w: &mut W,
+) -> Result<(), WriteError> {
+    let markup = xhtml_attributes(attrs);
     match node {
         ContentNode::Paragraph { children } => {
-            write!(w, "<p>")?;
+            write!(w, "<p{}>", markup)?;
             for c in children {
-                write_inline_xhtml(c, w)?;
+                write_inline_xhtml(c, ctx, w)?;
             }
             writeln!(w, "</p>")?;
         }
         ContentNode::Heading { level, children } => {
-            write!(w, "<h{}>", level)?;
+            let n = ctx.headings.get() + 1;
+            ctx.headings.set(n);
+            // Headings without an id of their own get one for the TOC.
+            if attrs.id.is_none() {
+                write!(w, "<h{} id=\"heading-{}\"{}>", level, n, markup)?;
+            } else {
+                write!(w, "<h{}{}>", level, markup)?;
+            }
             for c in children {
-                write_inline_xhtml(c, w)?;
+                write_inline_xhtml(c, ctx, w)?;
             }
             writeln!(w, "</h{}>", level)?;
         }
         ContentNode::List { ordered, items } => {
             let tag = if *ordered { "ol" } else { "ul" };
-            writeln!(w, "<{}>", tag)?;
+            writeln!(w, "<{}{}>", tag, markup)?;
             for item in items {
                 write!(w, "<li>")?;
                 for sub in item {
-                    write_content_node_xhtml(sub, w)?;
+                    write_content_node_xhtml(sub, ctx, w)?;
                 }
                 writeln!(w, "</li>")?;
             }
             writeln!(w, "</{}>", tag)?;
         }
         ContentNode::Table { headers, rows } => {
-            writeln!(w, "<table>")?;
+            writeln!(w, "<table{}>", markup)?;
             writeln!(w, "<thead><tr>")?;
             for cell in headers {
                 write!(w, "<th>")?;
                 for c in cell {
-                    write_inline_xhtml(c, w)?;
+                    write_inline_xhtml(c, ctx, w)?;
                 }
                 write!(w, "</th>")?;
             }
@@ -244,7 +1152,7 @@ fn write_content_node_xhtml<W: Write>(node: &ContentNode, w: &mut W) -> Result<(
                 for cell in row {
                     write!(w, "<td>")?;
                     for c in cell {
-                        write_inline_xhtml(c, w)?;
+                        write_inline_xhtml(c, ctx, w)?;
                     }
                     write!(w, "</td>")?;
                 }
@@ -253,85 +1161,115 @@ fn write_content_node_xhtml<W: Write>(node: &ContentNode, w: &mut W) -> Result<(
             writeln!(w, "</tbody></table>")?;
         }
         ContentNode::BlockQuote { children } => {
-            writeln!(w, "<blockquote>")?;
+            writeln!(w, "<blockquote{}>", markup)?;
             for c in children {
-                write_content_node_xhtml(c, w)?;
+                write_content_node_xhtml(c, ctx, w)?;
             }
             writeln!(w, "</blockquote>")?;
         }
         ContentNode::CodeBlock { code, .. } => {
-            writeln!(w, "<pre><code>{}</code></pre>", escape_xml(code))?;
+            writeln!(w, "<pre{}><code>{}</code></pre>", markup, escape_xml(code))?;
         }
         ContentNode::Image {
             resource_id,
             alt_text,
             ..
         } => {
-            let alt = alt_text
-                .as_deref()
-                .unwrap_or("")
-                .replace('<', "&lt;")
-                .replace('>', "&gt;")
-                .replace('&', "&amp;")
-                .replace('"', "&quot;");
             writeln!(
                 w,
-                "<img src=\"resources/{}\" alt=\"{}\"/>",
-                resource_id.replace('"', "%22"),
-                alt
+                "<img{} src=\"{}\" alt=\"{}\"/>",
+                markup,
+                escape_xml(&ctx.image_src(resource_id)),
+                escape_xml(alt_text.as_deref().unwrap_or(""))
             )?;
         }
         ContentNode::HorizontalRule => {
-            writeln!(w, "<hr/>")?;
+            writeln!(w, "<hr{}/>", markup)?;
         }
         ContentNode::RawHtml(s) => {
             write!(w, "{}", s)?;
         }
+        // EPUB3 reading systems show these as popups from the noteref.
+        ContentNode::Note { id, kind, children } => {
+            let epub_type = match kind {
+                NoteKind::Footnote => "footnote",
+                NoteKind::Endnote => "endnote",
+            };
+            writeln!(
+                w,
+                "<aside epub:type=\"{}\" id=\"{}\"{}>",
+                epub_type,
+                escape_xml(id),
+                markup
+            )?;
+            for c in children {
+                write_content_node_xhtml(c, ctx, w)?;
+            }
+            writeln!(w, "</aside>")?;
+        }
+        ContentNode::Container {
+            attrs: own,
+            children,
+        } => {
+            writeln!(w, "<div{}{}>", markup, xhtml_attributes(own))?;
+            for c in children {
+                write_content_node_xhtml(c, ctx, w)?;
+            }
+            writeln!(w, "</div>")?;
+        }
+        ContentNode::Styled { attrs: own, node } if attrs.is_empty() => {
+            write_block_xhtml(node, own, ctx, w)?;
+        }
+        // Nested styling: the outer attributes go on a wrapper.
+        ContentNode::Styled { .. } => {
+            writeln!(w, "<div{}>", markup)?;
+            write_content_node_xhtml(node, ctx, w)?;
+            writeln!(w, "</div>")?;
+        }
     }
     Ok(())
 }
 
-fn write_inline_xhtml<W: Write>(node: &InlineNode, w: &mut W) -> Result<(), WriteError> {
+fn write_inline_xhtml<W: Write>(
+    node: &InlineNode,
+    ctx: &XhtmlContext,
+    w: &mut W,
+) -> Result<(), WriteError> {
     match node {
         InlineNode::Text(s) => write!(w, "{}", escape_xml(s))?,
         InlineNode::Emphasis(children) => {
             write!(w, "<em>")?;
             for c in children {
-                write_inline_xhtml(c, w)?;
+                write_inline_xhtml(c, ctx, w)?;
             }
             write!(w, "</em>")?;
         }
         InlineNode::Strong(children) => {
             write!(w, "<strong>")?;
             for c in children {
-                write_inline_xhtml(c, w)?;
+                write_inline_xhtml(c, ctx, w)?;
             }
             write!(w, "</strong>")?;
         }
         InlineNode::Code(s) => write!(w, "<code>{}</code>", escape_xml(s))?,
         InlineNode::Link { href, children } => {
-            let href_esc = href
-                .replace('&', "&amp;")
-                .replace('<', "&lt;")
-                .replace('>', "&gt;")
-                .replace('"', "&quot;");
-            write!(w, "<a href=\"{}\">", href_esc)?;
+            write!(w, "<a href=\"{}\">", escape_xml(&ctx.link_href(href)))?;
             for c in children {
-                write_inline_xhtml(c, w)?;
+                write_inline_xhtml(c, ctx, w)?;
             }
             write!(w, "</a>")?;
         }
         InlineNode::Superscript(children) => {
             write!(w, "<sup>")?;
             for c in children {
-                write_inline_xhtml(c, w)?;
+                write_inline_xhtml(c, ctx, w)?;
             }
             write!(w, "</sup>")?;
         }
         InlineNode::Subscript(children) => {
             write!(w, "<sub>")?;
             for c in children {
-                write_inline_xhtml(c, w)?;
+                write_inline_xhtml(c, ctx, w)?;
             }
             write!(w, "</sub>")?;
         }
@@ -344,14 +1282,635 @@ fn write_inline_xhtml<W: Write>(node: &InlineNode, w: &mut W) -> Result<(), Writ
             )?;
         }
         InlineNode::LineBreak => write!(w, "<br/>")?,
+        InlineNode::NoteRef { id, label } => write!(
+            w,
+            "<sup><a epub:type=\"noteref\" href=\"{}\">{}</a></sup>",
+            escape_xml(&ctx.note_href(id)),
+            escape_xml(label)
+        )?,
+        InlineNode::Span { attrs, children } => {
+            write!(w, "<span{}>", xhtml_attributes(attrs))?;
+            for c in children {
+                write_inline_xhtml(c, ctx, w)?;
+            }
+            write!(w, "</span>")?;
+        }
     }
     Ok(())
 }
 
-fn escape_xml(s: &str) -> String {
-    s.replace('&', "&amp;")
-        .replace('<', "&lt;")
-        .replace('>', "&gt;")
-        .replace('"', "&quot;")
-        .replace('\'', "&apos;")
+/// ` id="…" class="…" role="…" xml:lang="…" lang="…"` for the attributes
+/// that are set.
+fn xhtml_attributes(attrs: &Attributes) -> String {
+    let mut out = String::new();
+    if let Some(id) = &attrs.id {
+        out.push_str(&format!(" id=\"{}\"", escape_xml(id)));
+    }
+    if let Some(class) = &attrs.class {
+        out.push_str(&format!(" class=\"{}\"", escape_xml(class)));
+    }
+    if let Some(role) = &attrs.role {
+        out.push_str(&format!(" role=\"{}\"", escape_xml(role)));
+    }
+    if let Some(lang) = &attrs.lang {
+        let lang = escape_xml(lang);
+        out.push_str(&format!(" xml:lang=\"{}\" lang=\"{}\"", lang, lang));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::readers::cbz::CbzReader;
+    use crate::readers::{FormatReader, ReadOptions};
+    use std::io::Read;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut data = Vec::new();
+        image::RgbImage::new(width, height)
+            .write_to(&mut Cursor::new(&mut data), image::ImageFormat::Png)
+            .unwrap();
+        data
+    }
+
+    #[test]
+    fn test_manga_cbz_to_fixed_layout_epub() {
+        let mut cbz = Vec::new();
+        {
+            let mut zip = zip::ZipWriter::new(Cursor::new(&mut cbz));
+            let options: zip::write::FileOptions<'_, ()> = zip::write::FileOptions::default();
+            zip.start_file("ComicInfo.xml", options).unwrap();
+            zip.write_all(b"<ComicInfo><Series>Blade</Series><Manga>YesAndRightToLeft</Manga></ComicInfo>")
+                .unwrap();
+            for name in ["10.png", "9.png"] {
+                zip.start_file(name, options).unwrap();
+                zip.write_all(&png(40, 60)).unwrap();
+            }
+            zip.finish().unwrap();
+        }
+        let doc = CbzReader::read(Cursor::new(cbz), &ReadOptions::default(), None).unwrap();
+
+        let mut epub = Vec::new();
+        EpubWriter::write(&doc, &mut epub, &WriteOptions::default(), None).unwrap();
+        let mut archive = zip::ZipArchive::new(Cursor::new(epub)).unwrap();
+        let read = |archive: &mut zip::ZipArchive<Cursor<Vec<u8>>>, name: &str| {
+            let mut s = String::new();
+            archive.by_name(name).unwrap().read_to_string(&mut s).unwrap();
+            s
+        };
+
+        let opf = read(&mut archive, OPF_PATH);
+        assert!(opf.contains("<meta property=\"rendition:layout\">pre-paginated</meta>"));
+        assert!(opf.contains("<meta property=\"rendition:spread\">landscape</meta>"));
+        assert!(opf.contains("<spine toc=\"ncx\" page-progression-direction=\"rtl\">"));
+        assert!(opf.contains("idref=\"chapter1\" properties=\"page-spread-left\""));
+        assert!(opf.contains("idref=\"chapter2\" properties=\"page-spread-right\""));
+
+        let page = read(&mut archive, "OEBPS/chapter1.xhtml");
+        assert!(page.contains("<meta name=\"viewport\" content=\"width=40, height=60\"/>"));
+        assert!(page.contains("<img src=\"resources/page0001.png\""));
+        assert!(archive.by_name("OEBPS/resources/page0002.png").is_ok());
+        let nav = read(&mut archive, "OEBPS/nav.xhtml");
+        assert!(nav.contains("<li><a href=\"chapter2.xhtml\">2</a></li>"));
+        assert!(read(&mut archive, "OEBPS/toc.ncx").contains("<pageTarget id=\"page-2\""));
+    }
+
+    #[test]
+    fn test_notes_round_trip_as_popups() {
+        let chapter = |id: &str, content| Chapter {
+            id: id.into(),
+            title: None,
+            content,
+            text_direction: None,
+        };
+        let doc = Document {
+            content: vec![
+                chapter(
+                    "text",
+                    vec![ContentNode::Paragraph {
+                        children: vec![
+                            InlineNode::Text("Claim".into()),
+                            InlineNode::NoteRef {
+                                id: "en1".into(),
+                                label: "1".into(),
+                            },
+                        ],
+                    }],
+                ),
+                chapter(
+                    "notes",
+                    vec![ContentNode::Note {
+                        id: "en1".into(),
+                        kind: NoteKind::Endnote,
+                        children: vec![ContentNode::Paragraph {
+                            children: vec![InlineNode::Text("Source.".into())],
+                        }],
+                    }],
+                ),
+            ],
+            ..Default::default()
+        };
+
+        let mut epub = Vec::new();
+        EpubWriter::write(&doc, &mut epub, &WriteOptions::default(), None).unwrap();
+        let mut archive = zip::ZipArchive::new(Cursor::new(epub.clone())).unwrap();
+        let mut page = String::new();
+        archive
+            .by_name("OEBPS/chapter1.xhtml")
+            .unwrap()
+            .read_to_string(&mut page)
+            .unwrap();
+        assert!(
+            page.contains("<sup><a epub:type=\"noteref\" href=\"chapter2.xhtml#en1\">1</a></sup>")
+        );
+
+        let back = crate::readers::epub::EpubReader::read(
+            Cursor::new(epub),
+            &ReadOptions::default(),
+            None,
+        )
+        .unwrap();
+        let ContentNode::Paragraph { children } = &back.content[0].content[0] else {
+            panic!("expected paragraph");
+        };
+        assert!(
+            matches!(&children[1], InlineNode::NoteRef { id, label } if id == "en1" && label == "1")
+        );
+        assert!(matches!(
+            &back.content[1].content[0],
+            ContentNode::Note { id, kind: NoteKind::Endnote, .. } if id == "en1"
+        ));
+    }
+
+    #[test]
+    fn test_stylesheets_and_classes() {
+        let mut doc = Document {
+            content: vec![Chapter {
+                id: "one".into(),
+                title: None,
+                content: vec![ContentNode::Styled {
+                    attrs: Attributes {
+                        class: Some("noindent".into()),
+                        lang: Some("fr".into()),
+                        ..Default::default()
+                    },
+                    node: Box::new(ContentNode::Paragraph {
+                        children: vec![InlineNode::Span {
+                            attrs: Attributes {
+                                class: Some("sc".into()),
+                                ..Default::default()
+                            },
+                            children: vec![InlineNode::Text("Bonjour".into())],
+                        }],
+                    }),
+                }],
+                text_direction: None,
+            }],
+            ..Default::default()
+        };
+        let read = |doc: &Document, name: &str| {
+            let mut epub = Vec::new();
+            EpubWriter::write(doc, &mut epub, &WriteOptions::default(), None).unwrap();
+            let mut archive = zip::ZipArchive::new(Cursor::new(epub)).unwrap();
+            let mut s = String::new();
+            archive.by_name(name).unwrap().read_to_string(&mut s).unwrap();
+            s
+        };
+
+        // Without stylesheets of its own the book gets the default one.
+        let page = read(&doc, "OEBPS/chapter1.xhtml");
+        assert!(page.contains("<link rel=\"stylesheet\" type=\"text/css\" href=\"style.css\"/>"));
+        assert!(page.contains(
+            "<p class=\"noindent\" xml:lang=\"fr\" lang=\"fr\"><span class=\"sc\">Bonjour</span></p>"
+        ));
+        assert!(read(&doc, OPF_PATH).contains("href=\"style.css\" media-type=\"text/css\""));
+
+        doc.resources.insert(
+            "css".into(),
+            Resource {
+                id: "css".into(),
+                media_type: "text/css".into(),
+                data: b".sc { font-variant: small-caps; }".to_vec(),
+                filename: Some("Styles/book.css".into()),
+                obfuscation: None,
+            },
+        );
+        let page = read(&doc, "OEBPS/chapter1.xhtml");
+        assert!(page.contains("href=\"resources/Styles/book.css\"/>"));
+        assert!(!page.contains("href=\"style.css\""));
+    }
+
+    #[test]
+    fn test_nav_and_ncx_from_toc() {
+        let heading = |level, text: &str| ContentNode::Heading {
+            level,
+            children: vec![InlineNode::Text(text.into())],
+        };
+        let doc = Document {
+            metadata: Metadata {
+                title: Some("Tales".into()),
+                ..Default::default()
+            },
+            content: vec![
+                Chapter {
+                    id: "part1".into(),
+                    title: None,
+                    content: vec![heading(1, "Part One"), heading(2, "The Storm")],
+                    text_direction: None,
+                },
+                Chapter {
+                    id: "ch2".into(),
+                    title: None,
+                    content: vec![heading(2, "Calm")],
+                    text_direction: None,
+                },
+            ],
+            toc: vec![TocEntry {
+                title: "Part One".into(),
+                href: String::new(),
+                children: vec![
+                    TocEntry {
+                        title: "The  Storm".into(),
+                        href: "Text/part1.xhtml#sec-storm".into(),
+                        children: Vec::new(),
+                    },
+                    TocEntry {
+                        title: "Calm".into(),
+                        href: "ch2".into(),
+                        children: Vec::new(),
+                    },
+                ],
+            }],
+            ..Default::default()
+        };
+
+        let mut epub = Vec::new();
+        EpubWriter::write(&doc, &mut epub, &WriteOptions::default(), None).unwrap();
+        let mut archive = zip::ZipArchive::new(Cursor::new(epub.clone())).unwrap();
+        let mut read = |name: &str| {
+            let mut s = String::new();
+            archive
+                .by_name(name)
+                .unwrap()
+                .read_to_string(&mut s)
+                .unwrap();
+            s
+        };
+
+        let nav = read("OEBPS/nav.xhtml");
+        assert!(nav.contains("<li><a href=\"chapter1.xhtml#heading-2\">Part One</a>"));
+        assert!(nav.contains("<li><a href=\"chapter1.xhtml#heading-2\">The  Storm</a></li>"));
+        assert!(nav.contains("<li><a href=\"chapter2.xhtml\">Calm</a></li>"));
+        assert!(nav.contains("epub:type=\"landmarks\""));
+        assert!(read("OEBPS/chapter1.xhtml").contains("<h2 id=\"heading-2\">The Storm</h2>"));
+
+        let ncx = read("OEBPS/toc.ncx");
+        assert!(ncx.contains("<meta name=\"dtb:depth\" content=\"2\"/>"));
+        assert!(ncx.contains("<navPoint id=\"navpoint-2\" playOrder=\"1\">"));
+        assert!(ncx.contains("<navPoint id=\"navpoint-3\" playOrder=\"2\">"));
+        let opf = read(OPF_PATH);
+        assert!(opf.contains(
+            "href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\""
+        ));
+        assert!(opf.contains("<spine toc=\"ncx\">"));
+
+        let back = crate::readers::epub::EpubReader::read(
+            Cursor::new(epub),
+            &ReadOptions::default(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(back.toc.len(), 1);
+        assert_eq!(back.toc[0].href, "chapter1#heading-2");
+        assert_eq!(back.toc[0].children[1].href, "chapter2");
+    }
+
+    #[test]
+    fn test_toc_synthesized_from_headings() {
+        let doc = Document {
+            content: vec![
+                Chapter {
+                    id: "a".into(),
+                    title: Some("Preface".into()),
+                    content: vec![ContentNode::Paragraph {
+                        children: vec![InlineNode::Text("Hello".into())],
+                    }],
+                    text_direction: None,
+                },
+                Chapter {
+                    id: "b".into(),
+                    title: None,
+                    content: vec![
+                        ContentNode::Heading {
+                            level: 1,
+                            children: vec![InlineNode::Text("One".into())],
+                        },
+                        ContentNode::Styled {
+                            attrs: Attributes {
+                                class: Some("sub".into()),
+                                ..Default::default()
+                            },
+                            node: Box::new(ContentNode::Heading {
+                                level: 2,
+                                children: vec![InlineNode::Text("One A".into())],
+                            }),
+                        },
+                    ],
+                    text_direction: None,
+                },
+            ],
+            ..Default::default()
+        };
+        let opts = WriteOptions {
+            epub_version: Some(EpubVersion::V2),
+            ..Default::default()
+        };
+        let mut epub = Vec::new();
+        EpubWriter::write(&doc, &mut epub, &opts, None).unwrap();
+        let mut archive = zip::ZipArchive::new(Cursor::new(epub)).unwrap();
+        assert!(archive.by_name("OEBPS/nav.xhtml").is_err());
+        let mut ncx = String::new();
+        archive
+            .by_name("OEBPS/toc.ncx")
+            .unwrap()
+            .read_to_string(&mut ncx)
+            .unwrap();
+
+        let labels: Vec<&str> = ncx
+            .match_indices("<text>")
+            .map(|(i, _)| &ncx[i + 6..i + ncx[i..].find("</text>").unwrap()])
+            .collect();
+        assert_eq!(labels, ["Untitled", "Preface", "One", "One A"]);
+        assert!(ncx.contains("<content src=\"chapter1.xhtml\"/>"));
+        assert!(ncx.contains("<content src=\"chapter2.xhtml#heading-2\"/>"));
+        assert!(ncx.contains("<meta name=\"dtb:depth\" content=\"2\"/>"));
+    }
+
+    #[test]
+    fn test_metadata_round_trip() {
+        let contributor = |name: &str, file_as: Option<&str>, role: &str| Contributor {
+            name: name.into(),
+            file_as: file_as.map(Into::into),
+            role: Some(role.into()),
+        };
+        let doc = Document {
+            metadata: Metadata {
+                title: Some("Persuasion".into()),
+                subtitle: Some("A Novel".into()),
+                authors: vec!["Jane Austen".into()],
+                contributors: vec![
+                    contributor("Jane Austen", Some("Austen, Jane"), "aut"),
+                    contributor("Anthea Bell", Some("Bell, Anthea"), "trl"),
+                ],
+                identifiers: vec![
+                    Identifier {
+                        value: "9780141439688".into(),
+                        scheme: Some("ISBN".into()),
+                    },
+                    Identifier {
+                        value: "B008TVLRHQ".into(),
+                        scheme: Some("AMAZON".into()),
+                    },
+                ],
+                series: Some(SeriesInfo {
+                    name: "Austen Novels".into(),
+                    position: Some(6.0),
+                }),
+                modified: Some("2020-01-02T03:04:05Z".into()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        for version in [EpubVersion::V3, EpubVersion::V2] {
+            let opts = WriteOptions {
+                epub_version: Some(version),
+                ..Default::default()
+            };
+            let mut epub = Vec::new();
+            EpubWriter::write(&doc, &mut epub, &opts, None).unwrap();
+            let mut opf = String::new();
+            zip::ZipArchive::new(Cursor::new(epub.clone()))
+                .unwrap()
+                .by_name(OPF_PATH)
+                .unwrap()
+                .read_to_string(&mut opf)
+                .unwrap();
+            assert!(opf.contains("<dc:identifier id=\"uid\""));
+            assert!(opf.contains(">urn:isbn:9780141439688</dc:identifier>"));
+            assert!(opf.contains("name=\"calibre:series\" content=\"Austen Novels\""));
+
+            let back = crate::readers::epub::EpubReader::read(
+                Cursor::new(epub),
+                &ReadOptions::default(),
+                None,
+            )
+            .unwrap()
+            .metadata;
+            assert_eq!(back.authors, doc.metadata.authors);
+            assert_eq!(back.contributors, doc.metadata.contributors);
+            assert_eq!(back.identifiers, doc.metadata.identifiers);
+            let series = back.series.unwrap();
+            assert_eq!(
+                (series.name.as_str(), series.position),
+                ("Austen Novels", Some(6.0))
+            );
+            // EPUB2 has no refines for a subtitle and no modification date.
+            if version == EpubVersion::V3 {
+                assert_eq!(back.subtitle, doc.metadata.subtitle);
+                assert_eq!(back.modified, doc.metadata.modified);
+            }
+        }
+    }
+
+    #[test]
+    fn test_streams_to_seekable_sink_with_progress() {
+        struct Events(std::sync::Mutex<Vec<(u64, Option<u64>, String)>>);
+        impl ProgressHandler for Events {
+            fn on_progress(&self, e: crate::progress::ProgressEvent) {
+                let mut events = self.0.lock().unwrap();
+                events.push((e.current, e.total, e.message.unwrap_or_default()));
+            }
+        }
+
+        let mut doc = Document::default();
+        for i in 0..2 {
+            doc.content.push(Chapter {
+                id: format!("c{}", i),
+                title: None,
+                content: vec![ContentNode::Image {
+                    resource_id: "plate".into(),
+                    alt_text: None,
+                    caption: None,
+                }],
+                text_direction: None,
+            });
+        }
+        doc.resources.insert(
+            "plate".into(),
+            Resource {
+                id: "plate".into(),
+                data: png(4, 4),
+                media_type: "image/png".into(),
+                filename: Some("plate.png".into()),
+                obfuscation: None,
+            },
+        );
+
+        let events = Events(Default::default());
+        let mut file = tempfile::tempfile().unwrap();
+        EpubWriter::write_seekable(&doc, &mut file, &WriteOptions::default(), Some(&events))
+            .unwrap();
+        let events = events.0.into_inner().unwrap();
+        assert_eq!(
+            events,
+            [
+                (1, Some(3), "Chapter 1/2".to_string()),
+                (2, Some(3), "Chapter 2/2".to_string()),
+                (3, Some(3), "resources/plate.png".to_string()),
+            ]
+        );
+
+        let mut streamed = Vec::new();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.read_to_end(&mut streamed).unwrap();
+        let mut spooled = Vec::new();
+        EpubWriter::write(&doc, &mut spooled, &WriteOptions::default(), None).unwrap();
+        assert_eq!(streamed.len(), spooled.len());
+        let mut archive = zip::ZipArchive::new(Cursor::new(streamed)).unwrap();
+        assert_eq!(archive.by_index(0).unwrap().name(), "mimetype");
+        assert!(archive.by_name("OEBPS/resources/plate.png").is_ok());
+    }
+
+    #[test]
+    fn test_image_src_matches_manifest_href() {
+        let image = |reference: &str| ContentNode::Image {
+            resource_id: reference.into(),
+            alt_text: Some("A \"plate\" & <map>".into()),
+            caption: None,
+        };
+        let mut doc = Document::default();
+        doc.content.push(Chapter {
+            id: "c1".into(),
+            title: None,
+            // By id, by the source's relative path, and by a bare file name.
+            content: vec![
+                image("plate"),
+                image("../Images/map.png"),
+                image("seal.png"),
+            ],
+            text_direction: None,
+        });
+        for (id, filename) in [
+            ("plate", Some("Images/plate.png")),
+            ("map", Some("Images/map.png")),
+            ("seal.png", None),
+        ] {
+            doc.resources.insert(
+                id.into(),
+                Resource {
+                    id: id.into(),
+                    data: png(4, 4),
+                    media_type: "image/png".into(),
+                    filename: filename.map(str::to_string),
+                    obfuscation: None,
+                },
+            );
+        }
+
+        let mut epub = Vec::new();
+        EpubWriter::write(&doc, &mut epub, &WriteOptions::default(), None).unwrap();
+        let mut archive = zip::ZipArchive::new(Cursor::new(epub)).unwrap();
+        let mut read = |name: &str| {
+            let mut s = String::new();
+            archive
+                .by_name(name)
+                .unwrap()
+                .read_to_string(&mut s)
+                .unwrap();
+            s
+        };
+        let opf = read(OPF_PATH);
+        let page = read("OEBPS/chapter1.xhtml");
+
+        let attrs = |xml: &str, prefix: &str, attr: &str| -> Vec<String> {
+            xml.split(prefix)
+                .skip(1)
+                .filter_map(|tag| tag.split(&format!("{}=\"", attr)).nth(1))
+                .filter_map(|rest| rest.split('"').next())
+                .map(str::to_string)
+                .collect()
+        };
+        let manifest = attrs(&opf, "<item ", "href");
+        let srcs = attrs(&page, "<img", "src");
+        assert_eq!(
+            srcs,
+            [
+                "resources/Images/plate.png",
+                "resources/Images/map.png",
+                "resources/seal.png.bin",
+            ]
+        );
+        for src in &srcs {
+            assert!(manifest.contains(src), "{} not in manifest", src);
+            assert!(archive.by_name(&format!("OEBPS/{}", src)).is_ok());
+        }
+        assert!(page.contains("alt=\"A &quot;plate&quot; &amp; &lt;map&gt;\""));
+    }
+
+    #[test]
+    fn test_obfuscated_fonts_round_trip() {
+        let font: Vec<u8> = (0..2000u32).map(|i| (i % 251) as u8).collect();
+        let mut doc = Document::default();
+        doc.metadata.identifiers.push(Identifier {
+            value: "urn:uuid:1b4e28ba-2fa1-11d2-883f-0016d3cca427".into(),
+            scheme: None,
+        });
+        doc.resources.insert(
+            "serif".into(),
+            Resource {
+                id: "serif".into(),
+                data: font.clone(),
+                media_type: "font/ttf".into(),
+                filename: Some("fonts/serif.ttf".into()),
+                obfuscation: Some(crate::fonts::idpf_obfuscation("urn:isbn:0000000000")),
+            },
+        );
+
+        let mut epub = Vec::new();
+        EpubWriter::write(&doc, &mut epub, &WriteOptions::default(), None).unwrap();
+        let mut archive = zip::ZipArchive::new(Cursor::new(epub.clone())).unwrap();
+        let mut encryption = String::new();
+        archive
+            .by_name("META-INF/encryption.xml")
+            .unwrap()
+            .read_to_string(&mut encryption)
+            .unwrap();
+        assert!(encryption.contains("Algorithm=\"http://www.idpf.org/2008/embedding\""));
+        assert!(encryption.contains("URI=\"OEBPS/resources/fonts/serif.ttf\""));
+        let mut stored = Vec::new();
+        archive
+            .by_name("OEBPS/resources/fonts/serif.ttf")
+            .unwrap()
+            .read_to_end(&mut stored)
+            .unwrap();
+        assert_ne!(stored[..1040], font[..1040]);
+        assert_eq!(stored[1040..], font[1040..]);
+
+        let back = crate::readers::epub::EpubReader::read(
+            Cursor::new(epub),
+            &ReadOptions::default(),
+            None,
+        )
+        .unwrap();
+        let res = back.resources.get("serif").unwrap();
+        assert_eq!(res.data, font);
+        assert_eq!(
+            res.obfuscation,
+            Some(crate::fonts::idpf_obfuscation(
+                "urn:uuid:1b4e28ba-2fa1-11d2-883f-0016d3cca427"
+            ))
+        );
+    }
 }
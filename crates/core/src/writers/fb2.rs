@@ -0,0 +1,871 @@
+//! FictionBook 2 writer: IR → FB2 XML.
+//!
+//! `Metadata` becomes `<description>` (series as `<sequence>`), each chapter a
+//! top-level `<section>` of the main body, and lower-level headings open nested
+//! sections. Block quotes whose paragraphs carry line breaks are written as
+//! poems, other block quotes as citations. Image resources are embedded as
+//! base64 `<binary>` elements.
+
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+
+use base64::Engine;
+use sha2::{Digest, Sha256};
+
+use crate::document::*;
+use crate::error::WriteError;
+use crate::progress::{emit_progress, ProgressHandler};
+use crate::readers::{collapse_whitespace, inline_text};
+use crate::writers::{collect_notes, find_resource, FormatWriter, WriteOptions};
+use crate::xml::escape_xml;
+
+pub struct Fb2Writer;
+
+/// `<lang>` is required in `<title-info>`; used when the book has no language.
+const DEFAULT_LANG: &str = "en";
+/// `<genre>` is required too; FB2 readers file unknown books under "prose".
+const DEFAULT_GENRE: &str = "prose";
+
+impl FormatWriter for Fb2Writer {
+    fn write<W: Write>(
+        doc: &Document,
+        output: W,
+        _opts: &WriteOptions,
+        progress: Option<&dyn ProgressHandler>,
+    ) -> Result<(), WriteError> {
+        let mut w = output;
+        let ctx = Context::new(doc);
+        let total = doc.content.len() as u64;
+
+        writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            w,
+            r#"<FictionBook xmlns="http://www.gribuser.ru/xml/fictionbook/2.0" xmlns:l="http://www.w3.org/1999/xlink">"#
+        )?;
+        write_description(&mut w, doc, &ctx)?;
+        writeln!(w, "<body>")?;
+        for (i, chapter) in doc.content.iter().enumerate() {
+            write_chapter(&mut w, chapter, &ctx)?;
+            emit_progress(
+                progress,
+                "Writing FB2",
+                i as u64 + 1,
+                Some(total),
+                Some(&format!("Chapter {}/{}", i + 1, total)),
+            );
+        }
+        writeln!(w, "</body>")?;
+        write_notes(&mut w, doc, &ctx)?;
+        write_binaries(&mut w, doc, &ctx)?;
+        writeln!(w, "</FictionBook>")?;
+        Ok(())
+    }
+}
+
+/// Ids shared between the description, bodies and binaries.
+struct Context<'a> {
+    doc: &'a Document,
+    /// Resource id → `<binary>` id (an XML ID, so no slashes or leading digits).
+    binary_ids: HashMap<String, String>,
+    /// Element ids written in the body, so `chapter#fragment` links can keep
+    /// their fragment when it exists and fall back to the chapter otherwise.
+    element_ids: HashSet<String>,
+    /// Nested section ids keyed by `(chapter id, title)`, taken from the TOC.
+    section_ids: HashMap<(String, String), String>,
+}
+
+impl<'a> Context<'a> {
+    fn new(doc: &'a Document) -> Self {
+        let mut binary_ids = HashMap::new();
+        let mut used = HashSet::new();
+        let mut resources: Vec<&Resource> = doc
+            .resources
+            .iter()
+            .map(|(_, r)| r)
+            .filter(|r| r.media_type.starts_with("image/"))
+            .collect();
+        resources.sort_by(|a, b| a.id.cmp(&b.id));
+        for res in resources {
+            let base = xml_id(
+                res.filename
+                    .as_deref()
+                    .map_or(res.id.as_str(), |f| f.rsplit('/').next().unwrap_or(f)),
+            );
+            let mut id = base.clone();
+            let mut n = 2;
+            while !used.insert(id.clone()) {
+                id = format!("{}-{}", base, n);
+                n += 1;
+            }
+            binary_ids.insert(res.id.clone(), id);
+        }
+
+        let mut element_ids: HashSet<String> = doc.content.iter().map(|c| c.id.clone()).collect();
+        let mut section_ids = HashMap::new();
+        fn collect(
+            entries: &[TocEntry],
+            element_ids: &mut HashSet<String>,
+            section_ids: &mut HashMap<(String, String), String>,
+        ) {
+            for entry in entries {
+                if let Some((chapter, fragment)) = entry.href.split_once('#') {
+                    if !fragment.is_empty() && element_ids.insert(fragment.to_string()) {
+                        section_ids.insert(
+                            (chapter.to_string(), entry.title.clone()),
+                            fragment.to_string(),
+                        );
+                    }
+                }
+                collect(&entry.children, element_ids, section_ids);
+            }
+        }
+        collect(&doc.toc, &mut element_ids, &mut section_ids);
+        element_ids.extend(collect_notes(doc).iter().map(|n| n.id.to_string()));
+
+        Self {
+            doc,
+            binary_ids,
+            element_ids,
+            section_ids,
+        }
+    }
+
+    fn binary_id(&self, reference: &str) -> Option<&str> {
+        let res = find_resource(self.doc, reference)?;
+        self.binary_ids.get(&res.id).map(String::as_str)
+    }
+
+    /// Map an IR link (`chapter-id#fragment`) to an FB2 `#id` link.
+    fn href(&self, href: &str) -> String {
+        if href.contains("://") || href.starts_with("mailto:") {
+            return href.to_string();
+        }
+        let (chapter, fragment) = href.split_once('#').unwrap_or((href, ""));
+        if !fragment.is_empty() && self.element_ids.contains(fragment) {
+            format!("#{}", fragment)
+        } else if self.element_ids.contains(chapter) {
+            format!("#{}", chapter)
+        } else {
+            href.to_string()
+        }
+    }
+}
+
+/// Turn an arbitrary name into a valid XML ID.
+fn xml_id(name: &str) -> String {
+    let mut id: String = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if !id.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+        id.insert(0, '_');
+    }
+    id
+}
+
+// --- Description ---
+
+fn write_description<W: Write>(w: &mut W, doc: &Document, ctx: &Context) -> Result<(), WriteError> {
+    let m = &doc.metadata;
+    writeln!(w, "<description>")?;
+    writeln!(w, "<title-info>")?;
+
+    // FB2 genres are a fixed vocabulary of codes (`sf_fantasy`, `prose_classic`);
+    // free-form subjects go to `<keywords>` instead.
+    let (genres, keywords): (Vec<&String>, Vec<&String>) = m.subjects.iter().partition(|s| {
+        !s.is_empty()
+            && s.chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+    });
+    if genres.is_empty() {
+        writeln!(w, "<genre>{}</genre>", DEFAULT_GENRE)?;
+    }
+    for genre in genres {
+        writeln!(w, "<genre>{}</genre>", escape_xml(genre))?;
+    }
+    if m.authors.is_empty() {
+        writeln!(w, "<author><nickname>Unknown</nickname></author>")?;
+    }
+    for author in &m.authors {
+        write_person(w, "author", author)?;
+    }
+    writeln!(
+        w,
+        "<book-title>{}</book-title>",
+        escape_xml(m.title.as_deref().unwrap_or("Untitled"))
+    )?;
+    if let Some(description) = m.description.as_deref().filter(|d| !d.trim().is_empty()) {
+        writeln!(w, "<annotation>")?;
+        for para in description
+            .split("\n\n")
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+        {
+            writeln!(w, "<p>{}</p>", escape_xml(&collapse_whitespace(para)))?;
+        }
+        writeln!(w, "</annotation>")?;
+    }
+    if !keywords.is_empty() {
+        let keywords: Vec<&str> = keywords.iter().map(|k| k.as_str()).collect();
+        writeln!(
+            w,
+            "<keywords>{}</keywords>",
+            escape_xml(&keywords.join(", "))
+        )?;
+    }
+    if let Some(date) = &m.publish_date {
+        writeln!(w, "<date>{}</date>", escape_xml(date))?;
+    }
+    if let Some(cover) = m.cover_image_id.as_deref().and_then(|c| ctx.binary_id(c)) {
+        writeln!(
+            w,
+            "<coverpage><image l:href=\"#{}\"/></coverpage>",
+            escape_xml(cover)
+        )?;
+    }
+    writeln!(
+        w,
+        "<lang>{}</lang>",
+        escape_xml(m.language.as_deref().unwrap_or(DEFAULT_LANG))
+    )?;
+    if let Some(src) = m.custom.get("source_language") {
+        writeln!(w, "<src-lang>{}</src-lang>", escape_xml(src))?;
+    }
+    if let Some(translators) = m.custom.get("translator") {
+        for t in translators
+            .split(';')
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+        {
+            write_person(w, "translator", t)?;
+        }
+    }
+    if let Some(series) = &m.series {
+        write!(w, "<sequence name=\"{}\"", escape_xml(&series.name))?;
+        if let Some(pos) = series.position {
+            write!(w, " number=\"{}\"", pos)?;
+        }
+        writeln!(w, "/>")?;
+    }
+    writeln!(w, "</title-info>")?;
+
+    writeln!(w, "<document-info>")?;
+    writeln!(w, "<author><nickname>ebook-converter</nickname></author>")?;
+    writeln!(w, "<program-used>ebook-converter</program-used>")?;
+    writeln!(
+        w,
+        "<date>{}</date>",
+        escape_xml(m.publish_date.as_deref().unwrap_or(""))
+    )?;
+    writeln!(w, "<id>{}</id>", escape_xml(&document_id(m)))?;
+    writeln!(w, "<version>1.0</version>")?;
+    writeln!(w, "</document-info>")?;
+
+    let year = m
+        .publish_date
+        .as_deref()
+        .and_then(|d| d.get(..4))
+        .filter(|y| y.chars().all(|c| c.is_ascii_digit()));
+    if m.publisher.is_some() || year.is_some() || m.isbn_13.is_some() || m.isbn_10.is_some() {
+        writeln!(w, "<publish-info>")?;
+        if let Some(publisher) = &m.publisher {
+            writeln!(w, "<publisher>{}</publisher>", escape_xml(publisher))?;
+        }
+        if let Some(year) = year {
+            writeln!(w, "<year>{}</year>", year)?;
+        }
+        if let Some(isbn) = m.isbn_13.as_ref().or(m.isbn_10.as_ref()) {
+            writeln!(w, "<isbn>{}</isbn>", escape_xml(isbn))?;
+        }
+        writeln!(w, "</publish-info>")?;
+    }
+    writeln!(w, "</description>")?;
+    Ok(())
+}
+
+/// The `document_id` read from an FB2 source, else a stable id derived from
+/// title and authors so re-exports of the same book keep their identity.
+fn document_id(m: &Metadata) -> String {
+    if let Some(id) = m.custom.get("document_id") {
+        return id.clone();
+    }
+    let mut hasher = Sha256::new();
+    hasher.update(m.title.as_deref().unwrap_or("").as_bytes());
+    for author in &m.authors {
+        hasher.update(b"\0");
+        hasher.update(author.as_bytes());
+    }
+    hasher.finalize()[..16]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Split "First Middle Last" into FB2 name parts; a single word is a nickname.
+fn write_person<W: Write>(w: &mut W, tag: &str, name: &str) -> Result<(), WriteError> {
+    let parts: Vec<&str> = name.split_whitespace().collect();
+    write!(w, "<{}>", tag)?;
+    match parts.as_slice() {
+        [] => {}
+        [nick] => write!(w, "<nickname>{}</nickname>", escape_xml(nick))?,
+        [first, middle @ .., last] => {
+            write!(w, "<first-name>{}</first-name>", escape_xml(first))?;
+            if !middle.is_empty() {
+                write!(
+                    w,
+                    "<middle-name>{}</middle-name>",
+                    escape_xml(&middle.join(" "))
+                )?;
+            }
+            write!(w, "<last-name>{}</last-name>", escape_xml(last))?;
+        }
+    }
+    writeln!(w, "</{}>", tag)?;
+    Ok(())
+}
+
+// --- Body ---
+
+/// One open `<section>` while walking a chapter's headings.
+struct OpenSection {
+    level: u8,
+    /// Written a nested section; further blocks need a section of their own,
+    /// since FB2 does not allow content after subsections.
+    has_subsections: bool,
+}
+
+fn write_chapter<W: Write>(w: &mut W, chapter: &Chapter, ctx: &Context) -> Result<(), WriteError> {
+    writeln!(w, "<section id=\"{}\">", escape_xml(&chapter.id))?;
+    let mut nodes = chapter.content.as_slice();
+    // A leading heading is the chapter's title; otherwise use the chapter title.
+    let base_level = match nodes.first().map(ContentNode::unstyled) {
+        Some(ContentNode::Heading { level, children }) => {
+            write_title(w, children, ctx)?;
+            nodes = &nodes[1..];
+            *level
+        }
+        _ => {
+            if let Some(title) = &chapter.title {
+                writeln!(w, "<title><p>{}</p></title>", escape_xml(title))?;
+            }
+            1
+        }
+    };
+
+    let mut stack = vec![OpenSection {
+        level: base_level,
+        has_subsections: false,
+    }];
+    for node in nodes {
+        match node.unstyled() {
+            ContentNode::Heading { level, children } if *level > 1 || stack.len() > 1 => {
+                while stack.len() > 1 && stack.last().is_some_and(|s| s.level >= *level) {
+                    writeln!(w, "</section>")?;
+                    stack.pop();
+                }
+                if let Some(parent) = stack.last_mut() {
+                    parent.has_subsections = true;
+                }
+                let title = inline_text(children);
+                match ctx.section_ids.get(&(chapter.id.clone(), title)) {
+                    Some(id) => writeln!(w, "<section id=\"{}\">", escape_xml(id))?,
+                    None => writeln!(w, "<section>")?,
+                }
+                write_title(w, children, ctx)?;
+                stack.push(OpenSection {
+                    level: *level,
+                    has_subsections: false,
+                });
+            }
+            _ => {
+                if stack.last().is_some_and(|s| s.has_subsections) {
+                    writeln!(w, "<section>")?;
+                    stack.push(OpenSection {
+                        level: u8::MAX,
+                        has_subsections: false,
+                    });
+                }
+                write_block(w, node, ctx)?;
+            }
+        }
+    }
+    for _ in 0..stack.len() {
+        writeln!(w, "</section>")?;
+    }
+    Ok(())
+}
+
+fn write_title<W: Write>(
+    w: &mut W,
+    children: &[InlineNode],
+    ctx: &Context,
+) -> Result<(), WriteError> {
+    write!(w, "<title>")?;
+    for line in split_lines(children) {
+        write!(w, "<p>{}</p>", render_inlines(line, ctx))?;
+    }
+    writeln!(w, "</title>")?;
+    Ok(())
+}
+
+fn write_block<W: Write>(w: &mut W, node: &ContentNode, ctx: &Context) -> Result<(), WriteError> {
+    match node {
+        ContentNode::Paragraph { children } => write_paragraphs(w, children, ctx)?,
+        ContentNode::Heading { children, .. } => {
+            let text = render_inlines(children, ctx);
+            if !text.trim().is_empty() {
+                writeln!(w, "<subtitle>{}</subtitle>", text.trim())?;
+            }
+        }
+        ContentNode::List { ordered, items } => {
+            // FB2 has no lists; items become paragraphs with their marker.
+            for (i, item) in items.iter().enumerate() {
+                let marker = if *ordered {
+                    format!("{}. ", i + 1)
+                } else {
+                    "• ".to_string()
+                };
+                let mut first = true;
+                for sub in item {
+                    match sub {
+                        ContentNode::Paragraph { children } if first => {
+                            let mut children = children.clone();
+                            children.insert(0, InlineNode::Text(marker.clone()));
+                            write_paragraphs(w, &children, ctx)?;
+                        }
+                        _ => write_block(w, sub, ctx)?,
+                    }
+                    first = false;
+                }
+            }
+        }
+        ContentNode::Table { headers, rows } => {
+            writeln!(w, "<table>")?;
+            if !headers.is_empty() {
+                write!(w, "<tr>")?;
+                for cell in headers {
+                    write!(w, "<th>{}</th>", render_inlines(cell, ctx).trim())?;
+                }
+                writeln!(w, "</tr>")?;
+            }
+            for row in rows {
+                write!(w, "<tr>")?;
+                for cell in row {
+                    write!(w, "<td>{}</td>", render_inlines(cell, ctx).trim())?;
+                }
+                writeln!(w, "</tr>")?;
+            }
+            writeln!(w, "</table>")?;
+        }
+        ContentNode::BlockQuote { children } => {
+            let is_poem = children.iter().any(|c| {
+                matches!(c, ContentNode::Paragraph { children }
+                    if children.iter().any(|i| matches!(i, InlineNode::LineBreak)))
+            });
+            if is_poem {
+                write_poem(w, children, ctx)?;
+            } else {
+                writeln!(w, "<cite>")?;
+                for child in children {
+                    write_block(w, child, ctx)?;
+                }
+                writeln!(w, "</cite>")?;
+            }
+        }
+        ContentNode::CodeBlock { code, .. } => {
+            for line in code.lines() {
+                if line.trim().is_empty() {
+                    writeln!(w, "<empty-line/>")?;
+                } else {
+                    writeln!(w, "<p><code>{}</code></p>", escape_xml(line))?;
+                }
+            }
+        }
+        ContentNode::Image {
+            resource_id,
+            alt_text,
+            caption,
+        } => {
+            let Some(id) = ctx.binary_id(resource_id) else {
+                tracing::warn!("Skipping image '{}': resource not found", resource_id);
+                return Ok(());
+            };
+            write!(w, "<image l:href=\"#{}\"", escape_xml(id))?;
+            if let Some(alt) = alt_text {
+                write!(w, " alt=\"{}\"", escape_xml(alt))?;
+            }
+            if let Some(caption) = caption {
+                write!(w, " title=\"{}\"", escape_xml(caption))?;
+            }
+            writeln!(w, "/>")?;
+        }
+        ContentNode::HorizontalRule => writeln!(w, "<subtitle>* * *</subtitle>")?,
+        ContentNode::Styled { node, .. } => write_block(w, node, ctx)?,
+        ContentNode::Container { children, .. } => {
+            for c in children {
+                write_block(w, c, ctx)?;
+            }
+        }
+        // Written as sections of the notes body.
+        ContentNode::RawHtml(_) | ContentNode::Note { .. } => {}
+    }
+    Ok(())
+}
+
+/// Notes go in a second `<body name="notes">`, one section per note; FB2
+/// readers show them from `<a type="note">` references.
+fn write_notes<W: Write>(w: &mut W, doc: &Document, ctx: &Context) -> Result<(), WriteError> {
+    let notes = collect_notes(doc);
+    if notes.is_empty() {
+        return Ok(());
+    }
+    writeln!(w, "<body name=\"notes\">")?;
+    for note in notes {
+        writeln!(w, "<section id=\"{}\">", escape_xml(note.id))?;
+        if let Some(label) = note.label {
+            writeln!(w, "<title><p>{}</p></title>", escape_xml(label))?;
+        }
+        for child in note.children {
+            write_block(w, child, ctx)?;
+        }
+        writeln!(w, "</section>")?;
+    }
+    writeln!(w, "</body>")?;
+    Ok(())
+}
+
+/// Paragraphs with line breaks become one `<p>` per line.
+fn write_paragraphs<W: Write>(
+    w: &mut W,
+    children: &[InlineNode],
+    ctx: &Context,
+) -> Result<(), WriteError> {
+    for line in split_lines(children) {
+        let text = render_inlines(line, ctx);
+        if !text.trim().is_empty() {
+            writeln!(w, "<p>{}</p>", text.trim())?;
+        }
+    }
+    Ok(())
+}
+
+/// Each paragraph is a stanza, each line a verse; headings are the poem title.
+fn write_poem<W: Write>(
+    w: &mut W,
+    children: &[ContentNode],
+    ctx: &Context,
+) -> Result<(), WriteError> {
+    writeln!(w, "<poem>")?;
+    let mut nodes = children;
+    if let Some(ContentNode::Heading { children, .. }) = nodes.first() {
+        write_title(w, children, ctx)?;
+        nodes = &nodes[1..];
+    }
+    let mut author = None;
+    for node in nodes {
+        match node {
+            ContentNode::Paragraph { children } => {
+                // A trailing italic line is the poem's author, as read from `<text-author>`.
+                if let [InlineNode::Emphasis(inner)] = children.as_slice() {
+                    author = Some(inner);
+                    continue;
+                }
+                writeln!(w, "<stanza>")?;
+                for line in split_lines(children) {
+                    let text = render_inlines(line, ctx);
+                    if !text.trim().is_empty() {
+                        writeln!(w, "<v>{}</v>", text.trim())?;
+                    }
+                }
+                writeln!(w, "</stanza>")?;
+            }
+            ContentNode::Heading { children, .. } => {
+                writeln!(
+                    w,
+                    "<stanza><title><p>{}</p></title></stanza>",
+                    render_inlines(children, ctx).trim()
+                )?;
+            }
+            _ => {
+                // Anything else stays readable as a verse line.
+                let text = escape_xml(&block_plain_text(node));
+                if !text.is_empty() {
+                    writeln!(w, "<stanza><v>{}</v></stanza>", text)?;
+                }
+            }
+        }
+    }
+    if let Some(author) = author {
+        writeln!(
+            w,
+            "<text-author>{}</text-author>",
+            render_inlines(author, ctx).trim()
+        )?;
+    }
+    writeln!(w, "</poem>")?;
+    Ok(())
+}
+
+// --- Binaries ---
+
+fn write_binaries<W: Write>(w: &mut W, doc: &Document, ctx: &Context) -> Result<(), WriteError> {
+    let mut binaries: Vec<(&String, &Resource)> = ctx
+        .binary_ids
+        .iter()
+        .filter_map(|(res_id, bin_id)| doc.resources.get(res_id).map(|r| (bin_id, r)))
+        .collect();
+    binaries.sort_by(|a, b| a.0.cmp(b.0));
+    for (id, res) in binaries {
+        let encoded = base64::engine::general_purpose::STANDARD.encode(&res.data);
+        writeln!(
+            w,
+            "<binary id=\"{}\" content-type=\"{}\">",
+            escape_xml(id),
+            escape_xml(&res.media_type)
+        )?;
+        // Wrap at 76 columns like MIME base64; the string is ASCII.
+        for chunk in encoded.as_bytes().chunks(76) {
+            w.write_all(chunk)?;
+            writeln!(w)?;
+        }
+        writeln!(w, "</binary>")?;
+    }
+    Ok(())
+}
+
+// --- Inlines ---
+
+/// Split inline content at top-level line breaks.
+fn split_lines(children: &[InlineNode]) -> Vec<&[InlineNode]> {
+    children
+        .split(|n| matches!(n, InlineNode::LineBreak))
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+fn render_inlines(nodes: &[InlineNode], ctx: &Context) -> String {
+    let mut out = String::new();
+    for node in nodes {
+        render_inline(node, ctx, &mut out);
+    }
+    out
+}
+
+fn render_inline(node: &InlineNode, ctx: &Context, out: &mut String) {
+    match node {
+        InlineNode::Text(s) => out.push_str(&escape_xml(&collapse_whitespace(s))),
+        InlineNode::Emphasis(c) => {
+            out.push_str("<emphasis>");
+            out.push_str(&render_inlines(c, ctx));
+            out.push_str("</emphasis>");
+        }
+        InlineNode::Strong(c) => {
+            out.push_str("<strong>");
+            out.push_str(&render_inlines(c, ctx));
+            out.push_str("</strong>");
+        }
+        InlineNode::Code(s) => {
+            out.push_str("<code>");
+            out.push_str(&escape_xml(s));
+            out.push_str("</code>");
+        }
+        InlineNode::Link { href, children } => {
+            out.push_str(&format!("<a l:href=\"{}\">", escape_xml(&ctx.href(href))));
+            out.push_str(&render_inlines(children, ctx));
+            out.push_str("</a>");
+        }
+        InlineNode::Superscript(c) => match c.as_slice() {
+            // `<sup><a>1</a></sup>` is a note reference.
+            [InlineNode::Link { href, children }] => {
+                out.push_str(&format!(
+                    "<a l:href=\"{}\" type=\"note\">",
+                    escape_xml(&ctx.href(href))
+                ));
+                out.push_str(&render_inlines(children, ctx));
+                out.push_str("</a>");
+            }
+            _ => {
+                out.push_str("<sup>");
+                out.push_str(&render_inlines(c, ctx));
+                out.push_str("</sup>");
+            }
+        },
+        InlineNode::Subscript(c) => {
+            out.push_str("<sub>");
+            out.push_str(&render_inlines(c, ctx));
+            out.push_str("</sub>");
+        }
+        InlineNode::Ruby { base, annotation } => {
+            out.push_str(&escape_xml(&format!("{} ({})", base, annotation)))
+        }
+        InlineNode::LineBreak => out.push(' '),
+        InlineNode::NoteRef { id, label } => out.push_str(&format!(
+            "<a l:href=\"#{}\" type=\"note\">{}</a>",
+            escape_xml(id),
+            escape_xml(label)
+        )),
+        InlineNode::Span { children, .. } => out.push_str(&render_inlines(children, ctx)),
+    }
+}
+
+fn block_plain_text(node: &ContentNode) -> String {
+    match node {
+        ContentNode::Paragraph { children } | ContentNode::Heading { children, .. } => {
+            inline_text(children)
+        }
+        ContentNode::CodeBlock { code, .. } => collapse_whitespace(code).trim().to_string(),
+        ContentNode::Styled { node, .. } => block_plain_text(node),
+        ContentNode::BlockQuote { children } | ContentNode::Container { children, .. } => children
+            .iter()
+            .map(block_plain_text)
+            .collect::<Vec<_>>()
+            .join(" "),
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::readers::fb2::Fb2Reader;
+    use crate::readers::{FormatReader, ReadOptions};
+    use std::io::Cursor;
+
+    fn doc() -> Document {
+        let mut resources = ResourceMap::new();
+        resources.insert(
+            "images/cover.png".into(),
+            Resource {
+                id: "images/cover.png".into(),
+                media_type: "image/png".into(),
+                data: b"\x89PNG\r\n\x1a\n".to_vec(),
+                filename: Some("images/cover.png".into()),
+                obfuscation: None,
+            },
+        );
+        Document {
+            metadata: Metadata {
+                title: Some("Tom & Jerry".into()),
+                authors: vec!["Lev Nikolayevich Tolstoy".into()],
+                language: Some("ru".into()),
+                subjects: vec!["prose_classic".into(), "War stories".into()],
+                series: Some(SeriesInfo {
+                    name: "Saga".into(),
+                    position: Some(2.0),
+                }),
+                cover_image_id: Some("images/cover.png".into()),
+                ..Default::default()
+            },
+            toc: vec![TocEntry {
+                title: "One".into(),
+                href: "c1".into(),
+                children: vec![TocEntry {
+                    title: "Inner".into(),
+                    href: "c1#inner".into(),
+                    children: Vec::new(),
+                }],
+            }],
+            content: vec![
+                Chapter {
+                    id: "c1".into(),
+                    title: Some("One".into()),
+                    content: vec![
+                        ContentNode::Heading {
+                            level: 1,
+                            children: vec![InlineNode::Text("One".into())],
+                        },
+                        ContentNode::Paragraph {
+                            children: vec![
+                                InlineNode::Text("Hello ".into()),
+                                InlineNode::Emphasis(vec![InlineNode::Text("world".into())]),
+                            ],
+                        },
+                        ContentNode::Heading {
+                            level: 2,
+                            children: vec![InlineNode::Text("Inner".into())],
+                        },
+                        ContentNode::BlockQuote {
+                            children: vec![ContentNode::Paragraph {
+                                children: vec![
+                                    InlineNode::Text("Line one".into()),
+                                    InlineNode::LineBreak,
+                                    InlineNode::Text("Line two".into()),
+                                ],
+                            }],
+                        },
+                        ContentNode::HorizontalRule,
+                        ContentNode::Image {
+                            resource_id: "images/cover.png".into(),
+                            alt_text: None,
+                            caption: None,
+                        },
+                    ],
+                    text_direction: None,
+                },
+                Chapter {
+                    id: "c2".into(),
+                    title: Some("Two".into()),
+                    content: vec![ContentNode::Paragraph {
+                        children: vec![InlineNode::Link {
+                            href: "c1#inner".into(),
+                            children: vec![InlineNode::Text("back".into())],
+                        }],
+                    }],
+                    text_direction: None,
+                },
+            ],
+            resources,
+            ..Default::default()
+        }
+    }
+
+    fn write(doc: &Document) -> String {
+        let mut out = Vec::new();
+        Fb2Writer::write(doc, &mut out, &WriteOptions::default(), None).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_writes_description_sections_and_binaries() {
+        let fb2 = write(&doc());
+        assert!(fb2.contains("<genre>prose_classic</genre>"));
+        assert!(fb2.contains("<keywords>War stories</keywords>"));
+        assert!(fb2.contains(
+            "<author><first-name>Lev</first-name><middle-name>Nikolayevich</middle-name><last-name>Tolstoy</last-name></author>"
+        ));
+        assert!(fb2.contains("<book-title>Tom &amp; Jerry</book-title>"));
+        assert!(fb2.contains("<sequence name=\"Saga\" number=\"2\"/>"));
+        assert!(fb2.contains("<coverpage><image l:href=\"#cover.png\"/></coverpage>"));
+        assert!(fb2.contains("<section id=\"inner\">\n<title><p>Inner</p></title>"));
+        assert!(fb2.contains("<poem>\n<stanza>\n<v>Line one</v>\n<v>Line two</v>\n</stanza>"));
+        assert!(fb2.contains("<a l:href=\"#inner\">back</a>"));
+        assert!(fb2.contains(
+            "<binary id=\"cover.png\" content-type=\"image/png\">\niVBORw0KGgo=\n</binary>"
+        ));
+    }
+
+    #[test]
+    fn test_round_trips_through_reader() {
+        let fb2 = write(&doc());
+        let back =
+            Fb2Reader::read(Cursor::new(fb2.into_bytes()), &ReadOptions::default(), None).unwrap();
+        assert_eq!(back.metadata.title.as_deref(), Some("Tom & Jerry"));
+        assert_eq!(back.metadata.authors, ["Lev Nikolayevich Tolstoy"]);
+        assert_eq!(back.metadata.series.as_ref().unwrap().position, Some(2.0));
+        assert_eq!(back.metadata.cover_image_id.as_deref(), Some("cover.png"));
+        let ids: Vec<&str> = back.content.iter().map(|c| c.id.as_str()).collect();
+        assert_eq!(ids, ["c1", "c2"]);
+        assert_eq!(back.toc[0].children[0].href, "c1#inner");
+        let first = &back.content[0].content;
+        assert!(matches!(first[2], ContentNode::Heading { level: 2, .. }));
+        assert!(matches!(first[3], ContentNode::BlockQuote { .. }));
+        assert!(matches!(first[4], ContentNode::HorizontalRule));
+        assert!(matches!(first[5], ContentNode::Image { .. }));
+    }
+}
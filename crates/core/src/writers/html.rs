@@ -0,0 +1,873 @@
+//! HTML writer: IR → standalone HTML5.
+//!
+//! Two layouts are supported:
+//! - a single self-contained file (the `FormatWriter` impl), with every chapter
+//!   in its own `<section>` and images inlined as `data:` URIs;
+//! - a directory (`HtmlWriter::write_dir`) with `index.html`, one page per
+//!   chapter, a shared `style.css` and images under `resources/`.
+//!
+//! Both include a navigation sidebar built from `Document.toc` (or from chapter
+//! titles when the document has no TOC).
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Write;
+use std::path::{Component, Path};
+
+use base64::Engine;
+
+use crate::document::*;
+use crate::error::WriteError;
+use crate::links::{LinkIndex, LinkTarget};
+use crate::progress::{emit_progress, ProgressHandler};
+use crate::writers::{collect_notes, find_resource, FormatWriter, WriteOptions};
+use crate::xml::escape_xml;
+
+/// Options for `HtmlWriter`, set through `WriteOptions::html`.
+#[derive(Debug, Clone)]
+pub struct HtmlWriteOptions {
+    /// Write a directory with one page per chapter instead of a single file.
+    pub split_chapters: bool,
+    /// Include the table-of-contents sidebar.
+    pub sidebar: bool,
+}
+
+impl Default for HtmlWriteOptions {
+    fn default() -> Self {
+        Self {
+            split_chapters: false,
+            sidebar: true,
+        }
+    }
+}
+
+pub struct HtmlWriter;
+
+const STYLESHEET_NAME: &str = "style.css";
+const INDEX_NAME: &str = "index.html";
+
+const DEFAULT_CSS: &str = r#"body { margin: 0; font-family: Georgia, serif; line-height: 1.6; color: #222; }
+.sidebar { position: fixed; inset-block: 0; inset-inline-start: 0; width: 16rem; overflow-y: auto; padding: 1rem; box-sizing: border-box; background: #f5f5f0; border-inline-end: 1px solid #ddd; font-family: sans-serif; font-size: 0.9rem; }
+.sidebar ol { padding-inline-start: 1.2rem; }
+.sidebar a { color: #333; text-decoration: none; }
+.sidebar a:hover { text-decoration: underline; }
+main { max-width: 42rem; padding: 2rem; margin-inline: auto; }
+.sidebar + main { margin-inline-start: 18rem; }
+section.chapter + section.chapter { border-top: 1px solid #ddd; margin-top: 3rem; }
+img { max-width: 100%; height: auto; }
+figure { margin: 1.5rem 0; text-align: center; }
+pre { overflow-x: auto; background: #f4f4f4; padding: 0.75rem; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #ccc; padding: 0.25rem 0.5rem; }
+.pager { display: flex; justify-content: space-between; margin-top: 3rem; font-family: sans-serif; }
+@media (max-width: 50rem) { .sidebar { position: static; width: auto; border: none; } .sidebar + main { margin-inline-start: auto; } }
+"#;
+
+impl FormatWriter for HtmlWriter {
+    fn write<W: Write>(
+        doc: &Document,
+        output: W,
+        opts: &WriteOptions,
+        progress: Option<&dyn ProgressHandler>,
+    ) -> Result<(), WriteError> {
+        let mut w = output;
+        let layout = Layout::single(doc);
+        let total = doc.content.len() as u64;
+
+        write_page_start(&mut w, doc, doc.metadata.title.as_deref(), None, None)?;
+        if opts.html.sidebar {
+            write_sidebar(&mut w, doc, &layout)?;
+        }
+        writeln!(w, "<main>")?;
+        for (i, chapter) in doc.content.iter().enumerate() {
+            write!(
+                w,
+                "<section class=\"chapter\" id=\"{}\"",
+                escape_xml(&layout.anchors[i])
+            )?;
+            write_dir_attr(&mut w, chapter.text_direction)?;
+            writeln!(w, ">")?;
+            write_nodes(&mut w, &chapter.content, &layout)?;
+            writeln!(w, "</section>")?;
+            emit_progress(
+                progress,
+                "Writing HTML",
+                i as u64 + 1,
+                Some(total),
+                Some(&format!("Chapter {}/{}", i + 1, total)),
+            );
+        }
+        writeln!(w, "</main>")?;
+        write_page_end(&mut w)?;
+        Ok(())
+    }
+}
+
+impl HtmlWriter {
+    /// Write `doc` as a browsable site into `dir` (created if missing):
+    /// `index.html`, one `chapterN.html` per chapter, `style.css` and `resources/`.
+    pub fn write_dir(
+        doc: &Document,
+        dir: &Path,
+        opts: &WriteOptions,
+        progress: Option<&dyn ProgressHandler>,
+    ) -> Result<(), WriteError> {
+        fs::create_dir_all(dir)?;
+        let layout = Layout::split(doc);
+        let total = doc.content.len() as u64 + 1;
+
+        fs::write(dir.join(STYLESHEET_NAME), DEFAULT_CSS)?;
+        for (id, path) in &layout.resource_paths {
+            if let Some(res) = doc.resources.get(id) {
+                let target = dir.join(path);
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::write(target, &res.data)?;
+            }
+        }
+
+        // Index page: title block, cover and the full table of contents.
+        let mut w = std::io::BufWriter::new(fs::File::create(dir.join(INDEX_NAME))?);
+        write_page_start(
+            &mut w,
+            doc,
+            doc.metadata.title.as_deref(),
+            Some(STYLESHEET_NAME),
+            None,
+        )?;
+        if opts.html.sidebar {
+            write_sidebar(&mut w, doc, &layout)?;
+        }
+        writeln!(w, "<main>")?;
+        write_title_block(&mut w, doc, &layout)?;
+        writeln!(w, "<nav aria-label=\"Contents\">")?;
+        write_toc_list(&mut w, &toc_entries(doc), &layout)?;
+        writeln!(w, "</nav>")?;
+        writeln!(w, "</main>")?;
+        write_page_end(&mut w)?;
+        w.flush()?;
+        emit_progress(progress, "Writing HTML", 1, Some(total), Some("Index"));
+
+        for (i, chapter) in doc.content.iter().enumerate() {
+            let mut w = std::io::BufWriter::new(fs::File::create(dir.join(&layout.files[i]))?);
+            write_page_start(
+                &mut w,
+                doc,
+                chapter.title.as_deref().or(doc.metadata.title.as_deref()),
+                Some(STYLESHEET_NAME),
+                chapter.text_direction,
+            )?;
+            if opts.html.sidebar {
+                write_sidebar(&mut w, doc, &layout)?;
+            }
+            writeln!(w, "<main>")?;
+            write_nodes(&mut w, &chapter.content, &layout)?;
+            write_pager(&mut w, &layout, i)?;
+            writeln!(w, "</main>")?;
+            write_page_end(&mut w)?;
+            w.flush()?;
+            emit_progress(
+                progress,
+                "Writing HTML",
+                i as u64 + 2,
+                Some(total),
+                Some(&format!("Chapter {}/{}", i + 1, doc.content.len())),
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Where chapters and images end up in the output, and how to link to them.
+struct Layout<'a> {
+    doc: &'a Document,
+    split: bool,
+    /// Per-chapter page files (split mode).
+    files: Vec<String>,
+    /// Per-chapter `<section>` ids (single-file mode).
+    anchors: Vec<String>,
+    /// Resource id → path relative to the output directory (split mode only).
+    resource_paths: HashMap<String, String>,
+    /// Note id → index of the chapter containing it.
+    note_chapters: HashMap<&'a str, usize>,
+    /// Targets of internal links.
+    links: LinkIndex<'a>,
+}
+
+impl<'a> Layout<'a> {
+    fn single(doc: &'a Document) -> Self {
+        let mut seen = HashSet::new();
+        let anchors = doc
+            .content
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                let base = if c.id.is_empty() {
+                    format!("chapter{}", i + 1)
+                } else {
+                    c.id.replace(|ch: char| ch.is_whitespace(), "-")
+                };
+                let mut anchor = base.clone();
+                let mut n = 2;
+                while !seen.insert(anchor.clone()) {
+                    anchor = format!("{}-{}", base, n);
+                    n += 1;
+                }
+                anchor
+            })
+            .collect();
+        Self {
+            doc,
+            split: false,
+            files: Vec::new(),
+            anchors,
+            resource_paths: HashMap::new(),
+            note_chapters: note_chapters(doc),
+            links: LinkIndex::new(doc),
+        }
+    }
+
+    fn split(doc: &'a Document) -> Self {
+        let files = (1..=doc.content.len())
+            .map(|i| format!("chapter{}.html", i))
+            .collect();
+
+        let mut used = HashSet::new();
+        let mut resource_paths = HashMap::new();
+        let mut ids: Vec<&String> = doc.resources.iter().map(|(id, _)| id).collect();
+        ids.sort();
+        for id in ids {
+            let Some(res) = doc.resources.get(id) else {
+                continue;
+            };
+            let name = res
+                .filename
+                .as_deref()
+                .and_then(|f| Path::new(f).file_name())
+                .and_then(|n| n.to_str())
+                .filter(|n| safe_file_name(n))
+                .map(|n| n.to_string())
+                .unwrap_or_else(|| sanitize_file_name(id));
+            let mut candidate = name.clone();
+            let mut n = 2;
+            while !used.insert(candidate.clone()) {
+                candidate = format!("{}-{}", n, name);
+                n += 1;
+            }
+            resource_paths.insert(id.clone(), format!("resources/{}", candidate));
+        }
+
+        Self {
+            doc,
+            split: true,
+            files,
+            anchors: Vec::new(),
+            resource_paths,
+            note_chapters: note_chapters(doc),
+            links: LinkIndex::new(doc),
+        }
+    }
+
+    fn index_href(&self) -> String {
+        if self.split {
+            INDEX_NAME.to_string()
+        } else {
+            "#top".to_string()
+        }
+    }
+
+    fn chapter_href(&self, index: usize) -> String {
+        if self.split {
+            self.files[index].clone()
+        } else {
+            format!("#{}", self.anchors[index])
+        }
+    }
+
+    /// Map a TOC or in-book link to its place in the output, dropping
+    /// anchors that no longer exist. External links and links that match no
+    /// chapter are returned unchanged.
+    fn resolve_href(&self, href: &str) -> String {
+        match self.links.resolve(href) {
+            LinkTarget::Internal {
+                chapter,
+                anchor: Some(anchor),
+            } if self.split => format!("{}#{}", self.files[chapter], anchor),
+            LinkTarget::Internal {
+                anchor: Some(anchor),
+                ..
+            } => format!("#{}", anchor),
+            LinkTarget::Internal { chapter, .. } | LinkTarget::MissingAnchor { chapter, .. } => {
+                self.chapter_href(chapter)
+            }
+            LinkTarget::External | LinkTarget::Dangling => href.to_string(),
+        }
+    }
+
+    /// Link to a note: a fragment in single-file mode, `chapterN.html#id`
+    /// in split mode since the note may live in another page.
+    fn note_href(&self, id: &str) -> String {
+        match self.note_chapters.get(id) {
+            Some(&i) if self.split => format!("{}#{}", self.files[i], id),
+            _ => format!("#{}", id),
+        }
+    }
+
+    /// The `src` for an image: a `data:` URI in single-file mode, a relative
+    /// path in split mode, or the original reference if the resource is missing.
+    fn image_src(&self, reference: &str) -> String {
+        let Some(res) = find_resource(self.doc, reference) else {
+            return reference.to_string();
+        };
+        if self.split {
+            self.resource_paths
+                .get(&res.id)
+                .cloned()
+                .unwrap_or_else(|| reference.to_string())
+        } else {
+            format!(
+                "data:{};base64,{}",
+                res.media_type,
+                base64::engine::general_purpose::STANDARD.encode(&res.data)
+            )
+        }
+    }
+}
+
+fn safe_file_name(name: &str) -> bool {
+    !name.is_empty()
+        && Path::new(name)
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+}
+
+fn sanitize_file_name(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let cleaned = cleaned.trim_start_matches('.');
+    if cleaned.is_empty() {
+        "resource".to_string()
+    } else {
+        cleaned.to_string()
+    }
+}
+
+// --- Page chrome ---
+
+fn write_page_start<W: Write>(
+    w: &mut W,
+    doc: &Document,
+    title: Option<&str>,
+    stylesheet: Option<&str>,
+    chapter_direction: Option<TextDirection>,
+) -> Result<(), WriteError> {
+    write!(w, "<!DOCTYPE html>\n<html")?;
+    if let Some(ref lang) = doc.metadata.language {
+        write!(w, " lang=\"{}\"", escape_xml(lang))?;
+    }
+    write_dir_attr(w, chapter_direction.or(Some(doc.text_direction)))?;
+    writeln!(w, ">")?;
+    writeln!(w, "<head>")?;
+    writeln!(w, "<meta charset=\"utf-8\">")?;
+    writeln!(
+        w,
+        "<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">"
+    )?;
+    writeln!(
+        w,
+        "<title>{}</title>",
+        escape_xml(title.unwrap_or("Untitled"))
+    )?;
+    for author in &doc.metadata.authors {
+        writeln!(
+            w,
+            "<meta name=\"author\" content=\"{}\">",
+            escape_xml(author)
+        )?;
+    }
+    if let Some(ref d) = doc.metadata.description {
+        writeln!(
+            w,
+            "<meta name=\"description\" content=\"{}\">",
+            escape_xml(d)
+        )?;
+    }
+    match stylesheet {
+        Some(href) => writeln!(w, "<link rel=\"stylesheet\" href=\"{}\">", href)?,
+        None => writeln!(w, "<style>\n{}</style>", DEFAULT_CSS)?,
+    }
+    writeln!(w, "</head>")?;
+    writeln!(w, "<body id=\"top\">")?;
+    Ok(())
+}
+
+fn write_page_end<W: Write>(w: &mut W) -> Result<(), WriteError> {
+    writeln!(w, "</body>")?;
+    writeln!(w, "</html>")?;
+    Ok(())
+}
+
+/// `dir` attribute for a direction; left-to-right is the HTML default and omitted.
+fn write_dir_attr<W: Write>(w: &mut W, dir: Option<TextDirection>) -> Result<(), WriteError> {
+    match dir {
+        Some(TextDirection::Rtl) => write!(w, " dir=\"rtl\"")?,
+        Some(TextDirection::Auto) => write!(w, " dir=\"auto\"")?,
+        _ => {}
+    }
+    Ok(())
+}
+
+fn write_title_block<W: Write>(
+    w: &mut W,
+    doc: &Document,
+    layout: &Layout,
+) -> Result<(), WriteError> {
+    writeln!(w, "<header>")?;
+    if let Some(cover) = doc.metadata.cover_image_id.as_deref() {
+        writeln!(
+            w,
+            "<img class=\"cover\" src=\"{}\" alt=\"Cover\">",
+            escape_xml(&layout.image_src(cover))
+        )?;
+    }
+    writeln!(
+        w,
+        "<h1>{}</h1>",
+        escape_xml(doc.metadata.title.as_deref().unwrap_or("Untitled"))
+    )?;
+    if let Some(ref sub) = doc.metadata.subtitle {
+        writeln!(w, "<p class=\"subtitle\">{}</p>", escape_xml(sub))?;
+    }
+    if !doc.metadata.authors.is_empty() {
+        writeln!(
+            w,
+            "<p class=\"authors\">{}</p>",
+            escape_xml(&doc.metadata.authors.join(", "))
+        )?;
+    }
+    if let Some(ref d) = doc.metadata.description {
+        writeln!(w, "<p class=\"description\">{}</p>", escape_xml(d))?;
+    }
+    writeln!(w, "</header>")?;
+    Ok(())
+}
+
+/// The document TOC, or one entry per titled chapter when the TOC is empty.
+fn toc_entries(doc: &Document) -> Vec<TocEntry> {
+    if !doc.toc.is_empty() {
+        return doc.toc.clone();
+    }
+    doc.content
+        .iter()
+        .enumerate()
+        .map(|(i, c)| TocEntry {
+            title: c
+                .title
+                .clone()
+                .unwrap_or_else(|| format!("Chapter {}", i + 1)),
+            href: c.id.clone(),
+            children: Vec::new(),
+        })
+        .collect()
+}
+
+fn write_sidebar<W: Write>(w: &mut W, doc: &Document, layout: &Layout) -> Result<(), WriteError> {
+    writeln!(
+        w,
+        "<nav class=\"sidebar\" aria-label=\"Table of contents\">"
+    )?;
+    writeln!(
+        w,
+        "<p class=\"book-title\"><a href=\"{}\">{}</a></p>",
+        escape_xml(&layout.index_href()),
+        escape_xml(doc.metadata.title.as_deref().unwrap_or("Contents"))
+    )?;
+    write_toc_list(w, &toc_entries(doc), layout)?;
+    writeln!(w, "</nav>")?;
+    Ok(())
+}
+
+fn write_toc_list<W: Write>(
+    w: &mut W,
+    entries: &[TocEntry],
+    layout: &Layout,
+) -> Result<(), WriteError> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+    writeln!(w, "<ol>")?;
+    for entry in entries {
+        write!(
+            w,
+            "<li><a href=\"{}\">{}</a>",
+            escape_xml(&layout.resolve_href(&entry.href)),
+            escape_xml(&entry.title)
+        )?;
+        if !entry.children.is_empty() {
+            writeln!(w)?;
+            write_toc_list(w, &entry.children, layout)?;
+        }
+        writeln!(w, "</li>")?;
+    }
+    writeln!(w, "</ol>")?;
+    Ok(())
+}
+
+/// Previous / next chapter links at the foot of a split-mode page.
+fn write_pager<W: Write>(w: &mut W, layout: &Layout, index: usize) -> Result<(), WriteError> {
+    writeln!(w, "<nav class=\"pager\">")?;
+    if index > 0 {
+        writeln!(
+            w,
+            "<a rel=\"prev\" href=\"{}\">&larr; Previous</a>",
+            layout.chapter_href(index - 1)
+        )?;
+    } else {
+        writeln!(w, "<a href=\"{}\">&larr; Contents</a>", INDEX_NAME)?;
+    }
+    if index + 1 < layout.files.len() {
+        writeln!(
+            w,
+            "<a rel=\"next\" href=\"{}\">Next &rarr;</a>",
+            layout.chapter_href(index + 1)
+        )?;
+    }
+    writeln!(w, "</nav>")?;
+    Ok(())
+}
+
+fn note_chapters(doc: &Document) -> HashMap<&str, usize> {
+    collect_notes(doc)
+        .iter()
+        .map(|n| (n.id, n.chapter))
+        .collect()
+}
+
+// --- Content ---
+
+fn write_nodes<W: Write>(
+    w: &mut W,
+    nodes: &[ContentNode],
+    layout: &Layout,
+) -> Result<(), WriteError> {
+    for node in nodes {
+        write_node(w, node, layout)?;
+    }
+    Ok(())
+}
+
+fn write_node<W: Write>(w: &mut W, node: &ContentNode, layout: &Layout) -> Result<(), WriteError> {
+    match node {
+        ContentNode::Paragraph { children } => {
+            write!(w, "<p>")?;
+            write_inlines(w, children, layout)?;
+            writeln!(w, "</p>")?;
+        }
+        ContentNode::Heading { level, children } => {
+            let level = (*level).clamp(1, 6);
+            write!(w, "<h{}>", level)?;
+            write_inlines(w, children, layout)?;
+            writeln!(w, "</h{}>", level)?;
+        }
+        ContentNode::List { ordered, items } => {
+            let tag = if *ordered { "ol" } else { "ul" };
+            writeln!(w, "<{}>", tag)?;
+            for item in items {
+                write!(w, "<li>")?;
+                // A lone paragraph renders inline to keep tight lists tight.
+                match item.as_slice() {
+                    [ContentNode::Paragraph { children }] => write_inlines(w, children, layout)?,
+                    _ => write_nodes(w, item, layout)?,
+                }
+                writeln!(w, "</li>")?;
+            }
+            writeln!(w, "</{}>", tag)?;
+        }
+        ContentNode::Table { headers, rows } => {
+            writeln!(w, "<table>")?;
+            if !headers.is_empty() {
+                write!(w, "<thead><tr>")?;
+                for cell in headers {
+                    write!(w, "<th>")?;
+                    write_inlines(w, cell, layout)?;
+                    write!(w, "</th>")?;
+                }
+                writeln!(w, "</tr></thead>")?;
+            }
+            writeln!(w, "<tbody>")?;
+            for row in rows {
+                write!(w, "<tr>")?;
+                for cell in row {
+                    write!(w, "<td>")?;
+                    write_inlines(w, cell, layout)?;
+                    write!(w, "</td>")?;
+                }
+                writeln!(w, "</tr>")?;
+            }
+            writeln!(w, "</tbody></table>")?;
+        }
+        ContentNode::BlockQuote { children } => {
+            writeln!(w, "<blockquote>")?;
+            write_nodes(w, children, layout)?;
+            writeln!(w, "</blockquote>")?;
+        }
+        ContentNode::CodeBlock { language, code } => {
+            match language {
+                Some(lang) => write!(w, "<pre><code class=\"language-{}\">", escape_xml(lang))?,
+                None => write!(w, "<pre><code>")?,
+            }
+            writeln!(w, "{}</code></pre>", escape_xml(code))?;
+        }
+        ContentNode::Image {
+            resource_id,
+            alt_text,
+            caption,
+        } => {
+            let img = format!(
+                "<img src=\"{}\" alt=\"{}\">",
+                escape_xml(&layout.image_src(resource_id)),
+                escape_xml(alt_text.as_deref().unwrap_or(""))
+            );
+            match caption {
+                Some(c) => writeln!(
+                    w,
+                    "<figure>{}<figcaption>{}</figcaption></figure>",
+                    img,
+                    escape_xml(c)
+                )?,
+                None => writeln!(w, "{}", img)?,
+            }
+        }
+        ContentNode::HorizontalRule => writeln!(w, "<hr>")?,
+        ContentNode::RawHtml(s) => writeln!(w, "{}", s)?,
+        ContentNode::Note { id, kind, children } => {
+            let role = match kind {
+                NoteKind::Footnote => "doc-footnote",
+                NoteKind::Endnote => "doc-endnote",
+            };
+            writeln!(w, "<aside role=\"{}\" id=\"{}\">", role, escape_xml(id))?;
+            write_nodes(w, children, layout)?;
+            writeln!(w, "</aside>")?;
+        }
+        // The block's id, class, role and language go on a wrapper, so links
+        // into the block still land and its language is kept.
+        ContentNode::Styled { attrs, node } if !attrs.is_empty() => {
+            writeln!(w, "<div{}>", html_attributes(attrs))?;
+            write_node(w, node, layout)?;
+            writeln!(w, "</div>")?;
+        }
+        ContentNode::Styled { node, .. } => write_node(w, node, layout)?,
+        ContentNode::Container { attrs, children } => {
+            writeln!(w, "<div{}>", html_attributes(attrs))?;
+            write_nodes(w, children, layout)?;
+            writeln!(w, "</div>")?;
+        }
+    }
+    Ok(())
+}
+
+fn write_inlines<W: Write>(
+    w: &mut W,
+    nodes: &[InlineNode],
+    layout: &Layout,
+) -> Result<(), WriteError> {
+    for node in nodes {
+        write_inline(w, node, layout)?;
+    }
+    Ok(())
+}
+
+fn write_inline<W: Write>(w: &mut W, node: &InlineNode, layout: &Layout) -> Result<(), WriteError> {
+    match node {
+        InlineNode::Text(s) => write!(w, "{}", escape_xml(s))?,
+        InlineNode::Emphasis(children) => {
+            write!(w, "<em>")?;
+            write_inlines(w, children, layout)?;
+            write!(w, "</em>")?;
+        }
+        InlineNode::Strong(children) => {
+            write!(w, "<strong>")?;
+            write_inlines(w, children, layout)?;
+            write!(w, "</strong>")?;
+        }
+        InlineNode::Code(s) => write!(w, "<code>{}</code>", escape_xml(s))?,
+        InlineNode::Link { href, children } => {
+            write!(
+                w,
+                "<a href=\"{}\">",
+                escape_xml(&layout.resolve_href(href))
+            )?;
+            write_inlines(w, children, layout)?;
+            write!(w, "</a>")?;
+        }
+        InlineNode::Superscript(children) => {
+            write!(w, "<sup>")?;
+            write_inlines(w, children, layout)?;
+            write!(w, "</sup>")?;
+        }
+        InlineNode::Subscript(children) => {
+            write!(w, "<sub>")?;
+            write_inlines(w, children, layout)?;
+            write!(w, "</sub>")?;
+        }
+        InlineNode::Ruby { base, annotation } => write!(
+            w,
+            "<ruby>{}<rp>(</rp><rt>{}</rt><rp>)</rp></ruby>",
+            escape_xml(base),
+            escape_xml(annotation)
+        )?,
+        InlineNode::LineBreak => write!(w, "<br>")?,
+        InlineNode::NoteRef { id, label } => write!(
+            w,
+            "<sup><a href=\"{}\" role=\"doc-noteref\">{}</a></sup>",
+            escape_xml(&layout.note_href(id)),
+            escape_xml(label)
+        )?,
+        InlineNode::Span { attrs, children } => {
+            write!(w, "<span{}>", html_attributes(attrs))?;
+            write_inlines(w, children, layout)?;
+            write!(w, "</span>")?;
+        }
+    }
+    Ok(())
+}
+
+/// ` id="…" class="…" role="…" lang="…"` for the attributes that are set.
+fn html_attributes(attrs: &Attributes) -> String {
+    let mut out = String::new();
+    for (name, value) in [
+        ("id", &attrs.id),
+        ("class", &attrs.class),
+        ("role", &attrs.role),
+        ("lang", &attrs.lang),
+    ] {
+        if let Some(value) = value {
+            out.push_str(&format!(" {}=\"{}\"", name, escape_xml(value)));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_doc() -> Document {
+        let mut resources = ResourceMap::new();
+        resources.insert(
+            "pic".into(),
+            Resource {
+                id: "pic".into(),
+                media_type: "image/png".into(),
+                data: vec![1, 2, 3],
+                filename: Some("images/pic.png".into()),
+                obfuscation: None,
+            },
+        );
+        Document {
+            metadata: Metadata {
+                title: Some("Book".into()),
+                language: Some("ja".into()),
+                ..Default::default()
+            },
+            toc: vec![TocEntry {
+                title: "One".into(),
+                href: "c1".into(),
+                children: Vec::new(),
+            }],
+            content: vec![
+                Chapter {
+                    id: "c1".into(),
+                    title: Some("One".into()),
+                    content: vec![
+                        ContentNode::Paragraph {
+                            children: vec![InlineNode::Ruby {
+                                base: "漢字".into(),
+                                annotation: "かんじ".into(),
+                            }],
+                        },
+                        ContentNode::Image {
+                            resource_id: "../images/pic.png".into(),
+                            alt_text: Some("A picture".into()),
+                            caption: None,
+                        },
+                    ],
+                    text_direction: None,
+                },
+                Chapter {
+                    id: "c2".into(),
+                    title: Some("Two".into()),
+                    content: vec![ContentNode::Paragraph {
+                        children: vec![InlineNode::Link {
+                            href: "c1.xhtml".into(),
+                            children: vec![InlineNode::Text("back".into())],
+                        }],
+                    }],
+                    text_direction: None,
+                },
+            ],
+            resources,
+            text_direction: TextDirection::Rtl,
+            epub_version: None,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_single_file_inlines_images() {
+        let mut out = Vec::new();
+        HtmlWriter::write(&sample_doc(), &mut out, &WriteOptions::default(), None).unwrap();
+        let html = String::from_utf8(out).unwrap();
+        assert!(html.contains("<html lang=\"ja\" dir=\"rtl\">"));
+        assert!(html.contains("src=\"data:image/png;base64,AQID\""));
+        assert!(html.contains("<ruby>漢字<rp>(</rp><rt>かんじ</rt><rp>)</rp></ruby>"));
+        assert!(html.contains("<nav class=\"sidebar\""));
+        assert!(html.contains("<a href=\"#c1\">One</a>"));
+        assert!(html.contains("<a href=\"#c1\">back</a>"));
+    }
+
+    #[test]
+    fn test_split_writes_pages_and_resources() {
+        let dir = tempfile::tempdir().unwrap();
+        HtmlWriter::write_dir(&sample_doc(), dir.path(), &WriteOptions::default(), None).unwrap();
+        let index = fs::read_to_string(dir.path().join("index.html")).unwrap();
+        assert!(index.contains("<a href=\"chapter1.html\">One</a>"));
+        let ch1 = fs::read_to_string(dir.path().join("chapter1.html")).unwrap();
+        assert!(ch1.contains("src=\"resources/pic.png\""));
+        assert!(ch1.contains("rel=\"next\" href=\"chapter2.html\""));
+        assert_eq!(
+            fs::read(dir.path().join("resources/pic.png")).unwrap(),
+            vec![1, 2, 3]
+        );
+        assert!(dir.path().join("style.css").exists());
+    }
+
+    #[test]
+    fn test_styled_blocks_keep_attributes() {
+        let mut doc = sample_doc();
+        doc.content[1].content.push(ContentNode::Styled {
+            attrs: Attributes {
+                id: Some("greeting".into()),
+                class: Some("x".into()),
+                lang: Some("fr".into()),
+                ..Default::default()
+            },
+            node: Box::new(ContentNode::Paragraph {
+                children: vec![InlineNode::Text("Bonjour".into())],
+            }),
+        });
+        let mut out = Vec::new();
+        HtmlWriter::write(&doc, &mut out, &WriteOptions::default(), None).unwrap();
+        let html = String::from_utf8(out).unwrap();
+        assert!(html.contains("<div id=\"greeting\" class=\"x\" lang=\"fr\">\n<p>Bonjour</p>"));
+    }
+}
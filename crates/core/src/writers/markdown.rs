@@ -0,0 +1,780 @@
+//! Markdown writer: IR → GitHub-flavoured Markdown with YAML front matter.
+//!
+//! Output mirrors what `readers::markdown` accepts, so a Markdown → IR → Markdown
+//! round trip only normalises formatting (list markers, emphasis style, escapes).
+//! `MarkdownWriter::write_path` also writes image resources into an `images/`
+//! directory next to the `.md` file; the plain `FormatWriter` impl only emits
+//! the text, with image links pointing at where those files would be.
+//!
+//! All chapters go into one file, so links between chapters become fragments
+//! (`#anchor`), with an `<a id>` placed at each element or chapter they target.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use crate::document::*;
+use crate::error::WriteError;
+use crate::links::{visit_hrefs, LinkIndex, LinkTarget};
+use crate::progress::{emit_progress, ProgressHandler};
+use crate::writers::{find_resource, FormatWriter, WriteOptions};
+use crate::xml::escape_xml;
+
+pub struct MarkdownWriter;
+
+const IMAGES_DIR: &str = "images";
+
+impl FormatWriter for MarkdownWriter {
+    fn write<W: Write>(
+        doc: &Document,
+        output: W,
+        opts: &WriteOptions,
+        progress: Option<&dyn ProgressHandler>,
+    ) -> Result<(), WriteError> {
+        let _ = opts;
+        let mut w = output;
+        let ctx = MdContext::new(doc);
+        w.write_all(render_document(doc, &ctx, progress)?.as_bytes())?;
+        Ok(())
+    }
+}
+
+impl MarkdownWriter {
+    /// Write `doc` to `path` and its images to `images/` beside it.
+    pub fn write_path(
+        doc: &Document,
+        path: &Path,
+        opts: &WriteOptions,
+        progress: Option<&dyn ProgressHandler>,
+    ) -> Result<(), WriteError> {
+        let _ = opts;
+        let ctx = MdContext::new(doc);
+        let text = render_document(doc, &ctx, progress)?;
+
+        let base = path.parent().unwrap_or(Path::new("."));
+        if !ctx.image_paths.is_empty() {
+            fs::create_dir_all(base.join(IMAGES_DIR))?;
+        }
+        for (id, rel) in &ctx.image_paths {
+            if let Some(res) = doc.resources.get(id) {
+                fs::write(base.join(rel), &res.data)?;
+            }
+        }
+        fs::write(path, text)?;
+        Ok(())
+    }
+}
+
+struct MdContext<'a> {
+    doc: &'a Document,
+    /// Image resource id → path relative to the `.md` file.
+    image_paths: HashMap<String, String>,
+    links: LinkIndex<'a>,
+    /// Element ids that internal links point at, which get an `<a id>`.
+    linked_anchors: HashSet<String>,
+    /// Chapters linked to as a whole, which get an `<a id>` at their start.
+    linked_chapters: HashSet<usize>,
+}
+
+impl<'a> MdContext<'a> {
+    fn new(doc: &'a Document) -> Self {
+        let mut ids: Vec<&String> = doc
+            .resources
+            .iter()
+            .filter(|(_, r)| r.media_type.starts_with("image/"))
+            .map(|(id, _)| id)
+            .collect();
+        ids.sort();
+
+        let mut used = HashSet::new();
+        let mut image_paths = HashMap::new();
+        for id in ids {
+            let Some(res) = doc.resources.get(id) else {
+                continue;
+            };
+            let name = res
+                .filename
+                .as_deref()
+                .and_then(|f| f.rsplit('/').next())
+                .unwrap_or(id);
+            let name = sanitize_file_name(name);
+            let mut candidate = name.clone();
+            let mut n = 2;
+            while !used.insert(candidate.clone()) {
+                candidate = format!("{}-{}", n, name);
+                n += 1;
+            }
+            image_paths.insert(id.clone(), format!("{}/{}", IMAGES_DIR, candidate));
+        }
+
+        let links = LinkIndex::new(doc);
+        let mut linked_anchors = HashSet::new();
+        let mut linked_chapters = HashSet::new();
+        for chapter in &doc.content {
+            visit_hrefs(&chapter.content, &mut |href| match links.resolve(href) {
+                LinkTarget::Internal {
+                    anchor: Some(anchor),
+                    ..
+                } => {
+                    linked_anchors.insert(anchor.to_string());
+                }
+                LinkTarget::Internal { chapter, .. }
+                | LinkTarget::MissingAnchor { chapter, .. } => {
+                    linked_chapters.insert(chapter);
+                }
+                LinkTarget::External | LinkTarget::Dangling => {}
+            });
+        }
+
+        Self {
+            doc,
+            image_paths,
+            links,
+            linked_anchors,
+            linked_chapters,
+        }
+    }
+
+    /// Internal links as fragments within the one output file; anything else
+    /// is kept as is.
+    fn link_href(&self, href: &str) -> String {
+        match self.links.resolve(href) {
+            LinkTarget::Internal {
+                anchor: Some(anchor),
+                ..
+            } => format!("#{}", anchor),
+            LinkTarget::Internal { chapter, .. } | LinkTarget::MissingAnchor { chapter, .. } => {
+                format!("#{}", self.doc.content[chapter].id)
+            }
+            LinkTarget::External | LinkTarget::Dangling => href.to_string(),
+        }
+    }
+
+    /// `<a id>` for an element id that a link points at.
+    fn anchor(&self, id: Option<&str>) -> Option<String> {
+        id.filter(|id| self.linked_anchors.contains(*id))
+            .map(|id| format!("<a id=\"{}\"></a>", escape_xml(id)))
+    }
+
+    fn image_path(&self, reference: &str) -> String {
+        find_resource(self.doc, reference)
+            .and_then(|r| self.image_paths.get(&r.id))
+            .cloned()
+            .unwrap_or_else(|| reference.to_string())
+    }
+}
+
+fn sanitize_file_name(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let cleaned = cleaned.trim_start_matches('.');
+    if cleaned.is_empty() {
+        "image".to_string()
+    } else {
+        cleaned.to_string()
+    }
+}
+
+// --- Document ---
+
+fn render_document(
+    doc: &Document,
+    ctx: &MdContext,
+    progress: Option<&dyn ProgressHandler>,
+) -> Result<String, WriteError> {
+    let mut blocks = Vec::new();
+    if let Some(front_matter) = front_matter(doc, ctx)? {
+        blocks.push(front_matter);
+    }
+
+    let total = doc.content.len() as u64;
+    for (i, chapter) in doc.content.iter().enumerate() {
+        let nodes = &chapter.content;
+        if ctx.linked_chapters.contains(&i) {
+            blocks.push(format!("<a id=\"{}\"></a>", escape_xml(&chapter.id)));
+        }
+
+        let starts_with_title = matches!(
+            nodes.first().map(ContentNode::unstyled),
+            Some(ContentNode::Heading { level: 1, .. })
+        );
+        if !starts_with_title {
+            if let Some(ref title) = chapter.title {
+                blocks.push(format!("# {}", escape_text(title)));
+            }
+        }
+        for node in nodes {
+            blocks.push(render_block(node, ctx));
+        }
+
+        emit_progress(
+            progress,
+            "Writing Markdown",
+            i as u64 + 1,
+            Some(total),
+            Some(&format!("Chapter {}/{}", i + 1, total)),
+        );
+    }
+
+    let mut out = blocks
+        .into_iter()
+        .filter(|b| !b.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    out.push('\n');
+    Ok(out)
+}
+
+fn front_matter(doc: &Document, ctx: &MdContext) -> Result<Option<String>, WriteError> {
+    use serde_yaml::{Mapping, Value};
+
+    let m = &doc.metadata;
+    let mut map = Mapping::new();
+    let mut put = |key: &str, value: Value| {
+        map.insert(Value::String(key.to_string()), value);
+    };
+    let string = |s: &str| Value::String(s.to_string());
+    let list = |items: &[String]| Value::Sequence(items.iter().map(|s| string(s)).collect());
+
+    if let Some(ref v) = m.title {
+        put("title", string(v));
+    }
+    if let Some(ref v) = m.subtitle {
+        put("subtitle", string(v));
+    }
+    if !m.authors.is_empty() {
+        put("authors", list(&m.authors));
+    }
+    if let Some(ref v) = m.language {
+        put("language", string(v));
+    }
+    if let Some(ref v) = m.publisher {
+        put("publisher", string(v));
+    }
+    if let Some(ref v) = m.publish_date {
+        put("date", string(v));
+    }
+    if let Some(ref v) = m.description {
+        put("description", string(v));
+    }
+    if !m.subjects.is_empty() {
+        put("subjects", list(&m.subjects));
+    }
+    if let Some(ref series) = m.series {
+        let mut s = Mapping::new();
+        s.insert(string("name"), string(&series.name));
+        if let Some(pos) = series.position {
+            let value = if pos.fract() == 0.0 {
+                Value::from(pos as i64)
+            } else {
+                Value::from(pos as f64)
+            };
+            s.insert(string("position"), value);
+        }
+        put("series", Value::Mapping(s));
+    }
+    if let Some(v) = m.isbn_13.as_ref().or(m.isbn_10.as_ref()) {
+        put("isbn", string(v));
+    }
+    if let Some(ref v) = m.rights {
+        put("rights", string(v));
+    }
+    if let Some(ref cover) = m.cover_image_id {
+        put("cover", string(&ctx.image_path(cover)));
+    }
+    match doc.text_direction {
+        TextDirection::Rtl => put("dir", string("rtl")),
+        TextDirection::Auto => put("dir", string("auto")),
+        TextDirection::Ltr => {}
+    }
+    let mut custom: Vec<_> = m.custom.iter().collect();
+    custom.sort();
+    for (k, v) in custom {
+        put(k, string(v));
+    }
+
+    if map.is_empty() {
+        return Ok(None);
+    }
+    let yaml = serde_yaml::to_string(&map).map_err(|e| WriteError::WriteFailed {
+        format: "Markdown".into(),
+        detail: format!("front matter: {}", e),
+    })?;
+    Ok(Some(format!("---\n{}---", yaml)))
+}
+
+// --- Blocks ---
+
+fn render_blocks(nodes: &[ContentNode], ctx: &MdContext, tight: bool) -> String {
+    let sep = if tight { "\n" } else { "\n\n" };
+    nodes
+        .iter()
+        .map(|n| render_block(n, ctx))
+        .filter(|b| !b.is_empty())
+        .collect::<Vec<_>>()
+        .join(sep)
+}
+
+fn render_block(node: &ContentNode, ctx: &MdContext) -> String {
+    match node {
+        ContentNode::Paragraph { children } => escape_line_start(&render_inlines(children, ctx)),
+        ContentNode::Heading { level, children } => format!(
+            "{} {}",
+            "#".repeat((*level).clamp(1, 6) as usize),
+            render_inlines(children, ctx)
+        ),
+        ContentNode::List { ordered, items } => render_list(*ordered, items, ctx),
+        ContentNode::Table { headers, rows } => render_table(headers, rows, ctx),
+        ContentNode::BlockQuote { children } => {
+            let inner = render_blocks(children, ctx, false);
+            inner
+                .lines()
+                .map(|l| {
+                    if l.is_empty() {
+                        ">".to_string()
+                    } else {
+                        format!("> {}", l)
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+        ContentNode::CodeBlock { language, code } => {
+            let longest = longest_run(code, '`');
+            let fence = "`".repeat(longest.max(2) + 1);
+            format!(
+                "{}{}\n{}\n{}",
+                fence,
+                language.as_deref().unwrap_or(""),
+                code.trim_end_matches('\n'),
+                fence
+            )
+        }
+        ContentNode::Image {
+            resource_id,
+            alt_text,
+            caption,
+        } => {
+            let mut s = format!(
+                "![{}]({}",
+                escape_text(alt_text.as_deref().unwrap_or("")),
+                link_destination(&ctx.image_path(resource_id))
+            );
+            if let Some(c) = caption {
+                s.push_str(&format!(" \"{}\"", c.replace('"', "\\\"")));
+            }
+            s.push(')');
+            s
+        }
+        ContentNode::HorizontalRule => "---".to_string(),
+        ContentNode::RawHtml(html) => html.trim().to_string(),
+        // Definitions may sit anywhere; keeping them in place keeps them near
+        // their reference.
+        ContentNode::Note { id, children, .. } => format!(
+            "[^{}]: {}",
+            note_label(id),
+            indent(&render_blocks(children, ctx, false), "    ", false)
+        ),
+        ContentNode::Styled { attrs, node } => {
+            with_anchor(ctx.anchor(attrs.id.as_deref()), render_block(node, ctx))
+        }
+        ContentNode::Container { attrs, children } => with_anchor(
+            ctx.anchor(attrs.id.as_deref()),
+            render_blocks(children, ctx, false),
+        ),
+    }
+}
+
+/// Put an element's `<a id>` in a block of its own before it.
+fn with_anchor(anchor: Option<String>, block: String) -> String {
+    match anchor {
+        Some(anchor) if block.is_empty() => anchor,
+        Some(anchor) => format!("{}\n\n{}", anchor, block),
+        None => block,
+    }
+}
+
+/// Note ids as footnote labels, which may not contain whitespace or `]`.
+/// The `fn-` prefix the Markdown reader gives its notes is dropped again.
+fn note_label(id: &str) -> String {
+    id.strip_prefix("fn-")
+        .unwrap_or(id)
+        .chars()
+        .map(|c| {
+            if c.is_whitespace() || c == ']' {
+                '-'
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+fn render_list(ordered: bool, items: &[Vec<ContentNode>], ctx: &MdContext) -> String {
+    // Tight when every item is a single paragraph, optionally followed by a sublist.
+    let tight = items.iter().all(|item| {
+        matches!(
+            item.as_slice(),
+            [] | [ContentNode::Paragraph { .. }]
+                | [ContentNode::Paragraph { .. }, ContentNode::List { .. }]
+        )
+    });
+    let rendered: Vec<String> = items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| {
+            let marker = if ordered {
+                format!("{}. ", i + 1)
+            } else {
+                "- ".to_string()
+            };
+            let mut body = render_blocks(item, ctx, tight);
+            // Keep GFM task-list markers live rather than escaped.
+            for task in ["[ ] ", "[x] ", "[X] "] {
+                let escaped = format!("\\[{}\\] ", &task[1..2]);
+                if let Some(rest) = body.strip_prefix(&escaped) {
+                    body = format!("{}{}", task, rest);
+                }
+            }
+            let pad = " ".repeat(marker.len());
+            format!("{}{}", marker, indent(&body, &pad, false))
+        })
+        .collect();
+    rendered.join(if tight { "\n" } else { "\n\n" })
+}
+
+fn render_table(
+    headers: &[Vec<InlineNode>],
+    rows: &[Vec<Vec<InlineNode>>],
+    ctx: &MdContext,
+) -> String {
+    let columns = rows
+        .iter()
+        .map(|r| r.len())
+        .chain(std::iter::once(headers.len()))
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let cell = |c: Option<&Vec<InlineNode>>| -> String {
+        c.map(|c| render_table_cell(c, ctx)).unwrap_or_default()
+    };
+    let row_line = |cells: Vec<String>| format!("| {} |", cells.join(" | "));
+
+    let mut lines = vec![row_line(
+        (0..columns).map(|i| cell(headers.get(i))).collect(),
+    )];
+    lines.push(row_line(vec!["---".to_string(); columns]));
+    for row in rows {
+        lines.push(row_line((0..columns).map(|i| cell(row.get(i))).collect()));
+    }
+    lines.join("\n")
+}
+
+fn render_table_cell(cell: &[InlineNode], ctx: &MdContext) -> String {
+    render_inlines(cell, ctx)
+        .replace('|', "\\|")
+        .replace("\\\n", "<br>")
+        .replace('\n', " ")
+}
+
+/// Indent every line after the first (or every line, with `first`) by `pad`.
+fn indent(text: &str, pad: &str, first: bool) -> String {
+    text.lines()
+        .enumerate()
+        .map(|(i, l)| {
+            if l.is_empty() || (i == 0 && !first) {
+                l.to_string()
+            } else {
+                format!("{}{}", pad, l)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn longest_run(s: &str, ch: char) -> usize {
+    let mut longest = 0;
+    let mut current = 0;
+    for c in s.chars() {
+        if c == ch {
+            current += 1;
+            longest = longest.max(current);
+        } else {
+            current = 0;
+        }
+    }
+    longest
+}
+
+// --- Inlines ---
+
+fn render_inlines(nodes: &[InlineNode], ctx: &MdContext) -> String {
+    let mut out = String::new();
+    for node in nodes {
+        render_inline(node, ctx, &mut out);
+    }
+    // A trailing hard break would render as a literal backslash.
+    while out.ends_with("\\\n") {
+        out.truncate(out.len() - 2);
+    }
+    out
+}
+
+fn render_inline(node: &InlineNode, ctx: &MdContext, out: &mut String) {
+    match node {
+        InlineNode::Text(s) => out.push_str(&escape_text(&s.replace('\n', " "))),
+        InlineNode::Emphasis(children) => wrap_delimited(out, "*", &render_inlines(children, ctx)),
+        InlineNode::Strong(children) => wrap_delimited(out, "**", &render_inlines(children, ctx)),
+        InlineNode::Code(code) => {
+            let ticks = "`".repeat(longest_run(code, '`') + 1);
+            let pad = if code.starts_with('`') || code.ends_with('`') {
+                " "
+            } else {
+                ""
+            };
+            out.push_str(&format!("{ticks}{pad}{code}{pad}{ticks}"));
+        }
+        InlineNode::Link { href, children } => out.push_str(&format!(
+            "[{}]({})",
+            render_inlines(children, ctx),
+            link_destination(&ctx.link_href(href))
+        )),
+        InlineNode::Superscript(children) => {
+            out.push_str(&format!("<sup>{}</sup>", render_inlines(children, ctx)))
+        }
+        InlineNode::Subscript(children) => {
+            out.push_str(&format!("<sub>{}</sub>", render_inlines(children, ctx)))
+        }
+        InlineNode::Ruby { base, annotation } => out.push_str(&format!(
+            "<ruby>{}<rt>{}</rt></ruby>",
+            escape_text(base),
+            escape_text(annotation)
+        )),
+        InlineNode::LineBreak => out.push_str("\\\n"),
+        InlineNode::NoteRef { id, .. } => out.push_str(&format!("[^{}]", note_label(id))),
+        InlineNode::Span { attrs, children } => {
+            if let Some(anchor) = ctx.anchor(attrs.id.as_deref()) {
+                out.push_str(&anchor);
+            }
+            for c in children {
+                render_inline(c, ctx, out);
+            }
+        }
+    }
+}
+
+/// Wrap in emphasis delimiters, keeping surrounding whitespace outside them
+/// (`** bold**` is not emphasis in CommonMark).
+fn wrap_delimited(out: &mut String, delim: &str, inner: &str) {
+    let trimmed = inner.trim();
+    if trimmed.is_empty() {
+        out.push_str(inner);
+        return;
+    }
+    let lead = &inner[..inner.len() - inner.trim_start().len()];
+    let trail = &inner[inner.trim_end().len()..];
+    out.push_str(&format!("{lead}{delim}{trimmed}{delim}{trail}"));
+}
+
+fn link_destination(href: &str) -> String {
+    if href.contains([' ', '(', ')', '<', '>']) {
+        format!("<{}>", href.replace('<', "%3C").replace('>', "%3E"))
+    } else {
+        href.to_string()
+    }
+}
+
+/// Backslash-escape characters that would otherwise start Markdown syntax.
+/// `_` is only escaped at word boundaries so `snake_case` stays readable.
+fn escape_text(s: &str) -> String {
+    let chars: Vec<char> = s.chars().collect();
+    let mut out = String::with_capacity(s.len());
+    for (i, &c) in chars.iter().enumerate() {
+        let escape = match c {
+            '\\' | '*' | '`' | '[' | ']' | '<' => true,
+            '_' => {
+                let before = i > 0 && chars[i - 1].is_alphanumeric();
+                let after = chars.get(i + 1).is_some_and(|n| n.is_alphanumeric());
+                !(before && after)
+            }
+            _ => false,
+        };
+        if escape {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Escape a paragraph whose first characters would read as a block marker.
+fn escape_line_start(s: &str) -> String {
+    let digits = s.chars().take_while(|c| c.is_ascii_digit()).count();
+    let rest = &s[digits..];
+    let is_marker = if digits > 0 {
+        rest.starts_with(". ") || rest.starts_with(") ")
+    } else {
+        s.starts_with('#')
+            || s.starts_with("> ")
+            || s.starts_with("- ")
+            || s.starts_with("+ ")
+            || s.starts_with("---")
+            || s.starts_with("===")
+    };
+    if !is_marker {
+        return s.to_string();
+    }
+    if digits > 0 {
+        format!("{}\\{}", &s[..digits], rest)
+    } else {
+        format!("\\{}", s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::readers::markdown::MarkdownReader;
+    use crate::readers::{FormatReader, ReadOptions};
+    use std::io::Cursor;
+
+    fn round_trip(md: &str) -> String {
+        let doc = MarkdownReader::read(Cursor::new(md.as_bytes()), &ReadOptions::default(), None)
+            .unwrap();
+        let mut out = Vec::new();
+        MarkdownWriter::write(&doc, &mut out, &WriteOptions::default(), None).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip_is_stable() {
+        let md = "---\ntitle: Book\nauthors:\n- Ann\nseries:\n  name: Saga\n  position: 2\n---\n\n\
+                  # One\n\nSome *emphasis*, **strong**, `code` and [a link](http://x.test).\n\n\
+                  - first\n- second\n  1. nested\n\n> quoted\n\n```rust\nfn main() {}\n```\n\n\
+                  | A | B |\n| --- | --- |\n| 1 | 2 |\n\nNote[^n] here.\n\n[^n]: The note.\n";
+        let once = round_trip(md);
+        let twice = round_trip(&once);
+        assert_eq!(once, twice);
+        assert!(once.starts_with("---\ntitle: Book\n"));
+        assert!(once.contains("- second\n  1. nested"));
+        assert!(once.contains("```rust\nfn main() {}\n```"));
+        assert!(once.contains("| 1 | 2 |"));
+        assert!(once.contains("Note[^n] here."));
+        assert!(once.contains("[^n]: The note."));
+    }
+
+    #[test]
+    fn test_escapes_literal_markup() {
+        let out = round_trip("A \\*star\\* and snake_case.\n\n\\# not a heading\n");
+        assert!(out.contains("A \\*star\\* and snake_case."));
+        assert!(out.contains("\\# not a heading"));
+    }
+
+    #[test]
+    fn test_cross_chapter_links_become_fragments() {
+        let link = |href: &str, text: &str| InlineNode::Link {
+            href: href.into(),
+            children: vec![InlineNode::Text(text.into())],
+        };
+        let paragraph = |children| ContentNode::Paragraph { children };
+        let doc = Document {
+            content: vec![
+                Chapter {
+                    id: "c1x".into(),
+                    title: Some("One".into()),
+                    content: vec![paragraph(vec![
+                        link("c2x.xhtml#s2", "two"),
+                        InlineNode::Text(", ".into()),
+                        link("c2x", "chapter"),
+                        InlineNode::Text(", ".into()),
+                        link("#s1", "here"),
+                        InlineNode::Text(" and ".into()),
+                        link("https://example.com/c2x", "away"),
+                    ])],
+                    text_direction: None,
+                },
+                Chapter {
+                    id: "c2x".into(),
+                    title: Some("Two".into()),
+                    content: vec![
+                        ContentNode::Styled {
+                            attrs: Attributes {
+                                id: Some("s2".into()),
+                                ..Default::default()
+                            },
+                            node: Box::new(paragraph(vec![InlineNode::Text("Target.".into())])),
+                        },
+                        paragraph(vec![InlineNode::Span {
+                            attrs: Attributes {
+                                id: Some("s1".into()),
+                                ..Default::default()
+                            },
+                            children: vec![InlineNode::Text("Inline.".into())],
+                        }]),
+                    ],
+                    text_direction: None,
+                },
+            ],
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        MarkdownWriter::write(&doc, &mut out, &WriteOptions::default(), None).unwrap();
+        let text = String::from_utf8(out).unwrap();
+
+        assert!(text.contains(
+            "[two](#s2), [chapter](#c2x), [here](#s1) and [away](https://example.com/c2x)"
+        ));
+        assert!(text.contains("<a id=\"c2x\"></a>\n\n# Two"));
+        assert!(text.contains("<a id=\"s2\"></a>\n\nTarget."));
+        assert!(text.contains("<a id=\"s1\"></a>Inline."));
+        // Unlinked chapters get no anchor.
+        assert!(!text.contains("<a id=\"c1x\">"));
+    }
+
+    #[test]
+    fn test_write_path_writes_images() {
+        let mut resources = ResourceMap::new();
+        resources.insert(
+            "img1".into(),
+            Resource {
+                id: "img1".into(),
+                media_type: "image/png".into(),
+                data: vec![7, 7],
+                filename: Some("OEBPS/pic.png".into()),
+                obfuscation: None,
+            },
+        );
+        let doc = Document {
+            content: vec![Chapter {
+                id: "c1".into(),
+                title: Some("One".into()),
+                content: vec![ContentNode::Image {
+                    resource_id: "img1".into(),
+                    alt_text: Some("pic".into()),
+                    caption: None,
+                }],
+                text_direction: None,
+            }],
+            resources,
+            ..Default::default()
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("book.md");
+        MarkdownWriter::write_path(&doc, &path, &WriteOptions::default(), None).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.contains("# One\n\n![pic](images/pic.png)"));
+        assert_eq!(
+            fs::read(dir.path().join("images/pic.png")).unwrap(),
+            vec![7, 7]
+        );
+    }
+}
@@ -1,20 +1,26 @@
 //! Format writers — each format implements FormatWriter to emit from the Document IR.
 
+pub mod cbz;
 pub mod epub;
+pub mod fb2;
+pub mod html;
+pub mod markdown;
+pub mod pdf;
+pub mod ssml;
 pub mod txt;
 
-// Phase 2
-// pub mod html;
-// pub mod markdown;
-// pub mod ssml;
+use std::collections::HashMap;
 
-// Phase 3
-// pub mod pdf;
-
-use crate::document::{Document, EpubVersion};
+use crate::document::{ContentNode, Document, EpubVersion, InlineNode, Resource};
 use crate::error::WriteError;
 use crate::progress::ProgressHandler;
 use crate::transform::Transform;
+use crate::writers::cbz::CbzWriteOptions;
+use crate::writers::epub::EpubWriteOptions;
+use crate::writers::html::HtmlWriteOptions;
+use crate::writers::pdf::PdfWriteOptions;
+use crate::writers::ssml::SsmlWriteOptions;
+use crate::writers::txt::TxtWriteOptions;
 
 pub trait FormatWriter: Send + Sync {
     /// Write the document to a byte sink.
@@ -34,6 +40,18 @@ pub struct WriteOptions {
     pub embed_fonts: bool,
     pub minify: bool,
     pub transforms: Vec<Box<dyn Transform>>,
+    /// Options specific to the CBZ writer.
+    pub cbz: CbzWriteOptions,
+    /// Options specific to the EPUB writer.
+    pub epub: EpubWriteOptions,
+    /// Options specific to the HTML writer.
+    pub html: HtmlWriteOptions,
+    /// Options specific to the SSML writer.
+    pub ssml: SsmlWriteOptions,
+    /// Options specific to the PDF writer.
+    pub pdf: PdfWriteOptions,
+    /// Options specific to the plain-text writer.
+    pub txt: TxtWriteOptions,
 }
 
 impl Default for WriteOptions {
@@ -44,6 +62,126 @@ impl Default for WriteOptions {
             embed_fonts: true,
             minify: false,
             transforms: Vec::new(),
+            cbz: CbzWriteOptions::default(),
+            epub: EpubWriteOptions::default(),
+            html: HtmlWriteOptions::default(),
+            ssml: SsmlWriteOptions::default(),
+            pdf: PdfWriteOptions::default(),
+            txt: TxtWriteOptions::default(),
         }
     }
 }
+
+/// Look up the resource an image reference points at: by id first, then by
+/// filename, since readers may keep the original relative path (`../images/a.png`).
+pub(crate) fn find_resource<'a>(doc: &'a Document, reference: &str) -> Option<&'a Resource> {
+    if let Some(res) = doc.resources.get(reference) {
+        return Some(res);
+    }
+    let path = reference.split(['#', '?']).next().unwrap_or(reference);
+    let path = path.trim_start_matches("./").trim_start_matches('/');
+    let path = path.rsplit("../").next().unwrap_or(path);
+    if path.is_empty() {
+        return None;
+    }
+    doc.resources.iter().map(|(_, r)| r).find(|r| {
+        r.filename
+            .as_deref()
+            .is_some_and(|f| f == path || f.ends_with(&format!("/{}", path)) || path.ends_with(&format!("/{}", f)))
+    })
+}
+
+/// The chapter a TOC or link href points into, matched on the chapter id,
+/// file name or stem.
+pub(crate) fn chapter_index(doc: &Document, href: &str) -> Option<usize> {
+    let path = href.split('#').next().unwrap_or(href);
+    if path.is_empty() {
+        return None;
+    }
+    let file = path.rsplit('/').next().unwrap_or(path);
+    let stem = file.rsplit_once('.').map(|(s, _)| s).unwrap_or(file);
+    doc.content
+        .iter()
+        .position(|c| c.id == path || c.id == file || c.id == stem)
+}
+
+/// A note body in the document, with where it sits and how it is referenced.
+pub(crate) struct NoteBody<'a> {
+    pub id: &'a str,
+    pub children: &'a [ContentNode],
+    /// Index of the chapter containing the note.
+    pub chapter: usize,
+    /// Marker of the first `NoteRef` pointing at the note.
+    pub label: Option<&'a str>,
+}
+
+/// All notes in document order, for writers that move them (to the end of a
+/// chapter or book, or into a separate notes section).
+pub(crate) fn collect_notes(doc: &Document) -> Vec<NoteBody<'_>> {
+    fn blocks<'a>(
+        nodes: &'a [ContentNode],
+        chapter: usize,
+        notes: &mut Vec<NoteBody<'a>>,
+        labels: &mut HashMap<&'a str, &'a str>,
+    ) {
+        for node in nodes {
+            match node {
+                ContentNode::Note { id, children, .. } => {
+                    notes.push(NoteBody {
+                        id,
+                        children,
+                        chapter,
+                        label: None,
+                    });
+                    blocks(children, chapter, notes, labels);
+                }
+                ContentNode::Paragraph { children } | ContentNode::Heading { children, .. } => {
+                    inlines(children, labels)
+                }
+                ContentNode::BlockQuote { children } | ContentNode::Container { children, .. } => {
+                    blocks(children, chapter, notes, labels)
+                }
+                ContentNode::Styled { node, .. } => {
+                    blocks(std::slice::from_ref(node.as_ref()), chapter, notes, labels)
+                }
+                ContentNode::List { items, .. } => {
+                    for item in items {
+                        blocks(item, chapter, notes, labels);
+                    }
+                }
+                ContentNode::Table { headers, rows } => {
+                    for cell in headers.iter().chain(rows.iter().flatten()) {
+                        inlines(cell, labels);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    fn inlines<'a>(nodes: &'a [InlineNode], labels: &mut HashMap<&'a str, &'a str>) {
+        for node in nodes {
+            match node {
+                InlineNode::NoteRef { id, label } => {
+                    labels.entry(id).or_insert(label);
+                }
+                InlineNode::Emphasis(c)
+                | InlineNode::Strong(c)
+                | InlineNode::Superscript(c)
+                | InlineNode::Subscript(c)
+                | InlineNode::Link { children: c, .. }
+                | InlineNode::Span { children: c, .. } => inlines(c, labels),
+                _ => {}
+            }
+        }
+    }
+
+    let mut notes = Vec::new();
+    let mut labels = HashMap::new();
+    for (i, chapter) in doc.content.iter().enumerate() {
+        blocks(&chapter.content, i, &mut notes, &mut labels);
+    }
+    for note in &mut notes {
+        note.label = labels.get(note.id).copied();
+    }
+    notes
+}
@@ -0,0 +1,1777 @@
+//! PDF writer — lays reflowable content out onto fixed pages with printpdf.
+//!
+//! Text is wrapped using real glyph advances (from the embedded TrueType font,
+//! or the standard Times/Courier metrics when fonts are not embedded). Chapters
+//! start on a new page; other pages carry a running header with the book title
+//! and a page number in the footer. The TOC becomes the PDF outline.
+
+use std::collections::HashMap;
+use std::io::{Cursor, Write};
+
+use lopdf::{dictionary, Object, ObjectId, StringFormat};
+use printpdf::{
+    BuiltinFont, ColorBits, ColorSpace, CustomPdfConformance, Image, ImageFilter, ImageTransform,
+    ImageXObject, IndirectFontRef, Line, Mm, PdfConformance, PdfDocument, PdfDocumentReference,
+    PdfLayerReference, Point, Pt, Px,
+};
+
+use crate::document::*;
+use crate::error::WriteError;
+use crate::progress::{emit_progress, ProgressHandler};
+use crate::writers::{chapter_index, find_resource, FormatWriter, WriteOptions};
+
+/// Options for `PdfWriter`, set through `WriteOptions::pdf`. Lengths are in
+/// millimetres; the default page is A5.
+#[derive(Debug, Clone)]
+pub struct PdfWriteOptions {
+    pub page_width_mm: f32,
+    pub page_height_mm: f32,
+    pub margin_top_mm: f32,
+    pub margin_bottom_mm: f32,
+    pub margin_left_mm: f32,
+    pub margin_right_mm: f32,
+    /// Body text size in points; headings are scaled from it.
+    pub font_size_pt: f32,
+    /// Line height as a multiple of the font size.
+    pub line_height: f32,
+    /// Justify body paragraphs instead of setting them ragged-right.
+    pub justify: bool,
+    /// Print the book title at the top of every page except chapter openings.
+    pub running_header: bool,
+    pub page_numbers: bool,
+    /// TrueType/OpenType faces to embed when `WriteOptions::embed_fonts` is
+    /// set. Faces not given here are taken from the document's font resources.
+    pub fonts: PdfFonts,
+}
+
+impl Default for PdfWriteOptions {
+    fn default() -> Self {
+        Self {
+            page_width_mm: 148.0,
+            page_height_mm: 210.0,
+            margin_top_mm: 20.0,
+            margin_bottom_mm: 20.0,
+            margin_left_mm: 18.0,
+            margin_right_mm: 18.0,
+            font_size_pt: 11.0,
+            line_height: 1.4,
+            justify: true,
+            running_header: true,
+            page_numbers: true,
+            fonts: PdfFonts::default(),
+        }
+    }
+}
+
+/// Raw font files (TTF/OTF) for each face the writer uses.
+#[derive(Debug, Clone, Default)]
+pub struct PdfFonts {
+    pub regular: Option<Vec<u8>>,
+    pub bold: Option<Vec<u8>>,
+    pub italic: Option<Vec<u8>>,
+    pub bold_italic: Option<Vec<u8>>,
+    pub monospace: Option<Vec<u8>>,
+}
+
+pub struct PdfWriter;
+
+impl FormatWriter for PdfWriter {
+    fn write<W: Write>(
+        doc: &Document,
+        mut output: W,
+        opts: &WriteOptions,
+        progress: Option<&dyn ProgressHandler>,
+    ) -> Result<(), WriteError> {
+        let po = &opts.pdf;
+        let title = doc.metadata.title.clone().unwrap_or_default();
+        let (pdf, page, layer) = PdfDocument::new(
+            title.as_str(),
+            Mm(po.page_width_mm),
+            Mm(po.page_height_mm),
+            "Layer 1",
+        );
+        let pdf = pdf
+            .with_conformance(PdfConformance::Custom(CustomPdfConformance {
+                allows_default_fonts: true,
+                ..Default::default()
+            }))
+            .with_author(doc.metadata.authors.join(", "))
+            .with_subject(doc.metadata.description.clone().unwrap_or_default())
+            .with_keywords(doc.metadata.subjects.clone())
+            .with_creator("ebook-converter");
+
+        let fonts = load_fonts(&pdf, doc, opts)?;
+        let layer = pdf.get_page(page).get_layer(layer);
+        let mut w = Writer::new(&pdf, layer, doc, po, fonts);
+        w.decorate(false);
+
+        let total = doc.content.len() as u64;
+        let mut chapter_pages = Vec::with_capacity(doc.content.len());
+        for (i, chapter) in doc.content.iter().enumerate() {
+            if w.page_used {
+                w.new_page(false);
+            }
+            chapter_pages.push(w.page_count - 1);
+            let frame = w.body_frame();
+            w.blocks(&chapter.content, frame);
+            emit_progress(
+                progress,
+                "Writing PDF",
+                i as u64 + 1,
+                Some(total),
+                chapter.title.as_deref(),
+            );
+        }
+        drop(w);
+
+        let bytes = pdf.save_to_bytes().map_err(pdf_error)?;
+        let mut out = lopdf::Document::load_mem(&bytes).map_err(pdf_error)?;
+        normalize_to_unicode(&mut out);
+        set_info(&mut out, &doc.metadata);
+        let outline = outline_items(doc, &chapter_pages);
+        add_outline(&mut out, &outline, doc.metadata.language.as_deref()).map_err(pdf_error)?;
+        out.compress();
+        out.save_to(&mut output).map_err(pdf_error)?;
+        output.flush()?;
+        Ok(())
+    }
+}
+
+fn pdf_error(e: impl std::fmt::Display) -> WriteError {
+    WriteError::WriteFailed {
+        format: "PDF".into(),
+        detail: e.to_string(),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Fonts
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Face {
+    Regular,
+    Bold,
+    Italic,
+    BoldItalic,
+    Mono,
+}
+
+impl Face {
+    const ALL: [Face; 5] = [
+        Face::Regular,
+        Face::Bold,
+        Face::Italic,
+        Face::BoldItalic,
+        Face::Mono,
+    ];
+
+    fn styled(bold: bool, italic: bool, mono: bool) -> Face {
+        match (mono, bold, italic) {
+            (true, _, _) => Face::Mono,
+            (_, true, true) => Face::BoldItalic,
+            (_, true, false) => Face::Bold,
+            (_, false, true) => Face::Italic,
+            _ => Face::Regular,
+        }
+    }
+
+    fn builtin(self) -> BuiltinFont {
+        match self {
+            Face::Regular => BuiltinFont::TimesRoman,
+            Face::Bold => BuiltinFont::TimesBold,
+            Face::Italic => BuiltinFont::TimesItalic,
+            Face::BoldItalic => BuiltinFont::TimesBoldItalic,
+            Face::Mono => BuiltinFont::Courier,
+        }
+    }
+}
+
+/// How to measure text set in a face.
+enum Metrics<'a> {
+    Times { bold: bool },
+    Courier,
+    Ttf(Box<ttf_parser::Face<'a>>),
+}
+
+impl Metrics<'_> {
+    /// Advance width of `text` at `size`, in points.
+    fn width(&self, text: &str, size: f32) -> f32 {
+        let units: f32 = match self {
+            Metrics::Times { bold } => {
+                let w: f32 = text.chars().map(times_width).sum();
+                if *bold {
+                    w * 1.04
+                } else {
+                    w
+                }
+            }
+            Metrics::Courier => text.chars().count() as f32 * 600.0,
+            Metrics::Ttf(face) => {
+                let scale = 1000.0 / face.units_per_em() as f32;
+                text.chars()
+                    .filter_map(|c| face.glyph_index(c))
+                    .filter_map(|g| face.glyph_hor_advance(g))
+                    .map(|a| a as f32 * scale)
+                    .sum()
+            }
+        };
+        units * size / 1000.0
+    }
+}
+
+/// Times-Roman advance widths (1/1000 em) for ASCII 32..=126, from the
+/// standard Adobe font metrics.
+#[rustfmt::skip]
+const TIMES_ASCII: [u16; 95] = [
+    250, 333, 408, 500, 500, 833, 778, 180, 333, 333, 500, 564, 250, 333, 250, 278, // ' '../
+    500, 500, 500, 500, 500, 500, 500, 500, 500, 500, 278, 278, 564, 564, 564, 444, // 0..?
+    921, 722, 667, 667, 722, 611, 556, 722, 722, 333, 389, 722, 611, 889, 722, 722, // @..O
+    556, 722, 667, 556, 611, 722, 722, 944, 722, 722, 611, 333, 278, 333, 469, 500, // P.._
+    333, 444, 500, 444, 500, 444, 333, 500, 500, 278, 278, 500, 278, 778, 500, 500, // `..o
+    500, 500, 333, 389, 278, 500, 500, 722, 500, 500, 444, 480, 200, 480, 541,      // p..~
+];
+
+fn times_width(c: char) -> f32 {
+    let w = match c {
+        ' '..='~' => TIMES_ASCII[c as usize - 32],
+        '\u{2018}' | '\u{2019}' | '\u{201A}' => 333,
+        '\u{201C}' | '\u{201D}' | '\u{201E}' => 444,
+        '\u{2013}' => 500,
+        '\u{2014}' | '\u{2026}' => 1000,
+        '\u{2022}' => 350,
+        '\u{00A0}' => 250,
+        _ => 500,
+    };
+    w as f32
+}
+
+struct Fonts<'a> {
+    refs: HashMap<Face, IndirectFontRef>,
+    metrics: HashMap<Face, Metrics<'a>>,
+}
+
+impl Fonts<'_> {
+    fn width(&self, face: Face, text: &str, size: f32) -> f32 {
+        self.metrics[&face].width(text, size)
+    }
+}
+
+fn is_font_resource(res: &Resource) -> bool {
+    matches!(
+        res.media_type.as_str(),
+        "font/ttf"
+            | "font/otf"
+            | "font/sfnt"
+            | "application/x-font-ttf"
+            | "application/x-font-truetype"
+            | "application/x-font-opentype"
+            | "application/font-sfnt"
+            | "application/vnd.ms-opentype"
+    )
+}
+
+/// Register the five faces with the PDF. With `embed_fonts`, faces come from
+/// `PdfWriteOptions::fonts` and then the document's own font resources; any
+/// face still missing falls back to the regular embedded face, and without an
+/// embeddable regular face everything uses the standard PDF fonts.
+fn load_fonts<'a>(
+    pdf: &PdfDocumentReference,
+    doc: &'a Document,
+    opts: &'a WriteOptions,
+) -> Result<Fonts<'a>, WriteError> {
+    let mut files: HashMap<Face, &'a [u8]> = HashMap::new();
+    if opts.embed_fonts {
+        let given = &opts.pdf.fonts;
+        for (face, data) in [
+            (Face::Regular, &given.regular),
+            (Face::Bold, &given.bold),
+            (Face::Italic, &given.italic),
+            (Face::BoldItalic, &given.bold_italic),
+            (Face::Mono, &given.monospace),
+        ] {
+            if let Some(data) = data {
+                files.insert(face, data.as_slice());
+            }
+        }
+
+        let mut resources: Vec<&Resource> = doc
+            .resources
+            .iter()
+            .map(|(_, r)| r)
+            .filter(|r| is_font_resource(r))
+            .collect();
+        resources.sort_by(|a, b| a.id.cmp(&b.id));
+        for res in resources {
+            let Ok(parsed) = ttf_parser::Face::parse(&res.data, 0) else {
+                continue;
+            };
+            let face = Face::styled(parsed.is_bold(), parsed.is_italic(), parsed.is_monospaced());
+            files.entry(face).or_insert(res.data.as_slice());
+        }
+    }
+
+    let mut fonts = Fonts {
+        refs: HashMap::new(),
+        metrics: HashMap::new(),
+    };
+    for (face, data) in files {
+        let Ok(parsed) = ttf_parser::Face::parse(data, 0) else {
+            tracing::warn!("Cannot parse {:?} font; using a standard PDF font", face);
+            continue;
+        };
+        match pdf.add_external_font(Cursor::new(data)) {
+            Ok(font_ref) => {
+                fonts.refs.insert(face, font_ref);
+                fonts.metrics.insert(face, Metrics::Ttf(Box::new(parsed)));
+            }
+            Err(e) => tracing::warn!("Cannot embed {:?} font: {}", face, e),
+        }
+    }
+    if opts.embed_fonts && !fonts.refs.contains_key(&Face::Regular) {
+        tracing::debug!("No embeddable font found; using the standard PDF fonts");
+    }
+
+    let regular_embedded = fonts.refs.contains_key(&Face::Regular);
+    for face in Face::ALL {
+        if fonts.refs.contains_key(&face) {
+            continue;
+        }
+        if regular_embedded && face != Face::Mono {
+            let font_ref = fonts.refs[&Face::Regular].clone();
+            let Some(Metrics::Ttf(parsed)) = fonts.metrics.get(&Face::Regular) else {
+                unreachable!("embedded faces always have TrueType metrics");
+            };
+            let parsed = parsed.clone();
+            fonts.refs.insert(face, font_ref);
+            fonts.metrics.insert(face, Metrics::Ttf(parsed));
+            continue;
+        }
+        let font_ref = pdf.add_builtin_font(face.builtin()).map_err(pdf_error)?;
+        let metrics = match face {
+            Face::Mono => Metrics::Courier,
+            Face::Bold | Face::BoldItalic => Metrics::Times { bold: true },
+            _ => Metrics::Times { bold: false },
+        };
+        fonts.refs.insert(face, font_ref);
+        fonts.metrics.insert(face, metrics);
+    }
+    Ok(fonts)
+}
+
+// ---------------------------------------------------------------------------
+// Inline text → words → lines
+
+#[derive(Debug, Clone, Copy)]
+struct Style {
+    bold: bool,
+    italic: bool,
+    mono: bool,
+    /// Size relative to the block's font size.
+    scale: f32,
+    /// Baseline shift as a fraction of the block's font size.
+    rise: f32,
+}
+
+impl Style {
+    fn plain() -> Self {
+        Self {
+            bold: false,
+            italic: false,
+            mono: false,
+            scale: 1.0,
+            rise: 0.0,
+        }
+    }
+
+    fn face(&self) -> Face {
+        Face::styled(self.bold, self.italic, self.mono)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Piece {
+    text: String,
+    face: Face,
+    size: f32,
+    rise: f32,
+    width: f32,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Word {
+    pieces: Vec<Piece>,
+    /// Whether whitespace separates this word from the previous one.
+    space_before: bool,
+}
+
+impl Word {
+    fn width(&self) -> f32 {
+        self.pieces.iter().map(|p| p.width).sum()
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Token {
+    Word(Word),
+    Break,
+}
+
+#[derive(Debug, Default)]
+struct TextLine {
+    words: Vec<Word>,
+    /// Ended by an explicit line break or the end of the paragraph.
+    last: bool,
+}
+
+/// Flatten inline nodes into styled text runs; `None` marks a forced line break.
+fn runs(nodes: &[InlineNode], style: Style, out: &mut Vec<Option<(String, Style)>>) {
+    for node in nodes {
+        match node {
+            InlineNode::Text(t) => out.push(Some((t.clone(), style))),
+            InlineNode::Emphasis(c) => runs(
+                c,
+                Style {
+                    italic: !style.italic,
+                    ..style
+                },
+                out,
+            ),
+            InlineNode::Strong(c) => runs(
+                c,
+                Style {
+                    bold: true,
+                    ..style
+                },
+                out,
+            ),
+            InlineNode::Code(t) => out.push(Some((
+                t.clone(),
+                Style {
+                    mono: true,
+                    scale: style.scale * 0.9,
+                    ..style
+                },
+            ))),
+            InlineNode::Link { children, .. } => runs(children, style, out),
+            InlineNode::Superscript(c) => runs(
+                c,
+                Style {
+                    scale: style.scale * 0.7,
+                    rise: style.rise + 0.33,
+                    ..style
+                },
+                out,
+            ),
+            InlineNode::Subscript(c) => runs(
+                c,
+                Style {
+                    scale: style.scale * 0.7,
+                    rise: style.rise - 0.15,
+                    ..style
+                },
+                out,
+            ),
+            InlineNode::Ruby { base, annotation } => {
+                out.push(Some((format!("{}({})", base, annotation), style)))
+            }
+            InlineNode::LineBreak => out.push(None),
+            InlineNode::NoteRef { label, .. } => out.push(Some((
+                label.clone(),
+                Style {
+                    scale: style.scale * 0.7,
+                    rise: style.rise + 0.33,
+                    ..style
+                },
+            ))),
+            InlineNode::Span { children, .. } => runs(children, style, out),
+        }
+    }
+}
+
+impl Fonts<'_> {
+    /// Split styled runs into words at whitespace, keeping style changes
+    /// inside a word (`*emph*,`) as separate pieces.
+    fn tokenize(&self, runs: &[Option<(String, Style)>], size: f32) -> Vec<Token> {
+        let mut tokens = Vec::new();
+        let mut word = Word::default();
+        let mut pending_space = false;
+
+        fn finish(word: &mut Word, tokens: &mut Vec<Token>) {
+            if !word.pieces.is_empty() {
+                tokens.push(Token::Word(std::mem::take(word)));
+            }
+        }
+
+        for run in runs {
+            let Some((text, style)) = run else {
+                finish(&mut word, &mut tokens);
+                tokens.push(Token::Break);
+                pending_space = false;
+                continue;
+            };
+            let face = style.face();
+            let piece_size = size * style.scale;
+            let rise = size * style.rise;
+            for c in text.chars() {
+                if c.is_whitespace() && c != '\u{00A0}' {
+                    finish(&mut word, &mut tokens);
+                    pending_space = true;
+                    continue;
+                }
+                if word.pieces.is_empty() {
+                    word.space_before = pending_space;
+                    pending_space = false;
+                }
+                match word.pieces.last_mut() {
+                    Some(p) if p.face == face && p.size == piece_size && p.rise == rise => {
+                        p.text.push(c)
+                    }
+                    _ => word.pieces.push(Piece {
+                        text: c.to_string(),
+                        face,
+                        size: piece_size,
+                        rise,
+                        width: 0.0,
+                    }),
+                }
+            }
+        }
+        finish(&mut word, &mut tokens);
+
+        for token in &mut tokens {
+            if let Token::Word(w) = token {
+                for p in &mut w.pieces {
+                    p.width = self.width(p.face, &p.text, p.size);
+                }
+            }
+        }
+        tokens
+    }
+
+    fn space_width(&self, word: &Word) -> f32 {
+        let p = &word.pieces[0];
+        self.width(p.face, " ", p.size)
+    }
+
+    /// Greedy line breaking. Words wider than a line are split between characters.
+    fn break_lines(&self, tokens: Vec<Token>, width: f32) -> Vec<TextLine> {
+        let mut lines = Vec::new();
+        let mut line = TextLine::default();
+        let mut used = 0.0;
+        for token in tokens {
+            let word = match token {
+                Token::Break => {
+                    line.last = true;
+                    lines.push(std::mem::take(&mut line));
+                    used = 0.0;
+                    continue;
+                }
+                Token::Word(w) => w,
+            };
+            for word in self.split_wide(word, width) {
+                let gap = if line.words.is_empty() || !word.space_before {
+                    0.0
+                } else {
+                    self.space_width(&word)
+                };
+                let w = word.width();
+                if !line.words.is_empty() && used + gap + w > width {
+                    lines.push(std::mem::take(&mut line));
+                    used = w;
+                } else {
+                    used += gap + w;
+                }
+                line.words.push(word);
+            }
+        }
+        if !line.words.is_empty() {
+            line.last = true;
+            lines.push(line);
+        } else if let Some(prev) = lines.last_mut() {
+            prev.last = true;
+        }
+        lines
+    }
+
+    fn split_wide(&self, word: Word, width: f32) -> Vec<Word> {
+        if word.width() <= width {
+            return vec![word];
+        }
+        let mut parts = Vec::new();
+        let mut current = Word {
+            pieces: Vec::new(),
+            space_before: word.space_before,
+        };
+        let mut used = 0.0;
+        for piece in word.pieces {
+            for c in piece.text.chars() {
+                let mut buf = [0u8; 4];
+                let cw = self.width(piece.face, c.encode_utf8(&mut buf), piece.size);
+                if used + cw > width && !current.pieces.is_empty() {
+                    parts.push(std::mem::take(&mut current));
+                    used = 0.0;
+                }
+                match current.pieces.last_mut() {
+                    Some(p) if p.face == piece.face && p.size == piece.size => {
+                        p.text.push(c);
+                        p.width += cw;
+                    }
+                    _ => current.pieces.push(Piece {
+                        text: c.to_string(),
+                        width: cw,
+                        ..piece.clone()
+                    }),
+                }
+                used += cw;
+            }
+        }
+        if !current.pieces.is_empty() {
+            parts.push(current);
+        }
+        parts
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Page layout
+
+/// Horizontal extent of the current text block, in points.
+#[derive(Debug, Clone, Copy)]
+struct Frame {
+    x: f32,
+    width: f32,
+}
+
+impl Frame {
+    fn inset(self, left: f32, right: f32) -> Frame {
+        Frame {
+            x: self.x + left,
+            width: (self.width - left - right).max(36.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Align {
+    Left,
+    Justify,
+    Center,
+}
+
+struct Writer<'a> {
+    pdf: &'a PdfDocumentReference,
+    layer: PdfLayerReference,
+    doc: &'a Document,
+    opts: &'a PdfWriteOptions,
+    fonts: Fonts<'a>,
+    page_w: f32,
+    page_h: f32,
+    top: f32,
+    bottom: f32,
+    /// Top of the free space on the current page, in points from the bottom edge.
+    y: f32,
+    page_count: usize,
+    /// Whether anything has been placed below the page's top margin.
+    page_used: bool,
+    /// List marker to draw beside the next line of text.
+    marker: Option<(String, f32)>,
+}
+
+fn mm(pt: f32) -> Mm {
+    Mm::from(Pt(pt))
+}
+
+fn pt(mm: f32) -> f32 {
+    Pt::from(Mm(mm)).0
+}
+
+impl<'a> Writer<'a> {
+    fn new(
+        pdf: &'a PdfDocumentReference,
+        layer: PdfLayerReference,
+        doc: &'a Document,
+        opts: &'a PdfWriteOptions,
+        fonts: Fonts<'a>,
+    ) -> Self {
+        let page_h = pt(opts.page_height_mm);
+        let top = page_h - pt(opts.margin_top_mm);
+        Self {
+            pdf,
+            layer,
+            doc,
+            opts,
+            fonts,
+            page_w: pt(opts.page_width_mm),
+            page_h,
+            top,
+            bottom: pt(opts.margin_bottom_mm),
+            y: top,
+            page_count: 1,
+            page_used: false,
+            marker: None,
+        }
+    }
+
+    fn body_frame(&self) -> Frame {
+        let x = pt(self.opts.margin_left_mm);
+        Frame {
+            x,
+            width: (self.page_w - x - pt(self.opts.margin_right_mm)).max(36.0),
+        }
+    }
+
+    fn base_size(&self) -> f32 {
+        self.opts.font_size_pt
+    }
+
+    fn line_height(&self, size: f32) -> f32 {
+        size * self.opts.line_height
+    }
+
+    fn new_page(&mut self, header: bool) {
+        let (page, layer) = self.pdf.add_page(
+            Mm(self.opts.page_width_mm),
+            Mm(self.opts.page_height_mm),
+            "Layer 1",
+        );
+        self.layer = self.pdf.get_page(page).get_layer(layer);
+        self.page_count += 1;
+        self.y = self.top;
+        self.page_used = false;
+        self.decorate(header);
+    }
+
+    /// Draw the page number and, unless this page opens a chapter, the running header.
+    fn decorate(&self, header: bool) {
+        let size = self.base_size() * 0.8;
+        if self.opts.page_numbers {
+            let number = self.page_count.to_string();
+            let w = self.fonts.width(Face::Regular, &number, size);
+            let y = pt(self.opts.margin_bottom_mm) / 2.0;
+            self.draw(&number, Face::Regular, size, (self.page_w - w) / 2.0, y);
+        }
+        let title = self.doc.metadata.title.as_deref().unwrap_or("").trim();
+        if header && self.opts.running_header && !title.is_empty() {
+            let frame = self.body_frame();
+            let text = self.truncate(title, Face::Italic, size, frame.width);
+            let w = self.fonts.width(Face::Italic, &text, size);
+            let y = self.page_h - pt(self.opts.margin_top_mm) / 2.0;
+            self.draw(
+                &text,
+                Face::Italic,
+                size,
+                frame.x + (frame.width - w) / 2.0,
+                y,
+            );
+        }
+    }
+
+    fn truncate(&self, text: &str, face: Face, size: f32, width: f32) -> String {
+        if self.fonts.width(face, text, size) <= width {
+            return text.to_string();
+        }
+        let mut out = String::new();
+        for c in text.chars() {
+            out.push(c);
+            if self.fonts.width(face, &format!("{}\u{2026}", out), size) > width {
+                out.pop();
+                break;
+            }
+        }
+        format!("{}\u{2026}", out.trim_end())
+    }
+
+    fn draw(&self, text: &str, face: Face, size: f32, x: f32, y: f32) {
+        self.layer
+            .use_text(text, size, mm(x), mm(y), &self.fonts.refs[&face]);
+    }
+
+    /// Make sure `height` points fit below the cursor, starting a new page if not.
+    fn ensure(&mut self, height: f32) {
+        if self.y - height < self.bottom && self.page_used {
+            self.new_page(true);
+        }
+    }
+
+    /// Vertical space between blocks; dropped at the top of a page.
+    fn gap(&mut self, height: f32) {
+        if self.page_used {
+            self.y -= height;
+        }
+    }
+
+    fn blocks(&mut self, nodes: &[ContentNode], frame: Frame) {
+        for node in nodes {
+            self.block(node, frame);
+        }
+    }
+
+    fn block(&mut self, node: &ContentNode, frame: Frame) {
+        let base = self.base_size();
+        match node {
+            ContentNode::Paragraph { children } => {
+                let align = if self.opts.justify {
+                    Align::Justify
+                } else {
+                    Align::Left
+                };
+                self.paragraph(children, Style::plain(), base, frame, align);
+                self.gap(base * 0.5);
+            }
+            ContentNode::Heading { level, children } => {
+                let scale = match level {
+                    1 => 2.0,
+                    2 => 1.6,
+                    3 => 1.35,
+                    4 => 1.2,
+                    5 => 1.1,
+                    _ => 1.0,
+                };
+                let size = base * scale;
+                let style = Style {
+                    bold: true,
+                    ..Style::plain()
+                };
+                let mut runs_out = Vec::new();
+                runs(children, style, &mut runs_out);
+                let tokens = self.fonts.tokenize(&runs_out, size);
+                let lines = self.fonts.break_lines(tokens, frame.width);
+                // Keep the heading with at least two lines of what follows.
+                self.gap(size * 0.8);
+                let needed =
+                    lines.len() as f32 * self.line_height(size) + 2.0 * self.line_height(base);
+                self.ensure(needed);
+                self.lines(lines, size, frame, Align::Left);
+                self.gap(size * 0.4);
+            }
+            ContentNode::List { ordered, items } => {
+                let indent = base * 1.8;
+                let inner = frame.inset(indent, 0.0);
+                for (i, item) in items.iter().enumerate() {
+                    let marker = if *ordered {
+                        format!("{}.", i + 1)
+                    } else {
+                        "\u{2022}".to_string()
+                    };
+                    let w = self.fonts.width(Face::Regular, &marker, base);
+                    self.marker = Some((marker, inner.x - w - base * 0.5));
+                    self.blocks(item, inner);
+                    self.marker = None;
+                }
+                self.gap(base * 0.3);
+            }
+            ContentNode::BlockQuote { children } => {
+                let inset = base * 1.5;
+                self.blocks(children, frame.inset(inset, inset));
+            }
+            ContentNode::CodeBlock { code, .. } => self.code_block(code, frame),
+            ContentNode::Table { headers, rows } => self.table(headers, rows, frame),
+            ContentNode::Image {
+                resource_id,
+                alt_text,
+                caption,
+            } => self.image(resource_id, alt_text.as_deref(), caption.as_deref(), frame),
+            ContentNode::HorizontalRule => {
+                let lh = self.line_height(base);
+                self.ensure(lh);
+                let y = self.y - lh / 2.0;
+                let w = frame.width * 0.3;
+                let x = frame.x + (frame.width - w) / 2.0;
+                self.rule(x, y, x + w, 0.5);
+                self.y -= lh;
+                self.page_used = true;
+            }
+            ContentNode::RawHtml(html) => {
+                let text = strip_tags(html);
+                if !text.trim().is_empty() {
+                    let nodes = [InlineNode::Text(text)];
+                    self.paragraph(&nodes, Style::plain(), base, frame, Align::Left);
+                    self.gap(base * 0.5);
+                }
+            }
+            // Notes stay where the source put them, set off by a short rule.
+            ContentNode::Note { children, .. } => {
+                let lh = self.line_height(base);
+                self.ensure(lh);
+                let y = self.y - lh / 4.0;
+                self.rule(frame.x, y, frame.x + frame.width * 0.2, 0.4);
+                self.y -= lh / 2.0;
+                self.blocks(children, frame.inset(base, 0.0));
+            }
+            ContentNode::Styled { node, .. } => self.block(node, frame),
+            ContentNode::Container { children, .. } => self.blocks(children, frame),
+        }
+    }
+
+    fn paragraph(
+        &mut self,
+        children: &[InlineNode],
+        style: Style,
+        size: f32,
+        frame: Frame,
+        align: Align,
+    ) {
+        let mut runs_out = Vec::new();
+        runs(children, style, &mut runs_out);
+        let tokens = self.fonts.tokenize(&runs_out, size);
+        let lines = self.fonts.break_lines(tokens, frame.width);
+        self.lines(lines, size, frame, align);
+    }
+
+    fn lines(&mut self, lines: Vec<TextLine>, size: f32, frame: Frame, align: Align) {
+        for line in lines {
+            let line_size = line
+                .words
+                .iter()
+                .flat_map(|w| &w.pieces)
+                .map(|p| p.size)
+                .fold(size, f32::max);
+            let lh = self.line_height(line_size);
+            self.ensure(lh);
+            let baseline = self.y - lh * 0.78;
+            if let Some((marker, x)) = self.marker.take() {
+                self.draw(&marker, Face::Regular, self.base_size(), x, baseline);
+            }
+            self.draw_line(&line, frame, align, baseline);
+            self.y -= lh;
+            self.page_used = true;
+        }
+    }
+
+    fn draw_line(&self, line: &TextLine, frame: Frame, align: Align, baseline: f32) {
+        let gaps: Vec<f32> = line
+            .words
+            .iter()
+            .enumerate()
+            .map(|(i, w)| {
+                if i > 0 && w.space_before {
+                    self.fonts.space_width(w)
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+        let natural: f32 =
+            line.words.iter().map(Word::width).sum::<f32>() + gaps.iter().sum::<f32>();
+        let stretchable = gaps.iter().filter(|g| **g > 0.0).count();
+        let mut x = frame.x;
+        let mut extra = 0.0;
+        match align {
+            Align::Center => x += ((frame.width - natural) / 2.0).max(0.0),
+            Align::Justify if !line.last && stretchable > 0 => {
+                let per_gap = (frame.width - natural) / stretchable as f32;
+                // Very loose lines look worse justified than ragged.
+                if per_gap > 0.0 && per_gap < self.base_size() * 1.5 {
+                    extra = per_gap;
+                }
+            }
+            _ => {}
+        }
+        for (i, (word, gap)) in line.words.iter().zip(&gaps).enumerate() {
+            if *gap > 0.0 {
+                x += gap + extra;
+            }
+            // Words are placed individually, but a real space after each one
+            // keeps text extraction and copy/paste readable.
+            let spaced = gaps.get(i + 1).is_some_and(|g| *g > 0.0);
+            for (j, piece) in word.pieces.iter().enumerate() {
+                let text = if spaced && j + 1 == word.pieces.len() {
+                    format!("{} ", piece.text)
+                } else {
+                    piece.text.clone()
+                };
+                self.draw(&text, piece.face, piece.size, x, baseline + piece.rise);
+                x += piece.width;
+            }
+        }
+    }
+
+    fn rule(&self, x1: f32, y1: f32, x2: f32, thickness: f32) {
+        self.layer.set_outline_thickness(thickness);
+        self.layer.add_line(Line {
+            points: vec![
+                (Point::new(mm(x1), mm(y1)), false),
+                (Point::new(mm(x2), mm(y1)), false),
+            ],
+            is_closed: false,
+        });
+    }
+
+    fn code_block(&mut self, code: &str, frame: Frame) {
+        let size = self.base_size() * 0.85;
+        let lh = self.line_height(size) * 0.9;
+        let frame = frame.inset(self.base_size(), 0.0);
+        let char_w = self.fonts.width(Face::Mono, "m", size).max(0.1);
+        let per_line = ((frame.width / char_w).floor() as usize).max(1);
+        self.gap(self.base_size() * 0.3);
+        for source in code.trim_end_matches('\n').lines() {
+            let chars: Vec<char> = source.replace('\t', "    ").chars().collect();
+            let chunks: Vec<String> = if chars.is_empty() {
+                vec![String::new()]
+            } else {
+                chars.chunks(per_line).map(|c| c.iter().collect()).collect()
+            };
+            for chunk in chunks {
+                self.ensure(lh);
+                let baseline = self.y - lh * 0.78;
+                if !chunk.is_empty() {
+                    self.draw(&chunk, Face::Mono, size, frame.x, baseline);
+                }
+                self.y -= lh;
+                self.page_used = true;
+            }
+        }
+        self.gap(self.base_size() * 0.6);
+    }
+
+    fn table(&mut self, headers: &[Vec<InlineNode>], rows: &[Vec<Vec<InlineNode>>], frame: Frame) {
+        let cols = rows
+            .iter()
+            .map(Vec::len)
+            .chain(std::iter::once(headers.len()))
+            .max()
+            .unwrap_or(0);
+        if cols == 0 {
+            return;
+        }
+        let size = self.base_size() * 0.9;
+        let lh = self.line_height(size);
+        let pad = size * 0.3;
+        let col_w = frame.width / cols as f32;
+        let bold = Style {
+            bold: true,
+            ..Style::plain()
+        };
+
+        self.gap(self.base_size() * 0.3);
+        let all = (!headers.is_empty())
+            .then_some((headers, bold))
+            .into_iter()
+            .chain(rows.iter().map(|r| (r.as_slice(), Style::plain())));
+        for (row_index, (row, style)) in all.enumerate() {
+            let cells: Vec<Vec<TextLine>> = (0..cols)
+                .map(|c| {
+                    let mut runs_out = Vec::new();
+                    if let Some(cell) = row.get(c) {
+                        runs(cell, style, &mut runs_out);
+                    }
+                    let tokens = self.fonts.tokenize(&runs_out, size);
+                    self.fonts.break_lines(tokens, col_w - 2.0 * pad)
+                })
+                .collect();
+            let height =
+                cells.iter().map(Vec::len).max().unwrap_or(0).max(1) as f32 * lh + 2.0 * pad;
+            self.ensure(height);
+            if row_index == 0 || !self.page_used {
+                self.rule(frame.x, self.y, frame.x + frame.width, 0.5);
+            }
+            for (c, lines) in cells.iter().enumerate() {
+                let cell = Frame {
+                    x: frame.x + c as f32 * col_w + pad,
+                    width: col_w - 2.0 * pad,
+                };
+                let mut y = self.y - pad;
+                for line in lines {
+                    self.draw_line(line, cell, Align::Left, y - lh * 0.78);
+                    y -= lh;
+                }
+            }
+            self.y -= height;
+            self.page_used = true;
+            let thickness = if row_index == 0 && !headers.is_empty() {
+                0.75
+            } else {
+                0.25
+            };
+            self.rule(frame.x, self.y, frame.x + frame.width, thickness);
+        }
+        self.gap(self.base_size() * 0.6);
+    }
+
+    fn image(&mut self, reference: &str, alt: Option<&str>, caption: Option<&str>, frame: Frame) {
+        let base = self.base_size();
+        let italic = Style {
+            italic: true,
+            ..Style::plain()
+        };
+        let decoded = find_resource(self.doc, reference).and_then(|res| image_xobject(&res.data));
+        let Some(xobject) = decoded else {
+            // Keep the reader informed when the picture itself can't be placed.
+            if let Some(alt) = alt.filter(|a| !a.trim().is_empty()) {
+                let nodes = [InlineNode::Text(format!("[{}]", alt.trim()))];
+                self.paragraph(&nodes, italic, base, frame, Align::Center);
+                self.gap(base * 0.5);
+            }
+            return;
+        };
+
+        // Natural size at 96 dpi, shrunk to the text block and page height.
+        let px_w = xobject.width.0 as f32;
+        let px_h = xobject.height.0 as f32;
+        let caption_room = if caption.is_some() {
+            self.line_height(base) * 2.0
+        } else {
+            0.0
+        };
+        let max_h = (self.top - self.bottom - caption_room).max(36.0);
+        let scale = (0.75f32).min(frame.width / px_w).min(max_h / px_h);
+        let (w, h) = (px_w * scale, px_h * scale);
+
+        self.gap(base * 0.3);
+        self.ensure(h);
+        let x = frame.x + (frame.width - w) / 2.0;
+        Image::from(xobject).add_to_layer(
+            self.layer.clone(),
+            ImageTransform {
+                translate_x: Some(mm(x)),
+                translate_y: Some(mm(self.y - h)),
+                scale_x: Some(scale),
+                scale_y: Some(scale),
+                dpi: Some(72.0),
+                ..Default::default()
+            },
+        );
+        self.y -= h;
+        self.page_used = true;
+
+        if let Some(caption) = caption.filter(|c| !c.trim().is_empty()) {
+            self.gap(base * 0.3);
+            let nodes = [InlineNode::Text(caption.to_string())];
+            self.paragraph(&nodes, italic, base * 0.9, frame, Align::Center);
+        }
+        self.gap(base * 0.6);
+    }
+}
+
+/// Decode an image resource for embedding. JPEGs are passed through as DCT
+/// streams when the PDF can use them directly; everything else is decoded,
+/// with transparency flattened onto white.
+fn image_xobject(data: &[u8]) -> Option<ImageXObject> {
+    let img = image::load_from_memory(data).ok()?;
+    let (width, height) = (img.width() as usize, img.height() as usize);
+    if width == 0 || height == 0 {
+        return None;
+    }
+    let is_jpeg = matches!(image::guess_format(data), Ok(image::ImageFormat::Jpeg));
+    let (color_space, image_data, image_filter) = match img.color() {
+        image::ColorType::L8 if is_jpeg => {
+            (ColorSpace::Greyscale, data.to_vec(), Some(ImageFilter::DCT))
+        }
+        image::ColorType::Rgb8 if is_jpeg => {
+            (ColorSpace::Rgb, data.to_vec(), Some(ImageFilter::DCT))
+        }
+        image::ColorType::L8 | image::ColorType::L16 => {
+            (ColorSpace::Greyscale, img.to_luma8().into_raw(), None)
+        }
+        c if c.has_alpha() => {
+            let rgba = img.to_rgba8();
+            let mut rgb = Vec::with_capacity(width * height * 3);
+            for px in rgba.pixels() {
+                let a = px[3] as u32;
+                for ch in &px.0[..3] {
+                    rgb.push(((*ch as u32 * a + 255 * (255 - a)) / 255) as u8);
+                }
+            }
+            (ColorSpace::Rgb, rgb, None)
+        }
+        _ => (ColorSpace::Rgb, img.to_rgb8().into_raw(), None),
+    };
+    Some(ImageXObject {
+        width: Px(width),
+        height: Px(height),
+        color_space,
+        bits_per_component: ColorBits::Bit8,
+        interpolate: true,
+        image_data,
+        image_filter,
+        smask: None,
+        clipping_bbox: None,
+    })
+}
+
+fn strip_tags(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                out.push(' ');
+            }
+            _ if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    out.replace("&nbsp;", "\u{00A0}")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Re-encode the document information strings. printpdf writes them as raw
+/// UTF-8, which readers decode as PDFDocEncoding.
+fn set_info(pdf: &mut lopdf::Document, metadata: &Metadata) {
+    let Ok(info_id) = pdf.trailer.get(b"Info").and_then(Object::as_reference) else {
+        return;
+    };
+    let Ok(info) = pdf.get_object_mut(info_id).and_then(Object::as_dict_mut) else {
+        return;
+    };
+    let fields = [
+        ("Title", metadata.title.clone()),
+        (
+            "Author",
+            Some(metadata.authors.join(", ")).filter(|a| !a.is_empty()),
+        ),
+        ("Subject", metadata.description.clone()),
+        (
+            "Keywords",
+            Some(metadata.subjects.join(", ")).filter(|k| !k.is_empty()),
+        ),
+    ];
+    for (key, value) in fields {
+        match value {
+            Some(v) => info.set(key, text_string(&v)),
+            None => {
+                info.remove(key.as_bytes());
+            }
+        }
+    }
+}
+
+/// Rewrite the ToUnicode CMaps of embedded fonts in the conventional Adobe
+/// layout. printpdf writes characters outside the BMP as bare code points
+/// rather than UTF-16, which strict parsers (lopdf's among them) reject,
+/// leaving the whole font's text unextractable.
+fn normalize_to_unicode(pdf: &mut lopdf::Document) {
+    let cmap_ids: Vec<ObjectId> = pdf
+        .objects
+        .values()
+        .filter_map(|o| o.as_dict().ok())
+        .filter(|d| d.type_is(b"Font"))
+        .filter_map(|d| d.get(b"ToUnicode").and_then(Object::as_reference).ok())
+        .collect();
+    for id in cmap_ids {
+        let Ok(stream) = pdf.get_object_mut(id).and_then(Object::as_stream_mut) else {
+            continue;
+        };
+        let content = stream
+            .decompressed_content()
+            .unwrap_or_else(|_| stream.content.clone());
+        let text = String::from_utf8_lossy(&content);
+        let mut pairs: Vec<(&str, String)> = Vec::new();
+        let mut in_block = false;
+        for line in text.lines().map(str::trim) {
+            if line.ends_with("beginbfchar") {
+                in_block = true;
+            } else if line == "endbfchar" {
+                in_block = false;
+            } else if let Some((code, uni)) = line.split_once(' ').filter(|_| in_block) {
+                let target = uni.trim().trim_start_matches('<').trim_end_matches('>');
+                let Some(c) = u32::from_str_radix(target, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                else {
+                    continue;
+                };
+                // Targets must be UTF-16: astral characters become surrogate pairs.
+                let mut units = [0u16; 2];
+                let hex: String = c
+                    .encode_utf16(&mut units)
+                    .iter()
+                    .map(|u| format!("{:04X}", u))
+                    .collect();
+                pairs.push((code, format!("<{}>", hex)));
+            }
+        }
+        if pairs.is_empty() {
+            continue;
+        }
+        let mut cmap = String::from(
+            "/CIDInit /ProcSet findresource begin\n12 dict begin\nbegincmap\n\
+             /CIDSystemInfo << /Registry (Adobe) /Ordering (UCS) /Supplement 0 >> def\n\
+             /CMapName /Adobe-Identity-UCS def\n/CMapType 2 def\n\
+             1 begincodespacerange\n<0000> <FFFF>\nendcodespacerange\n",
+        );
+        for chunk in pairs.chunks(100) {
+            cmap.push_str(&format!("{} beginbfchar\n", chunk.len()));
+            for (code, uni) in chunk {
+                cmap.push_str(&format!("{} {}\n", code, uni));
+            }
+            cmap.push_str("endbfchar\n");
+        }
+        cmap.push_str("endcmap\nCMapName currentdict /CMap defineresource pop\nend\nend\n");
+        stream.dict.remove(b"Filter");
+        stream.dict.remove(b"DecodeParms");
+        stream.set_content(cmap.into_bytes());
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Outline
+
+#[derive(Debug)]
+struct OutlineItem {
+    title: String,
+    /// Zero-based page index.
+    page: usize,
+    children: Vec<OutlineItem>,
+}
+
+/// Build the outline from the TOC, or from the chapters when the TOC is empty.
+/// Entries are placed on the page where their chapter starts.
+fn outline_items(doc: &Document, chapter_pages: &[usize]) -> Vec<OutlineItem> {
+    if doc.toc.is_empty() {
+        return doc
+            .content
+            .iter()
+            .zip(chapter_pages)
+            .enumerate()
+            .map(|(i, (chapter, page))| OutlineItem {
+                title: chapter_title(chapter).unwrap_or_else(|| format!("Chapter {}", i + 1)),
+                page: *page,
+                children: Vec::new(),
+            })
+            .collect();
+    }
+    toc_items(doc, &doc.toc, chapter_pages)
+}
+
+fn toc_items(doc: &Document, entries: &[TocEntry], chapter_pages: &[usize]) -> Vec<OutlineItem> {
+    entries
+        .iter()
+        .filter_map(|entry| {
+            let children = toc_items(doc, &entry.children, chapter_pages);
+            // Entries whose target is unknown point at their first child.
+            let page = chapter_index(doc, &entry.href)
+                .and_then(|i| chapter_pages.get(i).copied())
+                .or_else(|| children.first().map(|c| c.page))?;
+            Some(OutlineItem {
+                title: entry.title.clone(),
+                page,
+                children,
+            })
+        })
+        .collect()
+}
+
+fn chapter_title(chapter: &Chapter) -> Option<String> {
+    chapter.title.clone().or_else(|| {
+        chapter
+            .content
+            .iter()
+            .find_map(|node| match node.unstyled() {
+                ContentNode::Heading { children, .. } => {
+                    let text = crate::readers::inline_text(children);
+                    (!text.trim().is_empty()).then(|| text.trim().to_string())
+                }
+                _ => None,
+            })
+    })
+}
+
+/// PDF text string: plain bytes for ASCII, UTF-16BE with a BOM otherwise.
+fn text_string(s: &str) -> Object {
+    let bytes = if s.is_ascii() {
+        s.as_bytes().to_vec()
+    } else {
+        let mut b = vec![0xFE, 0xFF];
+        for unit in s.encode_utf16() {
+            b.extend_from_slice(&unit.to_be_bytes());
+        }
+        b
+    };
+    Object::String(bytes, StringFormat::Literal)
+}
+
+fn add_outline(
+    pdf: &mut lopdf::Document,
+    items: &[OutlineItem],
+    lang: Option<&str>,
+) -> lopdf::Result<()> {
+    let pages: Vec<ObjectId> = pdf.get_pages().into_values().collect();
+    let root = pdf.new_object_id();
+    let level = outline_level(pdf, root, items, &pages);
+    if let Some((first, last, count)) = level {
+        pdf.objects.insert(
+            root,
+            Object::Dictionary(dictionary! {
+                "Type" => "Outlines",
+                "First" => first,
+                "Last" => last,
+                "Count" => count,
+            }),
+        );
+    }
+    let catalog = pdf.catalog_mut()?;
+    if level.is_some() {
+        catalog.set("Outlines", root);
+        catalog.set("PageMode", "UseOutlines");
+    }
+    if let Some(lang) = lang.filter(|l| !l.is_empty()) {
+        catalog.set("Lang", Object::string_literal(lang));
+    }
+    Ok(())
+}
+
+/// Write one level of outline items under `parent`; returns the first and last
+/// item ids and the number of visible descendants.
+fn outline_level(
+    pdf: &mut lopdf::Document,
+    parent: ObjectId,
+    items: &[OutlineItem],
+    pages: &[ObjectId],
+) -> Option<(ObjectId, ObjectId, i64)> {
+    let items: Vec<&OutlineItem> = items.iter().filter(|i| i.page < pages.len()).collect();
+    if items.is_empty() {
+        return None;
+    }
+    let ids: Vec<ObjectId> = items.iter().map(|_| pdf.new_object_id()).collect();
+    let mut count = 0;
+    for (i, item) in items.iter().enumerate() {
+        let mut dict = dictionary! {
+            "Title" => text_string(&item.title),
+            "Parent" => parent,
+            "Dest" => vec![Object::Reference(pages[item.page]), "Fit".into()],
+        };
+        if i > 0 {
+            dict.set("Prev", ids[i - 1]);
+        }
+        if i + 1 < ids.len() {
+            dict.set("Next", ids[i + 1]);
+        }
+        count += 1;
+        if let Some((first, last, n)) = outline_level(pdf, ids[i], &item.children, pages) {
+            dict.set("First", first);
+            dict.set("Last", last);
+            dict.set("Count", n);
+            count += n;
+        }
+        pdf.objects.insert(ids[i], Object::Dictionary(dict));
+    }
+    Some((ids[0], ids[ids.len() - 1], count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::readers::pdf::PdfReader;
+    use crate::readers::{FormatReader, ReadOptions};
+
+    fn para(text: &str) -> ContentNode {
+        ContentNode::Paragraph {
+            children: vec![InlineNode::Text(text.into())],
+        }
+    }
+
+    #[test]
+    fn writes_readable_pdf_with_outline() {
+        let mut doc = Document::default();
+        doc.metadata.title = Some("Proof Copy".into());
+        doc.metadata.authors = vec!["A. Writer".into()];
+        let long = "The quick brown fox jumps over the lazy dog. ".repeat(120);
+        doc.content = vec![
+            Chapter {
+                id: "one".into(),
+                title: Some("One".into()),
+                content: vec![
+                    ContentNode::Heading {
+                        level: 1,
+                        children: vec![InlineNode::Text("Chapter One".into())],
+                    },
+                    para(&long),
+                ],
+                text_direction: None,
+            },
+            Chapter {
+                id: "two".into(),
+                title: Some("Two".into()),
+                content: vec![
+                    ContentNode::Heading {
+                        level: 1,
+                        children: vec![InlineNode::Text("Chapter Two".into())],
+                    },
+                    para("Short closing paragraph."),
+                ],
+                text_direction: None,
+            },
+        ];
+        doc.toc = vec![TocEntry {
+            title: "Part I".into(),
+            href: String::new(),
+            children: vec![
+                TocEntry {
+                    title: "Chapter One".into(),
+                    href: "one.xhtml".into(),
+                    children: Vec::new(),
+                },
+                TocEntry {
+                    title: "Chapter Two".into(),
+                    href: "two.xhtml#start".into(),
+                    children: Vec::new(),
+                },
+            ],
+        }];
+
+        let mut out = Vec::new();
+        let opts = WriteOptions {
+            embed_fonts: false,
+            ..Default::default()
+        };
+        PdfWriter::write(&doc, &mut out, &opts, None).unwrap();
+        assert!(out.starts_with(b"%PDF"));
+
+        let pdf = lopdf::Document::load_mem(&out).unwrap();
+        assert!(
+            pdf.get_pages().len() >= 3,
+            "chapter two starts on a fresh page"
+        );
+        assert!(pdf.catalog().unwrap().get(b"Outlines").is_ok());
+
+        let back = PdfReader::read(Cursor::new(out), &ReadOptions::default(), None).unwrap();
+        assert_eq!(back.metadata.title.as_deref(), Some("Proof Copy"));
+        let titles: Vec<&str> = back.toc.iter().map(|e| e.title.as_str()).collect();
+        assert_eq!(titles, ["Part I"]);
+        assert_eq!(back.toc[0].children.len(), 2);
+    }
+
+    fn chapter(id: &str, heading: &str, body: Vec<ContentNode>) -> Chapter {
+        let mut content = vec![ContentNode::Heading {
+            level: 1,
+            children: vec![InlineNode::Text(heading.into())],
+        }];
+        content.extend(body);
+        Chapter {
+            id: id.into(),
+            title: Some(heading.into()),
+            content,
+            text_direction: None,
+        }
+    }
+
+    fn write_pdf(doc: &Document) -> Vec<u8> {
+        let mut out = Vec::new();
+        let opts = WriteOptions {
+            embed_fonts: false,
+            ..Default::default()
+        };
+        PdfWriter::write(doc, &mut out, &opts, None).unwrap();
+        out
+    }
+
+    /// Text of a 1-based page with the per-word line breaks collapsed.
+    fn page_text(pdf: &lopdf::Document, page: u32) -> String {
+        let text = pdf.extract_text(&[page]).unwrap();
+        text.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    /// Zero-based page index of an outline item's explicit destination.
+    fn dest_page_index(pdf: &lopdf::Document, item: &lopdf::Dictionary) -> usize {
+        let pages: Vec<ObjectId> = pdf.get_pages().into_values().collect();
+        let dest = item.get(b"Dest").unwrap().as_array().unwrap();
+        let target = dest[0].as_reference().unwrap();
+        pages.iter().position(|p| *p == target).unwrap()
+    }
+
+    #[test]
+    fn paginates_with_running_header_and_page_numbers() {
+        let mut doc = Document::default();
+        doc.metadata.title = Some("Running Title".into());
+        let names = [
+            "Alpha", "Bravo", "Charlie", "Delta", "Echo", "Foxtrot", "Golf", "Hotel", "India",
+            "Juliett", "Kilo", "Lima",
+        ];
+        let paras: Vec<String> = names
+            .iter()
+            .map(|name| format!("{} paragraph of the long chapter. ", name).repeat(24))
+            .map(|p| p.trim_end().to_string())
+            .collect();
+        doc.content = vec![
+            chapter(
+                "one",
+                "Chapter One",
+                paras.iter().map(|p| para(p)).collect(),
+            ),
+            chapter("two", "Chapter Two", vec![para("The end.")]),
+        ];
+
+        let out = write_pdf(&doc);
+        let pdf = lopdf::Document::load_mem(&out).unwrap();
+        let count = pdf.get_pages().len() as u32;
+        assert!(count >= 4, "long chapter spills over several pages");
+        let text = |n: u32| page_text(&pdf, n);
+
+        // Chapter openings carry no running header; continuation pages do.
+        assert!(!text(1).contains("Running Title"));
+        assert!(text(2).contains("Running Title"));
+        assert!(text(count).contains("Chapter Two"));
+        assert!(!text(count).contains("Running Title"));
+        for n in 1..=count {
+            assert!(
+                text(n).split(' ').any(|w| w == n.to_string()),
+                "page {} is numbered",
+                n
+            );
+        }
+
+        // Reading it back drops the running lines and page numbers and rejoins
+        // text broken across pages.
+        let back = PdfReader::read(Cursor::new(out), &ReadOptions::default(), None).unwrap();
+        assert_eq!(back.content.len(), 2);
+        let body: Vec<String> = back.content[0]
+            .content
+            .iter()
+            .filter_map(|n| match n {
+                ContentNode::Paragraph { children } => Some(crate::readers::inline_text(children)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(body.join(" "), paras.join(" "));
+    }
+
+    #[test]
+    fn outline_points_at_chapter_opening_pages() {
+        let mut doc = Document::default();
+        doc.metadata.title = Some("Outlined".into());
+        let long = "Words fill the first chapter until it turns the page. ".repeat(80);
+        doc.content = vec![
+            chapter("one", "Chapter One", vec![para(&long)]),
+            chapter("two", "Chapter Two", vec![para("Closing words.")]),
+        ];
+        doc.toc = vec![
+            TocEntry {
+                title: "Chapter One".into(),
+                href: "one".into(),
+                children: vec![TocEntry {
+                    title: "Unresolvable".into(),
+                    href: "missing.xhtml".into(),
+                    children: Vec::new(),
+                }],
+            },
+            TocEntry {
+                title: "Chapter Two".into(),
+                href: "two.xhtml".into(),
+                children: Vec::new(),
+            },
+        ];
+
+        let out = write_pdf(&doc);
+        let pdf = lopdf::Document::load_mem(&out).unwrap();
+        let catalog = pdf.catalog().unwrap();
+        assert_eq!(
+            catalog.get(b"PageMode").unwrap().as_name().unwrap(),
+            b"UseOutlines"
+        );
+        let root = pdf
+            .get_dictionary(catalog.get(b"Outlines").unwrap().as_reference().unwrap())
+            .unwrap();
+        let first = pdf
+            .get_dictionary(root.get(b"First").unwrap().as_reference().unwrap())
+            .unwrap();
+        let second = pdf
+            .get_dictionary(first.get(b"Next").unwrap().as_reference().unwrap())
+            .unwrap();
+        assert_eq!(dest_page_index(&pdf, first), 0);
+        let two_page = dest_page_index(&pdf, second);
+        assert!(two_page >= 2, "chapter two opens after the overflow page");
+        assert!(page_text(&pdf, two_page as u32 + 1).contains("Chapter Two"));
+        // Entries with an unknown target are left out of the outline.
+        assert!(first.get(b"First").is_err());
+
+        let back = PdfReader::read(Cursor::new(out), &ReadOptions::default(), None).unwrap();
+        let titles: Vec<Option<&str>> = back.content.iter().map(|c| c.title.as_deref()).collect();
+        assert_eq!(titles, [Some("Chapter One"), Some("Chapter Two")]);
+        let toc: Vec<(&str, &str)> = back
+            .toc
+            .iter()
+            .map(|e| (e.title.as_str(), e.href.as_str()))
+            .collect();
+        assert_eq!(
+            toc,
+            [
+                ("Chapter One", back.content[0].id.as_str()),
+                ("Chapter Two", back.content[1].id.as_str()),
+            ]
+        );
+    }
+
+    #[test]
+    fn embeds_images_and_falls_back_to_alt_text() {
+        let mut png = Vec::new();
+        image::RgbImage::from_pixel(40, 20, image::Rgb([200, 30, 30]))
+            .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        let mut doc = Document::default();
+        doc.resources.insert(
+            "pic".into(),
+            Resource {
+                id: "pic".into(),
+                media_type: "image/png".into(),
+                data: png,
+                filename: Some("images/pic.png".into()),
+                obfuscation: None,
+            },
+        );
+        doc.content = vec![chapter(
+            "one",
+            "Pictures",
+            vec![
+                ContentNode::Image {
+                    resource_id: "images/pic.png".into(),
+                    alt_text: Some("A red box".into()),
+                    caption: Some("Figure 1".into()),
+                },
+                ContentNode::Image {
+                    resource_id: "images/missing.png".into(),
+                    alt_text: Some("Lost map".into()),
+                    caption: None,
+                },
+            ],
+        )];
+
+        let out = write_pdf(&doc);
+        let pdf = lopdf::Document::load_mem(&out).unwrap();
+        let images: Vec<(i64, i64)> = pdf
+            .objects
+            .values()
+            .filter_map(|o| o.as_stream().ok())
+            .filter(|s| s.dict.get(b"Subtype").and_then(Object::as_name).ok() == Some(b"Image"))
+            .map(|s| {
+                (
+                    s.dict.get(b"Width").unwrap().as_i64().unwrap(),
+                    s.dict.get(b"Height").unwrap().as_i64().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(images, [(40, 20)]);
+        let text = page_text(&pdf, 1);
+        assert!(text.contains("Figure 1"));
+        assert!(text.contains("[Lost map]"));
+    }
+
+    #[test]
+    fn lines_fit_the_measure() {
+        let pdf = PdfDocument::empty("t");
+        let doc = Document::default();
+        let opts = WriteOptions {
+            embed_fonts: false,
+            ..Default::default()
+        };
+        let fonts = load_fonts(&pdf, &doc, &opts).unwrap();
+        let mut out = Vec::new();
+        let text = [InlineNode::Text("lorem ipsum dolor sit amet ".repeat(20))];
+        runs(&text, Style::plain(), &mut out);
+        let tokens = fonts.tokenize(&out, 11.0);
+        let lines = fonts.break_lines(tokens, 200.0);
+        assert!(lines.len() > 5);
+        for line in &lines {
+            let words: f32 = line.words.iter().map(Word::width).sum();
+            let gaps = (line.words.len() - 1) as f32 * fonts.width(Face::Regular, " ", 11.0);
+            assert!(words + gaps <= 200.0);
+        }
+        assert!(lines.last().unwrap().last);
+    }
+}
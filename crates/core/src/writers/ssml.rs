@@ -0,0 +1,396 @@
+//! SSML writer: IR → Speech Synthesis Markup Language 1.1 for TTS engines.
+//!
+//! Headings, paragraphs, list items and captions become `<p>` elements split into
+//! `<s>` sentences, with configurable `<break>` pauses after each block. Code
+//! blocks and raw HTML are not spoken. Split output (`SsmlWriter::write_dir`)
+//! produces one `<speak>` document per chapter.
+
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use crate::document::*;
+use crate::error::WriteError;
+use crate::progress::{emit_progress, ProgressHandler};
+use crate::readers::collapse_whitespace;
+use crate::writers::{FormatWriter, WriteOptions};
+use crate::xml::escape_xml;
+
+/// Options for `SsmlWriter`, set through `WriteOptions::ssml`.
+/// Pauses are in milliseconds; `0` omits the `<break>`.
+#[derive(Debug, Clone)]
+pub struct SsmlWriteOptions {
+    pub paragraph_break_ms: u32,
+    pub heading_break_ms: u32,
+    /// Pause for horizontal rules (scene breaks).
+    pub section_break_ms: u32,
+    /// Pause between chapters in single-document output.
+    pub chapter_break_ms: u32,
+    /// Write a directory with one SSML document per chapter.
+    pub split_chapters: bool,
+    /// Read footnotes and endnotes where they appear in the text. Note
+    /// references are never spoken.
+    pub read_notes: bool,
+}
+
+impl Default for SsmlWriteOptions {
+    fn default() -> Self {
+        Self {
+            paragraph_break_ms: 400,
+            heading_break_ms: 800,
+            section_break_ms: 1200,
+            chapter_break_ms: 2000,
+            split_chapters: false,
+            read_notes: false,
+        }
+    }
+}
+
+pub struct SsmlWriter;
+
+/// `xml:lang` is required on `<speak>`; used when the book has no language.
+const DEFAULT_LANG: &str = "en";
+
+impl FormatWriter for SsmlWriter {
+    fn write<W: Write>(
+        doc: &Document,
+        output: W,
+        opts: &WriteOptions,
+        progress: Option<&dyn ProgressHandler>,
+    ) -> Result<(), WriteError> {
+        let mut w = output;
+        let total = doc.content.len() as u64;
+        write_speak_start(&mut w, doc)?;
+        for (i, chapter) in doc.content.iter().enumerate() {
+            if i > 0 {
+                write_break(&mut w, opts.ssml.chapter_break_ms)?;
+            }
+            write_chapter(&mut w, chapter, &opts.ssml)?;
+            emit_progress(
+                progress,
+                "Writing SSML",
+                i as u64 + 1,
+                Some(total),
+                Some(&format!("Chapter {}/{}", i + 1, total)),
+            );
+        }
+        writeln!(w, "</speak>")?;
+        Ok(())
+    }
+}
+
+impl SsmlWriter {
+    /// Write one `chapterN.ssml` document per chapter into `dir` (created if missing).
+    pub fn write_dir(
+        doc: &Document,
+        dir: &Path,
+        opts: &WriteOptions,
+        progress: Option<&dyn ProgressHandler>,
+    ) -> Result<(), WriteError> {
+        fs::create_dir_all(dir)?;
+        let total = doc.content.len() as u64;
+        for (i, chapter) in doc.content.iter().enumerate() {
+            let file = fs::File::create(dir.join(format!("chapter{}.ssml", i + 1)))?;
+            let mut w = std::io::BufWriter::new(file);
+            write_speak_start(&mut w, doc)?;
+            write_chapter(&mut w, chapter, &opts.ssml)?;
+            writeln!(w, "</speak>")?;
+            w.flush()?;
+            emit_progress(
+                progress,
+                "Writing SSML",
+                i as u64 + 1,
+                Some(total),
+                Some(&format!("Chapter {}/{}", i + 1, total)),
+            );
+        }
+        Ok(())
+    }
+}
+
+fn write_speak_start<W: Write>(w: &mut W, doc: &Document) -> Result<(), WriteError> {
+    writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        w,
+        r#"<speak version="1.1" xmlns="http://www.w3.org/2001/10/synthesis" xml:lang="{}">"#,
+        escape_xml(doc.metadata.language.as_deref().unwrap_or(DEFAULT_LANG))
+    )?;
+    Ok(())
+}
+
+fn write_break<W: Write>(w: &mut W, ms: u32) -> Result<(), WriteError> {
+    if ms > 0 {
+        writeln!(w, "<break time=\"{}ms\"/>", ms)?;
+    }
+    Ok(())
+}
+
+fn write_chapter<W: Write>(
+    w: &mut W,
+    chapter: &Chapter,
+    opts: &SsmlWriteOptions,
+) -> Result<(), WriteError> {
+    // Chapters without a leading heading still announce their title.
+    let has_heading = matches!(
+        chapter.content.first().map(ContentNode::unstyled),
+        Some(ContentNode::Heading { .. })
+    );
+    if !has_heading {
+        if let Some(ref title) = chapter.title {
+            write_paragraph(w, &[InlineNode::Text(title.clone())])?;
+            write_break(w, opts.heading_break_ms)?;
+        }
+    }
+    for node in &chapter.content {
+        write_block(w, node, opts)?;
+    }
+    Ok(())
+}
+
+fn write_block<W: Write>(
+    w: &mut W,
+    node: &ContentNode,
+    opts: &SsmlWriteOptions,
+) -> Result<(), WriteError> {
+    match node {
+        ContentNode::Paragraph { children } => {
+            if write_paragraph(w, children)? {
+                write_break(w, opts.paragraph_break_ms)?;
+            }
+        }
+        ContentNode::Heading { children, .. } => {
+            if write_paragraph(w, children)? {
+                write_break(w, opts.heading_break_ms)?;
+            }
+        }
+        ContentNode::List { items, .. } => {
+            for item in items {
+                for sub in item {
+                    write_block(w, sub, opts)?;
+                }
+            }
+        }
+        ContentNode::BlockQuote { children } | ContentNode::Container { children, .. } => {
+            for c in children {
+                write_block(w, c, opts)?;
+            }
+        }
+        ContentNode::Styled { node, .. } => write_block(w, node, opts)?,
+        ContentNode::Table { headers, rows } => {
+            for row in std::iter::once(headers).chain(rows) {
+                // Read each row as one sentence, cells separated by commas.
+                let mut cells = Vec::new();
+                for cell in row {
+                    let mut s = String::new();
+                    render_inlines(cell, &mut s);
+                    let s = s.trim().to_string();
+                    if !s.is_empty() {
+                        cells.push(s);
+                    }
+                }
+                if !cells.is_empty() {
+                    writeln!(w, "<p><s>{}</s></p>", cells.join(", "))?;
+                    write_break(w, opts.paragraph_break_ms)?;
+                }
+            }
+        }
+        ContentNode::Image {
+            alt_text, caption, ..
+        } => {
+            if let Some(text) = caption.as_ref().or(alt_text.as_ref()) {
+                if write_paragraph(w, &[InlineNode::Text(text.clone())])? {
+                    write_break(w, opts.paragraph_break_ms)?;
+                }
+            }
+        }
+        ContentNode::HorizontalRule => write_break(w, opts.section_break_ms)?,
+        ContentNode::Note { children, .. } => {
+            if opts.read_notes {
+                for c in children {
+                    write_block(w, c, opts)?;
+                }
+            }
+        }
+        ContentNode::CodeBlock { .. } | ContentNode::RawHtml(_) => {}
+    }
+    Ok(())
+}
+
+/// Write inline content as a `<p>` of `<s>` sentences. Returns false (and
+/// writes nothing) when there is nothing to speak.
+fn write_paragraph<W: Write>(w: &mut W, children: &[InlineNode]) -> Result<bool, WriteError> {
+    let sentences = split_sentences(children);
+    if sentences.is_empty() {
+        return Ok(false);
+    }
+    write!(w, "<p>")?;
+    for s in sentences {
+        write!(w, "<s>{}</s>", s)?;
+    }
+    writeln!(w, "</p>")?;
+    Ok(true)
+}
+
+/// Split top-level text at sentence-ending punctuation; markup such as
+/// `<emphasis>` stays inside the sentence it starts in.
+fn split_sentences(children: &[InlineNode]) -> Vec<String> {
+    let mut sentences = Vec::new();
+    let mut current = String::new();
+
+    for node in children {
+        let InlineNode::Text(text) = node else {
+            render_inline(node, &mut current);
+            continue;
+        };
+        let chars: Vec<char> = text.chars().collect();
+        let mut start = 0;
+        for i in 0..chars.len() {
+            if !is_sentence_end(chars[i]) {
+                continue;
+            }
+            let next = chars.get(i + 1).copied();
+            // Latin punctuation needs following whitespace (so "3.14" and
+            // "e.g.," stay intact); CJK full stops end a sentence outright.
+            let ends = match chars[i] {
+                '.' | '!' | '?' | '…' => next.map_or(true, char::is_whitespace),
+                _ => true,
+            };
+            if ends && !next.is_some_and(is_sentence_end) {
+                let piece: String = chars[start..=i].iter().collect();
+                current.push_str(&escape_xml(&collapse_whitespace(&piece)));
+                push_sentence(&mut sentences, &mut current);
+                start = i + 1;
+            }
+        }
+        let rest: String = chars[start..].iter().collect();
+        current.push_str(&escape_xml(&collapse_whitespace(&rest)));
+    }
+    push_sentence(&mut sentences, &mut current);
+    sentences
+}
+
+fn is_sentence_end(c: char) -> bool {
+    matches!(c, '.' | '!' | '?' | '…' | '。' | '！' | '？')
+}
+
+fn push_sentence(sentences: &mut Vec<String>, current: &mut String) {
+    let s = current.trim();
+    if !s.is_empty() {
+        sentences.push(s.to_string());
+    }
+    current.clear();
+}
+
+fn render_inlines(nodes: &[InlineNode], out: &mut String) {
+    for node in nodes {
+        render_inline(node, out);
+    }
+}
+
+fn render_inline(node: &InlineNode, out: &mut String) {
+    match node {
+        InlineNode::Text(s) => out.push_str(&escape_xml(&collapse_whitespace(s))),
+        InlineNode::Emphasis(children) => {
+            out.push_str("<emphasis level=\"moderate\">");
+            render_inlines(children, out);
+            out.push_str("</emphasis>");
+        }
+        InlineNode::Strong(children) => {
+            out.push_str("<emphasis level=\"strong\">");
+            render_inlines(children, out);
+            out.push_str("</emphasis>");
+        }
+        InlineNode::Code(s) => out.push_str(&escape_xml(s)),
+        InlineNode::Link { children, .. } | InlineNode::Subscript(children) => {
+            render_inlines(children, out)
+        }
+        InlineNode::Superscript(children) => {
+            // Note references (`<sup><a href="#…">1</a></sup>`) are not read aloud.
+            let is_note_ref = matches!(
+                children.as_slice(),
+                [InlineNode::Link { href, .. }] if href.starts_with('#')
+            );
+            if !is_note_ref {
+                render_inlines(children, out);
+            }
+        }
+        InlineNode::Ruby { base, annotation } => out.push_str(&format!(
+            "<sub alias=\"{}\">{}</sub>",
+            escape_xml(annotation),
+            escape_xml(base)
+        )),
+        InlineNode::LineBreak => out.push_str("<break strength=\"weak\"/>"),
+        InlineNode::NoteRef { .. } => {}
+        // Foreign-language runs switch the voice's pronunciation.
+        InlineNode::Span { attrs, children } => match &attrs.lang {
+            Some(lang) => {
+                out.push_str(&format!("<lang xml:lang=\"{}\">", escape_xml(lang)));
+                render_inlines(children, out);
+                out.push_str("</lang>");
+            }
+            None => render_inlines(children, out),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc() -> Document {
+        Document {
+            metadata: Metadata {
+                language: Some("fr".into()),
+                ..Default::default()
+            },
+            content: vec![Chapter {
+                id: "c1".into(),
+                title: Some("Un".into()),
+                content: vec![
+                    ContentNode::Heading {
+                        level: 1,
+                        children: vec![InlineNode::Text("Chapitre un".into())],
+                    },
+                    ContentNode::Paragraph {
+                        children: vec![
+                            InlineNode::Text("Pi is 3.14. It is ".into()),
+                            InlineNode::Emphasis(vec![InlineNode::Text("irrational".into())]),
+                            InlineNode::Text("! Done".into()),
+                        ],
+                    },
+                ],
+                text_direction: None,
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_sentences_breaks_and_lang() {
+        let mut out = Vec::new();
+        SsmlWriter::write(&doc(), &mut out, &WriteOptions::default(), None).unwrap();
+        let ssml = String::from_utf8(out).unwrap();
+        assert!(ssml.contains("xml:lang=\"fr\""));
+        assert!(ssml.contains("<p><s>Chapitre un</s></p>\n<break time=\"800ms\"/>"));
+        assert!(ssml.contains(
+            "<p><s>Pi is 3.14.</s><s>It is <emphasis level=\"moderate\">irrational</emphasis>!</s><s>Done</s></p>"
+        ));
+        assert!(ssml.trim_end().ends_with("</speak>"));
+    }
+
+    #[test]
+    fn test_split_writes_one_document_per_chapter() {
+        let mut d = doc();
+        d.content.push(Chapter {
+            id: "c2".into(),
+            title: Some("Deux".into()),
+            content: vec![],
+            text_direction: None,
+        });
+        let dir = tempfile::tempdir().unwrap();
+        SsmlWriter::write_dir(&d, dir.path(), &WriteOptions::default(), None).unwrap();
+        let second = fs::read_to_string(dir.path().join("chapter2.ssml")).unwrap();
+        assert!(second.contains("<speak"));
+        assert!(second.contains("<p><s>Deux</s></p>"));
+    }
+}
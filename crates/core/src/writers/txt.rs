@@ -1,11 +1,59 @@
-//! Plain text writer: flatten document content to UTF-8 text.
+//! Plain text writer: lay document content out as UTF-8 text.
+//!
+//! Blocks are separated by blank lines and optionally hard-wrapped (counting
+//! grapheme clusters, so combining marks and emoji sequences don't split).
+//! Headings take one of several styles, tables become ASCII grids, and
+//! footnotes, endnotes (marked `[^n]`) and external link targets (marked
+//! `[n]`) are listed at the end.
 
-use std::io::Write;
+use std::collections::HashMap;
+
+use unicode_segmentation::UnicodeSegmentation;
 
 use crate::document::*;
 use crate::error::WriteError;
-use crate::writers::{FormatWriter, WriteOptions};
-use crate::progress::ProgressHandler;
+use crate::progress::{emit_progress, ProgressHandler};
+use crate::writers::{collect_notes, FormatWriter, WriteOptions};
+
+/// Options for `TxtWriter`, set through `WriteOptions::txt`.
+#[derive(Debug, Clone)]
+pub struct TxtWriteOptions {
+    /// Wrap lines at this many characters (grapheme clusters); `None` keeps
+    /// each paragraph on one line.
+    pub wrap_width: Option<usize>,
+    pub heading_style: HeadingStyle,
+    /// Replace external link targets with `[n]` markers and list them at the
+    /// end of the text.
+    pub link_endnotes: bool,
+    pub line_ending: LineEnding,
+}
+
+impl Default for TxtWriteOptions {
+    fn default() -> Self {
+        Self {
+            wrap_width: None,
+            heading_style: HeadingStyle::Underline,
+            link_endnotes: true,
+            line_ending: LineEnding::Lf,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeadingStyle {
+    /// Setext style: `===` under level-1 headings, `---` under the rest.
+    Underline,
+    /// Heading text in capitals.
+    Uppercase,
+    /// Markdown ATX style: `#`, `##`, …
+    Hash,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+}
 
 pub struct TxtWriter;
 
@@ -13,143 +61,504 @@ impl FormatWriter for TxtWriter {
     fn write<W: std::io::Write>(
         doc: &Document,
         mut output: W,
-        _opts: &WriteOptions,
-        _progress: Option<&dyn ProgressHandler>,
+        opts: &WriteOptions,
+        progress: Option<&dyn ProgressHandler>,
     ) -> Result<(), WriteError> {
+        let mut layout = Layout {
+            opts: &opts.txt,
+            notes: Vec::new(),
+            note_numbers: HashMap::new(),
+            note_labels: collect_notes(doc)
+                .into_iter()
+                .filter_map(|n| Some((n.id.to_string(), n.label?.to_string())))
+                .collect(),
+            endnotes: Vec::new(),
+        };
+        let width = opts.txt.wrap_width;
+        let mut blocks: Vec<Vec<String>> = Vec::new();
+
         if let Some(ref title) = doc.metadata.title {
-            writeln!(output, "{}", title)?;
-            writeln!(output)?;
+            blocks.push(layout.heading(1, title, width));
         }
-
-        for chapter in &doc.content {
-            if let Some(ref title) = chapter.title {
-                writeln!(output, "{}", title)?;
-                writeln!(output)?;
+        let total = doc.content.len() as u64;
+        for (i, chapter) in doc.content.iter().enumerate() {
+            let starts_with_heading = matches!(
+                chapter.content.first().map(ContentNode::unstyled),
+                Some(ContentNode::Heading { .. })
+            );
+            if let (Some(title), false) = (&chapter.title, starts_with_heading) {
+                blocks.push(layout.heading(1, title, width));
             }
             for node in &chapter.content {
-                write_content_node(node, &mut output)?;
+                blocks.push(layout.block(node, width));
             }
+            emit_progress(
+                progress,
+                "Writing TXT",
+                i as u64 + 1,
+                Some(total),
+                Some(&format!("Chapter {}/{}", i + 1, total)),
+            );
         }
 
+        // Footnotes and endnotes alike collect at the end of the text.
+        if !layout.endnotes.is_empty() {
+            blocks.push(vec!["---".to_string()]);
+            blocks.append(&mut layout.endnotes);
+        }
+        if !layout.notes.is_empty() {
+            blocks.push(vec!["---".to_string()]);
+            let notes = std::mem::take(&mut layout.notes);
+            blocks.push(
+                notes
+                    .iter()
+                    .enumerate()
+                    .map(|(i, href)| format!("[{}] {}", i + 1, href))
+                    .collect(),
+            );
+        }
+
+        let eol = match opts.txt.line_ending {
+            LineEnding::Lf => "\n",
+            LineEnding::Crlf => "\r\n",
+        };
+        let mut first = true;
+        for block in blocks.iter().filter(|b| !b.is_empty()) {
+            if !first {
+                output.write_all(eol.as_bytes())?;
+            }
+            first = false;
+            for line in block {
+                write!(output, "{}{}", line.trim_end(), eol)?;
+            }
+        }
         Ok(())
     }
 }
 
-fn write_content_node<W: Write>(node: &ContentNode, output: &mut W) -> Result<(), WriteError> {
-    match node {
-        ContentNode::Paragraph { children } => {
-            let text = flatten_inlines(children);
-            writeln!(output, "{}", text)?;
-        }
-        ContentNode::Heading { level: _, children } => {
-            let text = flatten_inlines(children);
-            writeln!(output, "{}", text)?;
-            writeln!(output)?;
-        }
-        ContentNode::List { ordered, items } => {
-            for (i, item) in items.iter().enumerate() {
-                let prefix = if *ordered {
-                    format!("{}. ", i + 1)
-                } else {
-                    "- ".to_string()
-                };
-                for sub in item {
-                    let line = content_node_to_line(sub);
-                    writeln!(output, "{}{}", prefix, line)?;
+struct Layout<'a> {
+    opts: &'a TxtWriteOptions,
+    /// External hrefs in first-seen order; `[n]` refers to `notes[n - 1]`.
+    notes: Vec<String>,
+    note_numbers: HashMap<String, usize>,
+    /// Note id → marker used by its references.
+    note_labels: HashMap<String, String>,
+    /// Rendered note bodies, in document order.
+    endnotes: Vec<Vec<String>>,
+}
+
+impl Layout<'_> {
+    /// Lines for one block, wrapped to `width`.
+    fn block(&mut self, node: &ContentNode, width: Option<usize>) -> Vec<String> {
+        match node {
+            ContentNode::Paragraph { children } => {
+                let text = self.inlines(children);
+                text.split('\n').flat_map(|l| wrap(l, width)).collect()
+            }
+            ContentNode::Heading { level, children } => {
+                let text = self.inlines(children);
+                self.heading(*level, &text, width)
+            }
+            ContentNode::List { ordered, items } => {
+                let mut lines = Vec::new();
+                for (i, item) in items.iter().enumerate() {
+                    let marker = if *ordered {
+                        format!("{}. ", i + 1)
+                    } else {
+                        "- ".to_string()
+                    };
+                    let indent = " ".repeat(marker.len());
+                    let inner = width.map(|w| w.saturating_sub(marker.len()).max(1));
+                    let mut item_lines = Vec::new();
+                    for sub in item {
+                        item_lines.extend(self.block(sub, inner));
+                    }
+                    if item_lines.is_empty() {
+                        item_lines.push(String::new());
+                    }
+                    for (j, line) in item_lines.into_iter().enumerate() {
+                        let prefix = if j == 0 { &marker } else { &indent };
+                        lines.push(format!("{}{}", prefix, line));
+                    }
                 }
+                lines
             }
-        }
-        ContentNode::Table { headers, rows } => {
-            let header_line: Vec<String> = headers.iter().map(|c| flatten_inlines(c)).collect();
-            writeln!(output, "{}", header_line.join("\t"))?;
-            for row in rows {
-                let row_line: Vec<String> = row.iter().map(|c| flatten_inlines(c)).collect();
-                writeln!(output, "{}", row_line.join("\t"))?;
+            ContentNode::Table { headers, rows } => {
+                let headers: Vec<String> = headers.iter().map(|c| self.inlines(c)).collect();
+                let rows: Vec<Vec<String>> = rows
+                    .iter()
+                    .map(|r| r.iter().map(|c| self.inlines(c)).collect())
+                    .collect();
+                ascii_table(&headers, &rows)
             }
-        }
-        ContentNode::BlockQuote { children } => {
-            for c in children {
-                write_content_node(c, output)?;
+            ContentNode::BlockQuote { children } => {
+                let inner = width.map(|w| w.saturating_sub(2).max(1));
+                let mut lines = Vec::new();
+                for (i, child) in children.iter().enumerate() {
+                    if i > 0 {
+                        lines.push(">".to_string());
+                    }
+                    lines.extend(
+                        self.block(child, inner)
+                            .into_iter()
+                            .map(|l| format!("> {}", l)),
+                    );
+                }
+                lines
+            }
+            // Code keeps its own line breaks and is never wrapped.
+            ContentNode::CodeBlock { code, .. } => code
+                .replace("\r\n", "\n")
+                .trim_end_matches('\n')
+                .split('\n')
+                .map(|l| format!("    {}", l))
+                .collect(),
+            ContentNode::Image {
+                resource_id,
+                alt_text,
+                ..
+            } => {
+                let label = alt_text
+                    .as_deref()
+                    .map(str::trim)
+                    .filter(|a| !a.is_empty())
+                    .unwrap_or(resource_id);
+                wrap(&format!("[image: {}]", label), width)
+            }
+            ContentNode::HorizontalRule => vec!["---".to_string()],
+            ContentNode::RawHtml(s) => s.lines().map(str::to_string).collect(),
+            ContentNode::Styled { node, .. } => self.block(node, width),
+            ContentNode::Container { children, .. } => {
+                let mut lines = Vec::new();
+                for child in children {
+                    let block = self.block(child, width);
+                    if block.is_empty() {
+                        continue;
+                    }
+                    if !lines.is_empty() {
+                        lines.push(String::new());
+                    }
+                    lines.extend(block);
+                }
+                lines
+            }
+            ContentNode::Note { id, children, .. } => {
+                let marker = format!("[^{}] ", self.note_label(id));
+                let indent = " ".repeat(marker.graphemes(true).count());
+                let inner = width.map(|w| w.saturating_sub(indent.len()).max(1));
+                let mut lines = Vec::new();
+                for child in children {
+                    lines.extend(self.block(child, inner));
+                }
+                let lines = lines
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, l)| format!("{}{}", if i == 0 { &marker } else { &indent }, l))
+                    .collect();
+                self.endnotes.push(lines);
+                Vec::new()
             }
         }
-        ContentNode::CodeBlock { code, .. } => {
-            writeln!(output, "{}", code)?;
-        }
-        ContentNode::Image {
-            resource_id,
-            alt_text,
-            ..
-        } => {
-            let placeholder = alt_text
-                .as_deref()
-                .unwrap_or(resource_id)
-                .trim();
-            if placeholder.is_empty() {
-                writeln!(output, "[image: {}]", resource_id)?;
-            } else {
-                writeln!(output, "[image: {}]", placeholder)?;
+    }
+
+    fn note_label(&self, id: &str) -> String {
+        self.note_labels
+            .get(id)
+            .cloned()
+            .unwrap_or_else(|| id.to_string())
+    }
+
+    fn heading(&self, level: u8, text: &str, width: Option<usize>) -> Vec<String> {
+        match self.opts.heading_style {
+            HeadingStyle::Underline => {
+                let mut lines = wrap(text, width);
+                let len = lines.iter().map(|l| l.graphemes(true).count()).max();
+                let mark = if level <= 1 { "=" } else { "-" };
+                lines.push(mark.repeat(len.unwrap_or(0).max(3)));
+                lines
             }
+            HeadingStyle::Uppercase => wrap(&text.to_uppercase(), width),
+            HeadingStyle::Hash => wrap(
+                &format!("{} {}", "#".repeat(level.clamp(1, 6) as usize), text),
+                width,
+            ),
         }
-        ContentNode::HorizontalRule => {
-            writeln!(output, "---")?;
+    }
+
+    fn inlines(&mut self, nodes: &[InlineNode]) -> String {
+        let mut s = String::new();
+        for node in nodes {
+            self.inline(node, &mut s);
         }
-        ContentNode::RawHtml(s) => {
-            writeln!(output, "{}", s)?;
+        s
+    }
+
+    fn inline(&mut self, node: &InlineNode, out: &mut String) {
+        match node {
+            InlineNode::Text(t) | InlineNode::Code(t) => out.push_str(t),
+            InlineNode::Emphasis(children)
+            | InlineNode::Strong(children)
+            | InlineNode::Superscript(children)
+            | InlineNode::Subscript(children)
+            | InlineNode::Span { children, .. } => {
+                for c in children {
+                    self.inline(c, out);
+                }
+            }
+            InlineNode::Link { href, children } => {
+                let start = out.len();
+                for c in children {
+                    self.inline(c, out);
+                }
+                let external = href.contains("://") || href.starts_with("mailto:");
+                if self.opts.link_endnotes && external && out[start..].trim() != href {
+                    let next = self.notes.len() + 1;
+                    let n = *self.note_numbers.entry(href.clone()).or_insert(next);
+                    if n == next {
+                        self.notes.push(href.clone());
+                    }
+                    out.push_str(&format!("[{}]", n));
+                }
+            }
+            InlineNode::Ruby { base, .. } => out.push_str(base),
+            InlineNode::LineBreak => out.push('\n'),
+            InlineNode::NoteRef { id, .. } => out.push_str(&format!("[^{}]", self.note_label(id))),
         }
     }
-    Ok(())
 }
 
-fn content_node_to_line(node: &ContentNode) -> String {
-    match node {
-        ContentNode::Paragraph { children } => flatten_inlines(children),
-        ContentNode::Heading { children, .. } => flatten_inlines(children),
-        ContentNode::List { items, .. } => items
-            .iter()
-            .flat_map(|row| row.iter().map(content_node_to_line))
-            .collect::<Vec<_>>()
-            .join(" "),
-        ContentNode::Table { .. } => "[table]".to_string(),
-        ContentNode::BlockQuote { children } => children
-            .iter()
-            .map(content_node_to_line)
-            .collect::<Vec<_>>()
-            .join(" "),
-        ContentNode::CodeBlock { code, .. } => code.clone(),
-        ContentNode::Image { resource_id, .. } => format!("[image: {}]", resource_id),
-        ContentNode::HorizontalRule => "---".to_string(),
-        ContentNode::RawHtml(s) => s.clone(),
+/// Greedy word wrap counting grapheme clusters. Words longer than the width
+/// (URLs, mostly) stay whole on their own line.
+fn wrap(text: &str, width: Option<usize>) -> Vec<String> {
+    let Some(width) = width.filter(|w| *w > 0) else {
+        return vec![text.split_whitespace().collect::<Vec<_>>().join(" ")];
+    };
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut len = 0;
+    for word in text.split_whitespace() {
+        let word_len = word.graphemes(true).count();
+        if len > 0 && len + 1 + word_len > width {
+            lines.push(std::mem::take(&mut line));
+            len = 0;
+        }
+        if len > 0 {
+            line.push(' ');
+            len += 1;
+        }
+        line.push_str(word);
+        len += word_len;
     }
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
 }
 
-fn flatten_inlines(nodes: &[InlineNode]) -> String {
-    let mut s = String::new();
-    for node in nodes {
-        flatten_inline(node, &mut s);
+/// Render a grid with `+---+` borders. Columns whose body cells are all
+/// numbers are right-aligned, the rest left-aligned.
+fn ascii_table(headers: &[String], rows: &[Vec<String>]) -> Vec<String> {
+    let columns = rows
+        .iter()
+        .map(Vec::len)
+        .chain(std::iter::once(headers.len()))
+        .max()
+        .unwrap_or(0);
+    if columns == 0 {
+        return Vec::new();
     }
-    s
-}
+    let cell = |row: &[String], i: usize| -> String {
+        row.get(i)
+            .map(|c| c.split_whitespace().collect::<Vec<_>>().join(" "))
+            .unwrap_or_default()
+    };
+    let width = |s: &str| s.graphemes(true).count();
+    let widths: Vec<usize> = (0..columns)
+        .map(|i| {
+            rows.iter()
+                .map(|r| width(&cell(r, i)))
+                .chain(std::iter::once(width(&cell(headers, i))))
+                .max()
+                .unwrap_or(0)
+                .max(1)
+        })
+        .collect();
+    let numeric: Vec<bool> = (0..columns)
+        .map(|i| {
+            let mut cells = rows.iter().map(|r| cell(r, i)).filter(|c| !c.is_empty());
+            let mut any = false;
+            let all = cells.all(|c| {
+                any = true;
+                c.trim_start_matches(['-', '+', '$', '€', '£'])
+                    .trim_end_matches('%')
+                    .replace([',', '.'], "")
+                    .chars()
+                    .all(|ch| ch.is_ascii_digit())
+            });
+            any && all
+        })
+        .collect();
 
-fn flatten_inline(node: &InlineNode, out: &mut String) {
-    match node {
-        InlineNode::Text(t) => out.push_str(t),
-        InlineNode::Emphasis(children) | InlineNode::Strong(children) => {
-            for c in children {
-                flatten_inline(c, out);
-            }
+    let border = |fill: &str| -> String {
+        let mut s = String::from("+");
+        for w in &widths {
+            s.push_str(&fill.repeat(w + 2));
+            s.push('+');
         }
-        InlineNode::Code(c) => out.push_str(c),
-        InlineNode::Link { children, .. } => {
-            for c in children {
-                flatten_inline(c, out);
+        s
+    };
+    let line = |row: &[String], align: bool| -> String {
+        let mut s = String::from("|");
+        for (i, w) in widths.iter().enumerate() {
+            let text = cell(row, i);
+            let pad = " ".repeat(w - width(&text));
+            if align && numeric[i] {
+                s.push_str(&format!(" {}{} |", pad, text));
+            } else {
+                s.push_str(&format!(" {}{} |", text, pad));
             }
         }
-        InlineNode::Superscript(children) | InlineNode::Subscript(children) => {
-            for c in children {
-                flatten_inline(c, out);
-            }
+        s
+    };
+
+    let mut lines = vec![border("-")];
+    if !headers.is_empty() {
+        lines.push(line(headers, false));
+        lines.push(border("="));
+    }
+    for row in rows {
+        lines.push(line(row, true));
+    }
+    lines.push(border("-"));
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(s: &str) -> Vec<InlineNode> {
+        vec![InlineNode::Text(s.into())]
+    }
+
+    fn render(doc: &Document, txt: TxtWriteOptions) -> String {
+        let opts = WriteOptions {
+            txt,
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        TxtWriter::write(doc, &mut out, &opts, None).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn doc() -> Document {
+        Document {
+            content: vec![Chapter {
+                id: "chapter-1".into(),
+                title: Some("Intro".into()),
+                content: vec![
+                    ContentNode::Heading {
+                        level: 1,
+                        children: text("Intro"),
+                    },
+                    ContentNode::Paragraph {
+                        children: vec![
+                            InlineNode::Text("Crème brûlée, see ".into()),
+                            InlineNode::Link {
+                                href: "https://example.com/recipe".into(),
+                                children: text("the recipe"),
+                            },
+                            InlineNode::Text(" for details.".into()),
+                        ],
+                    },
+                    ContentNode::Table {
+                        headers: vec![text("Item"), text("Qty")],
+                        rows: vec![
+                            vec![text("Eggs"), text("12")],
+                            vec![text("Sugar"), text("3")],
+                        ],
+                    },
+                ],
+                text_direction: None,
+            }],
+            ..Default::default()
         }
-        InlineNode::Ruby { base, .. } => out.push_str(base),
-        InlineNode::LineBreak => out.push(' '),
+    }
+
+    #[test]
+    fn test_wrap_headings_tables_and_endnotes() {
+        let out = render(
+            &doc(),
+            TxtWriteOptions {
+                wrap_width: Some(20),
+                ..Default::default()
+            },
+        );
+        let expected = "Intro\n=====\n\n\
+            Crème brûlée, see\nthe recipe[1] for\ndetails.\n\n\
+            +-------+-----+\n\
+            | Item  | Qty |\n\
+            +=======+=====+\n\
+            | Eggs  |  12 |\n\
+            | Sugar |   3 |\n\
+            +-------+-----+\n\n\
+            ---\n\n\
+            [1] https://example.com/recipe\n";
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn test_hash_headings_and_crlf() {
+        let out = render(
+            &doc(),
+            TxtWriteOptions {
+                heading_style: HeadingStyle::Hash,
+                link_endnotes: false,
+                line_ending: LineEnding::Crlf,
+                ..Default::default()
+            },
+        );
+        assert!(out.starts_with("# Intro\r\n\r\nCrème brûlée, see the recipe for details.\r\n"));
+        assert!(!out.contains("[1]"));
+        assert!(!out.replace("\r\n", "").contains('\n'));
+    }
+
+    #[test]
+    fn test_note_and_link_markers_differ() {
+        let doc = Document {
+            content: vec![Chapter {
+                id: "chapter-1".into(),
+                title: None,
+                content: vec![
+                    ContentNode::Paragraph {
+                        children: vec![
+                            InlineNode::Text("See ".into()),
+                            InlineNode::Link {
+                                href: "https://example.com".into(),
+                                children: text("site"),
+                            },
+                            InlineNode::NoteRef {
+                                id: "fn-1".into(),
+                                label: "1".into(),
+                            },
+                        ],
+                    },
+                    ContentNode::Note {
+                        id: "fn-1".into(),
+                        kind: NoteKind::Footnote,
+                        children: vec![ContentNode::Paragraph {
+                            children: text("A note."),
+                        }],
+                    },
+                ],
+                text_direction: None,
+            }],
+            ..Default::default()
+        };
+        let out = render(&doc, TxtWriteOptions::default());
+        assert_eq!(
+            out,
+            "See site[1][^1]\n\n---\n\n[^1] A note.\n\n---\n\n[1] https://example.com\n"
+        );
     }
 }
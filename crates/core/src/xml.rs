@@ -0,0 +1,11 @@
+//! Escaping shared by the XML-based writers and readers that re-serialize markup.
+
+/// Escape text for XML, XHTML or HTML, safe in element content and in single-
+/// or double-quoted attribute values.
+pub(crate) fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
@@ -0,0 +1,80 @@
+//! Reading the malformed EPUB fixture: the broken chapter is recovered and
+//! recorded as a diagnostic, which `repair` then resolves.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+
+use ebook_converter_core::document::{ContentNode, Document, InlineNode};
+use ebook_converter_core::readers::epub::EpubReader;
+use ebook_converter_core::readers::ReadOptions;
+use ebook_converter_core::repair::{repair, RepairOptions};
+
+fn read_fixture() -> Document {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("../../tests/fixtures/malformed/unclosed-tags.epub");
+    let file = BufReader::new(File::open(path).unwrap());
+    EpubReader::read(file, &ReadOptions::default(), None).unwrap()
+}
+
+fn text(nodes: &[InlineNode]) -> String {
+    nodes
+        .iter()
+        .map(|node| match node {
+            InlineNode::Text(t) => t.clone(),
+            InlineNode::Strong(c) | InlineNode::Emphasis(c) => text(c),
+            InlineNode::LineBreak => "\n".to_string(),
+            _ => String::new(),
+        })
+        .collect()
+}
+
+fn paragraphs(nodes: &[ContentNode]) -> Vec<String> {
+    nodes
+        .iter()
+        .filter_map(|node| match node.unstyled() {
+            ContentNode::Paragraph { children } => Some(text(children)),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn unclosed_tags_are_recovered_with_a_diagnostic() {
+    let doc = read_fixture();
+
+    assert_eq!(doc.content.len(), 2);
+    assert_eq!(
+        paragraphs(&doc.content[0].content),
+        ["This is the first chapter."]
+    );
+    // The unclosed paragraph ends where the next one starts; the HTML entity
+    // and the unclosed `<br>` survive.
+    let second = paragraphs(&doc.content[1].content);
+    assert_eq!(second.len(), 2, "{second:?}");
+    assert!(second[0].starts_with("This is the second chapter with some"));
+    assert!(second[0].contains("bold") && second[0].contains("italic"));
+    assert!(second[1].contains("non-breaking\u{a0}space"));
+    assert!(second[1].contains("space\nand a line break."));
+
+    assert_eq!(doc.diagnostics.len(), 1);
+    let diagnostic = &doc.diagnostics[0];
+    assert_eq!(diagnostic.code, "malformed-xhtml");
+    assert_eq!(diagnostic.location.as_deref(), Some("chapter[1]"));
+}
+
+#[test]
+fn repair_rewrites_the_recovered_chapter() {
+    let mut doc = read_fixture();
+    let report = repair(&mut doc, &RepairOptions::default());
+
+    assert!(report
+        .fixes_applied
+        .iter()
+        .any(|a| a.code == "malformed-xhtml" && a.description.contains("chapter[1]")));
+    assert!(doc.diagnostics.is_empty());
+    assert!(report
+        .issues_remaining
+        .iter()
+        .all(|i| i.code != "malformed-xhtml"));
+}
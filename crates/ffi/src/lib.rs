@@ -8,7 +8,7 @@ use std::io::{Read, Seek};
 use std::os::raw::c_char;
 use std::path::Path;
 
-use ebook_converter_core::config::{load_config, read_options_from_config, write_options_from_config};
+use ebook_converter_core::config::{load_config, read_options_for_path, read_options_from_config, write_options_from_config};
 use ebook_converter_core::convert::{convert_path, parse_format, read_document};
 use ebook_converter_core::detect::detect;
 use ebook_converter_core::validate::{validate, ValidateOptions, WcagLevel};
@@ -48,6 +48,7 @@ pub extern "C" fn ebook_convert(
         format,
         &read_opts,
         &write_opts,
+        None,
     ) {
         Ok(()) => 0,  // EBOOK_OK
         Err(_) => -3,  // EBOOK_ERR_CONVERT
@@ -67,7 +68,7 @@ pub extern "C" fn ebook_validate(input_path: *const c_char) -> i32 {
     };
     let path = Path::new(input);
     let cfg = load_config();
-    let read_opts = read_options_from_config(&cfg);
+    let read_opts = read_options_for_path(&cfg, path);
     let doc = match std::fs::File::open(path) {
         Ok(f) => {
             let mut r = std::io::BufReader::new(f);